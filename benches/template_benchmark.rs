@@ -10,7 +10,8 @@
 //! templates using various contexts and cached templates.
 
 use criterion::{
-    black_box, criterion_group, criterion_main, Criterion,
+    black_box, criterion_group, criterion_main, BenchmarkId, Criterion,
+    Throughput,
 };
 use staticweaver::{Context, Engine};
 use std::time::Duration;
@@ -21,7 +22,7 @@
 /// Creates a context for benchmarking.
 fn create_benchmark_context() -> Context {
     let mut context = Context::new();
-    context.set("name".to_string(), "Alice".to_string());
+    context.set("name", "Alice");
     context
 }
 
@@ -53,5 +54,55 @@ fn benchmark_template_rendering(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, benchmark_template_rendering);
+/// Builds a template of `size` bytes of literal text per tag.
+fn literal_heavy_template(size: usize) -> String {
+    let literal =
+        "<p>Lorem ipsum dolor sit amet.</p>\n".repeat(size / 35 + 1);
+    format!("{0}{{{{name}}}}{0}{{{{name}}}}{0}", literal)
+}
+
+/// Benchmarks rendering templates that are mostly literal text, into a
+/// new string and into a reused buffer.
+fn benchmark_literal_heavy_rendering(c: &mut Criterion) {
+    let engine = Engine::new("dummy_path", Duration::from_secs(60));
+    let context = create_benchmark_context();
+    let mut group = c.benchmark_group("literal_heavy_rendering");
+
+    for size in [1_024, 64 * 1_024, 1_024 * 1_024] {
+        let template = literal_heavy_template(size);
+        let _ =
+            group.throughput(Throughput::Bytes(template.len() as u64));
+        let _ = group.bench_with_input(
+            BenchmarkId::new("render_template", size),
+            &template,
+            |b, template| {
+                b.iter(|| render_template(&engine, template, &context))
+            },
+        );
+        let mut buffer = String::with_capacity(template.len());
+        let _ = group.bench_with_input(
+            BenchmarkId::new("render_template_to", size),
+            &template,
+            |b, template| {
+                b.iter(|| {
+                    buffer.clear();
+                    engine
+                        .render_template_to(
+                            black_box(template),
+                            black_box(&context),
+                            &mut buffer,
+                        )
+                        .expect("Failed to render template");
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    benchmark_template_rendering,
+    benchmark_literal_heavy_rendering
+);
 criterion_main!(benches);
@@ -32,8 +32,8 @@ fn basic_context_operations() -> Result<(), Box<dyn std::error::Error>>
 
     let mut context = Context::new();
 
-    context.set("name".to_string(), "Alice".to_string());
-    context.set("age".to_string(), "30".to_string());
+    context.set("name", "Alice");
+    context.set("age", "30");
 
     match context.get("name") {
         Some(value) => println!("    ✅ Retrieved name: {}", value),
@@ -68,8 +68,8 @@ fn context_capacity_and_clear() -> Result<(), Box<dyn std::error::Error>>
     let mut context = Context::with_capacity(10);
     println!("    ✅ Created context with capacity >= 10");
 
-    context.set("key1".to_string(), "value1".to_string());
-    context.set("key2".to_string(), "value2".to_string());
+    context.set("key1", "value1");
+    context.set("key2", "value2");
 
     println!("    ✅ Context size before clear: {}", context.len());
     println!("    ✅ Is context empty? {}", context.is_empty());
@@ -90,7 +90,7 @@ fn context_update_and_remove() -> Result<(), Box<dyn std::error::Error>>
 
     let mut context = Context::new();
 
-    context.set("color".to_string(), "blue".to_string());
+    context.set("color", "blue");
     println!("    ✅ Set color to blue");
 
     context.update("color", "red");
@@ -120,9 +120,9 @@ fn context_iteration() -> Result<(), Box<dyn std::error::Error>> {
     println!("---------------------------------------------");
 
     let mut context = Context::new();
-    context.set("name".to_string(), "Bob".to_string());
-    context.set("age".to_string(), "25".to_string());
-    context.set("city".to_string(), "New York".to_string());
+    context.set("name", "Bob");
+    context.set("age", "25");
+    context.set("city", "New York");
 
     println!("    ✅ Iterating over context entries:");
     for (key, value) in context.iter() {
@@ -165,10 +165,10 @@ fn context_hashing() -> Result<(), Box<dyn std::error::Error>> {
     println!("---------------------------------------------");
 
     let mut context1 = Context::new();
-    context1.set("key".to_string(), "value".to_string());
+    context1.set("key", "value");
 
     let mut context2 = Context::new();
-    context2.set("key".to_string(), "value".to_string());
+    context2.set("key", "value");
 
     let hash1 = context1.hash();
     let hash2 = context2.hash();
@@ -182,8 +182,7 @@ fn context_hashing() -> Result<(), Box<dyn std::error::Error>> {
         println!("    ❌ Unexpected: Hashes are not equal for identical contexts");
     }
 
-    context2
-        .set("another_key".to_string(), "another_value".to_string());
+    context2.set("another_key", "another_value");
     let hash3 = context2.hash();
 
     if hash1 != hash3 {
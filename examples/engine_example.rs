@@ -35,8 +35,8 @@ fn basic_template_rendering() -> Result<(), Box<dyn std::error::Error>>
 
     let engine = Engine::new("templates", Duration::from_secs(60));
     let mut context = Context::new();
-    context.set("name".to_string(), "Alice".to_string());
-    context.set("greeting".to_string(), "Hello".to_string());
+    context.set("name", "Alice");
+    context.set("greeting", "Hello");
 
     let template = "{{greeting}}, {{name}}!";
 
@@ -69,15 +69,12 @@ fn page_rendering() -> Result<(), Box<dyn std::error::Error>> {
     let template_path = temp_dir.path().join("layout.html");
     fs::write(&template_path, "<html><body>{{content}}</body></html>")?;
 
-    let mut engine = Engine::new(
+    let engine = Engine::new(
         temp_dir.path().to_str().unwrap(),
         Duration::from_secs(60),
     );
     let mut context = Context::new();
-    context.set(
-        "content".to_string(),
-        "Welcome to StaticWeaver!".to_string(),
-    );
+    context.set("content", "Welcome to StaticWeaver!");
 
     match engine.render_page(&context, "layout") {
         Ok(result) => println!("    ✅ Rendered page: {}", result),
@@ -93,10 +90,10 @@ fn custom_delimiters() -> Result<(), Box<dyn std::error::Error>> {
     println!("---------------------------------------------");
 
     let mut engine = Engine::new("templates", Duration::from_secs(60));
-    engine.set_delimiters("<<", ">>");
+    engine.set_delimiters("<<", ">>")?;
 
     let mut context = Context::new();
-    context.set("name".to_string(), "Bob".to_string());
+    context.set("name", "Bob");
 
     let template = "Hello, <<name>>!";
 
@@ -127,12 +124,12 @@ fn cache_operations() -> Result<(), Box<dyn std::error::Error>> {
     let _ = engine.render_template(template, &context)?;
     let _ = engine.render_template(template, &context)?;
 
-    println!("    ✅ Cache size: {}", engine.render_cache.len());
+    println!("    ✅ Cache size: {}", engine.render_cache().len());
 
     engine.clear_cache();
     println!(
         "    ✅ Cache cleared. New size: {}",
-        engine.render_cache.len()
+        engine.render_cache().len()
     );
 
     engine.set_max_cache_size(10);
@@ -152,7 +149,10 @@ fn template_folder_creation() -> Result<(), Box<dyn std::error::Error>>
     // Test with a local path
     match engine.create_template_folder(Some("test_templates")) {
         Ok(path) => {
-            println!("    ✅ Created local template folder: {}", path)
+            println!(
+                "    ✅ Created local template folder: {}",
+                path.path().display()
+            )
         }
         Err(e) => println!(
             "    ❌ Failed to create local template folder: {:?}",
@@ -164,7 +164,10 @@ fn template_folder_creation() -> Result<(), Box<dyn std::error::Error>>
     let url = "https://raw.githubusercontent.com/sebastienrousseau/shokunin/main/template/";
     match engine.create_template_folder(Some(url)) {
         Ok(path) => {
-            println!("    ✅ Downloaded templates to: {}", path)
+            println!(
+                "    ✅ Downloaded templates to: {}",
+                path.path().display()
+            )
         }
         Err(e) => {
             println!("    ❌ Failed to download templates: {:?}", e)
@@ -34,7 +34,7 @@ fn io_error_example() -> Result<(), Box<dyn std::error::Error>> {
     println!("🦀 I/O Error Example");
     println!("---------------------------------------------");
 
-    let mut engine =
+    let engine =
         Engine::new("nonexistent_path", Duration::from_secs(60));
     let context = Context::new();
 
@@ -127,12 +127,12 @@ fn template_rendering_example() -> Result<(), Box<dyn std::error::Error>>
     let template_path = temp_dir.path().join("template.html");
     fs::write(&template_path, "Hello, {{name}}!")?;
 
-    let mut engine = Engine::new(
+    let engine = Engine::new(
         temp_dir.path().to_str().unwrap(),
         Duration::from_secs(60),
     );
     let mut context = Context::new();
-    context.set("name".to_string(), "World".to_string());
+    context.set("name", "World");
 
     match engine.render_page(&context, "template") {
         Ok(result) => println!("    ✅ Rendered template: {}", result),
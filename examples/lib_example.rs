@@ -31,8 +31,8 @@ fn context_usage() -> Result<(), Box<dyn std::error::Error>> {
     println!("---------------------------------------------");
 
     let mut context = Context::new();
-    context.set("name".to_string(), "Alice".to_string());
-    context.set("age".to_string(), "30".to_string());
+    context.set("name", "Alice");
+    context.set("age", "30");
 
     println!("    ✅ Created context with name and age");
 
@@ -55,8 +55,8 @@ fn engine_usage() -> Result<(), Box<dyn std::error::Error>> {
 
     let engine = Engine::new("templates", Duration::from_secs(60));
     let mut context = Context::new();
-    context.set("greeting".to_string(), "Hello".to_string());
-    context.set("name".to_string(), "World".to_string());
+    context.set("greeting", "Hello");
+    context.set("name", "World");
 
     let template = "{{greeting}}, {{name}}!";
 
@@ -74,12 +74,9 @@ fn page_options_usage() -> Result<(), Box<dyn std::error::Error>> {
     println!("---------------------------------------------");
 
     let mut options = PageOptions::new();
+    options.set("title", "My StaticWeaver Page");
     options
-        .set("title".to_string(), "My StaticWeaver Page".to_string());
-    options.set(
-        "description".to_string(),
-        "A sample page created with StaticWeaver".to_string(),
-    );
+        .set("description", "A sample page created with StaticWeaver");
 
     println!("    ✅ Created PageOptions with title and description");
 
@@ -0,0 +1,298 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Static Weaver Macros
+//!
+//! This crate provides the `render_static!` macro, re-exported by
+//! `staticweaver` with its `macros` feature. Use it through
+//! `staticweaver::render_static!`: the code it generates calls into
+//! `staticweaver`.
+
+#![deny(missing_docs)]
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use std::collections::BTreeSet;
+use std::path::Path;
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{braced, parse_macro_input, Expr, Ident, LitStr, Token};
+
+/// The opening delimiter of tags.
+const OPEN_DELIM: &str = "{{";
+
+/// The closing delimiter of tags.
+const CLOSE_DELIM: &str = "}}";
+
+/// The pragma declaring the syntax version of a template.
+const SYNTAX_PRAGMA: &str = "#syntax";
+
+/// The latest syntax version.
+const LATEST_SYNTAX: u32 = 2;
+
+/// The filters of templates, and the names of their `Filter` variants.
+const FILTERS: [(&str, &str); 5] = [
+    ("cdata", "Cdata"),
+    ("slugify", "Slugify"),
+    ("urlencode", "Urlencode"),
+    ("absolute_url", "AbsoluteUrl"),
+    ("safe", "Safe"),
+];
+
+/// Renders a template file checked at compile time.
+///
+/// See `staticweaver::render_static!` for the documentation.
+#[proc_macro]
+pub fn render_static(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as Input);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// The input of `render_static!`: a template path and its bindings.
+struct Input {
+    path: LitStr,
+    bindings: Vec<Binding>,
+}
+
+/// A value bound to a template key, as `key: expr`.
+struct Binding {
+    key: String,
+    span: Span,
+    value: Expr,
+}
+
+impl Parse for Input {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let path = input.parse()?;
+        let mut bindings = Vec::new();
+        if input.parse::<Option<Token![,]>>()?.is_some()
+            && !input.is_empty()
+        {
+            let content;
+            let _ = braced!(content in input);
+            bindings =
+                Punctuated::<Binding, Token![,]>::parse_terminated(
+                    &content,
+                )?
+                .into_iter()
+                .collect();
+            let _ = input.parse::<Option<Token![,]>>()?;
+        }
+        Ok(Self { path, bindings })
+    }
+}
+
+impl Parse for Binding {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let (key, span) = if input.peek(LitStr) {
+            let key: LitStr = input.parse()?;
+            (key.value(), key.span())
+        } else {
+            // Keywords such as `type` are keys too.
+            let key = input.call(Ident::parse_any)?;
+            (key.to_string(), key.span())
+        };
+        let _ = input.parse::<Token![:]>()?;
+        Ok(Self {
+            key,
+            span,
+            value: input.parse()?,
+        })
+    }
+}
+
+/// A piece of a template.
+enum Segment<'a> {
+    /// Literal text.
+    Text(&'a str),
+    /// A key and the `Filter` variants of its filters.
+    Value(&'a str, Vec<&'static str>),
+}
+
+/// Reads the template of `input` and generates the code rendering it.
+fn expand(input: &Input) -> syn::Result<proc_macro2::TokenStream> {
+    let error =
+        |message: String| syn::Error::new(input.path.span(), message);
+    let relative = input.path.value();
+    let root = std::env::var("CARGO_MANIFEST_DIR").map_err(|_| {
+        error("CARGO_MANIFEST_DIR is not set".to_string())
+    })?;
+    let path = Path::new(&root).join(&relative);
+    let template = std::fs::read_to_string(&path).map_err(|err| {
+        error(format!("cannot read {}: {}", path.display(), err))
+    })?;
+    let (syntax, body) = pragma(&template).map_err(error)?;
+    let segments = parse(body, syntax).map_err(error)?;
+
+    let mut seen = BTreeSet::new();
+    for binding in &input.bindings {
+        if !seen.insert(binding.key.as_str()) {
+            return Err(syn::Error::new(
+                binding.span,
+                format!("`{}` is bound more than once", binding.key),
+            ));
+        }
+    }
+    let missing: Vec<&str> = segments
+        .iter()
+        .filter_map(|segment| match segment {
+            Segment::Value(key, _) if !seen.contains(key) => Some(*key),
+            _ => None,
+        })
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if !missing.is_empty() {
+        return Err(error(format!(
+            "{} uses unbound {}: {}",
+            relative,
+            if missing.len() == 1 { "key" } else { "keys" },
+            missing.join(", ")
+        )));
+    }
+
+    let names: Vec<Ident> = (0..input.bindings.len())
+        .map(|index| {
+            Ident::new(
+                &format!("__staticweaver_{}", index),
+                Span::call_site(),
+            )
+        })
+        .collect();
+    let values = input.bindings.iter().map(|binding| &binding.value);
+    let path_str = path.to_string_lossy().into_owned();
+    let capacity = body.len();
+    let writes = segments.iter().map(|segment| match segment {
+        Segment::Text(text) => quote! { __out.push_str(#text); },
+        Segment::Value(key, filters) => {
+            let index = input
+                .bindings
+                .iter()
+                .position(|binding| binding.key == *key)
+                .unwrap_or_default();
+            let name = &names[index];
+            let filters = filters.iter().map(|variant| {
+                let variant = Ident::new(variant, Span::call_site());
+                quote! { ::staticweaver::filter::Filter::#variant }
+            });
+            quote! {
+                ::staticweaver::static_render::write_value(
+                    &mut __out,
+                    #name,
+                    &[#(#filters),*],
+                    __format,
+                );
+            }
+        }
+    });
+
+    Ok(quote! {
+        {
+            // Rebuilds the caller when the template changes.
+            const _: &str = include_str!(#path_str);
+            #(let #names = &(#values);)*
+            let __format = ::staticweaver::static_render::output_format(
+                #relative,
+                #syntax,
+            );
+            let mut __out = ::std::string::String::with_capacity(#capacity);
+            #(#writes)*
+            __out
+        }
+    })
+}
+
+/// Returns the syntax version declared by the pragma of `template`, and
+/// the template after it.
+fn pragma(template: &str) -> Result<(u32, &str), String> {
+    let declared = template.strip_prefix(OPEN_DELIM).and_then(|rest| {
+        let end = rest.find(CLOSE_DELIM)?;
+        let number = rest[..end].trim().strip_prefix(SYNTAX_PRAGMA)?;
+        Some((number.trim(), &rest[end + CLOSE_DELIM.len()..]))
+    });
+    let Some((number, rest)) = declared else {
+        return Ok((1, template));
+    };
+    let version = number
+        .parse()
+        .ok()
+        .filter(|version| (1..=LATEST_SYNTAX).contains(version))
+        .ok_or_else(|| {
+            format!("unsupported syntax version: {}", number)
+        })?;
+    let rest = rest
+        .strip_prefix("\r\n")
+        .or_else(|| rest.strip_prefix('\n'))
+        .unwrap_or(rest);
+    Ok((version, rest))
+}
+
+/// Splits `body` into text and values, rejecting the tags that need an
+/// engine to render.
+fn parse(body: &str, syntax: u32) -> Result<Vec<Segment<'_>>, String> {
+    let mut segments = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find(OPEN_DELIM) {
+        if start > 0 {
+            segments.push(Segment::Text(&rest[..start]));
+        }
+        let after = &rest[start + OPEN_DELIM.len()..];
+        let end = after
+            .find(CLOSE_DELIM)
+            .filter(|end| !after[..*end].contains(OPEN_DELIM))
+            .ok_or_else(|| {
+                format!(
+                    "unclosed tag at byte {}",
+                    body.len() - rest.len() + start
+                )
+            })?;
+        segments.push(tag(&after[..end], syntax)?);
+        rest = &after[end + CLOSE_DELIM.len()..];
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+    Ok(segments)
+}
+
+/// Parses the contents of a tag, keys trimmed as `syntax` says.
+fn tag(tag: &str, syntax: u32) -> Result<Segment<'_>, String> {
+    let mut parts = tag.split('|');
+    let key = parts.next().unwrap_or_default();
+    let key = if syntax >= 2 || tag.contains('|') {
+        key.trim()
+    } else {
+        key
+    };
+    let unsupported = |what: &str| {
+        Err(format!(
+            "{{{{{}}}}} {}, which render_static! cannot render",
+            tag, what
+        ))
+    };
+    if key.contains('(') {
+        return unsupported("calls a function");
+    }
+    if key.starts_with('#') || key.starts_with('/') {
+        return unsupported("is a block tag");
+    }
+    if key.starts_with("env.") || key.starts_with("data.") {
+        return unsupported("reads the engine's environment or data");
+    }
+    let filters = parts
+        .map(str::trim)
+        .map(|name| {
+            FILTERS
+                .iter()
+                .find(|(filter, _)| *filter == name)
+                .map(|(_, variant)| *variant)
+                .ok_or_else(|| format!("unknown filter: {}", name))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Segment::Value(key, filters))
+}
@@ -0,0 +1,388 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Asset Module
+//!
+//! This module copies the files of a site that are not rendered, such
+//! as images, fonts, and scripts, to its output directory. Assets are
+//! copied byte for byte and never parsed as templates.
+//!
+//! Copies are incremental: an asset is skipped when its copy is at
+//! least as new as it and has the same size, so rebuilding a site only
+//! copies the assets that changed since the last build.
+//!
+//! Once assets are copied, templates can emit their subresource
+//! integrity hashes with the `sri()` function, which reads the output
+//! directory set by
+//! [`Engine::set_asset_dir`](crate::Engine::set_asset_dir):
+//!
+//! ```text
+//! <script src="/js/app.js" integrity="{{ sri('js/app.js') }}" crossorigin="anonymous"></script>
+//! ```
+//!
+//! Hashes are cached in [`AssetHashes`] and recomputed when the size or
+//! modification time of an asset changes.
+
+use crate::engine::EngineError;
+use crate::function::Functions;
+use crate::integrity::base64;
+use fnv::FnvHashMap;
+use sha2::{Digest, Sha384};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
+use std::time::SystemTime;
+
+/// The name of the subresource integrity function.
+pub const SRI_FUNCTION: &str = "sri";
+
+/// Returns whether `dest` is an up-to-date copy of `source`: it exists,
+/// has the same size, and was modified no earlier.
+///
+/// # Arguments
+///
+/// * `source` - The asset.
+/// * `dest` - The copy of the asset.
+#[must_use]
+pub fn is_fresh(source: &Path, dest: &Path) -> bool {
+    let (Ok(source), Ok(dest)) =
+        (fs::metadata(source), fs::metadata(dest))
+    else {
+        return false;
+    };
+    match (source.modified(), dest.modified()) {
+        (Ok(source_time), Ok(dest_time)) => {
+            dest.len() == source.len() && dest_time >= source_time
+        }
+        _ => false,
+    }
+}
+
+/// Copies `source` to `dest` unless [`is_fresh`] says the copy is up to
+/// date, creating the parent directories of `dest`.
+///
+/// # Arguments
+///
+/// * `source` - The asset.
+/// * `dest` - The path to copy it to.
+///
+/// # Returns
+///
+/// Whether the asset was copied.
+///
+/// # Errors
+///
+/// Returns an error if the asset cannot be read or the copy cannot be
+/// written.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::asset::copy_asset;
+///
+/// let dir = tempfile::tempdir()?;
+/// let source = dir.path().join("logo.png");
+/// std::fs::write(&source, b"\x89PNG\r\n\x1a\n")?;
+///
+/// let dest = dir.path().join("public/img/logo.png");
+/// assert!(copy_asset(&source, &dest)?);
+/// assert!(!copy_asset(&source, &dest)?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn copy_asset(source: &Path, dest: &Path) -> io::Result<bool> {
+    if is_fresh(source, dest) {
+        return Ok(false);
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let _ = fs::copy(source, dest)?;
+    Ok(true)
+}
+
+/// Returns the subresource integrity value of `bytes`, such as
+/// `sha384-...`.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::asset::sri_hash;
+///
+/// assert_eq!(
+///     sri_hash(b"alert('Hello, world.');"),
+///     "sha384-H8BRh8j48O9oYatfu5AZzq6A9RINhZO5H16dQZngK7T62em8MUt1FLm52t+eX6xO"
+/// );
+/// ```
+#[must_use]
+pub fn sri_hash(bytes: &[u8]) -> String {
+    format!("sha384-{}", base64(&Sha384::digest(bytes)))
+}
+
+/// The integrity value of an asset, and the size and modification time
+/// it was computed for.
+#[derive(Debug, Clone)]
+struct Hashed {
+    len: u64,
+    modified: Option<SystemTime>,
+    integrity: Arc<str>,
+}
+
+impl Hashed {
+    /// Returns whether the hash was computed for a file with `metadata`.
+    fn matches(&self, metadata: &fs::Metadata) -> bool {
+        self.len == metadata.len()
+            && self.modified == metadata.modified().ok()
+    }
+}
+
+/// The subresource integrity hashes of the assets of an output
+/// directory, as read by the `sri()` function.
+///
+/// Hashes are computed on first use and recomputed when the size or
+/// modification time of an asset changes.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::asset::{sri_hash, AssetHashes};
+///
+/// let dir = tempfile::tempdir()?;
+/// std::fs::create_dir(dir.path().join("js"))?;
+/// std::fs::write(dir.path().join("js/app.js"), "run();")?;
+///
+/// let hashes = AssetHashes::new(dir.path());
+/// assert_eq!(hashes.integrity("/js/app.js")?, sri_hash(b"run();"));
+/// assert!(hashes.integrity("../secret.txt").is_err());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct AssetHashes {
+    dir: PathBuf,
+    hashes: RwLock<FnvHashMap<PathBuf, Hashed>>,
+}
+
+impl Clone for AssetHashes {
+    fn clone(&self) -> Self {
+        Self {
+            dir: self.dir.clone(),
+            hashes: RwLock::new(
+                self.hashes
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone(),
+            ),
+        }
+    }
+}
+
+impl AssetHashes {
+    /// Creates an empty cache of the assets in `dir`.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The output directory the assets are copied to.
+    #[must_use]
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            hashes: RwLock::default(),
+        }
+    }
+
+    /// Returns the output directory of the assets.
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the subresource integrity value of the asset at `path`,
+    /// relative to the output directory. A leading `/` is ignored, so
+    /// site URLs can be passed as is.
+    ///
+    /// # Errors
+    ///
+    /// * `EngineError::Render` - If `path` leaves the output directory.
+    /// * `EngineError::Io` - If the asset cannot be read.
+    pub fn integrity(&self, path: &str) -> Result<String, EngineError> {
+        let relative = Path::new(path.trim_start_matches('/'));
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(EngineError::Render(format!(
+                "Asset path leaves the output directory: {}",
+                path
+            )));
+        }
+        let file = self.dir.join(relative);
+        let metadata = fs::metadata(&file)?;
+        if let Some(hashed) = self.read().get(relative) {
+            if hashed.matches(&metadata) {
+                return Ok(hashed.integrity.to_string());
+            }
+        }
+        let hashed = Hashed {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            integrity: Arc::from(sri_hash(&fs::read(&file)?)),
+        };
+        let integrity = hashed.integrity.to_string();
+        let _ = self
+            .hashes
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(relative.to_path_buf(), hashed);
+        Ok(integrity)
+    }
+
+    /// Forgets the hashes of assets that changed or were removed since
+    /// they were computed.
+    ///
+    /// # Returns
+    ///
+    /// Whether any hash was forgotten.
+    pub fn refresh(&self) -> bool {
+        let mut hashes =
+            self.hashes.write().unwrap_or_else(PoisonError::into_inner);
+        let before = hashes.len();
+        let dir = &self.dir;
+        hashes.retain(|path, hashed| {
+            fs::metadata(dir.join(path))
+                .map_or(false, |metadata| hashed.matches(&metadata))
+        });
+        hashes.len() != before
+    }
+
+    /// Locks the hashes for reading.
+    fn read(&self) -> RwLockReadGuard<'_, FnvHashMap<PathBuf, Hashed>> {
+        self.hashes.read().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Registers the `sri` function.
+pub(crate) fn register(functions: &mut Functions) {
+    functions.register(SRI_FUNCTION, |call| {
+        let path = call.value(0)?;
+        let Some(assets) = call.engine().asset_hashes() else {
+            return Err(EngineError::Render(format!(
+                "{}() requires an asset directory",
+                SRI_FUNCTION
+            )));
+        };
+        assets.integrity(&path)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const BYTES: [u8; 6] = [0, 159, 146, 150, 255, 0];
+
+    /// Returns a directory holding the binary file `font.woff2`, and the
+    /// path its copy is written to.
+    fn font() -> (TempDir, PathBuf, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("font.woff2");
+        let dest = dir.path().join("out/fonts/font.woff2");
+        fs::write(&source, BYTES).unwrap();
+        (dir, source, dest)
+    }
+
+    /// Returns an output directory holding `js/app.js`, and its path.
+    fn script(contents: &str) -> (TempDir, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("js")).unwrap();
+        let script = dir.path().join("js/app.js");
+        fs::write(&script, contents).unwrap();
+        (dir, script)
+    }
+
+    #[test]
+    fn test_copy_asset_creates_dest() {
+        let (_dir, source, dest) = font();
+        assert!(!is_fresh(&source, &dest));
+        assert!(copy_asset(&source, &dest).unwrap());
+        assert_eq!(fs::read(&dest).unwrap(), BYTES);
+    }
+
+    #[test]
+    fn test_copy_asset_skips_fresh_copy() {
+        let (_dir, source, dest) = font();
+        assert!(copy_asset(&source, &dest).unwrap());
+        assert!(!copy_asset(&source, &dest).unwrap());
+    }
+
+    #[test]
+    fn test_copy_asset_replaces_changed_copy() {
+        let (_dir, source, dest) = font();
+        assert!(copy_asset(&source, &dest).unwrap());
+        fs::write(&dest, b"short").unwrap();
+        assert!(!is_fresh(&source, &dest));
+        assert!(copy_asset(&source, &dest).unwrap());
+        assert_eq!(fs::read(&dest).unwrap(), BYTES);
+    }
+
+    #[test]
+    fn test_copy_asset_missing_source() {
+        let (dir, _, dest) = font();
+        assert!(copy_asset(&dir.path().join("none"), &dest).is_err());
+    }
+
+    #[test]
+    fn test_integrity_with_or_without_leading_slash() {
+        let (dir, _) = script("a();");
+        let hashes = AssetHashes::new(dir.path());
+        assert_eq!(
+            hashes.integrity("js/app.js").unwrap(),
+            sri_hash(b"a();")
+        );
+        assert_eq!(
+            hashes.integrity("/js/app.js").unwrap(),
+            sri_hash(b"a();")
+        );
+    }
+
+    #[test]
+    fn test_refresh_rehashes_changed_assets() {
+        let (dir, script) = script("a();");
+        let hashes = AssetHashes::new(dir.path());
+        let _ = hashes.integrity("js/app.js").unwrap();
+        assert!(!hashes.refresh());
+
+        fs::write(&script, "b(); c();").unwrap();
+        assert!(hashes.refresh());
+        assert_eq!(
+            hashes.integrity("js/app.js").unwrap(),
+            sri_hash(b"b(); c();")
+        );
+    }
+
+    #[test]
+    fn test_integrity_of_removed_asset() {
+        let (dir, script) = script("a();");
+        let hashes = AssetHashes::new(dir.path());
+        let _ = hashes.integrity("js/app.js").unwrap();
+
+        fs::remove_file(&script).unwrap();
+        assert_eq!(
+            hashes.integrity("js/app.js").unwrap_err().code(),
+            "io"
+        );
+        assert!(hashes.refresh());
+    }
+
+    #[test]
+    fn test_integrity_rejects_paths_outside_dir() {
+        let (dir, _) = script("a();");
+        let hashes = AssetHashes::new(dir.path());
+        for path in ["../app.js", "js/../../app.js"] {
+            let err = hashes.integrity(path).unwrap_err();
+            assert!(err
+                .to_string()
+                .contains("leaves the output directory"));
+        }
+    }
+}
@@ -0,0 +1,388 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Block Module
+//!
+//! This module provides the `Blocks` registry of block tags. A block
+//! tag wraps part of a template, and is replaced by the output of the
+//! handler registered for its name:
+//!
+//! ```text
+//! {{#figure src="/beach.jpg" alt='The beach'}}Sunset at {{place}}{{/figure}}
+//! ```
+//!
+//! The opening tag is `#` followed by the block name and its arguments,
+//! written like those of [shortcodes](crate::shortcode): `key="value"`,
+//! `key='value'`, `key=value`, or a bare value, numbered from `0`. The
+//! block ends at the matching `/name` tag. Blocks nest, and the content
+//! in between is rendered with the context of the template before the
+//! handler is called.
+//!
+//! Handlers produce markup, so their output is written verbatim, like
+//! that of [functions](crate::function): a handler must escape the
+//! arguments it inserts. The rendered content is escaped for the output
+//! format already. Tags starting with `#` or `/` whose name is not
+//! registered are looked up as keys, as before.
+
+use crate::cancel::Deadline;
+use crate::context::Context;
+use crate::engine::{Engine, EngineError};
+use crate::function::next_revision;
+use crate::profile::ProfileKind;
+use crate::shortcode::parse_args;
+use fnv::FnvHashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// The prefix of the tag opening a block.
+pub const BLOCK_OPEN: char = '#';
+
+/// The prefix of the tag closing a block.
+pub const BLOCK_CLOSE: char = '/';
+
+/// The signature of a block handler.
+type BlockFn =
+    dyn Fn(&Block<'_>) -> Result<String, EngineError> + Send + Sync;
+
+/// A block tag, as passed to its handler.
+#[derive(Debug)]
+pub struct Block<'a> {
+    name: &'a str,
+    args: Context,
+    inner: &'a str,
+    source: &'a str,
+    engine: &'a Engine,
+    context: &'a Context,
+    deadline: Option<&'a Deadline<'a>>,
+}
+
+impl<'a> Block<'a> {
+    /// Returns the name of the block.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    /// Returns the arguments of the opening tag.
+    #[must_use]
+    pub fn args(&self) -> &Context {
+        &self.args
+    }
+
+    /// Returns the argument `key`, or the bare argument at the position
+    /// `key` names, such as `"0"`.
+    #[must_use]
+    pub fn arg(&self, key: &str) -> Option<&str> {
+        self.args.get(key).map(String::as_str)
+    }
+
+    /// Returns the rendered content of the block.
+    #[must_use]
+    pub fn inner(&self) -> &'a str {
+        self.inner
+    }
+
+    /// Returns the content of the block as written in the template,
+    /// before rendering.
+    #[must_use]
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    /// Returns the engine rendering the block.
+    #[must_use]
+    pub fn engine(&self) -> &'a Engine {
+        self.engine
+    }
+
+    /// Returns the context of the template.
+    #[must_use]
+    pub fn context(&self) -> &'a Context {
+        self.context
+    }
+
+    /// Returns an error if the render ran out of time or was
+    /// cancelled, as described in the [`cancel`](crate::cancel)
+    /// module.
+    ///
+    /// Handlers that loop over many items call it between items, so
+    /// that a render can stop before the handler returns.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EngineError::Timeout`] if the render must stop. The
+    /// engine reports it at the position of the block.
+    pub fn check(&self) -> Result<(), EngineError> {
+        self.deadline
+            .map_or(Ok(()), |deadline| deadline.check(self.source, 0))
+    }
+}
+
+/// Block handlers registered by name.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::escape::escape_html;
+/// use staticweaver::{Context, Engine};
+/// use std::time::Duration;
+///
+/// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+/// engine.blocks.register("figure", |block| {
+///     Ok(format!(
+///         "<figure><img src=\"{}\"><figcaption>{}</figcaption></figure>",
+///         escape_html(block.arg("src").unwrap_or_default()),
+///         block.inner().trim()
+///     ))
+/// });
+///
+/// let mut context = Context::new();
+/// context.set("place", "Malibu");
+/// let html = engine
+///     .render_template(
+///         "{{#figure src=\"/beach.jpg\"}} Sunset at {{place}} {{/figure}}",
+///         &context,
+///     )
+///     .unwrap();
+/// assert_eq!(
+///     html,
+///     "<figure><img src=\"/beach.jpg\"><figcaption>Sunset at Malibu</figcaption></figure>"
+/// );
+/// ```
+#[derive(Clone, Default)]
+pub struct Blocks {
+    handlers: FnvHashMap<String, Arc<BlockFn>>,
+    /// Changes whenever a handler is registered or removed.
+    revision: u64,
+}
+
+impl fmt::Debug for Blocks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.handlers.keys().collect();
+        names.sort_unstable();
+        f.debug_set().entries(names).finish()
+    }
+}
+
+impl Blocks {
+    /// Creates an empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the handler of the block `name`, replacing any
+    /// previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the block, as written after `#`.
+    /// * `handler` - The handler, returning the markup to insert.
+    pub fn register<F>(&mut self, name: &str, handler: F)
+    where
+        F: Fn(&Block<'_>) -> Result<String, EngineError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let _ =
+            self.handlers.insert(name.to_string(), Arc::new(handler));
+        self.revision = next_revision();
+    }
+
+    /// Removes the block `name`, returning whether it was registered.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the block.
+    pub fn unregister(&mut self, name: &str) -> bool {
+        let removed = self.handlers.remove(name).is_some();
+        if removed {
+            self.revision = next_revision();
+        }
+        removed
+    }
+
+    /// Returns the revision of the registry, which changes whenever a
+    /// handler is registered or removed.
+    pub(crate) fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Returns whether the block `name` is registered.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the block.
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.handlers.contains_key(name)
+    }
+
+    /// Returns whether no block is registered.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    /// Splits the trimmed tag `tag` into the name and arguments of the
+    /// registered block it opens.
+    ///
+    /// Returns `None` if `tag` does not open a registered block.
+    pub(crate) fn opening<'t>(
+        &self,
+        tag: &'t str,
+    ) -> Option<(&'t str, &'t str)> {
+        let body = tag.strip_prefix(BLOCK_OPEN)?.trim_start();
+        let name_len =
+            body.find(char::is_whitespace).unwrap_or(body.len());
+        let name = &body[..name_len];
+        self.contains(name).then(|| (name, &body[name_len..]))
+    }
+
+    /// Returns the name of the registered block the trimmed tag `tag`
+    /// closes, if any.
+    pub(crate) fn closing<'t>(&self, tag: &'t str) -> Option<&'t str> {
+        let name = tag.strip_prefix(BLOCK_CLOSE)?.trim();
+        self.contains(name).then(|| name)
+    }
+
+    /// Returns whether the trimmed tag `tag` opens or closes a
+    /// registered block.
+    pub(crate) fn is_block_tag(&self, tag: &str) -> bool {
+        self.opening(tag).is_some() || self.closing(tag).is_some()
+    }
+
+    /// Calls the handler of the block `name`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the block.
+    /// * `args` - The arguments of the opening tag, unparsed.
+    /// * `inner` - The rendered content of the block.
+    /// * `source` - The content of the block, before rendering.
+    /// * `engine` - The engine rendering the template.
+    /// * `context` - The context of the template.
+    /// * `deadline` - The time limit and token of the render, if any.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn call(
+        &self,
+        name: &str,
+        args: &str,
+        inner: &str,
+        source: &str,
+        engine: &Engine,
+        context: &Context,
+        deadline: Option<&Deadline<'_>>,
+    ) -> Result<String, EngineError> {
+        let handler = self.handlers.get(name).ok_or_else(|| {
+            EngineError::Render(format!("Unknown block: {}", name))
+        })?;
+        engine.profile(ProfileKind::Block, name, || {
+            handler(&Block {
+                name,
+                args: parse_args(args),
+                inner,
+                source,
+                engine,
+                context,
+                deadline,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn engine() -> Engine {
+        let mut engine =
+            Engine::new("templates", Duration::from_secs(60));
+        engine.blocks.register("wrap", |block| {
+            Ok(format!(
+                "<{tag}>{}</{tag}>",
+                block.inner(),
+                tag = block.arg("0").unwrap_or("div")
+            ))
+        });
+        engine
+            .blocks
+            .register("raw", |block| Ok(block.source().to_string()));
+        engine
+    }
+
+    #[test]
+    fn test_opening_and_closing() {
+        let engine = engine();
+        assert_eq!(
+            engine.blocks.opening("#wrap p class=\"a\""),
+            Some(("wrap", " p class=\"a\""))
+        );
+        assert_eq!(engine.blocks.opening("# wrap"), Some(("wrap", "")));
+        assert_eq!(engine.blocks.opening("#figure"), None);
+        assert_eq!(engine.blocks.opening("wrap"), None);
+        assert_eq!(engine.blocks.closing("/ wrap "), Some("wrap"));
+        assert_eq!(engine.blocks.closing("/figure"), None);
+    }
+
+    #[test]
+    fn test_render_blocks() {
+        let engine = engine();
+        let mut context = Context::new();
+        context.set("name", "Ada");
+
+        assert_eq!(
+            engine
+                .render_template(
+                    "{{#wrap section}}{{#wrap}}{{name}}{{/wrap}}{{#wrap p}}!{{/wrap}}{{/wrap}}",
+                    &context,
+                )
+                .unwrap(),
+            "<section><div>Ada</div><p>!</p></section>"
+        );
+        assert_eq!(
+            engine
+                .render_template("a{{#raw}}{{name}}{{/raw}}b", &context)
+                .unwrap(),
+            "a{{name}}b"
+        );
+
+        let err = engine
+            .render_template("{{#wrap}}{{name}}", &context)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Render error: Unclosed block: wrap"
+        );
+        let err =
+            engine.render_template("{{/wrap}}", &context).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Render error: Unexpected closing block: wrap"
+        );
+        assert!(engine
+            .render_template("{{#figure}}", &context)
+            .unwrap_err()
+            .to_string()
+            .contains("Unresolved template tag: #figure"));
+    }
+
+    #[test]
+    fn test_block_errors_are_reported() {
+        let engine = engine();
+        let report = engine
+            .render_template_report(
+                "{{#wrap}}\n{{missing}}{{/wrap}}\n{{other}}",
+                &Context::new(),
+            )
+            .unwrap_err();
+        let lines: Vec<usize> = report
+            .problems
+            .iter()
+            .map(|problem| problem.line)
+            .collect();
+        assert_eq!(lines, [2, 3]);
+    }
+}
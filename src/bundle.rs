@@ -0,0 +1,290 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Bundle Module
+//!
+//! This module provides the `ArchiveSink` struct, an
+//! [`OutputSink`](crate::sink::OutputSink) that streams the pages of a
+//! build into a single `.zip` or `.tar.gz` archive, for hosts that
+//! deploy a site bundle. Pages are compressed into the archive as they
+//! are written, without a file per page on disk:
+//!
+//! ```
+//! use staticweaver::bundle::{ArchiveFormat, ArchiveSink};
+//! use staticweaver::loader::MemoryLoader;
+//! use staticweaver::shared::SharedEngine;
+//! use staticweaver::site::{SiteBuilder, SitePage};
+//! use staticweaver::{Context, Engine};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let loader = MemoryLoader::new();
+//! loader.insert("templates/page.html", "<h1>{{title}}</h1>");
+//! let mut engine = Engine::new("templates", Duration::from_secs(60));
+//! engine.set_loader(Arc::new(loader));
+//!
+//! let sink = Arc::new(ArchiveSink::new(ArchiveFormat::Zip, Vec::new()));
+//! let mut builder = SiteBuilder::new(Arc::new(SharedEngine::new(engine)), "public");
+//! builder.set_sink(sink.clone());
+//!
+//! let mut context = Context::new();
+//! context.set("title", "Home");
+//! let report = builder.build(vec![SitePage::new("index.html", "page", context)]);
+//! assert!(report.is_success());
+//! let zip: Vec<u8> = sink.finish().unwrap();
+//! assert!(zip.starts_with(b"PK"));
+//! ```
+//!
+//! The archive is complete once [`ArchiveSink::finish`] writes its
+//! trailer; pages written after that fail. Entries carry no timestamps
+//! or owners, so the same pages make the same archive. An archive
+//! cannot be read back, so a builder with a
+//! [journal](crate::journal) still writes every page into it.
+//!
+//! This module requires the `bundle` feature.
+
+use crate::engine::EngineError;
+use crate::sink::{validate_page_path, OutputSink};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use zip::write::{SimpleFileOptions, StreamWriter};
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+/// The format of a site archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArchiveFormat {
+    /// A zip archive, each page compressed with Deflate.
+    Zip,
+    /// A tar archive compressed with gzip.
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// Infers the format from the file name of `path`: `.zip`, or
+    /// `.tar.gz` and `.tgz`, ignoring case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::bundle::ArchiveFormat;
+    /// use std::path::Path;
+    ///
+    /// assert_eq!(ArchiveFormat::from_path(Path::new("site.tar.gz")), Some(ArchiveFormat::TarGz));
+    /// assert_eq!(ArchiveFormat::from_path(Path::new("site.ZIP")), Some(ArchiveFormat::Zip));
+    /// assert_eq!(ArchiveFormat::from_path(Path::new("site.tar")), None);
+    /// ```
+    #[must_use]
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// The open archive of a sink.
+enum Writer<W: Write> {
+    Zip(ZipWriter<StreamWriter<W>>),
+    TarGz(tar::Builder<GzEncoder<W>>),
+}
+
+/// Streams pages into a single archive, as described in the
+/// [module documentation](self).
+pub struct ArchiveSink<W: Write> {
+    format: ArchiveFormat,
+    writer: Mutex<Option<Writer<W>>>,
+}
+
+impl<W: Write> ArchiveSink<W> {
+    /// Creates a sink writing an archive in `format` to `out`.
+    #[must_use]
+    pub fn new(format: ArchiveFormat, out: W) -> Self {
+        let writer = match format {
+            ArchiveFormat::Zip => {
+                Writer::Zip(ZipWriter::new_stream(out))
+            }
+            ArchiveFormat::TarGz => Writer::TarGz(tar::Builder::new(
+                GzEncoder::new(out, Compression::default()),
+            )),
+        };
+        Self {
+            format,
+            writer: Mutex::new(Some(writer)),
+        }
+    }
+
+    /// Returns the format of the archive.
+    #[must_use]
+    pub fn format(&self) -> ArchiveFormat {
+        self.format
+    }
+
+    /// Writes the trailer of the archive and returns the writer it was
+    /// written to. Pages written afterwards fail.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EngineError::Io` if the trailer cannot be written, or
+    /// if the archive was already finished.
+    pub fn finish(&self) -> Result<W, EngineError> {
+        let writer = self
+            .writer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .ok_or_else(finished)?;
+        Ok(match writer {
+            Writer::Zip(zip) => {
+                zip.finish().map_err(io::Error::from)?.into_inner()
+            }
+            Writer::TarGz(tar) => tar.into_inner()?.finish()?,
+        })
+    }
+}
+
+impl ArchiveSink<BufWriter<File>> {
+    /// Creates the archive file at `path`, in the format its name
+    /// implies.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EngineError::Io` of kind [`io::ErrorKind::InvalidInput`]
+    /// if the name of `path` implies no [`ArchiveFormat`], or any error
+    /// raised while creating the file.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, EngineError> {
+        let path = path.as_ref();
+        let format =
+            ArchiveFormat::from_path(path).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Unknown archive format: {}",
+                        path.display()
+                    ),
+                )
+            })?;
+        Ok(Self::new(format, BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> fmt::Debug for ArchiveSink<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let finished = self
+            .writer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_none();
+        f.debug_struct("ArchiveSink")
+            .field("format", &self.format)
+            .field("finished", &finished)
+            .finish()
+    }
+}
+
+impl<W: Write + Send> OutputSink for ArchiveSink<W> {
+    fn write(
+        &self,
+        path: &str,
+        content: &[u8],
+    ) -> Result<(), EngineError> {
+        validate_page_path(path)?;
+        let mut writer =
+            self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        match writer.as_mut().ok_or_else(finished)? {
+            Writer::Zip(zip) => {
+                let options = SimpleFileOptions::default()
+                    .compression_method(CompressionMethod::Deflated)
+                    .last_modified_time(DateTime::default())
+                    .unix_permissions(0o644);
+                zip.start_file(path, options)
+                    .map_err(io::Error::from)?;
+                zip.write_all(content)?;
+            }
+            Writer::TarGz(tar) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(content.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(0);
+                tar.append_data(&mut header, path, content)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the error of a write to a finished archive.
+fn finished() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "Archive already finished")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn test_zip_archive() {
+        let sink = ArchiveSink::new(ArchiveFormat::Zip, Vec::new());
+        sink.write("index.html", b"<h1>Home</h1>").unwrap();
+        sink.write("blog/post.html", b"<p>Post</p>").unwrap();
+        assert_eq!(
+            sink.write("../escape.html", b"").unwrap_err().code(),
+            "io"
+        );
+        let zip = sink.finish().unwrap();
+        assert!(sink.write("late.html", b"").is_err());
+        assert!(sink.finish().is_err());
+
+        let mut archive =
+            zip::ZipArchive::new(Cursor::new(zip)).unwrap();
+        assert_eq!(archive.len(), 2);
+        let mut page = String::new();
+        let _ = archive
+            .by_name("blog/post.html")
+            .unwrap()
+            .read_to_string(&mut page)
+            .unwrap();
+        assert_eq!(page, "<p>Post</p>");
+    }
+
+    #[test]
+    fn test_tar_gz_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("site.tgz");
+        let sink = ArchiveSink::create(&path).unwrap();
+        assert_eq!(sink.format(), ArchiveFormat::TarGz);
+        sink.write("index.html", b"<h1>Home</h1>").unwrap();
+        sink.finish().unwrap().flush().unwrap();
+
+        let file = File::open(&path).unwrap();
+        let mut archive = tar::Archive::new(GzDecoder::new(file));
+        let pages: Vec<(String, String)> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let mut page = String::new();
+                let _ = entry.read_to_string(&mut page).unwrap();
+                (entry.path().unwrap().display().to_string(), page)
+            })
+            .collect();
+        assert_eq!(
+            pages,
+            [("index.html".to_string(), "<h1>Home</h1>".to_string())]
+        );
+        assert!(
+            ArchiveSink::create(dir.path().join("site.rar")).is_err()
+        );
+    }
+}
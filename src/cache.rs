@@ -1,29 +1,431 @@
 // Copyright © 2024 StaticWeaver. All rights reserved.
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
+use std::borrow::Borrow;
+use std::collections::hash_map::{Entry, RandomState};
 use std::collections::HashMap;
-use std::hash::Hash;
+use std::convert::Infallible;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-/// Represents a cached item with its value and expiration time.
+/// A source of monotonic time for a [`Cache`].
+///
+/// Caches read the [`SystemClock`] by default. Tests can use a
+/// [`ManualClock`] instead, to expire items without sleeping.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+}
+
+/// The clock of the operating system.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when advanced.
+///
+/// Clones share their time, so a test can keep one clone and advance the
+/// clock of the cache it handed the other to.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::cache::{Cache, ManualClock};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::new();
+/// let mut cache = Cache::new(Duration::from_secs(60));
+/// cache.set_clock(Arc::new(clock.clone()));
+/// cache.insert("key", 1);
+///
+/// clock.advance(Duration::from_secs(61));
+/// assert_eq!(cache.get(&"key"), None);
+/// ```
 #[derive(Debug, Clone)]
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    /// Creates a clock stopped at the current time.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - How far to move the clock.
+    pub fn advance(&self, duration: Duration) {
+        let _ = self
+            .elapsed
+            .fetch_add(as_nanos(duration), Ordering::Relaxed);
+    }
+
+    /// Returns how far the clock has been moved since it was created.
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed.load(Ordering::Relaxed))
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+}
+
+/// How the expiration deadline of a cached item is computed.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::cache::{Cache, ExpirationPolicy};
+/// use std::time::Duration;
+///
+/// let mut cache: Cache<String, String> = Cache::new(Duration::from_secs(60));
+/// cache.set_expiration_policy(ExpirationPolicy::SlidingTtl);
+/// assert_eq!(cache.expiration_policy(), ExpirationPolicy::SlidingTtl);
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum ExpirationPolicy {
+    /// Items expire a fixed TTL after they were inserted or refreshed.
+    #[default]
+    AbsoluteTtl,
+    /// Like `AbsoluteTtl`, but every successful `get` also restarts the
+    /// TTL, so frequently read items stay cached.
+    SlidingTtl,
+    /// Items never expire; they stay until removed or cleared.
+    NoExpiry,
+}
+
+impl ExpirationPolicy {
+    /// Returns the deadline of an item stored or refreshed at `now`,
+    /// with a TTL of `ttl`.
+    fn deadline(self, ttl: Duration, now: Stamp) -> u64 {
+        match self {
+            Self::NoExpiry => NEVER,
+            Self::AbsoluteTtl | Self::SlidingTtl => {
+                now.nanos.saturating_add(as_nanos(ttl))
+            }
+        }
+    }
+}
+
+/// The deadline of an item that never expires.
+const NEVER: u64 = u64::MAX;
+
+/// Converts a duration to nanoseconds, saturating at `NEVER`.
+fn as_nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(NEVER)
+}
+
+/// A callback invoked with the key and value of a cache item.
+type Hook<K, V> = Arc<dyn Fn(&K, &V) + Send + Sync>;
+
+/// The callbacks registered on a [`Cache`].
+struct Hooks<K, V> {
+    on_insert: Option<Hook<K, V>>,
+    on_evict: Option<Hook<K, V>>,
+    on_expire: Option<Hook<K, V>>,
+}
+
+impl<K, V> Hooks<K, V> {
+    /// Calls `hook`, if registered, with `key` and `value`.
+    fn fire(hook: &Option<Hook<K, V>>, key: &K, value: &V) {
+        if let Some(hook) = hook {
+            hook(key, value);
+        }
+    }
+
+    /// Reports an item dropped at `now` as evicted, or as expired if it
+    /// had already expired.
+    fn fire_removed(&self, key: &K, item: &CachedItem<V>, now: Stamp) {
+        if item.is_live(now) {
+            Self::fire(&self.on_evict, key, &item.value);
+        } else {
+            Self::fire(&self.on_expire, key, &item.value);
+        }
+    }
+}
+
+impl<K, V> Default for Hooks<K, V> {
+    fn default() -> Self {
+        Self {
+            on_insert: None,
+            on_evict: None,
+            on_expire: None,
+        }
+    }
+}
+
+impl<K, V> Clone for Hooks<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            on_insert: self.on_insert.clone(),
+            on_evict: self.on_evict.clone(),
+            on_expire: self.on_expire.clone(),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for Hooks<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_insert", &self.on_insert.is_some())
+            .field("on_evict", &self.on_evict.is_some())
+            .field("on_expire", &self.on_expire.is_some())
+            .finish()
+    }
+}
+
+/// A function returning the weight of a cache value.
+type Weigher<V> = Arc<dyn Fn(&V) -> usize + Send + Sync>;
+
+/// The weight budget of a [`Cache`], and the recency of its items.
+struct Budget<V> {
+    weigher: Option<Weigher<V>>,
+    max_weight: Option<usize>,
+    /// The total weight of the stored items, expired ones included.
+    weight: usize,
+    /// Hands out increasing ticks, so that the least recently used item
+    /// has the lowest.
+    ticks: AtomicU64,
+}
+
+impl<V> Budget<V> {
+    /// Returns the weight of `value`, which is 1 without a weigher.
+    fn weigh(&self, value: &V) -> usize {
+        self.weigher.as_ref().map_or(1, |weigher| weigher(value))
+    }
+
+    /// Returns the next recency tick.
+    fn tick(&self) -> u64 {
+        self.ticks.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Returns whether an item of `weight` exceeds the whole budget, so
+    /// that it can never be stored.
+    fn exceeds(&self, weight: usize) -> bool {
+        self.max_weight
+            .map_or(false, |max_weight| weight > max_weight)
+    }
+
+    /// Returns whether an item of `weight` fits in the budget without
+    /// dropping other items, once an item of `replaced` weight is gone.
+    fn fits(&self, weight: usize, replaced: usize) -> bool {
+        self.max_weight.map_or(true, |max_weight| {
+            self.weight - replaced + weight <= max_weight
+        })
+    }
+}
+
+impl<V> Default for Budget<V> {
+    fn default() -> Self {
+        Self {
+            weigher: None,
+            max_weight: None,
+            weight: 0,
+            ticks: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<V> Clone for Budget<V> {
+    fn clone(&self) -> Self {
+        Self {
+            weigher: self.weigher.clone(),
+            max_weight: self.max_weight,
+            weight: self.weight,
+            ticks: AtomicU64::new(self.ticks.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl<V> fmt::Debug for Budget<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Budget")
+            .field("weigher", &self.weigher.is_some())
+            .field("max_weight", &self.max_weight)
+            .field("weight", &self.weight)
+            .finish()
+    }
+}
+
+/// A point in time as seen by a cache.
+#[derive(Debug, Clone, Copy)]
+struct Stamp {
+    /// Nanoseconds since the cache's epoch.
+    nanos: u64,
+    /// The cache's generation; items from older generations are stale.
+    generation: u64,
+}
+
+impl Stamp {
+    /// Returns the time of `clock` since `epoch`, or zero before the
+    /// epoch is set, in `generation`.
+    fn at(
+        clock: &dyn Clock,
+        epoch: Option<Instant>,
+        generation: u64,
+    ) -> Self {
+        Self {
+            nanos: epoch.map_or(0, |epoch| {
+                as_nanos(clock.now().saturating_duration_since(epoch))
+            }),
+            generation,
+        }
+    }
+}
+
+/// Represents a cached item with its value and expiration time.
+///
+/// The deadline is stored in nanoseconds since the cache's epoch, in an
+/// atomic so that sliding expiration can extend it from `&self` lookups.
+#[derive(Debug)]
 struct CachedItem<T> {
     value: T,
-    expiration: Instant,
+    deadline: AtomicU64,
+    generation: u64,
+    weight: usize,
+    /// The recency tick of the last store or hit.
+    last_used: AtomicU64,
+}
+
+impl<T> CachedItem<T> {
+    fn new(
+        value: T,
+        deadline: u64,
+        generation: u64,
+        weight: usize,
+        last_used: u64,
+    ) -> Self {
+        Self {
+            value,
+            deadline: AtomicU64::new(deadline),
+            generation,
+            weight,
+            last_used: AtomicU64::new(last_used),
+        }
+    }
+
+    fn deadline(&self) -> u64 {
+        self.deadline.load(Ordering::Relaxed)
+    }
+
+    fn set_deadline(&self, deadline: u64) {
+        self.deadline.store(deadline, Ordering::Relaxed);
+    }
+
+    fn is_live(&self, now: Stamp) -> bool {
+        self.generation == now.generation && self.deadline() > now.nanos
+    }
+
+    fn last_used(&self) -> u64 {
+        self.last_used.load(Ordering::Relaxed)
+    }
+
+    fn set_last_used(&self, tick: u64) {
+        self.last_used.store(tick, Ordering::Relaxed);
+    }
+}
+
+impl<T: Clone> Clone for CachedItem<T> {
+    fn clone(&self) -> Self {
+        Self::new(
+            self.value.clone(),
+            self.deadline(),
+            self.generation,
+            self.weight,
+            self.last_used(),
+        )
+    }
 }
 
 /// A simple cache implementation with expiration and optional capacity limit.
 ///
 /// This cache provides time-based expiration for items and an optional maximum capacity.
 /// It's designed to be generic over both key and value types for maximum flexibility.
+///
+/// Values need not implement `Clone`. Store large values as `Arc<V>` and
+/// read them with [`Cache::get_shared`] so a hit only bumps a reference
+/// count instead of copying the value.
+///
+/// Expired items are skipped by lookups but stay in memory until they are
+/// removed. Call [`Cache::remove_expired`] periodically, or enable
+/// automatic sweeping with [`Cache::set_sweep_interval`].
+///
+/// Callbacks registered with [`Cache::on_insert`], [`Cache::on_evict`],
+/// and [`Cache::on_expire`] observe every change to the stored items.
+///
+/// A cache can also hold a weight budget, set with
+/// [`Cache::set_max_weight`]. Each item weighs what the function given
+/// to [`Cache::set_weigher`] returns, such as the size of a compiled
+/// template, and the least recently used items are evicted to keep the
+/// total within the budget.
+///
+/// With the `serde` feature, a cache can be serialized together with the
+/// remaining TTL of each live item and reloaded later, for example to
+/// keep rendered pages across restarts of a preview server.
+///
+/// Keys are hashed with SipHash by default, which resists collision
+/// attacks from untrusted keys. Caches of trusted keys can trade that
+/// for speed with a faster hasher `S`, such as
+/// [`FnvBuildHasher`](fnv::FnvBuildHasher) or `ahash::RandomState`:
+///
+/// ```
+/// use fnv::FnvBuildHasher;
+/// use staticweaver::cache::Cache;
+/// use std::time::Duration;
+///
+/// let mut cache: Cache<String, u32, FnvBuildHasher> =
+///     Cache::with_hasher(Duration::from_secs(60), FnvBuildHasher::default());
+/// let _ = cache.insert("answer".to_string(), 42);
+/// assert_eq!(cache.get(&"answer".to_string()), Some(&42));
+/// ```
 #[derive(Debug, Clone)]
-pub struct Cache<K, V> {
-    items: HashMap<K, CachedItem<V>>,
+pub struct Cache<K, V, S = RandomState> {
+    items: HashMap<K, CachedItem<V>, S>,
     ttl: Duration,
+    policy: ExpirationPolicy,
+    /// Set when the first item is stored, so that caches can be created
+    /// on platforms without a clock, such as `wasm32-unknown-unknown`.
+    epoch: Option<Instant>,
+    generation: u64,
     capacity: Option<usize>,
+    sweep_interval: Option<Duration>,
+    last_sweep: Option<Instant>,
+    hooks: Hooks<K, V>,
+    budget: Budget<V>,
+    clock: Arc<dyn Clock>,
 }
 
-impl<K: Hash + Eq, V: Clone> Cache<K, V> {
+impl<K: Hash + Eq, V> Cache<K, V> {
     /// Creates a new Cache with the specified time-to-live (TTL) for items.
     ///
     /// # Arguments
@@ -44,23 +446,101 @@ impl<K: Hash + Eq, V: Clone> Cache<K, V> {
     /// ```
     #[must_use]
     pub fn new(ttl: Duration) -> Self {
+        Self::with_hasher(ttl, RandomState::new())
+    }
+
+    /// Creates a new Cache with the specified TTL and initial capacity.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - The time-to-live for cached items.
+    /// * `capacity` - The initial capacity of the cache.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ttl` is zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use staticweaver::cache::Cache;
+    /// use std::time::Duration;
+    ///
+    /// let cache: Cache<String, String> = Cache::with_capacity(Duration::from_secs(60), 100);
+    /// ```
+    #[must_use]
+    pub fn with_capacity(ttl: Duration, capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(
+            ttl,
+            capacity,
+            RandomState::new(),
+        )
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> Cache<K, V, S> {
+    /// Creates a new Cache with the specified TTL, hashing keys with
+    /// `hash_builder`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - The time-to-live for cached items.
+    /// * `hash_builder` - The hasher of the keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ttl` is zero.
+    #[must_use]
+    pub fn with_hasher(ttl: Duration, hash_builder: S) -> Self {
         assert!(!ttl.is_zero(), "TTL must be greater than zero");
         Self {
-            items: HashMap::new(),
+            items: HashMap::with_hasher(hash_builder),
             ttl,
+            policy: ExpirationPolicy::default(),
+            epoch: None,
+            generation: 0,
             capacity: None,
+            sweep_interval: None,
+            last_sweep: None,
+            hooks: Hooks::default(),
+            budget: Budget::default(),
+            clock: Arc::new(SystemClock),
         }
     }
 
+    /// Creates a new Cache with the specified TTL and initial capacity,
+    /// hashing keys with `hash_builder`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - The time-to-live for cached items.
+    /// * `capacity` - The initial capacity of the cache.
+    /// * `hash_builder` - The hasher of the keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ttl` is zero.
+    #[must_use]
+    pub fn with_capacity_and_hasher(
+        ttl: Duration,
+        capacity: usize,
+        hash_builder: S,
+    ) -> Self {
+        let mut cache = Self::with_hasher(ttl, hash_builder);
+        cache.items.reserve(capacity);
+        cache.capacity = Some(capacity);
+        cache
+    }
+
     /// Returns an iterator over the key-value pairs in the cache.
     ///
     /// # Returns
     ///
     /// An iterator over the key-value pairs in the cache.
     pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
-        let now = Instant::now();
+        let now = self.now();
         self.items.iter().filter_map(move |(k, item)| {
-            if item.expiration > now {
+            if item.is_live(now) {
                 Some((k, &item.value))
             } else {
                 None
@@ -68,16 +548,408 @@ pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
         })
     }
 
-    /// Creates a new Cache with the specified TTL and initial capacity.
+    /// Inserts a key-value pair into the cache.
+    ///
+    /// If the cache is at capacity and the key doesn't already exist, the new item won't be inserted.
+    /// Under a weight budget, least recently used items are evicted to
+    /// make room, and an item heavier than the whole budget won't be
+    /// inserted.
     ///
     /// # Arguments
     ///
-    /// * `ttl` - The time-to-live for cached items.
-    /// * `capacity` - The initial capacity of the cache.
+    /// * `key` - The key to insert.
+    /// * `value` - The value to insert.
     ///
-    /// # Panics
+    /// # Returns
+    ///
+    /// The old value if the key was already present.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use staticweaver::cache::Cache;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Cache::new(Duration::from_secs(60));
+    /// cache.insert("key".to_string(), "value".to_string());
+    /// ```
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.sweep_if_due();
+        if let Some(cap) = self.capacity {
+            if self.items.len() >= cap && !self.items.contains_key(&key)
+            {
+                return None; // Cache is at capacity
+            }
+        }
+        let weight = self.budget.weigh(&value);
+        if !self.make_room(Some(&key), weight) {
+            return None;
+        }
+        let now = self.start_clock();
+        let item = self.new_item(value, weight, now);
+        self.budget.weight += weight;
+        match self.items.entry(key) {
+            Entry::Occupied(mut entry) => {
+                Hooks::fire(
+                    &self.hooks.on_insert,
+                    entry.key(),
+                    &item.value,
+                );
+                let old_item = entry.insert(item);
+                self.budget.weight -= old_item.weight;
+                Some(old_item.value)
+            }
+            Entry::Vacant(entry) => {
+                Hooks::fire(
+                    &self.hooks.on_insert,
+                    entry.key(),
+                    &item.value,
+                );
+                let _ = entry.insert(item);
+                None
+            }
+        }
+    }
+
+    /// Retrieves a value from the cache if it exists and hasn't expired.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to look up.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing a reference to the value if it exists and hasn't expired, or `None` otherwise.
+    ///
+    /// Under [`ExpirationPolicy::SlidingTtl`], a hit restarts the item's TTL.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use staticweaver::cache::Cache;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Cache::new(Duration::from_secs(60));
+    /// cache.insert("key".to_string(), "value".to_string());
+    ///
+    /// // Any borrowed form of the key works, so lookups need not allocate.
+    /// assert_eq!(cache.get("key"), Some(&"value".to_string()));
+    /// ```
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.now();
+        self.items.get(key).and_then(|item| {
+            if item.is_live(now) {
+                self.touch(item, now);
+                Some(&item.value)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Returns the unexpired value for `key`, or computes, stores, and
+    /// returns a new one, with a single lookup.
+    ///
+    /// An expired entry is replaced. If the cache is at capacity and
+    /// `key` is not present, or the value is heavier than the weight
+    /// budget, the computed value is returned without being stored. The
+    /// value is cloned out of the cache, which is a pointer bump for
+    /// `Arc` values.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to look up.
+    /// * `compute` - Produces the value when no unexpired entry exists.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use staticweaver::cache::Cache;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Cache::new(Duration::from_secs(60));
+    /// let value = cache.get_or_insert_with("key".to_string(), || 42);
+    /// assert_eq!(value, 42);
+    /// let value = cache.get_or_insert_with("key".to_string(), || 0);
+    /// assert_eq!(value, 42);
+    /// ```
+    pub fn get_or_insert_with<F>(&mut self, key: K, compute: F) -> V
+    where
+        V: Clone,
+        F: FnOnce() -> V,
+    {
+        match self.try_get_or_insert_with(key, || {
+            Ok::<V, Infallible>(compute())
+        }) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Like [`Cache::get_or_insert_with`], but `compute` may fail.
+    ///
+    /// Nothing is stored when `compute` returns an error, and an expired
+    /// entry for `key` is left in place. When an expired entry is
+    /// replaced, its `on_expire` hook fires before the `on_insert` hook
+    /// of the new value.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to look up.
+    /// * `compute` - Produces the value when no unexpired entry exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error produced by `compute`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use staticweaver::cache::Cache;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache: Cache<String, u32> = Cache::new(Duration::from_secs(60));
+    /// let result = cache.try_get_or_insert_with("port".to_string(), || "80".parse());
+    /// assert_eq!(result, Ok(80));
+    /// ```
+    pub fn try_get_or_insert_with<E, F>(
+        &mut self,
+        key: K,
+        compute: F,
+    ) -> Result<V, E>
+    where
+        V: Clone,
+        F: FnOnce() -> Result<V, E>,
+    {
+        self.sweep_if_due();
+        let now = self.start_clock();
+        let full =
+            self.capacity.map_or(false, |cap| self.items.len() >= cap);
+        let Self {
+            items,
+            hooks,
+            budget,
+            policy,
+            ttl,
+            clock,
+            epoch,
+            ..
+        } = self;
+        // Stores the computed value in place when it fits in the weight
+        // budget, and otherwise hands the key back to make room below.
+        let (key, value, weight) = match items.entry(key) {
+            Entry::Occupied(mut entry) => {
+                let item = entry.get();
+                if item.is_live(now) {
+                    item.set_last_used(budget.tick());
+                    if *policy == ExpirationPolicy::SlidingTtl {
+                        item.set_deadline(policy.deadline(*ttl, now));
+                    }
+                    return Ok(item.value.clone());
+                }
+                let value = compute()?;
+                let weight = budget.weigh(&value);
+                if budget.exceeds(weight) {
+                    return Ok(value);
+                }
+                if budget.fits(weight, item.weight) {
+                    let now =
+                        Stamp::at(&**clock, *epoch, now.generation);
+                    let old_item = entry.insert(CachedItem::new(
+                        value.clone(),
+                        policy.deadline(*ttl, now),
+                        now.generation,
+                        weight,
+                        budget.tick(),
+                    ));
+                    budget.weight =
+                        budget.weight - old_item.weight + weight;
+                    Hooks::fire(
+                        &hooks.on_expire,
+                        entry.key(),
+                        &old_item.value,
+                    );
+                    Hooks::fire(&hooks.on_insert, entry.key(), &value);
+                    return Ok(value);
+                }
+                let (key, old_item) = entry.remove_entry();
+                budget.weight -= old_item.weight;
+                Hooks::fire(&hooks.on_expire, &key, &old_item.value);
+                (key, value, weight)
+            }
+            Entry::Vacant(entry) => {
+                let value = compute()?;
+                let weight = budget.weigh(&value);
+                if full || budget.exceeds(weight) {
+                    return Ok(value);
+                }
+                if budget.fits(weight, 0) {
+                    let now =
+                        Stamp::at(&**clock, *epoch, now.generation);
+                    Hooks::fire(&hooks.on_insert, entry.key(), &value);
+                    let _ = entry.insert(CachedItem::new(
+                        value.clone(),
+                        policy.deadline(*ttl, now),
+                        now.generation,
+                        weight,
+                        budget.tick(),
+                    ));
+                    budget.weight += weight;
+                    return Ok(value);
+                }
+                (entry.into_key(), value, weight)
+            }
+        };
+
+        let _ = self.make_room(None::<&K>, weight);
+        let item = self.new_item(value.clone(), weight, self.now());
+        self.budget.weight += weight;
+        Hooks::fire(&self.hooks.on_insert, &key, &value);
+        let _ = self.items.insert(key, item);
+        Ok(value)
+    }
+
+    /// Removes expired items from the cache.
+    ///
+    /// This method should be called periodically to clean up the cache.
+    ///
+    /// Time complexity: O(n) where n is the number of items in the cache.
+    pub fn remove_expired(&mut self) {
+        let now = self.now();
+        let on_expire = &self.hooks.on_expire;
+        let mut dropped = 0;
+        self.items.retain(|key, item| {
+            let live = item.is_live(now);
+            if !live {
+                Hooks::fire(on_expire, key, &item.value);
+                dropped += item.weight;
+            }
+            live
+        });
+        self.budget.weight -= dropped;
+        self.last_sweep = Some(self.clock.now());
+    }
+
+    /// Registers a callback invoked with the key and value of every item
+    /// stored by [`Cache::insert`], [`Cache::update`], or
+    /// [`Cache::get_or_insert_with`].
+    ///
+    /// Registering a callback replaces the previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - The callback.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use staticweaver::cache::Cache;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache: Cache<String, String> = Cache::new(Duration::from_secs(60));
+    /// cache.on_insert(|key, _| println!("cached {}", key));
+    /// cache.insert("page".to_string(), "<html></html>".to_string());
+    /// ```
+    pub fn on_insert<F>(&mut self, hook: F)
+    where
+        F: Fn(&K, &V) + Send + Sync + 'static,
+    {
+        self.hooks.on_insert = Some(Arc::new(hook));
+    }
+
+    /// Registers a callback invoked with the key and value of every item
+    /// removed before it expired, by [`Cache::remove`] or
+    /// [`Cache::clear`].
+    ///
+    /// Values replaced by a new insert are not reported.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - The callback.
+    pub fn on_evict<F>(&mut self, hook: F)
+    where
+        F: Fn(&K, &V) + Send + Sync + 'static,
+    {
+        self.hooks.on_evict = Some(Arc::new(hook));
+    }
+
+    /// Registers a callback invoked with the key and value of every
+    /// expired item as it is dropped from the cache, whether by
+    /// [`Cache::remove_expired`], an automatic sweep, or being replaced
+    /// by [`Cache::get_or_insert_with`].
+    ///
+    /// Expired items are only reported once they are actually dropped,
+    /// not at the moment they expire.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - The callback.
+    pub fn on_expire<F>(&mut self, hook: F)
+    where
+        F: Fn(&K, &V) + Send + Sync + 'static,
+    {
+        self.hooks.on_expire = Some(Arc::new(hook));
+    }
+
+    /// Enables or disables automatic removal of expired items.
+    ///
+    /// With an interval set, inserts run [`Cache::remove_expired`] once
+    /// at least `interval` has passed since the previous sweep, so the
+    /// cleanup cost is amortized over writes and no background thread is
+    /// needed. `None` disables automatic sweeping.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The minimum time between sweeps.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use staticweaver::cache::Cache;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache: Cache<String, String> = Cache::new(Duration::from_secs(60));
+    /// cache.set_sweep_interval(Some(Duration::from_secs(30)));
+    /// assert_eq!(cache.sweep_interval(), Some(Duration::from_secs(30)));
+    /// ```
+    pub fn set_sweep_interval(&mut self, interval: Option<Duration>) {
+        self.sweep_interval = interval;
+    }
+
+    /// Returns the automatic sweep interval, if enabled.
+    #[must_use]
+    pub fn sweep_interval(&self) -> Option<Duration> {
+        self.sweep_interval
+    }
+
+    /// Sets how item deadlines are computed.
+    ///
+    /// The policy applies to items stored or accessed from now on;
+    /// deadlines of existing items are left as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The expiration policy.
+    pub fn set_expiration_policy(&mut self, policy: ExpirationPolicy) {
+        self.policy = policy;
+    }
+
+    /// Returns the expiration policy.
+    #[must_use]
+    pub fn expiration_policy(&self) -> ExpirationPolicy {
+        self.policy
+    }
+
+    /// Marks every item as expired in constant time.
     ///
-    /// Panics if `ttl` is zero.
+    /// Items are not dropped immediately; they are reported to the
+    /// [`Cache::on_expire`] callback and freed by the next
+    /// [`Cache::remove_expired`] or automatic sweep, like items whose TTL
+    /// has run out.
     ///
     /// # Example
     ///
@@ -85,30 +957,23 @@ pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
     /// use staticweaver::cache::Cache;
     /// use std::time::Duration;
     ///
-    /// let cache: Cache<String, String> = Cache::with_capacity(Duration::from_secs(60), 100);
+    /// let mut cache = Cache::new(Duration::from_secs(60));
+    /// cache.insert("key".to_string(), 1);
+    /// cache.invalidate_all();
+    /// assert_eq!(cache.get(&"key".to_string()), None);
     /// ```
-    #[must_use]
-    pub fn with_capacity(ttl: Duration, capacity: usize) -> Self {
-        assert!(!ttl.is_zero(), "TTL must be greater than zero");
-        Self {
-            items: HashMap::with_capacity(capacity),
-            ttl,
-            capacity: Some(capacity),
-        }
+    pub fn invalidate_all(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
     }
 
-    /// Inserts a key-value pair into the cache.
+    /// Removes every item for which `keep` returns `false`.
     ///
-    /// If the cache is at capacity and the key doesn't already exist, the new item won't be inserted.
+    /// Removed items are reported to the [`Cache::on_evict`] callback, or
+    /// to [`Cache::on_expire`] if they had already expired.
     ///
     /// # Arguments
     ///
-    /// * `key` - The key to insert.
-    /// * `value` - The value to insert.
-    ///
-    /// # Returns
-    ///
-    /// The old value if the key was already present.
+    /// * `keep` - Decides, from the key and value, whether an item stays.
     ///
     /// # Example
     ///
@@ -117,30 +982,40 @@ pub fn with_capacity(ttl: Duration, capacity: usize) -> Self {
     /// use std::time::Duration;
     ///
     /// let mut cache = Cache::new(Duration::from_secs(60));
-    /// cache.insert("key".to_string(), "value".to_string());
+    /// cache.insert("post:1".to_string(), 1);
+    /// cache.insert("page:1".to_string(), 2);
+    /// cache.retain(|key, _| !key.starts_with("post:"));
+    /// assert_eq!(cache.len(), 1);
     /// ```
-    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
-        if let Some(cap) = self.capacity {
-            if self.items.len() >= cap && !self.items.contains_key(&key)
-            {
-                return None; // Cache is at capacity
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let now = self.now();
+        let hooks = &self.hooks;
+        let mut dropped = 0;
+        self.items.retain(|key, item| {
+            if keep(key, &item.value) {
+                return true;
             }
-        }
-        let expiration = Instant::now() + self.ttl;
-        self.items
-            .insert(key, CachedItem { value, expiration })
-            .map(|old_item| old_item.value)
+            hooks.fire_removed(key, item, now);
+            dropped += item.weight;
+            false
+        });
+        self.budget.weight -= dropped;
     }
 
-    /// Retrieves a value from the cache if it exists and hasn't expired.
+    /// Sets the function returning the weight of a value, counted
+    /// against the budget set with [`Cache::set_max_weight`].
     ///
-    /// # Arguments
-    ///
-    /// * `key` - The key to look up.
+    /// Without a weigher, every item weighs 1, so the budget caps the
+    /// number of items. The weigher applies to items stored from now
+    /// on.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// An `Option` containing a reference to the value if it exists and hasn't expired, or `None` otherwise.
+    /// * `weigher` - Returns the weight of a value, such as its size in
+    ///   bytes.
     ///
     /// # Example
     ///
@@ -148,29 +1023,213 @@ pub fn insert(&mut self, key: K, value: V) -> Option<V> {
     /// use staticweaver::cache::Cache;
     /// use std::time::Duration;
     ///
-    /// let mut cache = Cache::new(Duration::from_secs(60));
-    /// cache.insert("key".to_string(), "value".to_string());
+    /// let mut cache: Cache<&str, String> = Cache::new(Duration::from_secs(60));
+    /// cache.set_weigher(|page: &String| page.len());
+    /// cache.set_max_weight(Some(10));
     ///
-    /// assert_eq!(cache.get(&"key".to_string()), Some(&"value".to_string()));
+    /// cache.insert("a", "x".repeat(4));
+    /// cache.insert("b", "x".repeat(4));
+    /// let _ = cache.get("a");
+    /// cache.insert("c", "x".repeat(4));
+    ///
+    /// // "b" was the least recently used item.
+    /// assert!(cache.contains_key("a") && cache.contains_key("c"));
+    /// assert!(!cache.contains_key("b"));
+    /// assert_eq!(cache.weight(), 8);
     /// ```
-    pub fn get(&self, key: &K) -> Option<&V> {
-        self.items.get(key).and_then(|item| {
-            if item.expiration > Instant::now() {
-                Some(&item.value)
-            } else {
-                None
-            }
-        })
+    pub fn set_weigher<F>(&mut self, weigher: F)
+    where
+        F: Fn(&V) -> usize + Send + Sync + 'static,
+    {
+        self.budget.weigher = Some(Arc::new(weigher));
     }
 
-    /// Removes expired items from the cache.
+    /// Sets the maximum total weight of the stored items, or removes
+    /// the budget with `None`.
     ///
-    /// This method should be called periodically to clean up the cache.
+    /// When the budget is exceeded, expired items are dropped first,
+    /// then the least recently stored or read items are evicted and
+    /// reported to the [`Cache::on_evict`] callback. Lowering the budget
+    /// evicts immediately.
     ///
-    /// Time complexity: O(n) where n is the number of items in the cache.
-    pub fn remove_expired(&mut self) {
-        let now = Instant::now();
-        self.items.retain(|_, item| item.expiration > now);
+    /// # Arguments
+    ///
+    /// * `max_weight` - The budget.
+    pub fn set_max_weight(&mut self, max_weight: Option<usize>) {
+        self.budget.max_weight = max_weight;
+        let _ = self.make_room::<K>(None, 0);
+    }
+
+    /// Returns the weight budget, if set.
+    #[must_use]
+    pub fn max_weight(&self) -> Option<usize> {
+        self.budget.max_weight
+    }
+
+    /// Returns the total weight of the stored items, including expired
+    /// items that have not been dropped yet.
+    #[must_use]
+    pub fn weight(&self) -> usize {
+        self.budget.weight
+    }
+
+    /// Sets the clock the cache reads, such as a [`ManualClock`] in
+    /// tests.
+    ///
+    /// Deadlines of stored items are kept, so the new clock should not
+    /// run behind the previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - The clock.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Returns the clock the cache reads.
+    #[must_use]
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// Returns the current time and generation of the cache.
+    ///
+    /// Before the first item is stored, the time is zero.
+    fn now(&self) -> Stamp {
+        Stamp::at(&*self.clock, self.epoch, self.generation)
+    }
+
+    /// Starts the cache's clock if needed, then returns [`Cache::now`].
+    fn start_clock(&mut self) -> Stamp {
+        if self.epoch.is_none() {
+            self.epoch = Some(self.clock.now());
+        }
+        self.now()
+    }
+
+    /// Returns the deadline of an item stored or refreshed at `now`.
+    fn next_deadline(&self, now: Stamp) -> u64 {
+        self.policy.deadline(self.ttl, now)
+    }
+
+    /// Marks a live item as recently used, and restarts its TTL under
+    /// the sliding policy.
+    fn touch(&self, item: &CachedItem<V>, now: Stamp) {
+        item.set_last_used(self.budget.tick());
+        if self.policy == ExpirationPolicy::SlidingTtl {
+            item.set_deadline(self.next_deadline(now));
+        }
+    }
+
+    /// Returns a new item holding `value`, stored at `now`.
+    fn new_item(
+        &self,
+        value: V,
+        weight: usize,
+        now: Stamp,
+    ) -> CachedItem<V> {
+        CachedItem::new(
+            value,
+            self.next_deadline(now),
+            now.generation,
+            weight,
+            self.budget.tick(),
+        )
+    }
+
+    /// Drops items until an item of `weight` fits in the weight budget,
+    /// replacing the item of `replaced` if any: first the expired items,
+    /// then the least recently used ones.
+    ///
+    /// Returns `false`, dropping nothing, if `weight` exceeds the whole
+    /// budget.
+    fn make_room<Q>(
+        &mut self,
+        replaced: Option<&Q>,
+        weight: usize,
+    ) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(max_weight) = self.budget.max_weight else {
+            return true;
+        };
+        if weight > max_weight {
+            return false;
+        }
+        let excess = |cache: &Self| {
+            let freed = replaced
+                .and_then(|key| cache.items.get(key))
+                .map_or(0, |item| item.weight);
+            (cache.budget.weight - freed + weight)
+                .saturating_sub(max_weight)
+        };
+        if excess(self) > 0 {
+            self.remove_expired();
+            let needed = excess(self);
+            if needed > 0 {
+                self.evict_lru(needed, replaced);
+            }
+        }
+        true
+    }
+
+    /// Evicts the least recently used items other than `keep` until
+    /// their weights add up to at least `needed`.
+    fn evict_lru<Q>(&mut self, needed: usize, keep: Option<&Q>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut candidates: Vec<(u64, usize)> = self
+            .items
+            .iter()
+            .filter(|(key, _)| keep != Some((*key).borrow()))
+            .map(|(_, item)| (item.last_used(), item.weight))
+            .collect();
+        candidates.sort_unstable();
+
+        let mut freed = 0;
+        let mut threshold = None;
+        for (last_used, weight) in candidates {
+            if freed >= needed {
+                break;
+            }
+            freed += weight;
+            threshold = Some(last_used);
+        }
+        let Some(threshold) = threshold else {
+            return;
+        };
+
+        let now = self.now();
+        let hooks = &self.hooks;
+        let mut dropped = 0;
+        self.items.retain(|key, item| {
+            if keep == Some(key.borrow())
+                || item.last_used() > threshold
+            {
+                return true;
+            }
+            hooks.fire_removed(key, item, now);
+            dropped += item.weight;
+            false
+        });
+        self.budget.weight -= dropped;
+    }
+
+    /// Removes expired items if the sweep interval has elapsed.
+    fn sweep_if_due(&mut self) {
+        if let Some(interval) = self.sweep_interval {
+            let due = self.last_sweep.map_or(true, |last_sweep| {
+                self.clock.now().saturating_duration_since(last_sweep)
+                    >= interval
+            });
+            if due {
+                self.remove_expired();
+            }
+        }
     }
 
     /// Checks if a key exists in the cache and hasn't expired.
@@ -182,10 +1241,13 @@ pub fn remove_expired(&mut self) {
     /// # Returns
     ///
     /// `true` if the key exists and hasn't expired, `false` otherwise.
-    pub fn contains_key(&self, key: &K) -> bool {
-        self.items
-            .get(key)
-            .map_or(false, |item| item.expiration > Instant::now())
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.now();
+        self.items.get(key).map_or(false, |item| item.is_live(now))
     }
 
     /// Gets the remaining time-to-live for an item.
@@ -197,13 +1259,22 @@ pub fn contains_key(&self, key: &K) -> bool {
     /// # Returns
     ///
     /// An `Option` containing the remaining TTL if the item exists and hasn't expired, or `None` otherwise.
-    pub fn ttl(&self, key: &K) -> Option<Duration> {
+    /// Items that never expire report `Duration::MAX`.
+    pub fn ttl<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.now();
         self.items.get(key).and_then(|item| {
-            let now = Instant::now();
-            if item.expiration > now {
-                Some(item.expiration - now)
-            } else {
-                None
+            if !item.is_live(now) {
+                return None;
+            }
+            match item.deadline() {
+                NEVER => Some(Duration::MAX),
+                deadline => {
+                    Some(Duration::from_nanos(deadline - now.nanos))
+                }
             }
         })
     }
@@ -217,12 +1288,20 @@ pub fn ttl(&self, key: &K) -> Option<Duration> {
     /// # Returns
     ///
     /// `true` if the item was found and refreshed, `false` otherwise.
-    pub fn refresh(&mut self, key: &K) -> bool {
-        if let Some(item) = self.items.get_mut(key) {
-            item.expiration = Instant::now() + self.ttl;
-            true
-        } else {
-            false
+    /// Items invalidated by [`Cache::invalidate_all`] cannot be refreshed.
+    pub fn refresh<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.now();
+        let deadline = self.next_deadline(now);
+        match self.items.get(key) {
+            Some(item) if item.generation == now.generation => {
+                item.set_deadline(deadline);
+                true
+            }
+            _ => false,
         }
     }
 
@@ -235,8 +1314,15 @@ pub fn refresh(&mut self, key: &K) -> bool {
     /// # Returns
     ///
     /// The removed value if the key was present, or `None` otherwise.
-    pub fn remove(&mut self, key: &K) -> Option<V> {
-        self.items.remove(key).map(|item| item.value)
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, item) = self.items.remove_entry(key)?;
+        self.hooks.fire_removed(&key, &item, self.now());
+        self.budget.weight -= item.weight;
+        Some(item.value)
     }
 
     /// Updates the value for an existing key in the cache.
@@ -248,11 +1334,32 @@ pub fn remove(&mut self, key: &K) -> Option<V> {
     ///
     /// # Returns
     ///
-    /// `true` if the key was found and updated, `false` otherwise.
-    pub fn update(&mut self, key: &K, value: V) -> bool {
+    /// `true` if the key was found and updated, `false` otherwise, or if
+    /// the value is heavier than the whole weight budget.
+    pub fn update<Q>(&mut self, key: &Q, value: V) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let weight = self.budget.weigh(&value);
+        if !self.items.contains_key(key)
+            || !self.make_room(Some(key), weight)
+        {
+            return false;
+        }
+        let now = self.now();
+        let deadline = self.next_deadline(now);
+        if let Some((stored, _)) = self.items.get_key_value(key) {
+            Hooks::fire(&self.hooks.on_insert, stored, &value);
+        }
         if let Some(item) = self.items.get_mut(key) {
+            self.budget.weight =
+                self.budget.weight - item.weight + weight;
             item.value = value;
-            item.expiration = Instant::now() + self.ttl;
+            item.weight = weight;
+            item.set_deadline(deadline);
+            item.set_last_used(self.budget.tick());
+            item.generation = now.generation;
             true
         } else {
             false
@@ -272,7 +1379,11 @@ pub fn set_capacity(&mut self, capacity: usize) {
 
     /// Clears all items from the cache.
     pub fn clear(&mut self) {
-        self.items.clear();
+        let now = self.now();
+        for (key, item) in self.items.drain() {
+            self.hooks.fire_removed(&key, &item, now);
+        }
+        self.budget.weight = 0;
     }
 
     /// Returns the number of items in the cache.
@@ -296,28 +1407,65 @@ pub fn is_empty(&self) -> bool {
     }
 }
 
-impl<K: Hash + Eq, V: Clone> IntoIterator for Cache<K, V> {
+impl<K: Hash + Eq, V: ?Sized, S: BuildHasher> Cache<K, Arc<V>, S> {
+    /// Retrieves a shared handle to a value if it exists and hasn't
+    /// expired.
+    ///
+    /// This clones the `Arc`, not the value it points to.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to look up.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use staticweaver::cache::Cache;
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache: Cache<String, Arc<str>> =
+    ///     Cache::new(Duration::from_secs(60));
+    /// cache.insert("page".to_string(), Arc::from("<html></html>"));
+    ///
+    /// let page = cache.get_shared("page").unwrap();
+    /// assert_eq!(&*page, "<html></html>");
+    /// ```
+    pub fn get_shared<Q>(&self, key: &Q) -> Option<Arc<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).map(Arc::clone)
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> IntoIterator for Cache<K, V, S> {
     type Item = (K, V);
     type IntoIter = std::collections::hash_map::IntoIter<K, V>;
 
     fn into_iter(self) -> Self::IntoIter {
-        let now = Instant::now();
+        let now = self.now();
         self.items
             .into_iter()
-            .filter(|(_, item)| item.expiration > now)
+            .filter(|(_, item)| item.is_live(now))
             .map(|(k, item)| (k, item.value))
             .collect::<HashMap<K, V>>()
             .into_iter()
     }
 }
 
-impl<K: Hash + Eq, V: Clone> Default for Cache<K, V> {
+impl<K: Hash + Eq, V, S: BuildHasher + Default> Default
+    for Cache<K, V, S>
+{
     fn default() -> Self {
-        Self::new(Duration::from_secs(60))
+        Self::with_hasher(Duration::from_secs(60), S::default())
     }
 }
 
-impl<K: Hash + Eq, V: Clone> FromIterator<(K, V)> for Cache<K, V> {
+impl<K: Hash + Eq, V, S: BuildHasher + Default> FromIterator<(K, V)>
+    for Cache<K, V, S>
+{
     fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
         let mut cache = Self::default();
         for (k, v) in iter {
@@ -327,10 +1475,138 @@ fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
     }
 }
 
+/// Serde support for [`Cache`], enabled by the `serde` feature.
+///
+/// A cache serializes its settings and its live items. Each item carries
+/// its remaining TTL rather than an `Instant`, so a deserialized cache
+/// resumes the countdown from the moment it is loaded. Expired and
+/// invalidated items are left out, and callbacks are not serialized.
+#[cfg(feature = "serde")]
+mod persist {
+    use super::{as_nanos, Cache, ExpirationPolicy, NEVER};
+    use serde::de::{Deserialize, Deserializer, Error};
+    use serde::ser::{Serialize, Serializer};
+    use std::hash::{BuildHasher, Hash};
+    use std::time::Duration;
+
+    #[derive(serde::Serialize)]
+    struct CacheRef<'a, K, V> {
+        ttl: Duration,
+        policy: ExpirationPolicy,
+        capacity: Option<usize>,
+        sweep_interval: Option<Duration>,
+        entries: Vec<EntryRef<'a, K, V>>,
+    }
+
+    #[derive(serde::Serialize)]
+    struct EntryRef<'a, K, V> {
+        key: &'a K,
+        value: &'a V,
+        /// The remaining TTL, or `None` if the item never expires.
+        ttl: Option<Duration>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct CacheData<K, V> {
+        ttl: Duration,
+        policy: ExpirationPolicy,
+        capacity: Option<usize>,
+        sweep_interval: Option<Duration>,
+        entries: Vec<EntryData<K, V>>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct EntryData<K, V> {
+        key: K,
+        value: V,
+        ttl: Option<Duration>,
+    }
+
+    impl<K, V, H> Serialize for Cache<K, V, H>
+    where
+        K: Hash + Eq + Serialize,
+        V: Serialize,
+        H: BuildHasher,
+    {
+        fn serialize<S: Serializer>(
+            &self,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            let now = self.now();
+            let entries = self
+                .items
+                .iter()
+                .filter(|(_, item)| item.is_live(now))
+                .map(|(key, item)| EntryRef {
+                    key,
+                    value: &item.value,
+                    ttl: match item.deadline() {
+                        NEVER => None,
+                        deadline => Some(Duration::from_nanos(
+                            deadline - now.nanos,
+                        )),
+                    },
+                })
+                .collect();
+            CacheRef {
+                ttl: self.ttl,
+                policy: self.policy,
+                capacity: self.capacity,
+                sweep_interval: self.sweep_interval,
+                entries,
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de, K, V, H> Deserialize<'de> for Cache<K, V, H>
+    where
+        K: Hash + Eq + Deserialize<'de>,
+        V: Deserialize<'de>,
+        H: BuildHasher + Default,
+    {
+        fn deserialize<D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Self, D::Error> {
+            let data = CacheData::<K, V>::deserialize(deserializer)?;
+            if data.ttl.is_zero() {
+                return Err(D::Error::custom(
+                    "TTL must be greater than zero",
+                ));
+            }
+            let mut cache = Self::with_hasher(data.ttl, H::default());
+            cache.policy = data.policy;
+            cache.capacity = data.capacity;
+            cache.sweep_interval = data.sweep_interval;
+            let now = cache.start_clock();
+            for entry in data.entries {
+                let deadline = entry.ttl.map_or(NEVER, |ttl| {
+                    now.nanos.saturating_add(as_nanos(ttl))
+                });
+                let weight = cache.budget.weigh(&entry.value);
+                let item = cache.new_item(entry.value, weight, now);
+                item.set_deadline(deadline);
+                cache.budget.weight += weight;
+                let _ = cache.items.insert(entry.key, item);
+            }
+            Ok(cache)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::thread::sleep;
+
+    /// Returns an empty cache and the manual clock it reads.
+    fn manual<K: Hash + Eq, V>(
+        ttl: Duration,
+    ) -> (Cache<K, V>, ManualClock) {
+        let clock = ManualClock::new();
+        let mut cache = Cache::new(ttl);
+        cache.set_clock(Arc::new(clock.clone()));
+        (cache, clock)
+    }
 
     #[test]
     fn test_new_cache() {
@@ -359,35 +1635,151 @@ fn test_insert_overwrite() {
 
     #[test]
     fn test_get_expired() {
-        let mut cache = Cache::new(Duration::from_millis(100));
+        let (mut cache, clock) = manual(Duration::from_millis(100));
         let _ = cache.insert("key1".to_string(), 42);
-        sleep(Duration::from_millis(150));
+        clock.advance(Duration::from_millis(150));
         assert_eq!(cache.get(&"key1".to_string()), None);
     }
 
     #[test]
     fn test_remove_expired() {
-        let mut cache = Cache::new(Duration::from_millis(100));
+        let (mut cache, clock) = manual(Duration::from_millis(100));
         let _ = cache.insert("key1".to_string(), 42);
         let _ = cache.insert("key2".to_string(), 43);
-        sleep(Duration::from_millis(150));
+        clock.advance(Duration::from_millis(150));
         cache.remove_expired();
         assert!(cache.items.is_empty());
     }
 
+    #[test]
+    fn test_sweep_interval() {
+        let (mut cache, clock) = manual(Duration::from_millis(50));
+        let _ = cache.insert("key1", 1);
+        clock.advance(Duration::from_millis(100));
+
+        // Without an interval, expired items stay until removed.
+        let _ = cache.insert("key2", 2);
+        assert_eq!(cache.len(), 2);
+
+        cache.set_sweep_interval(Some(Duration::from_millis(10)));
+        clock.advance(Duration::from_millis(100));
+        let _ = cache.insert("key3", 3);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&"key3"), Some(&3));
+    }
+
+    #[test]
+    fn test_hooks() {
+        use std::sync::Mutex;
+
+        type Events = Arc<Mutex<Vec<String>>>;
+        fn record(
+            events: &Events,
+            name: &'static str,
+        ) -> impl Fn(&&str, &i32) + Send + Sync + 'static {
+            let events = Arc::clone(events);
+            move |key, value| {
+                events
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {}={}", name, key, value));
+            }
+        }
+
+        let events: Events = Arc::default();
+        let (mut cache, clock) = manual(Duration::from_millis(50));
+        cache.on_insert(record(&events, "insert"));
+        cache.on_evict(record(&events, "evict"));
+        cache.on_expire(record(&events, "expire"));
+
+        let _ = cache.insert("a", 1);
+        let _ = cache.insert("b", 2);
+        let _ = cache.remove(&"a");
+        clock.advance(Duration::from_millis(100));
+        cache.remove_expired();
+        let _ = cache.insert("c", 3);
+        cache.clear();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "insert a=1",
+                "insert b=2",
+                "evict a=1",
+                "expire b=2",
+                "insert c=3",
+                "evict c=3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sliding_ttl() {
+        let (mut cache, clock) = manual(Duration::from_millis(100));
+        cache.set_expiration_policy(ExpirationPolicy::SlidingTtl);
+        let _ = cache.insert("key1", 1);
+        for _ in 0..3 {
+            clock.advance(Duration::from_millis(60));
+            assert_eq!(cache.get(&"key1"), Some(&1));
+        }
+        clock.advance(Duration::from_millis(150));
+        assert_eq!(cache.get(&"key1"), None);
+    }
+
+    #[test]
+    fn test_no_expiry() {
+        let (mut cache, clock) = manual(Duration::from_millis(10));
+        cache.set_expiration_policy(ExpirationPolicy::NoExpiry);
+        let _ = cache.insert("key1", 1);
+        clock.advance(Duration::from_millis(50));
+        cache.remove_expired();
+        assert_eq!(cache.get(&"key1"), Some(&1));
+        assert_eq!(cache.ttl(&"key1"), Some(Duration::MAX));
+    }
+
+    #[test]
+    fn test_invalidate_all() {
+        let mut cache = Cache::new(Duration::from_secs(60));
+        let _ = cache.insert("key1", 1);
+        let _ = cache.insert("key2", 2);
+        cache.invalidate_all();
+
+        assert_eq!(cache.get(&"key1"), None);
+        assert!(!cache.contains_key(&"key2"));
+        assert_eq!(cache.ttl(&"key1"), None);
+        assert!(!cache.refresh(&"key1"));
+        assert_eq!(cache.len(), 2);
+
+        let _ = cache.insert("key1", 3);
+        cache.remove_expired();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&"key1"), Some(&3));
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut cache = Cache::new(Duration::from_secs(60));
+        for key in ["post:1", "post:2", "page:1"] {
+            let _ = cache.insert(key, key.len());
+        }
+        cache.retain(|key, _| !key.starts_with("post:"));
+        assert_eq!(cache.len(), 1);
+        assert!(cache.contains_key(&"page:1"));
+    }
+
     #[test]
     fn test_contains_key() {
-        let mut cache = Cache::new(Duration::from_millis(100));
+        let (mut cache, clock) = manual(Duration::from_millis(100));
         let _ = cache.insert("key1".to_string(), 42);
         assert!(cache.contains_key(&"key1".to_string()));
         assert!(!cache.contains_key(&"key2".to_string()));
-        sleep(Duration::from_millis(150));
+        clock.advance(Duration::from_millis(150));
         assert!(!cache.contains_key(&"key1".to_string()));
     }
 
     #[test]
     fn test_ttl() {
-        let mut cache = Cache::new(Duration::from_millis(100));
+        let (mut cache, clock) = manual(Duration::from_millis(100));
         let _ = cache.insert("key1".to_string(), 42);
         assert!(cache.ttl(&"key1".to_string()).is_some());
         assert!(
@@ -395,17 +1787,17 @@ fn test_ttl() {
                 <= Duration::from_millis(100)
         );
         assert_eq!(cache.ttl(&"key2".to_string()), None);
-        sleep(Duration::from_millis(150));
+        clock.advance(Duration::from_millis(150));
         assert_eq!(cache.ttl(&"key1".to_string()), None);
     }
 
     #[test]
     fn test_refresh() {
-        let mut cache = Cache::new(Duration::from_millis(100));
+        let (mut cache, clock) = manual(Duration::from_millis(100));
         let _ = cache.insert("key1".to_string(), 42);
-        sleep(Duration::from_millis(50));
+        clock.advance(Duration::from_millis(50));
         assert!(cache.refresh(&"key1".to_string()));
-        sleep(Duration::from_millis(75));
+        clock.advance(Duration::from_millis(75));
         assert_eq!(cache.get(&"key1".to_string()), Some(&42));
         assert!(!cache.refresh(&"key2".to_string()));
     }
@@ -526,14 +1918,14 @@ fn test_capacity() {
 
     #[test]
     fn test_iter() {
-        let mut cache = Cache::new(Duration::from_millis(100));
+        let (mut cache, clock) = manual(Duration::from_millis(100));
         let _ = cache.insert("key1".to_string(), "value1".to_string());
         let _ = cache.insert("key2".to_string(), "value2".to_string());
 
         let items: Vec<(&String, &String)> = cache.iter().collect();
         assert_eq!(items.len(), 2);
 
-        sleep(Duration::from_millis(150));
+        clock.advance(Duration::from_millis(150));
 
         let items: Vec<(&String, &String)> = cache.iter().collect();
         assert_eq!(items.len(), 0);
@@ -556,10 +1948,260 @@ fn test_from_iterator() {
         );
     }
 
+    #[test]
+    fn test_get_or_insert_with() {
+        let (mut cache, clock) = manual(Duration::from_millis(100));
+        let mut calls = 0;
+        for _ in 0..3 {
+            let value = cache.get_or_insert_with("key1", || {
+                calls += 1;
+                calls
+            });
+            assert_eq!(value, 1);
+        }
+        assert_eq!(calls, 1);
+
+        clock.advance(Duration::from_millis(150));
+        assert_eq!(cache.get_or_insert_with("key1", || 2), 2);
+        assert_eq!(cache.get(&"key1"), Some(&2));
+    }
+
+    #[test]
+    fn test_get_or_insert_with_at_capacity() {
+        let mut cache =
+            Cache::with_capacity(Duration::from_secs(60), 1);
+        assert_eq!(cache.get_or_insert_with("key1", || 1), 1);
+        assert_eq!(cache.get_or_insert_with("key2", || 2), 2);
+        assert_eq!(cache.len(), 1);
+        assert!(!cache.contains_key(&"key2"));
+    }
+
+    #[test]
+    fn test_try_get_or_insert_with_error() {
+        let mut cache: Cache<&str, i32> =
+            Cache::new(Duration::from_secs(60));
+        let result =
+            cache.try_get_or_insert_with("key1", || Err("boom"));
+        assert_eq!(result, Err("boom"));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_get_or_insert_with_expires_before_insert() {
+        use std::sync::Mutex;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (mut cache, clock) = manual(Duration::from_millis(50));
+        let log = Arc::clone(&events);
+        cache.on_insert(move |key: &&str, value: &i32| {
+            log.lock()
+                .unwrap()
+                .push(format!("insert {}={}", key, value));
+        });
+        let log = Arc::clone(&events);
+        cache.on_expire(move |key: &&str, value: &i32| {
+            log.lock()
+                .unwrap()
+                .push(format!("expire {}={}", key, value));
+        });
+
+        let _ = cache.get_or_insert_with("a", || 1);
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(cache.get_or_insert_with("a", || 2), 2);
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["insert a=1", "expire a=1", "insert a=2"]
+        );
+    }
+
+    #[test]
+    fn test_get_or_insert_with_evicts_over_budget() {
+        let (mut cache, clock) = manual(Duration::from_millis(50));
+        cache.set_weigher(|value: &String| value.len());
+        cache.set_max_weight(Some(4));
+        let _ = cache.get_or_insert_with("a", || "xx".to_string());
+        let _ = cache.get_or_insert_with("b", || "xx".to_string());
+
+        // "a" is the least recently used item.
+        let _ = cache.get_or_insert_with("c", || "xx".to_string());
+        assert!(!cache.contains_key("a"));
+        assert_eq!(cache.weight(), 4);
+
+        // An expired item is replaced even when it must make room.
+        clock.advance(Duration::from_millis(100));
+        let value =
+            cache.get_or_insert_with("b", || "xxxx".to_string());
+        assert_eq!(value, "xxxx");
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.weight(), 4);
+
+        // Values heavier than the budget are returned, not stored.
+        let value = cache.get_or_insert_with("d", || "x".repeat(5));
+        assert_eq!(value.len(), 5);
+        assert!(!cache.contains_key("d"));
+    }
+
+    #[test]
+    fn test_get_shared() {
+        struct Page(String);
+
+        let mut cache: Cache<&str, Arc<Page>> =
+            Cache::new(Duration::from_secs(60));
+        let _ =
+            cache.insert("home", Arc::new(Page("body".to_string())));
+
+        let first = cache.get_shared(&"home").unwrap();
+        let second = cache.get_shared(&"home").unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(first.0, "body");
+        assert!(cache.get_shared(&"missing").is_none());
+    }
+
     #[test]
     fn test_with_capacity() {
         let cache: Cache<String, String> =
             Cache::with_capacity(Duration::from_secs(60), 100);
         assert!(cache.items.capacity() >= 100);
     }
+
+    #[test]
+    fn test_weight_budget() {
+        let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (mut cache, clock) = manual(Duration::from_secs(60));
+        cache.set_weigher(|value: &String| value.len());
+        cache.set_max_weight(Some(10));
+        let log = Arc::clone(&evicted);
+        cache.on_evict(move |key: &&str, _: &String| {
+            log.lock().unwrap().push(*key);
+        });
+
+        let _ = cache.insert("a", "xxx".to_string());
+        let _ = cache.insert("b", "xxx".to_string());
+        let _ = cache.insert("c", "xxx".to_string());
+        assert_eq!(cache.weight(), 9);
+        assert!(cache.get("a").is_some());
+
+        // "b" is the least recently used item.
+        let _ = cache.insert("d", "xxx".to_string());
+        assert_eq!(*evicted.lock().unwrap(), ["b"]);
+        assert_eq!(cache.weight(), 9);
+
+        // Items heavier than the budget are not stored.
+        assert_eq!(cache.insert("e", "x".repeat(11)), None);
+        assert!(!cache.contains_key("e"));
+        assert!(!cache.update("a", "x".repeat(11)));
+
+        // Replacing an item frees its weight first.
+        assert!(cache.update("a", "xxxx".to_string()));
+        assert_eq!(cache.weight(), 10);
+        assert_eq!(evicted.lock().unwrap().len(), 1);
+
+        // Expired items are dropped before live ones are evicted.
+        clock.advance(Duration::from_secs(61));
+        let _ = cache.insert("f", "x".repeat(10));
+        assert_eq!(evicted.lock().unwrap().len(), 1);
+        assert_eq!(cache.weight(), 10);
+
+        cache.set_max_weight(Some(5));
+        assert!(cache.is_empty());
+        assert_eq!(cache.weight(), 0);
+        assert_eq!(*evicted.lock().unwrap(), ["b", "f"]);
+    }
+
+    #[test]
+    fn test_weight_tracking() {
+        let mut cache: Cache<u32, u32> =
+            Cache::new(Duration::from_secs(60));
+        cache.set_max_weight(Some(2));
+        for i in 0..4 {
+            assert_eq!(cache.get_or_insert_with(i, || i), i);
+        }
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains_key(&2) && cache.contains_key(&3));
+
+        let _ = cache.remove(&2);
+        assert_eq!(cache.weight(), 1);
+        cache.retain(|_, _| false);
+        assert_eq!(cache.weight(), 0);
+        let _ = cache.insert(5, 5);
+        cache.clear();
+        assert_eq!(cache.weight(), 0);
+        assert_eq!(cache.max_weight(), Some(2));
+    }
+
+    #[test]
+    fn test_borrowed_lookups() {
+        let mut cache: Cache<String, i32> =
+            Cache::new(Duration::from_secs(60));
+        let _ = cache.insert("key".to_string(), 1);
+
+        assert_eq!(cache.get("key"), Some(&1));
+        assert!(cache.contains_key("key"));
+        assert!(cache.ttl("key").is_some());
+        assert!(cache.refresh("key"));
+        assert!(cache.update("key", 2));
+        assert!(!cache.update("missing", 3));
+        assert_eq!(cache.remove("key"), Some(2));
+        assert!(cache.get("key").is_none());
+    }
+
+    #[test]
+    fn test_with_hasher() {
+        let mut cache: Cache<String, i32, fnv::FnvBuildHasher> =
+            Cache::with_capacity_and_hasher(
+                Duration::from_secs(60),
+                1,
+                fnv::FnvBuildHasher::default(),
+            );
+        assert_eq!(cache.insert("a".to_string(), 1), None);
+        assert_eq!(cache.insert("b".to_string(), 2), None);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&"a".to_string()), Some(&1));
+
+        let collected: Cache<&str, i32, fnv::FnvBuildHasher> =
+            vec![("x", 1)].into_iter().collect();
+        assert_eq!(collected.get(&"x"), Some(&1));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut cache: Cache<String, Arc<str>> =
+            Cache::new(Duration::from_secs(60));
+        let _ = cache.insert("live".to_string(), Arc::from("page"));
+        let _ = cache.insert("stale".to_string(), Arc::from("old"));
+        let _ = cache.remove(&"stale".to_string());
+        cache.set_sweep_interval(Some(Duration::from_secs(5)));
+
+        let json = serde_json::to_string(&cache).unwrap();
+        let decoded: Cache<String, Arc<str>> =
+            serde_json::from_str(&json).unwrap();
+
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(
+            decoded.get(&"live".to_string()).map(|page| &**page),
+            Some("page")
+        );
+        assert!(
+            decoded.ttl(&"live".to_string()).unwrap()
+                <= Duration::from_secs(60)
+        );
+        assert_eq!(
+            decoded.sweep_interval(),
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_skips_expired_items() {
+        let (mut cache, clock) = manual(Duration::from_millis(10));
+        let _ = cache.insert("key".to_string(), 1);
+        clock.advance(Duration::from_millis(20));
+
+        let json = serde_json::to_string(&cache).unwrap();
+        let decoded: Cache<String, i32> =
+            serde_json::from_str(&json).unwrap();
+        assert!(decoded.is_empty());
+    }
 }
@@ -0,0 +1,213 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Cancel Module
+//!
+//! This module bounds how long a page may take to render. The
+//! [`RenderOptions`](crate::engine::RenderOptions) of a page can set a
+//! `timeout`, a [`CancelToken`] another thread cancels, or both:
+//!
+//! ```
+//! use staticweaver::cancel::CancelToken;
+//! use staticweaver::engine::{EngineError, RenderOptions};
+//! use staticweaver::loader::MemoryLoader;
+//! use staticweaver::{Context, Engine};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let loader = MemoryLoader::new();
+//! loader.insert("templates/page.html", "<h1>{{title}}</h1>");
+//! let mut engine = Engine::new("templates", Duration::from_secs(60));
+//! engine.set_loader(Arc::new(loader));
+//!
+//! let token = CancelToken::new();
+//! let mut options = RenderOptions::new();
+//! options.timeout = Some(Duration::from_secs(5));
+//! options.cancel = Some(token.clone());
+//!
+//! token.cancel();
+//! let mut context = Context::new();
+//! context.set("title", "Hello");
+//! let err = engine.render_page_with(&options, &context, "page").unwrap_err();
+//! assert!(matches!(err.root(), EngineError::Timeout { cancelled: true, .. }));
+//! ```
+//!
+//! Cancellation is cooperative: the engine checks the clock and the
+//! token before each tag and text of a template, and so at every block
+//! boundary. A block handler that loops over many items can check them
+//! between items with [`Block::check`](crate::block::Block::check). A
+//! render that stops returns [`EngineError::Timeout`] with how far it
+//! got, and its page is not cached.
+
+use crate::engine::EngineError;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A flag through which renders are cancelled from another thread.
+///
+/// Clones share the flag, so cancelling one cancels the renders given
+/// any of them. Tokens are equal when they share a flag.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Creates a token that is not cancelled.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the renders given this token or its clones.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the token was cancelled.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for CancelToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancelToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.cancelled, &other.cancelled)
+    }
+}
+
+impl Eq for CancelToken {}
+
+/// The time limit and token of a single render.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Deadline<'a> {
+    started: Instant,
+    at: Option<Instant>,
+    token: Option<&'a CancelToken>,
+}
+
+impl<'a> Deadline<'a> {
+    /// Starts the clock of a render limited by `timeout` and `token`,
+    /// or returns `None` if neither is set.
+    pub(crate) fn start(
+        timeout: Option<Duration>,
+        token: Option<&'a CancelToken>,
+    ) -> Option<Self> {
+        if timeout.is_none() && token.is_none() {
+            return None;
+        }
+        let started = Instant::now();
+        Some(Self {
+            started,
+            at: timeout
+                .and_then(|timeout| started.checked_add(timeout)),
+            token,
+        })
+    }
+
+    /// Returns [`EngineError::Timeout`] if the render was cancelled or
+    /// ran out of time, having reached byte `offset` of `template`.
+    pub(crate) fn check(
+        &self,
+        template: &str,
+        offset: usize,
+    ) -> Result<(), EngineError> {
+        let cancelled =
+            self.token.map_or(false, CancelToken::is_cancelled);
+        let now = Instant::now();
+        if !cancelled && self.at.map_or(true, |at| now < at) {
+            return Ok(());
+        }
+        Err(timeout(
+            cancelled,
+            now.duration_since(self.started),
+            template,
+            offset,
+        ))
+    }
+}
+
+/// Returns `err` at byte `offset` of `template` if it is a
+/// [`EngineError::Timeout`], such as one raised inside a block.
+pub(crate) fn locate(
+    err: EngineError,
+    template: &str,
+    offset: usize,
+) -> EngineError {
+    match err {
+        EngineError::Timeout {
+            cancelled, elapsed, ..
+        } => timeout(cancelled, elapsed, template, offset),
+        err => err,
+    }
+}
+
+/// Returns the [`EngineError::Timeout`] of a render that reached byte
+/// `offset` of `template`.
+fn timeout(
+    cancelled: bool,
+    elapsed: Duration,
+    template: &str,
+    offset: usize,
+) -> EngineError {
+    let offset = offset.min(template.len());
+    EngineError::Timeout {
+        cancelled,
+        elapsed,
+        line: template[..offset].matches('\n').count() + 1,
+        offset,
+        len: template.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_token() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert_eq!(token, clone);
+        assert_ne!(token, CancelToken::new());
+        clone.cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(
+            format!("{:?}", token),
+            "CancelToken { cancelled: true }"
+        );
+    }
+
+    #[test]
+    fn test_deadline() {
+        assert!(Deadline::start(None, None).is_none());
+        let deadline =
+            Deadline::start(Some(Duration::from_secs(60)), None)
+                .unwrap();
+        assert!(deadline.check("a\nb", 2).is_ok());
+
+        let deadline =
+            Deadline::start(Some(Duration::ZERO), None).unwrap();
+        match deadline.check("a\nb\nc", 2) {
+            Err(EngineError::Timeout {
+                cancelled: false,
+                line: 2,
+                offset: 2,
+                len: 5,
+                ..
+            }) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,386 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Codegen Module
+//!
+//! This module compiles layouts into Rust source, so that services can
+//! render them without reading or parsing templates at run time. Each
+//! layout becomes a context struct, with a `&str` field for each key it
+//! reads, and a function rendering it:
+//!
+//! ```text
+//! /// The context of the `blog/post` layout.
+//! pub struct BlogPost<'a> {
+//!     /// The value of the key "title".
+//!     pub title: &'a str,
+//! }
+//!
+//! /// Renders the `blog/post` layout.
+//! pub fn blog_post(context: &BlogPost<'_>) -> String { ... }
+//! ```
+//!
+//! A build script generates the source with
+//! [`Engine::compile_templates`](crate::Engine::compile_templates), and
+//! the crate includes it:
+//!
+//! ```no_run
+//! // build.rs
+//! use staticweaver::Engine;
+//! use std::path::Path;
+//! use std::time::Duration;
+//!
+//! fn main() {
+//!     println!("cargo:rerun-if-changed=templates");
+//!     let engine = Engine::new("templates", Duration::from_secs(60));
+//!     let source = engine.compile_templates().unwrap();
+//!     let out_dir = std::env::var("OUT_DIR").unwrap();
+//!     std::fs::write(Path::new(&out_dir).join("templates.rs"), source).unwrap();
+//! }
+//! ```
+//!
+//! ```text
+//! // src/lib.rs
+//! mod templates {
+//!     include!(concat!(env!("OUT_DIR"), "/templates.rs"));
+//! }
+//!
+//! let page = templates::blog_post(&templates::BlogPost { title: "Hello" });
+//! ```
+//!
+//! The generated functions render as the engine does: with its
+//! delimiters, the syntax version of each layout, its filters, and the
+//! escaping of its output format. Tags that need an engine at run time,
+//! such as function calls, block tags, and `env.*` or `data.*` keys,
+//! fail the compilation.
+
+use crate::data::DATA_PREFIX;
+use crate::engine::{Engine, EngineError};
+use crate::environment::ENV_PREFIX;
+use crate::filter::Filter;
+use crate::function::split_call;
+use crate::parser::{Parser, Segment, Token};
+use crate::syntax;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// The keywords that cannot name Rust items without the `r#` prefix.
+const KEYWORDS: [&str; 50] = [
+    "abstract", "as", "async", "await", "become", "box", "break",
+    "const", "continue", "crate", "do", "dyn", "else", "enum",
+    "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in",
+    "let", "loop", "macro", "match", "mod", "move", "mut", "override",
+    "priv", "pub", "ref", "return", "self", "static", "struct",
+    "super", "trait", "true", "try", "type", "typeof", "unsafe",
+    "unsized", "use", "virtual", "where", "while",
+];
+
+/// A piece of a compiled layout.
+#[derive(Debug)]
+enum Piece<'a> {
+    /// Literal text.
+    Text(&'a str),
+    /// The field of a key, and the filters applied to it.
+    Value(String, Vec<Filter>),
+}
+
+/// Compiles `layouts` of `engine` into Rust source, as described in the
+/// [module documentation](self).
+pub(crate) fn compile(
+    engine: &Engine,
+    layouts: &[&str],
+) -> Result<String, EngineError> {
+    let mut source = String::from(
+        "// @generated by staticweaver from its layouts. Do not edit.\n",
+    );
+    let mut names = BTreeMap::new();
+    for layout in layouts {
+        let function = snake_case(layout);
+        if let Some(other) = names.insert(function.clone(), *layout) {
+            return Err(EngineError::InvalidTemplate(format!(
+                "Layouts '{}' and '{}' both compile to {}",
+                other, layout, function
+            )));
+        }
+        compile_layout(engine, layout, &function, &mut source)?;
+    }
+    Ok(source)
+}
+
+/// Appends the context struct and render function of `layout`, named
+/// after `function`, to `source`.
+fn compile_layout(
+    engine: &Engine,
+    layout: &str,
+    function: &str,
+    source: &mut String,
+) -> Result<(), EngineError> {
+    let (path, template) = engine.read_layout(layout)?;
+    let open = engine.open_delim.as_str();
+    let close = engine.close_delim.as_str();
+    let (syntax, start) = syntax::pragma(&template, open, close)?
+        .map_or((engine.syntax, 0), |(syntax, len)| (syntax, len));
+    let format = engine.output_format_for_template(&path, &template);
+    let unsupported = |tag: &str, what: &str| {
+        EngineError::InvalidTemplate(format!(
+            "Cannot compile {}{}{} in layout '{}': {}",
+            open, tag, close, layout, what
+        ))
+    };
+
+    let mut fields = BTreeMap::new();
+    let mut pieces = Vec::new();
+    for token in Parser::new(&template[start..], open, close) {
+        let Token { segment, .. } = token.map_err(EngineError::from)?;
+        let tag = match segment {
+            Segment::Text(text) => {
+                pieces.push(Piece::Text(text));
+                continue;
+            }
+            Segment::Tag(tag) => tag,
+        };
+        if split_call(tag.trim()).is_some() {
+            return Err(unsupported(tag, "functions need an engine"));
+        }
+        if engine.blocks.is_block_tag(tag.trim()) {
+            return Err(unsupported(tag, "blocks need an engine"));
+        }
+        let mut parts = tag.split('|');
+        let key = parts.next().unwrap_or_default();
+        let key = if engine.trim_tag_keys
+            || syntax.trims_keys()
+            || tag.contains('|')
+        {
+            key.trim()
+        } else {
+            key
+        };
+        if key.starts_with(ENV_PREFIX) || key.starts_with(DATA_PREFIX) {
+            return Err(unsupported(
+                tag,
+                "the key is read by an engine",
+            ));
+        }
+        let filters = parts
+            .map(str::trim)
+            .map(|name| {
+                Filter::from_name(name).ok_or_else(|| {
+                    unsupported(
+                        tag,
+                        &format!("unknown filter {}", name),
+                    )
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let field = snake_case(key);
+        match fields.get(&field) {
+            Some(other) if *other != key => {
+                return Err(unsupported(
+                    tag,
+                    &format!("the key '{}' has the same field", other),
+                ));
+            }
+            _ => {
+                let _ = fields.insert(field.clone(), key);
+            }
+        }
+        pieces.push(Piece::Value(field, filters));
+    }
+
+    let name = camel_case(layout);
+    let lifetime = if fields.is_empty() { "" } else { "<'a>" };
+    let _ = write!(
+        source,
+        "\n/// The context of the `{layout}` layout.\n\
+         #[derive(Debug, Clone, Copy, Default)]\n\
+         pub struct {name}{lifetime}",
+        layout = layout,
+        name = name,
+        lifetime = lifetime,
+    );
+    if fields.is_empty() {
+        source.push_str(";\n");
+    } else {
+        source.push_str(" {\n");
+        for (field, key) in &fields {
+            let _ = write!(
+                source,
+                "    /// The value of the key {:?}.\n    pub {}: &'a str,\n",
+                key,
+                ident(field)
+            );
+        }
+        source.push_str("}\n");
+    }
+
+    let _ = write!(
+        source,
+        "\n/// Renders the `{layout}` layout, compiled from `{path}`.\n\
+         #[must_use]\n\
+         pub fn {function}({argument}: &{name}{lifetime}) -> String {{\n    \
+         let mut out = String::with_capacity({capacity});\n",
+        layout = layout,
+        path = path.display().to_string().replace('\\', "/"),
+        function = ident(function),
+        argument = if fields.is_empty() { "_context" } else { "context" },
+        name = name,
+        lifetime = if fields.is_empty() { "" } else { "<'_>" },
+        capacity = template.len() - start,
+    );
+    for piece in &pieces {
+        match piece {
+            Piece::Text(text) => {
+                let _ =
+                    writeln!(source, "    out.push_str({:?});", text);
+            }
+            Piece::Value(field, filters) => {
+                let filters = filters
+                    .iter()
+                    .map(|filter| {
+                        format!(
+                            "::staticweaver::filter::Filter::{:?}",
+                            filter
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let _ = writeln!(
+                    source,
+                    "    ::staticweaver::static_render::write_str(\
+                     &mut out, context.{}, &[{}], \
+                     ::staticweaver::escape::OutputFormat::{:?});",
+                    ident(field),
+                    filters,
+                    format
+                );
+            }
+        }
+    }
+    source.push_str("    out\n}\n");
+    Ok(())
+}
+
+/// Returns the snake case identifier of `name`, such as `blog_post` for
+/// `blog/post`.
+fn snake_case(name: &str) -> String {
+    let mut ident = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            ident.push(c.to_ascii_lowercase());
+        } else if !ident.is_empty() && !ident.ends_with('_') {
+            ident.push('_');
+        }
+    }
+    let ident = ident.trim_end_matches('_');
+    match ident.chars().next() {
+        None => "_".to_string(),
+        Some(c) if c.is_ascii_digit() => format!("_{}", ident),
+        Some(_) => ident.to_string(),
+    }
+}
+
+/// Returns the camel case identifier of `name`, such as `BlogPost` for
+/// `blog/post`.
+fn camel_case(name: &str) -> String {
+    let ident: String = snake_case(name)
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars.next().map_or_else(String::new, |first| {
+                first.to_ascii_uppercase().to_string() + chars.as_str()
+            })
+        })
+        .collect();
+    match ident.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => ident,
+        _ => format!("Layout{}", ident),
+    }
+}
+
+/// Returns `name` as written in Rust source, with the `r#` prefix if it
+/// is a keyword.
+fn ident(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("r#{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::MemoryLoader;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn engine(layouts: &[(&str, &str)]) -> Engine {
+        let loader = MemoryLoader::new();
+        for (layout, template) in layouts {
+            let _ = loader
+                .insert(format!("site/{}.html", layout), *template);
+        }
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        engine
+    }
+
+    #[test]
+    fn test_identifiers() {
+        assert_eq!(snake_case("blog/post-list"), "blog_post_list");
+        assert_eq!(snake_case("site.title"), "site_title");
+        assert_eq!(snake_case("404"), "_404");
+        assert_eq!(camel_case("blog/post-list"), "BlogPostList");
+        assert_eq!(camel_case("404"), "Layout404");
+        assert_eq!(ident("type"), "r#type");
+    }
+
+    #[test]
+    fn test_compile() {
+        let engine = engine(&[
+            ("blog/post", "<h1>{{title}}</h1>{{ type | slugify }}"),
+            ("empty", "static"),
+        ]);
+        let source = engine.compile_templates().unwrap();
+        assert!(source.contains("pub struct BlogPost<'a> {"));
+        assert!(source.contains("    pub r#type: &'a str,\n"));
+        assert!(source.contains(
+            "pub fn blog_post(context: &BlogPost<'_>) -> String {"
+        ));
+        assert!(source
+            .contains("&[::staticweaver::filter::Filter::Slugify]"));
+        assert!(source.contains("pub struct Empty;\n"));
+        assert!(source
+            .contains("pub fn empty(_context: &Empty) -> String {"));
+        assert_eq!(
+            engine
+                .compile_layouts(&["empty"])
+                .unwrap()
+                .matches("pub fn")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let compile = |template: &str| {
+            engine(&[("page", template)])
+                .compile_templates()
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(
+            compile("{{ now() }}").contains("functions need an engine")
+        );
+        assert!(compile("{{env.HOME}}").contains("read by an engine"));
+        assert!(
+            compile("{{a | upper}}").contains("unknown filter upper")
+        );
+        assert!(compile("{{a-b}}{{a_b}}").contains("same field"));
+        assert!(engine(&[("a-b", "x"), ("a_b", "x")])
+            .compile_templates()
+            .unwrap_err()
+            .to_string()
+            .contains("both compile to a_b"));
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Compress Module
+//!
+//! This module writes pre-compressed copies of rendered pages next to
+//! them, as Brotli `.br` and gzip `.gz` files, which static hosts such as
+//! nginx with `gzip_static` serve in place of the page to clients that
+//! accept them:
+//!
+//! ```text
+//! public/blog/index.html
+//! public/blog/index.html.br
+//! public/blog/index.html.gz
+//! ```
+//!
+//! Pages are compressed while they are written, so a build does not
+//! read every page again in a second pass. Pages shorter than
+//! [`MIN_COMPRESS_LEN`], and copies that would save less than a tenth of
+//! the page, are skipped, and any stale copy of them is removed. Copies
+//! are reproducible: the gzip header carries no timestamp.
+//!
+//! This module requires the `compress` feature.
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The length in bytes under which pages are not compressed.
+pub const MIN_COMPRESS_LEN: usize = 512;
+
+/// A content encoding of pre-compressed copies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentEncoding {
+    /// Brotli, at its best quality.
+    Brotli,
+    /// gzip, at its best level.
+    Gzip,
+}
+
+impl ContentEncoding {
+    /// Every encoding, in the order copies are written.
+    pub const ALL: [Self; 2] = [Self::Brotli, Self::Gzip];
+
+    /// Returns the extension appended to the file name of copies, such
+    /// as `br` for `index.html.br`.
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gz",
+        }
+    }
+
+    /// Returns `bytes` compressed with this encoding.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::compress::ContentEncoding;
+    ///
+    /// let page = "<p>Hello</p>".repeat(100);
+    /// let compressed = ContentEncoding::Gzip.compress(page.as_bytes());
+    /// assert!(compressed.len() < page.len());
+    /// ```
+    #[must_use]
+    pub fn compress(self, bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        // Writes into a `Vec` cannot fail.
+        match self {
+            Self::Brotli => {
+                let mut writer = brotli::CompressorWriter::new(
+                    &mut out, 4096, 11, 22,
+                );
+                let _ = writer.write_all(bytes);
+            }
+            Self::Gzip => {
+                let mut encoder =
+                    GzEncoder::new(&mut out, Compression::best());
+                let _ = encoder.write_all(bytes);
+                let _ = encoder.finish();
+            }
+        }
+        out
+    }
+
+    /// Returns the path of the copy of the file at `path`.
+    fn sibling(self, path: &Path) -> PathBuf {
+        let mut name = path.file_name().unwrap_or_default().to_owned();
+        name.push(".");
+        name.push(self.extension());
+        path.with_file_name(name)
+    }
+}
+
+/// Writes the pre-compressed copies of `content`, the page written to
+/// `dest`, next to it.
+///
+/// Copies that are not worth serving are not written, and any copy left
+/// by a previous build is removed, so that hosts never serve a stale
+/// page.
+///
+/// # Arguments
+///
+/// * `dest` - The path of the page.
+/// * `content` - The page.
+///
+/// # Returns
+///
+/// The paths of the copies written.
+///
+/// # Errors
+///
+/// Returns an error if a copy cannot be written or removed.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::compress::write_compressed;
+///
+/// let dir = tempfile::tempdir()?;
+/// let dest = dir.path().join("index.html");
+/// let page = "<p>Hello</p>".repeat(100);
+/// std::fs::write(&dest, &page)?;
+///
+/// let written = write_compressed(&dest, page.as_bytes())?;
+/// assert_eq!(written, [dir.path().join("index.html.br"), dir.path().join("index.html.gz")]);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn write_compressed(
+    dest: &Path,
+    content: &[u8],
+) -> io::Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    for encoding in ContentEncoding::ALL {
+        let sibling = encoding.sibling(dest);
+        let compressed = if content.len() < MIN_COMPRESS_LEN {
+            None
+        } else {
+            Some(encoding.compress(content))
+                .filter(|copy| copy.len() * 10 <= content.len() * 9)
+        };
+        match compressed {
+            Some(copy) => {
+                fs::write(&sibling, copy)?;
+                written.push(sibling);
+            }
+            None => match fs::remove_file(&sibling) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    return Err(err)
+                }
+                _ => {}
+            },
+        }
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_compress_roundtrip() {
+        let page = "<li>item</li>".repeat(200);
+
+        let mut unzipped = String::new();
+        let gzip = ContentEncoding::Gzip.compress(page.as_bytes());
+        let _ = GzDecoder::new(&gzip[..])
+            .read_to_string(&mut unzipped)
+            .unwrap();
+        assert_eq!(unzipped, page);
+        assert_eq!(
+            gzip,
+            ContentEncoding::Gzip.compress(page.as_bytes())
+        );
+
+        let mut unbrotli = Vec::new();
+        let brotli = ContentEncoding::Brotli.compress(page.as_bytes());
+        brotli::BrotliDecompress(&mut &brotli[..], &mut unbrotli)
+            .unwrap();
+        assert_eq!(unbrotli, page.as_bytes());
+    }
+
+    #[test]
+    fn test_write_compressed() {
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("feed.xml");
+        let page = "<item/>".repeat(200);
+        let written = write_compressed(&dest, page.as_bytes()).unwrap();
+        assert_eq!(written.len(), 2);
+        assert!(dir.path().join("feed.xml.gz").is_file());
+
+        // Short and incompressible pages remove their stale copies.
+        assert!(write_compressed(&dest, b"<item/>")
+            .unwrap()
+            .is_empty());
+        assert!(!dir.path().join("feed.xml.br").exists());
+        assert!(!dir.path().join("feed.xml.gz").exists());
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        assert!(write_compressed(&dest, &noise).unwrap().is_empty());
+    }
+}
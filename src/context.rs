@@ -7,16 +7,36 @@
 //! key-value pairs for template rendering. It offers a flexible and efficient way
 //! to handle template variables and their values.
 
-use fnv::FnvHashMap;
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
-use std::ops::{Deref, DerefMut};
+use crate::engine::PageOptions;
+use crate::environment::REDACTED;
+use crate::error::TemplateError;
+use crate::value::ToContextValue;
+use fnv::{FnvBuildHasher, FnvHashMap, FnvHasher};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::ops::{Deref, Index};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// The default maximum value length shown by the `Display` impl of
+/// [`Context`].
+///
+/// Values longer than this are replaced by a redaction marker. Use
+/// [`Context::display_with`] to choose a different limit.
+pub const DEFAULT_DISPLAY_MAX_LEN: usize = 64;
 
 /// Represents the context for template rendering.
 ///
 /// `Context` holds key-value pairs that can be used to populate
-/// placeholders in a template during the rendering process. It uses
-/// `FnvHashMap` for efficient string-based key lookups.
+/// placeholders in a template during the rendering process. It hashes
+/// keys with FNV by default, for fast string-based key lookups.
+///
+/// Contexts built from untrusted keys, such as user-supplied JSON, can
+/// use a hasher `S` that resists collision attacks instead, such as
+/// SipHash from [`RandomState`](std::collections::hash_map::RandomState).
+/// The engine renders contexts with the default hasher.
 ///
 /// # Examples
 ///
@@ -24,15 +44,151 @@
 /// use staticweaver::Context;
 ///
 /// let mut context = Context::new();
-/// context.set("name".to_string(), "Alice".to_string());
+/// context.set("name", "Alice");
+/// assert_eq!(context.get("name"), Some(&"Alice".to_string()));
+/// ```
+///
+/// ```
+/// use staticweaver::Context;
+/// use std::collections::hash_map::RandomState;
+///
+/// let mut context: Context<RandomState> = Context::default();
+/// context.set("name", "Alice");
 /// assert_eq!(context.get("name"), Some(&"Alice".to_string()));
 /// ```
-#[derive(Debug, Default, PartialEq, Eq, Clone)]
-pub struct Context {
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent, bound = "S: BuildHasher + Default")
+)]
+pub struct Context<S = FnvBuildHasher> {
     /// The internal storage for context key-value pairs.
-    elements: FnvHashMap<String, String>,
+    elements: ContextMap<S>,
+    /// Values computed on first use, keyed like `elements`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    lazy: HashMap<String, LazyValue, S>,
+    /// The keys whose values are redacted, set with
+    /// [`Context::mark_sensitive`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    sensitive: BTreeSet<String>,
+    /// The keys whose values are JSON text rather than strings, set
+    /// with [`Context::set_json`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    json_keys: BTreeSet<String>,
+}
+
+/// Formats the context with the values of
+/// [sensitive](Context::mark_sensitive) keys replaced by [`REDACTED`].
+impl<S> fmt::Debug for Context<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Context")
+            .field("elements", &RedactedElements(self))
+            .field("lazy", &self.lazy)
+            .field("sensitive", &self.sensitive)
+            .field("json_keys", &self.json_keys)
+            .finish()
+    }
+}
+
+/// The values of a context, formatted as a map with the values of
+/// sensitive keys redacted.
+struct RedactedElements<'a, S>(&'a Context<S>);
+
+impl<S> fmt::Debug for RedactedElements<'_, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.0.elements.iter().map(|(key, value)| {
+                if self.0.sensitive.contains(key) {
+                    (key, REDACTED)
+                } else {
+                    (key, value.as_str())
+                }
+            }))
+            .finish()
+    }
+}
+
+impl<S: Default> Default for Context<S> {
+    fn default() -> Self {
+        Self {
+            elements: ContextMap::default(),
+            lazy: HashMap::default(),
+            sensitive: BTreeSet::new(),
+            json_keys: BTreeSet::new(),
+        }
+    }
+}
+
+impl<S: BuildHasher + Default> PartialEq for Context<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.elements == other.elements
+            && self.lazy == other.lazy
+            && self.json_keys == other.json_keys
+    }
+}
+
+impl<S: BuildHasher + Default> Eq for Context<S> {}
+
+impl<S: Clone> Clone for Context<S> {
+    fn clone(&self) -> Self {
+        Self {
+            elements: self.elements.clone(),
+            lazy: self.lazy.clone(),
+            sensitive: self.sensitive.clone(),
+            json_keys: self.json_keys.clone(),
+        }
+    }
+}
+
+/// A context value that is computed the first time it is resolved.
+///
+/// Clones share both the provider and the memoized result.
+#[derive(Clone)]
+struct LazyValue {
+    provider: Arc<dyn Fn() -> String + Send + Sync>,
+    value: Arc<Mutex<Option<String>>>,
+}
+
+impl LazyValue {
+    /// Returns the memoized value, evaluating the provider if needed.
+    fn resolve(&self) -> String {
+        let mut value = match self.value.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        value.get_or_insert_with(|| (self.provider)()).clone()
+    }
+
+    /// Returns `true` if the provider has already been evaluated.
+    fn is_evaluated(&self) -> bool {
+        self.evaluated().is_some()
+    }
+
+    /// Returns the memoized value, without evaluating the provider.
+    fn evaluated(&self) -> Option<String> {
+        match self.value.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+impl fmt::Debug for LazyValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyValue")
+            .field("evaluated", &self.is_evaluated())
+            .finish()
+    }
 }
 
+impl PartialEq for LazyValue {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.provider, &other.provider)
+    }
+}
+
+impl Eq for LazyValue {}
+
 impl Context {
     /// Creates a new, empty `Context`.
     ///
@@ -56,24 +212,112 @@ pub fn new() -> Self {
     /// ```
     /// use staticweaver::Context;
     ///
-    /// let context = Context::with_capacity(10);
+    /// let context = Context::with_capacity(4);
     /// assert!(context.is_empty());
-    /// assert!(context.capacity() >= 10);
+    /// assert!(context.capacity() >= 4);
     /// ```
     #[must_use]
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
-            elements: FnvHashMap::with_capacity_and_hasher(
-                capacity,
-                Default::default(),
-            ),
+            elements: ContextMap::with_capacity(capacity),
+            lazy: FnvHashMap::default(),
+            sensitive: BTreeSet::new(),
+            json_keys: BTreeSet::new(),
+        }
+    }
+
+    /// Creates a `Context` from the members of a JSON object.
+    ///
+    /// String values are stored as they are; other JSON values, such as
+    /// numbers, booleans, arrays, and nested objects, are stored as JSON
+    /// text, as by [`Context::set_json`], so that [`Context::to_json`]
+    /// restores them.
+    ///
+    /// This method requires the `serde` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TemplateError::InvalidSyntax` if `value` is not a JSON
+    /// object.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use serde_json::json;
+    /// use staticweaver::Context;
+    ///
+    /// let value = json!({"title": "Home", "tags": ["rust", "web"]});
+    /// let context = Context::from_json(value.clone()).unwrap();
+    /// assert_eq!(context.get("tags"), Some(&r#"["rust","web"]"#.to_string()));
+    /// assert_eq!(context.to_json(), value);
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn from_json(
+        value: serde_json::Value,
+    ) -> Result<Self, TemplateError> {
+        let values = match value {
+            serde_json::Value::Object(values) => values,
+            other => {
+                return Err(TemplateError::InvalidSyntax(format!(
+                    "Expected a JSON object, found {}",
+                    other
+                )))
+            }
+        };
+        let mut context = Self::with_capacity(values.len());
+        for (key, value) in values {
+            context.set_json(key, value);
         }
+        Ok(context)
+    }
+
+    /// Creates a `Context` from JSON text holding an object, as
+    /// [`Context::from_json`] does from its value.
+    ///
+    /// This method requires the `serde` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TemplateError::InvalidSyntax` if `json` is not a JSON
+    /// object.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let context =
+    ///     Context::from_json_str(r#"{"name": "Ada", "age": 36}"#).unwrap();
+    /// assert_eq!(context.get("name"), Some(&"Ada".to_string()));
+    /// assert_eq!(context.get("age"), Some(&"36".to_string()));
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn from_json_str(json: &str) -> Result<Self, TemplateError> {
+        serde_json::from_str(json)
+            .map_err(|err| {
+                TemplateError::InvalidSyntax(err.to_string())
+            })
+            .and_then(Self::from_json)
     }
+}
 
+impl<S: BuildHasher + Default> Context<S> {
     /// Computes a hash of the context.
     ///
-    /// This method is used for caching purposes. It creates a stable hash
-    /// based on the current state of the context.
+    /// This method is used for caching purposes. The hash is independent
+    /// of insertion order and is stable across processes, platforms, and
+    /// program runs: entries are hashed in sorted key order with the
+    /// 64-bit FNV-1a algorithm, and each key and value is length-prefixed
+    /// so that adjacent strings cannot be confused. The result is
+    /// therefore safe to persist, for example as part of an on-disk cache
+    /// key.
+    ///
+    /// Lazy values are evaluated and hashed by their computed value, so
+    /// that contexts whose lazy values differ never share a cache key.
+    /// The value is memoized, so rendering the context afterwards does
+    /// not run the provider again. Keys set from
+    /// JSON values other than strings are hashed as such, since
+    /// `Context::to_json` converts them differently.
     ///
     /// # Returns
     ///
@@ -85,15 +329,53 @@ pub fn with_capacity(capacity: usize) -> Self {
     /// use staticweaver::Context;
     ///
     /// let mut context = Context::new();
-    /// context.set("key".to_string(), "value".to_string());
+    /// context.set("key", "value");
     /// let hash = context.hash();
     /// assert_ne!(hash, 0);
     /// ```
     pub fn hash(&self) -> u64 {
-        let mut hasher = DefaultHasher::new();
-        for (key, value) in &self.elements {
-            key.hash(&mut hasher);
-            value.hash(&mut hasher);
+        self.hash_ignoring(&[])
+    }
+
+    /// Computes [`Context::hash`] as if the keys `ignored` were not
+    /// set, so that contexts whose [diff](Context::diff) lies within
+    /// `ignored` hash equally.
+    ///
+    /// The names of the ignored keys are hashed in their place, so that
+    /// a context that lacks them does not hash like one that has them.
+    pub(crate) fn hash_ignoring(&self, ignored: &[String]) -> u64 {
+        let kept = |key: &&String| !ignored.contains(key);
+        let mut entries: Vec<(&String, &String)> =
+            self.elements.iter().filter(|(key, _)| kept(key)).collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+        let mut hasher = FnvHasher::default();
+        for (key, value) in entries {
+            write_stable_str(&mut hasher, key);
+            write_stable_str(&mut hasher, value);
+        }
+
+        let mut lazy: Vec<(&String, &LazyValue)> =
+            self.lazy.iter().filter(|(key, _)| kept(key)).collect();
+        lazy.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        for (key, value) in lazy {
+            hasher.write_u8(0xff);
+            write_stable_str(&mut hasher, key);
+            write_stable_str(&mut hasher, &value.resolve());
+        }
+
+        // Sets keep their keys sorted.
+        for key in self.json_keys.iter().filter(kept) {
+            hasher.write_u8(0xfd);
+            write_stable_str(&mut hasher, key);
+        }
+
+        let mut ignored: Vec<&String> = ignored.iter().collect();
+        ignored.sort_unstable();
+        ignored.dedup();
+        for key in ignored {
+            hasher.write_u8(0xfe);
+            write_stable_str(&mut hasher, key);
         }
         hasher.finish()
     }
@@ -104,8 +386,9 @@ pub fn hash(&self) -> u64 {
     ///
     /// # Arguments
     ///
-    /// * `key` - The key to set.
-    /// * `value` - The value to associate with the key.
+    /// * `key` - The key to set; `&str` and `String` are both accepted.
+    /// * `value` - The value to associate with the key. Any type
+    ///   implementing [`ToContextValue`] is accepted.
     ///
     /// # Examples
     ///
@@ -113,11 +396,190 @@ pub fn hash(&self) -> u64 {
     /// use staticweaver::Context;
     ///
     /// let mut context = Context::new();
-    /// context.set("name".to_string(), "Alice".to_string());
+    /// context.set("name", "Alice");
+    /// context.set("age", 30);
+    /// context.set("tags", vec!["rust", "web"]);
     /// assert_eq!(context.get("name"), Some(&"Alice".to_string()));
+    /// assert_eq!(context.get("age"), Some(&"30".to_string()));
+    /// assert_eq!(context.get("tags"), Some(&"rust, web".to_string()));
+    /// ```
+    pub fn set<K, V>(&mut self, key: K, value: V)
+    where
+        K: Into<String>,
+        V: ToContextValue,
+    {
+        let key = key.into();
+        let _ = self.lazy.remove(&key);
+        let _ = self.json_keys.remove(&key);
+        let _ = self.elements.insert(key, value.to_context_value());
+    }
+
+    /// Sets a JSON value for `key`.
+    ///
+    /// A JSON string is stored as it is, like a value set with
+    /// [`Context::set`]. Any other value, such as a number, a boolean,
+    /// `null`, an array, or an object, is stored as its JSON text, and
+    /// the key is remembered as holding JSON, so that
+    /// `Context::to_json` returns the value unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use serde_json::json;
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.set_json("count", json!(3));
+    /// context.set_json("label", json!("3"));
+    /// assert_eq!(context.get("count"), context.get("label"));
+    /// ```
+    pub fn set_json<K: Into<String>>(
+        &mut self,
+        key: K,
+        value: serde_json::Value,
+    ) {
+        match value {
+            serde_json::Value::String(value) => self.set(key, value),
+            value => {
+                let key = key.into();
+                self.set(key.clone(), value.to_string());
+                let _ = self.json_keys.insert(key);
+            }
+        }
+    }
+
+    /// Registers a value that is computed only when it is first needed.
+    ///
+    /// The provider runs at most once, the first time the key is looked
+    /// up through [`Context::resolve`] (which the engine uses for every
+    /// template tag), and the result is memoized for the lifetime of the
+    /// context and its clones. Templates that never reference the key
+    /// never pay for the computation, although [`Context::hash`], and
+    /// so rendering a page through the render cache, evaluates every
+    /// lazy value.
+    ///
+    /// Setting an eager value for the same key with [`Context::set`]
+    /// replaces the lazy value, and vice versa.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to set.
+    /// * `provider` - A function computing the value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.set_lazy("reading_time", || "4 min".to_string());
+    /// assert_eq!(
+    ///     context.resolve("reading_time").as_deref(),
+    ///     Some("4 min")
+    /// );
+    /// ```
+    pub fn set_lazy<K, F>(&mut self, key: K, provider: F)
+    where
+        K: Into<String>,
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        let key = key.into();
+        let _ = self.elements.remove(&key);
+        let _ = self.json_keys.remove(&key);
+        let _ = self.lazy.insert(
+            key,
+            LazyValue {
+                provider: Arc::new(provider),
+                value: Arc::new(Mutex::new(None)),
+            },
+        );
+    }
+
+    /// Resolves the value for `key`, evaluating a lazy value if needed.
+    ///
+    /// Eager values are borrowed; lazy values are computed on first use
+    /// and memoized.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to look up.
+    ///
+    /// # Returns
+    ///
+    /// The value for `key`, or `None` if neither an eager nor a lazy
+    /// value is registered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.set("name", "Alice");
+    /// assert_eq!(context.resolve("name").as_deref(), Some("Alice"));
+    /// assert_eq!(context.resolve("missing"), None);
+    /// ```
+    #[must_use]
+    pub fn resolve(&self, key: &str) -> Option<Cow<'_, str>> {
+        if let Some(value) = self.elements.get(key) {
+            return Some(Cow::Borrowed(value));
+        }
+        self.lazy.get(key).map(|lazy| Cow::Owned(lazy.resolve()))
+    }
+
+    /// Resolves the value for `key` like [`Context::resolve`], ignoring
+    /// case if there is no exact match.
+    ///
+    /// Keys are compared with Unicode lowercase mapping. If several keys
+    /// differ from `key` only in case, the first in sorted order wins.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to look up.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.set("Title", "Home");
+    /// assert_eq!(
+    ///     context.resolve_ignore_case("title").as_deref(),
+    ///     Some("Home")
+    /// );
     /// ```
-    pub fn set(&mut self, key: String, value: String) {
-        let _ = self.elements.insert(key, value);
+    #[must_use]
+    pub fn resolve_ignore_case(
+        &self,
+        key: &str,
+    ) -> Option<Cow<'_, str>> {
+        if let Some(value) = self.resolve(key) {
+            return Some(value);
+        }
+        let matches = |candidate: &&String| {
+            candidate
+                .chars()
+                .flat_map(char::to_lowercase)
+                .eq(key.chars().flat_map(char::to_lowercase))
+        };
+        let found = self
+            .elements
+            .keys()
+            .chain(self.lazy.keys())
+            .filter(matches)
+            .min()?;
+        self.resolve(found)
+    }
+
+    /// Returns `true` if `key` has a lazy value registered.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to check.
+    #[must_use]
+    pub fn is_lazy(&self, key: &str) -> bool {
+        self.lazy.contains_key(key)
     }
 
     /// Retrieves the value associated with a key from the context.
@@ -137,13 +599,143 @@ pub fn set(&mut self, key: String, value: String) {
     /// use staticweaver::Context;
     ///
     /// let mut context = Context::new();
-    /// context.set("name".to_string(), "Bob".to_string());
+    /// context.set("name", "Bob");
     /// assert_eq!(context.get("name"), Some(&"Bob".to_string()));
     /// assert_eq!(context.get("age"), None);
     /// ```
     #[must_use]
-    pub fn get(&self, key: &str) -> Option<&String> {
-        self.elements.get(key)
+    pub fn get<K: AsRef<str>>(&self, key: K) -> Option<&String> {
+        self.elements.get(key.as_ref())
+    }
+
+    /// Retrieves the value for `key` and parses it into `T`.
+    ///
+    /// The value is looked up with [`Context::resolve`], so lazy values
+    /// are evaluated.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to look up.
+    ///
+    /// # Errors
+    ///
+    /// * `TemplateError::MissingVariable` - If the key does not exist.
+    /// * `TemplateError::InvalidValue` - If the value cannot be parsed
+    ///   as `T`. The error names the offending key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.set("count", "42");
+    /// let count: u32 = context.get_as("count").unwrap();
+    /// assert_eq!(count, 42);
+    /// assert!(context.get_as::<u32>("missing").is_err());
+    /// ```
+    pub fn get_as<T>(&self, key: &str) -> Result<T, TemplateError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let value = self.resolve(key).ok_or_else(|| {
+            TemplateError::MissingVariable(key.to_string())
+        })?;
+        value.trim().parse().map_err(|err: T::Err| {
+            TemplateError::InvalidValue {
+                key: key.to_string(),
+                message: self.redact(&err.to_string()).into_owned(),
+            }
+        })
+    }
+
+    /// Retrieves the value for `key` as a boolean.
+    ///
+    /// Besides `true` and `false`, the common frontmatter spellings
+    /// `yes`/`no`, `on`/`off`, and `1`/`0` are accepted, ignoring case.
+    /// Lazy values are evaluated, as by [`Context::get_as`].
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to look up.
+    ///
+    /// # Errors
+    ///
+    /// * `TemplateError::MissingVariable` - If the key does not exist.
+    /// * `TemplateError::InvalidValue` - If the value is not a boolean.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.set("draft", "Yes");
+    /// assert!(context.get_bool("draft").unwrap());
+    /// ```
+    pub fn get_bool(&self, key: &str) -> Result<bool, TemplateError> {
+        let value = self.resolve(key).ok_or_else(|| {
+            TemplateError::MissingVariable(key.to_string())
+        })?;
+        match value.trim().to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Ok(true),
+            "false" | "no" | "off" | "0" => Ok(false),
+            other => Err(TemplateError::InvalidValue {
+                key: key.to_string(),
+                message: self
+                    .redact(&format!("'{}' is not a boolean", other))
+                    .into_owned(),
+            }),
+        }
+    }
+
+    /// Retrieves the value for `key` as an `i64`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to look up.
+    ///
+    /// # Errors
+    ///
+    /// * `TemplateError::MissingVariable` - If the key does not exist.
+    /// * `TemplateError::InvalidValue` - If the value is not an integer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.set("year", "2024");
+    /// assert_eq!(context.get_i64("year").unwrap(), 2024);
+    /// ```
+    pub fn get_i64(&self, key: &str) -> Result<i64, TemplateError> {
+        self.get_as(key)
+    }
+
+    /// Retrieves the value for `key` as an `f64`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to look up.
+    ///
+    /// # Errors
+    ///
+    /// * `TemplateError::MissingVariable` - If the key does not exist.
+    /// * `TemplateError::InvalidValue` - If the value is not a number.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.set("ratio", "0.5");
+    /// assert_eq!(context.get_f64("ratio").unwrap(), 0.5);
+    /// ```
+    pub fn get_f64(&self, key: &str) -> Result<f64, TemplateError> {
+        self.get_as(key)
     }
 
     /// Retrieves a mutable reference to the value associated with a key from the context.
@@ -163,7 +755,7 @@ pub fn get(&self, key: &str) -> Option<&String> {
     /// use staticweaver::Context;
     ///
     /// let mut context = Context::new();
-    /// context.set("name".to_string(), "Bob".to_string());
+    /// context.set("name", "Bob");
     /// if let Some(value) = context.get_mut("name") {
     ///     *value = "Alice".to_string();
     /// }
@@ -189,46 +781,55 @@ pub fn get_mut(&mut self, key: &str) -> Option<&mut String> {
     /// use staticweaver::Context;
     ///
     /// let mut context = Context::new();
-    /// context.set("name".to_string(), "Alice".to_string());
+    /// context.set("name", "Alice");
     /// assert_eq!(context.remove("name"), Some("Alice".to_string()));
     /// assert_eq!(context.get("name"), None);
     /// ```
     pub fn remove(&mut self, key: &str) -> Option<String> {
+        let _ = self.lazy.remove(key);
+        let _ = self.json_keys.remove(key);
         self.elements.remove(key)
     }
 
     /// Returns the number of elements in the context.
     ///
+    /// Lazy values are counted without being evaluated.
+    ///
     /// # Examples
     ///
     /// ```
     /// use staticweaver::Context;
     ///
     /// let mut context = Context::new();
-    /// context.set("key".to_string(), "value".to_string());
+    /// context.set("key", "value");
     /// assert_eq!(context.len(), 1);
     /// ```
     #[must_use]
     pub fn len(&self) -> usize {
-        self.elements.len()
+        self.elements.len() + self.lazy.len()
     }
 
     /// Returns the number of elements the context can hold without reallocating.
     ///
+    /// With the `persistent` feature, a context of more than eight
+    /// elements allocates as it grows and never reallocates, so its
+    /// capacity is its length.
+    ///
     /// # Examples
     ///
     /// ```
     /// use staticweaver::Context;
     ///
-    /// let context = Context::with_capacity(10);
-    /// assert!(context.capacity() >= 10);
+    /// let context = Context::with_capacity(4);
+    /// assert!(context.capacity() >= 4);
     /// ```
     #[must_use]
     pub fn capacity(&self) -> usize {
         self.elements.capacity()
     }
 
-    /// Returns true if the context contains no elements.
+    /// Returns true if the context contains no elements, neither eager
+    /// nor lazy.
     ///
     /// # Examples
     ///
@@ -240,19 +841,44 @@ pub fn capacity(&self) -> usize {
     /// ```
     #[must_use]
     pub fn is_empty(&self) -> bool {
-        self.elements.is_empty()
+        self.elements.is_empty() && self.lazy.is_empty()
+    }
+
+    /// Returns `true` if the context has a value for `key`, eager or
+    /// lazy.
+    ///
+    /// Lazy values are not evaluated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.set("name", "Alice");
+    /// context.set_lazy("reading_time", || "4 min".to_string());
+    /// assert!(context.contains_key("name"));
+    /// assert!(context.contains_key("reading_time"));
+    /// assert!(!context.contains_key("age"));
+    /// ```
+    #[must_use]
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.elements.contains_key(key) || self.lazy.contains_key(key)
     }
 
     /// Returns an iterator over the context's key-value pairs.
     ///
+    /// Only eager values are listed: lazy values are skipped rather
+    /// than evaluated. Use [`Context::resolve`] to read them.
+    ///
     /// # Examples
     ///
     /// ```
     /// use staticweaver::Context;
     ///
     /// let mut context = Context::new();
-    /// context.set("key1".to_string(), "value1".to_string());
-    /// context.set("key2".to_string(), "value2".to_string());
+    /// context.set("key1", "value1");
+    /// context.set("key2", "value2");
     ///
     /// for (key, value) in context.iter() {
     ///     println!("{}: {}", key, value);
@@ -262,7 +888,14 @@ pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
         self.elements.iter()
     }
 
-    /// Removes all key-value pairs from the context.
+    /// Returns an iterator over the context's key-value pairs in sorted
+    /// key order.
+    ///
+    /// Unlike [`Context::iter`], whose order depends on the hasher and
+    /// on how the context was built, the order is the same on every
+    /// run, so output listing the entries of a context is
+    /// deterministic. Lazy values are skipped, as they are by
+    /// [`Context::iter`].
     ///
     /// # Examples
     ///
@@ -270,14 +903,38 @@ pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
     /// use staticweaver::Context;
     ///
     /// let mut context = Context::new();
-    /// context.set("key".to_string(), "value".to_string());
-    /// assert!(!context.is_empty());
+    /// context.set("b", "2");
+    /// context.set("c", "3");
+    /// context.set("a", "1");
     ///
-    /// context.clear();
+    /// let keys: Vec<&String> =
+    ///     context.iter_sorted().map(|(key, _)| key).collect();
+    /// assert_eq!(keys, ["a", "b", "c"]);
+    /// ```
+    pub fn iter_sorted(
+        &self,
+    ) -> impl Iterator<Item = (&String, &String)> {
+        self.elements.iter_sorted()
+    }
+
+    /// Removes all key-value pairs from the context.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.set("key", "value");
+    /// assert!(!context.is_empty());
+    ///
+    /// context.clear();
     /// assert!(context.is_empty());
     /// ```
     pub fn clear(&mut self) {
         self.elements.clear();
+        self.lazy.clear();
+        self.json_keys.clear();
     }
 
     /// Updates an existing key with a new value or inserts it if it doesn't exist.
@@ -293,7 +950,7 @@ pub fn clear(&mut self) {
     /// use staticweaver::Context;
     ///
     /// let mut context = Context::new();
-    /// context.set("key".to_string(), "old_value".to_string());
+    /// context.set("key", "old_value");
     /// context.update("key", "new_value");
     /// assert_eq!(context.get("key"), Some(&"new_value".to_string()));
     /// ```
@@ -302,11 +959,604 @@ pub fn update<K, V>(&mut self, key: K, value: V)
         K: Into<String>,
         V: Into<String>,
     {
-        let _ = self.elements.insert(key.into(), value.into());
+        self.set(key.into(), value.into());
+    }
+
+    /// Copies every value from `other` into this context.
+    ///
+    /// Keys present in both take the value from `other`. Lazy values
+    /// remain lazy and share their memoized result with `other`.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The context whose values take precedence.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut defaults = Context::new();
+    /// defaults.set("title", "Untitled");
+    /// defaults.set("lang", "en");
+    ///
+    /// let mut page = Context::new();
+    /// page.set("title", "Home");
+    ///
+    /// defaults.merge(&page);
+    /// assert_eq!(defaults.get("title"), Some(&"Home".to_string()));
+    /// assert_eq!(defaults.get("lang"), Some(&"en".to_string()));
+    /// ```
+    pub fn merge(&mut self, other: &Self) {
+        for (key, value) in &other.elements {
+            self.set(key.clone(), value.clone());
+        }
+        for (key, lazy) in &other.lazy {
+            let _ = self.elements.remove(key);
+            let _ = self.json_keys.remove(key);
+            let _ = self.lazy.insert(key.clone(), lazy.clone());
+        }
+        self.sensitive.extend(other.sensitive.iter().cloned());
+        self.json_keys.extend(other.json_keys.iter().cloned());
+    }
+
+    /// Converts the context into a JSON object, the inverse of
+    /// [`Context::from_json`].
+    ///
+    /// Values set from JSON numbers, booleans, `null`, arrays, and
+    /// objects, with [`Context::set_json`] or [`Context::from_json`],
+    /// are restored as those values; every other value, including JSON
+    /// text since edited into invalid JSON, becomes a JSON string. Lazy
+    /// values are skipped, as they are when serializing a context.
+    ///
+    /// This method requires the `serde` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use serde_json::json;
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.set("count", 3);
+    /// context.set_json("author", json!({"name": "Ada", "age": 36}));
+    /// assert_eq!(
+    ///     context.to_json(),
+    ///     json!({"author": {"age": 36, "name": "Ada"}, "count": "3"})
+    /// );
+    /// ```
+    #[cfg(feature = "serde")]
+    #[must_use]
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::Value::Object(
+            self.iter_sorted()
+                .map(|(key, value)| {
+                    let json = if self.json_keys.contains(key) {
+                        serde_json::from_str(value).ok()
+                    } else {
+                        None
+                    };
+                    let json = json.unwrap_or_else(|| {
+                        serde_json::Value::String(value.clone())
+                    });
+                    (key.clone(), json)
+                })
+                .collect(),
+        )
+    }
+
+    /// Converts the context into JSON text, the inverse of
+    /// [`Context::from_json_str`], as described for
+    /// [`Context::to_json`].
+    ///
+    /// This method requires the `serde` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let json = r#"{"age":36,"name":"Ada","tags":["rust"]}"#;
+    /// let context = Context::from_json_str(json).unwrap();
+    /// assert_eq!(context.to_json_string(), json);
+    /// ```
+    #[cfg(feature = "serde")]
+    #[must_use]
+    pub fn to_json_string(&self) -> String {
+        self.to_json().to_string()
+    }
+
+    /// Marks `key` as sensitive, such as an API token or draft content,
+    /// so that its value is redacted from the `Debug` and `Display`
+    /// output of the context, from the errors of its getters, and from
+    /// the render errors of the engine.
+    ///
+    /// The mark stays when the value of `key` is set again or removed,
+    /// and is carried over by [`Context::merge`]. It does not change
+    /// how the value renders.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.set("title", "Home");
+    /// context.set("api_token", "s3cr3t");
+    /// context.mark_sensitive("api_token");
+    ///
+    /// assert!(!format!("{:?}", context).contains("s3cr3t"));
+    /// assert_eq!(context.to_string(), r#"{api_token: [REDACTED], title: "Home"}"#);
+    /// assert_eq!(context.redact("token=s3cr3t"), "token=[REDACTED]");
+    /// ```
+    pub fn mark_sensitive(&mut self, key: impl Into<String>) {
+        let _ = self.sensitive.insert(key.into());
+    }
+
+    /// Returns whether `key` was marked with
+    /// [`Context::mark_sensitive`].
+    #[must_use]
+    pub fn is_sensitive(&self, key: &str) -> bool {
+        self.sensitive.contains(key)
+    }
+
+    /// Returns the keys marked sensitive, sorted.
+    pub fn sensitive_keys(&self) -> impl Iterator<Item = &str> {
+        self.sensitive.iter().map(String::as_str)
+    }
+
+    /// Replaces the values of sensitive keys in `text` by [`REDACTED`],
+    /// for messages and logs that may quote them.
+    ///
+    /// Lazy values are redacted only once evaluated, and empty values
+    /// never.
+    #[must_use]
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for key in &self.sensitive {
+            let value = match self.elements.get(key) {
+                Some(value) => Some(Cow::Borrowed(value.as_str())),
+                None => self
+                    .lazy
+                    .get(key)
+                    .and_then(LazyValue::evaluated)
+                    .map(Cow::Owned),
+            };
+            match value {
+                Some(value)
+                    if !value.is_empty() && text.contains(&*value) =>
+                {
+                    text = Cow::Owned(text.replace(&*value, REDACTED));
+                }
+                _ => {}
+            }
+        }
+        text
+    }
+
+    /// Compares the context with `other`, listing the keys added,
+    /// removed, and changed from this context to `other`.
+    ///
+    /// Lazy values are compared without evaluating them: a lazy value
+    /// is unchanged only if both contexts share its provider, as in
+    /// clones.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut old = Context::new();
+    /// old.set("title", "Home");
+    /// old.set("draft", true);
+    ///
+    /// let mut new = old.clone();
+    /// new.set("title", "Welcome");
+    /// new.set("lang", "en");
+    /// let _ = new.remove("draft");
+    ///
+    /// let diff = old.diff(&new);
+    /// assert_eq!(diff.added, ["lang"]);
+    /// assert_eq!(diff.removed, ["draft"]);
+    /// assert_eq!(diff.changed, ["title"]);
+    /// assert_eq!(diff.to_string(), "added: lang; removed: draft; changed: title");
+    /// assert!(old.diff(&old).is_empty());
+    /// ```
+    #[must_use]
+    pub fn diff(&self, other: &Self) -> ContextDiff {
+        let mut diff = ContextDiff::default();
+        for key in self.elements.keys().chain(self.lazy.keys()) {
+            if !other.elements.contains_key(key)
+                && !other.lazy.contains_key(key)
+            {
+                diff.removed.push(key.clone());
+            } else if self.elements.get(key) != other.elements.get(key)
+                || self.lazy.get(key) != other.lazy.get(key)
+            {
+                diff.changed.push(key.clone());
+            }
+        }
+        for key in other.elements.keys().chain(other.lazy.keys()) {
+            if !self.elements.contains_key(key)
+                && !self.lazy.contains_key(key)
+            {
+                diff.added.push(key.clone());
+            }
+        }
+        diff.added.sort_unstable();
+        diff.removed.sort_unstable();
+        diff.changed.sort_unstable();
+        diff
+    }
+
+    /// Converts the context into `PageOptions`.
+    ///
+    /// Lazy values are evaluated so that every key is carried over as a
+    /// plain string.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.set("title", "Home");
+    /// let options = context.to_page_options();
+    /// assert_eq!(options.get("title"), Some(&"Home".to_string()));
+    /// ```
+    #[must_use]
+    pub fn to_page_options(&self) -> PageOptions {
+        let mut options = PageOptions::new();
+        for (key, value) in &self.elements {
+            options.set(key.clone(), value.clone());
+        }
+        for (key, lazy) in &self.lazy {
+            options.set(key.clone(), lazy.resolve());
+        }
+        options
+    }
+
+    /// Gets the entry for `key` for in-place manipulation.
+    ///
+    /// This mirrors [`HashMap::entry`](std::collections::HashMap::entry)
+    /// and avoids a separate lookup and insertion when populating
+    /// defaults. A [lazy](Context::set_lazy) value of `key` counts as
+    /// present: it is evaluated and stored as an eager value when the
+    /// entry is used.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key whose entry should be returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// let _ = context.entry("title").or_insert("Untitled");
+    /// let _ = context
+    ///     .entry("title")
+    ///     .and_modify(|title| title.push_str("!"))
+    ///     .or_insert("ignored");
+    /// assert_eq!(context.get("title"), Some(&"Untitled!".to_string()));
+    /// ```
+    pub fn entry<K: Into<String>>(&mut self, key: K) -> Entry<'_, S> {
+        Entry {
+            context: self,
+            key: key.into(),
+        }
+    }
+
+    /// Returns a displayable view of the context that redacts long values.
+    ///
+    /// Entries are printed in sorted key order. Any value longer than
+    /// `max_len` characters is replaced by a marker giving its length, so
+    /// large bodies or sensitive blobs do not flood logs.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_len` - The maximum number of characters shown for a value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.set("name", "Alice");
+    /// context.set("body", "x".repeat(100));
+    ///
+    /// assert_eq!(
+    ///     context.display_with(10).to_string(),
+    ///     "{body: <redacted 100 chars>, name: \"Alice\"}"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn display_with(
+        &self,
+        max_len: usize,
+    ) -> ContextDisplay<'_, S> {
+        ContextDisplay {
+            context: self,
+            max_len,
+        }
+    }
+}
+
+/// A view into a single entry of a [`Context`], which may be vacant or
+/// occupied.
+///
+/// This is created by [`Context::entry`].
+#[derive(Debug)]
+pub struct Entry<'a, S = FnvBuildHasher> {
+    context: &'a mut Context<S>,
+    key: String,
+}
+
+impl<'a, S: BuildHasher + Default> Entry<'a, S> {
+    /// Returns the key of this entry.
+    #[must_use]
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Replaces a lazy value of the key with its evaluated value, so
+    /// that the value the entry stores does not shadow it.
+    fn resolve_lazy(&mut self) {
+        if let Some(lazy) = self.context.lazy.remove(&self.key) {
+            let _ = self
+                .context
+                .elements
+                .insert(self.key.clone(), lazy.resolve());
+        }
+    }
+
+    /// Returns the value of the key, first inserting the result of
+    /// `default` if the key is missing.
+    fn get_or_insert_with<F>(mut self, default: F) -> &'a mut String
+    where
+        F: FnOnce() -> String,
+    {
+        self.resolve_lazy();
+        let Self { context, key } = self;
+        context.elements.get_or_insert_with(key, default)
+    }
+
+    /// Inserts `default` if the entry is vacant and returns a mutable
+    /// reference to the value.
+    ///
+    /// # Arguments
+    ///
+    /// * `default` - The value to insert if the key is missing.
+    pub fn or_insert<V: Into<String>>(
+        self,
+        default: V,
+    ) -> &'a mut String {
+        self.get_or_insert_with(|| default.into())
+    }
+
+    /// Inserts the result of `default` if the entry is vacant and returns
+    /// a mutable reference to the value.
+    ///
+    /// The closure is only called when the key is missing, so expensive
+    /// defaults are not computed needlessly.
+    ///
+    /// # Arguments
+    ///
+    /// * `default` - A function producing the value to insert.
+    pub fn or_insert_with<V, F>(self, default: F) -> &'a mut String
+    where
+        V: Into<String>,
+        F: FnOnce() -> V,
+    {
+        self.get_or_insert_with(|| default().into())
+    }
+
+    /// Inserts an empty string if the entry is vacant and returns a
+    /// mutable reference to the value.
+    pub fn or_default(self) -> &'a mut String {
+        self.get_or_insert_with(String::new)
+    }
+
+    /// Calls `f` with the value if the entry is occupied, then returns
+    /// the entry for further chaining.
+    ///
+    /// The modified value is a string: a key set from a JSON number,
+    /// array, or object with [`Context::set_json`] no longer converts
+    /// back to one.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - A function modifying the existing value in place.
+    #[must_use]
+    pub fn and_modify<F: FnOnce(&mut String)>(mut self, f: F) -> Self {
+        self.resolve_lazy();
+        if let Some(value) = self.context.elements.get_mut(&self.key) {
+            f(value);
+            let _ = self.context.json_keys.remove(&self.key);
+        }
+        self
+    }
+}
+
+/// A `Display` adapter for [`Context`] created by
+/// [`Context::display_with`].
+#[derive(Debug)]
+pub struct ContextDisplay<'a, S = FnvBuildHasher> {
+    context: &'a Context<S>,
+    max_len: usize,
+}
+
+impl<S> Clone for ContextDisplay<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S> Copy for ContextDisplay<'_, S> {}
+
+impl<S> fmt::Display for ContextDisplay<'_, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries: Vec<(&String, Option<&String>)> = self
+            .context
+            .elements
+            .iter()
+            .map(|(key, value)| (key, Some(value)))
+            .chain(self.context.lazy.keys().map(|key| (key, None)))
+            .collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+        write!(f, "{{")?;
+        for (index, (key, value)) in entries.into_iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            match value {
+                None => write!(f, "{}: <lazy>", key)?,
+                Some(_) if self.context.sensitive.contains(key) => {
+                    write!(f, "{}: {}", key, REDACTED)?;
+                }
+                Some(value) => {
+                    let len = value.chars().count();
+                    if len > self.max_len {
+                        write!(f, "{}: <redacted {} chars>", key, len)?;
+                    } else {
+                        write!(f, "{}: {:?}", key, value)?;
+                    }
+                }
+            }
+        }
+        write!(f, "}}")
+    }
+}
+
+/// The keys that differ between two contexts, as returned by
+/// [`Context::diff`]. Each list is sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContextDiff {
+    /// The keys set only in the second context.
+    pub added: Vec<String>,
+    /// The keys set only in the first context.
+    pub removed: Vec<String>,
+    /// The keys set in both contexts, to different values.
+    pub changed: Vec<String>,
+}
+
+impl ContextDiff {
+    /// Returns whether the contexts hold the same keys and values.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+    }
+
+    /// Returns the number of keys that differ.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.added.len() + self.removed.len() + self.changed.len()
+    }
+
+    /// Returns every key that differs, whether added, removed, or
+    /// changed.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.added
+            .iter()
+            .chain(&self.removed)
+            .chain(&self.changed)
+            .map(String::as_str)
+    }
+
+    /// Returns whether every key that differs is one of `keys`, such as
+    /// the engine's
+    /// [`cache_ignored_keys`](crate::Engine::cache_ignored_keys), in
+    /// which case both contexts share a render cache entry.
+    #[must_use]
+    pub fn is_within<K: AsRef<str>>(&self, keys: &[K]) -> bool {
+        self.keys()
+            .all(|key| keys.iter().any(|other| other.as_ref() == key))
+    }
+}
+
+impl fmt::Display for ContextDiff {
+    /// Formats the keys that differ, such as
+    /// `added: lang; changed: title`, or `no changes`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("no changes");
+        }
+        let mut first = true;
+        for (name, keys) in [
+            ("added", &self.added),
+            ("removed", &self.removed),
+            ("changed", &self.changed),
+        ] {
+            if keys.is_empty() {
+                continue;
+            }
+            if !first {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}: {}", name, keys.join(", "))?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+impl<S: BuildHasher + Default> fmt::Display for Context<S> {
+    /// Formats the context in sorted key order, redacting values longer
+    /// than [`DEFAULT_DISPLAY_MAX_LEN`] characters.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display_with(DEFAULT_DISPLAY_MAX_LEN).fmt(f)
+    }
+}
+
+impl<S: BuildHasher + Default> Hash for Context<S> {
+    /// Feeds the stable, order-independent [`Context::hash`] value into
+    /// `state`, so that equal contexts always hash equally.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(Self::hash(self));
+    }
+}
+
+/// Feeds a string into `hasher` using a platform-independent encoding.
+///
+/// The length is written as a little-endian `u64` ahead of the bytes so
+/// that the output does not depend on pointer width or endianness.
+fn write_stable_str(hasher: &mut FnvHasher, value: &str) {
+    hasher.write(&(value.len() as u64).to_le_bytes());
+    hasher.write(value.as_bytes());
+}
+
+impl From<PageOptions> for Context {
+    /// Creates a `Context` holding the same key-value pairs as the given
+    /// `PageOptions`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::{Context, PageOptions};
+    ///
+    /// let mut options = PageOptions::new();
+    /// options.set("title", "Home");
+    /// let context = Context::from(options);
+    /// assert_eq!(context.get("title"), Some(&"Home".to_string()));
+    /// ```
+    fn from(options: PageOptions) -> Self {
+        Self {
+            elements: ContextMap::from(options.elements),
+            lazy: FnvHashMap::default(),
+            sensitive: BTreeSet::new(),
+            json_keys: BTreeSet::new(),
+        }
     }
 }
 
-impl FromIterator<(String, String)> for Context {
+impl<S: BuildHasher + Default> FromIterator<(String, String)>
+    for Context<S>
+{
     /// Creates a `Context` from an iterator of key-value pairs.
     ///
     /// # Examples
@@ -325,49 +1575,451 @@ impl FromIterator<(String, String)> for Context {
     fn from_iter<I: IntoIterator<Item = (String, String)>>(
         iter: I,
     ) -> Self {
-        let mut context = Context::new();
+        let mut context = Self::default();
         context.extend(iter);
         context
     }
 }
 
-impl Extend<(String, String)> for Context {
-    /// Extends the context with the contents of the specified iterator.
-    ///
-    /// # Examples
+impl<S: BuildHasher + Default> Extend<(String, String)> for Context<S> {
+    /// Extends the context with the contents of the specified iterator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.extend(vec![
+    ///     ("key1".to_string(), "value1".to_string()),
+    ///     ("key2".to_string(), "value2".to_string()),
+    /// ]);
+    /// assert_eq!(context.get("key1"), Some(&"value1".to_string()));
+    /// assert_eq!(context.get("key2"), Some(&"value2".to_string()));
+    /// ```
+    fn extend<T: IntoIterator<Item = (String, String)>>(
+        &mut self,
+        iter: T,
+    ) {
+        for (key, value) in iter {
+            self.set(key, value);
+        }
+    }
+}
+
+/// The number of values a [`ContextMap`] holds inline before switching
+/// to a hash map.
+const INLINE_CAPACITY: usize = 8;
+
+/// The key-value storage of a [`Context`].
+///
+/// Small contexts, which most pages use, keep their values in a vector
+/// and find keys by a linear scan, which is faster than hashing for a
+/// handful of keys. Once more than eight values are stored, they move to
+/// a hash map with the hasher `S` of the context. The API mirrors the
+/// common subset of `HashMap`, and is reached through `Deref` on
+/// [`Context`]. Contexts are only changed through their own methods,
+/// which keep lazy and JSON values in step with the map.
+///
+/// With the `persistent` feature, the hash map is a persistent map
+/// whose nodes are shared between clones and copied only where a clone
+/// is modified, so cloning a large context, such as a global context
+/// handed to every page of a site, takes constant time.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::Context;
+///
+/// let mut context = Context::new();
+/// context.set("title", "Home");
+/// assert!(context.contains_key("title"));
+/// assert_eq!(context.keys().collect::<Vec<_>>(), ["title"]);
+/// ```
+#[derive(Clone)]
+pub struct ContextMap<S = FnvBuildHasher> {
+    repr: Repr<S>,
+}
+
+#[derive(Clone)]
+enum Repr<S> {
+    Inline(Vec<(String, String)>),
+    Map(Map<S>),
+}
+
+/// The hash map of a [`ContextMap`] past its inline capacity.
+#[cfg(not(feature = "persistent"))]
+type Map<S> = HashMap<String, String, S>;
+
+/// The hash map of a [`ContextMap`] past its inline capacity.
+#[cfg(feature = "persistent")]
+type Map<S> = imbl::GenericHashMap<
+    String,
+    String,
+    SharedHasher<S>,
+    imbl::shared_ptr::DefaultSharedPtr,
+>;
+
+/// An iterator over a [`Map`].
+#[cfg(not(feature = "persistent"))]
+type MapIter<'a> = std::collections::hash_map::Iter<'a, String, String>;
+
+/// An iterator over a [`Map`], boxed as it is far larger than a slice
+/// iterator.
+#[cfg(feature = "persistent")]
+type MapIter<'a> = Box<
+    imbl::hashmap::Iter<
+        'a,
+        String,
+        String,
+        imbl::shared_ptr::DefaultSharedPtr,
+    >,
+>;
+
+/// A hasher shared between the clones of a persistent [`Map`], which
+/// requires a cloneable hasher.
+#[cfg(feature = "persistent")]
+#[derive(Debug, Default)]
+struct SharedHasher<S>(Arc<S>);
+
+#[cfg(feature = "persistent")]
+impl<S> Clone for SharedHasher<S> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+#[cfg(feature = "persistent")]
+impl<S: BuildHasher> BuildHasher for SharedHasher<S> {
+    type Hasher = S::Hasher;
+
+    fn build_hasher(&self) -> S::Hasher {
+        self.0.build_hasher()
+    }
+}
+
+impl<S> ContextMap<S> {
+    /// Returns the number of values in the map.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        match &self.repr {
+            Repr::Inline(pairs) => pairs.len(),
+            Repr::Map(map) => map.len(),
+        }
+    }
+
+    /// Returns `true` if the map holds no values.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of values the map can hold without
+    /// reallocating. A persistent map adds nodes as it grows rather
+    /// than reallocating, so its capacity is its length.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        match &self.repr {
+            Repr::Inline(pairs) => pairs.capacity(),
+            #[cfg(not(feature = "persistent"))]
+            Repr::Map(map) => map.capacity(),
+            #[cfg(feature = "persistent")]
+            Repr::Map(map) => map.len(),
+        }
+    }
+
+    /// Removes every value, returning to inline storage.
+    pub fn clear(&mut self) {
+        self.repr = Repr::Inline(Vec::new());
+    }
+
+    /// Returns an iterator over the keys and values, in no particular
+    /// order.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            inner: match &self.repr {
+                Repr::Inline(pairs) => IterRepr::Inline(pairs.iter()),
+                #[cfg(not(feature = "persistent"))]
+                Repr::Map(map) => IterRepr::Map(map.iter()),
+                #[cfg(feature = "persistent")]
+                Repr::Map(map) => IterRepr::Map(Box::new(map.iter())),
+            },
+        }
+    }
+
+    /// Returns an iterator over the keys and values, in sorted key
+    /// order.
+    pub fn iter_sorted(
+        &self,
+    ) -> impl Iterator<Item = (&String, &String)> {
+        let mut pairs: Vec<(&String, &String)> = self.iter().collect();
+        pairs.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        pairs.into_iter()
+    }
+
+    /// Returns an iterator over the keys, in no particular order.
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Returns an iterator over the values, in no particular order.
+    pub fn values(&self) -> impl Iterator<Item = &String> {
+        self.iter().map(|(_, value)| value)
+    }
+}
+
+impl<S: BuildHasher + Default> ContextMap<S> {
+    /// Creates an empty map with room for `capacity` values.
+    fn with_capacity(capacity: usize) -> Self {
+        let repr = if capacity > INLINE_CAPACITY {
+            #[cfg(not(feature = "persistent"))]
+            let map = HashMap::with_capacity_and_hasher(
+                capacity,
+                S::default(),
+            );
+            #[cfg(feature = "persistent")]
+            let map = Map::default();
+            Repr::Map(map)
+        } else {
+            Repr::Inline(Vec::with_capacity(capacity))
+        };
+        Self { repr }
+    }
+
+    /// Returns the value of `key`.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&String> {
+        match &self.repr {
+            Repr::Inline(pairs) => pairs
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value),
+            Repr::Map(map) => map.get(key),
+        }
+    }
+
+    /// Returns a mutable reference to the value of `key`.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut String> {
+        match &mut self.repr {
+            Repr::Inline(pairs) => pairs
+                .iter_mut()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value),
+            Repr::Map(map) => map.get_mut(key),
+        }
+    }
+
+    /// Returns `true` if the map holds a value for `key`.
+    #[must_use]
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Stores `value` under `key`, returning the previous value.
+    pub fn insert(
+        &mut self,
+        key: String,
+        value: String,
+    ) -> Option<String> {
+        if let Some(old) = self.get_mut(&key) {
+            return Some(std::mem::replace(old, value));
+        }
+        self.push(key, value);
+        None
+    }
+
+    /// Removes `key`, returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        match &mut self.repr {
+            Repr::Inline(pairs) => {
+                let index = pairs.iter().position(|(k, _)| k == key)?;
+                Some(pairs.swap_remove(index).1)
+            }
+            Repr::Map(map) => map.remove(key),
+        }
+    }
+
+    /// Returns the value of `key`, first inserting the result of
+    /// `default` if the key is missing.
+    fn get_or_insert_with<F>(
+        &mut self,
+        key: String,
+        default: F,
+    ) -> &mut String
+    where
+        F: FnOnce() -> String,
+    {
+        if let Repr::Inline(pairs) = &mut self.repr {
+            if pairs.len() >= INLINE_CAPACITY
+                && !pairs.iter().any(|(k, _)| *k == key)
+            {
+                self.repr = Repr::Map(pairs.drain(..).collect());
+            }
+        }
+        match &mut self.repr {
+            Repr::Inline(pairs) => {
+                let index =
+                    match pairs.iter().position(|(k, _)| *k == key) {
+                        Some(index) => index,
+                        None => {
+                            pairs.push((key, default()));
+                            pairs.len() - 1
+                        }
+                    };
+                &mut pairs[index].1
+            }
+            Repr::Map(map) => map.entry(key).or_insert_with(default),
+        }
+    }
+
+    /// Adds a value for a key that is not in the map yet, moving the
+    /// values to a hash map once the inline storage is full.
+    fn push(&mut self, key: String, value: String) {
+        match &mut self.repr {
+            Repr::Inline(pairs) if pairs.len() < INLINE_CAPACITY => {
+                pairs.push((key, value));
+            }
+            Repr::Inline(pairs) => {
+                let mut map: Map<S> = pairs.drain(..).collect();
+                let _ = map.insert(key, value);
+                self.repr = Repr::Map(map);
+            }
+            Repr::Map(map) => {
+                let _ = map.insert(key, value);
+            }
+        }
+    }
+}
+
+impl<S> Default for ContextMap<S> {
+    fn default() -> Self {
+        Self {
+            repr: Repr::Inline(Vec::new()),
+        }
+    }
+}
+
+impl<S> fmt::Debug for ContextMap<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<S: BuildHasher + Default> PartialEq for ContextMap<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl<S: BuildHasher + Default> Eq for ContextMap<S> {}
+
+impl<S: BuildHasher + Default> From<HashMap<String, String, S>>
+    for ContextMap<S>
+{
+    fn from(map: HashMap<String, String, S>) -> Self {
+        let repr = if map.len() > INLINE_CAPACITY {
+            #[cfg(feature = "persistent")]
+            let map = map.into_iter().collect();
+            Repr::Map(map)
+        } else {
+            Repr::Inline(map.into_iter().collect())
+        };
+        Self { repr }
+    }
+}
+
+impl<S: BuildHasher + Default> Index<&str> for ContextMap<S> {
+    type Output = String;
+
+    /// Returns the value of `key`.
     ///
-    /// ```
-    /// use staticweaver::Context;
+    /// # Panics
     ///
-    /// let mut context = Context::new();
-    /// context.extend(vec![
-    ///     ("key1".to_string(), "value1".to_string()),
-    ///     ("key2".to_string(), "value2".to_string()),
-    /// ]);
-    /// assert_eq!(context.get("key1"), Some(&"value1".to_string()));
-    /// assert_eq!(context.get("key2"), Some(&"value2".to_string()));
-    /// ```
-    fn extend<T: IntoIterator<Item = (String, String)>>(
-        &mut self,
-        iter: T,
-    ) {
-        for (key, value) in iter {
-            self.set(key, value);
+    /// Panics if `key` is not in the map.
+    fn index(&self, key: &str) -> &String {
+        match self.get(key) {
+            Some(value) => value,
+            None => panic!("key not found: {}", key),
         }
     }
 }
 
-impl Deref for Context {
-    type Target = FnvHashMap<String, String>;
+impl<'a, S> IntoIterator for &'a ContextMap<S> {
+    type Item = (&'a String, &'a String);
+    type IntoIter = Iter<'a>;
 
-    fn deref(&self) -> &Self::Target {
-        &self.elements
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<H> serde::Serialize for ContextMap<H> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter_sorted())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, S: BuildHasher + Default> serde::Deserialize<'de>
+    for ContextMap<S>
+{
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        HashMap::deserialize(deserializer).map(Self::from)
     }
 }
 
-impl DerefMut for Context {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.elements
+/// An iterator over the keys and values of a [`ContextMap`].
+#[derive(Clone)]
+pub struct Iter<'a> {
+    inner: IterRepr<'a>,
+}
+
+#[derive(Clone)]
+enum IterRepr<'a> {
+    Inline(std::slice::Iter<'a, (String, String)>),
+    Map(MapIter<'a>),
+}
+
+impl fmt::Debug for Iter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a String, &'a String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.inner {
+            IterRepr::Inline(pairs) => {
+                pairs.next().map(|(key, value)| (key, value))
+            }
+            IterRepr::Map(map) => map.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.inner {
+            IterRepr::Inline(pairs) => pairs.size_hint(),
+            IterRepr::Map(map) => map.size_hint(),
+        }
+    }
+}
+
+impl<S> Deref for Context<S> {
+    type Target = ContextMap<S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.elements
     }
 }
 
@@ -381,23 +2033,54 @@ fn test_new_context() {
         assert!(context.is_empty());
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_from_json_str() {
+        let context = Context::from_json_str(
+            r#"{"title": "Home", "tags": ["a", "b"]}"#,
+        )
+        .unwrap();
+        assert_eq!(context.get("title"), Some(&"Home".to_string()));
+        assert_eq!(
+            context.get("tags"),
+            Some(&r#"["a","b"]"#.to_string())
+        );
+        assert!(matches!(
+            Context::from_json_str("[1, 2]"),
+            Err(TemplateError::InvalidSyntax(_))
+        ));
+    }
+
     #[test]
     fn test_with_capacity() {
-        let context = Context::with_capacity(10);
-        assert!(context.capacity() >= 10);
+        let context = Context::with_capacity(4);
+        assert!(context.capacity() >= 4);
+        #[cfg(not(feature = "persistent"))]
+        assert!(Context::with_capacity(100).capacity() >= 100);
+    }
+
+    #[cfg(feature = "persistent")]
+    #[test]
+    fn test_persistent_capacity_is_len() {
+        let mut context = Context::with_capacity(100);
+        assert_eq!(context.capacity(), 0);
+        for i in 0..=INLINE_CAPACITY {
+            context.set(format!("key{}", i), i);
+        }
+        assert_eq!(context.capacity(), context.len());
     }
 
     #[test]
     fn test_set_and_get() {
         let mut context = Context::new();
-        context.set("key".to_string(), "value".to_string());
+        context.set("key", "value");
         assert_eq!(context.get("key"), Some(&"value".to_string()));
     }
 
     #[test]
     fn test_get_mut() {
         let mut context = Context::new();
-        context.set("key".to_string(), "value".to_string());
+        context.set("key", "value");
         if let Some(value) = context.get_mut("key") {
             *value = "new_value".to_string();
         }
@@ -407,7 +2090,7 @@ fn test_get_mut() {
     #[test]
     fn test_remove() {
         let mut context = Context::new();
-        context.set("key".to_string(), "value".to_string());
+        context.set("key", "value");
         assert_eq!(context.remove("key"), Some("value".to_string()));
         assert_eq!(context.get("key"), None);
     }
@@ -415,17 +2098,184 @@ fn test_remove() {
     #[test]
     fn test_hash() {
         let mut context1 = Context::new();
-        context1.set("key1".to_string(), "value1".to_string());
+        context1.set("key1", "value1");
+
+        let mut context2 = Context::new();
+        context2.set("key1", "value1");
+
+        assert_eq!(context1.hash(), context2.hash());
+
+        context2.set("key2", "value2");
+        assert_ne!(context1.hash(), context2.hash());
+    }
 
+    #[test]
+    fn test_hash_lazy_value() {
+        let mut context1 = Context::new();
+        context1.set_lazy("rt", || "1 min".to_string());
         let mut context2 = Context::new();
-        context2.set("key1".to_string(), "value1".to_string());
+        context2.set_lazy("rt", || "9 min".to_string());
+        assert_ne!(context1.hash(), context2.hash());
+
+        let mut context3 = Context::new();
+        context3.set_lazy("rt", || "1 min".to_string());
+        assert_eq!(context1.hash(), context3.hash());
+    }
 
+    #[test]
+    fn test_hash_is_order_independent() {
+        let mut context1 = Context::new();
+        let mut context2 = Context::new();
+        for i in 0..32 {
+            context1.set(format!("key{}", i), format!("value{}", i));
+        }
+        for i in (0..32).rev() {
+            context2.set(format!("key{}", i), format!("value{}", i));
+        }
         assert_eq!(context1.hash(), context2.hash());
+    }
+
+    #[test]
+    fn test_hash_is_stable() {
+        let mut context = Context::new();
+        context.set("name", "Alice");
+        context.set("title", "Home");
+        assert_eq!(context.hash(), 0x1e5541fddac36b01);
+    }
+
+    #[test]
+    fn test_hash_distinguishes_boundaries() {
+        let mut context1 = Context::new();
+        context1.set("ab", "c");
+
+        let mut context2 = Context::new();
+        context2.set("a", "bc");
 
-        context2.set("key2".to_string(), "value2".to_string());
         assert_ne!(context1.hash(), context2.hash());
     }
 
+    // The memoized lazy values are interior-mutable, but they never
+    // change once evaluated, so neither `Hash` nor `Eq` changes.
+    #[allow(clippy::mutable_key_type)]
+    #[test]
+    fn test_std_hash_as_map_key() {
+        use std::collections::HashMap;
+
+        let mut context1 = Context::new();
+        context1.set("key", "value");
+        let context2 = context1.clone();
+
+        let mut map = HashMap::new();
+        let _ = map.insert(context1, 1);
+        assert_eq!(map.get(&context2), Some(&1));
+    }
+
+    #[test]
+    fn test_display() {
+        let mut context = Context::new();
+        context.set("b", "two");
+        context.set("a", "one");
+        assert_eq!(context.to_string(), "{a: \"one\", b: \"two\"}");
+        assert_eq!(Context::new().to_string(), "{}");
+    }
+
+    #[test]
+    fn test_display_redacts_long_values() {
+        let mut context = Context::new();
+        context.set("body", "x".repeat(DEFAULT_DISPLAY_MAX_LEN + 1));
+        assert_eq!(
+            context.to_string(),
+            format!(
+                "{{body: <redacted {} chars>}}",
+                DEFAULT_DISPLAY_MAX_LEN + 1
+            )
+        );
+        assert_eq!(
+            context.display_with(3).to_string(),
+            format!(
+                "{{body: <redacted {} chars>}}",
+                DEFAULT_DISPLAY_MAX_LEN + 1
+            )
+        );
+    }
+
+    #[test]
+    fn test_json_keys_in_eq_and_hash() {
+        let mut json = Context::new();
+        json.set_json("count", serde_json::json!(3));
+        let mut text = Context::new();
+        text.set("count", "3");
+        assert_eq!(json.get("count"), text.get("count"));
+        assert_ne!(json, text);
+        assert_ne!(json.hash(), text.hash());
+
+        text.set_json("count", serde_json::json!(3));
+        assert_eq!(json, text);
+        assert_eq!(json.hash(), text.hash());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_value_round_trip() {
+        use serde_json::json;
+
+        let value = json!({
+            "title": "Home",
+            "count": "3",
+            "draft": false,
+            "weight": 1.5,
+            "summary": null,
+            "tags": ["rust", "web"],
+            "author": {"name": "Ada", "links": [{"url": "/ada"}]},
+        });
+        let mut context = Context::from_json(value.clone()).unwrap();
+        assert_eq!(context.get("count"), Some(&"3".to_string()));
+        assert_eq!(context.get("draft"), Some(&"false".to_string()));
+        assert_eq!(context.to_json(), value);
+        assert_eq!(
+            Context::from_json_str(&context.to_json_string())
+                .unwrap()
+                .to_json(),
+            value
+        );
+
+        context.set("draft", "false");
+        let _ = context.get_mut("tags").unwrap().pop();
+        let _ = context.remove("summary");
+        context.set_lazy("lazy", || "value".to_string());
+        let value = context.to_json();
+        assert_eq!(value["draft"], json!("false"));
+        assert_eq!(value["tags"], json!(r#"["rust","web""#));
+        assert_eq!(value["weight"], json!(1.5));
+        assert!(value.get("summary").is_none());
+        assert!(value.get("lazy").is_none());
+
+        let mut merged = Context::new();
+        merged.set_json("draft", json!(true));
+        merged.merge(&context);
+        assert_eq!(merged.to_json()["draft"], json!("false"));
+        assert_eq!(merged.to_json()["author"]["name"], json!("Ada"));
+
+        assert!(matches!(
+            Context::from_json(json!([1, 2])),
+            Err(TemplateError::InvalidSyntax(_))
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut context = Context::new();
+        context.set("title", "Home");
+        context.set("name", "Alice");
+
+        let json = serde_json::to_string(&context).unwrap();
+        assert_eq!(json, r#"{"name":"Alice","title":"Home"}"#);
+
+        let decoded: Context = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, context);
+    }
+
     #[test]
     fn test_from_iterator() {
         let pairs = vec![
@@ -451,8 +2301,8 @@ fn test_extend() {
     #[test]
     fn test_iter() {
         let mut context = Context::new();
-        context.set("key1".to_string(), "value1".to_string());
-        context.set("key2".to_string(), "value2".to_string());
+        context.set("key1", "value1");
+        context.set("key2", "value2");
 
         let mut pairs: Vec<(&String, &String)> =
             context.iter().collect();
@@ -467,11 +2317,49 @@ fn test_iter() {
         );
     }
 
+    #[test]
+    fn test_iter_sorted() {
+        let mut context = Context::new();
+        let keys: Vec<String> =
+            (0..20).map(|i| format!("key{:02}", i)).collect();
+        for key in keys.iter().rev() {
+            context.set(key.clone(), key.len());
+        }
+        context.set_lazy("lazy", || "value".to_string());
+
+        let sorted: Vec<&String> =
+            context.iter_sorted().map(|(key, _)| key).collect();
+        assert_eq!(sorted, keys.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_storage_grows_past_inline_capacity() {
+        let mut context = Context::new();
+        for i in 0..INLINE_CAPACITY {
+            context.set(format!("key{}", i), i);
+        }
+        assert!(matches!(context.elements.repr, Repr::Inline(_)));
+        let small = context.clone();
+
+        context.set("key0", "replaced");
+        *context.entry("extra").or_default() = "value".to_string();
+        assert!(matches!(context.elements.repr, Repr::Map(_)));
+        assert_eq!(context.len(), INLINE_CAPACITY + 1);
+        assert_eq!(context.get("key0"), Some(&"replaced".to_string()));
+        assert_eq!(context["extra"], "value");
+
+        let _ = context.remove("extra");
+        context.set("key0", 0);
+        assert_eq!(context, small);
+        context.clear();
+        assert!(matches!(context.elements.repr, Repr::Inline(_)));
+    }
+
     #[test]
     fn test_clear() {
         let mut context = Context::new();
-        context.set("key1".to_string(), "value1".to_string());
-        context.set("key2".to_string(), "value2".to_string());
+        context.set("key1", "value1");
+        context.set("key2", "value2");
         assert_eq!(context.len(), 2);
 
         context.clear();
@@ -481,14 +2369,14 @@ fn test_clear() {
     #[test]
     fn test_deref() {
         let mut context = Context::new();
-        context.set("key".to_string(), "value".to_string());
+        context.set("key", "value");
         assert_eq!(context["key"], "value");
     }
 
     #[test]
     fn test_deref_mut() {
         let mut context = Context::new();
-        context.set("key".to_string(), "value".to_string());
+        context.set("key", "value");
 
         // Use the entry API to modify the value
         let _ = context
@@ -498,13 +2386,373 @@ fn test_deref_mut() {
         assert_eq!(context.get("key"), Some(&"new_value".to_string()));
     }
 
+    #[test]
+    fn test_entry_or_insert() {
+        let mut context = Context::new();
+        assert_eq!(
+            context.entry("key").or_insert("default"),
+            "default"
+        );
+        assert_eq!(context.entry("key").or_insert("other"), "default");
+        assert_eq!(context.entry("empty").or_default(), "");
+    }
+
+    #[test]
+    fn test_entry_or_insert_with_is_lazy() {
+        let mut context = Context::new();
+        context.set("key", "value");
+
+        let mut calls = 0;
+        let _ = context.entry("key").or_insert_with(|| {
+            calls += 1;
+            "computed"
+        });
+        assert_eq!(calls, 0);
+        assert_eq!(context.get("key"), Some(&"value".to_string()));
+
+        let _ = context.entry("other").or_insert_with(|| {
+            calls += 1;
+            "computed"
+        });
+        assert_eq!(calls, 1);
+        assert_eq!(context.get("other"), Some(&"computed".to_string()));
+    }
+
+    #[test]
+    fn test_entry_and_modify() {
+        let mut context = Context::new();
+        let entry = context.entry("count");
+        assert_eq!(entry.key(), "count");
+        let _ = entry.and_modify(|v| v.push('!')).or_insert("0");
+        let _ = context
+            .entry("count")
+            .and_modify(|v| v.push('!'))
+            .or_insert("0");
+        assert_eq!(context.get("count"), Some(&"0!".to_string()));
+    }
+
+    #[test]
+    fn test_entry_resolves_lazy_value() {
+        let mut context = Context::new();
+        context.set_lazy("title", || "Home".to_string());
+        assert_eq!(
+            context.entry("title").or_insert("Untitled"),
+            "Home"
+        );
+        assert!(!context.is_lazy("title"));
+        assert_eq!(context.get("title"), Some(&"Home".to_string()));
+
+        context.set_lazy("title", || "Home".to_string());
+        let _ = context.entry("title").and_modify(|v| v.push('!'));
+        assert_eq!(context.resolve("title").as_deref(), Some("Home!"));
+    }
+
+    #[test]
+    fn test_entry_and_modify_clears_json_mark() {
+        let mut json = Context::new();
+        json.set_json("count", serde_json::json!(3));
+        let _ = json.entry("count").and_modify(|v| v.push('0'));
+        let mut text = Context::new();
+        text.set("count", "30");
+        assert_eq!(json, text);
+
+        json.set_json("count", serde_json::json!(3));
+        let _ = json.entry("count").or_insert("0");
+        assert_ne!(json, text);
+    }
+
+    #[test]
+    fn test_get_as() {
+        let mut context = Context::new();
+        context.set("count", " 7 ");
+        context.set("name", "Alice");
+
+        assert_eq!(context.get_as::<u8>("count").unwrap(), 7);
+        assert!(matches!(
+            context.get_as::<u8>("missing"),
+            Err(TemplateError::MissingVariable(key)) if key == "missing"
+        ));
+        assert!(matches!(
+            context.get_as::<u8>("name"),
+            Err(TemplateError::InvalidValue { key, .. }) if key == "name"
+        ));
+    }
+
+    #[test]
+    fn test_get_bool() {
+        let mut context = Context::new();
+        context.set("a", "TRUE");
+        context.set("b", "off");
+        context.set("c", "maybe");
+
+        assert!(context.get_bool("a").unwrap());
+        assert!(!context.get_bool("b").unwrap());
+        assert!(matches!(
+            context.get_bool("c"),
+            Err(TemplateError::InvalidValue { key, .. }) if key == "c"
+        ));
+    }
+
+    #[test]
+    fn test_get_numbers() {
+        let mut context = Context::new();
+        context.set("int", "-12");
+        context.set("float", "2.5");
+
+        assert_eq!(context.get_i64("int").unwrap(), -12);
+        assert_eq!(context.get_f64("float").unwrap(), 2.5);
+        assert!(context.get_i64("float").is_err());
+    }
+
+    #[test]
+    fn test_typed_getters_resolve_lazy_values() {
+        let mut context = Context::new();
+        context.set_lazy("n", || "42".to_string());
+        context.set_lazy("draft", || "yes".to_string());
+        assert_eq!(context.get_as::<i64>("n").unwrap(), 42);
+        assert_eq!(context.get_f64("n").unwrap(), 42.0);
+        assert!(context.get_bool("draft").unwrap());
+    }
+
+    #[test]
+    fn test_lazy_values_are_counted() {
+        let mut context = Context::new();
+        context.set_lazy("n", || panic!("should not be evaluated"));
+        assert_eq!(context.len(), 1);
+        assert!(!context.is_empty());
+        assert!(context.contains_key("n"));
+        assert_eq!(context.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_set_lazy_is_evaluated_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let mut context = Context::new();
+        context.set_lazy("expensive", move || {
+            let _ = counter.fetch_add(1, Ordering::SeqCst);
+            "computed".to_string()
+        });
+
+        assert!(context.is_lazy("expensive"));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert_eq!(
+            context.resolve("expensive").as_deref(),
+            Some("computed")
+        );
+        assert_eq!(
+            context.resolve("expensive").as_deref(),
+            Some("computed")
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_set_replaces_lazy() {
+        let mut context = Context::new();
+        context.set_lazy("key", || "lazy".to_string());
+        context.set("key", "eager");
+        assert!(!context.is_lazy("key"));
+        assert_eq!(context.resolve("key").as_deref(), Some("eager"));
+
+        context.set_lazy("key", || "lazy".to_string());
+        assert_eq!(context.get("key"), None);
+        assert_eq!(context.resolve("key").as_deref(), Some("lazy"));
+        assert_eq!(context.to_string(), "{key: <lazy>}");
+    }
+
+    #[test]
+    fn test_page_options_round_trip() {
+        let mut options = PageOptions::new();
+        options.set("title", "Home");
+
+        let mut context = Context::from(options.clone());
+        assert_eq!(context.get("title"), Some(&"Home".to_string()));
+        assert_eq!(context.to_page_options(), options);
+
+        context.set_lazy("lazy", || "value".to_string());
+        assert_eq!(
+            context.to_page_options().get("lazy"),
+            Some(&"value".to_string())
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut base = Context::new();
+        base.set("a", "1");
+        base.set("b", "1");
+
+        let mut other = Context::new();
+        other.set("b", "2");
+        other.set_lazy("c", || "3".to_string());
+
+        base.merge(&other);
+        assert_eq!(base.get("a"), Some(&"1".to_string()));
+        assert_eq!(base.get("b"), Some(&"2".to_string()));
+        assert!(base.is_lazy("c"));
+        assert_eq!(base.resolve("c").as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut old = Context::new();
+        old.set("title", "Home");
+        old.set_lazy("menu", || "a, b".to_string());
+        old.set_lazy("footer", || "(c)".to_string());
+
+        let mut new = old.clone();
+        assert!(old.diff(&new).is_empty());
+        assert_eq!(old.diff(&new).to_string(), "no changes");
+        new.set("menu", "a, b");
+        new.set_lazy("footer", || "(c)".to_string());
+        new.set("build", 2);
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, ["build"]);
+        assert_eq!(diff.changed, ["footer", "menu"]);
+        assert_eq!(diff.len(), 3);
+        assert!(diff.is_within(&["build", "footer", "menu", "x"]));
+        assert!(!diff.is_within(&["build"]));
+
+        let diff = new.diff(&old);
+        assert_eq!(diff.removed, ["build"]);
+        assert_eq!(
+            diff.to_string(),
+            "removed: build; changed: footer, menu"
+        );
+    }
+
+    #[test]
+    fn test_sensitive_keys() {
+        let mut context = Context::new();
+        context.set("token", "s3cr3t");
+        context.set("draft", "maybe s3cr3t");
+        context.set_lazy("notes", || "private".to_string());
+        context.mark_sensitive("token");
+        context.mark_sensitive("notes");
+        assert!(context.is_sensitive("token"));
+        assert_eq!(
+            context.sensitive_keys().collect::<Vec<_>>(),
+            ["notes", "token"]
+        );
+
+        let debug = format!("{:?}", context);
+        assert!(!debug.contains("\"s3cr3t\""), "{}", debug);
+        assert!(debug.contains("\"token\": \"[REDACTED]\""));
+        assert!(context.to_string().contains("token: [REDACTED]"));
+        assert!(!context
+            .get_bool("draft")
+            .unwrap_err()
+            .to_string()
+            .contains("s3cr3t"));
+
+        assert_eq!(context.redact("see private"), "see private");
+        assert_eq!(context.resolve("notes").unwrap(), "private");
+        assert_eq!(context.redact("see private"), "see [REDACTED]");
+
+        context.set("token", "rotated");
+        let mut merged = Context::new();
+        merged.merge(&context);
+        assert_eq!(merged.redact("rotated"), "[REDACTED]");
+        assert_eq!(merged.resolve("token").unwrap(), "rotated");
+    }
+
+    #[test]
+    fn test_hash_ignoring() {
+        let ignored = ["build".to_string()];
+        let mut old = Context::new();
+        old.set("title", "Home");
+        old.set("build", 1);
+        let mut new = old.clone();
+        new.set("build", 2);
+        assert!(old.diff(&new).is_within(&ignored));
+        assert_ne!(old.hash(), new.hash());
+        assert_eq!(
+            old.hash_ignoring(&ignored),
+            new.hash_ignoring(&ignored)
+        );
+        assert_eq!(old.hash_ignoring(&[]), old.hash());
+
+        let _ = new.remove("build");
+        assert_eq!(
+            old.hash_ignoring(&ignored),
+            new.hash_ignoring(&ignored)
+        );
+        assert_ne!(new.hash_ignoring(&ignored), new.hash());
+    }
+
     #[test]
     fn test_update() {
         let mut context = Context::new();
-        context.set("key".to_string(), "value".to_string());
+        context.set("key", "value");
 
         context.update("key", "new_value");
 
         assert_eq!(context.get("key"), Some(&"new_value".to_string()));
     }
+
+    #[test]
+    fn test_custom_hasher() {
+        use std::collections::hash_map::RandomState;
+
+        let mut sip: Context<RandomState> = Context::default();
+        let mut fnv = Context::new();
+        for i in 0..=INLINE_CAPACITY {
+            sip.set(format!("key{}", i), i);
+            fnv.set(format!("key{}", i), i);
+        }
+        assert_eq!(sip.len(), INLINE_CAPACITY + 1);
+        assert_eq!(sip.get("key8"), Some(&"8".to_string()));
+        assert_eq!(sip.hash(), fnv.hash());
+
+        let _ = sip.entry("key0").and_modify(|value| value.push('!'));
+        assert_eq!(sip["key0"], "0!");
+        let copy: Context<RandomState> =
+            sip.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        assert_eq!(copy, sip);
+    }
+
+    #[test]
+    fn test_clone_is_independent() {
+        let mut global = Context::new();
+        for i in 0..100 {
+            global.set(format!("key{}", i), i);
+        }
+        let mut page = global.clone();
+        page.set("key0", "page");
+        page.set("title", "Home");
+        let _ = page.remove("key1");
+        *page.get_mut("key2").unwrap() = "edited".to_string();
+
+        assert_eq!(global.len(), 100);
+        assert_eq!(global["key0"], "0");
+        assert_eq!(global["key1"], "1");
+        assert_eq!(global["key2"], "2");
+        assert!(!global.contains_key("title"));
+        assert_eq!(page.len(), 100);
+        assert_eq!(page["key0"], "page");
+        assert_eq!(page["key99"], "99");
+    }
+
+    #[cfg(feature = "persistent")]
+    #[test]
+    fn test_clone_shares_storage() {
+        let mut global = Context::new();
+        for i in 0..100 {
+            global.set(format!("key{}", i), i);
+        }
+        let mut page = global.clone();
+        let shared = |a: &Context, b: &Context| match (
+            &a.elements.repr,
+            &b.elements.repr,
+        ) {
+            (Repr::Map(a), Repr::Map(b)) => a.ptr_eq(b),
+            _ => false,
+        };
+        assert!(shared(&global, &page));
+        page.set("title", "Home");
+        assert!(!shared(&global, &page));
+    }
 }
@@ -0,0 +1,264 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # CSP Module
+//!
+//! This module helps pages comply with a Content Security Policy that
+//! restricts inline scripts and styles. Templates mark inline elements
+//! with the `csp_nonce()` function, and can emit the policy of the page
+//! with the `csp_policy()` function:
+//!
+//! ```text
+//! <meta http-equiv="Content-Security-Policy" content="{{ csp_policy() }}">
+//! <script nonce="{{ csp_nonce() }}">init();</script>
+//! ```
+//!
+//! Both functions write placeholders, so the render cache stores pages
+//! without a nonce. The [`finalize`] pass, run by
+//! [`Engine::render_page_csp`](crate::Engine::render_page_csp) on every
+//! render, then replaces the placeholders with a fresh nonce and with a
+//! policy listing the SHA-256 hashes of the inline scripts and styles of
+//! the page as served, cache hits included. The policy is also returned
+//! to the caller, e.g. for a `Content-Security-Policy` header.
+//!
+//! Pages that call these functions must be finalized before they are
+//! served.
+
+use crate::engine::EngineError;
+use crate::escape::escape_html;
+use crate::function::Functions;
+use crate::integrity::base64;
+use sha2::{Digest, Sha256};
+use std::io;
+
+/// The name of the nonce function.
+pub const CSP_NONCE_FUNCTION: &str = "csp_nonce";
+
+/// The name of the policy function.
+pub const CSP_POLICY_FUNCTION: &str = "csp_policy";
+
+/// Written by `csp_nonce()` and replaced by the nonce of the response.
+const NONCE_PLACEHOLDER: &str = "\u{fdd0}csp-nonce\u{fdd0}";
+
+/// Written by `csp_policy()` and replaced by the policy of the page.
+const POLICY_PLACEHOLDER: &str = "\u{fdd0}csp-policy\u{fdd0}";
+
+/// The number of random bytes in a nonce.
+const NONCE_BYTES: usize = 16;
+
+/// Registers the `csp_nonce` and `csp_policy` functions.
+pub(crate) fn register(functions: &mut Functions) {
+    functions.register(CSP_NONCE_FUNCTION, |_| {
+        Ok(NONCE_PLACEHOLDER.to_string())
+    });
+    functions.register(CSP_POLICY_FUNCTION, |_| {
+        Ok(POLICY_PLACEHOLDER.to_string())
+    });
+}
+
+/// A finalized page and the Content Security Policy it complies with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CspPage {
+    /// The page, with its nonce and policy filled in.
+    pub html: String,
+    /// The nonce of the page, in base64.
+    pub nonce: String,
+    /// The hashes of the inline scripts of the page, as policy sources
+    /// such as `'sha256-...'`, sorted and without duplicates.
+    pub script_hashes: Vec<String>,
+    /// The hashes of the inline styles of the page, as policy sources.
+    pub style_hashes: Vec<String>,
+}
+
+impl CspPage {
+    /// Returns the `script-src` and `style-src` directives allowing the
+    /// nonce and the inline elements of the page, as written by
+    /// `csp_policy()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::csp::finalize;
+    ///
+    /// let page = finalize("<style>p{}</style>", "bm9uY2U=");
+    /// assert_eq!(
+    ///     page.policy(),
+    ///     "script-src 'nonce-bm9uY2U='; style-src 'nonce-bm9uY2U=' \
+    ///      'sha256-gG2yISYereRMiG2lMXrbiUgi0Ubw9p7QCeWcroOvy9Y='"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn policy(&self) -> String {
+        let nonce = format!("'nonce-{}'", self.nonce);
+        let directive = |name: &str, hashes: &[String]| {
+            let mut directive = format!("{} {}", name, nonce);
+            for hash in hashes {
+                directive.push(' ');
+                directive.push_str(hash);
+            }
+            directive
+        };
+        format!(
+            "{}; {}",
+            directive("script-src", &self.script_hashes),
+            directive("style-src", &self.style_hashes)
+        )
+    }
+}
+
+/// Fills in the nonce and policy of a rendered page, and collects the
+/// hashes of its inline scripts and styles.
+///
+/// Scripts with a `src` attribute are not inline and are not hashed.
+///
+/// # Arguments
+///
+/// * `page` - The rendered page.
+/// * `nonce` - The nonce of the response, such as one returned by
+///   [`nonce`].
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::csp::finalize;
+/// use staticweaver::{Context, Engine};
+/// use std::time::Duration;
+///
+/// let engine = Engine::new("templates", Duration::from_secs(3600));
+/// let page = engine
+///     .render_template(r#"<script nonce="{{ csp_nonce() }}">go()</script>"#, &Context::new())
+///     .unwrap();
+///
+/// let page = finalize(&page, "bm9uY2U=");
+/// assert_eq!(page.html, r#"<script nonce="bm9uY2U=">go()</script>"#);
+/// assert_eq!(page.script_hashes.len(), 1);
+/// ```
+#[must_use]
+pub fn finalize(page: &str, nonce: &str) -> CspPage {
+    let html = page.replace(NONCE_PLACEHOLDER, nonce);
+    let mut page = CspPage {
+        script_hashes: inline_hashes(&html, "script"),
+        style_hashes: inline_hashes(&html, "style"),
+        html,
+        nonce: nonce.to_string(),
+    };
+    if page.html.contains(POLICY_PLACEHOLDER) {
+        let policy = page.policy();
+        page.html = page
+            .html
+            .replace(POLICY_PLACEHOLDER, &escape_html(&policy));
+    }
+    page
+}
+
+/// Returns a random nonce, in base64.
+///
+/// # Errors
+///
+/// Returns `EngineError::Io` if the system has no source of randomness.
+pub fn nonce() -> Result<String, EngineError> {
+    let mut bytes = [0; NONCE_BYTES];
+    getrandom::fill(&mut bytes).map_err(|err| {
+        EngineError::Io(io::Error::new(
+            io::ErrorKind::Other,
+            err.to_string(),
+        ))
+    })?;
+    Ok(base64(&bytes))
+}
+
+/// Returns the nonce of a page rendered by a deterministic engine,
+/// derived from the page so that builds are reproducible.
+pub(crate) fn stable_nonce(page: &str) -> String {
+    base64(&Sha256::digest(page.as_bytes())[..NONCE_BYTES])
+}
+
+/// Returns the policy sources of the contents of the inline `element`s
+/// of `html`, sorted and without duplicates.
+fn inline_hashes(html: &str, element: &str) -> Vec<String> {
+    // ASCII lowercasing keeps byte offsets, so matches index `html`.
+    let lower = html.to_ascii_lowercase();
+    let open = format!("<{}", element);
+    let close = format!("</{}", element);
+    let mut hashes = Vec::new();
+    let mut from = 0;
+    while let Some(start) = lower[from..].find(&open).map(|i| from + i)
+    {
+        let name_end = start + open.len();
+        from = name_end;
+        // Skip longer element names, such as `<scripts>`.
+        if !lower[name_end..].starts_with(|c: char| {
+            c == '>' || c == '/' || c.is_ascii_whitespace()
+        }) {
+            continue;
+        }
+        let Some(tag_end) =
+            lower[name_end..].find('>').map(|i| name_end + i)
+        else {
+            break;
+        };
+        let Some(end) =
+            lower[tag_end..].find(&close).map(|i| tag_end + i)
+        else {
+            break;
+        };
+        from = end;
+        let has_src = lower[name_end..tag_end]
+            .split(|c: char| c.is_ascii_whitespace())
+            .any(|attribute| {
+                attribute == "src" || attribute.starts_with("src=")
+            });
+        if !has_src {
+            let digest =
+                Sha256::digest(&html.as_bytes()[tag_end + 1..end]);
+            hashes.push(format!("'sha256-{}'", base64(&digest)));
+        }
+    }
+    hashes.sort();
+    hashes.dedup();
+    hashes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_hashes() {
+        let html = "<SCRIPT type=\"module\">a()</SCRIPT>\
+                    <script src=\"app.js\"></script>\
+                    <scripts>b()</scripts>\
+                    <script>a()</script>\
+                    <style>p{}</style>";
+        let scripts = inline_hashes(html, "script");
+        assert_eq!(
+            scripts,
+            [format!("'sha256-{}'", base64(&Sha256::digest(b"a()")))]
+        );
+        assert_eq!(inline_hashes(html, "style").len(), 1);
+        assert!(inline_hashes("<script>unclosed", "script").is_empty());
+    }
+
+    #[test]
+    fn test_finalize() {
+        let page = format!(
+            "<meta content=\"{}\"><script nonce=\"{}\">a()</script>",
+            POLICY_PLACEHOLDER, NONCE_PLACEHOLDER
+        );
+        let finalized = finalize(&page, "abc");
+        assert_eq!(finalized.nonce, "abc");
+        assert!(!finalized.html.contains('\u{fdd0}'));
+        assert!(finalized.html.contains("<script nonce=\"abc\">"));
+        assert!(finalized
+            .html
+            .contains(&escape_html(&finalized.policy()).into_owned()));
+        assert!(finalized
+            .policy()
+            .starts_with("script-src 'nonce-abc' 'sha256-"));
+
+        let first = nonce().unwrap();
+        assert_eq!(first.len(), 24);
+        assert_ne!(first, nonce().unwrap());
+        assert_eq!(stable_nonce(&page), stable_nonce(&page));
+    }
+}
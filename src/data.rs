@@ -0,0 +1,382 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Data Module
+//!
+//! This module provides the `DataDir` struct, which loads a directory of
+//! data files, such as site navigation or author lists, so that every
+//! template can read them under the `data` namespace.
+//!
+//! Each file is named by its path relative to the directory, without the
+//! extension, and its values by their path inside the file. Objects are
+//! read by key and arrays by index, with `len` holding their length:
+//!
+//! ```text
+//! data/
+//! ├── site.json          {"title": "Blog", "nav": [{"url": "/"}]}
+//! └── authors/ada.json   {"name": "Ada"}
+//!
+//! {{data.site.title}}         Blog
+//! {{data.site.nav.len}}       1
+//! {{data.site.nav.0.url}}     /
+//! {{data.authors.ada.name}}   Ada
+//! ```
+//!
+//! Objects and arrays themselves render as JSON text. JSON files are
+//! always read; YAML (`.yaml`, `.yml`) and TOML (`.toml`) files require
+//! the `yaml` and `toml` features. Other files are ignored.
+
+use crate::engine::EngineError;
+use fnv::{FnvHashMap, FnvHasher};
+use std::fs;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The prefix of template tags that read the data directory.
+pub const DATA_PREFIX: &str = "data.";
+
+/// The parsed contents of a data directory.
+///
+/// Files are parsed once, when the directory is loaded. Call
+/// [`DataDir::refresh`], or [`Engine::refresh_data`](crate::Engine::refresh_data)
+/// for the active data directory, to reload it after files change.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::data::DataDir;
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// std::fs::write(dir.path().join("site.json"), r#"{"title": "Blog"}"#)
+///     .unwrap();
+///
+/// let data = DataDir::from_dir(dir.path()).unwrap();
+/// assert_eq!(data.get("site.title"), Some("Blog"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct DataDir {
+    root: PathBuf,
+    values: FnvHashMap<String, String>,
+    modified: Vec<(PathBuf, Option<SystemTime>)>,
+    /// A stable hash of `values`, used in disk cache keys.
+    hash: u64,
+}
+
+impl DataDir {
+    /// Loads every data file under `path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The data directory.
+    ///
+    /// # Errors
+    ///
+    /// * `EngineError::Io` - If `path` is not a directory, or a file
+    ///   cannot be read.
+    /// * `EngineError::InvalidTemplate` - If a file is malformed, or is
+    ///   a YAML or TOML file and the matching feature is disabled.
+    pub fn from_dir<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<Self, EngineError> {
+        let root = path.as_ref().to_path_buf();
+        if !root.is_dir() {
+            return Err(EngineError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Data directory not found: {}", root.display()),
+            )));
+        }
+
+        let mut data = Self {
+            root,
+            values: FnvHashMap::default(),
+            modified: Vec::new(),
+            hash: 0,
+        };
+        for path in data_files(&data.root)? {
+            let Some(value) = parse(&path)? else {
+                continue;
+            };
+            let name = path
+                .strip_prefix(&data.root)
+                .unwrap_or(&path)
+                .with_extension("")
+                .to_string_lossy()
+                .replace('\\', "/")
+                .replace('/', ".");
+            flatten(&name, &value, &mut data.values);
+            let modified = fs::metadata(&path)?.modified().ok();
+            data.modified.push((path, modified));
+        }
+        let mut values: Vec<_> = data.values.iter().collect();
+        values.sort_unstable();
+        let mut hasher = FnvHasher::default();
+        for (key, value) in values {
+            hasher.write(&(key.len() as u64).to_le_bytes());
+            hasher.write(key.as_bytes());
+            hasher.write(&(value.len() as u64).to_le_bytes());
+            hasher.write(value.as_bytes());
+        }
+        data.hash = hasher.finish();
+        Ok(data)
+    }
+
+    /// Returns a hash of the values, stable across processes.
+    pub(crate) fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Returns the data directory.
+    #[must_use]
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the value rendered by `{{data.<key>}}`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The tag key without the `data.` prefix.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    /// Returns the number of values, counting every object, array, and
+    /// scalar in every file.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns whether no data file holds any value.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns whether a data file was added, removed, or modified since
+    /// the directory was loaded.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EngineError::Io` if the directory cannot be read.
+    pub fn is_stale(&self) -> Result<bool, EngineError> {
+        let files = data_files(&self.root)?
+            .into_iter()
+            .filter(|path| is_data_file(path))
+            .collect::<Vec<_>>();
+        if files.len() != self.modified.len() {
+            return Ok(true);
+        }
+        for (path, (loaded, modified)) in
+            files.iter().zip(&self.modified)
+        {
+            let current = fs::metadata(path)?.modified().ok();
+            if path != loaded || current != *modified {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Reloads the directory if a data file changed since it was loaded.
+    ///
+    /// # Returns
+    ///
+    /// Whether the directory was reloaded.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`DataDir::from_dir`]. On error, the
+    /// previously loaded values are kept.
+    pub fn refresh(&mut self) -> Result<bool, EngineError> {
+        if !self.is_stale()? {
+            return Ok(false);
+        }
+        *self = Self::from_dir(&self.root)?;
+        Ok(true)
+    }
+}
+
+/// Returns the files under `dir`, recursively, in sorted order.
+fn data_files(dir: &Path) -> Result<Vec<PathBuf>, EngineError> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Returns the lowercase extension of `path`.
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+/// Returns whether `path` has the extension of a data file.
+fn is_data_file(path: &Path) -> bool {
+    matches!(extension(path).as_str(), "json" | "yaml" | "yml" | "toml")
+}
+
+/// Parses the data file at `path`, or returns `None` if it is not one.
+fn parse(
+    path: &Path,
+) -> Result<Option<serde_json::Value>, EngineError> {
+    if !is_data_file(path) {
+        return Ok(None);
+    }
+    let source = fs::read_to_string(path)?;
+    let invalid = |err: &dyn std::fmt::Display| {
+        EngineError::InvalidTemplate(format!(
+            "Invalid data file {}: {}",
+            path.display(),
+            err
+        ))
+    };
+    let value = match extension(path).as_str() {
+        "json" => {
+            serde_json::from_str(&source).map_err(|err| invalid(&err))
+        }
+        #[cfg(feature = "yaml")]
+        "yaml" | "yml" => {
+            serde_yaml::from_str(&source).map_err(|err| invalid(&err))
+        }
+        #[cfg(feature = "toml")]
+        "toml" => toml::from_str(&source).map_err(|err| invalid(&err)),
+        other => Err(invalid(&format!(
+            "enable the `{}` feature to read .{} files",
+            if other == "toml" { "toml" } else { "yaml" },
+            other
+        ))),
+    }?;
+    Ok(Some(value))
+}
+
+/// Stores `value` under `key`, and each of its members under
+/// `key.<member>`.
+fn flatten(
+    key: &str,
+    value: &serde_json::Value,
+    values: &mut FnvHashMap<String, String>,
+) {
+    let text = match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(value) => value.clone(),
+        serde_json::Value::Object(members) => {
+            for (member, value) in members {
+                flatten(&format!("{}.{}", key, member), value, values);
+            }
+            value.to_string()
+        }
+        serde_json::Value::Array(items) => {
+            let _ = values.insert(
+                format!("{}.len", key),
+                items.len().to_string(),
+            );
+            for (index, value) in items.iter().enumerate() {
+                flatten(&format!("{}.{}", key, index), value, values);
+            }
+            value.to_string()
+        }
+        other => other.to_string(),
+    };
+    let _ = values.insert(key.to_string(), text);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_from_dir() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("authors")).unwrap();
+        fs::write(
+            dir.path().join("site.json"),
+            r#"{"title": "Blog", "nav": [{"url": "/"}], "draft": null}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("authors/ada.json"),
+            r#"{"age": 36}"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("README.md"), "ignored").unwrap();
+
+        let data = DataDir::from_dir(dir.path()).unwrap();
+        assert_eq!(data.root(), dir.path());
+        assert_eq!(data.get("site.title"), Some("Blog"));
+        assert_eq!(data.get("site.nav.len"), Some("1"));
+        assert_eq!(data.get("site.nav.0.url"), Some("/"));
+        assert_eq!(data.get("site.nav.0"), Some(r#"{"url":"/"}"#));
+        assert_eq!(data.get("site.draft"), Some(""));
+        assert_eq!(data.get("authors.ada.age"), Some("36"));
+        assert_eq!(data.get("README"), None);
+        assert!(!data.is_empty());
+
+        assert!(matches!(
+            DataDir::from_dir(dir.path().join("missing")),
+            Err(EngineError::Io(err))
+                if err.kind() == std::io::ErrorKind::NotFound
+        ));
+        fs::write(dir.path().join("broken.json"), "{").unwrap();
+        assert!(matches!(
+            DataDir::from_dir(dir.path()),
+            Err(EngineError::InvalidTemplate(_))
+        ));
+    }
+
+    #[test]
+    fn test_refresh() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("site.json"), r#"{"title": "A"}"#)
+            .unwrap();
+        let mut data = DataDir::from_dir(dir.path()).unwrap();
+        assert!(!data.refresh().unwrap());
+
+        fs::write(dir.path().join("nav.json"), "[]").unwrap();
+        assert!(data.is_stale().unwrap());
+        assert!(data.refresh().unwrap());
+        assert_eq!(data.get("nav.len"), Some("0"));
+
+        fs::write(dir.path().join("nav.json"), "{").unwrap();
+        fs::remove_file(dir.path().join("site.json")).unwrap();
+        assert!(data.refresh().is_err());
+        assert_eq!(data.get("site.title"), Some("A"));
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("site.yml"), "title: Blog\n")
+            .unwrap();
+        let data = DataDir::from_dir(dir.path()).unwrap();
+        assert_eq!(data.get("site.title"), Some("Blog"));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("site.toml"),
+            "[author]\nname = \"Ada\"\n",
+        )
+        .unwrap();
+        let data = DataDir::from_dir(dir.path()).unwrap();
+        assert_eq!(data.get("site.author.name"), Some("Ada"));
+    }
+}
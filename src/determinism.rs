@@ -0,0 +1,255 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Determinism Module
+//!
+//! This module provides the `BuildTime` clock read by the built-in
+//! `now()` template function, and the helpers behind the engine's
+//! deterministic mode, which renders byte-identical output across runs:
+//!
+//! * `now()` reads a fixed time: the engine's `build_time` if it is
+//!   fixed, else the [`SOURCE_DATE_EPOCH`] environment variable, else
+//!   the Unix epoch.
+//! * Templates downloaded by
+//!   [`Engine::create_template_folder`](crate::Engine::create_template_folder)
+//!   are stored in a temporary directory named after their URL, rather
+//!   than a random one. The directory is still removed when the
+//!   returned [`TemplateFolder`](crate::fetch::TemplateFolder) is
+//!   dropped.
+//!
+//! Collections are always exposed to templates in a stable order:
+//! taxonomy terms, data files, and context listings are sorted, and
+//! arrays keep their source order.
+//!
+//! `now()` takes an optional format:
+//!
+//! ```text
+//! {{ now() }}              2024-03-01T12:30:00Z
+//! {{ now('date') }}        2024-03-01
+//! {{ now('year') }}        2024
+//! {{ now('unix') }}        1709296200
+//! ```
+
+use crate::engine::EngineError;
+use crate::function::{Call, Functions};
+use fnv::FnvHasher;
+use std::hash::Hasher;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The name of the current time function.
+pub const NOW_FUNCTION: &str = "now";
+
+/// The environment variable holding the build time of reproducible
+/// builds, in seconds since the Unix epoch.
+pub const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+/// The time read by the `now()` template function.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BuildTime {
+    /// The system time, unless the engine is deterministic.
+    #[default]
+    System,
+    /// A fixed time.
+    Fixed(SystemTime),
+}
+
+impl BuildTime {
+    /// Returns the fixed time in the [`SOURCE_DATE_EPOCH`] environment
+    /// variable, or `None` if it is unset or not a number of seconds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::determinism::{BuildTime, SOURCE_DATE_EPOCH};
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// std::env::set_var(SOURCE_DATE_EPOCH, "86400");
+    /// assert_eq!(
+    ///     BuildTime::from_source_date_epoch(),
+    ///     Some(BuildTime::Fixed(UNIX_EPOCH + Duration::from_secs(86400)))
+    /// );
+    /// ```
+    #[must_use]
+    pub fn from_source_date_epoch() -> Option<Self> {
+        let seconds = std::env::var(SOURCE_DATE_EPOCH).ok()?;
+        let seconds = seconds.trim().parse::<u64>().ok()?;
+        Some(Self::Fixed(UNIX_EPOCH + Duration::from_secs(seconds)))
+    }
+
+    /// Returns the current time, fixed if `deterministic` is set as
+    /// described in the [module documentation](self).
+    ///
+    /// # Arguments
+    ///
+    /// * `deterministic` - Whether the system time may not be read.
+    #[must_use]
+    pub fn now(self, deterministic: bool) -> SystemTime {
+        match self {
+            Self::Fixed(time) => time,
+            Self::System if deterministic => {
+                match Self::from_source_date_epoch() {
+                    Some(Self::Fixed(time)) => time,
+                    _ => UNIX_EPOCH,
+                }
+            }
+            Self::System => SystemTime::now(),
+        }
+    }
+}
+
+/// Returns a temporary directory named after `key`, the same in every
+/// run.
+///
+/// # Arguments
+///
+/// * `key` - What the directory holds, such as a URL.
+#[must_use]
+pub fn stable_temp_dir(key: &str) -> PathBuf {
+    let mut hasher = FnvHasher::default();
+    hasher.write(key.as_bytes());
+    std::env::temp_dir()
+        .join(format!("staticweaver-{:016x}", hasher.finish()))
+}
+
+/// Formats `time` as `rfc3339`, `date`, `year`, or `unix`, in UTC.
+///
+/// Returns `None` for an unknown format.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::determinism::format_time;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let time = UNIX_EPOCH + Duration::from_secs(1_709_296_200);
+/// assert_eq!(format_time(time, "rfc3339").unwrap(), "2024-03-01T12:30:00Z");
+/// assert_eq!(format_time(time, "date").unwrap(), "2024-03-01");
+/// ```
+#[must_use]
+pub fn format_time(time: SystemTime, format: &str) -> Option<String> {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => i64::try_from(since.as_secs()).unwrap_or(i64::MAX),
+        Err(err) => {
+            let before = err.duration();
+            let whole =
+                i64::try_from(before.as_secs()).unwrap_or(i64::MAX);
+            -whole - i64::from(before.subsec_nanos() > 0)
+        }
+    };
+    let days = seconds.div_euclid(86_400);
+    let of_day = seconds.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    Some(match format {
+        "rfc3339" => format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            of_day / 3600,
+            of_day / 60 % 60,
+            of_day % 60
+        ),
+        "date" => format!("{:04}-{:02}-{:02}", year, month, day),
+        "year" => year.to_string(),
+        "unix" => seconds.to_string(),
+        _ => return None,
+    })
+}
+
+/// Returns the year, month, and day of the date `days` after the Unix
+/// epoch, in the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460
+        + day_of_era / 36_524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year = day_of_era
+        - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (
+        year,
+        u32::try_from(month).unwrap_or(1),
+        u32::try_from(day).unwrap_or(1),
+    )
+}
+
+/// Registers the `now` function.
+pub(crate) fn register(functions: &mut Functions) {
+    functions.register(NOW_FUNCTION, now);
+}
+
+/// Emits the current time of the engine.
+fn now(call: &Call<'_>) -> Result<String, EngineError> {
+    let format = call.optional(0)?;
+    let format = format.as_deref().unwrap_or("rfc3339");
+    format_time(call.engine().now(), format).ok_or_else(|| {
+        EngineError::Render(format!("Unknown time format: {}", format))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Context, Engine};
+
+    #[test]
+    fn test_format_time() {
+        let at =
+            |seconds: u64| UNIX_EPOCH + Duration::from_secs(seconds);
+        assert_eq!(
+            format_time(UNIX_EPOCH, "rfc3339").unwrap(),
+            "1970-01-01T00:00:00Z"
+        );
+        assert_eq!(
+            format_time(at(951_782_400), "date").unwrap(),
+            "2000-02-29"
+        );
+        assert_eq!(
+            format_time(at(4_107_542_399), "rfc3339").unwrap(),
+            "2100-02-28T23:59:59Z"
+        );
+        assert_eq!(
+            format_time(UNIX_EPOCH - Duration::from_secs(1), "rfc3339")
+                .unwrap(),
+            "1969-12-31T23:59:59Z"
+        );
+        assert_eq!(format_time(at(0), "unix").unwrap(), "0");
+        assert!(format_time(at(0), "iso").is_none());
+    }
+
+    #[test]
+    fn test_now() {
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        let context = Context::new();
+        engine.build_time = BuildTime::Fixed(
+            UNIX_EPOCH + Duration::from_secs(1_709_296_200),
+        );
+        assert_eq!(
+            engine
+                .render_template(
+                    "{{ now() }} {{ now('year') }}",
+                    &context
+                )
+                .unwrap(),
+            "2024-03-01T12:30:00Z 2024"
+        );
+        assert!(engine
+            .render_template("{{ now('iso') }}", &context)
+            .is_err());
+
+        assert!(BuildTime::System.now(false) > UNIX_EPOCH);
+        assert_eq!(stable_temp_dir("a"), stable_temp_dir("a"));
+        assert_ne!(stable_temp_dir("a"), stable_temp_dir("b"));
+    }
+}
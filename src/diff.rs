@@ -0,0 +1,256 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Diff Module
+//!
+//! This module compares two renders of a page line by line, so that a
+//! preview server can patch the lines that changed in the browser
+//! instead of reloading the whole page. After a layout is edited, the
+//! server calls [`Engine::reload_template`](crate::Engine::reload_template),
+//! renders the page again, and sends the changes returned by
+//! [`diff_lines`]:
+//!
+//! ```
+//! use staticweaver::diff::diff_lines;
+//!
+//! let old = "<ul>\n<li>a</li>\n<li>b</li>\n</ul>\n";
+//! let new = "<ul>\n<li>a</li>\n<li>B</li>\n<li>c</li>\n</ul>\n";
+//!
+//! let changes = diff_lines(old, new);
+//! assert_eq!(changes.len(), 1);
+//! assert_eq!((changes[0].old.clone(), changes[0].new.clone()), (2..3, 2..4));
+//! assert_eq!(changes[0].new_text(new), "<li>B</li>\n<li>c</li>\n");
+//! ```
+//!
+//! Lines keep their line breaks, so a change to the last line break is
+//! a change to the last line. Edits far apart from each other produce
+//! separate changes; past [`MAX_EDITS`] inserted or removed lines, the
+//! lines between the first and last difference are reported as a single
+//! change.
+
+use std::ops::Range;
+
+/// The number of inserted and removed lines past which the changes are
+/// merged into one, bounding the time and memory spent comparing
+/// unrelated outputs.
+pub const MAX_EDITS: usize = 1024;
+
+/// A run of lines that changed between two outputs.
+///
+/// Lines are numbered from `0`. A range is empty where lines were only
+/// inserted or only removed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LineChange {
+    /// The lines replaced in the old output.
+    pub old: Range<usize>,
+    /// The lines replacing them in the new output.
+    pub new: Range<usize>,
+}
+
+impl LineChange {
+    /// Returns the text of the new lines, line breaks included.
+    ///
+    /// # Arguments
+    ///
+    /// * `new` - The new output the change was computed against.
+    #[must_use]
+    pub fn new_text<'a>(&self, new: &'a str) -> &'a str {
+        let lines: Vec<&str> = new.split_inclusive('\n').collect();
+        let start: usize = lines[..self.new.start.min(lines.len())]
+            .iter()
+            .map(|line| line.len())
+            .sum();
+        let len: usize = lines
+            .get(self.new.clone())
+            .unwrap_or_default()
+            .iter()
+            .map(|line| line.len())
+            .sum();
+        &new[start..start + len]
+    }
+}
+
+/// Returns the runs of lines that differ between `old` and `new`, in
+/// order, as described in the [module documentation](self).
+///
+/// # Arguments
+///
+/// * `old` - The previous output.
+/// * `new` - The current output.
+#[must_use]
+pub fn diff_lines(old: &str, new: &str) -> Vec<LineChange> {
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    let prefix = old
+        .iter()
+        .zip(&new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    if a.is_empty() && b.is_empty() {
+        return Vec::new();
+    }
+
+    let whole = LineChange {
+        old: prefix..prefix + a.len(),
+        new: prefix..prefix + b.len(),
+    };
+    let Some(matches) = common_lines(a, b) else {
+        return vec![whole];
+    };
+    let mut changes = Vec::new();
+    let (mut x, mut y) = (0, 0);
+    for (match_x, match_y) in
+        matches.into_iter().chain([(a.len(), b.len())])
+    {
+        if match_x > x || match_y > y {
+            changes.push(LineChange {
+                old: prefix + x..prefix + match_x,
+                new: prefix + y..prefix + match_y,
+            });
+        }
+        x = match_x + 1;
+        y = match_y + 1;
+    }
+    changes
+}
+
+/// Returns the pairs of indices of the lines `a` and `b` have in common,
+/// in order, with Myers' algorithm, or `None` past [`MAX_EDITS`].
+fn common_lines(a: &[&str], b: &[&str]) -> Option<Vec<(usize, usize)>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (a.len() + b.len()).min(MAX_EDITS) as isize;
+    // The furthest x reached on each diagonal k = x - y, offset by
+    // max + 1, and the diagonals -d - 1..=d + 1 before each step d.
+    let offset = max + 1;
+    let mut v = vec![0_isize; 2 * offset as usize + 1];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    let at = |k: isize| (k + offset) as usize;
+
+    let mut end = None;
+    'search: for d in 0..=max {
+        trace.push(v[at(-d - 1)..=at(d + 1)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x =
+                if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+                    v[at(k + 1)]
+                } else {
+                    v[at(k - 1)] + 1
+                };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[at(k)] = x;
+            if x >= n && y >= m {
+                end = Some(d);
+                break 'search;
+            }
+        }
+    }
+    let end = end?;
+
+    let mut matches = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..=end).rev() {
+        let v = &trace[d as usize];
+        let get = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && get(k - 1) < get(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = get(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        matches.push((x as usize, y as usize));
+    }
+    matches.reverse();
+    Some(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(
+        old: &str,
+        new: &str,
+    ) -> Vec<(Range<usize>, Range<usize>)> {
+        diff_lines(old, new)
+            .into_iter()
+            .map(|change| (change.old, change.new))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        assert!(ranges("a\nb\n", "a\nb\n").is_empty());
+        assert_eq!(ranges("", "a\n"), [(0..0, 0..1)]);
+        assert_eq!(ranges("a\nb\nc\n", "a\nc\n"), [(1..2, 1..1)]);
+        assert_eq!(ranges("a\nb\n", "a\nb"), [(1..2, 1..2)]);
+        assert_eq!(
+            ranges("a\nb\nc\nd\ne\n", "x\nb\nc\nd\ny\n"),
+            [(0..1, 0..1), (4..5, 4..5)]
+        );
+        assert_eq!(
+            ranges("a\nb\nc\nd\n", "b\nx\nc\ny\n"),
+            [(0..1, 0..0), (2..2, 1..2), (3..4, 3..4)]
+        );
+    }
+
+    #[test]
+    fn test_diff_lines_apply() {
+        let cases = [
+            ("a\nb\nc\nd\n", "b\nx\nc\ny\n"),
+            ("1\n2\n3\n4\n5\n6\n", "6\n5\n4\n3\n2\n1\n"),
+            ("x\ny", "y\nx\nz\ny"),
+        ];
+        for (old, new) in cases {
+            let old_lines: Vec<&str> =
+                old.split_inclusive('\n').collect();
+            let mut patched = String::new();
+            let mut line = 0;
+            for change in diff_lines(old, new) {
+                patched.push_str(
+                    &old_lines[line..change.old.start].concat(),
+                );
+                patched.push_str(change.new_text(new));
+                line = change.old.end;
+            }
+            patched.push_str(&old_lines[line..].concat());
+            assert_eq!(patched, new);
+        }
+    }
+
+    #[test]
+    fn test_diff_lines_limit() {
+        let old: String =
+            (0..3000).map(|i| format!("{}\n", i)).collect();
+        let new: String =
+            (0..3000).map(|i| format!("{}\n", i * 7 % 3001)).collect();
+        let changes = diff_lines(&old, &new);
+        assert_eq!(changes.len(), 1);
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Disk Cache Module
+//!
+//! This module provides the `DiskCache` struct, a second cache level
+//! that stores rendered pages in a directory, behind the in-memory
+//! render cache of an engine. Pages missed in memory are read from disk
+//! before they are rendered, and promoted to memory on a hit, so very
+//! large sites can keep a small in-memory cache and still skip most
+//! renders when they are rebuilt:
+//!
+//! ```
+//! use staticweaver::disk_cache::DiskCache;
+//! use staticweaver::loader::MemoryLoader;
+//! use staticweaver::{Context, Engine};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let loader = MemoryLoader::new();
+//! loader.insert("templates/page.html", "<h1>{{title}}</h1>");
+//!
+//! let mut engine = Engine::new("templates", Duration::from_secs(60));
+//! engine.set_loader(Arc::new(loader));
+//! engine.set_disk_cache(Some(DiskCache::new(dir.path())));
+//!
+//! let mut context = Context::new();
+//! context.set("title", "Home");
+//! engine.render_page(&context, "page").unwrap();
+//!
+//! // A later build, with an empty in-memory cache, reads the page back.
+//! engine.clear_cache();
+//! assert_eq!(engine.render_page(&context, "page").unwrap(), "<h1>Home</h1>");
+//! ```
+//!
+//! Pages are stored under a SHA-256 key of everything the engine knows
+//! a page depends on: the source of its layout, its context, the
+//! settings and environment of the engine, the active data directory,
+//! and the version of this crate. Editing a layout therefore misses the
+//! pages stored for its previous source, and the directory never needs
+//! to be invalidated by hand. Registered functions are not part of the
+//! key: [clear](DiskCache::clear) the directory when their output
+//! changes.
+//!
+//! The disk cache is best effort. Pages that cannot be read are
+//! rendered, and pages that cannot be written are only kept in memory.
+
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The extension of the files of cached pages.
+pub const PAGE_EXTENSION: &str = "page";
+
+/// A directory of rendered pages, used as the second level of the
+/// render cache by [`Engine::set_disk_cache`](crate::Engine::set_disk_cache).
+///
+/// Pages are spread over subdirectories named by the first two
+/// characters of their key, so that no directory holds too many files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    /// Creates a disk cache storing pages in `dir`, which is created
+    /// when the first page is stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The cache directory, such as `.cache/pages`.
+    #[must_use]
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Returns the cache directory.
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the number of pages stored.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.pages().count()
+    }
+
+    /// Returns whether no page is stored.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.pages().next().is_none()
+    }
+
+    /// Removes every stored page, leaving other files in the directory
+    /// alone.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a page cannot be removed.
+    pub fn clear(&self) -> io::Result<()> {
+        for page in self.pages() {
+            fs::remove_file(page)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the stored page of `key`, if any.
+    pub(crate) fn get(&self, key: &str) -> Option<Arc<str>> {
+        fs::read_to_string(self.path(key)).ok().map(Arc::from)
+    }
+
+    /// Stores `page` under `key`.
+    ///
+    /// The page is written to a temporary file first and then renamed,
+    /// so that concurrent builds never read a partial page.
+    pub(crate) fn insert(
+        &self,
+        key: &str,
+        page: &str,
+    ) -> io::Result<()> {
+        let path = self.path(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let partial =
+            path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&partial, page)?;
+        fs::rename(&partial, &path).map_err(|err| {
+            let _ = fs::remove_file(&partial);
+            err
+        })
+    }
+
+    /// Returns the file of the page of `key`.
+    fn path(&self, key: &str) -> PathBuf {
+        let shard = key.get(..2).unwrap_or("00");
+        self.dir
+            .join(shard)
+            .join(key)
+            .with_extension(PAGE_EXTENSION)
+    }
+
+    /// Returns the files of the stored pages.
+    fn pages(&self) -> impl Iterator<Item = PathBuf> {
+        fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|shard| fs::read_dir(shard.path()).ok())
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .map_or(false, |ext| ext == PAGE_EXTENSION)
+            })
+    }
+}
+
+/// Builds the key of a page stored in a [`DiskCache`] from its parts,
+/// each length-prefixed so that adjacent parts cannot be confused.
+#[derive(Debug, Clone)]
+pub(crate) struct DiskKey(Sha256);
+
+impl DiskKey {
+    /// Starts a key for pages rendered by this version of the crate.
+    pub(crate) fn new() -> Self {
+        let mut key = Self(Sha256::new());
+        let _ = key.bytes(env!("CARGO_PKG_VERSION").as_bytes());
+        key
+    }
+
+    /// Adds `bytes` to the key.
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.0.update((bytes.len() as u64).to_le_bytes());
+        self.0.update(bytes);
+        self
+    }
+
+    /// Adds `value` to the key.
+    pub(crate) fn u64(&mut self, value: u64) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    /// Returns the key, in lowercase hexadecimal.
+    pub(crate) fn finish(self) -> String {
+        self.0
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn key(part: &[u8]) -> String {
+        let mut key = DiskKey::new();
+        let _ = key.bytes(part).u64(7);
+        key.finish()
+    }
+
+    #[test]
+    fn test_disk_cache() {
+        let dir = TempDir::new().unwrap();
+        let cache = DiskCache::new(dir.path().join("pages"));
+        assert!(cache.is_empty());
+        assert!(cache.get(&key(b"a")).is_none());
+
+        cache.insert(&key(b"a"), "<p>a</p>").unwrap();
+        cache.insert(&key(b"b"), "<p>b</p>").unwrap();
+        cache.insert(&key(b"a"), "<p>A</p>").unwrap();
+        assert_eq!(cache.get(&key(b"a")).as_deref(), Some("<p>A</p>"));
+        assert_eq!(cache.len(), 2);
+
+        fs::write(cache.dir().join("README"), "kept").unwrap();
+        cache.clear().unwrap();
+        assert!(cache.is_empty());
+        assert!(cache.dir().join("README").is_file());
+    }
+
+    #[test]
+    fn test_disk_key() {
+        assert_eq!(key(b"a"), key(b"a"));
+        assert_ne!(key(b"a"), key(b"b"));
+        assert_eq!(key(b"a").len(), 64);
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Encoding Module
+//!
+//! This module decodes the bytes of layout files into text. A file is
+//! read as:
+//!
+//! 1. UTF-8, UTF-16LE, or UTF-16BE if it starts with the byte order
+//!    mark of that encoding, which is removed.
+//! 2. UTF-8 otherwise, if it is valid UTF-8.
+//! 3. The engine's
+//!    [`fallback_encoding`](crate::Engine::fallback_encoding), such as
+//!    `windows-1252` or `shift_jis`, if one is set. Transcoding requires
+//!    the `encoding` feature.
+//!
+//! A file that cannot be decoded fails with [`EngineError::Encoding`],
+//! naming the file, rather than being rendered with replacement
+//! characters.
+
+use crate::engine::EngineError;
+use std::path::Path;
+
+/// The byte order mark of UTF-8.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// The byte order mark of UTF-16LE.
+const UTF16LE_BOM: &[u8] = b"\xFF\xFE";
+
+/// The byte order mark of UTF-16BE.
+const UTF16BE_BOM: &[u8] = b"\xFE\xFF";
+
+/// Decodes the contents of the file at `path` as described in the
+/// [module documentation](self).
+///
+/// # Arguments
+///
+/// * `bytes` - The contents of the file.
+/// * `path` - The path of the file, reported in errors.
+/// * `fallback` - The label of the encoding of files that are not
+///   UTF-8, if any.
+///
+/// # Errors
+///
+/// Returns [`EngineError::Encoding`] if the file is not valid in its
+/// encoding, or `fallback` is unknown or cannot be transcoded.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::encoding::decode;
+/// use std::path::Path;
+///
+/// let path = Path::new("page.html");
+/// assert_eq!(decode(b"\xEF\xBB\xBFHi".to_vec(), path, None).unwrap(), "Hi");
+/// assert_eq!(decode(b"\xFF\xFEH\0i\0".to_vec(), path, None).unwrap(), "Hi");
+/// assert!(decode(b"caf\xE9".to_vec(), path, None).is_err());
+/// ```
+pub fn decode(
+    bytes: Vec<u8>,
+    path: &Path,
+    fallback: Option<&str>,
+) -> Result<String, EngineError> {
+    if let Some(rest) = bytes.strip_prefix(UTF8_BOM) {
+        return std::str::from_utf8(rest)
+            .map(str::to_string)
+            .map_err(|err| error(path, invalid_utf8(&err)));
+    }
+    if let Some(rest) = bytes.strip_prefix(UTF16LE_BOM) {
+        return decode_utf16(rest, path, u16::from_le_bytes);
+    }
+    if let Some(rest) = bytes.strip_prefix(UTF16BE_BOM) {
+        return decode_utf16(rest, path, u16::from_be_bytes);
+    }
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        Err(err) => match fallback {
+            Some(label) => transcode(err.as_bytes(), path, label),
+            None => Err(error(path, invalid_utf8(&err.utf8_error()))),
+        },
+    }
+}
+
+/// Decodes UTF-16 code units read with `unit`.
+fn decode_utf16(
+    bytes: &[u8],
+    path: &Path,
+    unit: fn([u8; 2]) -> u16,
+) -> Result<String, EngineError> {
+    if bytes.len() % 2 != 0 {
+        return Err(error(
+            path,
+            "odd number of bytes in UTF-16".into(),
+        ));
+    }
+    let units =
+        bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    std::char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .map_err(|err| {
+            error(
+                path,
+                format!(
+                    "unpaired UTF-16 surrogate {:#06x}",
+                    err.unpaired_surrogate()
+                ),
+            )
+        })
+}
+
+/// Transcodes `bytes` from the encoding labelled `label`.
+#[cfg(feature = "encoding")]
+fn transcode(
+    bytes: &[u8],
+    path: &Path,
+    label: &str,
+) -> Result<String, EngineError> {
+    let encoding = encoding_rs::Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| {
+        error(path, format!("unknown encoding '{}'", label))
+    })?;
+    encoding
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .map(std::borrow::Cow::into_owned)
+        .ok_or_else(|| {
+            error(path, format!("invalid {}", encoding.name()))
+        })
+}
+
+/// Fails to transcode when the `encoding` feature is disabled.
+#[cfg(not(feature = "encoding"))]
+fn transcode(
+    _bytes: &[u8],
+    path: &Path,
+    label: &str,
+) -> Result<String, EngineError> {
+    Err(error(
+        path,
+        format!(
+            "not UTF-8, and cannot transcode from '{}': the `encoding` feature is disabled",
+            label
+        ),
+    ))
+}
+
+/// Describes invalid UTF-8.
+fn invalid_utf8(err: &std::str::Utf8Error) -> String {
+    format!("invalid UTF-8 at byte {}", err.valid_up_to())
+}
+
+/// Builds the error of the file at `path`.
+fn error(path: &Path, message: String) -> EngineError {
+    EngineError::Encoding {
+        path: path.to_path_buf(),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let path = Path::new("page.html");
+        assert_eq!(
+            decode(b"plain".to_vec(), path, None).unwrap(),
+            "plain"
+        );
+        assert_eq!(
+            decode(b"\xFE\xFF\0H\0i".to_vec(), path, None).unwrap(),
+            "Hi"
+        );
+        assert!(decode(b"\xFF\xFEH".to_vec(), path, None).is_err());
+        assert!(
+            decode(b"\xFF\xFE\x00\xD8".to_vec(), path, None).is_err()
+        );
+
+        let err = decode(b"caf\xE9".to_vec(), path, None).unwrap_err();
+        assert_eq!(err.code(), "encoding");
+        assert_eq!(
+            err.to_string(),
+            "Cannot decode page.html: invalid UTF-8 at byte 3"
+        );
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn test_transcode() {
+        let path = Path::new("page.html");
+        assert_eq!(
+            decode(b"caf\xE9".to_vec(), path, Some("latin1")).unwrap(),
+            "café"
+        );
+        assert!(
+            decode(b"caf\xE9".to_vec(), path, Some("klingon")).is_err()
+        );
+        assert!(
+            decode(b"\x82".to_vec(), path, Some("shift_jis")).is_err()
+        );
+    }
+}
@@ -7,15 +7,58 @@
 //! It includes the `Engine` struct for rendering templates and the `PageOptions` struct
 //! for configuring page rendering options.
 
-use crate::cache::Cache;
+use crate::asset::{self, AssetHashes};
+use crate::block::Blocks;
+use crate::cache::{Cache, Clock};
+use crate::cancel::{self, CancelToken, Deadline};
+use crate::codegen;
 use crate::context::Context;
-use fnv::FnvHashMap;
-use reqwest;
-use std::fs::{self, File};
-use std::io::Write;
+use crate::csp::{self, CspPage};
+use crate::data::{DataDir, DATA_PREFIX};
+use crate::determinism::{self, stable_temp_dir, BuildTime};
+use crate::diff::{self, LineChange};
+use crate::disk_cache::{DiskCache, DiskKey};
+use crate::encoding::decode;
+use crate::environment::{self, EnvVars, Environment, ENV_PREFIX};
+use crate::error::{Diagnostic, Span};
+use crate::escape::OutputFormat;
+use crate::fetch::{
+    self, DownloadFailure, DownloadLimits, Fetcher, TemplateFolder,
+};
+use crate::filter::Filter;
+use crate::function::{split_call, Functions};
+use crate::hooks::{AfterRender, BeforeRender, RenderHooks};
+use crate::integrity::{sha256_hex, Integrity};
+use crate::intern::{Interner, Symbol};
+use crate::layer::{Next, RenderLayer, RenderRequest};
+use crate::limits::{self, Capped, SizeLimit};
+use crate::loader::{validate_layout_name, FsLoader, Loader};
+use crate::manifest::BuildManifest;
+use crate::meta::{self, MetaConfig};
+#[cfg(feature = "archive")]
+use crate::package;
+use crate::parser::{
+    validate_delimiters, Parser, Segment, Token, DEFAULT_CLOSE_DELIM,
+    DEFAULT_OPEN_DELIM,
+};
+use crate::profile::{ProfileKind, Profiler, RenderProfile};
+use crate::shared::EngineConfig;
+use crate::shortcode::Shortcodes;
+use crate::syntax::{self, SyntaxVersion};
+use crate::theme::Theme;
+use crate::warm::{WarmReport, WARM_THREADS};
+use fnv::{FnvHashMap, FnvHasher};
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::hash::Hasher;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
-use tempfile::tempdir;
+use std::sync::{
+    Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak,
+};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
 /// Error types specific to the engine operations.
@@ -26,6 +69,7 @@ pub enum EngineError {
     Io(#[from] std::io::Error),
 
     /// Network request related errors.
+    #[cfg(feature = "remote")]
     #[error("Request error: {0}")]
     Reqwest(#[from] reqwest::Error),
 
@@ -36,13 +80,534 @@ pub enum EngineError {
     /// Invalid template syntax errors.
     #[error("Invalid template: {0}")]
     InvalidTemplate(String),
+
+    /// A template file that cannot be decoded as text, as described in
+    /// the [`encoding`](crate::encoding) module.
+    #[error("Cannot decode {}: {message}", path.display())]
+    Encoding {
+        /// The path of the file.
+        path: PathBuf,
+        /// A description of the decoding failure.
+        message: String,
+    },
+
+    /// Tag delimiters that cannot be told apart from each other or from
+    /// the syntax inside tags, as checked by
+    /// [`validate_delimiters`](crate::parser::validate_delimiters).
+    #[error("Invalid delimiters '{open}' and '{close}': {message}")]
+    InvalidDelimiters {
+        /// The opening delimiter.
+        open: String,
+        /// The closing delimiter.
+        close: String,
+        /// Why the delimiters were rejected.
+        message: String,
+    },
+
+    /// A downloaded template that failed verification, as described in
+    /// the [`integrity`](crate::integrity) module.
+    #[error("Integrity check failed for {file}: {message}")]
+    IntegrityCheckFailed {
+        /// The file that failed verification.
+        file: String,
+        /// Why the file was rejected.
+        message: String,
+    },
+
+    /// An error raised while rendering a page, with the layout, file,
+    /// and phase it occurred in.
+    #[error(transparent)]
+    Page(Box<RenderErrorContext>),
+
+    /// A layout name that could resolve outside the template
+    /// directories, such as `../secret`, as described in the
+    /// [`loader`](crate::loader) module.
+    #[error("Invalid template name '{name}': {message}")]
+    InvalidTemplateName {
+        /// The rejected layout name.
+        name: String,
+        /// Why the name was rejected.
+        message: String,
+    },
+
+    /// A page whose render was refused by a before hook, as described
+    /// in the [`hooks`](crate::hooks) module.
+    #[error("Render of '{layout}' vetoed: {reason}")]
+    Vetoed {
+        /// The layout of the page.
+        layout: String,
+        /// Why the hook refused the render.
+        reason: String,
+    },
+
+    /// Several files that could not be downloaded, as described in the
+    /// [`fetch`](crate::fetch) module.
+    #[error("Failed to download {} files: {}", .0.len(), join_failures(.0))]
+    Downloads(Vec<DownloadFailure>),
+
+    /// A render stopped by its timeout or cancellation token, as
+    /// described in the [`cancel`](crate::cancel) module.
+    #[error(
+        "Render {} after {elapsed:?} at line {line} ({offset} of {len} bytes)",
+        if *.cancelled { "cancelled" } else { "timed out" }
+    )]
+    Timeout {
+        /// Whether the render was cancelled, rather than out of time.
+        cancelled: bool,
+        /// How long the render ran.
+        elapsed: Duration,
+        /// The line, starting at 1, of the template the render reached.
+        line: usize,
+        /// The byte offset in the template the render reached.
+        offset: usize,
+        /// The length in bytes of the template.
+        len: usize,
+    },
+
+    /// A render that outgrew a memory cap of its options, as described
+    /// in the [`limits`](crate::limits) module.
+    #[error("{limit} exceeds the limit of {max} bytes")]
+    TooLarge {
+        /// The cap that was exceeded.
+        limit: SizeLimit,
+        /// The cap, in bytes.
+        max: usize,
+    },
+}
+
+/// Returns the messages of `failures`, separated by semicolons.
+fn join_failures(failures: &[DownloadFailure]) -> String {
+    failures
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl EngineError {
+    /// Returns the stable, machine-readable code of the error.
+    ///
+    /// Codes match those of [`crate::error::EngineError::code`]; page
+    /// errors report the code of their underlying error.
+    ///
+    /// | Variant | Code |
+    /// |---|---|
+    /// | `Io` | `io` |
+    /// | `Reqwest` | `request` |
+    /// | `Render` | `render` |
+    /// | `InvalidTemplate` | `invalid_template` |
+    /// | `Encoding` | `encoding` |
+    /// | `InvalidDelimiters` | `invalid_delimiters` |
+    /// | `IntegrityCheckFailed` | `integrity_check_failed` |
+    /// | `InvalidTemplateName` | `invalid_template_name` |
+    /// | `Vetoed` | `vetoed` |
+    /// | `Downloads` | `downloads` |
+    /// | `Timeout` | `timeout` |
+    /// | `TooLarge` | `too_large` |
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Io(_) => "io",
+            #[cfg(feature = "remote")]
+            Self::Reqwest(_) => "request",
+            Self::Render(_) => "render",
+            Self::InvalidTemplate(_) => "invalid_template",
+            Self::Encoding { .. } => "encoding",
+            Self::InvalidDelimiters { .. } => "invalid_delimiters",
+            Self::IntegrityCheckFailed { .. } => {
+                "integrity_check_failed"
+            }
+            Self::Page(context) => context.source.code(),
+            Self::InvalidTemplateName { .. } => "invalid_template_name",
+            Self::Vetoed { .. } => "vetoed",
+            Self::Downloads(_) => "downloads",
+            Self::Timeout { .. } => "timeout",
+            Self::TooLarge { .. } => "too_large",
+        }
+    }
+
+    /// Returns the page context of the error, if it was raised while
+    /// rendering a page.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::{Engine, RenderPhase};
+    /// use staticweaver::Context;
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(60));
+    /// let err = engine.render_page(&Context::new(), "missing").unwrap_err();
+    /// let context = err.render_context().unwrap();
+    /// assert_eq!(context.layout, "missing");
+    /// assert_eq!(context.phase, RenderPhase::Load);
+    /// ```
+    #[must_use]
+    pub fn render_context(&self) -> Option<&RenderErrorContext> {
+        match self {
+            Self::Page(context) => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Returns the underlying error, looking through any page context.
+    #[must_use]
+    pub fn root(&self) -> &Self {
+        match self {
+            Self::Page(context) => context.source.root(),
+            err => err,
+        }
+    }
+}
+
+impl From<&EngineError> for Diagnostic {
+    fn from(err: &EngineError) -> Self {
+        Self {
+            code: err.code(),
+            message: err.to_string(),
+            file: err
+                .render_context()
+                .and_then(|context| context.path.clone()),
+            span: None,
+        }
+    }
+}
+
+/// A problem found in a template by [`Engine::render_template_report`].
+#[derive(Debug)]
+pub struct RenderProblem {
+    /// The byte offset in the template of the tag that failed.
+    pub offset: usize,
+    /// The length in bytes of the tag that failed, delimiters included.
+    pub len: usize,
+    /// The line, starting at 1, of the tag that failed.
+    pub line: usize,
+    /// The error the tag produced.
+    pub error: EngineError,
+}
+
+/// Every problem found in a template, in template order.
+#[derive(Debug, Default)]
+pub struct RenderReport {
+    /// The problems found.
+    pub problems: Vec<RenderProblem>,
+}
+
+impl RenderReport {
+    /// Records `error` for the tag spanning `span` in `template`.
+    fn push(
+        &mut self,
+        template: &str,
+        span: Range<usize>,
+        error: EngineError,
+    ) {
+        let line = template[..span.start].matches('\n').count() + 1;
+        self.problems.push(RenderProblem {
+            offset: span.start,
+            len: span.len(),
+            line,
+            error,
+        });
+    }
+
+    /// Returns a [`Diagnostic`] for each problem, in template order.
+    #[must_use]
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.problems.iter().map(Diagnostic::from).collect()
+    }
+
+    /// Returns whether the render stopped early, having run out of
+    /// time, been cancelled, or exceeded a memory cap.
+    fn stopped(&self) -> bool {
+        self.problems.last().map_or(false, |problem| {
+            matches!(
+                problem.error,
+                EngineError::Timeout { .. }
+                    | EngineError::TooLarge { .. }
+            )
+        })
+    }
+
+    /// Returns the first problem's error.
+    fn into_first_error(self) -> EngineError {
+        self.problems.into_iter().next().map_or_else(
+            || EngineError::Render("Unknown render error".to_string()),
+            |problem| problem.error,
+        )
+    }
+}
+
+impl fmt::Display for RenderReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} problem(s) found", self.problems.len())?;
+        for problem in &self.problems {
+            write!(f, "\n  line {}: {}", problem.line, problem.error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RenderReport {}
+
+impl From<&RenderProblem> for Diagnostic {
+    fn from(problem: &RenderProblem) -> Self {
+        Self {
+            span: Some(Span {
+                offset: problem.offset,
+                len: problem.len,
+                line: problem.line,
+            }),
+            ..Self::from(&problem.error)
+        }
+    }
+}
+
+/// The context key that names a page's layout, as read by
+/// [`Engine::layout_for`].
+pub const LAYOUT_KEY: &str = "layout";
+
+/// The signature of a layout resolver callback.
+type LayoutFn = dyn Fn(&Context) -> Option<String> + Send + Sync;
+
+/// A callback choosing the layout of a page from its context.
+#[derive(Clone)]
+struct LayoutResolver(Arc<LayoutFn>);
+
+impl fmt::Debug for LayoutResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LayoutResolver")
+    }
+}
+
+/// The render cache key of a page.
+///
+/// Keys are built from the interned layout name, the output format, and
+/// the hashes of the settings, the context, and the environment, so
+/// looking a page up neither allocates nor hashes the layout name. A key
+/// is only meaningful to the engine that created it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PageKey {
+    layout: Symbol,
+    format: Option<OutputFormat>,
+    auto_escape: bool,
+    settings_hash: u64,
+    context_hash: u64,
+    environment_hash: u64,
+}
+
+impl PageKey {
+    /// Creates the key of the page `engine` renders with `layout` and
+    /// `options`, from a context hashed as by [`Engine::context_hash`].
+    pub(crate) fn new(
+        engine: &Engine,
+        layout: &str,
+        options: &RenderOptions,
+        context_hash: u64,
+    ) -> Self {
+        let settings = Settings::of(engine).with(options);
+        let format = settings.output_format;
+        Self {
+            layout: engine.layouts.intern(layout),
+            format,
+            auto_escape: format.is_none()
+                && (settings.auto_escape
+                    || engine.syntax.escapes_html()),
+            settings_hash: settings.hash(),
+            context_hash,
+            environment_hash: engine.environment_hash(),
+        }
+    }
+}
+
+/// What [`Engine::render_page`] would do for a page, as reported by
+/// [`Engine::plan_page`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderPlan {
+    /// The layout of the page.
+    pub layout: String,
+    /// The layout file that would be read, or `None` if it was not found.
+    pub template_path: Option<PathBuf>,
+    /// The output format values would be escaped for, or `None` if the
+    /// layout file was not found.
+    pub format: Option<OutputFormat>,
+    /// The render cache key of the page.
+    pub cache_key: PageKey,
+    /// Whether the page would be served from the render cache.
+    pub cached: bool,
+}
+
+/// Where a page returned by [`Engine::render_page_detailed`] came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheStatus {
+    /// Served from the in-memory render cache.
+    Hit,
+    /// Served from the disk cache, and added to the render cache.
+    DiskHit,
+    /// Rendered, then cached.
+    Miss,
+    /// Rendered without consulting the caches, as requested by
+    /// [`RenderOptions::bypass_cache`].
+    Bypass,
+}
+
+/// A layout found by [`Engine::list_templates`], and what it reads.
+///
+/// Layouts are flat lists of tags, so what a layout reads is described
+/// by the context keys and functions its tags name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateMeta {
+    /// The layout name, as passed to [`Engine::render_page`].
+    pub name: String,
+    /// The file the layout resolves to.
+    pub path: PathBuf,
+    /// The size of the file, in bytes.
+    pub size: u64,
+    /// When the file was last modified, if the loader knows.
+    pub modified: Option<SystemTime>,
+    /// The syntax version of the layout, declared by its pragma or
+    /// defaulted by the engine.
+    pub syntax: SyntaxVersion,
+    /// The context keys read by the tags of the layout, sorted and
+    /// without duplicates.
+    pub keys: Vec<String>,
+    /// The functions called by the tags of the layout, sorted and
+    /// without duplicates.
+    pub functions: Vec<String>,
+}
+
+/// A page rendered by [`Engine::render_page_detailed`], and how it was
+/// produced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rendered {
+    /// The rendered page.
+    pub output: Arc<str>,
+    /// Whether the page was served from a cache.
+    pub cache: CacheStatus,
+    /// The time taken to serve the page.
+    pub duration: Duration,
+    /// The files the page was rendered from: its layout file.
+    pub dependencies: Vec<PathBuf>,
+    /// The SHA-256 checksum of the page, in lowercase hexadecimal.
+    ///
+    /// The checksum of a cached page is computed once, and reused for
+    /// every cache hit.
+    pub content_hash: String,
+}
+
+impl Rendered {
+    /// Returns the strong `ETag` of the page, its content hash quoted as
+    /// HTTP requires.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::RenderOptions;
+    /// use staticweaver::{Context, Engine};
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// if let Ok(page) =
+    ///     engine.render_page_detailed(&RenderOptions::new(), &Context::new(), "index")
+    /// {
+    ///     println!("ETag: {}", page.etag());
+    /// }
+    /// ```
+    #[must_use]
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", self.content_hash)
+    }
+
+    /// Returns the page with `output` in place of its output, and the
+    /// content hash of `output`, e.g. for a [layer](crate::layer) that
+    /// post-processes pages.
+    #[must_use]
+    pub fn with_output(self, output: impl Into<Arc<str>>) -> Self {
+        let output = output.into();
+        Self {
+            content_hash: sha256_hex(output.as_bytes()),
+            output,
+            ..self
+        }
+    }
+}
+
+/// The phase of page rendering in which an error occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RenderPhase {
+    /// Resolving or reading the layout file.
+    Load,
+    /// Checking the template syntax.
+    Parse,
+    /// Substituting context values and applying filters.
+    Render,
+}
+
+impl fmt::Display for RenderPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Load => "load",
+            Self::Parse => "parse",
+            Self::Render => "render",
+        })
+    }
+}
+
+/// Where a page render failed, wrapped around the underlying error.
+#[derive(Debug)]
+pub struct RenderErrorContext {
+    /// The layout being rendered, as passed to [`Engine::render_page`].
+    pub layout: String,
+    /// The absolute path of the layout file, if it was resolved.
+    pub path: Option<PathBuf>,
+    /// The phase in which the error occurred.
+    pub phase: RenderPhase,
+    /// The underlying error.
+    pub source: EngineError,
+}
+
+impl RenderErrorContext {
+    /// Wraps `source` in the context of rendering `layout`.
+    fn wrap(
+        layout: &str,
+        path: Option<&Path>,
+        phase: RenderPhase,
+        source: EngineError,
+    ) -> EngineError {
+        let path = path.map(|path| {
+            fs::canonicalize(path)
+                .unwrap_or_else(|_| path.to_path_buf())
+        });
+        EngineError::Page(Box::new(Self {
+            layout: layout.to_string(),
+            path,
+            phase,
+            source,
+        }))
+    }
+}
+
+impl fmt::Display for RenderErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to {} layout '{}'", self.phase, self.layout)?;
+        if let Some(path) = &self.path {
+            write!(f, " ({})", path.display())?;
+        }
+        write!(f, ": {}", self.source)
+    }
+}
+
+impl std::error::Error for RenderErrorContext {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
 }
 
 /// Options for rendering a page template.
 ///
 /// This struct contains the options for rendering a page template.
-/// These options are used to construct a context `FnvHashMap` that is
-/// passed to the `render_template` function.
+/// These options are converted into a [`Context`] (see
+/// `From<PageOptions> for Context`) or merged into an existing one with
+/// [`Engine::render_page_merged`].
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
 pub struct PageOptions {
     /// Elements of the page
@@ -78,11 +643,15 @@ pub fn new() -> Self {
     /// use staticweaver::engine::PageOptions;
     ///
     /// let mut options = PageOptions::new();
-    /// options.set("title".to_string(), "My Page".to_string());
+    /// options.set("title", "My Page");
     /// assert_eq!(options.get("title"), Some(&"My Page".to_string()));
     /// ```
-    pub fn set(&mut self, key: String, value: String) {
-        let _ = self.elements.insert(key, value);
+    pub fn set<K, V>(&mut self, key: K, value: V)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let _ = self.elements.insert(key.into(), value.into());
     }
 
     /// Retrieves a page option from the `elements` map.
@@ -102,13 +671,292 @@ pub fn set(&mut self, key: String, value: String) {
     /// use staticweaver::engine::PageOptions;
     ///
     /// let mut options = PageOptions::new();
-    /// options.set("title".to_string(), "My Page".to_string());
+    /// options.set("title", "My Page");
     /// assert_eq!(options.get("title"), Some(&"My Page".to_string()));
     /// assert_eq!(options.get("nonexistent"), None);
     /// ```
     #[must_use]
-    pub fn get(&self, key: &str) -> Option<&String> {
-        self.elements.get(key)
+    pub fn get<K: AsRef<str>>(&self, key: K) -> Option<&String> {
+        self.elements.get(key.as_ref())
+    }
+}
+
+/// What happens when a tag names a key that has no value.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MissingKeys {
+    /// The render fails with an "Unresolved template tag" error.
+    #[default]
+    Error,
+    /// The tag is replaced by nothing.
+    Empty,
+    /// The tag is written out unchanged, delimiters included.
+    Keep,
+}
+
+/// Settings that override the engine's own for a single call to
+/// [`Engine::render_page_with`], leaving the engine unchanged.
+///
+/// Fields left as `None` use the engine's setting.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// The opening and closing delimiters of tags.
+    pub delimiters: Option<(String, String)>,
+    /// Whether values rendered into HTML templates are HTML-escaped.
+    pub auto_escape: Option<bool>,
+    /// The output format values are escaped for.
+    pub output_format: Option<OutputFormat>,
+    /// What happens when a tag names a key that has no value.
+    pub missing_keys: Option<MissingKeys>,
+    /// Whether the page is rendered even if it is cached, and left out
+    /// of the render cache.
+    pub bypass_cache: bool,
+    /// How long the page may take to render before it fails with
+    /// [`EngineError::Timeout`].
+    pub timeout: Option<Duration>,
+    /// A token that stops the render with [`EngineError::Timeout`] once
+    /// cancelled, as described in the [`cancel`](crate::cancel)
+    /// module.
+    pub cancel: Option<CancelToken>,
+    /// The length in bytes past which the rendered page fails with
+    /// [`EngineError::TooLarge`], as described in the
+    /// [`limits`](crate::limits) module.
+    pub max_output: Option<usize>,
+    /// The length in bytes past which any value built during the
+    /// render fails with [`EngineError::TooLarge`].
+    pub max_allocation: Option<usize>,
+}
+
+impl RenderOptions {
+    /// Creates options that override nothing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::RenderOptions;
+    ///
+    /// let options = RenderOptions::new();
+    /// assert!(options.delimiters.is_none());
+    /// assert!(!options.bypass_cache);
+    /// ```
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the delimiters of tags.
+    ///
+    /// # Arguments
+    ///
+    /// * `open` - The opening delimiter (e.g., `<<`).
+    /// * `close` - The closing delimiter (e.g., `>>`).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EngineError::InvalidDelimiters`] if
+    /// [`validate_delimiters`] rejects the delimiters, leaving the
+    /// options unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::RenderOptions;
+    ///
+    /// let mut options = RenderOptions::new();
+    /// options.set_delimiters("<<", ">>").unwrap();
+    /// assert_eq!(
+    ///     options.delimiters,
+    ///     Some(("<<".to_string(), ">>".to_string()))
+    /// );
+    /// assert!(options.set_delimiters("<<", "<<").is_err());
+    /// ```
+    pub fn set_delimiters(
+        &mut self,
+        open: &str,
+        close: &str,
+    ) -> Result<(), EngineError> {
+        validate_delimiters(open, close)?;
+        self.delimiters = Some((open.to_string(), close.to_string()));
+        Ok(())
+    }
+}
+
+/// Values derived from cached pages, such as their content hashes and
+/// the layout files they were rendered from, so that serving a cached
+/// page does not derive them again.
+///
+/// Each value is kept with a weak reference to its page, and only reused
+/// for that very page: a key whose page was replaced in the render cache
+/// is derived again, and entries whose page was dropped are swept as the
+/// map grows, without hooks into the render cache.
+#[derive(Debug)]
+struct PageMemo<T> {
+    values: RwLock<FnvHashMap<PageKey, (Weak<str>, T)>>,
+}
+
+impl<T> Default for PageMemo<T> {
+    fn default() -> Self {
+        Self {
+            values: RwLock::default(),
+        }
+    }
+}
+
+impl<T: Clone> PageMemo<T> {
+    /// The number of values kept before dropped pages are swept.
+    const SWEEP_LEN: usize = 256;
+
+    /// Returns the value of `page` cached under `key`, deriving it with
+    /// `derive` if it is not known yet.
+    fn get_or_insert_with<F>(
+        &self,
+        key: PageKey,
+        page: &Arc<str>,
+        derive: F,
+    ) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        if let Some((cached, value)) = self
+            .values
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&key)
+        {
+            if Weak::ptr_eq(cached, &Arc::downgrade(page)) {
+                return value.clone();
+            }
+        }
+        let value = derive();
+        self.insert(key, page, value.clone());
+        value
+    }
+
+    /// Records `value` for `page`, cached under `key`.
+    fn insert(&self, key: PageKey, page: &Arc<str>, value: T) {
+        let mut values =
+            self.values.write().unwrap_or_else(PoisonError::into_inner);
+        if values.len() >= Self::SWEEP_LEN
+            && values.len().is_power_of_two()
+            && !values.contains_key(&key)
+        {
+            values.retain(|_, (page, _)| page.strong_count() > 0);
+        }
+        let _ = values.insert(key, (Arc::downgrade(page), value));
+    }
+}
+
+/// A template being rendered, and how.
+#[derive(Debug, Clone, Copy)]
+struct TemplateRender<'a> {
+    /// The whole template, for the lines of problems.
+    template: &'a str,
+    /// The offset of the body of the template, after any pragma.
+    start: usize,
+    context: &'a Context,
+    format: OutputFormat,
+    syntax: SyntaxVersion,
+    settings: &'a Settings<'a>,
+    /// Whether the render stops at the first problem.
+    fail_fast: bool,
+}
+
+/// The settings of a single render: the engine's own, with any
+/// [`RenderOptions`] applied.
+#[derive(Debug, Clone, Copy)]
+struct Settings<'a> {
+    engine: &'a Engine,
+    open: &'a str,
+    close: &'a str,
+    auto_escape: bool,
+    output_format: Option<OutputFormat>,
+    missing_keys: MissingKeys,
+    deadline: Option<Deadline<'a>>,
+    max_output: Option<usize>,
+    max_allocation: Option<usize>,
+}
+
+impl<'a> Settings<'a> {
+    /// Returns the engine's own settings.
+    fn of(engine: &'a Engine) -> Self {
+        Self {
+            engine,
+            open: &engine.open_delim,
+            close: &engine.close_delim,
+            auto_escape: engine.auto_escape,
+            output_format: engine.output_format,
+            missing_keys: engine.missing_keys,
+            deadline: None,
+            max_output: None,
+            max_allocation: None,
+        }
+    }
+
+    /// Applies the overrides of `options`.
+    fn with(mut self, options: &'a RenderOptions) -> Self {
+        if let Some((open, close)) = &options.delimiters {
+            self.open = open;
+            self.close = close;
+        }
+        self.auto_escape =
+            options.auto_escape.unwrap_or(self.auto_escape);
+        self.output_format =
+            options.output_format.or(self.output_format);
+        self.missing_keys =
+            options.missing_keys.unwrap_or(self.missing_keys);
+        self.deadline =
+            Deadline::start(options.timeout, options.cancel.as_ref());
+        self.max_output = options.max_output;
+        self.max_allocation = options.max_allocation;
+        self
+    }
+
+    /// Hashes the settings that change a page without being part of
+    /// its format, including those of the engine that no option
+    /// overrides, such as its base URL and its functions.
+    fn hash(&self) -> u64 {
+        let engine = self.engine;
+        let mut hasher = FnvHasher::default();
+        hash_str(&mut hasher, Some(self.open));
+        hash_str(&mut hasher, Some(self.close));
+        hash_str(&mut hasher, Some(&engine.template_path));
+        hasher.write_u8(self.missing_keys as u8);
+        hasher.write_u8(engine.syntax as u8);
+        hasher.write_u8(u8::from(engine.trim_tag_keys));
+        hasher.write_u8(u8::from(engine.case_insensitive_keys));
+        hasher.write_u8(u8::from(engine.deterministic));
+        hash_str(&mut hasher, engine.base_url.as_deref());
+        let meta = &engine.meta;
+        hash_str(&mut hasher, meta.site_name.as_deref());
+        hash_str(&mut hasher, Some(&meta.default_type));
+        hash_str(&mut hasher, meta.default_image.as_deref());
+        hash_str(&mut hasher, meta.locale.as_deref());
+        hash_str(&mut hasher, meta.twitter_site.as_deref());
+        match engine.build_time {
+            BuildTime::System => hasher.write_u8(0),
+            BuildTime::Fixed(time) => {
+                hasher.write_u8(1);
+                let since =
+                    time.duration_since(UNIX_EPOCH).unwrap_or_default();
+                hasher.write_u64(since.as_secs());
+                hasher.write_u32(since.subsec_nanos());
+            }
+        }
+        hasher.write_u64(engine.functions.revision());
+        hasher.write_u64(engine.blocks.revision());
+        hasher.finish()
+    }
+}
+
+/// Feeds `value` to `hasher`, length-prefixed and told apart from
+/// `None`.
+fn hash_str(hasher: &mut FnvHasher, value: Option<&str>) {
+    match value {
+        Some(value) => {
+            hasher.write_u8(1);
+            hasher.write(&(value.len() as u64).to_le_bytes());
+            hasher.write(value.as_bytes());
+        }
+        None => hasher.write_u8(0),
     }
 }
 
@@ -116,13 +964,196 @@ pub fn get(&self, key: &str) -> Option<&String> {
 #[derive(Debug)]
 pub struct Engine {
     /// Path to the template directory.
+    ///
+    /// The path is part of render cache keys, so changing it never
+    /// serves pages rendered from the previous directory. Layouts
+    /// remembered as missing are only forgotten by
+    /// [`Engine::clear_cache`].
     pub template_path: String,
-    /// Cache for rendered templates.
-    pub render_cache: Cache<String, String>,
+    /// Additional template directories searched, in order, after
+    /// `template_path` when a layout is not found there.
+    template_dirs: Vec<String>,
     /// Opening delimiter for template tags.
     pub open_delim: String,
     /// Closing delimiter for template tags.
     pub close_delim: String,
+    /// Extension appended to layout names that do not name a file
+    /// explicitly, without the leading dot.
+    default_extension: String,
+    /// Whether layout names must match the case of their files
+    /// exactly, as described in the [`loader`](crate::loader) module,
+    /// so that sites built on case-insensitive file systems also build
+    /// on case-sensitive ones.
+    strict_layout_case: bool,
+    /// Whether values rendered into HTML templates are HTML-escaped.
+    ///
+    /// XML and JSON templates are always escaped for their format; HTML
+    /// templates are rendered verbatim unless this is enabled, because
+    /// their context values commonly contain markup.
+    pub auto_escape: bool,
+    /// The syntax version of templates that do not declare one with a
+    /// pragma, as described in the [`syntax`](crate::syntax) module.
+    pub syntax: SyntaxVersion,
+    /// Whether whitespace around tag keys is ignored, so that
+    /// `{{  name  }}` reads `name`.
+    ///
+    /// Keys are always trimmed in tags that apply filters.
+    pub trim_tag_keys: bool,
+    /// Whether tag keys match context keys that differ only in case,
+    /// so that `{{title}}` reads a `Title` value.
+    ///
+    /// An exact match always wins over a case-insensitive one.
+    pub case_insensitive_keys: bool,
+    /// What happens when a tag names a key that has no value.
+    pub missing_keys: MissingKeys,
+    /// Context keys left out of render cache keys, so that pages whose
+    /// contexts differ only in these keys, as told by
+    /// [`ContextDiff::is_within`](crate::context::ContextDiff::is_within),
+    /// share a cached page.
+    ///
+    /// Suits values that change on every build but need not invalidate
+    /// pages, such as a build number: a page cached before the value
+    /// changed is served with the old value.
+    pub cache_ignored_keys: Vec<String>,
+    /// Output format applied to every render, overriding the format
+    /// inferred from the template extension. `None` infers the format.
+    pub output_format: Option<OutputFormat>,
+    /// The environment the site is built for, readable from templates
+    /// as `{{env.mode}}` and `{{env.<flag>}}`.
+    ///
+    /// Context values take precedence over the environment, so a
+    /// context key named `env.mode` hides the mode.
+    pub environment: Environment,
+    /// Functions callable from templates, as described in the
+    /// [`function`](crate::function) module. The [`meta`](crate::meta)
+    /// functions are registered by default.
+    pub functions: Functions,
+    /// Handlers of the block tags of templates, as described in the
+    /// [`block`](crate::block) module.
+    pub blocks: Blocks,
+    /// Site-wide settings of the built-in `og_tags` and `meta_tags`
+    /// functions.
+    pub meta: MetaConfig,
+    /// Templates of the shortcodes expanded by
+    /// [`Engine::expand_shortcodes`].
+    pub shortcodes: Shortcodes,
+    /// Base URL joined onto values by the `absolute_url` filter, e.g.
+    /// `https://example.com`.
+    pub base_url: Option<String>,
+    /// The time read by the built-in `now()` function.
+    pub build_time: BuildTime,
+    /// Whether output must be identical across runs, as described in the
+    /// [`determinism`](crate::determinism) module.
+    pub deterministic: bool,
+    /// Layout rendered by [`Engine::render_page_with_fallback`] when a
+    /// page cannot be rendered.
+    pub error_layout: Option<String>,
+    /// The label of the encoding of layout files that are neither
+    /// UTF-8 nor marked by a byte order mark, such as `windows-1252`.
+    /// Transcoding requires the `encoding` feature.
+    pub fallback_encoding: Option<String>,
+    /// How downloaded templates are verified before use, as described in
+    /// the [`integrity`](crate::integrity) module. `None` trusts them.
+    pub integrity: Option<Integrity>,
+    /// How many template files are downloaded at once, and how often
+    /// each host is requested.
+    pub download_limits: DownloadLimits,
+    /// The active theme, layered under the engine's own templates.
+    theme: Option<Theme>,
+    /// The active data directory, read by `{{data.*}}` tags.
+    data: Option<DataDir>,
+    /// Process environment variables readable by the `env()` function.
+    env_vars: EnvVars,
+    /// The hashes of the output assets read by the `sri()` function.
+    assets: Option<AssetHashes>,
+    /// The second level of the render cache, read on in-memory misses.
+    disk_cache: Option<DiskCache>,
+    /// Cache for rendered templates. Pages are shared, so a cache hit
+    /// does not copy the page.
+    render_cache: RwLock<Cache<PageKey, Arc<str>>>,
+    /// The content hashes of cached pages.
+    content_hashes: PageMemo<String>,
+    /// The layout files cached pages were rendered from.
+    template_paths: PageMemo<Option<PathBuf>>,
+    /// Layouts recently found missing, when negative caching is enabled.
+    missing_layouts: RwLock<Option<Cache<Symbol, ()>>>,
+    /// Interned layout names, used in render cache keys.
+    layouts: Interner,
+    /// Chooses the layout of pages rendered by
+    /// [`Engine::render_page_auto`].
+    layout_resolver: Option<LayoutResolver>,
+    /// The layouts that logical layout names stand for, set with
+    /// [`Engine::add_alias`].
+    aliases: FnvHashMap<String, String>,
+    /// Runs around every page served, as described in the
+    /// [`hooks`](crate::hooks) module.
+    hooks: RenderHooks,
+    /// The layers pages are served through, or `None` to serve them
+    /// from the render caches.
+    layers: Option<Vec<Arc<dyn RenderLayer>>>,
+    /// Records the render in progress of
+    /// [`Engine::render_page_profiled`].
+    profiler: Option<Profiler>,
+    /// Finds and reads layout files.
+    loader: Arc<dyn Loader>,
+    /// Downloads remote templates.
+    fetcher: Arc<dyn Fetcher>,
+}
+
+/// Clones copy the settings and the render cache of the engine; the
+/// clone and the original are then configured and cached independently.
+impl Clone for Engine {
+    fn clone(&self) -> Self {
+        Self {
+            template_path: self.template_path.clone(),
+            template_dirs: self.template_dirs.clone(),
+            open_delim: self.open_delim.clone(),
+            close_delim: self.close_delim.clone(),
+            default_extension: self.default_extension.clone(),
+            strict_layout_case: self.strict_layout_case,
+            auto_escape: self.auto_escape,
+            syntax: self.syntax,
+            trim_tag_keys: self.trim_tag_keys,
+            case_insensitive_keys: self.case_insensitive_keys,
+            missing_keys: self.missing_keys,
+            cache_ignored_keys: self.cache_ignored_keys.clone(),
+            output_format: self.output_format,
+            environment: self.environment.clone(),
+            env_vars: self.env_vars.clone(),
+            assets: self.assets.clone(),
+            disk_cache: self.disk_cache.clone(),
+            functions: self.functions.clone(),
+            blocks: self.blocks.clone(),
+            meta: self.meta.clone(),
+            shortcodes: self.shortcodes.clone(),
+            base_url: self.base_url.clone(),
+            build_time: self.build_time,
+            deterministic: self.deterministic,
+            error_layout: self.error_layout.clone(),
+            fallback_encoding: self.fallback_encoding.clone(),
+            integrity: self.integrity.clone(),
+            download_limits: self.download_limits,
+            theme: self.theme.clone(),
+            data: self.data.clone(),
+            render_cache: RwLock::new(self.render_cache().clone()),
+            content_hashes: PageMemo::default(),
+            template_paths: PageMemo::default(),
+            missing_layouts: RwLock::new(
+                self.missing_layouts
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone(),
+            ),
+            layouts: self.layouts.clone(),
+            layout_resolver: self.layout_resolver.clone(),
+            aliases: self.aliases.clone(),
+            hooks: self.hooks.clone(),
+            layers: self.layers.clone(),
+            profiler: None,
+            loader: Arc::clone(&self.loader),
+            fetcher: Arc::clone(&self.fetcher),
+        }
+    }
 }
 
 impl Engine {
@@ -143,16 +1174,65 @@ impl Engine {
     /// ```
     #[must_use]
     pub fn new(template_path: &str, cache_ttl: Duration) -> Self {
+        let mut render_cache = Cache::new(cache_ttl);
+        render_cache.set_sweep_interval(Some(cache_ttl));
+        let mut functions = Functions::new();
+        meta::register(&mut functions);
+        determinism::register(&mut functions);
+        csp::register(&mut functions);
+        asset::register(&mut functions);
         Self {
             template_path: template_path.to_string(),
-            render_cache: Cache::new(cache_ttl),
-            open_delim: "{{".to_string(),
-            close_delim: "}}".to_string(),
+            template_dirs: Vec::new(),
+            open_delim: DEFAULT_OPEN_DELIM.to_string(),
+            close_delim: DEFAULT_CLOSE_DELIM.to_string(),
+            default_extension: "html".to_string(),
+            strict_layout_case: false,
+            auto_escape: false,
+            syntax: SyntaxVersion::V1,
+            trim_tag_keys: false,
+            case_insensitive_keys: false,
+            missing_keys: MissingKeys::Error,
+            cache_ignored_keys: Vec::new(),
+            output_format: None,
+            environment: Environment::default(),
+            env_vars: EnvVars::new(),
+            assets: None,
+            disk_cache: None,
+            functions,
+            blocks: Blocks::new(),
+            meta: MetaConfig::default(),
+            shortcodes: Shortcodes::new(),
+            base_url: None,
+            build_time: BuildTime::System,
+            deterministic: false,
+            error_layout: None,
+            fallback_encoding: None,
+            integrity: None,
+            download_limits: DownloadLimits::default(),
+            theme: None,
+            data: None,
+            render_cache: RwLock::new(render_cache),
+            content_hashes: PageMemo::default(),
+            template_paths: PageMemo::default(),
+            missing_layouts: RwLock::new(None),
+            layouts: Interner::default(),
+            layout_resolver: None,
+            aliases: FnvHashMap::default(),
+            hooks: RenderHooks::default(),
+            layers: None,
+            profiler: None,
+            loader: Arc::new(FsLoader),
+            fetcher: fetch::default_fetcher(),
         }
     }
 
     /// Renders a page using the specified layout and context, with caching.
     ///
+    /// Rendering only borrows the engine: the render cache is locked
+    /// internally, so one engine can serve many threads through an
+    /// `Arc`.
+    ///
     /// # Arguments
     ///
     /// * `context` - The rendering context, which includes key-value pairs for variable substitution.
@@ -176,52 +1256,33 @@ pub fn new(template_path: &str, cache_ttl: Duration) -> Self {
     /// use staticweaver::Context;
     /// use std::time::Duration;
     ///
-    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
     /// let context = Context::new();
     /// let result = engine.render_page(&context, "default");
     /// ```
     pub fn render_page(
-        &mut self,
+        &self,
         context: &Context,
         layout: &str,
     ) -> Result<String, EngineError> {
-        let cache_key = format!("{}:{}", layout, context.hash());
-
-        // Return cached result if available
-        if let Some(cached) = self.render_cache.get(&cache_key) {
-            return Ok(cached.to_string());
-        }
-
-        // Attempt to read the layout template from the file system
-        let template_path = Path::new(&self.template_path)
-            .join(format!("{}.html", layout));
-        let template_content = fs::read_to_string(&template_path)?;
-
-        // Render the template with the provided context
-        let rendered =
-            self.render_template(&template_content, context)?;
-
-        // Cache the rendered result for future use
-        let _ = self.render_cache.insert(cache_key, rendered.clone());
-
-        Ok(rendered)
+        self.render_page_shared(context, layout)
+            .map(|page| page.to_string())
     }
 
-    /// Renders a template string with the given context and custom delimiters.
-    ///
-    /// # Arguments
+    /// Renders a page like [`Engine::render_page`], returning the cached
+    /// page itself rather than a copy.
     ///
-    /// * `template` - The template string containing the tags to be replaced.
-    /// * `context` - A `Context` containing the key-value pairs to use for substitution.
+    /// Cache hits only bump a reference count, which avoids copying large
+    /// pages that are served repeatedly.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// A `Result` containing the rendered string or an `EngineError` if an error occurs.
+    /// * `context` - The rendering context.
+    /// * `layout` - The layout file to use for rendering.
     ///
     /// # Errors
     ///
-    /// * `EngineError::InvalidTemplate` - If the template contains unclosed tags or is empty.
-    /// * `EngineError::Render` - If a template tag cannot be resolved from the context.
+    /// Returns the same errors as [`Engine::render_page`].
     ///
     /// # Examples
     ///
@@ -231,417 +1292,5447 @@ pub fn render_page(
     /// use std::time::Duration;
     ///
     /// let engine = Engine::new("templates", Duration::from_secs(3600));
-    /// let mut context = Context::new();
-    /// context.set("greeting".to_string(), "Hello".to_string());
-    /// context.set("name".to_string(), "Alice".to_string());
-    ///
-    /// let template = "{{greeting}}, {{name}}!";
-    /// let result = engine.render_template(template, &context).unwrap();
-    /// assert_eq!(result, "Hello, Alice!");
+    /// let result = engine.render_page_shared(&Context::new(), "default");
     /// ```
-    pub fn render_template(
+    pub fn render_page_shared(
         &self,
-        template: &str,
         context: &Context,
-    ) -> Result<String, EngineError> {
-        if template.trim().is_empty() {
-            return Err(EngineError::InvalidTemplate(
-                "Template is empty".to_string(),
-            ));
-        }
-
-        // Check for single delimiters
-        if template.contains(&self.open_delim[..1])
-            && !template.contains(&self.open_delim)
-        {
-            return Err(EngineError::InvalidTemplate(format!(
-                "Invalid template syntax: single '{}' are not allowed",
-                &self.open_delim[..1]
-            )));
-        }
+        layout: &str,
+    ) -> Result<Arc<str>, EngineError> {
+        self.render_page_inner(context, layout, &RenderOptions::new())
+    }
 
-        let mut output = String::with_capacity(template.len());
-        let mut last_end = 0;
-        let mut depth = 0;
-
-        for (idx, _) in template.match_indices(&self.open_delim) {
-            if depth > 0 {
-                return Err(EngineError::InvalidTemplate(
-                    "Nested delimiters are not allowed".to_string(),
-                ));
-            }
-            depth += 1;
-            output.push_str(&template[last_end..idx]);
-            if let Some(end) = template[idx..].find(&self.close_delim) {
-                let key =
-                    &template[idx + self.open_delim.len()..idx + end];
-                if let Some(value) = context.get(key) {
-                    output.push_str(value);
-                } else {
-                    return Err(EngineError::Render(format!(
-                        "Unresolved template tag: {}",
-                        key
-                    )));
-                }
-                last_end = idx + end + self.close_delim.len();
-                depth -= 1;
-            } else {
-                return Err(EngineError::InvalidTemplate(
-                    "Unclosed template tag".to_string(),
-                ));
-            }
-        }
-
-        output.push_str(&template[last_end..]);
-
-        Ok(output)
-    }
-
-    /// Sets custom delimiters for the template tags.
+    /// Renders a page like [`Engine::render_page`], then fills in its
+    /// Content Security Policy nonce and policy, as described in the
+    /// [`csp`](crate::csp) module.
+    ///
+    /// The nonce is random, unless the engine is deterministic, in which
+    /// case it is derived from the page so that builds are reproducible.
     ///
     /// # Arguments
     ///
-    /// * `open` - The string to use as the opening delimiter (e.g., `<<`).
-    /// * `close` - The string to use as the closing delimiter (e.g., `>>`).
+    /// * `context` - The rendering context.
+    /// * `layout` - The layout file to use for rendering.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Engine::render_page`], or
+    /// `EngineError::Io` if no random nonce can be generated.
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```no_run
     /// use staticweaver::engine::Engine;
+    /// use staticweaver::Context;
     /// use std::time::Duration;
     ///
-    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
-    /// engine.set_delimiters("<<", ">>");
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let page = engine.render_page_csp(&Context::new(), "index").unwrap();
+    /// println!("Content-Security-Policy: {}", page.policy());
     /// ```
-    pub fn set_delimiters(&mut self, open: &str, close: &str) {
-        self.open_delim = open.to_string();
-        self.close_delim = close.to_string();
+    pub fn render_page_csp(
+        &self,
+        context: &Context,
+        layout: &str,
+    ) -> Result<CspPage, EngineError> {
+        let page = self.render_page_inner(
+            context,
+            layout,
+            &RenderOptions::new(),
+        )?;
+        let nonce = if self.deterministic {
+            csp::stable_nonce(&page)
+        } else {
+            csp::nonce()?
+        };
+        Ok(csp::finalize(&page, &nonce))
     }
 
-    /// Creates or uses an existing template folder.
-    ///
-    /// # Arguments
+    /// Renders a page using an explicit output format for this call only.
     ///
-    /// * `template_path` - An optional path to the template folder. It can be a local path or a URL.
+    /// The format selects how substituted values are escaped, regardless
+    /// of the layout's extension or the engine-wide `output_format`.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// A `Result` containing the template folder path as a `String` on success, or an `EngineError` on failure.
+    /// * `context` - The rendering context.
+    /// * `layout` - The layout file to use for rendering.
+    /// * `format` - The output format to escape values for.
     ///
     /// # Errors
     ///
-    /// This function can return the following errors:
-    /// - `EngineError::Io`: If there is an issue with file operations.
-    /// - `EngineError::Reqwest`: If there is an issue downloading files from a URL.
+    /// Returns the same errors as [`Engine::render_page`].
     ///
     /// # Examples
     ///
     /// ```
     /// use staticweaver::engine::Engine;
+    /// use staticweaver::escape::OutputFormat;
+    /// use staticweaver::Context;
     /// use std::time::Duration;
     ///
     /// let engine = Engine::new("templates", Duration::from_secs(3600));
-    /// let result = engine.create_template_folder(Some("custom_templates"));
+    /// let result =
+    ///     engine.render_page_as(&Context::new(), "search", OutputFormat::Json);
     /// ```
-    pub fn create_template_folder(
+    pub fn render_page_as(
         &self,
-        template_path: Option<&str>,
+        context: &Context,
+        layout: &str,
+        format: OutputFormat,
     ) -> Result<String, EngineError> {
-        let current_dir = std::env::current_dir()?;
-
-        let template_dir_path = match template_path {
-            Some(path) if is_url(path) => {
-                // Download template files from the URL
-                Self::download_files_from_url(path)?
-            }
-            Some(path) => {
-                // Use the local directory if it exists
-                let local_path = current_dir.join(path);
-                if local_path.exists() && local_path.is_dir() {
-                    local_path
-                } else {
-                    // Return an I/O error if the directory is not found
-                    return Err(EngineError::Io(std::io::Error::new(
-                        std::io::ErrorKind::NotFound,
-                        format!(
-                            "Template directory not found: {}",
-                            path
-                        ),
-                    )));
-                }
-            }
-            None => {
-                // Default to downloading template files from the default URL
-                let default_url = "https://raw.githubusercontent.com/sebastienrousseau/shokunin/main/template/";
-                Self::download_files_from_url(default_url)?
-            }
+        let options = RenderOptions {
+            output_format: Some(format),
+            ..RenderOptions::default()
         };
-
-        // Ensure the template path is valid UTF-8
-        Ok(template_dir_path
-            .to_str()
-            .ok_or_else(|| {
-                EngineError::Io(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    "Invalid UTF-8 sequence in template path",
-                ))
-            })?
-            .to_string())
+        self.render_page_inner(context, layout, &options)
+            .map(|page| page.to_string())
     }
 
-    /// Helper function to download files from a URL and save to a directory.
+    /// Renders a page like [`Engine::render_page`], with `options`
+    /// overriding the engine's settings for this call only.
+    ///
+    /// Pages rendered with different delimiters, escaping, or
+    /// missing-key policies are cached separately.
     ///
     /// # Arguments
     ///
-    /// * `url` - The URL to download files from.
+    /// * `options` - The settings to override.
+    /// * `context` - The rendering context.
+    /// * `layout` - The layout file to use for rendering.
     ///
-    /// # Returns
+    /// # Errors
     ///
-    /// A `Result` containing the path to the directory or an `EngineError`.
-    fn download_files_from_url(
-        url: &str,
-    ) -> Result<PathBuf, EngineError> {
-        let template_dir_path = tempdir()?.into_path();
-
-        let files = [
-            "contact.html",
-            "index.html",
-            "page.html",
-            "post.html",
-            "main.js",
-            "sw.js",
-        ];
-
-        for file in &files {
-            Self::download_file(url, file, &template_dir_path)?;
+    /// Returns the same errors as [`Engine::render_page`], and
+    /// [`EngineError::InvalidDelimiters`] if the delimiters of `options`
+    /// are invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::{MissingKeys, RenderOptions};
+    /// use staticweaver::{Context, Engine};
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let mut options = RenderOptions::new();
+    /// options.set_delimiters("<%", "%>").unwrap();
+    /// options.missing_keys = Some(MissingKeys::Empty);
+    /// let result = engine.render_page_with(&options, &Context::new(), "legacy");
+    /// ```
+    pub fn render_page_with(
+        &self,
+        options: &RenderOptions,
+        context: &Context,
+        layout: &str,
+    ) -> Result<String, EngineError> {
+        if let Some((open, close)) = &options.delimiters {
+            validate_delimiters(open, close)?;
         }
-
-        Ok(template_dir_path)
+        self.render_page_inner(context, layout, options)
+            .map(|page| page.to_string())
     }
 
-    /// Downloads a single file from a URL to the given directory.
+    /// Renders a page like [`Engine::render_page_with`], returning it in
+    /// a [`Rendered`] with its cache status, the time taken to serve it,
+    /// the files it was rendered from, and its checksum.
     ///
     /// # Arguments
     ///
-    /// * `url` - The base URL.
-    /// * `file` - The file to download.
-    /// * `dir` - The directory to save the file.
+    /// * `options` - The settings to override, such as
+    ///   `RenderOptions::new()` to override none.
+    /// * `context` - The rendering context.
+    /// * `layout` - The layout file to use for rendering.
     ///
-    /// # Returns
+    /// # Errors
     ///
-    /// A `Result` indicating success or an `EngineError`.
-    fn download_file(
-        url: &str,
-        file: &str,
-        dir: &Path,
-    ) -> Result<(), EngineError> {
-        let file_url = format!("{}/{}", url, file);
-        let file_path = dir.join(file);
+    /// Returns the same errors as [`Engine::render_page_with`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::{CacheStatus, RenderOptions};
+    /// use staticweaver::loader::MemoryLoader;
+    /// use staticweaver::{Context, Engine};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let loader = MemoryLoader::new();
+    /// loader.insert("templates/page.html", "<h1>{{title}}</h1>");
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// engine.set_loader(Arc::new(loader));
+    /// let mut context = Context::new();
+    /// context.set("title", "Home");
+    ///
+    /// let options = RenderOptions::new();
+    /// let page = engine.render_page_detailed(&options, &context, "page").unwrap();
+    /// assert_eq!(&*page.output, "<h1>Home</h1>");
+    /// assert_eq!(page.cache, CacheStatus::Miss);
+    /// assert!(page.dependencies[0].ends_with("page.html"));
+    ///
+    /// let page = engine.render_page_detailed(&options, &context, "page").unwrap();
+    /// assert_eq!(page.cache, CacheStatus::Hit);
+    /// ```
+    pub fn render_page_detailed(
+        &self,
+        options: &RenderOptions,
+        context: &Context,
+        layout: &str,
+    ) -> Result<Rendered, EngineError> {
+        let start = Instant::now();
+        if let Some((open, close)) = &options.delimiters {
+            validate_delimiters(open, close)?;
+        }
+        let mut page_key = None;
+        let mut served = None;
+        let (output, cache) =
+            self.hooks.run(context, layout, |context| {
+                if let Some(layers) = &self.layers {
+                    let page = Next::new(self, layers).run(
+                        RenderRequest::new(
+                            context,
+                            layout,
+                            options.clone(),
+                        ),
+                    )?;
+                    let result = (Arc::clone(&page.output), page.cache);
+                    served = Some(page);
+                    return Ok(result);
+                }
+                let (context, cache_key) =
+                    self.page_key(context, layout, options);
+                page_key = Some(cache_key);
+                self.render_page_status(
+                    &context, layout, options, cache_key,
+                )
+            })?;
+        let page = match served {
+            Some(page) if Arc::ptr_eq(&page.output, &output) => page,
+            // After hooks changed the page.
+            Some(page) => page.with_output(output),
+            None if self.hooks.is_empty() => {
+                self.rendered(output, cache, start, page_key, layout)
+            }
+            // After hooks may have changed the cached page.
+            None => self.rendered(output, cache, start, None, layout),
+        };
+        Ok(Rendered {
+            duration: start.elapsed(),
+            ..page
+        })
+    }
+
+    /// Returns the [`Rendered`] page of `output`, served from `cache`
+    /// since `start`, hashing it and resolving its layout unless it is
+    /// cached under `cache_key`.
+    pub(crate) fn rendered(
+        &self,
+        output: Arc<str>,
+        cache: CacheStatus,
+        start: Instant,
+        cache_key: Option<PageKey>,
+        layout: &str,
+    ) -> Rendered {
+        let cache_key = match cache {
+            CacheStatus::Bypass => None,
+            _ => cache_key,
+        };
+        let template_path = match cache_key {
+            Some(cache_key) => self.template_paths.get_or_insert_with(
+                cache_key,
+                &output,
+                || self.resolve_template(layout),
+            ),
+            None => self.resolve_template(layout),
+        };
+        Rendered {
+            content_hash: match cache_key {
+                Some(cache_key) => {
+                    self.content_hash(cache_key, &output)
+                }
+                None => sha256_hex(output.as_bytes()),
+            },
+            dependencies: template_path.into_iter().collect(),
+            duration: start.elapsed(),
+            output,
+            cache,
+        }
+    }
 
-        let client = reqwest::blocking::Client::new();
-        let response = client
-            .get(&file_url)
-            .timeout(Duration::from_secs(10)) // Set a timeout
-            .send()?;
+    /// Renders the page of `request` without consulting the caches, as
+    /// the last of the [layers](crate::layer) of the engine.
+    pub(crate) fn render_request(
+        &self,
+        request: &RenderRequest<'_>,
+    ) -> Result<Rendered, EngineError> {
+        let start = Instant::now();
+        let context = self.themed(&request.context);
+        let output = self
+            .render_uncached(
+                &context,
+                &request.layout,
+                &request.options,
+            )
+            .map_err(|err| {
+                self.remember_missing(&request.layout, err)
+            })?;
+        Ok(self.rendered(
+            output,
+            CacheStatus::Bypass,
+            start,
+            None,
+            &request.layout,
+        ))
+    }
 
-        // Check if the response status is not a success (200-299)
-        if !response.status().is_success() {
-            return Err(EngineError::Render(format!(
-                "Failed to download {}: HTTP {}",
-                file,
-                response.status()
-            )));
+    /// Serves the page of `request` from the caches, or with `next` on a
+    /// miss, as the [`Cache`](crate::layer::Cache) layer.
+    pub(crate) fn render_request_cached(
+        &self,
+        request: RenderRequest<'_>,
+        next: Next<'_>,
+    ) -> Result<Rendered, EngineError> {
+        if request.options.bypass_cache {
+            return next.run(request);
+        }
+        let start = Instant::now();
+        let layout = request.layout.to_string();
+        let (_, cache_key) =
+            self.page_key(&request.context, &layout, &request.options);
+        if let Some(page) = self.render_cache().get_shared(&cache_key) {
+            return Ok(self.rendered(
+                page,
+                CacheStatus::Hit,
+                start,
+                Some(cache_key),
+                &layout,
+            ));
         }
+        let mut served = None;
+        let (output, cache) =
+            self.render_missed_with(&layout, cache_key, || {
+                let page = next.run(request)?;
+                let output = Arc::clone(&page.output);
+                served = Some(page);
+                Ok(output)
+            })?;
+        Ok(match served {
+            Some(page) => Rendered { cache, ..page },
+            None => self.rendered(
+                output,
+                cache,
+                start,
+                Some(cache_key),
+                &layout,
+            ),
+        })
+    }
+
+    /// Sets the stack of layers pages are served through, replacing
+    /// the default caching, as described in the
+    /// [`layer`](crate::layer) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `layers` - The layers, from the first called to the last.
+    pub fn set_layers(&mut self, layers: Vec<Arc<dyn RenderLayer>>) {
+        self.layers = Some(layers);
+    }
 
-        // Proceed with file saving if the response is successful
-        let mut file = File::create(&file_path)?;
-        let content = response.text()?;
-        file.write_all(content.as_bytes())?;
+    /// Restores the default caching, removing the layers set with
+    /// [`Engine::set_layers`].
+    pub fn clear_layers(&mut self) {
+        self.layers = None;
+    }
 
-        Ok(())
+    /// Returns the layers set with [`Engine::set_layers`], if any.
+    #[must_use]
+    pub fn layers(&self) -> Option<&[Arc<dyn RenderLayer>]> {
+        self.layers.as_deref()
     }
 
-    /// Clears all cached rendered templates.
+    /// Renders a page with the layout chosen by its context, as
+    /// returned by [`Engine::layout_for`].
     ///
-    /// This method removes all entries from the cache, freeing up memory.
-    /// After calling this, subsequent render requests will not retrieve
-    /// any cached results and will regenerate the templates.
+    /// This lets each page declare its layout, e.g. in a `layout`
+    /// frontmatter field, instead of the call site choosing it.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The rendering context.
+    /// * `default_layout` - The layout of pages that do not choose one.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Engine::render_page`].
     ///
     /// # Examples
     ///
     /// ```
     /// use staticweaver::engine::Engine;
+    /// use staticweaver::Context;
     /// use std::time::Duration;
     ///
-    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
-    /// // Cache some templates...
-    ///
-    /// // Clear the cache
-    /// engine.clear_cache();
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let mut context = Context::new();
+    /// context.set("layout", "post");
+    /// let result = engine.render_page_auto(&context, "default");
     /// ```
-    pub fn clear_cache(&mut self) {
-        self.render_cache.clear();
+    pub fn render_page_auto(
+        &self,
+        context: &Context,
+        default_layout: &str,
+    ) -> Result<String, EngineError> {
+        let layout =
+            self.layout_for(context, default_layout).into_owned();
+        self.render_page(context, &layout)
     }
 
-    /// Sets a maximum size for the render cache and clears the cache if it exceeds the specified limit.
+    /// Returns the layout a page's context chooses.
     ///
-    /// This method allows you to define a maximum number of entries that can be stored in the render cache.
-    /// If the cache size exceeds this limit, the cache will be cleared to prevent unbounded memory usage.
+    /// The layout resolver set with [`Engine::set_layout_resolver`] is
+    /// asked first. If there is none, or it returns `None`, the value of
+    /// the [`LAYOUT_KEY`] context key is used, and failing that
+    /// `default_layout`.
     ///
     /// # Arguments
     ///
-    /// * `max_size` - The maximum number of cache entries allowed before the cache is cleared.
+    /// * `context` - The rendering context.
+    /// * `default_layout` - The layout of pages that do not choose one.
     ///
     /// # Examples
     ///
     /// ```
     /// use staticweaver::engine::Engine;
+    /// use staticweaver::Context;
     /// use std::time::Duration;
     ///
-    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
-    ///
-    /// // Set a maximum cache size of 100 entries
-    /// engine.set_max_cache_size(100);
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let mut context = Context::new();
+    /// assert_eq!(engine.layout_for(&context, "default"), "default");
+    /// context.set("layout", "post");
+    /// assert_eq!(engine.layout_for(&context, "default"), "post");
     /// ```
-    pub fn set_max_cache_size(&mut self, max_size: usize) {
-        if self.render_cache.len() > max_size {
-            self.clear_cache();
+    #[must_use]
+    pub fn layout_for<'a>(
+        &self,
+        context: &'a Context,
+        default_layout: &'a str,
+    ) -> Cow<'a, str> {
+        if let Some(layout) = self
+            .layout_resolver
+            .as_ref()
+            .and_then(|resolver| (resolver.0)(context))
+        {
+            return Cow::Owned(layout);
         }
+        context
+            .resolve(LAYOUT_KEY)
+            .unwrap_or(Cow::Borrowed(default_layout))
     }
-}
-
-/// Utility function to check if a given path is a URL.
-///
-/// # Arguments
-///
-/// * `path` - The path to check.
-///
-/// # Returns
-///
-/// `true` if the path is a URL, `false` otherwise.
-fn is_url(path: &str) -> bool {
-    path.starts_with("http://") || path.starts_with("https://")
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::Context;
-
-    #[test]
-    fn test_render_template() {
-        let mut engine = Engine::new("", Duration::from_secs(60));
-        engine.set_delimiters("<<", ">>");
-        let mut context = Context::new();
-        context.set("name".to_string(), "Alice".to_string());
-        context.set("greeting".to_string(), "Hello".to_string());
+    /// Sets a callback that chooses the layout of a page from its
+    /// context, replacing any previous one.
+    ///
+    /// The callback is consulted by [`Engine::layout_for`] before the
+    /// [`LAYOUT_KEY`] context key; returning `None` falls back to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `resolver` - A function returning the layout for a context.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use staticweaver::Context;
+    /// use std::time::Duration;
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// engine.set_layout_resolver(|context| {
+    ///     context.get("date").map(|_| "post".to_string())
+    /// });
+    /// let mut context = Context::new();
+    /// context.set("date", "2024-01-01");
+    /// assert_eq!(engine.layout_for(&context, "page"), "post");
+    /// ```
+    pub fn set_layout_resolver<F>(&mut self, resolver: F)
+    where
+        F: Fn(&Context) -> Option<String> + Send + Sync + 'static,
+    {
+        self.layout_resolver = Some(LayoutResolver(Arc::new(resolver)));
+    }
 
-        let template = "<<greeting>>, <<name>>!";
-        let result =
-            engine.render_template(template, &context).unwrap();
-        assert_eq!(result, "Hello, Alice!");
+    /// Removes the callback set with [`Engine::set_layout_resolver`].
+    pub fn clear_layout_resolver(&mut self) {
+        self.layout_resolver = None;
     }
 
-    #[test]
-    fn test_render_template_empty() {
-        let engine = Engine::new("", Duration::from_secs(60));
-        let context = Context::new();
+    /// Adds a hook run before every page is served, which may change
+    /// the context of the page, respond in its place, or veto it, as
+    /// described in the [`hooks`](crate::hooks) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Called with the context and the layout of the page.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::hooks::BeforeRender;
+    /// use staticweaver::{Context, Engine};
+    /// use std::time::Duration;
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(60));
+    /// engine.on_before_render(|_, layout| match layout {
+    ///     "drafts" => BeforeRender::Veto("drafts are not published".to_string()),
+    ///     _ => BeforeRender::Continue,
+    /// });
+    /// let err = engine.render_page(&Context::new(), "drafts").unwrap_err();
+    /// assert_eq!(err.code(), "vetoed");
+    /// ```
+    pub fn on_before_render<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut Context, &str) -> BeforeRender
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.hooks.push_before(hook);
+    }
 
-        let template = "";
-        let result = engine.render_template(template, &context);
-        assert!(matches!(result, Err(EngineError::InvalidTemplate(_))));
+    /// Adds a hook run after every page is served, cache hits
+    /// included, which may change or replace the result, as described
+    /// in the [`hooks`](crate::hooks) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Called with the outcome of serving the page.
+    pub fn on_after_render<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut AfterRender<'_>) + Send + Sync + 'static,
+    {
+        self.hooks.push_after(hook);
     }
 
-    #[test]
-    fn test_render_template_invalid_syntax() {
-        let mut engine = Engine::new("", Duration::from_secs(60));
-        engine.set_delimiters("{{", "}}"); // Set back to default delimiters
-        let context = Context::new();
-        let template = "Hello, {name}!";
-        let result = engine.render_template(template, &context);
-        assert!(
-            matches!(result, Err(EngineError::InvalidTemplate(msg)) if msg.contains("single '{'"))
-        );
+    /// Removes every hook added with [`Engine::on_before_render`] and
+    /// [`Engine::on_after_render`].
+    pub fn clear_render_hooks(&mut self) {
+        self.hooks = RenderHooks::default();
     }
 
-    #[test]
-    fn test_render_template_custom_delimiters() {
-        let mut engine = Engine::new("", Duration::from_secs(60));
-        engine.set_delimiters("<<", ">>");
-        let mut context = Context::new();
-        context.set("name".to_string(), "Alice".to_string());
-        context.set("greeting".to_string(), "Hello".to_string());
+    /// Returns the hooks of the engine.
+    pub(crate) fn hooks(&self) -> &RenderHooks {
+        &self.hooks
+    }
+
+    /// Makes the layout name `alias` stand for `target`, replacing any
+    /// previous target, so call sites keep a stable logical name while
+    /// layout files move.
+    ///
+    /// `target` is a layout name as passed to [`Engine::render_page`],
+    /// such as `blog/post_v2` or `blog/post_v2.html`, and is not itself
+    /// looked up as an alias. Pages cached under `alias` are dropped, so
+    /// the next render reads the new target.
+    ///
+    /// # Arguments
+    ///
+    /// * `alias` - The logical layout name.
+    /// * `target` - The layout it stands for.
+    ///
+    /// # Returns
+    ///
+    /// The previous target of `alias`, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::loader::MemoryLoader;
+    /// use staticweaver::{Context, Engine};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let loader = MemoryLoader::new();
+    /// loader.insert("templates/blog/post_v2.html", "v2");
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(60));
+    /// engine.set_loader(Arc::new(loader));
+    /// engine.add_alias("post", "blog/post_v2.html");
+    /// assert_eq!(engine.render_page(&Context::new(), "post").unwrap(), "v2");
+    /// ```
+    pub fn add_alias(
+        &mut self,
+        alias: &str,
+        target: &str,
+    ) -> Option<String> {
+        self.invalidate_layout(alias);
+        self.aliases.insert(alias.to_string(), target.to_string())
+    }
+
+    /// Removes the alias `alias`, so that the name is resolved as a
+    /// layout file again.
+    ///
+    /// # Arguments
+    ///
+    /// * `alias` - The logical layout name.
+    ///
+    /// # Returns
+    ///
+    /// The target of the removed alias, if any.
+    pub fn remove_alias(&mut self, alias: &str) -> Option<String> {
+        self.invalidate_layout(alias);
+        self.aliases.remove(alias)
+    }
+
+    /// Returns the layout `layout` stands for: the target of its alias,
+    /// or `layout` itself.
+    #[must_use]
+    pub fn alias_target<'a>(&'a self, layout: &'a str) -> &'a str {
+        self.aliases.get(layout).map_or(layout, String::as_str)
+    }
+
+    /// Shared implementation of the `render_page` family.
+    fn render_page_inner(
+        &self,
+        context: &Context,
+        layout: &str,
+        options: &RenderOptions,
+    ) -> Result<Arc<str>, EngineError> {
+        self.hooks
+            .run(context, layout, |context| {
+                if let Some(layers) = &self.layers {
+                    return Next::new(self, layers)
+                        .run(RenderRequest::new(
+                            context,
+                            layout,
+                            options.clone(),
+                        ))
+                        .map(|page| (page.output, page.cache));
+                }
+                let (context, cache_key) =
+                    self.page_key(context, layout, options);
+                self.render_page_status(
+                    &context, layout, options, cache_key,
+                )
+            })
+            .map(|(page, _)| page)
+    }
+
+    /// Renders the page of `context`, as returned by
+    /// [`Engine::page_key`] with `cache_key`, also returning whether it
+    /// was served from a cache.
+    fn render_page_status(
+        &self,
+        context: &Context,
+        layout: &str,
+        options: &RenderOptions,
+        cache_key: PageKey,
+    ) -> Result<(Arc<str>, CacheStatus), EngineError> {
+        if options.bypass_cache {
+            return self
+                .render_uncached(context, layout, options)
+                .map(|page| (page, CacheStatus::Bypass))
+                .map_err(|err| self.remember_missing(layout, err));
+        }
+
+        // Return cached result if available
+        if let Some(cached) = self.render_cache().get_shared(&cache_key)
+        {
+            return Ok((cached, CacheStatus::Hit));
+        }
+        self.render_missed(context, layout, options, cache_key)
+    }
+
+    /// Returns the SHA-256 checksum of `page`, cached under `cache_key`,
+    /// hashing each cached page only once.
+    pub(crate) fn content_hash(
+        &self,
+        cache_key: PageKey,
+        page: &Arc<str>,
+    ) -> String {
+        self.content_hashes.get_or_insert_with(cache_key, page, || {
+            sha256_hex(page.as_bytes())
+        })
+    }
+
+    /// Serves a page missed by the in-memory render cache from the disk
+    /// cache, or renders it, then caches it under `cache_key`.
+    pub(crate) fn render_missed(
+        &self,
+        context: &Context,
+        layout: &str,
+        options: &RenderOptions,
+        cache_key: PageKey,
+    ) -> Result<(Arc<str>, CacheStatus), EngineError> {
+        self.render_missed_with(layout, cache_key, || {
+            self.render_uncached(context, layout, options)
+                .map_err(|err| self.remember_missing(layout, err))
+        })
+    }
+
+    /// Serves a page missed by the in-memory render cache from the disk
+    /// cache, or renders it with `render`, then caches it under
+    /// `cache_key`.
+    fn render_missed_with<F>(
+        &self,
+        layout: &str,
+        cache_key: PageKey,
+        render: F,
+    ) -> Result<(Arc<str>, CacheStatus), EngineError>
+    where
+        F: FnOnce() -> Result<Arc<str>, EngineError>,
+    {
+        let disk = self.disk_cache.as_ref().and_then(|disk| {
+            self.disk_key(layout, &cache_key).map(|key| (disk, key))
+        });
+        if let Some((disk, key)) = &disk {
+            if let Some(page) = disk.get(key) {
+                let page = self.cache_page(cache_key, page);
+                self.record_template_path(cache_key, &page, layout);
+                return Ok((page, CacheStatus::DiskHit));
+            }
+        }
+
+        // Cache the rendered result for future use
+        let rendered = self.cache_page(cache_key, render()?);
+        self.record_template_path(cache_key, &rendered, layout);
+        if let Some((disk, key)) = disk {
+            if !self.env_vars.reveals_secret(&rendered) {
+                let _ = disk.insert(&key, &rendered);
+            }
+        }
+        Ok((rendered, CacheStatus::Miss))
+    }
+
+    /// Records the layout file `page`, cached under `cache_key`, was
+    /// rendered from, for [`Engine::rendered`] to report on cache hits.
+    fn record_template_path(
+        &self,
+        cache_key: PageKey,
+        page: &Arc<str>,
+        layout: &str,
+    ) {
+        self.template_paths.insert(
+            cache_key,
+            page,
+            self.resolve_template(layout),
+        );
+    }
+
+    /// Returns the disk cache key of the page of `layout` cached under
+    /// `cache_key`, or `None` if its layout cannot be read.
+    ///
+    /// Unlike render cache keys, disk cache keys outlive the engine, so
+    /// they cover the layout source and every setting of the engine that
+    /// changes a page.
+    fn disk_key(
+        &self,
+        layout: &str,
+        cache_key: &PageKey,
+    ) -> Option<String> {
+        self.layout_disk_key(layout)
+            .map(|key| self.page_disk_key(key, cache_key))
+    }
+
+    /// Starts the disk cache keys of the pages of `layout` with its name,
+    /// its source, and the data directory, which render cache keys
+    /// leave out.
+    fn layout_disk_key(&self, layout: &str) -> Option<DiskKey> {
+        let path = self.resolve_template(layout)?;
+        let source = self.loader.read(&path).ok()?;
+        let mut key = DiskKey::new();
+        let _ = key
+            .bytes(layout.as_bytes())
+            .bytes(&source)
+            .u64(self.data.as_ref().map_or(0, DataDir::hash));
+        Some(key)
+    }
+
+    /// Finishes a key started by [`Engine::layout_disk_key`] for the page
+    /// cached under `cache_key`.
+    fn page_disk_key(
+        &self,
+        mut key: DiskKey,
+        cache_key: &PageKey,
+    ) -> String {
+        let _ = key
+            .bytes(format!("{:?}", cache_key.format).as_bytes())
+            .bytes(&[u8::from(cache_key.auto_escape)])
+            .u64(cache_key.settings_hash)
+            .u64(cache_key.context_hash)
+            .u64(cache_key.environment_hash);
+        key.finish()
+    }
+
+    /// Returns a hash of everything a page is rendered from: its layout
+    /// file, the settings and data of the engine, its context, and
+    /// `options`.
+    ///
+    /// Pages with the same input hash render the same, so a build can
+    /// skip those it wrote before, as described in the
+    /// [`journal`](crate::journal) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The rendering context.
+    /// * `layout` - The layout of the page.
+    /// * `options` - The options the page is rendered with.
+    ///
+    /// # Returns
+    ///
+    /// The hash in hexadecimal, or `None` if the layout cannot be read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::RenderOptions;
+    /// use staticweaver::loader::MemoryLoader;
+    /// use staticweaver::{Context, Engine};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let loader = MemoryLoader::new();
+    /// loader.insert("templates/page.html", "<h1>{{title}}</h1>");
+    /// let mut engine = Engine::new("templates", Duration::from_secs(60));
+    /// engine.set_loader(Arc::new(loader.clone()));
+    /// let options = RenderOptions::new();
+    ///
+    /// let mut context = Context::new();
+    /// context.set("title", "Home");
+    /// let hash = engine.page_input_hash(&context, "page", &options).unwrap();
+    ///
+    /// loader.insert("templates/page.html", "<h2>{{title}}</h2>");
+    /// assert_ne!(engine.page_input_hash(&context, "page", &options), Some(hash));
+    /// assert_eq!(engine.page_input_hash(&context, "missing", &options), None);
+    /// ```
+    #[must_use]
+    pub fn page_input_hash(
+        &self,
+        context: &Context,
+        layout: &str,
+        options: &RenderOptions,
+    ) -> Option<String> {
+        let key = self.layout_disk_key(layout)?;
+        let (_, cache_key) = self.page_key(context, layout, options);
+        Some(self.page_disk_key(key, &cache_key))
+    }
+
+    /// Loads the pages listed in `manifest`, the manifest of a previous
+    /// build, from the disk cache into the render cache, as described in
+    /// the [`warm`](crate::warm) module.
+    ///
+    /// Page files are read by up to
+    /// [`WARM_THREADS`](crate::warm::WARM_THREADS) threads. Pages
+    /// missing from the disk cache, or whose layout cannot be found, are
+    /// reported as missed and rendered by the build as usual, as are
+    /// entries without a context hash. Without a disk cache, every page
+    /// not already in the render cache is missed.
+    ///
+    /// # Arguments
+    ///
+    /// * `manifest` - The manifest of the previous build.
+    /// * `options` - The options the build renders pages with.
+    ///
+    /// # Returns
+    ///
+    /// The number of pages in the render cache and the paths of the
+    /// pages missed.
+    pub fn warm_cache(
+        &self,
+        manifest: &BuildManifest,
+        options: &RenderOptions,
+    ) -> WarmReport {
+        let mut report = WarmReport::default();
+        let mut layouts: FnvHashMap<&str, Option<DiskKey>> =
+            FnvHashMap::default();
+        let mut pending = Vec::new();
+        for entry in manifest.entries() {
+            let Some(context_hash) = entry.context_hash else {
+                report.missed.push(entry.path.clone());
+                continue;
+            };
+            let cache_key = PageKey::new(
+                self,
+                &entry.layout,
+                options,
+                context_hash,
+            );
+            if self.render_cache().contains_key(&cache_key) {
+                report.loaded += 1;
+                continue;
+            }
+            let layout_key = match &self.disk_cache {
+                Some(_) => layouts
+                    .entry(&entry.layout)
+                    .or_insert_with(|| {
+                        self.layout_disk_key(&entry.layout)
+                    })
+                    .clone(),
+                None => None,
+            };
+            match layout_key {
+                Some(key) => pending.push((
+                    &entry.path,
+                    cache_key,
+                    self.page_disk_key(key, &cache_key),
+                )),
+                None => report.missed.push(entry.path.clone()),
+            }
+        }
+        let Some(disk) = &self.disk_cache else {
+            return report;
+        };
+
+        let chunk_size = pending.len() / WARM_THREADS + 1;
+        let handles: Vec<_> = pending
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let disk = disk.clone();
+                let keys: Vec<String> = chunk
+                    .iter()
+                    .map(|(_, _, key)| key.clone())
+                    .collect();
+                thread::spawn(move || {
+                    keys.iter()
+                        .map(|key| disk.get(key))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for (chunk, handle) in pending.chunks(chunk_size).zip(handles) {
+            let pages = handle.join().unwrap_or_default();
+            for (index, (path, cache_key, _)) in
+                chunk.iter().enumerate()
+            {
+                match pages.get(index).cloned().flatten() {
+                    Some(page) => {
+                        let _ = self.cache_page(*cache_key, page);
+                        report.loaded += 1;
+                    }
+                    None => report.missed.push((*path).clone()),
+                }
+            }
+        }
+        report
+    }
+
+    /// Renders a page like [`Engine::render_page`], recording the time
+    /// spent loading its layout and resolving each tag, filter, function
+    /// call, and shortcode in a [`RenderProfile`].
+    ///
+    /// The page is always rendered, bypassing the render cache, so that
+    /// the profile reflects the work of a cache miss; the cache is left
+    /// unchanged. Unlike other renders, profiling borrows the engine
+    /// mutably, so that no concurrent render adds steps to the profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The rendering context.
+    /// * `layout` - The layout file to use for rendering.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Engine::render_page`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use staticweaver::engine::Engine;
+    /// use staticweaver::Context;
+    /// use std::time::Duration;
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let (page, profile) =
+    ///     engine.render_page_profiled(&Context::new(), "index").unwrap();
+    /// println!("{}", profile);
+    /// ```
+    pub fn render_page_profiled(
+        &mut self,
+        context: &Context,
+        layout: &str,
+    ) -> Result<(String, RenderProfile), EngineError> {
+        let start = Instant::now();
+        self.profiler = Some(Profiler::new(RenderProfile::new(
+            ProfileKind::Page,
+            layout,
+        )));
+        let options = RenderOptions::new();
+        let (context, _) = self.page_key(context, layout, &options);
+        let rendered = self.render_uncached(&context, layout, &options);
+        let profile = self
+            .profiler
+            .take()
+            .map(|profiler| profiler.finish(start.elapsed()));
+        let rendered = rendered
+            .map_err(|err| self.remember_missing(layout, err))?;
+        Ok((
+            rendered.to_string(),
+            profile.unwrap_or_else(|| {
+                RenderProfile::new(ProfileKind::Page, layout)
+            }),
+        ))
+    }
+
+    /// Returns the current time of the engine, fixed when it is
+    /// deterministic.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::determinism::BuildTime;
+    /// use staticweaver::engine::Engine;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let time = UNIX_EPOCH + Duration::from_secs(60);
+    /// engine.build_time = BuildTime::Fixed(time);
+    /// assert_eq!(engine.now(), time);
+    /// ```
+    #[must_use]
+    pub fn now(&self) -> SystemTime {
+        self.build_time.now(self.deterministic)
+    }
+
+    /// Runs `step`, recording it as a `kind` step named `name` if a
+    /// render is being profiled.
+    pub(crate) fn profile<T>(
+        &self,
+        kind: ProfileKind,
+        name: &str,
+        step: impl FnOnce() -> T,
+    ) -> T {
+        match &self.profiler {
+            Some(profiler) => profiler.record(kind, name, step),
+            None => step(),
+        }
+    }
+
+    /// Layers the theme defaults under `context` and computes the render
+    /// cache key of the page.
+    pub(crate) fn page_key<'a>(
+        &self,
+        context: &'a Context,
+        layout: &str,
+        options: &RenderOptions,
+    ) -> (Cow<'a, Context>, PageKey) {
+        let context = self.themed(context);
+        let context_hash =
+            context.hash_ignoring(&self.cache_ignored_keys);
+        let cache_key =
+            PageKey::new(self, layout, options, context_hash);
+        (context, cache_key)
+    }
+
+    /// Returns the hash of `context` in render cache keys, with the
+    /// theme defaults layered under it and the
+    /// [ignored keys](Engine::cache_ignored_keys) left out.
+    pub(crate) fn context_hash(&self, context: &Context) -> u64 {
+        self.themed(context).hash_ignoring(&self.cache_ignored_keys)
+    }
+
+    /// Layers the theme defaults, if any, under `context`.
+    fn themed<'a>(&self, context: &'a Context) -> Cow<'a, Context> {
+        match &self.theme {
+            Some(theme) if !theme.defaults().is_empty() => {
+                Cow::Owned(theme.layer(context))
+            }
+            _ => Cow::Borrowed(context),
+        }
+    }
+
+    /// Reads and renders a layout without consulting the render cache.
+    pub(crate) fn render_uncached(
+        &self,
+        context: &Context,
+        layout: &str,
+        options: &RenderOptions,
+    ) -> Result<Arc<str>, EngineError> {
+        let settings = Settings::of(self).with(options);
+        let known_missing = match (
+            &*self
+                .missing_layouts
+                .read()
+                .unwrap_or_else(PoisonError::into_inner),
+            self.layouts.get(layout),
+        ) {
+            (Some(missing), Some(symbol)) => {
+                missing.contains_key(&symbol)
+            }
+            _ => false,
+        };
+        let (template_path, template_content) =
+            self.profile(ProfileKind::Load, layout, || {
+                if known_missing {
+                    Err(RenderErrorContext::wrap(
+                        layout,
+                        None,
+                        RenderPhase::Load,
+                        self.layout_not_found(layout),
+                    ))
+                } else {
+                    self.read_layout(layout)
+                }
+            })?;
+
+        // Render the template with escaping suited to its file type
+        let format = settings.output_format.unwrap_or_else(|| {
+            let syntax = self
+                .syntax_of(&template_content, &settings)
+                .map_or(self.syntax, |(syntax, _)| syntax);
+            self.format_for(&template_path, syntax, &settings)
+        });
+        let rendered = self
+            .profile(ProfileKind::Template, layout, || {
+                self.render_to_string(
+                    &template_content,
+                    context,
+                    format,
+                    true,
+                    &settings,
+                )
+                .map_err(RenderReport::into_first_error)
+            })
+            .map_err(|err| {
+                let phase = match err {
+                    EngineError::InvalidTemplate(_) => {
+                        RenderPhase::Parse
+                    }
+                    _ => RenderPhase::Render,
+                };
+                RenderErrorContext::wrap(
+                    layout,
+                    Some(&template_path),
+                    phase,
+                    err,
+                )
+            })?;
+        Ok(Arc::from(rendered))
+    }
+
+    /// Resolves `layout` and reads its file, returning the path and the
+    /// decoded template.
+    pub(crate) fn read_layout(
+        &self,
+        layout: &str,
+    ) -> Result<(PathBuf, String), EngineError> {
+        let resolved = self.resolve_layout(layout).map_err(|err| {
+            RenderErrorContext::wrap(
+                layout,
+                None,
+                RenderPhase::Load,
+                err,
+            )
+        })?;
+
+        // Attempt to read the layout template from the file system
+        let template_path = resolved.ok_or_else(|| {
+            RenderErrorContext::wrap(
+                layout,
+                None,
+                RenderPhase::Load,
+                self.layout_not_found(layout),
+            )
+        })?;
+        let template_content = self
+            .loader
+            .read(&template_path)
+            .map_err(EngineError::from)
+            .and_then(|bytes| {
+                decode(
+                    bytes,
+                    &template_path,
+                    self.fallback_encoding.as_deref(),
+                )
+            })
+            .map_err(|err| {
+                RenderErrorContext::wrap(
+                    layout,
+                    Some(&template_path),
+                    RenderPhase::Load,
+                    err,
+                )
+            })?;
+        Ok((template_path, template_content))
+    }
+
+    /// Builds the error returned when `layout` cannot be resolved.
+    fn layout_not_found(&self, layout: &str) -> EngineError {
+        EngineError::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!(
+                "Template '{}' not found in: {}",
+                layout,
+                self.search_paths()
+                    .iter()
+                    .map(|dir| dir.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        ))
+    }
+
+    /// Records `layout` as missing if `err` says it was not found and
+    /// negative caching is enabled, then returns `err`.
+    pub(crate) fn remember_missing(
+        &self,
+        layout: &str,
+        err: EngineError,
+    ) -> EngineError {
+        if let (Some(missing), EngineError::Io(io_err)) = (
+            &mut *self
+                .missing_layouts
+                .write()
+                .unwrap_or_else(PoisonError::into_inner),
+            err.root(),
+        ) {
+            if io_err.kind() == std::io::ErrorKind::NotFound {
+                let _ = missing.insert(self.layouts.intern(layout), ());
+            }
+        }
+        err
+    }
+
+    /// Enables or disables negative caching of missing layouts.
+    ///
+    /// When enabled, a layout that could not be found is reported as
+    /// missing without touching the file system again until `ttl` has
+    /// elapsed. Keep the TTL short so that newly created layouts are
+    /// picked up quickly. Clearing the cache, changing the theme, adding a
+    /// template directory, or changing the default extension or the
+    /// layout case forgets all missing layouts. Negative caching is disabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - How long a layout is remembered as missing, or `None` to
+    ///   disable negative caching.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ttl` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use std::time::Duration;
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// engine.set_not_found_ttl(Some(Duration::from_secs(2)));
+    /// ```
+    pub fn set_not_found_ttl(&mut self, ttl: Option<Duration>) {
+        let clock = Arc::clone(self.render_cache_mut().clock());
+        *self.missing_layouts_mut() = ttl.map(|ttl| {
+            let mut missing = Cache::new(ttl);
+            missing.set_sweep_interval(Some(ttl));
+            missing.set_clock(clock);
+            missing
+        });
+    }
+
+    /// Sets the clock read by the render cache and the negative cache
+    /// of missing layouts, such as a
+    /// [`ManualClock`](crate::cache::ManualClock) in tests.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - The clock.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        if let Some(missing) = self.missing_layouts_mut() {
+            missing.set_clock(Arc::clone(&clock));
+        }
+        self.render_cache_mut().set_clock(clock);
+    }
+
+    /// Sets the loader through which layout files are found and read,
+    /// as described in the [`loader`](crate::loader) module.
+    ///
+    /// Clears the render cache and forgets all missing layouts.
+    ///
+    /// # Arguments
+    ///
+    /// * `loader` - The loader.
+    pub fn set_loader(&mut self, loader: Arc<dyn Loader>) {
+        self.loader = loader;
+        self.clear_cache();
+    }
+
+    /// Forgets every layout remembered as missing.
+    fn forget_missing(&mut self) {
+        if let Some(missing) = self.missing_layouts_mut() {
+            missing.clear();
+        }
+    }
+
+    /// Returns the negative cache of missing layouts, if enabled.
+    fn missing_layouts_mut(
+        &mut self,
+    ) -> &mut Option<Cache<Symbol, ()>> {
+        self.missing_layouts
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Appends a template directory to the search path.
+    ///
+    /// Directories are searched in order after `template_path`, so a
+    /// site directory set as `template_path` overrides layouts provided
+    /// by a shared directory added here. The active theme, if any, is
+    /// always searched last.
+    ///
+    /// Clears the render cache and forgets all missing layouts, since
+    /// layouts may now resolve to files in `dir`.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The template directory to add.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use std::path::Path;
+    /// use std::time::Duration;
+    ///
+    /// let mut engine = Engine::new("site", Duration::from_secs(3600));
+    /// engine.add_template_dir("shared");
+    /// assert_eq!(
+    ///     engine.search_paths(),
+    ///     vec![Path::new("site"), Path::new("shared")]
+    /// );
+    /// ```
+    pub fn add_template_dir(&mut self, dir: &str) {
+        self.template_dirs.push(dir.to_string());
+        self.clear_cache();
+    }
+
+    /// Returns the template directories in the order they are searched.
+    ///
+    /// # Returns
+    ///
+    /// `template_path` followed by the directories added with
+    /// [`Engine::add_template_dir`] and the active theme's template
+    /// directory.
+    #[must_use]
+    pub fn search_paths(&self) -> Vec<&Path> {
+        std::iter::once(Path::new(&self.template_path))
+            .chain(self.template_dirs.iter().map(Path::new))
+            .chain(self.theme.as_ref().map(Theme::templates_dir))
+            .collect()
+    }
+
+    /// Activates a data directory, replacing any previously active one.
+    ///
+    /// Its values are readable from every template as `{{data.*}}`, as
+    /// described in the [`data`](crate::data) module. Context values take
+    /// precedence over the data directory. The render cache is cleared
+    /// because previously rendered pages may no longer match.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data directory to activate.
+    ///
+    /// # Returns
+    ///
+    /// The previously active data directory, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::data::DataDir;
+    /// use staticweaver::{Context, Engine};
+    /// use std::time::Duration;
+    ///
+    /// let dir = tempfile::tempdir().unwrap();
+    /// std::fs::write(dir.path().join("site.json"), r#"{"title": "Blog"}"#)
+    ///     .unwrap();
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let _ = engine.set_data(DataDir::from_dir(dir.path()).unwrap());
+    /// let page = engine
+    ///     .render_template("{{data.site.title}}", &Context::new())
+    ///     .unwrap();
+    /// assert_eq!(page, "Blog");
+    /// ```
+    pub fn set_data(&mut self, data: DataDir) -> Option<DataDir> {
+        self.clear_cache();
+        self.data.replace(data)
+    }
+
+    /// Deactivates the current data directory.
+    ///
+    /// # Returns
+    ///
+    /// The previously active data directory, if any.
+    pub fn clear_data(&mut self) -> Option<DataDir> {
+        self.clear_cache();
+        self.data.take()
+    }
+
+    /// Returns the active data directory, if any.
+    #[must_use]
+    pub fn data(&self) -> Option<&DataDir> {
+        self.data.as_ref()
+    }
+
+    /// Reloads the active data directory if one of its files changed,
+    /// clearing the render cache when it does.
+    ///
+    /// # Returns
+    ///
+    /// Whether the data directory was reloaded; `false` if there is none.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`DataDir::from_dir`]. On error, the
+    /// previously loaded values stay active.
+    pub fn refresh_data(&mut self) -> Result<bool, EngineError> {
+        let Some(data) = &mut self.data else {
+            return Ok(false);
+        };
+        let reloaded = data.refresh()?;
+        if reloaded {
+            self.clear_cache();
+        }
+        Ok(reloaded)
+    }
+
+    /// Allows templates to read the process environment variables in
+    /// `vars` with the `env()` function, as described in the
+    /// [`environment`](crate::environment) module.
+    ///
+    /// The function is only registered once this is called; the render
+    /// cache is cleared because the variables readable by pages changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `vars` - The variables templates may read.
+    pub fn set_env_vars(&mut self, vars: EnvVars) {
+        environment::register(&mut self.functions);
+        self.env_vars = vars;
+        self.clear_cache();
+    }
+
+    /// Returns the process environment variables templates may read.
+    #[must_use]
+    pub fn env_vars(&self) -> &EnvVars {
+        &self.env_vars
+    }
+
+    /// Returns a hash of the environment and of the variables readable
+    /// by pages, used in render cache keys.
+    fn environment_hash(&self) -> u64 {
+        let mut hasher = FnvHasher::default();
+        hasher.write_u64(self.environment.hash());
+        hasher.write_u64(self.env_vars.hash());
+        hasher.finish()
+    }
+
+    /// Stores a rendered page in the render cache, unless it contains the
+    /// value of a secret environment variable.
+    ///
+    /// Returns the page cached under `key`, which is the one another
+    /// thread stored first if it rendered the same page concurrently.
+    pub(crate) fn cache_page(
+        &self,
+        key: PageKey,
+        page: Arc<str>,
+    ) -> Arc<str> {
+        if self.env_vars.reveals_secret(&page) {
+            return page;
+        }
+        self.write_cache().get_or_insert_with(key, || page)
+    }
+
+    /// Replaces the values of secret environment variables, and of the
+    /// [sensitive](Context::mark_sensitive) keys of `context`, in the
+    /// messages of `err`.
+    fn redact_error(
+        &self,
+        err: EngineError,
+        context: &Context,
+    ) -> EngineError {
+        let message = err.to_string();
+        if !self.env_vars.reveals_secret(&message)
+            && matches!(context.redact(&message), Cow::Borrowed(_))
+        {
+            return err;
+        }
+        let redact = |message: &str| {
+            context.redact(&self.env_vars.redact(message)).into_owned()
+        };
+        match err {
+            EngineError::Page(mut page) => {
+                page.source = self.redact_error(page.source, context);
+                EngineError::Page(page)
+            }
+            EngineError::InvalidTemplate(message) => {
+                EngineError::InvalidTemplate(redact(&message))
+            }
+            EngineError::Render(message) => {
+                EngineError::Render(redact(&message))
+            }
+            _ => EngineError::Render(redact(&message)),
+        }
+    }
+
+    /// Sets the output directory whose assets the `sri()` function
+    /// hashes, as described in the [`asset`](crate::asset) module,
+    /// clearing the render cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory the assets of the site are copied to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::{Context, Engine};
+    /// use std::time::Duration;
+    ///
+    /// let out = tempfile::tempdir()?;
+    /// std::fs::write(out.path().join("app.js"), "run();")?;
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// engine.set_asset_dir(out.path());
+    /// let tag = engine.render_template(
+    ///     r#"<script src="/app.js" integrity="{{ sri('app.js') }}"></script>"#,
+    ///     &Context::new(),
+    /// )?;
+    /// assert!(tag.contains(r#"integrity="sha384-"#));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_asset_dir<P: AsRef<Path>>(&mut self, dir: P) {
+        self.clear_cache();
+        self.assets = Some(AssetHashes::new(dir));
+    }
+
+    /// Returns the hashes of the output assets, if an asset directory is
+    /// set.
+    #[must_use]
+    pub fn asset_hashes(&self) -> Option<&AssetHashes> {
+        self.assets.as_ref()
+    }
+
+    /// Forgets the hashes of output assets that changed since they were
+    /// computed, clearing the render cache when any did, so that pages
+    /// are not served with stale integrity values.
+    ///
+    /// # Returns
+    ///
+    /// Whether any asset changed; `false` if no asset directory is set.
+    pub fn refresh_assets(&mut self) -> bool {
+        let refreshed =
+            self.assets.as_ref().map_or(false, AssetHashes::refresh);
+        if refreshed {
+            self.clear_cache();
+        }
+        refreshed
+    }
+
+    /// Sets the disk cache read when a page misses the in-memory render
+    /// cache, as described in the [`disk_cache`](crate::disk_cache)
+    /// module, or removes it with `None`.
+    ///
+    /// Pages already stored in the directory are kept, so that a new
+    /// build of the site can reuse the pages of the previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `disk_cache` - The disk cache.
+    pub fn set_disk_cache(&mut self, disk_cache: Option<DiskCache>) {
+        self.disk_cache = disk_cache;
+    }
+
+    /// Returns the disk cache set with [`Engine::set_disk_cache`].
+    #[must_use]
+    pub fn disk_cache(&self) -> Option<&DiskCache> {
+        self.disk_cache.as_ref()
+    }
+
+    /// Activates a theme, replacing any previously active one.
+    ///
+    /// The theme's templates are searched after the engine's own
+    /// directories and its default values sit under the context passed
+    /// to [`Engine::render_page`]. The render cache is cleared because
+    /// previously rendered pages may no longer match.
+    ///
+    /// # Arguments
+    ///
+    /// * `theme` - The theme to activate.
+    ///
+    /// # Returns
+    ///
+    /// The previously active theme, if any.
+    pub fn set_theme(&mut self, theme: Theme) -> Option<Theme> {
+        self.clear_cache();
+        self.theme.replace(theme)
+    }
+
+    /// Writes the theme directory `dir` as a template package to `out`,
+    /// as described in the [`package`](crate::package) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The root directory of the theme.
+    /// * `out` - The path of the package file, usually ending in
+    ///   `.swpkg`.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error of [`package::write`], or an I/O error if `out` cannot
+    /// be written.
+    #[cfg(feature = "archive")]
+    pub fn save_package<P: AsRef<Path>, Q: AsRef<Path>>(
+        dir: P,
+        out: Q,
+    ) -> Result<package::PackageInfo, EngineError> {
+        let mut bytes = Vec::new();
+        let info = package::write(dir.as_ref(), &mut bytes)?;
+        fs::write(out.as_ref(), bytes)?;
+        Ok(info)
+    }
+
+    /// Loads a template package from a path or URL and activates it as
+    /// the theme, as described in the [`package`](crate::package)
+    /// module.
+    ///
+    /// Downloaded packages are also verified against the engine's
+    /// `integrity`, whose manifest lists the package by its file name.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The path or URL of the package.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the package cannot be read or downloaded, or
+    /// any error of [`package::read`].
+    #[cfg(feature = "archive")]
+    pub fn load_package(
+        &mut self,
+        source: &str,
+    ) -> Result<package::PackageInfo, EngineError> {
+        let (info, theme) = if is_url(source) {
+            let bytes = self.fetcher.fetch_bytes(source)?;
+            if let Some(integrity) = &self.integrity {
+                let (base, file) =
+                    source.rsplit_once('/').unwrap_or(("", source));
+                integrity
+                    .manifest(base, &*self.fetcher)?
+                    .verify(file, &bytes)?;
+            }
+            package::read(bytes.as_slice())?
+        } else {
+            package::read(fs::File::open(source)?)?
+        };
+        let _ = self.set_theme(theme);
+        Ok(info)
+    }
+
+    /// Deactivates the current theme.
+    ///
+    /// # Returns
+    ///
+    /// The previously active theme, if any.
+    pub fn clear_theme(&mut self) -> Option<Theme> {
+        self.clear_cache();
+        self.theme.take()
+    }
+
+    /// Returns the active theme, if any.
+    #[must_use]
+    pub fn theme(&self) -> Option<&Theme> {
+        self.theme.as_ref()
+    }
+
+    /// Resolves a layout name to the first matching file on the search
+    /// path.
+    ///
+    /// A layout that carries its own extension, such as `"feed.xml"`, is
+    /// first looked up as an explicit file name. Otherwise, or if no such
+    /// file exists, the [default extension](Engine::default_extension)
+    /// is appended. An alias set with [`Engine::add_alias`] resolves to
+    /// the file of its target.
+    ///
+    /// # Arguments
+    ///
+    /// * `layout` - The layout name, with or without extension.
+    ///
+    /// # Returns
+    ///
+    /// The path of the first existing layout file, or `None` if no
+    /// template directory contains it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// assert!(engine.resolve_template("missing").is_none());
+    /// assert!(engine.resolve_template("../templates/missing").is_none());
+    /// ```
+    #[must_use]
+    pub fn resolve_template(&self, layout: &str) -> Option<PathBuf> {
+        self.resolve_layout(layout).ok().flatten()
+    }
+
+    /// Resolves a layout name, or the target of its alias, as
+    /// [`Engine::resolve_template`] does, rejecting names and files
+    /// outside the template directories, and names that differ from
+    /// their file by case when
+    /// [`strict_layout_case`](Engine::strict_layout_case) is set, as
+    /// described in the [`loader`](crate::loader) module.
+    fn resolve_layout(
+        &self,
+        layout: &str,
+    ) -> Result<Option<PathBuf>, EngineError> {
+        let layout = self.alias_target(layout);
+        validate_layout_name(layout)?;
+        let default_name =
+            format!("{}.{}", layout, self.default_extension);
+        let explicit = Path::new(layout).extension().map(|_| layout);
+        let found = explicit
+            .into_iter()
+            .chain(std::iter::once(default_name.as_str()))
+            .flat_map(|file_name| {
+                self.search_paths()
+                    .into_iter()
+                    .map(move |dir| (dir, dir.join(file_name)))
+            })
+            .find(|(_, path)| self.loader.exists(path));
+        match found {
+            Some((dir, path)) if !self.loader.contains(dir, &path) => {
+                Err(EngineError::InvalidTemplateName {
+                    name: layout.to_string(),
+                    message: format!(
+                        "{} resolves outside {}",
+                        path.display(),
+                        dir.display()
+                    ),
+                })
+            }
+            Some((dir, path)) if self.strict_layout_case => {
+                match self.loader.stored_path(dir, &path) {
+                    Some(stored) if stored != path => {
+                        Err(EngineError::InvalidTemplateName {
+                            name: layout.to_string(),
+                            message: format!(
+                                "the file is stored as {}, which differs \
+                                 in case",
+                                stored.display()
+                            ),
+                        })
+                    }
+                    _ => Ok(Some(path)),
+                }
+            }
+            found => Ok(found.map(|(_, path)| path)),
+        }
+    }
+
+    /// Returns the names of the layouts found in the template
+    /// directories, sorted and without duplicates, e.g. to suggest
+    /// layouts when one is missing.
+    ///
+    /// Files with the default extension are listed without it, as they
+    /// are rendered; other files are listed with their extension. Only
+    /// loaders that can list their files contribute layouts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::loader::MemoryLoader;
+    /// use staticweaver::Engine;
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let loader = MemoryLoader::new();
+    /// loader.insert("templates/page.html", "");
+    /// loader.insert("templates/blog/post.html", "");
+    /// loader.insert("templates/feed.xml", "");
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(60));
+    /// engine.set_loader(Arc::new(loader));
+    /// assert_eq!(engine.available_layouts(), ["blog/post", "feed.xml", "page"]);
+    /// ```
+    #[must_use]
+    pub fn available_layouts(&self) -> Vec<String> {
+        let suffix = format!(".{}", self.default_extension);
+        let mut layouts: Vec<String> = self
+            .search_paths()
+            .into_iter()
+            .flat_map(|dir| self.loader.files(dir).unwrap_or_default())
+            .map(|file| {
+                let name = file
+                    .components()
+                    .map(|part| part.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                match name.strip_suffix(&suffix) {
+                    Some(layout) => layout.to_string(),
+                    None => name,
+                }
+            })
+            .collect();
+        layouts.sort();
+        layouts.dedup();
+        layouts
+    }
+
+    /// Compiles every layout of [`Engine::available_layouts`] into Rust
+    /// source, as described in the [`codegen`](crate::codegen) module.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a layout cannot be read or parsed, uses a
+    /// tag that needs an engine to render, or compiles to the same names
+    /// as another.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::loader::MemoryLoader;
+    /// use staticweaver::Engine;
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let loader = MemoryLoader::new();
+    /// loader.insert("templates/page.html", "<h1>{{title}}</h1>");
+    /// let mut engine = Engine::new("templates", Duration::from_secs(60));
+    /// engine.set_loader(Arc::new(loader));
+    ///
+    /// let source = engine.compile_templates().unwrap();
+    /// assert!(source.contains("pub fn page(context: &Page<'_>) -> String"));
+    /// ```
+    pub fn compile_templates(&self) -> Result<String, EngineError> {
+        let layouts = self.available_layouts();
+        let layouts: Vec<&str> =
+            layouts.iter().map(String::as_str).collect();
+        codegen::compile(self, &layouts)
+    }
+
+    /// Compiles `layouts` into Rust source, like
+    /// [`Engine::compile_templates`].
+    ///
+    /// # Arguments
+    ///
+    /// * `layouts` - The layouts to compile, such as those that call no
+    ///   function.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Engine::compile_templates`].
+    pub fn compile_layouts(
+        &self,
+        layouts: &[&str],
+    ) -> Result<String, EngineError> {
+        codegen::compile(self, layouts)
+    }
+
+    /// Returns the layouts of [`Engine::available_layouts`], with the
+    /// file each resolves to and the keys and functions it reads, read
+    /// through the engine's loader.
+    ///
+    /// Layouts that cannot be read or decoded are left out; tags that
+    /// cannot be parsed are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::loader::MemoryLoader;
+    /// use staticweaver::Engine;
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let loader = MemoryLoader::new();
+    /// loader.insert("templates/page.html", "<h1>{{ title | upper }}</h1>{{ csp_nonce() }}");
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(60));
+    /// engine.set_loader(Arc::new(loader));
+    /// let templates = engine.list_templates();
+    /// assert_eq!(templates[0].name, "page");
+    /// assert_eq!(templates[0].keys, ["title"]);
+    /// assert_eq!(templates[0].functions, ["csp_nonce"]);
+    /// ```
+    #[must_use]
+    pub fn list_templates(&self) -> Vec<TemplateMeta> {
+        let settings = Settings::of(self);
+        self.available_layouts()
+            .into_iter()
+            .filter_map(|name| {
+                let path = self.resolve_template(&name)?;
+                let bytes = self.loader.read(&path).ok()?;
+                let size = bytes.len() as u64;
+                let source = decode(
+                    bytes,
+                    &path,
+                    self.fallback_encoding.as_deref(),
+                )
+                .ok()?;
+                let (syntax, start) =
+                    self.syntax_of(&source, &settings).ok()?;
+                let mut keys = Vec::new();
+                let mut functions = Vec::new();
+                let tags = Parser::new(
+                    &source[start..],
+                    settings.open,
+                    settings.close,
+                )
+                .filter_map(|token| {
+                    match token.ok()?.segment {
+                        Segment::Tag(tag) => Some(tag.trim()),
+                        Segment::Text(_) => None,
+                    }
+                });
+                for tag in tags {
+                    if self.blocks.is_block_tag(tag) {
+                        continue;
+                    }
+                    match split_call(tag) {
+                        Some((function, _)) => {
+                            functions.push(function.to_string());
+                        }
+                        None => keys.push(
+                            tag.split('|')
+                                .next()
+                                .unwrap_or_default()
+                                .trim()
+                                .to_string(),
+                        ),
+                    }
+                }
+                keys.sort();
+                keys.dedup();
+                functions.sort();
+                functions.dedup();
+                Some(TemplateMeta {
+                    name,
+                    modified: self.loader.modified(&path),
+                    path,
+                    size,
+                    syntax,
+                    keys,
+                    functions,
+                })
+            })
+            .collect()
+    }
+
+    /// Sets the extension appended to layout names without one.
+    ///
+    /// Clears the render cache and forgets all missing layouts, since
+    /// layouts may now resolve to other files.
+    ///
+    /// # Arguments
+    ///
+    /// * `extension` - The extension, with or without a leading dot.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use std::time::Duration;
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// engine.set_default_extension(".htm");
+    /// assert_eq!(engine.default_extension(), "htm");
+    /// ```
+    pub fn set_default_extension(&mut self, extension: &str) {
+        self.default_extension =
+            extension.trim_start_matches('.').to_string();
+        self.clear_cache();
+    }
+
+    /// Returns the extension appended to layout names without one,
+    /// without the leading dot.
+    #[must_use]
+    pub fn default_extension(&self) -> &str {
+        &self.default_extension
+    }
+
+    /// Sets whether layout names must match the case of their files
+    /// exactly, as described in the [`loader`](crate::loader) module.
+    ///
+    /// Clears the render cache and forgets all missing layouts, since
+    /// layouts may now resolve differently.
+    ///
+    /// # Arguments
+    ///
+    /// * `strict` - Whether layout names are matched by case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use std::time::Duration;
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// engine.set_strict_layout_case(true);
+    /// assert!(engine.strict_layout_case());
+    /// ```
+    pub fn set_strict_layout_case(&mut self, strict: bool) {
+        self.strict_layout_case = strict;
+        self.clear_cache();
+    }
+
+    /// Returns whether layout names must match the case of their files
+    /// exactly.
+    #[must_use]
+    pub fn strict_layout_case(&self) -> bool {
+        self.strict_layout_case
+    }
+
+    /// Returns the output format used when rendering the template at
+    /// `path`.
+    ///
+    /// The engine-wide `output_format` wins if set. Otherwise the format
+    /// is inferred from the extension, with HTML templates resolving to
+    /// `OutputFormat::Plain` unless `auto_escape` is enabled or the
+    /// engine's `syntax` escapes HTML.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The template path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use staticweaver::escape::OutputFormat;
+    /// use std::path::Path;
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// assert_eq!(
+    ///     engine.output_format_for(Path::new("feed.xml")),
+    ///     OutputFormat::Xml
+    /// );
+    /// ```
+    #[must_use]
+    pub fn output_format_for(&self, path: &Path) -> OutputFormat {
+        self.format_for(path, self.syntax, &Settings::of(self))
+    }
+
+    /// Returns the output format used when rendering `template`, the
+    /// contents of the file at `path`, like [`Engine::output_format_for`]
+    /// but with the syntax version declared by the template's pragma.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The template path.
+    /// * `template` - The template.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use staticweaver::escape::OutputFormat;
+    /// use std::path::Path;
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let path = Path::new("page.html");
+    /// assert_eq!(
+    ///     engine.output_format_for_template(path, "{{#syntax 2}}\n{{body}}"),
+    ///     OutputFormat::Html
+    /// );
+    /// ```
+    #[must_use]
+    pub fn output_format_for_template(
+        &self,
+        path: &Path,
+        template: &str,
+    ) -> OutputFormat {
+        let settings = Settings::of(self);
+        let syntax = self
+            .syntax_of(template, &settings)
+            .map_or(self.syntax, |(syntax, _)| syntax);
+        self.format_for(path, syntax, &settings)
+    }
+
+    /// Returns the output format of the template at `path` read with
+    /// `syntax` and `settings`.
+    fn format_for(
+        &self,
+        path: &Path,
+        syntax: SyntaxVersion,
+        settings: &Settings<'_>,
+    ) -> OutputFormat {
+        if let Some(format) = settings.output_format {
+            return format;
+        }
+        match OutputFormat::from_path(path) {
+            OutputFormat::Html
+                if !settings.auto_escape && !syntax.escapes_html() =>
+            {
+                OutputFormat::Plain
+            }
+            format => format,
+        }
+    }
+
+    /// Returns the syntax version of `template` and the length of its
+    /// pragma, or the engine's version and zero if it has none.
+    fn syntax_of(
+        &self,
+        template: &str,
+        settings: &Settings<'_>,
+    ) -> Result<(SyntaxVersion, usize), EngineError> {
+        Ok(syntax::pragma(template, settings.open, settings.close)?
+            .unwrap_or((self.syntax, 0)))
+    }
+
+    /// Rewrites a template string rendered with
+    /// [`Engine::render_template`] to the latest syntax version, as
+    /// described in the [`syntax`](crate::syntax) module.
+    ///
+    /// The migrated template starts with a pragma naming the latest
+    /// version, and renders the same output as `source`. Templates
+    /// already at the latest version, or with an invalid pragma, are
+    /// returned unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The template.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// assert_eq!(
+    ///     engine.migrate_template("Hello, {{name}}!"),
+    ///     "{{#syntax 2}}\nHello, {{name}}!"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn migrate_template(&self, source: &str) -> String {
+        let format = self.output_format.unwrap_or_default();
+        syntax::migrate(self, source, format, format)
+    }
+
+    /// Rewrites the layout file at `path`, whose contents are `source`,
+    /// to the latest syntax version, like [`Engine::migrate_template`].
+    ///
+    /// Values that the layout's current version inserts verbatim and the
+    /// latest version would escape are marked with the `safe` filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The contents of the layout.
+    /// * `path` - The path of the layout, whose extension sets its
+    ///   output format.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use std::path::Path;
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// assert_eq!(
+    ///     engine.migrate_layout("<p>{{body}}</p>", Path::new("post.html")),
+    ///     "{{#syntax 2}}\n<p>{{body | safe}}</p>"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn migrate_layout(&self, source: &str, path: &Path) -> String {
+        let settings = Settings::of(self);
+        let syntax = self
+            .syntax_of(source, &settings)
+            .map_or(self.syntax, |(syntax, _)| syntax);
+        syntax::migrate(
+            self,
+            source,
+            self.format_for(path, syntax, &settings),
+            self.format_for(path, SyntaxVersion::LATEST, &settings),
+        )
+    }
+
+    /// Renders a page with `options` merged over `context`.
+    ///
+    /// Keys present in both take their value from `options`, so page-level
+    /// settings override shared site-wide values.
+    ///
+    /// Unlike [`Engine::render_page_with`], whose options change how the
+    /// page is rendered, `options` only provides values to render.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Page-specific values that take precedence.
+    /// * `context` - The base rendering context.
+    /// * `layout` - The layout file to use for rendering.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Engine::render_page`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::{Context, Engine, PageOptions};
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let mut options = PageOptions::new();
+    /// options.set("title", "Home");
+    /// let result =
+    ///     engine.render_page_merged(&options, &Context::new(), "default");
+    /// ```
+    pub fn render_page_merged(
+        &self,
+        options: &PageOptions,
+        context: &Context,
+        layout: &str,
+    ) -> Result<String, EngineError> {
+        let mut merged = context.clone();
+        merged.extend(
+            options
+                .elements
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone())),
+        );
+        self.render_page(&merged, layout)
+    }
+
+    /// Reports what [`Engine::render_page`] would do for a page, without
+    /// rendering it.
+    ///
+    /// Nothing is read or written apart from checking which layout file
+    /// exists, and the render cache is left unchanged, so plans can be
+    /// used to verify incremental builds.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The rendering context.
+    /// * `layout` - The layout file to use for rendering.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::{Context, Engine};
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let plan = engine.plan_page(&Context::new(), "missing");
+    /// assert!(plan.template_path.is_none());
+    /// assert!(!plan.cached);
+    /// ```
+    #[must_use]
+    pub fn plan_page(
+        &self,
+        context: &Context,
+        layout: &str,
+    ) -> RenderPlan {
+        let (_, cache_key) =
+            self.page_key(context, layout, &RenderOptions::new());
+        let template_path = self.resolve_template(layout);
+        let format = template_path
+            .as_deref()
+            .map(|path| self.output_format_for(path));
+        RenderPlan {
+            layout: layout.to_string(),
+            cached: self.render_cache().contains_key(&cache_key),
+            template_path,
+            format,
+            cache_key,
+        }
+    }
+
+    /// Renders a page with the first of `layouts` that exists.
+    ///
+    /// Layouts whose files cannot be found are skipped; any other error
+    /// stops the chain. If the page still cannot be rendered and
+    /// `error_layout` is set, that layout is rendered instead, with the
+    /// following values added to `context`:
+    ///
+    /// - `error`: the error message.
+    /// - `error_code`: the stable code of the error.
+    /// - `layout`: the layout that failed, or the last one tried.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The rendering context.
+    /// * `layouts` - The layouts to try, in order of preference.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the last layout tried, if neither it nor the
+    /// error layout could be rendered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::{Context, Engine};
+    /// use std::time::Duration;
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// engine.error_layout = Some("error".to_string());
+    /// let result = engine.render_page_with_fallback(
+    ///     &Context::new(),
+    ///     &["post", "page", "default"],
+    /// );
+    /// ```
+    pub fn render_page_with_fallback(
+        &self,
+        context: &Context,
+        layouts: &[&str],
+    ) -> Result<String, EngineError> {
+        let mut failure = None;
+        for layout in layouts {
+            match self.render_page(context, layout) {
+                Ok(page) => return Ok(page),
+                Err(err) => {
+                    let missing = is_missing_layout(&err);
+                    failure = Some((*layout, err));
+                    if !missing {
+                        break;
+                    }
+                }
+            }
+        }
+        let Some((layout, err)) = failure else {
+            return Err(EngineError::Render(
+                "No layouts to render".to_string(),
+            ));
+        };
+
+        let Some(error_layout) = self.error_layout.clone() else {
+            return Err(err);
+        };
+        let mut error_context = context.clone();
+        error_context.set("error", err.to_string());
+        error_context.set("error_code", err.code());
+        error_context.set("layout", layout);
+        self.render_page(&error_context, &error_layout)
+            .map_err(|_| err)
+    }
+
+    /// Renders a template string with the given context and custom delimiters.
+    ///
+    /// Values are escaped for the engine-wide `output_format`, or
+    /// inserted verbatim if none is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The template string containing the tags to be replaced.
+    /// * `context` - A `Context` containing the key-value pairs to use for substitution.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the rendered string or an `EngineError` if an error occurs.
+    ///
+    /// # Errors
+    ///
+    /// * `EngineError::InvalidTemplate` - If the template contains unclosed tags or is empty.
+    /// * `EngineError::Render` - If a template tag cannot be resolved from the context.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use staticweaver::Context;
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let mut context = Context::new();
+    /// context.set("greeting", "Hello");
+    /// context.set("name", "Alice");
+    ///
+    /// let template = "{{greeting}}, {{name}}!";
+    /// let result = engine.render_template(template, &context).unwrap();
+    /// assert_eq!(result, "Hello, Alice!");
+    /// ```
+    pub fn render_template(
+        &self,
+        template: &str,
+        context: &Context,
+    ) -> Result<String, EngineError> {
+        self.render_template_with_format(
+            template,
+            context,
+            self.output_format.unwrap_or_default(),
+        )
+    }
+
+    /// Renders a template string, escaping substituted values for
+    /// `format`.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The template string containing the tags to be replaced.
+    /// * `context` - A `Context` containing the key-value pairs to use for substitution.
+    /// * `format` - The output format to escape values for.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Engine::render_template`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use staticweaver::escape::OutputFormat;
+    /// use staticweaver::Context;
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let mut context = Context::new();
+    /// context.set("title", "Say \"hi\"");
+    ///
+    /// let result = engine
+    ///     .render_template_with_format(
+    ///         r#"{"title": "{{title}}"}"#,
+    ///         &context,
+    ///         OutputFormat::Json,
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(result, r#"{"title": "Say \"hi\""}"#);
+    /// ```
+    pub fn render_template_with_format(
+        &self,
+        template: &str,
+        context: &Context,
+        format: OutputFormat,
+    ) -> Result<String, EngineError> {
+        self.render_to_string(
+            template,
+            context,
+            format,
+            true,
+            &Settings::of(self),
+        )
+        .map_err(RenderReport::into_first_error)
+    }
+
+    /// Renders a template like [`Engine::render_template`], but keeps
+    /// going after a tag fails and reports every problem at once.
+    ///
+    /// Problems that prevent the rest of the template from being read,
+    /// such as an empty template or an unclosed tag, end the report.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The template string containing the tags to be replaced.
+    /// * `context` - A `Context` containing the key-value pairs to use for substitution.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`RenderReport`] listing every problem, in template
+    /// order, if any tag could not be rendered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use staticweaver::Context;
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let report = engine
+    ///     .render_template_report("{{title}}\n{{author}}", &Context::new())
+    ///     .unwrap_err();
+    /// assert_eq!(report.problems.len(), 2);
+    /// assert_eq!(report.problems[1].line, 2);
+    /// ```
+    pub fn render_template_report(
+        &self,
+        template: &str,
+        context: &Context,
+    ) -> Result<String, RenderReport> {
+        self.render_to_string(
+            template,
+            context,
+            self.output_format.unwrap_or_default(),
+            false,
+            &Settings::of(self),
+        )
+    }
+
+    /// Renders a template string into `out`, like
+    /// [`Engine::render_template`].
+    ///
+    /// Literal text and values are written straight to `out`, with no
+    /// intermediate strings, so reusing one buffer across renders avoids
+    /// allocating for each page.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The template string containing the tags to be replaced.
+    /// * `context` - A `Context` containing the key-value pairs to use for substitution.
+    /// * `out` - The buffer or writer to append the rendered output to.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Engine::render_template`], and
+    /// `EngineError::Render` if `out` fails. On error, `out` may hold
+    /// part of the rendered output.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use staticweaver::Context;
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let mut buffer = String::new();
+    /// for name in ["Alice", "Bob"] {
+    ///     let mut context = Context::new();
+    ///     context.set("name", name);
+    ///     buffer.clear();
+    ///     engine
+    ///         .render_template_to("Hello, {{name}}!", &context, &mut buffer)
+    ///         .unwrap();
+    ///     assert_eq!(buffer, format!("Hello, {}!", name));
+    /// }
+    /// ```
+    pub fn render_template_to<W: fmt::Write>(
+        &self,
+        template: &str,
+        context: &Context,
+        out: &mut W,
+    ) -> Result<(), EngineError> {
+        self.render_checked(
+            template,
+            context,
+            self.output_format.unwrap_or_default(),
+            true,
+            &Settings::of(self),
+            out,
+        )
+        .map_err(RenderReport::into_first_error)
+    }
+
+    /// Renders a template string into a new string, stopping at the
+    /// first problem if `fail_fast` is set.
+    fn render_to_string(
+        &self,
+        template: &str,
+        context: &Context,
+        format: OutputFormat,
+        fail_fast: bool,
+        settings: &Settings<'_>,
+    ) -> Result<String, RenderReport> {
+        let mut output = String::with_capacity(template.len());
+        self.render_checked(
+            template,
+            context,
+            format,
+            fail_fast,
+            settings,
+            &mut output,
+        )?;
+        Ok(output)
+    }
+
+    /// Shared implementation of template rendering, stopping at the
+    /// first problem if `fail_fast` is set.
+    fn render_checked<W: fmt::Write>(
+        &self,
+        template: &str,
+        context: &Context,
+        format: OutputFormat,
+        fail_fast: bool,
+        settings: &Settings<'_>,
+        out: &mut W,
+    ) -> Result<(), RenderReport> {
+        let mut report = RenderReport::default();
+        let (syntax, start) = match self.syntax_of(template, settings) {
+            Ok(syntax) => syntax,
+            Err(err) => {
+                report.push(template, 0..0, err);
+                return Err(report);
+            }
+        };
+        let mut parser = Parser::new(
+            &template[start..],
+            settings.open,
+            settings.close,
+        );
+        let render = TemplateRender {
+            template,
+            start,
+            context,
+            format,
+            syntax,
+            settings,
+            fail_fast,
+        };
+        let mut out =
+            Capped::new(out, SizeLimit::Output, settings.max_output);
+        let _ = self.render_tokens(
+            &render,
+            &mut parser,
+            None,
+            &mut report,
+            &mut out,
+        );
+
+        if report.problems.is_empty() {
+            Ok(())
+        } else {
+            Err(report)
+        }
+    }
+
+    /// Renders the tokens of `parser` into `out`, up to the tag closing
+    /// `block` if given, and returns the span of that tag.
+    ///
+    /// Returns `None` once `parser` is exhausted, or at the first
+    /// problem if `render.fail_fast` is set.
+    fn render_tokens<W: fmt::Write>(
+        &self,
+        render: &TemplateRender<'_>,
+        parser: &mut Parser<'_>,
+        block: Option<&str>,
+        report: &mut RenderReport,
+        out: &mut Capped<'_, W>,
+    ) -> Option<Range<usize>> {
+        let body = &render.template[render.start..];
+        while let Some(result) = parser.next() {
+            if let Some(deadline) = &render.settings.deadline {
+                let offset = result.as_ref().map_or_else(
+                    |err| err.span.start,
+                    |token| token.span.start,
+                );
+                if let Err(err) = deadline
+                    .check(render.template, offset + render.start)
+                {
+                    let offset = offset + render.start;
+                    report.push(render.template, offset..offset, err);
+                    return None;
+                }
+            }
+            let (span, rendered) = match result {
+                Ok(Token {
+                    segment: Segment::Text(text),
+                    span,
+                }) => (span, write_output(out, text)),
+                Ok(Token {
+                    segment: Segment::Tag(tag),
+                    span,
+                }) => {
+                    let trimmed = tag.trim();
+                    if let Some((name, args)) =
+                        self.blocks.opening(trimmed)
+                    {
+                        let problems = report.problems.len();
+                        let mut inner = String::new();
+                        let Some(close) = self.render_tokens(
+                            render,
+                            parser,
+                            Some(name),
+                            report,
+                            &mut Capped::new(
+                                &mut inner,
+                                SizeLimit::Allocation,
+                                render.settings.max_allocation,
+                            ),
+                        ) else {
+                            if !report.stopped()
+                                && (!render.fail_fast
+                                    || report.problems.len()
+                                        == problems)
+                            {
+                                let span = span.start + render.start
+                                    ..span.end + render.start;
+                                report.push(
+                                    render.template,
+                                    span,
+                                    EngineError::Render(format!(
+                                        "Unclosed block: {}",
+                                        name
+                                    )),
+                                );
+                            }
+                            return None;
+                        };
+                        if report.problems.len() > problems {
+                            // The content already failed.
+                            continue;
+                        }
+                        let source = &body[span.end..close.start];
+                        (
+                            span.start..close.end,
+                            self.blocks
+                                .call(
+                                    name,
+                                    args,
+                                    &inner,
+                                    source,
+                                    self,
+                                    render.context,
+                                    render.settings.deadline.as_ref(),
+                                )
+                                .and_then(|output| {
+                                    limits::check(
+                                        SizeLimit::Allocation,
+                                        render.settings.max_allocation,
+                                        output.len(),
+                                    )?;
+                                    write_output(out, &output)
+                                }),
+                        )
+                    } else if let Some(name) =
+                        self.blocks.closing(trimmed)
+                    {
+                        if block == Some(name) {
+                            return Some(span);
+                        }
+                        (
+                            span,
+                            Err(EngineError::Render(format!(
+                                "Unexpected closing block: {}",
+                                name
+                            ))),
+                        )
+                    } else {
+                        (
+                            span,
+                            self.profile(
+                                ProfileKind::Tag,
+                                trimmed,
+                                || {
+                                    self.write_tag(
+                                        tag,
+                                        render.context,
+                                        render.format,
+                                        render.syntax,
+                                        render.settings,
+                                        out,
+                                    )
+                                },
+                            ),
+                        )
+                    }
+                }
+                Err(err) => (err.span.clone(), Err(err.into())),
+            };
+            if let Err(err) = rendered {
+                let span =
+                    span.start + render.start..span.end + render.start;
+                let err = cancel::locate(
+                    out.error(err),
+                    render.template,
+                    span.start,
+                );
+                report.push(
+                    render.template,
+                    span,
+                    self.redact_error(err, render.context),
+                );
+                if render.fail_fast || report.stopped() {
+                    return None;
+                }
+            }
+        }
+        None
+    }
+
+    /// Resolves a single tag, applying any `|`-separated filters, and
+    /// writes the result to `out`, escaped for `format`.
+    ///
+    /// A tag without filters is looked up verbatim and written without
+    /// copying its value. Escaping is skipped when the last filter
+    /// already produces output that is safe for the document. A tag that
+    /// calls a registered function is replaced by its output verbatim.
+    /// A tag whose key has no value is handled as `settings` say.
+    fn write_tag<W: fmt::Write>(
+        &self,
+        tag: &str,
+        context: &Context,
+        format: OutputFormat,
+        syntax: SyntaxVersion,
+        settings: &Settings<'_>,
+        out: &mut W,
+    ) -> Result<(), EngineError> {
+        let allocated = |len: usize| {
+            limits::check(
+                SizeLimit::Allocation,
+                settings.max_allocation,
+                len,
+            )
+        };
+        if let Some(output) =
+            self.functions.call(tag.trim(), self, context)
+        {
+            let output = output?;
+            allocated(output.len())?;
+            return write_output(out, &output);
+        }
+
+        let mut parts = tag.split('|');
+        let key = parts.next().unwrap_or_default();
+        let key = if self.trim_tag_keys
+            || syntax.trims_keys()
+            || tag.contains('|')
+        {
+            key.trim()
+        } else {
+            key
+        };
+
+        let Some(mut value) = self.lookup(key, context) else {
+            return match settings.missing_keys {
+                MissingKeys::Error => Err(EngineError::Render(
+                    format!("Unresolved template tag: {}", key),
+                )),
+                MissingKeys::Empty => Ok(()),
+                MissingKeys::Keep => {
+                    write_output(out, settings.open)?;
+                    write_output(out, tag)?;
+                    write_output(out, settings.close)
+                }
+            };
+        };
+
+        let mut safe = false;
+        for name in parts.map(str::trim) {
+            let filter = Filter::from_name(name).ok_or_else(|| {
+                EngineError::Render(format!("Unknown filter: {}", name))
+            })?;
+            value = Cow::Owned(self.profile(
+                ProfileKind::Filter,
+                name,
+                || filter.apply(&value, self.base_url.as_deref()),
+            ));
+            allocated(value.len())?;
+            safe = filter.is_safe();
+        }
+
+        if safe {
+            write_output(out, &value)
+        } else {
+            let escaped = format.escape(&value);
+            allocated(escaped.len())?;
+            write_output(out, &escaped)
+        }
+    }
+
+    /// Looks up the value of the tag key `key`: in `context`, then in
+    /// the environment for `env.*` keys and in the data directory for
+    /// `data.*` keys.
+    pub(crate) fn lookup<'a>(
+        &'a self,
+        key: &str,
+        context: &'a Context,
+    ) -> Option<Cow<'a, str>> {
+        let resolved = if self.case_insensitive_keys {
+            context.resolve_ignore_case(key)
+        } else {
+            context.resolve(key)
+        };
+        resolved.or_else(|| {
+            key.strip_prefix(ENV_PREFIX)
+                .and_then(|key| self.environment.get(key))
+                .or_else(|| {
+                    let key = key.strip_prefix(DATA_PREFIX)?;
+                    self.data.as_ref()?.get(key)
+                })
+                .map(Cow::Borrowed)
+        })
+    }
+
+    /// Expands the shortcodes in `content` with the templates registered
+    /// in [`Engine::shortcodes`], as described in the
+    /// [`shortcode`](crate::shortcode) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content to expand, e.g. a page body.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Shortcodes::expand`].
+    pub fn expand_shortcodes(
+        &self,
+        content: &str,
+    ) -> Result<String, EngineError> {
+        self.shortcodes.expand(self, content)
+    }
+
+    /// Sets custom delimiters for the template tags.
+    ///
+    /// # Arguments
+    ///
+    /// * `open` - The string to use as the opening delimiter (e.g., `<<`).
+    /// * `close` - The string to use as the closing delimiter (e.g., `>>`).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EngineError::InvalidDelimiters`] if
+    /// [`validate_delimiters`] rejects the delimiters, leaving the
+    /// engine's delimiters unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use std::time::Duration;
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// engine.set_delimiters("<<", ">>").unwrap();
+    /// assert!(engine.set_delimiters("", ">>").is_err());
+    /// assert_eq!(engine.open_delim, "<<");
+    /// ```
+    pub fn set_delimiters(
+        &mut self,
+        open: &str,
+        close: &str,
+    ) -> Result<(), EngineError> {
+        validate_delimiters(open, close)?;
+        self.open_delim = open.to_string();
+        self.close_delim = close.to_string();
+        Ok(())
+    }
+
+    /// Creates or uses an existing template folder.
+    ///
+    /// Templates downloaded from a URL are stored in a temporary
+    /// directory, owned by the returned folder and removed when it is
+    /// dropped, as described in [`TemplateFolder`].
+    ///
+    /// # Arguments
+    ///
+    /// * `template_path` - An optional path to the template folder. It can be a local path or a URL.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the template folder on success, or an `EngineError` on failure.
+    ///
+    /// # Errors
+    ///
+    /// This function can return the following errors:
+    /// - `EngineError::Io`: If there is an issue with file operations.
+    /// - `EngineError::Reqwest`: If there is an issue downloading files from a URL.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let result = engine.create_template_folder(Some("custom_templates"));
+    /// ```
+    pub fn create_template_folder(
+        &self,
+        template_path: Option<&str>,
+    ) -> Result<TemplateFolder, EngineError> {
+        let current_dir = std::env::current_dir()?;
+
+        match template_path {
+            Some(path) if is_url(path) => {
+                // Download template files from the URL
+                self.download_templates(path)
+            }
+            Some(path) => {
+                // Use the local directory if it exists
+                let local_path = current_dir.join(path);
+                if local_path.exists() && local_path.is_dir() {
+                    Ok(TemplateFolder::existing(local_path))
+                } else {
+                    // Return an I/O error if the directory is not found
+                    Err(EngineError::Io(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!(
+                            "Template directory not found: {}",
+                            path
+                        ),
+                    )))
+                }
+            }
+            None => {
+                // Default to downloading template files from the default URL
+                let default_url = "https://raw.githubusercontent.com/sebastienrousseau/shokunin/main/template/";
+                self.download_templates(default_url)
+            }
+        }
+    }
+
+    /// Downloads the template files at `url` into a temporary
+    /// directory, owned by the returned folder.
+    ///
+    /// The directory is named after the URL when the engine is
+    /// deterministic, and random otherwise. It is removed if a file
+    /// cannot be downloaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to download files from.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created or a file
+    /// cannot be downloaded or written.
+    pub fn download_templates(
+        &self,
+        url: &str,
+    ) -> Result<TemplateFolder, EngineError> {
+        let folder = if self.deterministic {
+            let dir = stable_temp_dir(url);
+            fs::create_dir_all(&dir)?;
+            TemplateFolder::temporary(dir)
+        } else {
+            TemplateFolder::create_temporary()?
+        };
+        self.download_files_from_url(url, folder.path())?;
+        Ok(folder)
+    }
+
+    /// Downloads the template files at `url` into `dest`, creating it
+    /// if needed. The directory belongs to the caller and is never
+    /// removed by the returned folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to download files from.
+    /// * `dest` - The directory to save the files to.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created or a file
+    /// cannot be downloaded or written.
+    pub fn download_templates_to(
+        &self,
+        url: &str,
+        dest: &Path,
+    ) -> Result<TemplateFolder, EngineError> {
+        fs::create_dir_all(dest)?;
+        self.download_files_from_url(url, dest)?;
+        Ok(TemplateFolder::existing(dest.to_path_buf()))
+    }
+
+    /// Helper function to download files from a URL and save them to a
+    /// directory.
+    ///
+    /// Files are only written once all of them are downloaded and, if
+    /// `integrity` is set, verified.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to download files from.
+    /// * `dir` - The directory to save the files to.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or an `EngineError`.
+    fn download_files_from_url(
+        &self,
+        url: &str,
+        dir: &Path,
+    ) -> Result<(), EngineError> {
+        let files = [
+            "contact.html",
+            "index.html",
+            "page.html",
+            "post.html",
+            "main.js",
+            "sw.js",
+        ];
+
+        let manifest = match &self.integrity {
+            Some(integrity) => {
+                Some(integrity.manifest(url, &*self.fetcher)?)
+            }
+            None => None,
+        };
+        let urls: Vec<String> = files
+            .iter()
+            .map(|file| format!("{}/{}", url, file))
+            .collect();
+        let contents = fetch::fetch_all(
+            &self.fetcher,
+            &urls,
+            &self.download_limits,
+        )?;
+        if let Some(manifest) = &manifest {
+            for (file, content) in files.iter().zip(&contents) {
+                manifest.verify(file, content.as_bytes())?;
+            }
+        }
+
+        for (file, content) in files.iter().zip(contents) {
+            fs::write(dir.join(file), content)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the fetcher through which remote templates are downloaded,
+    /// as described in the [`fetch`](crate::fetch) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `fetcher` - The fetcher.
+    pub fn set_fetcher(&mut self, fetcher: Arc<dyn Fetcher>) {
+        self.fetcher = fetcher;
+    }
+
+    /// Returns the fetcher through which remote templates are
+    /// downloaded, e.g. to wrap it in a
+    /// [`CachingFetcher`](crate::http_cache::CachingFetcher).
+    #[must_use]
+    pub fn fetcher(&self) -> &Arc<dyn Fetcher> {
+        &self.fetcher
+    }
+
+    /// Clears all cached rendered templates.
+    ///
+    /// This method removes all entries from the cache, freeing up memory.
+    /// After calling this, subsequent render requests will not retrieve
+    /// any cached results and will regenerate the templates.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use std::time::Duration;
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// // Cache some templates...
+    ///
+    /// // Clear the cache
+    /// engine.clear_cache();
+    /// ```
+    pub fn clear_cache(&mut self) {
+        self.render_cache_mut().clear();
+        self.forget_missing();
+    }
+
+    /// Drops the cached pages rendered from a single layout.
+    ///
+    /// Pages rendered from other layouts stay cached. Call this after
+    /// editing a layout file so the next render picks up the change.
+    ///
+    /// # Arguments
+    ///
+    /// * `layout` - The name of the layout, as passed to [`Engine::render_page`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use std::time::Duration;
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// engine.invalidate_layout("post");
+    /// ```
+    pub fn invalidate_layout(&mut self, layout: &str) {
+        let Some(symbol) = self.layouts.get(layout) else {
+            return;
+        };
+        self.render_cache_mut()
+            .retain(|key, _| key.layout != symbol);
+        if let Some(missing) = self.missing_layouts_mut() {
+            let _ = missing.remove(&symbol);
+        }
+    }
+
+    /// Reloads a layout after its file was edited, e.g. by a preview
+    /// server watching the template directories.
+    ///
+    /// The pages cached from the layout, or from aliases of it, are
+    /// dropped as by [`Engine::invalidate_layout`], and the file is read
+    /// and parsed again, so that an edit that breaks the layout is
+    /// reported before any page is rendered with it. Renders the pages
+    /// again and compares them with [`Engine::diff_output`] to patch
+    /// them in place.
+    ///
+    /// # Arguments
+    ///
+    /// * `layout` - The name of the layout, as passed to [`Engine::render_page`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, wrapped with the layout and its file, if the
+    /// layout cannot be found, read, or parsed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::loader::MemoryLoader;
+    /// use staticweaver::{Context, Engine};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let loader = Arc::new(MemoryLoader::new());
+    /// loader.insert("templates/page.html", "<h1>{{title}}</h1>\n<p>Hi</p>\n");
+    /// let mut engine = Engine::new("templates", Duration::from_secs(60));
+    /// engine.set_loader(loader.clone());
+    /// let mut context = Context::new();
+    /// context.set("title", "Home");
+    /// let old = engine.render_page(&context, "page").unwrap();
+    ///
+    /// loader.insert("templates/page.html", "<h1>{{title}}</h1>\n<p>Hello</p>\n");
+    /// engine.reload_template("page").unwrap();
+    /// let new = engine.render_page(&context, "page").unwrap();
+    /// let changes = Engine::diff_output(&old, &new);
+    /// assert_eq!(changes[0].new, 1..2);
+    ///
+    /// loader.insert("templates/page.html", "<h1>{{title</h1>");
+    /// assert!(engine.reload_template("page").is_err());
+    /// ```
+    pub fn reload_template(
+        &mut self,
+        layout: &str,
+    ) -> Result<(), EngineError> {
+        let aliases: Vec<String> = self
+            .aliases
+            .iter()
+            .filter(|(_, target)| *target == layout)
+            .map(|(alias, _)| alias.clone())
+            .collect();
+        for name in aliases.iter().map(String::as_str).chain([layout]) {
+            self.invalidate_layout(name);
+        }
+
+        let (path, template) = self.read_layout(layout)?;
+        let settings = Settings::of(self);
+        let parsed = self.syntax_of(&template, &settings).and_then(
+            |(_, start)| {
+                Parser::new(
+                    &template[start..],
+                    settings.open,
+                    settings.close,
+                )
+                .try_for_each(|token| token.map(|_| ()))
+                .map_err(EngineError::from)
+            },
+        );
+        parsed.map_err(|err| {
+            RenderErrorContext::wrap(
+                layout,
+                Some(&path),
+                RenderPhase::Parse,
+                err,
+            )
+        })
+    }
+
+    /// Returns the runs of lines that differ between two renders of a
+    /// page, as described in the [`diff`](crate::diff) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `old` - The previous output.
+    /// * `new` - The current output.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::Engine;
+    ///
+    /// let changes = Engine::diff_output("a\nb\nc\n", "a\nB\nc\n");
+    /// assert_eq!((changes[0].old.clone(), changes[0].new.clone()), (1..2, 1..2));
+    /// ```
+    #[must_use]
+    pub fn diff_output(old: &str, new: &str) -> Vec<LineChange> {
+        diff::diff_lines(old, new)
+    }
+
+    /// Sets a maximum size for the render cache and clears the cache if it exceeds the specified limit.
+    ///
+    /// This method allows you to define a maximum number of entries that can be stored in the render cache.
+    /// If the cache size exceeds this limit, the cache will be cleared to prevent unbounded memory usage.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - The maximum number of cache entries allowed before the cache is cleared.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use std::time::Duration;
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    ///
+    /// // Set a maximum cache size of 100 entries
+    /// engine.set_max_cache_size(100);
+    /// ```
+    pub fn set_max_cache_size(&mut self, max_size: usize) {
+        if self.render_cache_mut().len() > max_size {
+            self.clear_cache();
+        }
+    }
+
+    /// Sets how often expired pages are swept from the render cache.
+    ///
+    /// Sweeps run while new pages are cached, at most once per
+    /// `interval`. The default interval is the cache TTL passed to
+    /// [`Engine::new`]; `None` disables sweeping, leaving expired pages
+    /// in memory until [`Engine::clear_cache`] is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The minimum time between sweeps.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use std::time::Duration;
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// engine.set_cache_sweep_interval(Some(Duration::from_secs(300)));
+    /// ```
+    pub fn set_cache_sweep_interval(
+        &mut self,
+        interval: Option<Duration>,
+    ) {
+        self.render_cache_mut().set_sweep_interval(interval);
+    }
+
+    /// Returns an immutable copy of the engine's configuration, for
+    /// render workers to use while the engine is reconfigured.
+    ///
+    /// The snapshot starts with a copy of the render cache and caches
+    /// its own pages from then on. Cloning a snapshot is cheap: clones
+    /// share the configuration and the cache.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::{Context, Engine};
+    /// use std::time::Duration;
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// let config = engine.snapshot();
+    /// engine.set_delimiters("<%", "%>");
+    ///
+    /// let mut context = Context::new();
+    /// context.set("name", "Ada");
+    /// assert_eq!(config.render_template("{{name}}", &context).unwrap(), "Ada");
+    /// ```
+    #[must_use]
+    pub fn snapshot(&self) -> EngineConfig {
+        EngineConfig::new(self.clone())
+    }
+
+    /// Locks the render cache for reading, e.g. to inspect its size.
+    ///
+    /// Renders lock the cache briefly to look pages up and to cache new
+    /// ones, so the guard should not be held across a render.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(3600));
+    /// assert!(engine.render_cache().is_empty());
+    /// ```
+    pub fn render_cache(
+        &self,
+    ) -> RwLockReadGuard<'_, Cache<PageKey, Arc<str>>> {
+        self.render_cache
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the render cache for changing it, e.g. to register hooks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::Engine;
+    /// use std::time::Duration;
+    ///
+    /// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+    /// engine.render_cache_mut().on_insert(|_, page| {
+    ///     println!("cached {} bytes", page.len());
+    /// });
+    /// ```
+    pub fn render_cache_mut(
+        &mut self,
+    ) -> &mut Cache<PageKey, Arc<str>> {
+        self.render_cache
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the render cache for caching a page.
+    pub(crate) fn write_cache(
+        &self,
+    ) -> RwLockWriteGuard<'_, Cache<PageKey, Arc<str>>> {
+        self.render_cache
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Returns `true` if `err` says that a page's layout file was not found.
+fn is_missing_layout(err: &EngineError) -> bool {
+    err.render_context().map_or(false, |context| {
+        context.phase == RenderPhase::Load && context.path.is_none()
+    })
+}
+
+/// Writes `s` to `out`, reporting a failed write as a render error.
+fn write_output<W: fmt::Write>(
+    out: &mut W,
+    s: &str,
+) -> Result<(), EngineError> {
+    out.write_str(s).map_err(|_| {
+        EngineError::Render(
+            "Failed to write rendered output".to_string(),
+        )
+    })
+}
+
+/// Utility function to check if a given path is a URL.
+///
+/// # Arguments
+///
+/// * `path` - The path to check.
+///
+/// # Returns
+///
+/// `true` if the path is a URL, `false` otherwise.
+fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Context;
+    use tempfile::tempdir;
+
+    /// Builds the render cache key of a page of `layout`.
+    fn page_key(
+        engine: &Engine,
+        layout: &str,
+        context_hash: u64,
+    ) -> PageKey {
+        PageKey::new(
+            engine,
+            layout,
+            &RenderOptions::new(),
+            context_hash,
+        )
+    }
+
+    #[test]
+    fn test_render_template() {
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        engine.set_delimiters("<<", ">>").unwrap();
+        let mut context = Context::new();
+        context.set("name", "Alice");
+        context.set("greeting", "Hello");
+
+        let template = "<<greeting>>, <<name>>!";
+        let result =
+            engine.render_template(template, &context).unwrap();
+        assert_eq!(result, "Hello, Alice!");
+    }
+
+    #[test]
+    fn test_render_template_empty() {
+        let engine = Engine::new("", Duration::from_secs(60));
+        let context = Context::new();
+
+        let template = "";
+        let result = engine.render_template(template, &context);
+        assert!(matches!(result, Err(EngineError::InvalidTemplate(_))));
+    }
+
+    #[test]
+    fn test_render_template_invalid_syntax() {
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        engine.set_delimiters("{{", "}}").unwrap(); // Set back to default delimiters
+        let context = Context::new();
+        let template = "Hello, {name}!";
+        let result = engine.render_template(template, &context);
+        assert!(
+            matches!(result, Err(EngineError::InvalidTemplate(msg)) if msg.contains("single '{'"))
+        );
+    }
+
+    #[test]
+    fn test_render_template_custom_delimiters() {
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        engine.set_delimiters("<<", ">>").unwrap();
+        let mut context = Context::new();
+        context.set("name", "Alice");
+        context.set("greeting", "Hello");
 
         let template = "<<greeting>>, <<name>>!";
         let result =
             engine.render_template(template, &context).unwrap();
         assert_eq!(result, "Hello, Alice!");
 
-        // Test invalid syntax with custom delimiters
-        let invalid_template = "Hello, <name>!";
-        let result = engine.render_template(invalid_template, &context);
-        assert!(
-            matches!(result, Err(EngineError::InvalidTemplate(msg)) if msg.contains("single '<'"))
+        // Test invalid syntax with custom delimiters
+        let invalid_template = "Hello, <name>!";
+        let result = engine.render_template(invalid_template, &context);
+        assert!(
+            matches!(result, Err(EngineError::InvalidTemplate(msg)) if msg.contains("single '<'"))
+        );
+    }
+
+    #[test]
+    fn test_render_template_unresolved_tag() {
+        let engine = Engine::new("", Duration::from_secs(60));
+        let context = Context::new();
+
+        let template = "Hello, {{name}}!";
+        let result = engine.render_template(template, &context);
+        assert!(matches!(result, Err(EngineError::Render(_))));
+    }
+
+    #[test]
+    fn test_render_template_lazy_values() {
+        let engine = Engine::new("", Duration::from_secs(60));
+        let mut context = Context::new();
+        context.set_lazy("used", || "computed".to_string());
+        context
+            .set_lazy("unused", || panic!("should not be evaluated"));
+
+        let result = engine
+            .render_template("Value: {{used}}", &context)
+            .unwrap();
+        assert_eq!(result, "Value: computed");
+    }
+
+    #[test]
+    fn test_render_template_cdata_filter() {
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        engine.output_format = Some(OutputFormat::Xml);
+        let mut context = Context::new();
+        context.set("title", "Fish & <Chips>");
+
+        let result = engine
+            .render_template(
+                "<title>{{title}}</title><summary>{{ title | cdata }}</summary>",
+                &context,
+            )
+            .unwrap();
+        assert_eq!(
+            result,
+            "<title>Fish &amp; &lt;Chips&gt;</title>\
+             <summary><![CDATA[Fish & <Chips>]]></summary>"
+        );
+
+        let result =
+            engine.render_template("{{title | bogus}}", &context);
+        assert!(
+            matches!(result, Err(EngineError::Render(msg)) if msg.contains("bogus"))
+        );
+    }
+
+    #[test]
+    fn test_render_template_url_filters() {
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        engine.base_url = Some("https://example.com/".to_string());
+        let mut context = Context::new();
+        context.set("title", "Hello, World!");
+
+        let result = engine
+            .render_template(
+                "{{ title | slugify | absolute_url }} ?q={{title|urlencode}}",
+                &context,
+            )
+            .unwrap();
+        assert_eq!(
+            result,
+            "https://example.com/hello-world ?q=Hello%2C%20World%21"
+        );
+    }
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("http://example.com"));
+        assert!(is_url("https://example.com"));
+        assert!(!is_url("file:///path/to/file"));
+        assert!(!is_url("/local/path"));
+    }
+
+    #[test]
+    fn test_page_options() {
+        let mut options = PageOptions::new();
+        options.set("title", "My Page");
+        assert_eq!(options.get("title"), Some(&"My Page".to_string()));
+        assert_eq!(options.get("non_existent"), None);
+    }
+
+    #[test]
+    fn test_render_page() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let template_path = temp_dir.path().join("template.html");
+        fs::write(&template_path, "Hello, {{name}}!").unwrap();
+
+        let engine = Engine::new(
+            temp_dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let mut context = Context::new();
+        context.set("name", "World");
+
+        let result = engine.render_page(&context, "template").unwrap();
+        assert_eq!(result, "Hello, World!");
+    }
+
+    #[test]
+    fn test_render_page_environment() {
+        use crate::environment::Mode;
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("page.html"),
+            "{{env.mode}} {{env.analytics}}",
+        )
+        .unwrap();
+
+        let mut engine = Engine::new(
+            temp_dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let mut context = Context::new();
+        assert!(engine.render_page(&context, "page").is_err());
+
+        engine.environment.set_flag("analytics", "off");
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "prod off"
+        );
+        engine.environment.mode = Mode::Dev;
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "dev off"
+        );
+
+        context.set("env.analytics", "on");
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "dev on"
+        );
+    }
+
+    #[test]
+    fn test_render_page_env_vars() {
+        use crate::environment::{EnvVars, REDACTED};
+        use std::fs;
+        use tempfile::TempDir;
+
+        std::env::set_var(
+            "STATICWEAVER_TEST_SITE_URL",
+            "https://a.test/?x&y",
+        );
+        std::env::set_var("STATICWEAVER_TEST_TOKEN", "t0k3n");
+        std::env::remove_var("STATICWEAVER_TEST_UNSET");
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("public.html"),
+            r#"{{ env("STATICWEAVER_TEST_SITE_URL") }}"#,
+        )
+        .unwrap();
+        fs::write(
+            temp_dir.path().join("secret.html"),
+            r#"{{ env("STATICWEAVER_TEST_TOKEN") }}"#,
+        )
+        .unwrap();
+        let mut engine = Engine::new(
+            temp_dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let context = Context::new();
+        assert!(engine.render_page(&context, "public").is_err());
+
+        let mut vars = EnvVars::new();
+        vars.allow("STATICWEAVER_TEST_SITE_URL");
+        vars.allow("STATICWEAVER_TEST_UNSET");
+        vars.allow_secret("STATICWEAVER_TEST_TOKEN");
+        engine.set_env_vars(vars);
+        assert_eq!(
+            engine.render_page(&context, "public").unwrap(),
+            "https://a.test/?x&amp;y"
+        );
+        assert_eq!(engine.render_cache().len(), 1);
+        std::env::set_var(
+            "STATICWEAVER_TEST_SITE_URL",
+            "https://b.test",
+        );
+        assert_eq!(
+            engine.render_page(&context, "public").unwrap(),
+            "https://b.test"
+        );
+
+        let cached = engine.render_cache().len();
+        assert_eq!(
+            engine.render_page(&context, "secret").unwrap(),
+            "t0k3n"
+        );
+        assert_eq!(engine.render_cache().len(), cached);
+
+        let err = engine
+            .render_template(r#"{{ env("HOME") }}"#, &context)
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("HOME is not allowed"),
+            "{}",
+            err
+        );
+        let err = engine
+            .render_template(
+                r#"{{ env("STATICWEAVER_TEST_UNSET") }}"#,
+                &context,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("is not set"), "{}", err);
+        assert_eq!(
+            engine
+                .render_template(
+                    r#"{{ env("STATICWEAVER_TEST_UNSET", "none") }}"#,
+                    &context
+                )
+                .unwrap(),
+            "none"
+        );
+
+        engine.functions.register("leak", |call| {
+            Err(EngineError::Render(format!(
+                "Cannot use {}",
+                call.value(0)?
+            )))
+        });
+        fs::write(
+            temp_dir.path().join("leak.html"),
+            "{{ leak(token) }}",
+        )
+        .unwrap();
+        let mut context = Context::new();
+        context.set("token", "t0k3n");
+        let err = engine
+            .render_template(r#"{{ leak(token) }}"#, &context)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Render error: Cannot use {}", REDACTED)
+        );
+        let err = engine.render_page(&context, "leak").unwrap_err();
+        assert!(!err.to_string().contains("t0k3n"), "{}", err);
+    }
+
+    #[test]
+    fn test_render_page_csp() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("page.html"),
+            r#"<meta content="{{ csp_policy() }}"><script nonce="{{ csp_nonce() }}">{{title}}</script>"#,
+        )
+        .unwrap();
+        let mut engine = Engine::new(
+            temp_dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let mut context = Context::new();
+        context.set("title", "go()");
+
+        let first = engine.render_page_csp(&context, "page").unwrap();
+        let second = engine.render_page_csp(&context, "page").unwrap();
+        assert_eq!(engine.render_cache().len(), 1);
+        assert_ne!(first.nonce, second.nonce);
+        assert!(second
+            .html
+            .contains(&format!("<script nonce=\"{}\">", second.nonce)));
+        assert_eq!(first.script_hashes, second.script_hashes);
+
+        context.set("title", "stop()");
+        let third = engine.render_page_csp(&context, "page").unwrap();
+        assert_ne!(third.script_hashes, second.script_hashes);
+
+        engine.deterministic = true;
+        assert_eq!(
+            engine.render_page_csp(&context, "page").unwrap(),
+            engine.render_page_csp(&context, "page").unwrap()
+        );
+    }
+
+    /// Returns an engine whose `page` layout embeds the `sri()` of
+    /// `app.js`, and the asset directory holding `app.js`.
+    fn sri_engine() -> (Engine, tempfile::TempDir, tempfile::TempDir) {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let out = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("page.html"),
+            r#"<script src="/app.js" integrity="{{ sri('app.js') }}"></script>"#,
+        )
+        .unwrap();
+        fs::write(out.path().join("app.js"), "a();").unwrap();
+        let engine = Engine::new(
+            temp_dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        (engine, temp_dir, out)
+    }
+
+    #[test]
+    fn test_sri_requires_asset_dir() {
+        let (engine, _templates, _out) = sri_engine();
+        let err =
+            engine.render_page(&Context::new(), "page").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("requires an asset directory"));
+    }
+
+    #[test]
+    fn test_render_page_sri() {
+        use crate::asset::sri_hash;
+
+        let (mut engine, _templates, out) = sri_engine();
+        engine.set_asset_dir(out.path());
+        let page = engine.render_page(&Context::new(), "page").unwrap();
+        assert!(page.contains(&sri_hash(b"a();")));
+    }
+
+    #[test]
+    fn test_refresh_assets_clears_render_cache() {
+        use crate::asset::sri_hash;
+        use std::fs;
+
+        let (mut engine, _templates, out) = sri_engine();
+        engine.set_asset_dir(out.path());
+        let context = Context::new();
+        let _ = engine.render_page(&context, "page").unwrap();
+        assert!(!engine.refresh_assets());
+        assert_eq!(engine.render_cache().len(), 1);
+
+        fs::write(out.path().join("app.js"), "b(); c();").unwrap();
+        assert!(engine.refresh_assets());
+        assert!(engine.render_cache().is_empty());
+        let page = engine.render_page(&context, "page").unwrap();
+        assert!(page.contains(&sri_hash(b"b(); c();")));
+    }
+
+    #[test]
+    fn test_data() {
+        use crate::data::DataDir;
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("page.html"),
+            "{{data.site.title}}",
+        )
+        .unwrap();
+        let data_dir = temp_dir.path().join("data");
+        fs::create_dir(&data_dir).unwrap();
+        fs::write(data_dir.join("site.json"), r#"{"title": "A"}"#)
+            .unwrap();
+
+        let mut engine = Engine::new(
+            temp_dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let mut context = Context::new();
+        assert!(!engine.refresh_data().unwrap());
+        assert!(engine.render_page(&context, "page").is_err());
+
+        assert!(engine
+            .set_data(DataDir::from_dir(&data_dir).unwrap())
+            .is_none());
+        assert_eq!(engine.render_page(&context, "page").unwrap(), "A");
+
+        fs::write(data_dir.join("nav.json"), "[]").unwrap();
+        fs::write(data_dir.join("site.json"), r#"{"title": "B"}"#)
+            .unwrap();
+        assert!(engine.refresh_data().unwrap());
+        assert_eq!(engine.render_page(&context, "page").unwrap(), "B");
+        assert_eq!(engine.data().unwrap().get("nav.len"), Some("0"));
+
+        context.set("data.site.title", "C");
+        assert_eq!(engine.render_page(&context, "page").unwrap(), "C");
+        assert!(engine.clear_data().is_some());
+    }
+
+    #[test]
+    fn test_render_page_auto() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("page.html"), "Page {{title}}")
+            .unwrap();
+        fs::write(temp_dir.path().join("post.html"), "Post {{title}}")
+            .unwrap();
+        fs::write(
+            temp_dir.path().join("draft.html"),
+            "Draft {{title}}",
+        )
+        .unwrap();
+
+        let mut engine = Engine::new(
+            temp_dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let mut context = Context::new();
+        context.set("title", "One");
+        assert_eq!(
+            engine.render_page_auto(&context, "page").unwrap(),
+            "Page One"
+        );
+
+        context.set(LAYOUT_KEY, "post");
+        assert_eq!(
+            engine.render_page_auto(&context, "page").unwrap(),
+            "Post One"
+        );
+
+        engine.set_layout_resolver(|context| {
+            context.get("draft").map(|_| "draft".to_string())
+        });
+        assert_eq!(engine.layout_for(&context, "page"), "post");
+        context.set("draft", "true");
+        assert_eq!(
+            engine.render_page_auto(&context, "page").unwrap(),
+            "Draft One"
+        );
+
+        engine.clear_layout_resolver();
+        assert_eq!(engine.layout_for(&context, "page"), "post");
+    }
+
+    #[test]
+    fn test_render_page_profiled() {
+        use crate::profile::ProfileKind;
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("page.html"),
+            "{{title | slugify}} {{ og_tags() }}",
+        )
+        .unwrap();
+        let mut engine = Engine::new(
+            temp_dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let mut context = Context::new();
+        context.set("title", "Hello World");
+
+        let (page, profile) =
+            engine.render_page_profiled(&context, "page").unwrap();
+        assert!(page.starts_with("hello-world <meta"));
+        assert!(engine.render_cache().is_empty());
+        let nodes: Vec<_> = profile
+            .iter()
+            .map(|(depth, node)| (depth, node.kind, node.name.as_str()))
+            .collect();
+        assert_eq!(
+            nodes,
+            [
+                (0, ProfileKind::Page, "page"),
+                (1, ProfileKind::Load, "page"),
+                (1, ProfileKind::Template, "page"),
+                (2, ProfileKind::Tag, "title | slugify"),
+                (3, ProfileKind::Filter, "slugify"),
+                (2, ProfileKind::Tag, "og_tags()"),
+                (3, ProfileKind::Function, "og_tags"),
+            ]
+        );
+        assert!(profile.duration >= profile.children[1].duration);
+
+        assert!(engine
+            .render_page_profiled(&context, "missing")
+            .is_err());
+        assert!(engine.profiler.is_none());
+    }
+
+    #[test]
+    fn test_render_page_shared_reuses_cached_page() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("template.html"), "Hi {{name}}")
+            .unwrap();
+
+        let engine = Engine::new(
+            temp_dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let mut context = Context::new();
+        context.set("name", "World");
+
+        let first =
+            engine.render_page_shared(&context, "template").unwrap();
+        let second =
+            engine.render_page_shared(&context, "template").unwrap();
+        assert_eq!(&*first, "Hi World");
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn test_render_page_merged() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("page.html"),
+            "{{title}} - {{site}}",
+        )
+        .unwrap();
+
+        let engine = Engine::new(
+            temp_dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let mut context = Context::new();
+        context.set("title", "Default");
+        context.set("site", "Weaver");
+        let mut options = PageOptions::new();
+        options.set("title", "About");
+
+        let result = engine
+            .render_page_merged(&options, &context, "page")
+            .unwrap();
+        assert_eq!(result, "About - Weaver");
+    }
+
+    #[test]
+    fn test_render_page_search_path_override() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let site_dir = TempDir::new().unwrap();
+        let theme_dir = TempDir::new().unwrap();
+        fs::write(site_dir.path().join("page.html"), "site {{name}}")
+            .unwrap();
+        fs::write(theme_dir.path().join("page.html"), "theme {{name}}")
+            .unwrap();
+        fs::write(theme_dir.path().join("post.html"), "post {{name}}")
+            .unwrap();
+
+        let mut engine = Engine::new(
+            site_dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        engine.add_template_dir(theme_dir.path().to_str().unwrap());
+        let mut context = Context::new();
+        context.set("name", "Alice");
+
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "site Alice"
+        );
+        assert_eq!(
+            engine.render_page(&context, "post").unwrap(),
+            "post Alice"
+        );
+        assert!(matches!(
+            engine.render_page(&context, "missing").unwrap_err().root(),
+            EngineError::Io(err)
+                if err.kind() == std::io::ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn test_render_page_with_theme() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let site_dir = TempDir::new().unwrap();
+        let theme_dir = TempDir::new().unwrap();
+        fs::write(
+            theme_dir.path().join("page.html"),
+            "{{title}} {{lang}}",
+        )
+        .unwrap();
+        fs::write(
+            theme_dir.path().join(crate::theme::THEME_MANIFEST),
+            r#"{"defaults": {"title": "Untitled", "lang": "en"}}"#,
+        )
+        .unwrap();
+
+        let mut engine = Engine::new(
+            site_dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let theme = Theme::from_dir(theme_dir.path()).unwrap();
+        assert!(engine.set_theme(theme).is_none());
+
+        let mut context = Context::new();
+        context.set("title", "Home");
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "Home en"
+        );
+
+        // Site templates override the theme's.
+        fs::write(site_dir.path().join("page.html"), "site {{title}}")
+            .unwrap();
+        engine.clear_cache();
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "site Home"
+        );
+
+        assert!(engine.clear_theme().is_some());
+        assert!(engine.theme().is_none());
+    }
+
+    #[test]
+    fn test_render_page_extensions() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("feed.xml"),
+            "<title>{{title}}</title>",
+        )
+        .unwrap();
+        fs::write(
+            temp_dir.path().join("manifest.json"),
+            r#"{"name": "{{title}}"}"#,
+        )
+        .unwrap();
+        fs::write(
+            temp_dir.path().join("page.htm"),
+            "<h1>{{title}}</h1>",
+        )
+        .unwrap();
+
+        let mut engine = Engine::new(
+            temp_dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let mut context = Context::new();
+        context.set("title", "Tom & \"Jerry\"");
+
+        assert_eq!(
+            engine.render_page(&context, "feed.xml").unwrap(),
+            "<title>Tom &amp; &quot;Jerry&quot;</title>"
+        );
+        assert_eq!(
+            engine.render_page(&context, "manifest.json").unwrap(),
+            r#"{"name": "Tom & \"Jerry\""}"#
+        );
+
+        engine.set_default_extension("htm");
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "<h1>Tom & \"Jerry\"</h1>"
+        );
+
+        engine.auto_escape = true;
+        engine.clear_cache();
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "<h1>Tom &amp; &quot;Jerry&quot;</h1>"
+        );
+    }
+
+    #[test]
+    fn test_render_page_as_overrides_extension() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("data.html"), "\"{{value}}\"")
+            .unwrap();
+
+        let mut engine = Engine::new(
+            temp_dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let mut context = Context::new();
+        context.set("value", "a\"b");
+
+        assert_eq!(
+            engine.render_page(&context, "data").unwrap(),
+            "\"a\"b\""
+        );
+        assert_eq!(
+            engine
+                .render_page_as(&context, "data", OutputFormat::Json)
+                .unwrap(),
+            "\"a\\\"b\""
+        );
+
+        engine.output_format = Some(OutputFormat::Html);
+        assert_eq!(
+            engine.render_template("{{value}}", &context).unwrap(),
+            "a&quot;b"
+        );
+    }
+
+    #[test]
+    fn test_clear_cache() {
+        let mut engine =
+            Engine::new("templates", Duration::from_secs(3600));
+        let _ = engine
+            .write_cache()
+            .insert(page_key(&engine, "key1", 1), Arc::from("value1"));
+        assert!(!engine.render_cache().is_empty());
+
+        engine.clear_cache();
+        assert!(engine.render_cache().is_empty());
+    }
+
+    #[test]
+    fn test_key_normalization() {
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        let mut context = Context::new();
+        context.set("Title", "Home");
+        context.set("title ", "Spaced");
+        let template = "{{ title }}|{{TITLE}}";
+        assert!(engine.render_template(template, &context).is_err());
+
+        engine.trim_tag_keys = true;
+        engine.case_insensitive_keys = true;
+        assert_eq!(
+            engine.render_template(template, &context).unwrap(),
+            "Home|Home"
+        );
+        assert_eq!(
+            engine.render_template("{{Title}}", &context).unwrap(),
+            "Home"
+        );
+    }
+
+    #[test]
+    fn test_render_template_to() {
+        struct Full;
+
+        impl fmt::Write for Full {
+            fn write_str(&mut self, _: &str) -> fmt::Result {
+                Err(fmt::Error)
+            }
+        }
+
+        let engine = Engine::new("", Duration::from_secs(60));
+        let mut context = Context::new();
+        context.set("name", "<World>");
+        let mut buffer = String::from("> ");
+        engine
+            .render_template_to(
+                "Hello, {{name|slugify}}!",
+                &context,
+                &mut buffer,
+            )
+            .unwrap();
+        assert_eq!(buffer, "> Hello, world!");
+
+        let err = engine
+            .render_template_to("Hello", &context, &mut Full)
+            .unwrap_err();
+        assert!(matches!(err, EngineError::Render(_)));
+    }
+
+    #[test]
+    fn test_render_template_report() {
+        let engine = Engine::new("", Duration::from_secs(60));
+        let mut context = Context::new();
+        context.set("title", "Hello");
+        let template =
+            "{{title}} {{missing}}\n{{title|bogus}}\n{{other}}";
+
+        let report = engine
+            .render_template_report(template, &context)
+            .unwrap_err();
+        let lines: Vec<usize> = report
+            .problems
+            .iter()
+            .map(|problem| problem.line)
+            .collect();
+        assert_eq!(lines, [1, 2, 3]);
+        assert_eq!(report.problems[0].offset, 10);
+        assert!(matches!(
+            &report.problems[1].error,
+            EngineError::Render(msg) if msg.contains("bogus")
+        ));
+        assert!(report.to_string().starts_with("3 problem(s) found"));
+
+        let diagnostics = report.diagnostics();
+        assert_eq!(diagnostics[0].code, "render");
+        assert_eq!(
+            diagnostics[0].span,
+            Some(Span {
+                offset: 10,
+                len: 11,
+                line: 1
+            })
+        );
+
+        // Fail-fast rendering reports only the first problem.
+        let result = engine.render_template(template, &context);
+        assert!(matches!(
+            result,
+            Err(EngineError::Render(msg)) if msg.contains("missing")
+        ));
+        assert_eq!(
+            engine
+                .render_template_report("{{title}}", &context)
+                .unwrap(),
+            "Hello"
+        );
+    }
+
+    #[test]
+    fn test_plan_page() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("page.html"), "{{title}}").unwrap();
+        let engine = Engine::new(
+            dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let mut context = Context::new();
+        context.set("title", "Home");
+
+        let plan = engine.plan_page(&context, "page");
+        assert_eq!(
+            plan.template_path,
+            Some(dir.path().join("page.html"))
+        );
+        assert_eq!(plan.format, Some(OutputFormat::Plain));
+        assert!(!plan.cached);
+        assert!(engine.render_cache().is_empty());
+
+        let _ = engine.render_page(&context, "page").unwrap();
+        let plan = engine.plan_page(&context, "page");
+        assert!(plan.cached);
+        assert!(engine.render_cache().contains_key(&plan.cache_key));
+    }
+
+    #[test]
+    fn test_render_page_with_fallback() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("page.html"), "page {{title}}")
+            .unwrap();
+        fs::write(dir.path().join("broken.html"), "{{title").unwrap();
+        fs::write(
+            dir.path().join("error.html"),
+            "{{error_code}} in {{layout}}",
+        )
+        .unwrap();
+        let mut engine = Engine::new(
+            dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let mut context = Context::new();
+        context.set("title", "Home");
+
+        assert_eq!(
+            engine
+                .render_page_with_fallback(&context, &["post", "page"])
+                .unwrap(),
+            "page Home"
+        );
+
+        // A broken layout stops the chain instead of falling through.
+        let err = engine
+            .render_page_with_fallback(&context, &["broken", "page"])
+            .unwrap_err();
+        assert_eq!(err.code(), "invalid_template");
+        assert!(engine
+            .render_page_with_fallback(&context, &[])
+            .is_err());
+
+        engine.error_layout = Some("error".to_string());
+        assert_eq!(
+            engine
+                .render_page_with_fallback(
+                    &context,
+                    &["broken", "page"]
+                )
+                .unwrap(),
+            "invalid_template in broken"
+        );
+        assert_eq!(
+            engine
+                .render_page_with_fallback(
+                    &context,
+                    &["post", "missing"]
+                )
+                .unwrap(),
+            "io in missing"
+        );
+    }
+
+    #[test]
+    fn test_render_error_context() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("broken.html"), "{{title").unwrap();
+        fs::write(dir.path().join("page.html"), "{{title}}").unwrap();
+        let engine = Engine::new(
+            dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let context = Context::new();
+
+        let err = engine.render_page(&context, "missing").unwrap_err();
+        let page = err.render_context().unwrap();
+        assert_eq!(page.phase, RenderPhase::Load);
+        assert_eq!(page.path, None);
+        assert!(err
+            .to_string()
+            .contains("Failed to load layout 'missing'"));
+
+        let err = engine.render_page(&context, "broken").unwrap_err();
+        assert_eq!(err.code(), "invalid_template");
+        assert_eq!(
+            Diagnostic::from(&err).file,
+            err.render_context().unwrap().path
+        );
+        let page = err.render_context().unwrap();
+        assert_eq!(page.phase, RenderPhase::Parse);
+        assert!(page.path.as_ref().unwrap().is_absolute());
+        assert!(page.path.as_ref().unwrap().ends_with("broken.html"));
+        assert!(matches!(err.root(), EngineError::InvalidTemplate(_)));
+
+        let err = engine.render_page(&context, "page").unwrap_err();
+        assert_eq!(
+            err.render_context().unwrap().phase,
+            RenderPhase::Render
+        );
+        assert!(matches!(err.root(), EngineError::Render(_)));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn test_not_found_ttl() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut engine = Engine::new(
+            dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let context = Context::new();
+
+        // Without negative caching, a new layout is found right away.
+        assert!(engine.render_page(&context, "late").is_err());
+        fs::write(dir.path().join("late.html"), "late").unwrap();
+        assert_eq!(
+            engine.render_page(&context, "late").unwrap(),
+            "late"
+        );
+
+        engine.set_not_found_ttl(Some(Duration::from_secs(60)));
+        assert!(engine.render_page(&context, "later").is_err());
+        fs::write(dir.path().join("later.html"), "later").unwrap();
+        let err = engine.render_page(&context, "later").unwrap_err();
+        assert!(matches!(err.root(), EngineError::Io(_)));
+
+        engine.invalidate_layout("later");
+        assert_eq!(
+            engine.render_page(&context, "later").unwrap(),
+            "later"
+        );
+    }
+
+    #[test]
+    fn test_layout_traversal() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let templates = dir.path().join("templates");
+        fs::create_dir(&templates).unwrap();
+        fs::write(dir.path().join("secret.html"), "secret").unwrap();
+        let engine = Engine::new(
+            templates.to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let context = Context::new();
+
+        for layout in ["../secret", "/etc/passwd"] {
+            let err = engine.render_page(&context, layout).unwrap_err();
+            assert_eq!(err.code(), "invalid_template_name", "{}", err);
+            assert_eq!(
+                err.render_context().unwrap().phase,
+                RenderPhase::Load
+            );
+            assert!(engine.resolve_template(layout).is_none());
+        }
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(
+                dir.path().join("secret.html"),
+                templates.join("linked.html"),
+            )
+            .unwrap();
+            let err =
+                engine.render_page(&context, "linked").unwrap_err();
+            assert!(matches!(
+                err.root(),
+                EngineError::InvalidTemplateName { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_set_default_extension_clears_cache() {
+        use crate::loader::MemoryLoader;
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/page.html", "html");
+        let _ = loader.insert("site/page.txt", "txt");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        let context = Context::new();
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "html"
+        );
+
+        engine.set_default_extension("txt");
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "txt"
+        );
+    }
+
+    #[test]
+    fn test_template_path_in_cache_key() {
+        use crate::loader::MemoryLoader;
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/page.html", "site");
+        let _ = loader.insert("draft/page.html", "draft");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        let context = Context::new();
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "site"
+        );
+
+        engine.template_path = "draft".to_string();
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "draft"
+        );
+    }
+
+    #[test]
+    fn test_strict_layout_case() {
+        use crate::loader::{Loader, MemoryLoader};
+
+        /// Finds files whatever their case, as macOS does.
+        #[derive(Debug)]
+        struct IgnoreCase(MemoryLoader);
+
+        impl IgnoreCase {
+            fn find(&self, path: &Path) -> Option<PathBuf> {
+                let path = path.to_string_lossy().to_lowercase();
+                self.0.files(Path::new("")).ok()?.into_iter().find(
+                    |file| {
+                        file.to_string_lossy().to_lowercase() == path
+                    },
+                )
+            }
+        }
+
+        impl Loader for IgnoreCase {
+            fn exists(&self, path: &Path) -> bool {
+                self.find(path).is_some()
+            }
+
+            fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+                self.0.read(&self.find(path).unwrap_or_default())
+            }
+
+            fn stored_path(
+                &self,
+                _: &Path,
+                path: &Path,
+            ) -> Option<PathBuf> {
+                self.find(path)
+            }
+
+            fn files(
+                &self,
+                dir: &Path,
+            ) -> std::io::Result<Vec<PathBuf>> {
+                self.0.files(dir)
+            }
+        }
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/Index.html", "index");
+        let _ = loader.insert("site/page.html", "page");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(IgnoreCase(loader)));
+        let context = Context::new();
+        assert_eq!(
+            engine.render_page(&context, "index").unwrap(),
+            "index"
+        );
+
+        engine.set_strict_layout_case(true);
+        let err = engine.render_page(&context, "index").unwrap_err();
+        assert_eq!(err.code(), "invalid_template_name");
+        assert!(err.to_string().contains("Index.html"), "{}", err);
+        assert_eq!(
+            engine.render_page(&context, "Index").unwrap(),
+            "index"
+        );
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "page"
+        );
+        assert_eq!(engine.available_layouts(), ["Index", "page"]);
+    }
+
+    #[test]
+    fn test_list_templates() {
+        let site = tempdir().unwrap();
+        let shared = tempdir().unwrap();
+        fs::create_dir(site.path().join("blog")).unwrap();
+        fs::write(
+            site.path().join("blog/post.html"),
+            "{{#syntax 2}}\n{{title}} {{ body|trim }} {{title}} {{oops",
+        )
+        .unwrap();
+        fs::write(shared.path().join("page.html"), "shared").unwrap();
+        fs::write(site.path().join("page.html"), "{{ now() }}")
+            .unwrap();
+
+        let mut engine = Engine::new(
+            site.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        engine.add_template_dir(shared.path().to_str().unwrap());
+        let templates = engine.list_templates();
+        assert_eq!(templates.len(), 2);
+
+        let post = &templates[0];
+        assert_eq!(post.name, "blog/post");
+        assert_eq!(post.path, site.path().join("blog/post.html"));
+        assert_eq!(post.syntax, SyntaxVersion::V2);
+        assert_eq!(post.keys, ["body", "title"]);
+        assert!(post.functions.is_empty());
+        assert!(post.modified.is_some());
+
+        let page = &templates[1];
+        assert_eq!(page.path, site.path().join("page.html"));
+        assert_eq!(page.size, 11);
+        assert_eq!(page.functions, ["now"]);
+    }
+
+    #[test]
+    fn test_aliases() {
+        use crate::loader::MemoryLoader;
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/post.html", "v1");
+        let _ = loader.insert("site/blog/post_v2.html", "v2");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        let context = Context::new();
+
+        assert_eq!(engine.render_page(&context, "post").unwrap(), "v1");
+        assert_eq!(engine.add_alias("post", "blog/post_v2"), None);
+        assert_eq!(engine.render_page(&context, "post").unwrap(), "v2");
+        assert_eq!(engine.alias_target("post"), "blog/post_v2");
+        assert_eq!(
+            engine.resolve_template("post").unwrap(),
+            Path::new("site/blog/post_v2.html")
+        );
+
+        let _ = engine.add_alias("post", "../secret");
+        let err = engine.render_page(&context, "post").unwrap_err();
+        assert_eq!(err.code(), "invalid_template_name");
+
+        assert_eq!(
+            engine.remove_alias("post").as_deref(),
+            Some("../secret")
         );
+        assert_eq!(engine.render_page(&context, "post").unwrap(), "v1");
     }
 
     #[test]
-    fn test_render_template_unresolved_tag() {
-        let engine = Engine::new("", Duration::from_secs(60));
+    fn test_reload_template() {
+        use crate::loader::MemoryLoader;
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/post.html", "v1");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader.clone()));
+        let _ = engine.add_alias("article", "post");
         let context = Context::new();
+        assert_eq!(engine.render_page(&context, "post").unwrap(), "v1");
+        assert_eq!(
+            engine.render_page(&context, "article").unwrap(),
+            "v1"
+        );
 
-        let template = "Hello, {{name}}!";
-        let result = engine.render_template(template, &context);
-        assert!(matches!(result, Err(EngineError::Render(_))));
+        let _ = loader.insert("site/post.html", "v2");
+        engine.reload_template("post").unwrap();
+        assert!(engine.render_cache().is_empty());
+        assert_eq!(
+            engine.render_page(&context, "article").unwrap(),
+            "v2"
+        );
+
+        let _ = loader.insert("site/post.html", "{{#syntax 9}}");
+        let err = engine.reload_template("post").unwrap_err();
+        assert_eq!(err.code(), "invalid_template");
+        assert!(engine.reload_template("missing").is_err());
     }
 
     #[test]
-    fn test_is_url() {
-        assert!(is_url("http://example.com"));
-        assert!(is_url("https://example.com"));
-        assert!(!is_url("file:///path/to/file"));
-        assert!(!is_url("/local/path"));
+    fn test_render_timeout_and_cancel() {
+        use crate::cancel::CancelToken;
+        use crate::loader::MemoryLoader;
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert(
+            "site/page.html",
+            "<h1>{{title}}</h1>\n{{#spin}}{{title}}{{/spin}}",
+        );
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        engine.blocks.register("spin", |block| loop {
+            block.check()?;
+            thread::sleep(Duration::from_millis(1));
+        });
+        let mut context = Context::new();
+        context.set("title", "a");
+
+        let mut options = RenderOptions::new();
+        options.timeout = Some(Duration::from_millis(20));
+        let err = engine
+            .render_page_with(&options, &context, "page")
+            .unwrap_err();
+        assert_eq!(err.code(), "timeout");
+        match err.root() {
+            EngineError::Timeout {
+                cancelled: false,
+                elapsed,
+                line: 2,
+                offset: 19,
+                ..
+            } => assert!(*elapsed >= Duration::from_millis(20)),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(engine.render_cache().is_empty());
+
+        let token = CancelToken::new();
+        let mut options = RenderOptions::new();
+        options.cancel = Some(token.clone());
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            token.cancel();
+        });
+        let err = engine
+            .render_page_with(&options, &context, "page")
+            .unwrap_err();
+        canceller.join().unwrap();
+        assert!(err.to_string().contains("Render cancelled after"));
+
+        let _ = engine.blocks.unregister("spin");
+        engine
+            .blocks
+            .register("spin", |block| Ok(block.inner().into()));
+        assert!(matches!(
+            engine.render_page_with(&options, &context, "page"),
+            Err(EngineError::Page(_))
+        ));
+        options.cancel = None;
+        assert_eq!(
+            engine
+                .render_page_with(&options, &context, "page")
+                .unwrap(),
+            "<h1>a</h1>\na"
+        );
     }
 
     #[test]
-    fn test_page_options() {
-        let mut options = PageOptions::new();
-        options.set("title".to_string(), "My Page".to_string());
-        assert_eq!(options.get("title"), Some(&"My Page".to_string()));
-        assert_eq!(options.get("non_existent"), None);
+    fn test_engine_settings_change_cache_key() {
+        use crate::loader::MemoryLoader;
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert(
+            "site/page.html",
+            "{{ title | absolute_url }} {{ now() }} {{Title}}",
+        );
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        engine.build_time = BuildTime::Fixed(UNIX_EPOCH);
+        engine.missing_keys = MissingKeys::Empty;
+        let mut context = Context::new();
+        context.set("title", "a");
+        let render = |engine: &Engine| {
+            engine.render_page(&context, "page").unwrap()
+        };
+        let first = render(&engine);
+
+        engine.base_url = Some("https://example.com".to_string());
+        let second = render(&engine);
+        assert_ne!(second, first);
+        engine.build_time =
+            BuildTime::Fixed(UNIX_EPOCH + Duration::from_secs(86_400));
+        let third = render(&engine);
+        assert_ne!(third, second);
+        engine.case_insensitive_keys = true;
+        assert_ne!(render(&engine), third);
     }
 
     #[test]
-    fn test_render_page() {
-        use std::fs;
-        use tempfile::TempDir;
+    fn test_registered_functions_change_cache_key() {
+        use crate::loader::MemoryLoader;
 
-        let temp_dir = TempDir::new().unwrap();
-        let template_path = temp_dir.path().join("template.html");
-        fs::write(&template_path, "Hello, {{name}}!").unwrap();
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/page.html", "{{ greet() }}");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        engine
+            .functions
+            .register("greet", |_| Ok("Hello".to_string()));
+        assert_eq!(
+            engine.render_page(&Context::new(), "page").unwrap(),
+            "Hello"
+        );
 
-        let mut engine = Engine::new(
-            temp_dir.path().to_str().unwrap(),
-            Duration::from_secs(60),
+        engine.functions.register("greet", |_| Ok("Hi".to_string()));
+        assert_eq!(
+            engine.render_page(&Context::new(), "page").unwrap(),
+            "Hi"
+        );
+    }
+
+    #[test]
+    fn test_render_page_keys_lazy_values() {
+        use crate::loader::MemoryLoader;
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/page.html", "<p>{{rt}}</p>");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        let mut a = Context::new();
+        a.set_lazy("rt", || "1 min".to_string());
+        let mut b = Context::new();
+        b.set_lazy("rt", || "9 min".to_string());
+
+        assert_eq!(
+            engine.render_page(&a, "page").unwrap(),
+            "<p>1 min</p>"
+        );
+        assert_eq!(
+            engine.render_page(&b, "page").unwrap(),
+            "<p>9 min</p>"
+        );
+        assert_ne!(
+            engine.page_input_hash(&a, "page", &RenderOptions::new()),
+            engine.page_input_hash(&b, "page", &RenderOptions::new())
+        );
+    }
+
+    #[test]
+    fn test_cache_ignored_keys() {
+        use crate::loader::MemoryLoader;
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/page.html", "{{title}} #{{build}}");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        let mut context = Context::new();
+        context.set("title", "Home");
+        context.set("build", 1);
+        let options = RenderOptions::new();
+        let render = |engine: &Engine, context: &Context| {
+            let page = engine
+                .render_page_detailed(&options, context, "page")
+                .unwrap();
+            (page.output.to_string(), page.cache)
+        };
+        assert_eq!(
+            render(&engine, &context),
+            ("Home #1".to_string(), CacheStatus::Miss)
+        );
+
+        let mut next = context.clone();
+        next.set("build", 2);
+        assert_eq!(render(&engine, &next).1, CacheStatus::Miss);
+
+        engine.clear_cache();
+        engine.cache_ignored_keys = vec!["build".to_string()];
+        assert_eq!(render(&engine, &context).1, CacheStatus::Miss);
+        assert!(context
+            .diff(&next)
+            .is_within(&engine.cache_ignored_keys));
+        assert_eq!(
+            render(&engine, &next),
+            ("Home #1".to_string(), CacheStatus::Hit)
         );
+        next.set("title", "About");
+        assert_eq!(
+            render(&engine, &next),
+            ("About #2".to_string(), CacheStatus::Miss)
+        );
+    }
+
+    #[test]
+    fn test_sensitive_keys_redacted_from_errors() {
+        let engine = Engine::new("templates", Duration::from_secs(60));
         let mut context = Context::new();
-        context.set("name".to_string(), "World".to_string());
+        context.set("token", "s3cr3t");
+        let render = |context: &Context| {
+            engine
+                .render_template("{{ now(token) }}", context)
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(render(&context).contains("s3cr3t"));
 
-        let result = engine.render_page(&context, "template").unwrap();
-        assert_eq!(result, "Hello, World!");
+        context.mark_sensitive("token");
+        let message = render(&context);
+        assert!(!message.contains("s3cr3t"), "{}", message);
+        assert!(message.contains("Unknown time format: [REDACTED]"));
     }
 
     #[test]
-    fn test_clear_cache() {
-        let mut engine =
-            Engine::new("templates", Duration::from_secs(3600));
+    fn test_render_memory_caps() {
+        use crate::limits::SizeLimit;
+        use crate::loader::MemoryLoader;
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert(
+            "site/page.html",
+            "<p>{{body}}</p>{{#repeat}}{{body}}{{/repeat}}",
+        );
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        engine
+            .blocks
+            .register("repeat", |block| Ok(block.inner().repeat(100)));
+        let mut context = Context::new();
+        context.set("body", "a&b");
+        let too_large = |options: &RenderOptions| match engine
+            .render_page_with(options, &context, "page")
+            .unwrap_err()
+            .root()
+        {
+            EngineError::TooLarge { limit, max } => (*limit, *max),
+            other => panic!("unexpected {:?}", other),
+        };
+
+        let mut options = RenderOptions::new();
+        options.max_output = Some(100);
+        assert_eq!(too_large(&options), (SizeLimit::Output, 100));
+        options.max_output = Some(1000);
+        options.max_allocation = Some(2);
+        assert_eq!(too_large(&options), (SizeLimit::Allocation, 2));
+        options.max_allocation = Some(64);
+        assert_eq!(too_large(&options), (SizeLimit::Allocation, 64));
+        assert!(engine.render_cache().is_empty());
+
+        options.max_allocation = Some(1000);
+        let page = engine
+            .render_page_with(&options, &context, "page")
+            .unwrap();
+        assert_eq!(page.len(), 310);
+    }
+
+    #[test]
+    fn test_clock_and_loader() {
+        use crate::cache::ManualClock;
+        use crate::loader::MemoryLoader;
+
+        let clock = ManualClock::new();
+        let loader = MemoryLoader::new();
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader.clone()));
+        engine.set_not_found_ttl(Some(Duration::from_secs(2)));
+        engine.set_clock(Arc::new(clock.clone()));
+        let context = Context::new();
+
+        assert!(engine.render_page(&context, "page").is_err());
+        let _ = loader.insert("site/page.html", "v1");
+        assert!(engine.render_page(&context, "page").is_err());
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(engine.render_page(&context, "page").unwrap(), "v1");
+
+        let _ = loader.insert("site/page.html", "v2");
+        assert_eq!(engine.render_page(&context, "page").unwrap(), "v1");
+        clock.advance(Duration::from_secs(61));
+        assert_eq!(engine.render_page(&context, "page").unwrap(), "v2");
+    }
+
+    #[test]
+    fn test_disk_cache() {
+        use crate::loader::MemoryLoader;
+
+        let dir = tempdir().unwrap();
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/page.html", "<p>{{title}}</p>");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader.clone()));
+        engine.set_disk_cache(Some(DiskCache::new(dir.path())));
+        let mut context = Context::new();
+        context.set("title", "Home");
+
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "<p>Home</p>"
+        );
+        let disk = engine.disk_cache().unwrap().clone();
+        assert_eq!(disk.len(), 1);
+
+        // Disk hits are promoted to the in-memory cache.
+        engine.clear_cache();
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "<p>Home</p>"
+        );
+        assert_eq!(engine.render_cache().len(), 1);
+        assert_eq!(disk.len(), 1);
+
+        // Editing the layout misses the page of its previous source.
+        let _ = loader.insert("site/page.html", "<h1>{{title}}</h1>");
+        engine.clear_cache();
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "<h1>Home</h1>"
+        );
+        assert_eq!(disk.len(), 2);
+
+        let options = RenderOptions {
+            bypass_cache: true,
+            ..RenderOptions::default()
+        };
+        context.set("title", "Away");
         let _ = engine
-            .render_cache
-            .insert("key1".to_string(), "value1".to_string());
-        assert!(!engine.render_cache.is_empty());
+            .render_page_with(&options, &context, "page")
+            .unwrap();
+        assert_eq!(disk.len(), 2);
+    }
+
+    #[test]
+    fn test_render_page_detailed() {
+        use crate::loader::MemoryLoader;
+
+        let dir = tempdir().unwrap();
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/page.html", "<p>{{title}}</p>");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        engine.set_disk_cache(Some(DiskCache::new(dir.path())));
+        let mut context = Context::new();
+        context.set("title", "Home");
+        let mut options = RenderOptions::new();
+
+        let page = engine
+            .render_page_detailed(&options, &context, "page")
+            .unwrap();
+        assert_eq!(&*page.output, "<p>Home</p>");
+        assert_eq!(page.cache, CacheStatus::Miss);
+        assert_eq!(
+            page.dependencies,
+            [PathBuf::from("site/page.html")]
+        );
+        assert_eq!(page.content_hash, sha256_hex(b"<p>Home</p>"));
+        assert_eq!(page.etag(), format!("\"{}\"", page.content_hash));
+        let status = |engine: &Engine, options: &RenderOptions| {
+            engine
+                .render_page_detailed(options, &context, "page")
+                .unwrap()
+                .cache
+        };
+        assert_eq!(status(&engine, &options), CacheStatus::Hit);
+        engine.clear_cache();
+        assert_eq!(status(&engine, &options), CacheStatus::DiskHit);
+        options.bypass_cache = true;
+        assert_eq!(status(&engine, &options), CacheStatus::Bypass);
+
+        let err = engine
+            .render_page_detailed(&options, &context, "missing")
+            .unwrap_err();
+        assert!(err.render_context().is_some());
+        assert!(engine
+            .render_page_detailed(
+                &RenderOptions {
+                    delimiters: Some((
+                        "%".to_string(),
+                        "%".to_string()
+                    )),
+                    ..RenderOptions::default()
+                },
+                &context,
+                "page"
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_render_page_detailed_hit_keeps_dependencies() {
+        use crate::loader::MemoryLoader;
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/page.html", "page");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader.clone()));
+        let context = Context::new();
+        let _ = engine.render_page(&context, "page").unwrap();
 
+        // Hits report the file the page was rendered from, without
+        // resolving the layout again.
+        let _ = loader.remove("site/page.html");
+        let page = engine
+            .render_page_detailed(
+                &RenderOptions::new(),
+                &context,
+                "page",
+            )
+            .unwrap();
+        assert_eq!(page.cache, CacheStatus::Hit);
+        assert_eq!(
+            page.dependencies,
+            [PathBuf::from("site/page.html")]
+        );
+    }
+
+    #[test]
+    fn test_page_memo() {
+        let engine = Engine::new("site", Duration::from_secs(60));
+        let hashes = PageMemo::default();
+        let hash = |key, page: &Arc<str>| {
+            hashes.get_or_insert_with(key, page, || {
+                sha256_hex(page.as_bytes())
+            })
+        };
+        let page: Arc<str> = Arc::from("a");
+        assert_eq!(
+            hash(page_key(&engine, "page", 1), &page),
+            sha256_hex(b"a")
+        );
+
+        // A replaced page is hashed again.
+        let page: Arc<str> = Arc::from("b");
+        assert_eq!(
+            hash(page_key(&engine, "page", 1), &page),
+            sha256_hex(b"b")
+        );
+
+        // Hashes of dropped pages are swept.
+        for index in 0..PageMemo::<String>::SWEEP_LEN as u64 {
+            let _ = hash(page_key(&engine, "page", index), &page);
+            let _ = hash(
+                page_key(&engine, "dropped", index),
+                &Arc::from("c"),
+            );
+        }
+        let len = hashes.values.read().unwrap().len();
+        assert!(len < 2 * PageMemo::<String>::SWEEP_LEN, "{}", len);
+    }
+
+    /// Returns an engine rendering `page` from memory, and the manifest
+    /// of `count` pages it rendered, followed by a page whose layout is
+    /// gone and one without a context hash.
+    fn warm_engine(count: usize) -> (Engine, BuildManifest) {
+        use crate::loader::MemoryLoader;
+        use crate::manifest::ManifestEntry;
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/page.html", "<p>{{title}}</p>");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        let entry =
+            |path: &str, layout: &str, context_hash| ManifestEntry {
+                path: path.to_string(),
+                layout: layout.to_string(),
+                source: None,
+                content_hash: String::new(),
+                size: 0,
+                render_time: None,
+                context_hash,
+            };
+        let mut manifest = BuildManifest::new();
+        for index in 0..count {
+            let mut context = Context::new();
+            context.set("title", index.to_string());
+            let _ = engine.render_page(&context, "page").unwrap();
+            manifest.insert(entry(
+                &format!("{}.html", index),
+                "page",
+                Some(engine.context_hash(&context)),
+            ));
+        }
+        manifest.insert(entry("gone.html", "gone", Some(0)));
+        manifest.insert(entry("unknown.html", "page", None));
+        (engine, manifest)
+    }
+
+    #[test]
+    fn test_warm_cache_counts_pages_in_memory() {
+        let (engine, manifest) = warm_engine(3);
+        let report =
+            engine.warm_cache(&manifest, &RenderOptions::new());
+        assert_eq!(report.loaded, 3);
+    }
+
+    #[test]
+    fn test_warm_cache_misses_unknown_pages() {
+        let (engine, manifest) = warm_engine(3);
+        let report =
+            engine.warm_cache(&manifest, &RenderOptions::new());
+        assert_eq!(report.missed, ["gone.html", "unknown.html"]);
+    }
+
+    #[test]
+    fn test_warm_cache_without_disk_cache() {
+        let (mut engine, manifest) = warm_engine(3);
         engine.clear_cache();
-        assert!(engine.render_cache.is_empty());
+        let report =
+            engine.warm_cache(&manifest, &RenderOptions::new());
+        assert_eq!(report.loaded, 0);
+        assert_eq!(report.missed.len(), 5);
+    }
+
+    #[test]
+    fn test_warm_cache_loads_disk_cache() {
+        let dir = tempdir().unwrap();
+        let (mut engine, manifest) = warm_engine(20);
+        engine.clear_cache();
+        engine.set_disk_cache(Some(DiskCache::new(dir.path())));
+        let mut context = Context::new();
+        for index in 0..10 {
+            context.set("title", index.to_string());
+            let _ = engine.render_page(&context, "page").unwrap();
+        }
+        engine.clear_cache();
+
+        let report =
+            engine.warm_cache(&manifest, &RenderOptions::new());
+        assert_eq!(report.loaded, 10);
+        assert_eq!(report.missed.len(), 12);
+        assert_eq!(engine.render_cache().len(), 10);
+        context.set("title", "3");
+        assert!(engine.plan_page(&context, "page").cached);
+    }
+
+    #[test]
+    fn test_template_encoding() {
+        use crate::loader::MemoryLoader;
+
+        let loader = MemoryLoader::new();
+        let _ =
+            loader.insert("site/bom.html", "\u{feff}<p>{{title}}</p>");
+        let _ = loader
+            .insert("site/latin1.html", &b"caf\xE9 {{title}}"[..]);
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        let mut context = Context::new();
+        context.set("title", "Menu");
+
+        assert_eq!(
+            engine.render_page(&context, "bom").unwrap(),
+            "<p>Menu</p>"
+        );
+        let err = engine.render_page(&context, "latin1").unwrap_err();
+        assert_eq!(err.code(), "encoding");
+        assert_eq!(
+            err.render_context().unwrap().phase,
+            RenderPhase::Load
+        );
+        assert!(matches!(
+            err.root(),
+            EngineError::Encoding { path, .. } if path.ends_with("latin1.html")
+        ));
+
+        engine.fallback_encoding = Some("windows-1252".to_string());
+        let rendered = engine.render_page(&context, "latin1");
+        if cfg!(feature = "encoding") {
+            assert_eq!(rendered.unwrap(), "café Menu");
+        } else {
+            assert!(rendered.is_err());
+        }
+    }
+
+    #[test]
+    fn test_create_template_folder_offline() {
+        use crate::fetch::StaticFetcher;
+
+        let url = "https://example.com/offline-templates";
+        let fetcher = StaticFetcher::new();
+        for file in [
+            "contact.html",
+            "index.html",
+            "page.html",
+            "post.html",
+            "main.js",
+        ] {
+            fetcher.insert(format!("{}/{}", url, file), file);
+        }
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        engine.set_fetcher(Arc::new(fetcher.clone()));
+        engine.deterministic = true;
+
+        let err = engine.create_template_folder(Some(url)).unwrap_err();
+        assert!(err.to_string().ends_with("sw.js: HTTP 404"));
+        assert!(!stable_temp_dir(url).exists());
+
+        fetcher.insert(format!("{}/sw.js", url), "sw.js");
+        let dir = engine.create_template_folder(Some(url)).unwrap();
+        assert!(dir.is_temporary());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("page.html")).unwrap(),
+            "page.html"
+        );
+        assert_eq!(fetcher.requests().len(), 12);
+        let path = dir.path().to_path_buf();
+        dir.cleanup().unwrap();
+        assert!(!path.exists());
+
+        engine.deterministic = false;
+        let dir = engine.create_template_folder(Some(url)).unwrap();
+        let path = dir.path().to_path_buf();
+        drop(dir);
+        assert!(!path.exists());
+
+        let dest = tempdir().unwrap();
+        let target = dest.path().join("templates");
+        let dir = engine.download_templates_to(url, &target).unwrap();
+        assert!(!dir.is_temporary());
+        dir.cleanup().unwrap();
+        assert!(target.join("sw.js").is_file());
+
+        let kept = engine.download_templates(url).unwrap().keep();
+        assert!(kept.join("index.html").is_file());
+        fs::remove_dir_all(kept).unwrap();
+    }
+
+    #[test]
+    fn test_download_templates_integrity() {
+        use crate::fetch::StaticFetcher;
+        use crate::integrity::{
+            sha256_hex, SignatureVerifier, MANIFEST_FILE,
+            SIGNATURE_FILE,
+        };
+
+        #[derive(Debug)]
+        struct Checksum;
+
+        impl SignatureVerifier for Checksum {
+            fn verify(
+                &self,
+                manifest: &[u8],
+                signature: &str,
+            ) -> Result<(), String> {
+                if signature == sha256_hex(manifest) {
+                    Ok(())
+                } else {
+                    Err("bad signature".to_string())
+                }
+            }
+        }
+
+        let url = "https://example.com/signed-templates";
+        let fetcher = StaticFetcher::new();
+        let mut manifest = String::new();
+        for file in [
+            "contact.html",
+            "index.html",
+            "page.html",
+            "post.html",
+            "main.js",
+            "sw.js",
+        ] {
+            fetcher.insert(format!("{}/{}", url, file), file);
+            manifest.push_str(&format!(
+                "{}  {}\n",
+                sha256_hex(file.as_bytes()),
+                file
+            ));
+        }
+        fetcher.insert(format!("{}/{}", url, MANIFEST_FILE), &manifest);
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        engine.set_fetcher(Arc::new(fetcher.clone()));
+        engine.integrity =
+            Some(Integrity::remote().signed_by(Arc::new(Checksum)));
+        let dest = tempdir().unwrap();
+        let target = dest.path().join("templates");
+
+        let err =
+            engine.download_templates_to(url, &target).unwrap_err();
+        assert_eq!(err.code(), "integrity_check_failed");
+        assert!(err.to_string().contains(SIGNATURE_FILE), "{}", err);
+
+        fetcher.insert(format!("{}/{}", url, SIGNATURE_FILE), "forged");
+        let err =
+            engine.download_templates_to(url, &target).unwrap_err();
+        assert!(err.to_string().ends_with("bad signature"), "{}", err);
+
+        fetcher.insert(
+            format!("{}/{}", url, SIGNATURE_FILE),
+            sha256_hex(manifest.as_bytes()),
+        );
+        let dir = engine.download_templates_to(url, &target).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("sw.js")).unwrap(),
+            "sw.js"
+        );
+
+        fs::remove_dir_all(&target).unwrap();
+        fetcher.insert(format!("{}/main.js", url), "tampered");
+        let err =
+            engine.download_templates_to(url, &target).unwrap_err();
+        assert!(
+            err.to_string().starts_with(
+                "Integrity check failed for main.js: SHA-256 checksum"
+            ),
+            "{}",
+            err
+        );
+        assert!(!target.join("index.html").exists());
+
+        engine.integrity = Some(Integrity::pinned(manifest));
+        assert!(engine.download_templates(url).is_err());
+        engine.integrity = None;
+        assert!(engine.download_templates(url).is_ok());
+    }
+
+    #[test]
+    fn test_invalidate_layout() {
+        let mut engine =
+            Engine::new("templates", Duration::from_secs(3600));
+        for (layout, hash) in [("post", 1), ("post", 2), ("page", 1)] {
+            let _ = engine.write_cache().insert(
+                page_key(&engine, layout, hash),
+                Arc::from("value"),
+            );
+        }
+
+        engine.invalidate_layout("post");
+        assert_eq!(engine.render_cache().len(), 1);
+        assert!(engine
+            .render_cache()
+            .contains_key(&page_key(&engine, "page", 1)));
+    }
+
+    #[test]
+    fn test_cache_sweep_interval() {
+        let mut engine =
+            Engine::new("templates", Duration::from_secs(3600));
+        assert_eq!(
+            engine.render_cache().sweep_interval(),
+            Some(Duration::from_secs(3600))
+        );
+
+        engine.set_cache_sweep_interval(None);
+        assert_eq!(engine.render_cache().sweep_interval(), None);
     }
 
     #[test]
@@ -649,14 +6740,115 @@ fn test_set_max_cache_size() {
         let mut engine =
             Engine::new("templates", Duration::from_secs(3600));
         let _ = engine
-            .render_cache
-            .insert("key1".to_string(), "value1".to_string());
+            .write_cache()
+            .insert(page_key(&engine, "key1", 1), Arc::from("value1"));
         let _ = engine
-            .render_cache
-            .insert("key2".to_string(), "value2".to_string());
-        assert_eq!(engine.render_cache.len(), 2);
+            .write_cache()
+            .insert(page_key(&engine, "key2", 1), Arc::from("value2"));
+        assert_eq!(engine.render_cache().len(), 2);
 
         engine.set_max_cache_size(1);
-        assert!(engine.render_cache.is_empty());
+        assert!(engine.render_cache().is_empty());
+    }
+
+    #[test]
+    fn test_render_page_with() {
+        use crate::loader::MemoryLoader;
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/page.html", "<p>{{title}}</p>");
+        let _ = loader
+            .insert("site/odd.html", "<p><%title%> <%author%></p>");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader.clone()));
+        let mut context = Context::new();
+        context.set("title", "<b>");
+
+        let mut options = RenderOptions::new();
+        options.set_delimiters("<%", "%>").unwrap();
+        assert!(engine
+            .render_page_with(&options, &context, "odd")
+            .is_err());
+        options.missing_keys = Some(MissingKeys::Keep);
+        assert_eq!(
+            engine.render_page_with(&options, &context, "odd").unwrap(),
+            "<p><b> <%author%></p>"
+        );
+        options.missing_keys = Some(MissingKeys::Empty);
+        assert_eq!(
+            engine.render_page_with(&options, &context, "odd").unwrap(),
+            "<p><b> </p>"
+        );
+        assert_eq!(engine.open_delim, "{{");
+        assert!(engine.set_delimiters("<%", "<%").is_err());
+        assert_eq!(engine.open_delim, "{{");
+
+        let invalid = RenderOptions {
+            delimiters: Some(("%".to_string(), "%%".to_string())),
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            engine
+                .render_page_with(&invalid, &context, "odd")
+                .unwrap_err()
+                .code(),
+            "invalid_delimiters"
+        );
+
+        let escape = RenderOptions {
+            auto_escape: Some(true),
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            engine.render_page_with(&escape, &context, "page").unwrap(),
+            "<p>&lt;b&gt;</p>"
+        );
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "<p><b></p>"
+        );
+
+        let _ = loader.insert("site/page.html", "<div>{{title}}</div>");
+        let bypass = RenderOptions {
+            bypass_cache: true,
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            engine.render_page_with(&bypass, &context, "page").unwrap(),
+            "<div><b></div>"
+        );
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "<p><b></p>"
+        );
+    }
+
+    #[test]
+    fn test_render_page_shared_across_threads() {
+        use crate::loader::MemoryLoader;
+
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Engine>();
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/page.html", "<p>{{n}}</p>");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        let engine = Arc::new(engine);
+
+        let handles: Vec<_> = (0..4)
+            .map(|n| {
+                let engine = Arc::clone(&engine);
+                thread::spawn(move || {
+                    let mut context = Context::new();
+                    context.set("n", n.to_string());
+                    engine.render_page(&context, "page").unwrap()
+                })
+            })
+            .collect();
+        for (n, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.join().unwrap(), format!("<p>{}</p>", n));
+        }
+        assert_eq!(engine.render_cache().len(), 4);
     }
 }
@@ -0,0 +1,407 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Environment Module
+//!
+//! This module provides the `Environment` struct, which describes the
+//! environment a site is built for: a [`Mode`], `dev` or `prod`, plus
+//! arbitrary flags. The environment of an [`Engine`](crate::Engine) is
+//! readable from every template as `{{env.mode}}` and `{{env.<flag>}}`,
+//! and decides whether pages marked as drafts are published.
+//!
+//! Templates cannot read process environment variables unless the
+//! engine opts in with [`Engine::set_env_vars`](crate::Engine::set_env_vars),
+//! which registers the `env()` function for the variables an [`EnvVars`]
+//! allows:
+//!
+//! ```text
+//! {{ env("SITE_URL") }}
+//! {{ env("ANALYTICS_ID", "none") }}   a fallback for unset variables
+//! ```
+//!
+//! Reading any other variable is an error. The values of variables
+//! allowed as secrets are replaced by [`REDACTED`] in error messages,
+//! and pages that contain them are never stored in the render cache.
+
+use crate::context::Context;
+use crate::engine::EngineError;
+use crate::escape::escape_html;
+use crate::function::Functions;
+use fnv::FnvHasher;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::Hasher;
+
+/// The prefix of template tags that read the environment.
+pub const ENV_PREFIX: &str = "env.";
+
+/// The name of the environment variable function.
+pub const ENV_FUNCTION: &str = "env";
+
+/// The text that replaces the values of secret variables in error
+/// messages.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// The context key that marks a page as a draft.
+pub const DRAFT_KEY: &str = "draft";
+
+/// Whether a site is built for development or production.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// A local build; drafts are published.
+    Dev,
+    /// A release build; drafts are skipped.
+    #[default]
+    Prod,
+}
+
+impl Mode {
+    /// Returns the mode named `name`, `dev` or `prod`.
+    ///
+    /// The comparison ignores case, and `development` and `production`
+    /// are accepted as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the mode.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "dev" | "development" => Some(Self::Dev),
+            "prod" | "production" => Some(Self::Prod),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the mode, as rendered by `{{env.mode}}`.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Dev => "dev",
+            Self::Prod => "prod",
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The environment a site is built for.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::environment::{Environment, Mode};
+/// use staticweaver::{Context, Engine};
+/// use std::time::Duration;
+///
+/// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+/// engine.environment = Environment::new(Mode::Dev);
+/// engine.environment.set_flag("analytics", "off");
+///
+/// let page = engine
+///     .render_template("{{env.mode}}/{{env.analytics}}", &Context::new())
+///     .unwrap();
+/// assert_eq!(page, "dev/off");
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Environment {
+    /// Whether the site is built for development or production.
+    pub mode: Mode,
+    flags: BTreeMap<String, String>,
+}
+
+impl Environment {
+    /// Creates an environment in `mode` without flags.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - Whether the site is built for development or production.
+    #[must_use]
+    pub fn new(mode: Mode) -> Self {
+        Self {
+            mode,
+            flags: BTreeMap::new(),
+        }
+    }
+
+    /// Returns whether the site is built for development.
+    #[must_use]
+    pub fn is_dev(&self) -> bool {
+        self.mode == Mode::Dev
+    }
+
+    /// Sets the flag `name`, readable from templates as `{{env.<name>}}`.
+    ///
+    /// A flag named `mode` cannot be read from templates, because
+    /// `{{env.mode}}` renders the mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the flag.
+    /// * `value` - The value of the flag.
+    pub fn set_flag(&mut self, name: &str, value: &str) {
+        let _ = self.flags.insert(name.to_string(), value.to_string());
+    }
+
+    /// Returns the value of the flag `name`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the flag.
+    #[must_use]
+    pub fn flag(&self, name: &str) -> Option<&str> {
+        self.flags.get(name).map(String::as_str)
+    }
+
+    /// Returns the value rendered by `{{env.<key>}}`: the mode for
+    /// `mode`, otherwise the flag `key`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The tag key without the `env.` prefix.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        if key == "mode" {
+            Some(self.mode.as_str())
+        } else {
+            self.flag(key)
+        }
+    }
+
+    /// Returns whether a page with `context` is published in this
+    /// environment.
+    ///
+    /// Pages whose [`DRAFT_KEY`] value is a true boolean, as read by
+    /// [`Context::get_bool`], are only published in development.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The context of the page.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::environment::{Environment, Mode};
+    /// use staticweaver::Context;
+    ///
+    /// let mut context = Context::new();
+    /// context.set("draft", "true");
+    /// assert!(!Environment::new(Mode::Prod).publishes(&context));
+    /// assert!(Environment::new(Mode::Dev).publishes(&context));
+    /// ```
+    #[must_use]
+    pub fn publishes(&self, context: &Context) -> bool {
+        self.is_dev() || !context.get_bool(DRAFT_KEY).unwrap_or(false)
+    }
+
+    /// Returns a hash of the environment, used in render cache keys.
+    pub(crate) fn hash(&self) -> u64 {
+        let mut hasher = FnvHasher::default();
+        hasher.write_u8(self.mode as u8);
+        for (name, value) in &self.flags {
+            hasher.write(&(name.len() as u64).to_le_bytes());
+            hasher.write(name.as_bytes());
+            hasher.write(&(value.len() as u64).to_le_bytes());
+            hasher.write(value.as_bytes());
+        }
+        hasher.finish()
+    }
+}
+
+/// The process environment variables readable by the `env()` template
+/// function, as described in the [module documentation](self).
+///
+/// Values are read when a page is rendered, never stored.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::environment::EnvVars;
+/// use staticweaver::{Context, Engine};
+/// use std::time::Duration;
+///
+/// std::env::set_var("DOCS_SITE_URL", "https://example.com");
+/// std::env::set_var("DOCS_API_TOKEN", "s3cr3t");
+///
+/// let mut vars = EnvVars::new();
+/// vars.allow("DOCS_SITE_URL");
+/// vars.allow_secret("DOCS_API_TOKEN");
+///
+/// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+/// engine.set_env_vars(vars);
+/// let context = Context::new();
+/// assert_eq!(
+///     engine.render_template(r#"{{ env("DOCS_SITE_URL") }}"#, &context).unwrap(),
+///     "https://example.com"
+/// );
+/// assert!(engine.render_template(r#"{{ env("HOME") }}"#, &context).is_err());
+/// assert_eq!(engine.env_vars().redact("token=s3cr3t"), "token=[REDACTED]");
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EnvVars {
+    /// The allowed variables, and whether each is a secret.
+    allowed: BTreeMap<String, bool>,
+}
+
+impl EnvVars {
+    /// Creates a list that allows no variable.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows templates to read the variable `name`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the variable.
+    pub fn allow(&mut self, name: &str) {
+        let _ = self.allowed.insert(name.to_string(), false);
+    }
+
+    /// Allows templates to read the variable `name`, whose value is
+    /// redacted from errors and kept out of the render cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the variable.
+    pub fn allow_secret(&mut self, name: &str) {
+        let _ = self.allowed.insert(name.to_string(), true);
+    }
+
+    /// Returns whether templates may read the variable `name`.
+    #[must_use]
+    pub fn is_allowed(&self, name: &str) -> bool {
+        self.allowed.contains_key(name)
+    }
+
+    /// Returns whether the variable `name` is allowed as a secret.
+    #[must_use]
+    pub fn is_secret(&self, name: &str) -> bool {
+        self.allowed.get(name).copied().unwrap_or(false)
+    }
+
+    /// Returns the value of the variable `name`, or `None` if it is
+    /// unset or not valid Unicode.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EngineError::Render` if the variable is not allowed.
+    pub fn get(
+        &self,
+        name: &str,
+    ) -> Result<Option<String>, EngineError> {
+        if !self.is_allowed(name) {
+            return Err(EngineError::Render(format!(
+                "Environment variable {} is not allowed",
+                name
+            )));
+        }
+        Ok(std::env::var(name).ok())
+    }
+
+    /// Returns the non-empty values of the secret variables that are
+    /// set.
+    fn secrets(&self) -> impl Iterator<Item = String> + '_ {
+        self.allowed
+            .iter()
+            .filter(|(_, secret)| **secret)
+            .filter_map(|(name, _)| std::env::var(name).ok())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Returns whether `text` contains the value of a secret variable.
+    #[must_use]
+    pub fn reveals_secret(&self, text: &str) -> bool {
+        self.secrets().any(|secret| text.contains(&secret))
+    }
+
+    /// Replaces the values of secret variables in `text` by
+    /// [`REDACTED`].
+    #[must_use]
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for secret in self.secrets() {
+            if text.contains(&secret) {
+                text = Cow::Owned(text.replace(&secret, REDACTED));
+            }
+        }
+        text
+    }
+
+    /// Returns a hash of the current values of the allowed variables
+    /// that are not secrets, used in render cache keys.
+    pub(crate) fn hash(&self) -> u64 {
+        let mut hasher = FnvHasher::default();
+        for (name, _) in
+            self.allowed.iter().filter(|(_, secret)| !**secret)
+        {
+            let value = std::env::var(name).unwrap_or_default();
+            hasher.write(&(name.len() as u64).to_le_bytes());
+            hasher.write(name.as_bytes());
+            hasher.write(&(value.len() as u64).to_le_bytes());
+            hasher.write(value.as_bytes());
+        }
+        hasher.finish()
+    }
+}
+
+/// Registers the `env` function.
+pub(crate) fn register(functions: &mut Functions) {
+    functions.register(ENV_FUNCTION, |call| {
+        let name = call.value(0)?;
+        match call.engine().env_vars().get(&name)? {
+            Some(value) => Ok(escape_html(&value).into_owned()),
+            None => match call.optional(1)? {
+                Some(fallback) => {
+                    Ok(escape_html(&fallback).into_owned())
+                }
+                None => Err(EngineError::Render(format!(
+                    "Environment variable {} is not set",
+                    name
+                ))),
+            },
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_from_name() {
+        assert_eq!(Mode::from_name("DEV"), Some(Mode::Dev));
+        assert_eq!(Mode::from_name("production"), Some(Mode::Prod));
+        assert_eq!(Mode::from_name("staging"), None);
+        assert_eq!(Mode::default().to_string(), "prod");
+    }
+
+    #[test]
+    fn test_environment() {
+        let mut env = Environment::default();
+        env.set_flag("analytics", "on");
+        env.set_flag("mode", "shadowed");
+        assert_eq!(env.get("mode"), Some("prod"));
+        assert_eq!(env.get("analytics"), Some("on"));
+        assert_eq!(env.flag("mode"), Some("shadowed"));
+        assert_eq!(env.get("missing"), None);
+
+        let mut context = Context::new();
+        assert!(env.publishes(&context));
+        context.set(DRAFT_KEY, "no");
+        assert!(env.publishes(&context));
+        context.set(DRAFT_KEY, "yes");
+        assert!(!env.publishes(&context));
+
+        let hash = env.hash();
+        env.mode = Mode::Dev;
+        assert!(env.publishes(&context));
+        assert_ne!(env.hash(), hash);
+    }
+}
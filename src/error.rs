@@ -6,7 +6,9 @@
 //! This module defines custom error types used throughout the library,
 //! providing detailed error information and context for various failure scenarios.
 
+use std::fmt;
 use std::io;
+use std::path::PathBuf;
 use thiserror::Error;
 
 /// Represents high-level errors that can occur during the operation of the engine.
@@ -22,6 +24,7 @@ pub enum EngineError {
     Io(#[from] io::Error),
 
     /// Network request error encountered during engine operations.
+    #[cfg(feature = "remote")]
     #[error("Request error: {0}")]
     Reqwest(#[from] reqwest::Error),
 
@@ -58,6 +61,7 @@ pub enum TemplateError {
     Io(#[from] io::Error),
 
     /// Network request error encountered during template operations.
+    #[cfg(feature = "remote")]
     #[error("Request error: {0}")]
     Reqwest(#[from] reqwest::Error),
 
@@ -80,6 +84,162 @@ pub enum TemplateError {
     /// Error when an invalid operation is attempted on a template.
     #[error("Invalid operation: {0}")]
     InvalidOperation(String),
+
+    /// Error when a context value cannot be parsed into the requested type.
+    #[error("Invalid value for '{key}': {message}")]
+    InvalidValue {
+        /// The context key whose value failed to parse.
+        key: String,
+        /// A description of the parse failure.
+        message: String,
+    },
+}
+
+impl EngineError {
+    /// Returns the stable, machine-readable code of the error.
+    ///
+    /// Codes never change between releases, so tools can match on them
+    /// instead of on the `Display` output. Template errors report the
+    /// code of the wrapped [`TemplateError`].
+    ///
+    /// | Variant | Code |
+    /// |---|---|
+    /// | `Io` | `io` |
+    /// | `Reqwest` | `request` |
+    /// | `Render` | `render` |
+    /// | `InvalidTemplate` | `invalid_template` |
+    /// | `ResourceNotFound` | `resource_not_found` |
+    /// | `Timeout` | `timeout` |
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::EngineError;
+    ///
+    /// let err = EngineError::Timeout("fetching theme".to_string());
+    /// assert_eq!(err.code(), "timeout");
+    /// ```
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Io(_) => "io",
+            #[cfg(feature = "remote")]
+            Self::Reqwest(_) => "request",
+            Self::Render(_) => "render",
+            Self::InvalidTemplate(_) => "invalid_template",
+            Self::Template(err) => err.code(),
+            Self::ResourceNotFound(_) => "resource_not_found",
+            Self::Timeout(_) => "timeout",
+        }
+    }
+}
+
+impl TemplateError {
+    /// Returns the stable, machine-readable code of the error.
+    ///
+    /// Engine errors report the code of the wrapped [`EngineError`].
+    ///
+    /// | Variant | Code |
+    /// |---|---|
+    /// | `Io` | `io` |
+    /// | `Reqwest` | `request` |
+    /// | `InvalidSyntax` | `invalid_syntax` |
+    /// | `RenderError` | `render` |
+    /// | `MissingVariable` | `missing_variable` |
+    /// | `InvalidOperation` | `invalid_operation` |
+    /// | `InvalidValue` | `invalid_value` |
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Io(_) => "io",
+            #[cfg(feature = "remote")]
+            Self::Reqwest(_) => "request",
+            Self::InvalidSyntax(_) => "invalid_syntax",
+            Self::RenderError(_) => "render",
+            Self::EngineError(err) => err.code(),
+            Self::MissingVariable(_) => "missing_variable",
+            Self::InvalidOperation(_) => "invalid_operation",
+            Self::InvalidValue { .. } => "invalid_value",
+        }
+    }
+}
+
+/// A machine-readable view of an error, for tools that report problems
+/// as data rather than text.
+///
+/// With the `serde` feature, diagnostics implement `Serialize`, e.g. to
+/// emit JSON problem reports from a build.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::error::Diagnostic;
+/// use staticweaver::TemplateError;
+///
+/// let err = TemplateError::MissingVariable("title".to_string());
+/// let diagnostic = Diagnostic::from(&err);
+/// assert_eq!(diagnostic.code, "missing_variable");
+/// assert_eq!(diagnostic.message, "Missing variable: title");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostic {
+    /// The stable error code.
+    pub code: &'static str,
+    /// The human-readable error message.
+    pub message: String,
+    /// The file the error occurred in, if known.
+    pub file: Option<PathBuf>,
+    /// The location of the error within the file, if known.
+    pub span: Option<Span>,
+}
+
+/// A location within a template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Span {
+    /// The byte offset of the start of the span.
+    pub offset: usize,
+    /// The length of the span in bytes.
+    pub len: usize,
+    /// The line, starting at 1, on which the span starts.
+    pub line: usize,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] ", self.code)?;
+        if let Some(file) = &self.file {
+            write!(f, "{}", file.display())?;
+            if let Some(span) = &self.span {
+                write!(f, ":{}", span.line)?;
+            }
+            f.write_str(": ")?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+impl From<&EngineError> for Diagnostic {
+    fn from(err: &EngineError) -> Self {
+        Self {
+            code: err.code(),
+            message: err.to_string(),
+            file: None,
+            span: None,
+        }
+    }
+}
+
+impl From<&TemplateError> for Diagnostic {
+    fn from(err: &TemplateError) -> Self {
+        Self {
+            code: err.code(),
+            message: err.to_string(),
+            file: None,
+            span: None,
+        }
+    }
 }
 
 /// A specialized `Result` type for StaticWeaver operations.
@@ -103,6 +263,57 @@ fn test_engine_error_display() {
         );
     }
 
+    #[test]
+    fn test_error_codes() {
+        let err = EngineError::Template(
+            TemplateError::MissingVariable("title".to_string()),
+        );
+        assert_eq!(err.code(), "missing_variable");
+        assert_eq!(
+            TemplateError::EngineError(Box::new(
+                EngineError::ResourceNotFound("theme".to_string())
+            ))
+            .code(),
+            "resource_not_found"
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_display() {
+        let mut diagnostic = Diagnostic::from(&EngineError::Render(
+            "Unresolved template tag: title".to_string(),
+        ));
+        assert_eq!(
+            diagnostic.to_string(),
+            "[render] Render error: Unresolved template tag: title"
+        );
+
+        diagnostic.file = Some(PathBuf::from("page.html"));
+        diagnostic.span = Some(Span {
+            offset: 0,
+            len: 9,
+            line: 3,
+        });
+        assert!(diagnostic
+            .to_string()
+            .starts_with("[render] page.html:3: "));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_diagnostic_serialize() {
+        let diagnostic = Diagnostic::from(
+            &TemplateError::InvalidSyntax("Unclosed tag".to_string()),
+        );
+        let json = serde_json::to_value(&diagnostic).unwrap();
+        assert_eq!(json["code"], "invalid_syntax");
+        assert_eq!(
+            json["message"],
+            "Invalid template syntax: Unclosed tag"
+        );
+        assert!(json["file"].is_null());
+    }
+
     #[test]
     fn test_template_error_display() {
         let err =
@@ -160,6 +371,18 @@ fn test_invalid_operation_error() {
         );
     }
 
+    #[test]
+    fn test_invalid_value_error() {
+        let err = TemplateError::InvalidValue {
+            key: "count".to_string(),
+            message: "invalid digit found in string".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Invalid value for 'count': invalid digit found in string"
+        );
+    }
+
     #[test]
     fn test_engine_error_io() {
         let io_err =
@@ -0,0 +1,274 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Escape Module
+//!
+//! This module provides the `OutputFormat` enum and the escaping
+//! functions used to make substituted values safe for the format of the
+//! document being rendered.
+
+use std::borrow::Cow;
+use std::path::Path;
+
+/// The format of a rendered document, which selects how substituted
+/// values are escaped.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::escape::OutputFormat;
+///
+/// assert_eq!(OutputFormat::from_extension("json"), OutputFormat::Json);
+/// assert_eq!(OutputFormat::Xml.escape("a & b"), "a &amp; b");
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    /// HTML documents; values are HTML-escaped.
+    Html,
+    /// XML documents such as feeds and sitemaps; values are XML-escaped.
+    Xml,
+    /// JSON documents; values are escaped for use inside JSON strings.
+    Json,
+    /// Plain text; values are inserted verbatim.
+    #[default]
+    Plain,
+}
+
+impl OutputFormat {
+    /// Infers the output format from a file extension.
+    ///
+    /// The comparison ignores case. Unknown extensions map to
+    /// `OutputFormat::Plain`.
+    ///
+    /// # Arguments
+    ///
+    /// * `extension` - The file extension, without the leading dot.
+    #[must_use]
+    pub fn from_extension(extension: &str) -> Self {
+        match extension.to_ascii_lowercase().as_str() {
+            "html" | "htm" | "xhtml" => Self::Html,
+            "xml" | "rss" | "atom" | "svg" => Self::Xml,
+            "json" | "webmanifest" => Self::Json,
+            _ => Self::Plain,
+        }
+    }
+
+    /// Infers the output format from the extension of `path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The template or output path.
+    #[must_use]
+    pub fn from_path(path: &Path) -> Self {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .map_or(Self::Plain, Self::from_extension)
+    }
+
+    /// Escapes `value` for this format.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to escape.
+    ///
+    /// # Returns
+    ///
+    /// The escaped value, borrowed when no escaping was necessary.
+    #[must_use]
+    pub fn escape(self, value: &str) -> Cow<'_, str> {
+        match self {
+            Self::Html => escape_html(value),
+            Self::Xml => escape_xml(value),
+            Self::Json => escape_json(value),
+            Self::Plain => Cow::Borrowed(value),
+        }
+    }
+
+    /// Returns the MIME type of documents in this format, suitable for a
+    /// `Content-Type` header.
+    #[must_use]
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Html => "text/html; charset=utf-8",
+            Self::Xml => "application/xml; charset=utf-8",
+            Self::Json => "application/json",
+            Self::Plain => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+/// Escapes the characters that are significant in HTML.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::escape::escape_html;
+///
+/// assert_eq!(
+///     escape_html("<a href=\"x\">Tom & Jerry's</a>"),
+///     "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+/// );
+/// ```
+#[must_use]
+pub fn escape_html(value: &str) -> Cow<'_, str> {
+    escape_with(value, |c| match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '"' => Some("&quot;"),
+        '\'' => Some("&#39;"),
+        _ => None,
+    })
+}
+
+/// Escapes the characters that are significant in XML.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::escape::escape_xml;
+///
+/// assert_eq!(escape_xml("Fish & <Chips>"), "Fish &amp; &lt;Chips&gt;");
+/// ```
+#[must_use]
+pub fn escape_xml(value: &str) -> Cow<'_, str> {
+    escape_with(value, |c| match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '"' => Some("&quot;"),
+        '\'' => Some("&apos;"),
+        _ => None,
+    })
+}
+
+/// Escapes a value for inclusion inside a JSON string literal.
+///
+/// The surrounding quotes are not added, so templates keep control over
+/// the document structure: `{"title": "{{title}}"}`.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::escape::escape_json;
+///
+/// assert_eq!(escape_json("say \"hi\"\n"), "say \\\"hi\\\"\\n");
+/// ```
+#[must_use]
+pub fn escape_json(value: &str) -> Cow<'_, str> {
+    if !value.chars().any(|c| matches!(c, '"' | '\\') || c < ' ') {
+        return Cow::Borrowed(value);
+    }
+
+    let mut escaped = String::with_capacity(value.len() + 8);
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c < ' ' => {
+                escaped.push_str(&format!("\\u{:04x}", c as u32));
+            }
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Wraps a value in an XML CDATA section.
+///
+/// Any `]]>` inside the value is split across two sections, so the
+/// result is always well-formed no matter what the value contains.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::escape::cdata;
+///
+/// assert_eq!(cdata("<p>Fish & Chips</p>"), "<![CDATA[<p>Fish & Chips</p>]]>");
+/// assert_eq!(cdata("a]]>b"), "<![CDATA[a]]]]><![CDATA[>b]]>");
+/// ```
+#[must_use]
+pub fn cdata(value: &str) -> String {
+    format!("<![CDATA[{}]]>", value.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+/// Replaces every character for which `replacement` returns `Some`.
+fn escape_with<F>(value: &str, replacement: F) -> Cow<'_, str>
+where
+    F: Fn(char) -> Option<&'static str>,
+{
+    if !value.chars().any(|c| replacement(c).is_some()) {
+        return Cow::Borrowed(value);
+    }
+
+    let mut escaped = String::with_capacity(value.len() + 8);
+    for c in value.chars() {
+        match replacement(c) {
+            Some(entity) => escaped.push_str(entity),
+            None => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_extension() {
+        assert_eq!(
+            OutputFormat::from_extension("HTML"),
+            OutputFormat::Html
+        );
+        assert_eq!(
+            OutputFormat::from_extension("atom"),
+            OutputFormat::Xml
+        );
+        assert_eq!(
+            OutputFormat::from_extension("webmanifest"),
+            OutputFormat::Json
+        );
+        assert_eq!(
+            OutputFormat::from_extension("txt"),
+            OutputFormat::Plain
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("feed.xml")),
+            OutputFormat::Xml
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("README")),
+            OutputFormat::Plain
+        );
+    }
+
+    #[test]
+    fn test_escape_borrows_when_unchanged() {
+        assert!(matches!(escape_html("plain"), Cow::Borrowed(_)));
+        assert!(matches!(escape_xml("plain"), Cow::Borrowed(_)));
+        assert!(matches!(escape_json("plain"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_escape_json_control_characters() {
+        assert_eq!(escape_json("a\u{1}b\\"), "a\\u0001b\\\\");
+    }
+
+    #[test]
+    fn test_cdata_splits_terminator() {
+        assert_eq!(cdata(""), "<![CDATA[]]>");
+        assert_eq!(
+            cdata("]]>]]>"),
+            "<![CDATA[]]]]><![CDATA[>]]]]><![CDATA[>]]>"
+        );
+    }
+
+    #[test]
+    fn test_plain_is_verbatim() {
+        assert_eq!(OutputFormat::Plain.escape("<&>"), "<&>");
+    }
+}
@@ -0,0 +1,792 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Fetch Module
+//!
+//! This module provides the `Fetcher` trait, through which
+//! [`Engine::create_template_folder`](crate::Engine::create_template_folder)
+//! downloads remote templates. Engines fetch over HTTP with the
+//! [`HttpFetcher`] when the `remote` feature is enabled, and fail to
+//! fetch otherwise.
+//!
+//! With the `test-util` feature, the `StaticFetcher` serves canned
+//! responses, so flows that download templates can be tested without
+//! network access:
+//!
+//! ```
+//! # #[cfg(feature = "test-util")]
+//! # {
+//! use staticweaver::fetch::StaticFetcher;
+//! use staticweaver::Engine;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let fetcher = StaticFetcher::new();
+//! for file in ["contact.html", "index.html", "page.html", "post.html", "main.js", "sw.js"] {
+//!     fetcher.insert(format!("https://example.com/t/{}", file), "{{title}}");
+//! }
+//!
+//! let mut engine = Engine::new("templates", Duration::from_secs(60));
+//! engine.set_fetcher(Arc::new(fetcher.clone()));
+//! let dir = engine.create_template_folder(Some("https://example.com/t")).unwrap();
+//! assert!(dir.path().join("page.html").is_file());
+//! assert_eq!(fetcher.requests().len(), 6);
+//! # }
+//! ```
+//!
+//! Downloaded templates are returned as a [`TemplateFolder`], which
+//! removes the temporary directory it owns when it is dropped or
+//! [cleaned up](TemplateFolder::cleanup), so repeated builds do not
+//! fill the temporary directory of the system. Downloads can be verified
+//! against checksums and signatures, as described in the
+//! [`integrity`](crate::integrity) module.
+//!
+//! The files of a template set are downloaded concurrently, within the
+//! engine's [`DownloadLimits`]. Every file is attempted, and when
+//! several fail, the error lists each of them as a [`DownloadFailure`].
+//! Files unchanged since an earlier download can be revalidated instead
+//! of downloaded again, as described in the
+//! [`http_cache`](crate::http_cache) module.
+
+use crate::engine::EngineError;
+use fnv::FnvHashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A source of remote files.
+pub trait Fetcher: fmt::Debug + Send + Sync {
+    /// Returns the body of the file at `url`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be fetched or the server
+    /// does not answer with a success status.
+    fn fetch(&self, url: &str) -> Result<String, EngineError>;
+
+    /// Returns the raw body of the file at `url`, for binary files such
+    /// as template packages.
+    ///
+    /// The default implementation returns the bytes of [`Fetcher::fetch`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be fetched or the server
+    /// does not answer with a success status.
+    fn fetch_bytes(&self, url: &str) -> Result<Vec<u8>, EngineError> {
+        self.fetch(url).map(String::into_bytes)
+    }
+
+    /// Returns the body of the file at `url`, unless it is unchanged
+    /// since it was downloaded with `validators`.
+    ///
+    /// The default implementation ignores the validators, and always
+    /// returns the body of [`Fetcher::fetch`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be fetched or the server
+    /// does not answer with a success or "Not Modified" status.
+    fn fetch_if_modified(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> Result<Conditional, EngineError> {
+        let _ = validators;
+        Ok(Conditional::Modified {
+            body: self.fetch(url)?,
+            validators: Validators::default(),
+        })
+    }
+}
+
+/// The validators of a downloaded file, which servers compare to ask
+/// for the file again only when it changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+    /// The `ETag` header of the response, sent back as `If-None-Match`.
+    pub etag: Option<String>,
+    /// The `Last-Modified` header of the response, sent back as
+    /// `If-Modified-Since`.
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Returns whether there is no validator, so that the file cannot
+    /// be requested conditionally.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// The answer to [`Fetcher::fetch_if_modified`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conditional {
+    /// The file is unchanged.
+    NotModified,
+    /// The file changed, or was not requested conditionally.
+    Modified {
+        /// The body of the file.
+        body: String,
+        /// The validators of the new body.
+        validators: Validators,
+    },
+}
+
+/// Returns the error of a response with a non-success `status`.
+#[cfg(any(feature = "remote", feature = "test-util", test))]
+fn status_error(url: &str, status: impl fmt::Display) -> EngineError {
+    EngineError::Render(format!(
+        "Failed to download {}: HTTP {}",
+        url, status
+    ))
+}
+
+/// Fetches files over HTTP.
+#[cfg(feature = "remote")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpFetcher {
+    /// How long a request may take.
+    pub timeout: Duration,
+}
+
+#[cfg(feature = "remote")]
+impl Default for HttpFetcher {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+#[cfg(feature = "remote")]
+impl Fetcher for HttpFetcher {
+    fn fetch(&self, url: &str) -> Result<String, EngineError> {
+        let response = reqwest::blocking::Client::new()
+            .get(url)
+            .timeout(self.timeout)
+            .send()?;
+        if !response.status().is_success() {
+            return Err(status_error(url, response.status()));
+        }
+        Ok(response.text()?)
+    }
+
+    fn fetch_bytes(&self, url: &str) -> Result<Vec<u8>, EngineError> {
+        let response = reqwest::blocking::Client::new()
+            .get(url)
+            .timeout(self.timeout)
+            .send()?;
+        if !response.status().is_success() {
+            return Err(status_error(url, response.status()));
+        }
+        Ok(response.bytes()?.to_vec())
+    }
+
+    fn fetch_if_modified(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> Result<Conditional, EngineError> {
+        use reqwest::header::{
+            ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+        };
+        use reqwest::StatusCode;
+
+        let mut request = reqwest::blocking::Client::new()
+            .get(url)
+            .timeout(self.timeout);
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        let response = request.send()?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Conditional::NotModified);
+        }
+        if !response.status().is_success() {
+            return Err(status_error(url, response.status()));
+        }
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let validators = Validators {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+        Ok(Conditional::Modified {
+            body: response.text()?,
+            validators,
+        })
+    }
+}
+
+/// Fails every fetch, when the `remote` feature is disabled.
+#[cfg(not(feature = "remote"))]
+#[derive(Debug, Clone, Copy)]
+struct Offline;
+
+#[cfg(not(feature = "remote"))]
+impl Fetcher for Offline {
+    fn fetch(&self, url: &str) -> Result<String, EngineError> {
+        Err(EngineError::Render(format!(
+            "Cannot download {}: the `remote` feature is disabled",
+            url
+        )))
+    }
+}
+
+/// How many files engines download at once, and how often they may
+/// request files from the same host.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::fetch::DownloadLimits;
+/// use staticweaver::Engine;
+/// use std::time::Duration;
+///
+/// let mut engine = Engine::new("templates", Duration::from_secs(60));
+/// engine.download_limits = DownloadLimits {
+///     parallelism: 8,
+///     host_interval: Some(Duration::from_millis(100)),
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadLimits {
+    /// The maximum number of files downloaded at once. `0` and `1` both
+    /// download one file at a time, on the calling thread.
+    pub parallelism: usize,
+    /// The minimum time between the starts of two requests to the same
+    /// host, or `None` to request files as fast as they are downloaded.
+    pub host_interval: Option<Duration>,
+}
+
+impl Default for DownloadLimits {
+    fn default() -> Self {
+        Self {
+            parallelism: 4,
+            host_interval: None,
+        }
+    }
+}
+
+/// A file that could not be downloaded, as listed by
+/// [`EngineError::Downloads`].
+#[derive(Debug)]
+pub struct DownloadFailure {
+    /// The URL of the file.
+    pub url: String,
+    /// Why the download failed.
+    pub error: EngineError,
+}
+
+impl fmt::Display for DownloadFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.url, self.error)
+    }
+}
+
+/// Spaces out the requests made to each host.
+#[derive(Debug)]
+struct HostLimiter {
+    interval: Option<Duration>,
+    /// The earliest start of the next request to each host.
+    next: Mutex<FnvHashMap<String, Instant>>,
+}
+
+impl HostLimiter {
+    /// Waits until a request to the host of `url` may start.
+    fn wait(&self, url: &str) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let now = Instant::now();
+        let start = {
+            let mut next = self
+                .next
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let slot = next.entry(host(url).to_string()).or_insert(now);
+            let start = (*slot).max(now);
+            *slot = start + interval;
+            start
+        };
+        thread::sleep(start - now);
+    }
+}
+
+/// Returns the host of `url`, with its port, if any.
+fn host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split(|c| c == '/' || c == '?' || c == '#')
+        .next()
+        .unwrap_or(rest)
+}
+
+/// Downloads every URL of `urls` within `limits`, returning their
+/// bodies in the order of `urls`.
+///
+/// Every URL is attempted even when some fail. A single failure is
+/// returned as it is; several are returned together as
+/// [`EngineError::Downloads`], in the order of `urls`.
+pub(crate) fn fetch_all(
+    fetcher: &Arc<dyn Fetcher>,
+    urls: &[String],
+    limits: &DownloadLimits,
+) -> Result<Vec<String>, EngineError> {
+    let work = Arc::new(Downloads {
+        fetcher: Arc::clone(fetcher),
+        urls: urls.to_vec(),
+        limiter: HostLimiter {
+            interval: limits.host_interval,
+            next: Mutex::default(),
+        },
+        cursor: AtomicUsize::new(0),
+        results: Mutex::new(urls.iter().map(|_| None).collect()),
+    });
+    let workers = limits.parallelism.clamp(1, urls.len().max(1));
+    // The calling thread is one of the workers, and does all the work
+    // if no other thread can be spawned.
+    let handles: Vec<_> = (1..workers)
+        .filter_map(|_| {
+            let work = Arc::clone(&work);
+            thread::Builder::new().spawn(move || work.run()).ok()
+        })
+        .collect();
+    work.run();
+    for handle in handles {
+        let _ = handle.join();
+    }
+
+    let results = std::mem::take(
+        &mut *work
+            .results
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner),
+    );
+    let mut bodies = Vec::with_capacity(urls.len());
+    let mut failures = Vec::new();
+    for (url, result) in urls.iter().zip(results) {
+        match result {
+            Some(Ok(body)) => bodies.push(body),
+            Some(Err(error)) => failures.push(DownloadFailure {
+                url: url.clone(),
+                error,
+            }),
+            None => failures.push(DownloadFailure {
+                url: url.clone(),
+                error: EngineError::Render(format!(
+                    "Download of {} did not complete",
+                    url
+                )),
+            }),
+        }
+    }
+    match failures.len() {
+        0 => Ok(bodies),
+        1 => Err(failures.remove(0).error),
+        _ => Err(EngineError::Downloads(failures)),
+    }
+}
+
+/// The downloads of [`fetch_all`], shared by its workers.
+#[derive(Debug)]
+struct Downloads {
+    fetcher: Arc<dyn Fetcher>,
+    urls: Vec<String>,
+    limiter: HostLimiter,
+    /// The index of the next URL to download.
+    cursor: AtomicUsize,
+    results: Mutex<Vec<Option<Result<String, EngineError>>>>,
+}
+
+impl Downloads {
+    /// Downloads URLs until none is left.
+    fn run(&self) {
+        loop {
+            let index = self.cursor.fetch_add(1, Ordering::Relaxed);
+            let Some(url) = self.urls.get(index) else {
+                return;
+            };
+            self.limiter.wait(url);
+            let result = self.fetcher.fetch(url);
+            self.results
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)[index] =
+                Some(result);
+        }
+    }
+}
+
+/// Returns the fetcher engines use by default.
+pub(crate) fn default_fetcher() -> Arc<dyn Fetcher> {
+    #[cfg(feature = "remote")]
+    let fetcher = HttpFetcher::default();
+    #[cfg(not(feature = "remote"))]
+    let fetcher = Offline;
+    Arc::new(fetcher)
+}
+
+/// A directory of templates, as returned by
+/// [`Engine::create_template_folder`](crate::Engine::create_template_folder).
+///
+/// A folder the engine downloaded into a temporary directory owns that
+/// directory, and removes it when dropped; [`TemplateFolder::keep`]
+/// keeps it instead. Folders that already existed, or were downloaded
+/// into a directory chosen by the caller, are never removed.
+#[derive(Debug)]
+pub struct TemplateFolder {
+    path: PathBuf,
+    owned: bool,
+}
+
+impl TemplateFolder {
+    /// Wraps a temporary directory, removed with the folder.
+    pub(crate) fn temporary(path: PathBuf) -> Self {
+        Self { path, owned: true }
+    }
+
+    /// Creates an empty temporary directory with a random name, only
+    /// accessible to the current user on Unix.
+    pub(crate) fn create_temporary() -> io::Result<Self> {
+        let mut bytes = [0_u8; 8];
+        getrandom::fill(&mut bytes).map_err(|err| {
+            io::Error::new(io::ErrorKind::Other, err.to_string())
+        })?;
+        let name: String =
+            bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let path = std::env::temp_dir()
+            .join(format!("staticweaver-tmp-{}", name));
+        #[cfg(unix)]
+        let builder = {
+            use std::os::unix::fs::DirBuilderExt;
+            let mut builder = fs::DirBuilder::new();
+            let _ = builder.mode(0o700);
+            builder
+        };
+        #[cfg(not(unix))]
+        let builder = fs::DirBuilder::new();
+        builder.create(&path)?;
+        Ok(Self::temporary(path))
+    }
+
+    /// Wraps a directory owned by the caller.
+    pub(crate) fn existing(path: PathBuf) -> Self {
+        Self { path, owned: false }
+    }
+
+    /// Returns the path of the directory.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether the directory is removed with the folder.
+    #[must_use]
+    pub fn is_temporary(&self) -> bool {
+        self.owned
+    }
+
+    /// Keeps the directory after the folder is dropped, returning its
+    /// path.
+    #[must_use]
+    pub fn keep(mut self) -> PathBuf {
+        self.owned = false;
+        std::mem::take(&mut self.path)
+    }
+
+    /// Removes the directory now if the folder owns it, reporting any
+    /// error that dropping the folder would ignore.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::{Context, Engine};
+    /// use std::time::Duration;
+    ///
+    /// let engine = Engine::new("templates", Duration::from_secs(60));
+    /// let dir = tempfile::tempdir()?;
+    /// let folder = engine.create_template_folder(dir.path().to_str())?;
+    /// assert!(!folder.is_temporary());
+    /// folder.cleanup()?;
+    /// assert!(dir.path().is_dir());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn cleanup(mut self) -> io::Result<()> {
+        if !self.owned {
+            return Ok(());
+        }
+        self.owned = false;
+        match fs::remove_dir_all(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                Err(err)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl AsRef<Path> for TemplateFolder {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TemplateFolder {
+    fn drop(&mut self) {
+        if self.owned {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+pub use self::canned::StaticFetcher;
+
+#[cfg(any(test, feature = "test-util"))]
+mod canned {
+    use super::{
+        status_error, Conditional, EngineError, Fetcher, Validators,
+    };
+    use crate::integrity::sha256_hex;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex, MutexGuard};
+
+    /// Serves canned responses instead of fetching over the network.
+    ///
+    /// URLs without a response answer with HTTP 404. Clones share their
+    /// responses and requests, so a test can keep one clone and inspect
+    /// the requests of the engine it handed the other to.
+    ///
+    /// Responses carry an `ETag` derived from their body, so conditional
+    /// requests for unchanged bodies answer "Not Modified".
+    #[derive(Debug, Default, Clone)]
+    pub struct StaticFetcher {
+        state: Arc<Mutex<State>>,
+    }
+
+    #[derive(Debug, Default)]
+    struct State {
+        responses: BTreeMap<String, Result<String, u16>>,
+        requests: Vec<String>,
+        not_modified: usize,
+    }
+
+    impl StaticFetcher {
+        /// Creates a fetcher without responses.
+        #[must_use]
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Answers requests for `url` with `body`.
+        ///
+        /// # Arguments
+        ///
+        /// * `url` - The URL of the file.
+        /// * `body` - The body of the file.
+        pub fn insert(
+            &self,
+            url: impl Into<String>,
+            body: impl Into<String>,
+        ) {
+            let _ = self
+                .state()
+                .responses
+                .insert(url.into(), Ok(body.into()));
+        }
+
+        /// Answers requests for `url` with the HTTP error `status`.
+        ///
+        /// # Arguments
+        ///
+        /// * `url` - The URL of the file.
+        /// * `status` - The HTTP status code, such as `500`.
+        pub fn fail(&self, url: impl Into<String>, status: u16) {
+            let _ =
+                self.state().responses.insert(url.into(), Err(status));
+        }
+
+        /// Returns the URLs requested so far, in order.
+        #[must_use]
+        pub fn requests(&self) -> Vec<String> {
+            self.state().requests.clone()
+        }
+
+        /// Returns the number of requests answered "Not Modified" so
+        /// far.
+        #[must_use]
+        pub fn not_modified(&self) -> usize {
+            self.state().not_modified
+        }
+
+        fn state(&self) -> MutexGuard<'_, State> {
+            match self.state.lock() {
+                Ok(state) => state,
+                Err(poisoned) => poisoned.into_inner(),
+            }
+        }
+    }
+
+    impl Fetcher for StaticFetcher {
+        fn fetch(&self, url: &str) -> Result<String, EngineError> {
+            let mut state = self.state();
+            state.requests.push(url.to_string());
+            match state.responses.get(url) {
+                Some(Ok(body)) => Ok(body.clone()),
+                Some(Err(status)) => Err(status_error(url, status)),
+                None => Err(status_error(url, 404)),
+            }
+        }
+
+        fn fetch_if_modified(
+            &self,
+            url: &str,
+            validators: &Validators,
+        ) -> Result<Conditional, EngineError> {
+            let body = self.fetch(url)?;
+            let etag =
+                format!("\"{}\"", &sha256_hex(body.as_bytes())[..16]);
+            if validators.etag.as_deref() == Some(etag.as_str()) {
+                self.state().not_modified += 1;
+                return Ok(Conditional::NotModified);
+            }
+            Ok(Conditional::Modified {
+                body,
+                validators: Validators {
+                    etag: Some(etag),
+                    last_modified: None,
+                },
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_static_fetcher() {
+        let fetcher = StaticFetcher::new();
+        fetcher.insert("https://example.com/a", "a");
+        fetcher.fail("https://example.com/b", 500);
+
+        assert_eq!(
+            fetcher.fetch("https://example.com/a").unwrap(),
+            "a"
+        );
+        assert_eq!(
+            fetcher.fetch("https://example.com/b").unwrap_err().to_string(),
+            "Render error: Failed to download https://example.com/b: HTTP 500"
+        );
+        assert!(fetcher
+            .clone()
+            .fetch("https://example.com/c")
+            .is_err());
+        assert_eq!(
+            fetcher.requests(),
+            [
+                "https://example.com/a",
+                "https://example.com/b",
+                "https://example.com/c"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fetch_all() {
+        let canned = StaticFetcher::new();
+        let urls: Vec<String> = (0..6)
+            .map(|i| format!("https://example.com/{}", i))
+            .collect();
+        for (i, url) in urls.iter().enumerate() {
+            canned.insert(url.clone(), i.to_string());
+        }
+        let fetcher: Arc<dyn Fetcher> = Arc::new(canned.clone());
+        let limits = DownloadLimits::default();
+        assert_eq!(
+            fetch_all(&fetcher, &urls, &limits).unwrap(),
+            ["0", "1", "2", "3", "4", "5"]
+        );
+        assert_eq!(canned.requests().len(), 6);
+        assert!(fetch_all(&fetcher, &[], &limits).unwrap().is_empty());
+
+        canned.fail(urls[4].clone(), 500);
+        let err = fetch_all(&fetcher, &urls, &limits).unwrap_err();
+        assert_eq!(err.code(), "render");
+        canned.fail(urls[1].clone(), 503);
+        let err = fetch_all(&fetcher, &urls, &limits).unwrap_err();
+        assert_eq!(err.code(), "downloads");
+        assert_eq!(
+            err.to_string(),
+            "Failed to download 2 files: \
+             https://example.com/1: Render error: Failed to download \
+             https://example.com/1: HTTP 503; \
+             https://example.com/4: Render error: Failed to download \
+             https://example.com/4: HTTP 500"
+        );
+    }
+
+    #[test]
+    fn test_fetch_all_host_interval() {
+        let canned = StaticFetcher::new();
+        let urls = [
+            "https://a.example.com/1",
+            "https://a.example.com/2",
+            "https://a.example.com/3",
+            "https://b.example.com/1",
+        ]
+        .map(String::from);
+        for url in &urls {
+            canned.insert(url.clone(), "");
+        }
+        let fetcher: Arc<dyn Fetcher> = Arc::new(canned);
+        let limits = DownloadLimits {
+            parallelism: 4,
+            host_interval: Some(Duration::from_millis(50)),
+        };
+        let start = Instant::now();
+        assert_eq!(
+            fetch_all(&fetcher, &urls, &limits).unwrap().len(),
+            4
+        );
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        assert_eq!(
+            host("https://a.example.com:8080/x?y"),
+            "a.example.com:8080"
+        );
+        assert_eq!(host("file.html"), "file.html");
+    }
+
+    #[test]
+    fn test_create_temporary() {
+        let folder = TemplateFolder::create_temporary().unwrap();
+        let path = folder.path().to_path_buf();
+        assert!(path.is_dir());
+        assert_ne!(
+            TemplateFolder::create_temporary().unwrap().path(),
+            path
+        );
+        drop(folder);
+        assert!(!path.exists());
+    }
+}
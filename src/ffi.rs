@@ -0,0 +1,243 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # FFI Module
+//!
+//! This module exposes the rendering core through a C ABI, so that the
+//! engine can be embedded from other languages, for example from Python
+//! or Node.js build scripts.
+//!
+//! Build a shared library with:
+//!
+//! ```text
+//! cargo rustc --release --features ffi --crate-type cdylib
+//! ```
+//!
+//! The matching C declarations are:
+//!
+//! ```text
+//! typedef struct SwEngine SwEngine;
+//!
+//! SwEngine *sw_engine_new(const char *template_path, uint64_t cache_ttl_secs);
+//! void sw_engine_free(SwEngine *engine);
+//! char *sw_render_template(const SwEngine *engine, const char *template,
+//!                          const char *context_json, char **error);
+//! void sw_free_string(char *s);
+//! ```
+//!
+//! Every string passed in must be NUL-terminated UTF-8. Every string
+//! returned, including error messages, is owned by the caller and must be
+//! released with `sw_free_string`.
+
+use crate::context::Context;
+use crate::engine::Engine;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+use std::time::Duration;
+
+/// Creates an engine loading templates from `template_path`, caching
+/// rendered pages for `cache_ttl_secs` seconds.
+///
+/// Returns a null pointer if `template_path` is null or not UTF-8, or if
+/// `cache_ttl_secs` is zero. Release the engine with [`sw_engine_free`].
+///
+/// # Safety
+///
+/// `template_path` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sw_engine_new(
+    template_path: *const c_char,
+    cache_ttl_secs: u64,
+) -> *mut Engine {
+    match to_str(template_path) {
+        Ok(template_path) if cache_ttl_secs > 0 => {
+            Box::into_raw(Box::new(Engine::new(
+                template_path,
+                Duration::from_secs(cache_ttl_secs),
+            )))
+        }
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Releases an engine created by [`sw_engine_new`].
+///
+/// Does nothing if `engine` is null.
+///
+/// # Safety
+///
+/// `engine` must be null or a pointer returned by [`sw_engine_new`] that
+/// has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn sw_engine_free(engine: *mut Engine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// Renders `template` with the values of the JSON object
+/// `context_json`, as read by [`Context::from_json_str`].
+///
+/// Returns the rendered string, or a null pointer on failure. On
+/// failure, if `error` is not null, `*error` is set to a message
+/// describing the problem. Release both strings with [`sw_free_string`].
+///
+/// # Safety
+///
+/// `engine` must be a live pointer returned by [`sw_engine_new`].
+/// `template` and `context_json` must point to NUL-terminated strings,
+/// and `error` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sw_render_template(
+    engine: *const Engine,
+    template: *const c_char,
+    context_json: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    let rendered = engine
+        .as_ref()
+        .ok_or_else(|| "Engine is null".to_string())
+        .and_then(|engine| {
+            let template = to_str(template)?;
+            let context = Context::from_json_str(to_str(context_json)?)
+                .map_err(|err| err.to_string())?;
+            engine
+                .render_template(template, &context)
+                .map_err(|err| err.to_string())
+        })
+        .and_then(|page| {
+            CString::new(page).map_err(|err| err.to_string())
+        });
+    match rendered {
+        Ok(page) => page.into_raw(),
+        Err(message) => {
+            if !error.is_null() {
+                *error = to_c_string(message);
+            }
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Releases a string returned by this module.
+///
+/// Does nothing if `s` is null.
+///
+/// # Safety
+///
+/// `s` must be null or a string returned by [`sw_render_template`] that
+/// has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn sw_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Borrows a C string as UTF-8.
+///
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string that outlives
+/// the returned reference.
+unsafe fn to_str<'a>(s: *const c_char) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err("String is null".to_string());
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|err| format!("String is not UTF-8: {}", err))
+}
+
+/// Converts an error message to an owned C string, dropping any NUL
+/// bytes.
+fn to_c_string(message: String) -> *mut c_char {
+    CString::new(message.replace('\0', ""))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    #[test]
+    fn test_render_template() {
+        let path = c("templates");
+        let template = c("Hello, {{name}}!");
+        let context = c(r#"{"name": "World"}"#);
+        unsafe {
+            let engine = sw_engine_new(path.as_ptr(), 60);
+            assert!(!engine.is_null());
+
+            let page = sw_render_template(
+                engine,
+                template.as_ptr(),
+                context.as_ptr(),
+                ptr::null_mut(),
+            );
+            assert_eq!(
+                CStr::from_ptr(page).to_str(),
+                Ok("Hello, World!")
+            );
+
+            sw_free_string(page);
+            sw_engine_free(engine);
+        }
+    }
+
+    #[test]
+    fn test_render_template_error() {
+        let path = c("templates");
+        let template = c("Hello, {{name}}!");
+        let context = c("{}");
+        let mut error = ptr::null_mut();
+        unsafe {
+            let engine = sw_engine_new(path.as_ptr(), 60);
+            let page = sw_render_template(
+                engine,
+                template.as_ptr(),
+                context.as_ptr(),
+                &mut error,
+            );
+            assert!(page.is_null());
+            assert!(CStr::from_ptr(error)
+                .to_str()
+                .unwrap()
+                .contains("Unresolved template tag: name"));
+
+            sw_free_string(error);
+            sw_engine_free(engine);
+        }
+    }
+
+    #[test]
+    fn test_null_arguments() {
+        let template = c("{{name}}");
+        let mut error = ptr::null_mut();
+        unsafe {
+            assert!(sw_engine_new(ptr::null(), 60).is_null());
+            assert!(sw_engine_new(template.as_ptr(), 0).is_null());
+            assert!(sw_render_template(
+                ptr::null(),
+                template.as_ptr(),
+                ptr::null(),
+                &mut error,
+            )
+            .is_null());
+            assert_eq!(
+                CStr::from_ptr(error).to_str(),
+                Ok("Engine is null")
+            );
+
+            sw_free_string(error);
+            sw_engine_free(ptr::null_mut());
+            sw_free_string(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Filter Module
+//!
+//! This module provides the built-in filters that can be applied to a
+//! value inside a template tag. Filters follow the key and are separated
+//! by `|`, and are applied from left to right:
+//!
+//! ```text
+//! <description>{{ summary | cdata }}</description>
+//! <a href="{{ title | slugify | absolute_url }}">{{title}}</a>
+//! ```
+
+use crate::escape;
+
+/// A built-in filter applied to a substituted value.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::filter::Filter;
+///
+/// let filter = Filter::from_name("cdata").unwrap();
+/// assert_eq!(filter.apply("a & b", None), "<![CDATA[a & b]]>");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Filter {
+    /// Wraps the value in an XML CDATA section.
+    Cdata,
+    /// Converts the value into a URL slug; see [`slugify`].
+    Slugify,
+    /// Percent-encodes the value; see [`urlencode`].
+    Urlencode,
+    /// Joins the value onto the engine's base URL; see [`absolute_url`].
+    AbsoluteUrl,
+    /// Inserts the value verbatim, without escaping it for the document.
+    Safe,
+}
+
+impl Filter {
+    /// Looks up a built-in filter by the name used in templates.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The filter name, e.g. `cdata`.
+    ///
+    /// # Returns
+    ///
+    /// The filter, or `None` if no built-in filter has that name.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "cdata" => Some(Self::Cdata),
+            "slugify" => Some(Self::Slugify),
+            "urlencode" => Some(Self::Urlencode),
+            "absolute_url" => Some(Self::AbsoluteUrl),
+            "safe" => Some(Self::Safe),
+            _ => None,
+        }
+    }
+
+    /// Applies the filter to `value`.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to transform.
+    /// * `base_url` - The base URL used by `absolute_url`. Without one,
+    ///   the value is returned unchanged by that filter.
+    #[must_use]
+    pub fn apply(self, value: &str, base_url: Option<&str>) -> String {
+        match self {
+            Self::Cdata => escape::cdata(value),
+            Self::Slugify => slugify(value),
+            Self::Urlencode => urlencode(value),
+            Self::AbsoluteUrl => match base_url {
+                Some(base_url) => absolute_url(base_url, value),
+                None => value.to_string(),
+            },
+            Self::Safe => value.to_string(),
+        }
+    }
+
+    /// Returns `true` if the filter's output is already safe for the
+    /// document and must not be escaped again.
+    #[must_use]
+    pub fn is_safe(self) -> bool {
+        matches!(self, Self::Cdata | Self::Safe)
+    }
+}
+
+/// Converts `value` into a lowercase, hyphen-separated URL slug.
+///
+/// Alphanumeric characters are kept and lowercased; every run of other
+/// characters becomes a single `-`, and leading or trailing hyphens are
+/// dropped.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::filter::slugify;
+///
+/// assert_eq!(slugify("Hello, World! 2024"), "hello-world-2024");
+/// assert_eq!(slugify("  Crème brûlée  "), "crème-brûlée");
+/// ```
+#[must_use]
+pub fn slugify(value: &str) -> String {
+    let mut slug = String::with_capacity(value.len());
+    let mut pending_hyphen = false;
+    for c in value.chars() {
+        if c.is_alphanumeric() {
+            if pending_hyphen && !slug.is_empty() {
+                slug.push('-');
+            }
+            pending_hyphen = false;
+            slug.extend(c.to_lowercase());
+        } else {
+            pending_hyphen = true;
+        }
+    }
+    slug
+}
+
+/// Percent-encodes every byte of `value` outside the URL unreserved set
+/// (`A-Z`, `a-z`, `0-9`, `-`, `_`, `.`, `~`).
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::filter::urlencode;
+///
+/// assert_eq!(urlencode("fish & chips/é"), "fish%20%26%20chips%2F%C3%A9");
+/// ```
+#[must_use]
+pub fn urlencode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Joins `path` onto `base_url` with exactly one `/` between them.
+///
+/// Values that are already absolute (containing `://` or starting with
+/// `//`) are returned unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::filter::absolute_url;
+///
+/// assert_eq!(
+///     absolute_url("https://example.com/", "/posts/hello"),
+///     "https://example.com/posts/hello"
+/// );
+/// assert_eq!(
+///     absolute_url("https://example.com", "https://cdn.example.com/a.png"),
+///     "https://cdn.example.com/a.png"
+/// );
+/// ```
+#[must_use]
+pub fn absolute_url(base_url: &str, path: &str) -> String {
+    if path.contains("://") || path.starts_with("//") {
+        return path.to_string();
+    }
+    format!(
+        "{}/{}",
+        base_url.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Filter::from_name("cdata"), Some(Filter::Cdata));
+        assert_eq!(Filter::from_name("unknown"), None);
+    }
+
+    #[test]
+    fn test_cdata_is_safe() {
+        assert!(Filter::Cdata.is_safe());
+        assert!(!Filter::Slugify.is_safe());
+        assert!(Filter::Safe.is_safe());
+        assert_eq!(
+            Filter::from_name("safe").unwrap().apply("<b>", None),
+            "<b>"
+        );
+        assert_eq!(Filter::Cdata.apply("<b>", None), "<![CDATA[<b>]]>");
+    }
+
+    #[test]
+    fn test_slugify_edge_cases() {
+        assert_eq!(slugify(""), "");
+        assert_eq!(slugify("--- ---"), "");
+        assert_eq!(
+            slugify("Rust_2024 -- Edition"),
+            "rust-2024-edition"
+        );
+    }
+
+    #[test]
+    fn test_urlencode_keeps_unreserved() {
+        assert_eq!(urlencode("a-b_c.d~e"), "a-b_c.d~e");
+        assert_eq!(urlencode("a+b=c"), "a%2Bb%3Dc");
+    }
+
+    #[test]
+    fn test_absolute_url_without_base() {
+        assert_eq!(Filter::AbsoluteUrl.apply("/about", None), "/about");
+        assert_eq!(
+            Filter::AbsoluteUrl
+                .apply("about", Some("https://example.com")),
+            "https://example.com/about"
+        );
+        assert_eq!(
+            absolute_url(
+                "https://example.com",
+                "//cdn.example.com/a.js"
+            ),
+            "//cdn.example.com/a.js"
+        );
+    }
+}
@@ -0,0 +1,394 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Function Module
+//!
+//! This module provides the `Functions` registry of template functions.
+//! A tag that is a call to a registered function is replaced by the
+//! function's output:
+//!
+//! ```text
+//! {{ image("photos/beach.jpg", title) }}
+//! ```
+//!
+//! Arguments are separated by commas. Quoted arguments (`"..."` or
+//! `'...'`) and numbers are literals; any other argument names a value,
+//! looked up like a tag key when the function reads it.
+//!
+//! Functions produce markup, so their output is written verbatim: a
+//! function must escape the values it inserts.
+
+use crate::context::Context;
+use crate::engine::{Engine, EngineError};
+use crate::profile::ProfileKind;
+use fnv::FnvHashMap;
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// The last revision given to a registry of functions or blocks.
+static REVISION: AtomicU64 = AtomicU64::new(0);
+
+/// Returns a revision no registry of functions or blocks had before,
+/// given to a registry whenever it changes, so that render cache keys
+/// tell its versions apart.
+pub(crate) fn next_revision() -> u64 {
+    REVISION.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// The signature of a template function.
+type FunctionFn =
+    dyn Fn(&Call<'_>) -> Result<String, EngineError> + Send + Sync;
+
+/// An argument of a function call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arg<'a> {
+    /// A quoted string or a number, without its quotes.
+    Literal(&'a str),
+    /// The name of a value.
+    Key(&'a str),
+}
+
+/// A call to a template function, as passed to the function.
+#[derive(Debug)]
+pub struct Call<'a> {
+    name: &'a str,
+    args: Vec<Arg<'a>>,
+    engine: &'a Engine,
+    context: &'a Context,
+}
+
+impl<'a> Call<'a> {
+    /// Returns the name of the called function.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    /// Returns the arguments of the call.
+    #[must_use]
+    pub fn args(&self) -> &[Arg<'a>] {
+        &self.args
+    }
+
+    /// Returns the engine rendering the call.
+    #[must_use]
+    pub fn engine(&self) -> &'a Engine {
+        self.engine
+    }
+
+    /// Returns the context of the template.
+    #[must_use]
+    pub fn context(&self) -> &'a Context {
+        self.context
+    }
+
+    /// Returns the value of the argument at `index`: a literal as
+    /// written, or the value a key names.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EngineError::Render` if there is no such argument or its
+    /// key cannot be resolved.
+    pub fn value(
+        &self,
+        index: usize,
+    ) -> Result<Cow<'a, str>, EngineError> {
+        match self.args.get(index) {
+            Some(Arg::Literal(value)) => Ok(Cow::Borrowed(value)),
+            Some(Arg::Key(key)) => {
+                self.engine.lookup(key, self.context).ok_or_else(|| {
+                    EngineError::Render(format!(
+                        "Unresolved argument of {}: {}",
+                        self.name, key
+                    ))
+                })
+            }
+            None => Err(EngineError::Render(format!(
+                "Missing argument {} of {}",
+                index + 1,
+                self.name
+            ))),
+        }
+    }
+
+    /// Returns the value of the argument at `index`, or `None` if the
+    /// call has fewer arguments.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EngineError::Render` if the argument is a key that
+    /// cannot be resolved.
+    pub fn optional(
+        &self,
+        index: usize,
+    ) -> Result<Option<Cow<'a, str>>, EngineError> {
+        if index < self.args.len() {
+            self.value(index).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Template functions registered by name.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::escape::escape_html;
+/// use staticweaver::{Context, Engine};
+/// use std::time::Duration;
+///
+/// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+/// engine.functions.register("link", |call| {
+///     Ok(format!(
+///         "<a href=\"{}\">{}</a>",
+///         escape_html(&call.value(0)?),
+///         escape_html(&call.value(1)?)
+///     ))
+/// });
+///
+/// let mut context = Context::new();
+/// context.set("title", "Home & Away");
+/// let html = engine
+///     .render_template("{{ link(\"/\", title) }}", &context)
+///     .unwrap();
+/// assert_eq!(html, "<a href=\"/\">Home &amp; Away</a>");
+/// ```
+#[derive(Clone, Default)]
+pub struct Functions {
+    functions: FnvHashMap<String, Arc<FunctionFn>>,
+    /// Changes whenever a function is registered or removed.
+    revision: u64,
+}
+
+impl fmt::Debug for Functions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.functions.keys().collect();
+        names.sort_unstable();
+        f.debug_set().entries(names).finish()
+    }
+}
+
+impl Functions {
+    /// Creates an empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the function `name`, replacing any previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name templates call the function by.
+    /// * `function` - The function, returning the markup to insert.
+    pub fn register<F>(&mut self, name: &str, function: F)
+    where
+        F: Fn(&Call<'_>) -> Result<String, EngineError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let _ =
+            self.functions.insert(name.to_string(), Arc::new(function));
+        self.revision = next_revision();
+    }
+
+    /// Removes the function `name`, returning whether it was registered.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the function.
+    pub fn unregister(&mut self, name: &str) -> bool {
+        let removed = self.functions.remove(name).is_some();
+        if removed {
+            self.revision = next_revision();
+        }
+        removed
+    }
+
+    /// Returns the revision of the registry, which changes whenever a
+    /// function is registered or removed.
+    pub(crate) fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Returns whether the function `name` is registered.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the function.
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+
+    /// Calls the function named by the call expression `tag`.
+    ///
+    /// Returns `None` if `tag` is not a call expression.
+    pub(crate) fn call(
+        &self,
+        tag: &str,
+        engine: &Engine,
+        context: &Context,
+    ) -> Option<Result<String, EngineError>> {
+        let (name, args) = split_call(tag)?;
+        Some(parse_args(args).and_then(|args| {
+            let function =
+                self.functions.get(name).ok_or_else(|| {
+                    EngineError::Render(format!(
+                        "Unknown function: {}",
+                        name
+                    ))
+                })?;
+            engine.profile(ProfileKind::Function, name, || {
+                function(&Call {
+                    name,
+                    args,
+                    engine,
+                    context,
+                })
+            })
+        }))
+    }
+}
+
+/// Returns whether `tag` is a call expression `name(args)`.
+pub(crate) fn is_call(tag: &str) -> bool {
+    split_call(tag).is_some()
+}
+
+/// Splits a call expression `name(args)` into its name and arguments.
+pub(crate) fn split_call(tag: &str) -> Option<(&str, &str)> {
+    let args = tag.strip_suffix(')')?;
+    let open = args.find('(')?;
+    let name = args[..open].trim_end();
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .map_or(false, |c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_');
+    valid.then(|| (name, &args[open + 1..]))
+}
+
+/// Parses the comma-separated arguments of a call.
+fn parse_args(input: &str) -> Result<Vec<Arg<'_>>, EngineError> {
+    let mut args = Vec::new();
+    let mut rest = input.trim();
+    while !rest.is_empty() {
+        let (arg, after) = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let value = &rest[1..];
+                let end = value.find(quote).ok_or_else(|| {
+                    EngineError::InvalidTemplate(format!(
+                        "Unterminated string in arguments: {}",
+                        input
+                    ))
+                })?;
+                (Arg::Literal(&value[..end]), &value[end + 1..])
+            }
+            _ => {
+                let end = rest.find(',').unwrap_or(rest.len());
+                let value = rest[..end].trim();
+                let arg = if value.parse::<f64>().is_ok() {
+                    Arg::Literal(value)
+                } else {
+                    Arg::Key(value)
+                };
+                (arg, &rest[end..])
+            }
+        };
+        if matches!(arg, Arg::Key("")) {
+            return Err(EngineError::InvalidTemplate(format!(
+                "Empty argument in: {}",
+                input
+            )));
+        }
+        args.push(arg);
+
+        let after = after.trim_start();
+        rest = match after.strip_prefix(',') {
+            Some(after) if !after.trim().is_empty() => {
+                after.trim_start()
+            }
+            Some(_) => {
+                return Err(EngineError::InvalidTemplate(format!(
+                    "Trailing ',' in arguments: {}",
+                    input
+                )))
+            }
+            None if after.is_empty() => after,
+            None => {
+                return Err(EngineError::InvalidTemplate(format!(
+                    "Expected ',' between arguments: {}",
+                    input
+                )))
+            }
+        };
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_split_call() {
+        assert_eq!(split_call("image(a, b)"), Some(("image", "a, b")));
+        assert_eq!(split_call("og_tags ()"), Some(("og_tags", "")));
+        assert_eq!(split_call("title"), None);
+        assert_eq!(split_call("(a)"), None);
+        assert_eq!(split_call("a b(c)"), None);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse_args(r#" "a, b" , 'c' ,12.5, page.title "#).unwrap(),
+            [
+                Arg::Literal("a, b"),
+                Arg::Literal("c"),
+                Arg::Literal("12.5"),
+                Arg::Key("page.title"),
+            ]
+        );
+        assert!(parse_args("").unwrap().is_empty());
+        for input in ["\"a", "a,", "a,,b", "\"a\" b"] {
+            assert!(matches!(
+                parse_args(input),
+                Err(EngineError::InvalidTemplate(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_call() {
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        engine.functions.register("join", |call| {
+            let mut joined = call.value(0)?.into_owned();
+            if let Some(more) = call.optional(1)? {
+                joined.push_str(&more);
+            }
+            Ok(joined)
+        });
+        let mut context = Context::new();
+        context.set("name", "<b>");
+
+        let render =
+            |template| engine.render_template(template, &context);
+        assert_eq!(render("{{ join(name, '!') }}").unwrap(), "<b>!");
+        assert_eq!(render("{{join(\"a\")}}").unwrap(), "a");
+        assert!(render("{{ join() }}").is_err());
+        assert!(render("{{ join(missing) }}").is_err());
+        assert!(render("{{ split(name) }}").is_err());
+        assert_eq!(
+            format!("{:?}", engine.functions),
+            "{\"csp_nonce\", \"csp_policy\", \"join\", \"meta_tags\", \"now\", \"og_tags\", \"sri\"}"
+        );
+    }
+}
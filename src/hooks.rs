@@ -0,0 +1,290 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Hooks Module
+//!
+//! This module provides the hooks an engine runs around every page it
+//! serves, for cross-cutting concerns such as per-page feature flags,
+//! analytics snippets, or timing.
+//!
+//! Hooks registered with
+//! [`Engine::on_before_render`](crate::Engine::on_before_render) run
+//! before the render cache is consulted. They may change the context of
+//! the page, which then selects its cache entry, and decide what
+//! happens next with a [`BeforeRender`]. Hooks registered with
+//! [`Engine::on_after_render`](crate::Engine::on_after_render) run after
+//! the page is served, cache hits included, and may change or replace
+//! the result in [`AfterRender`]. The render cache always stores pages
+//! as rendered, before any after hook.
+//!
+//! ```
+//! use staticweaver::hooks::BeforeRender;
+//! use staticweaver::loader::MemoryLoader;
+//! use staticweaver::{Context, Engine};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let loader = MemoryLoader::new();
+//! loader.insert("templates/page.html", "<body>{{banner}}</body>");
+//! let mut engine = Engine::new("templates", Duration::from_secs(60));
+//! engine.set_loader(Arc::new(loader));
+//!
+//! engine.on_before_render(|context, layout| {
+//!     context.set("banner", format!("Welcome to {}", layout));
+//!     BeforeRender::Continue
+//! });
+//! engine.on_after_render(|after| {
+//!     if let Ok(page) = &mut after.result {
+//!         *page = page.replace("</body>", "<script src=\"/a.js\"></script></body>");
+//!     }
+//! });
+//!
+//! let page = engine.render_page(&Context::new(), "page").unwrap();
+//! assert_eq!(page, "<body>Welcome to page<script src=\"/a.js\"></script></body>");
+//! ```
+//!
+//! Hooks run in the order they were registered. A hook that returns
+//! [`BeforeRender::Respond`] or [`BeforeRender::Veto`] skips the
+//! remaining before hooks and the render, but not the after hooks.
+
+use crate::context::Context;
+use crate::engine::{CacheStatus, EngineError};
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// What a before hook decides for the page being served.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BeforeRender {
+    /// Serve the page, with the context as the hook left it.
+    Continue,
+    /// Serve this output instead of rendering the page. The output is
+    /// not cached.
+    Respond(String),
+    /// Refuse to serve the page, failing with
+    /// [`EngineError::Vetoed`] and this reason.
+    Veto(String),
+}
+
+/// The outcome of serving a page, as seen and changed by after hooks.
+#[derive(Debug)]
+pub struct AfterRender<'a> {
+    /// The layout of the page.
+    pub layout: &'a str,
+    /// The time taken to serve the page, before hooks included.
+    pub duration: Duration,
+    /// Whether the page was served from a cache, or
+    /// [`CacheStatus::Bypass`] if a before hook responded or vetoed.
+    pub cache: CacheStatus,
+    /// The page, or the error that prevented serving it.
+    pub result: Result<String, EngineError>,
+}
+
+/// The signature of a before hook.
+type BeforeFn =
+    dyn Fn(&mut Context, &str) -> BeforeRender + Send + Sync;
+
+/// The signature of an after hook.
+type AfterFn = dyn Fn(&mut AfterRender<'_>) + Send + Sync;
+
+/// The hooks of an engine.
+#[derive(Clone, Default)]
+pub(crate) struct RenderHooks {
+    before: Vec<Arc<BeforeFn>>,
+    after: Vec<Arc<AfterFn>>,
+}
+
+impl fmt::Debug for RenderHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RenderHooks")
+            .field("before", &self.before.len())
+            .field("after", &self.after.len())
+            .finish()
+    }
+}
+
+impl RenderHooks {
+    /// Adds a before hook.
+    pub(crate) fn push_before<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut Context, &str) -> BeforeRender
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.before.push(Arc::new(hook));
+    }
+
+    /// Adds an after hook.
+    pub(crate) fn push_after<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut AfterRender<'_>) + Send + Sync + 'static,
+    {
+        self.after.push(Arc::new(hook));
+    }
+
+    /// Returns whether there is no hook.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.after.is_empty()
+    }
+
+    /// Serves the page of `context` with `render`, running the hooks
+    /// around it.
+    pub(crate) fn run<F>(
+        &self,
+        context: &Context,
+        layout: &str,
+        render: F,
+    ) -> Result<(Arc<str>, CacheStatus), EngineError>
+    where
+        F: FnOnce(
+            &Context,
+        )
+            -> Result<(Arc<str>, CacheStatus), EngineError>,
+    {
+        if self.is_empty() {
+            return render(context);
+        }
+        let start = Instant::now();
+        let mut context = Cow::Borrowed(context);
+        let mut decision = BeforeRender::Continue;
+        for hook in &self.before {
+            decision = hook(context.to_mut(), layout);
+            if decision != BeforeRender::Continue {
+                break;
+            }
+        }
+        let served = match decision {
+            BeforeRender::Continue => render(&context),
+            BeforeRender::Respond(output) => {
+                Ok((Arc::from(output), CacheStatus::Bypass))
+            }
+            BeforeRender::Veto(reason) => Err(EngineError::Vetoed {
+                layout: layout.to_string(),
+                reason,
+            }),
+        };
+        if self.after.is_empty() {
+            return served;
+        }
+
+        let (page, cache, result) = match served {
+            Ok((page, cache)) => {
+                let output = page.to_string();
+                (Some(page), cache, Ok(output))
+            }
+            Err(err) => (None, CacheStatus::Bypass, Err(err)),
+        };
+        let mut after = AfterRender {
+            layout,
+            duration: start.elapsed(),
+            cache,
+            result,
+        };
+        for hook in &self.after {
+            hook(&mut after);
+        }
+        match (after.result, page) {
+            // Unchanged pages keep sharing the cached page.
+            (Ok(output), Some(page)) if *output == *page => {
+                Ok((page, after.cache))
+            }
+            (Ok(output), _) => Ok((Arc::from(output), after.cache)),
+            (Err(err), _) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::MemoryLoader;
+    use crate::Engine;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn engine() -> Engine {
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/page.html", "{{lang}}");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        engine
+    }
+
+    #[test]
+    fn test_before_render() {
+        let mut engine = engine();
+        engine.on_before_render(|context, _| {
+            if context.get("lang").is_none() {
+                context.set("lang", "en");
+            }
+            BeforeRender::Continue
+        });
+        engine.on_before_render(|context, layout| {
+            match (layout, context.get("lang").map(String::as_str)) {
+                ("page", Some("xx")) => {
+                    BeforeRender::Veto("unknown language".to_string())
+                }
+                ("static", _) => {
+                    BeforeRender::Respond("fixed".to_string())
+                }
+                _ => BeforeRender::Continue,
+            }
+        });
+
+        let mut context = Context::new();
+        assert_eq!(engine.render_page(&context, "page").unwrap(), "en");
+        context.set("lang", "fr");
+        assert_eq!(engine.render_page(&context, "page").unwrap(), "fr");
+        assert_eq!(engine.render_cache().len(), 2);
+        assert_eq!(
+            engine.render_page(&context, "static").unwrap(),
+            "fixed"
+        );
+        assert_eq!(engine.render_cache().len(), 2);
+
+        context.set("lang", "xx");
+        let err = engine.render_page(&context, "page").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Render of 'page' vetoed: unknown language"
+        );
+
+        engine.clear_render_hooks();
+        assert_eq!(engine.render_page(&context, "page").unwrap(), "xx");
+    }
+
+    #[test]
+    fn test_after_render() {
+        let mut engine = engine();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        engine.on_after_render(move |after| {
+            let _ = counter.fetch_add(1, Ordering::Relaxed);
+            after.result = match &after.result {
+                Ok(page) => Ok(format!("{}!", page)),
+                Err(_) => Ok("fallback".to_string()),
+            };
+        });
+
+        let mut context = Context::new();
+        context.set("lang", "en");
+        let options = crate::engine::RenderOptions::new();
+        let page =
+            engine.render_page_detailed(&options, &context, "page");
+        assert_eq!(&*page.unwrap().output, "en!");
+        let page = engine
+            .render_page_detailed(&options, &context, "page")
+            .unwrap();
+        assert_eq!(page.cache, CacheStatus::Hit);
+        assert_eq!(
+            page.content_hash,
+            crate::integrity::sha256_hex(b"en!")
+        );
+        assert_eq!(
+            engine.render_page(&context, "missing").unwrap(),
+            "fallback"
+        );
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+    }
+}
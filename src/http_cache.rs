@@ -0,0 +1,315 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # HTTP Cache Module
+//!
+//! This module provides the `CachingFetcher`, which keeps the remote
+//! templates it downloads in an [`HttpCache`] directory, with the `ETag`
+//! and `Last-Modified` validators of their responses. Later downloads
+//! of the same files, including those of later processes such as CI
+//! runs sharing the directory, send conditional requests, and reuse the
+//! stored file when the server answers "Not Modified":
+//!
+//! ```
+//! # #[cfg(feature = "test-util")]
+//! # {
+//! use staticweaver::fetch::StaticFetcher;
+//! use staticweaver::http_cache::{CachingFetcher, HttpCache};
+//! use staticweaver::Engine;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let server = StaticFetcher::new();
+//! for file in ["contact.html", "index.html", "page.html", "post.html", "main.js", "sw.js"] {
+//!     server.insert(format!("https://example.com/t/{}", file), "{{title}}");
+//! }
+//! let dir = tempfile::tempdir().unwrap();
+//!
+//! let mut engine = Engine::new("templates", Duration::from_secs(60));
+//! engine.set_fetcher(Arc::new(server.clone()));
+//! let cache = HttpCache::new(dir.path());
+//! engine.set_fetcher(Arc::new(CachingFetcher::new(Arc::clone(engine.fetcher()), cache)));
+//!
+//! engine.create_template_folder(Some("https://example.com/t")).unwrap();
+//! engine.create_template_folder(Some("https://example.com/t")).unwrap();
+//! assert_eq!(server.not_modified(), 6);
+//! # }
+//! ```
+//!
+//! The cache is best effort, like the [disk cache](crate::disk_cache):
+//! entries that cannot be read are downloaded in full, and responses
+//! that cannot be written, or carry no validator, are not stored.
+//! Binary files fetched with [`Fetcher::fetch_bytes`] are not cached.
+
+use crate::engine::EngineError;
+use crate::fetch::{Conditional, Fetcher, Validators};
+use crate::integrity::sha256_hex;
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The extension of the files of cached responses.
+pub const ENTRY_EXTENSION: &str = "http";
+
+/// A directory of downloaded files and their validators, used by
+/// [`CachingFetcher`].
+///
+/// Each file is stored as a JSON object in a file named by the SHA-256
+/// checksum of its URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpCache {
+    dir: PathBuf,
+}
+
+impl HttpCache {
+    /// Creates a cache storing files in `dir`, which is created when the
+    /// first file is stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The cache directory, such as `.cache/http`.
+    #[must_use]
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Returns the cache directory.
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the number of files stored.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries().count()
+    }
+
+    /// Returns whether no file is stored.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries().next().is_none()
+    }
+
+    /// Removes every stored file, leaving other files in the directory
+    /// alone.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file cannot be removed.
+    pub fn clear(&self) -> io::Result<()> {
+        for entry in self.entries() {
+            fs::remove_file(entry)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the stored body of `url` and its validators, if any.
+    pub(crate) fn get(
+        &self,
+        url: &str,
+    ) -> Option<(Validators, String)> {
+        let entry: Value = serde_json::from_str(
+            &fs::read_to_string(self.path(url)).ok()?,
+        )
+        .ok()?;
+        if entry.get("url").and_then(Value::as_str) != Some(url) {
+            return None;
+        }
+        let field = |name: &str| {
+            entry.get(name).and_then(Value::as_str).map(str::to_string)
+        };
+        let validators = Validators {
+            etag: field("etag"),
+            last_modified: field("last_modified"),
+        };
+        Some((validators, field("body")?))
+    }
+
+    /// Stores `body`, the file at `url`, with its validators.
+    ///
+    /// The entry is written to a temporary file first and then renamed,
+    /// so that concurrent builds never read a partial entry.
+    pub(crate) fn insert(
+        &self,
+        url: &str,
+        validators: &Validators,
+        body: &str,
+    ) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let entry = json!({
+            "url": url,
+            "etag": validators.etag,
+            "last_modified": validators.last_modified,
+            "body": body,
+        });
+        let path = self.path(url);
+        let partial =
+            path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&partial, entry.to_string())?;
+        fs::rename(&partial, &path).map_err(|err| {
+            let _ = fs::remove_file(&partial);
+            err
+        })
+    }
+
+    /// Returns the file of the entry of `url`.
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir
+            .join(sha256_hex(url.as_bytes()))
+            .with_extension(ENTRY_EXTENSION)
+    }
+
+    /// Returns the files of the stored entries.
+    fn entries(&self) -> impl Iterator<Item = PathBuf> {
+        fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .map_or(false, |ext| ext == ENTRY_EXTENSION)
+            })
+    }
+}
+
+/// A fetcher that revalidates the files stored in an [`HttpCache`]
+/// instead of downloading them again, as described in the
+/// [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct CachingFetcher {
+    inner: Arc<dyn Fetcher>,
+    cache: HttpCache,
+}
+
+impl CachingFetcher {
+    /// Creates a fetcher downloading files with `inner`, and storing
+    /// them in `cache`.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The fetcher sending requests, such as the
+    ///   [`HttpFetcher`](crate::fetch::HttpFetcher).
+    /// * `cache` - The cache of downloaded files.
+    #[must_use]
+    pub fn new(inner: Arc<dyn Fetcher>, cache: HttpCache) -> Self {
+        Self { inner, cache }
+    }
+
+    /// Returns the cache of downloaded files.
+    #[must_use]
+    pub fn cache(&self) -> &HttpCache {
+        &self.cache
+    }
+}
+
+impl Fetcher for CachingFetcher {
+    fn fetch(&self, url: &str) -> Result<String, EngineError> {
+        let stored = self.cache.get(url);
+        let validators = stored
+            .as_ref()
+            .map(|(validators, _)| validators.clone())
+            .unwrap_or_default();
+        match self.inner.fetch_if_modified(url, &validators)? {
+            Conditional::NotModified => match stored {
+                Some((_, body)) => Ok(body),
+                // The server matched validators that were not sent.
+                None => self.inner.fetch(url),
+            },
+            Conditional::Modified { body, validators } => {
+                if !validators.is_empty() {
+                    let _ = self.cache.insert(url, &validators, &body);
+                }
+                Ok(body)
+            }
+        }
+    }
+
+    fn fetch_bytes(&self, url: &str) -> Result<Vec<u8>, EngineError> {
+        self.inner.fetch_bytes(url)
+    }
+
+    fn fetch_if_modified(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> Result<Conditional, EngineError> {
+        self.inner.fetch_if_modified(url, validators)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::StaticFetcher;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_caching_fetcher() {
+        let dir = TempDir::new().unwrap();
+        let server = StaticFetcher::new();
+        server.insert("https://example.com/a", "a");
+        let fetcher = CachingFetcher::new(
+            Arc::new(server.clone()),
+            HttpCache::new(dir.path().join("http")),
+        );
+
+        assert_eq!(
+            fetcher.fetch("https://example.com/a").unwrap(),
+            "a"
+        );
+        assert_eq!(fetcher.cache().len(), 1);
+        assert_eq!(
+            fetcher.fetch("https://example.com/a").unwrap(),
+            "a"
+        );
+        assert_eq!(server.not_modified(), 1);
+
+        server.insert("https://example.com/a", "b");
+        assert_eq!(
+            fetcher.fetch("https://example.com/a").unwrap(),
+            "b"
+        );
+        assert_eq!(server.not_modified(), 1);
+        let (validators, body) =
+            fetcher.cache().get("https://example.com/a").unwrap();
+        assert_eq!(body, "b");
+        assert!(validators.etag.is_some());
+
+        server.fail("https://example.com/a", 500);
+        assert!(fetcher.fetch("https://example.com/a").is_err());
+        assert!(fetcher.fetch("https://example.com/b").is_err());
+
+        fs::write(fetcher.cache().dir().join("README"), "kept")
+            .unwrap();
+        fetcher.cache().clear().unwrap();
+        assert!(fetcher.cache().is_empty());
+        assert!(fetcher.cache().dir().join("README").is_file());
+    }
+
+    #[test]
+    fn test_unvalidated_responses() {
+        #[derive(Debug)]
+        struct Plain;
+
+        impl Fetcher for Plain {
+            fn fetch(&self, _: &str) -> Result<String, EngineError> {
+                Ok("plain".to_string())
+            }
+        }
+
+        let dir = TempDir::new().unwrap();
+        let fetcher = CachingFetcher::new(
+            Arc::new(Plain),
+            HttpCache::new(dir.path()),
+        );
+        assert_eq!(
+            fetcher.fetch("https://example.com/a").unwrap(),
+            "plain"
+        );
+        assert!(fetcher.cache().is_empty());
+    }
+}
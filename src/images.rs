@@ -0,0 +1,371 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Images Module
+//!
+//! This module provides the `ImageProcessor` struct, which resizes and
+//! converts the images referenced by templates into responsive
+//! derivatives, and the `image()` template function that emits their
+//! markup:
+//!
+//! ```text
+//! {{ image("photos/beach.jpg", "A sandy beach") }}
+//!
+//! <img src="/images/photos/beach-1200.jpg"
+//!      srcset="/images/photos/beach-480.jpg 480w, ..., /images/photos/beach-1200.jpg 1200w"
+//!      width="1200" height="800" alt="A sandy beach">
+//! ```
+//!
+//! Derivatives are written once and regenerated only when their source
+//! image is newer, so the function can run on every page of every build.
+//! This module requires the `images` feature.
+
+use crate::engine::{Engine, EngineError};
+use crate::escape::escape_html;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The name of the template function registered by
+/// [`ImageProcessor::register`].
+pub const IMAGE_FUNCTION: &str = "image";
+
+/// The default widths of image derivatives, in pixels.
+pub const DEFAULT_WIDTHS: [u32; 3] = [480, 800, 1200];
+
+/// A resized copy of a source image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Derivative {
+    /// The path of the derivative file.
+    pub path: PathBuf,
+    /// The URL of the derivative.
+    pub url: String,
+    /// The width of the derivative, in pixels.
+    pub width: u32,
+    /// The height of the derivative, in pixels.
+    pub height: u32,
+}
+
+/// Resizes and converts source images into responsive derivatives.
+///
+/// # Examples
+///
+/// ```no_run
+/// use staticweaver::images::ImageProcessor;
+/// use staticweaver::Engine;
+/// use std::time::Duration;
+///
+/// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+/// let mut images = ImageProcessor::new("content", "public/images", "/images");
+/// images.format = Some("webp".to_string());
+/// images.register(&mut engine);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageProcessor {
+    /// The directory image paths are relative to.
+    pub source_dir: PathBuf,
+    /// The directory derivatives are written to.
+    pub output_dir: PathBuf,
+    /// The URL of `output_dir`, e.g. `/images`.
+    pub url_prefix: String,
+    /// The widths of the derivatives, in pixels. Widths larger than the
+    /// source image are skipped; the source width is used if all are.
+    pub widths: Vec<u32>,
+    /// The extension of the format derivatives are converted to, e.g.
+    /// `webp`. `None` keeps the format of the source image.
+    pub format: Option<String>,
+    /// The `sizes` attribute of the `<img>` markup, if any.
+    pub sizes: Option<String>,
+}
+
+impl ImageProcessor {
+    /// Creates a processor with the [`DEFAULT_WIDTHS`], keeping the
+    /// format of source images.
+    ///
+    /// # Arguments
+    ///
+    /// * `source_dir` - The directory image paths are relative to.
+    /// * `output_dir` - The directory derivatives are written to.
+    /// * `url_prefix` - The URL of `output_dir`.
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(
+        source_dir: P,
+        output_dir: Q,
+        url_prefix: &str,
+    ) -> Self {
+        Self {
+            source_dir: source_dir.as_ref().to_path_buf(),
+            output_dir: output_dir.as_ref().to_path_buf(),
+            url_prefix: url_prefix.trim_end_matches('/').to_string(),
+            widths: DEFAULT_WIDTHS.to_vec(),
+            format: None,
+            sizes: None,
+        }
+    }
+
+    /// Writes the derivatives of the image at `path`, relative to
+    /// `source_dir`, skipping those already newer than the image.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the source image.
+    ///
+    /// # Returns
+    ///
+    /// The derivatives, narrowest first.
+    ///
+    /// # Errors
+    ///
+    /// * `EngineError::Render` - If `path` leaves `source_dir`, or the
+    ///   image cannot be decoded or encoded.
+    /// * `EngineError::Io` - If a file cannot be read or written.
+    pub fn process(
+        &self,
+        path: &str,
+    ) -> Result<Vec<Derivative>, EngineError> {
+        let relative = Path::new(path);
+        if relative
+            .components()
+            .any(|part| !matches!(part, Component::Normal(_)))
+        {
+            return Err(EngineError::Render(format!(
+                "Image path must be relative to the source directory: {}",
+                path
+            )));
+        }
+        let source = self.source_dir.join(relative);
+        let (width, height) = image::image_dimensions(&source)
+            .map_err(|err| image_error(path, &err))?;
+
+        let mut widths: Vec<u32> = self
+            .widths
+            .iter()
+            .copied()
+            .filter(|&w| w > 0 && w <= width)
+            .collect();
+        if widths.is_empty() {
+            widths.push(width);
+        }
+        widths.sort_unstable();
+        widths.dedup();
+
+        let extension = match &self.format {
+            Some(format) => format.to_ascii_lowercase(),
+            None => relative
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+                .unwrap_or_default(),
+        };
+        let stem = relative.with_extension("");
+        let stem = stem.to_string_lossy().replace('\\', "/");
+        let modified = fs::metadata(&source)?.modified().ok();
+
+        let mut decoded: Option<DynamicImage> = None;
+        let mut derivatives = Vec::with_capacity(widths.len());
+        for w in widths {
+            let h = scaled_height(width, height, w);
+            let name = format!("{}-{}.{}", stem, w, extension);
+            let dest = self.output_dir.join(&name);
+            let fresh = match (fs::metadata(&dest), modified) {
+                (Ok(meta), Some(modified)) => meta
+                    .modified()
+                    .map_or(false, |time| time >= modified),
+                _ => false,
+            };
+            if !fresh {
+                if decoded.is_none() {
+                    decoded = Some(
+                        image::open(&source)
+                            .map_err(|err| image_error(path, &err))?,
+                    );
+                }
+                if let Some(image) = &decoded {
+                    let resized =
+                        image.resize_exact(w, h, FilterType::Lanczos3);
+                    if let Some(parent) = dest.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    encodable(resized, &extension)
+                        .save(&dest)
+                        .map_err(|err| image_error(path, &err))?;
+                }
+            }
+            derivatives.push(Derivative {
+                path: dest,
+                url: format!("{}/{}", self.url_prefix, name),
+                width: w,
+                height: h,
+            });
+        }
+        Ok(derivatives)
+    }
+
+    /// Writes the derivatives of the image at `path` and returns the
+    /// `<img>` markup referencing them.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the source image.
+    /// * `alt` - The alternative text of the image.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`ImageProcessor::process`].
+    pub fn img_tag(
+        &self,
+        path: &str,
+        alt: &str,
+    ) -> Result<String, EngineError> {
+        let derivatives = self.process(path)?;
+        let mut html = String::from("<img");
+        if let Some(largest) = derivatives.last() {
+            let srcset = derivatives
+                .iter()
+                .map(|d| format!("{} {}w", d.url, d.width))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let _ = write!(
+                html,
+                " src=\"{}\" srcset=\"{}\"",
+                escape_html(&largest.url),
+                escape_html(&srcset)
+            );
+            if let Some(sizes) = &self.sizes {
+                let _ =
+                    write!(html, " sizes=\"{}\"", escape_html(sizes));
+            }
+            let _ = write!(
+                html,
+                " width=\"{}\" height=\"{}\"",
+                largest.width, largest.height
+            );
+        }
+        let _ = write!(html, " alt=\"{}\">", escape_html(alt));
+        Ok(html)
+    }
+
+    /// Registers the `image(path, alt)` template function on `engine`,
+    /// which returns the markup of [`ImageProcessor::img_tag`]. `alt`
+    /// defaults to an empty string.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - The engine to register the function on.
+    pub fn register(self, engine: &mut Engine) {
+        engine.functions.register(IMAGE_FUNCTION, move |call| {
+            let path = call.value(0)?;
+            let alt = call.optional(1)?.unwrap_or_default();
+            self.img_tag(&path, &alt)
+        });
+    }
+}
+
+/// Returns the height of an image of `width` by `height` pixels scaled
+/// to `scaled_width`, at least one pixel.
+fn scaled_height(width: u32, height: u32, scaled_width: u32) -> u32 {
+    let scaled = (u64::from(height) * u64::from(scaled_width)
+        + u64::from(width) / 2)
+        / u64::from(width.max(1));
+    u32::try_from(scaled).unwrap_or(u32::MAX).max(1)
+}
+
+/// Converts `image` to a color type the format of `extension` can
+/// encode.
+fn encodable(image: DynamicImage, extension: &str) -> DynamicImage {
+    match extension {
+        "jpg" | "jpeg" => DynamicImage::ImageRgb8(image.to_rgb8()),
+        "webp" | "gif" => DynamicImage::ImageRgba8(image.to_rgba8()),
+        _ => image,
+    }
+}
+
+/// Wraps an image decoding or encoding error.
+fn image_error(path: &str, err: &image::ImageError) -> EngineError {
+    EngineError::Render(format!("Image error for {}: {}", path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Context;
+    use image::{Rgba, RgbaImage};
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn processor(dir: &TempDir) -> ImageProcessor {
+        let source = dir.path().join("content");
+        fs::create_dir_all(source.join("photos")).unwrap();
+        RgbaImage::from_pixel(100, 50, Rgba([255, 0, 0, 255]))
+            .save(source.join("photos/red.png"))
+            .unwrap();
+        let mut images = ImageProcessor::new(
+            source,
+            dir.path().join("public/img"),
+            "/img/",
+        );
+        images.widths = vec![200, 80, 40];
+        images
+    }
+
+    #[test]
+    fn test_process() {
+        let dir = TempDir::new().unwrap();
+        let images = processor(&dir);
+        let derivatives = images.process("photos/red.png").unwrap();
+        let sizes: Vec<_> =
+            derivatives.iter().map(|d| (d.width, d.height)).collect();
+        assert_eq!(sizes, [(40, 20), (80, 40)]);
+        assert_eq!(derivatives[0].url, "/img/photos/red-40.png");
+        assert_eq!(
+            image::image_dimensions(&derivatives[1].path).unwrap(),
+            (80, 40)
+        );
+
+        let modified = fs::metadata(&derivatives[0].path)
+            .unwrap()
+            .modified()
+            .unwrap();
+        let _ = images.process("photos/red.png").unwrap();
+        assert_eq!(
+            fs::metadata(&derivatives[0].path)
+                .unwrap()
+                .modified()
+                .unwrap(),
+            modified
+        );
+
+        let mut small = images.clone();
+        small.widths = vec![400];
+        small.format = Some("JPG".to_string());
+        let derivatives = small.process("photos/red.png").unwrap();
+        assert_eq!(derivatives[0].url, "/img/photos/red-100.jpg");
+        assert!(derivatives[0].path.is_file());
+
+        assert!(images.process("../red.png").is_err());
+        assert!(images.process("photos/missing.png").is_err());
+    }
+
+    #[test]
+    fn test_image_function() {
+        let dir = TempDir::new().unwrap();
+        let mut images = processor(&dir);
+        images.sizes = Some("100vw".to_string());
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        images.register(&mut engine);
+
+        let mut context = Context::new();
+        context.set("alt", "Red & square");
+        assert_eq!(
+            engine
+                .render_template("{{ image('photos/red.png', alt) }}", &context)
+                .unwrap(),
+            "<img src=\"/img/photos/red-80.png\" \
+             srcset=\"/img/photos/red-40.png 40w, /img/photos/red-80.png 80w\" \
+             sizes=\"100vw\" width=\"80\" height=\"40\" alt=\"Red &amp; square\">"
+        );
+        assert!(engine
+            .render_template("{{ image() }}", &context)
+            .is_err());
+    }
+}
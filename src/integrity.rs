@@ -0,0 +1,330 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Integrity Module
+//!
+//! This module verifies templates downloaded by
+//! [`Engine::create_template_folder`](crate::Engine::create_template_folder)
+//! before they are written to disk, when the engine's `integrity` is
+//! set.
+//!
+//! Every downloaded file must be listed, with its SHA-256 checksum, in a
+//! manifest in the format written by `sha256sum`:
+//!
+//! ```text
+//! # Lines starting with '#' are ignored.
+//! 2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae  index.html
+//! fcde2b2edba56bf408601fb721fe9b5c338d10ee429ea04fae5511b68fbf8fb9  main.js
+//! ```
+//!
+//! The manifest is either pinned by the caller, or downloaded as
+//! [`MANIFEST_FILE`] next to the templates. A [`SignatureVerifier`] can
+//! additionally require a detached signature of the manifest, downloaded
+//! as [`SIGNATURE_FILE`]. Any mismatch fails the download with
+//! [`EngineError::IntegrityCheckFailed`], and no file is written.
+//!
+//! ```
+//! # #[cfg(feature = "test-util")]
+//! # {
+//! use staticweaver::engine::EngineError;
+//! use staticweaver::fetch::StaticFetcher;
+//! use staticweaver::integrity::{sha256_hex, Integrity};
+//! use staticweaver::Engine;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let fetcher = StaticFetcher::new();
+//! let files = ["contact.html", "index.html", "page.html", "post.html", "main.js", "sw.js"];
+//! let mut manifest = String::new();
+//! for file in files {
+//!     fetcher.insert(format!("https://example.com/t/{}", file), file);
+//!     manifest.push_str(&format!("{}  {}\n", sha256_hex(file.as_bytes()), file));
+//! }
+//!
+//! let mut engine = Engine::new("", Duration::from_secs(60));
+//! engine.set_fetcher(Arc::new(fetcher.clone()));
+//! engine.integrity = Some(Integrity::pinned(manifest));
+//! assert!(engine.create_template_folder(Some("https://example.com/t")).is_ok());
+//!
+//! fetcher.insert("https://example.com/t/sw.js", "tampered");
+//! let err = engine.create_template_folder(Some("https://example.com/t")).unwrap_err();
+//! assert!(matches!(err, EngineError::IntegrityCheckFailed { .. }));
+//! # }
+//! ```
+
+use crate::engine::EngineError;
+use crate::fetch::Fetcher;
+use fnv::FnvHashMap;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::sync::Arc;
+
+/// The name of the checksum manifest downloaded next to the templates,
+/// when the manifest is not pinned.
+pub const MANIFEST_FILE: &str = "SHA256SUMS";
+
+/// The name of the detached signature of the manifest, downloaded next
+/// to the templates when a [`SignatureVerifier`] is set.
+pub const SIGNATURE_FILE: &str = "SHA256SUMS.sig";
+
+/// Verifies detached signatures of checksum manifests, e.g. with a
+/// trusted public key.
+pub trait SignatureVerifier: fmt::Debug + Send + Sync {
+    /// Checks that `signature` signs `manifest`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the failure if the signature is
+    /// malformed or does not sign the manifest.
+    fn verify(
+        &self,
+        manifest: &[u8],
+        signature: &str,
+    ) -> Result<(), String>;
+}
+
+/// How downloaded templates are verified, as described in the
+/// [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct Integrity {
+    /// The pinned manifest, or `None` to download [`MANIFEST_FILE`].
+    manifest: Option<String>,
+    /// Verifies the signature of the manifest, if one is required.
+    verifier: Option<Arc<dyn SignatureVerifier>>,
+}
+
+impl Integrity {
+    /// Verifies templates against a manifest known in advance.
+    #[must_use]
+    pub fn pinned(manifest: impl Into<String>) -> Self {
+        Self {
+            manifest: Some(manifest.into()),
+            verifier: None,
+        }
+    }
+
+    /// Verifies templates against the [`MANIFEST_FILE`] downloaded next
+    /// to them.
+    ///
+    /// A downloaded manifest only detects corrupted files unless it is
+    /// also [signed](Integrity::signed_by).
+    #[must_use]
+    pub fn remote() -> Self {
+        Self {
+            manifest: None,
+            verifier: None,
+        }
+    }
+
+    /// Requires the [`SIGNATURE_FILE`] downloaded next to the templates
+    /// to sign the manifest, as checked by `verifier`.
+    #[must_use]
+    pub fn signed_by(
+        mut self,
+        verifier: Arc<dyn SignatureVerifier>,
+    ) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    /// Returns the verified manifest of the templates at `url`.
+    pub(crate) fn manifest(
+        &self,
+        url: &str,
+        fetcher: &dyn Fetcher,
+    ) -> Result<Manifest, EngineError> {
+        let manifest = match &self.manifest {
+            Some(manifest) => manifest.clone(),
+            None => fetch(fetcher, url, MANIFEST_FILE)?,
+        };
+        if let Some(verifier) = &self.verifier {
+            let signature = fetch(fetcher, url, SIGNATURE_FILE)?;
+            verifier
+                .verify(manifest.as_bytes(), signature.trim())
+                .map_err(|message| failed(SIGNATURE_FILE, message))?;
+        }
+        Manifest::parse(&manifest)
+    }
+}
+
+/// Downloads `file` of the templates at `url`, reporting failures as
+/// integrity failures: a file that cannot be fetched cannot be trusted.
+fn fetch(
+    fetcher: &dyn Fetcher,
+    url: &str,
+    file: &str,
+) -> Result<String, EngineError> {
+    fetcher
+        .fetch(&format!("{}/{}", url, file))
+        .map_err(|err| failed(file, err.to_string()))
+}
+
+/// Returns the integrity error of `file`.
+fn failed(file: &str, message: impl Into<String>) -> EngineError {
+    EngineError::IntegrityCheckFailed {
+        file: file.to_string(),
+        message: message.into(),
+    }
+}
+
+/// The expected checksums of files, by file name.
+#[derive(Debug)]
+pub(crate) struct Manifest {
+    checksums: FnvHashMap<String, String>,
+}
+
+impl Manifest {
+    /// Parses a manifest in the format written by `sha256sum`.
+    pub(crate) fn parse(text: &str) -> Result<Self, EngineError> {
+        let mut checksums = FnvHashMap::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let entry = line.split_once(char::is_whitespace).and_then(
+                |(checksum, name)| {
+                    let name = name.trim_start();
+                    // `sha256sum` marks files read in binary mode with `*`.
+                    let name = name.strip_prefix('*').unwrap_or(name);
+                    let valid = checksum.len() == 64
+                        && checksum
+                            .bytes()
+                            .all(|b| b.is_ascii_hexdigit())
+                        && !name.is_empty();
+                    valid.then(|| (name, checksum))
+                },
+            );
+            let Some((name, checksum)) = entry else {
+                return Err(failed(
+                    MANIFEST_FILE,
+                    format!("malformed line {}", index + 1),
+                ));
+            };
+            let _ = checksums.insert(
+                name.to_string(),
+                checksum.to_ascii_lowercase(),
+            );
+        }
+        Ok(Self { checksums })
+    }
+
+    /// Returns the names of the files listed.
+    #[cfg(feature = "archive")]
+    pub(crate) fn files(&self) -> impl Iterator<Item = &str> {
+        self.checksums.keys().map(String::as_str)
+    }
+
+    /// Checks that `content` matches the checksum listed for `file`.
+    pub(crate) fn verify(
+        &self,
+        file: &str,
+        content: &[u8],
+    ) -> Result<(), EngineError> {
+        let Some(expected) = self.checksums.get(file) else {
+            return Err(failed(
+                file,
+                format!("not listed in {}", MANIFEST_FILE),
+            ));
+        };
+        let actual = sha256_hex(content);
+        if *expected != actual {
+            return Err(failed(
+                file,
+                format!(
+                    "SHA-256 checksum is {}, expected {}",
+                    actual, expected
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the SHA-256 checksum of `bytes` as lowercase hexadecimal, as
+/// listed in manifests.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::integrity::sha256_hex;
+///
+/// assert_eq!(
+///     sha256_hex(b"abc"),
+///     "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+/// );
+/// ```
+#[must_use]
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Returns `bytes` encoded in standard, padded base64, as used by
+/// content security policies and subresource integrity.
+pub(crate) fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let group =
+            chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+                group | u32::from(*byte) << (16 - 8 * i)
+            });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - 6 * i)) & 0x3f;
+                encoded.push(char::from(ALPHABET[index as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(&[0xfb, 0xff]), "+/8=");
+    }
+
+    #[test]
+    fn test_manifest() {
+        let index = sha256_hex(b"index");
+        let text = format!(
+            "# checksums\n\n{}  index.html\n{} *main.js\n",
+            index.to_ascii_uppercase(),
+            sha256_hex(b"main")
+        );
+        let manifest = Manifest::parse(&text).unwrap();
+        manifest.verify("index.html", b"index").unwrap();
+        manifest.verify("main.js", b"main").unwrap();
+
+        let err =
+            manifest.verify("index.html", b"tampered").unwrap_err();
+        assert_eq!(err.code(), "integrity_check_failed");
+        assert!(err.to_string().contains("index.html"), "{}", err);
+        let err = manifest.verify("sw.js", b"sw").unwrap_err();
+        assert!(err.to_string().ends_with("not listed in SHA256SUMS"));
+
+        for text in ["abc  index.html", &index, "not a checksum line"] {
+            let err = Manifest::parse(text).unwrap_err();
+            assert!(
+                err.to_string().ends_with("malformed line 1"),
+                "{}",
+                err
+            );
+        }
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Intern Module
+//!
+//! This module provides the `Interner` struct, which maps frequently used
+//! names, such as layout names, to small ids. Keys built from ids are
+//! compared and hashed without touching the names themselves.
+
+use fnv::FnvHashMap;
+use std::sync::{PoisonError, RwLock};
+
+/// The id of an interned name, only meaningful to the interner that
+/// created it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct Symbol(usize);
+
+/// A thread-safe table of interned names.
+///
+/// Names are never removed, so a symbol stays valid for the lifetime of
+/// its interner.
+#[derive(Debug, Default)]
+pub(crate) struct Interner {
+    ids: RwLock<FnvHashMap<Box<str>, Symbol>>,
+}
+
+impl Interner {
+    /// Returns the symbol of `name`, interning it if needed.
+    pub(crate) fn intern(&self, name: &str) -> Symbol {
+        if let Some(symbol) = self.get(name) {
+            return symbol;
+        }
+        let mut ids =
+            self.ids.write().unwrap_or_else(PoisonError::into_inner);
+        let next = Symbol(ids.len());
+        *ids.entry(Box::from(name)).or_insert(next)
+    }
+
+    /// Returns the symbol of `name` if it has been interned.
+    pub(crate) fn get(&self, name: &str) -> Option<Symbol> {
+        self.ids
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .copied()
+    }
+}
+
+/// Clones keep the symbols of the original, so keys built from them
+/// stay valid in the clone.
+impl Clone for Interner {
+    fn clone(&self) -> Self {
+        Self {
+            ids: RwLock::new(
+                self.ids
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_reuses_index() {
+        let interner = Interner::default();
+        let index = interner.intern("index");
+        assert_eq!(interner.intern("index"), index);
+        assert_ne!(interner.intern("post"), index);
+    }
+
+    #[test]
+    fn test_get_finds_interned() {
+        let interner = Interner::default();
+        let index = interner.intern("index");
+        assert_eq!(interner.get("index"), Some(index));
+        assert_eq!(interner.get("missing"), None);
+    }
+}
@@ -0,0 +1,263 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Journal Module
+//!
+//! This module provides the `BuildJournal` struct, the record of the
+//! pages a build has written, kept on disk so that an interrupted build
+//! can resume where it stopped.
+//!
+//! A [`SiteBuilder`](crate::site::SiteBuilder) given a journal appends
+//! a line to it as soon as each page is written, with the
+//! [input hash](crate::Engine::page_input_hash) of the page:
+//!
+//! ```text
+//! {"path":"blog/index.html","hash":"3b1f…"}
+//! ```
+//!
+//! When the build runs again, pages whose path is in the journal with
+//! the same input hash, and whose file still exists, are skipped. A
+//! page whose layout, context, data, or settings changed since is built
+//! again. The journal is flushed after every line, so a crash loses at
+//! most the pages being written at the time; a line cut short by the
+//! crash is ignored when the journal is opened again.
+//!
+//! ```
+//! use staticweaver::journal::BuildJournal;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let path = dir.path().join("build.journal");
+//!
+//! let journal = BuildJournal::open(&path).unwrap();
+//! journal.record("index.html", "abc").unwrap();
+//!
+//! // The next build.
+//! let journal = BuildJournal::open(&path).unwrap();
+//! assert!(journal.is_done("index.html", "abc"));
+//! assert!(!journal.is_done("index.html", "def"));
+//! ```
+
+use crate::engine::EngineError;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError, RwLock};
+
+/// The pages written by a build and their input hashes, as described
+/// in the [module documentation](self).
+#[derive(Debug)]
+pub struct BuildJournal {
+    path: PathBuf,
+    entries: RwLock<HashMap<String, String>>,
+    file: Mutex<File>,
+}
+
+impl BuildJournal {
+    /// Opens the journal at `path`, reading the pages it lists, or
+    /// creates it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EngineError::Io` if the journal cannot be read or
+    /// opened for writing.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, EngineError> {
+        let path = path.as_ref();
+        let mut entries = HashMap::new();
+        let mut torn = false;
+        match fs::read_to_string(path) {
+            Ok(journal) => {
+                for (page, hash) in
+                    journal.lines().filter_map(parse_line)
+                {
+                    let _ = entries.insert(page, hash);
+                }
+                torn = !journal.is_empty() && !journal.ends_with('\n');
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file =
+            OpenOptions::new().create(true).append(true).open(path)?;
+        if torn {
+            // Ends the line cut short, so that the next one is whole.
+            writeln!(file)?;
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries: RwLock::new(entries),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Returns the path of the journal file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether the page at `page` was written with the input
+    /// hash `hash`.
+    #[must_use]
+    pub fn is_done(&self, page: &str, hash: &str) -> bool {
+        self.entries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(page)
+            .map_or(false, |done| done == hash)
+    }
+
+    /// Records that the page at `page` was written with the input hash
+    /// `hash`, and flushes the journal.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EngineError::Io` if the journal cannot be written.
+    pub fn record(
+        &self,
+        page: &str,
+        hash: &str,
+    ) -> Result<(), EngineError> {
+        let line = json!({ "path": page, "hash": hash }).to_string();
+        {
+            let mut file = self
+                .file
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            writeln!(file, "{}", line)?;
+            file.flush()?;
+        }
+        let _ = self
+            .entries
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(page.to_string(), hash.to_string());
+        Ok(())
+    }
+
+    /// Returns the number of pages recorded.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Returns whether no page is recorded.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forgets every page recorded, so that the next build writes every
+    /// page again.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EngineError::Io` if the journal cannot be truncated.
+    pub fn clear(&self) -> Result<(), EngineError> {
+        let file =
+            self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        file.set_len(0)?;
+        self.entries
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+        Ok(())
+    }
+}
+
+/// Parses a line of the journal into a page path and its input hash,
+/// or returns `None` if the line is not a complete entry.
+fn parse_line(line: &str) -> Option<(String, String)> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let field = |name: &str| {
+        value.get(name).and_then(Value::as_str).map(str::to_string)
+    };
+    Some((field("path")?, field("hash")?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Returns a directory and the path of a journal in a subdirectory
+    /// of it, not created yet.
+    fn journal_path() -> (TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested/build.journal");
+        (dir, path)
+    }
+
+    #[test]
+    fn test_open_creates_empty_journal() {
+        let (_dir, path) = journal_path();
+        let journal = BuildJournal::open(&path).unwrap();
+        assert!(journal.is_empty());
+        assert!(path.is_file());
+    }
+
+    #[test]
+    fn test_reopen_keeps_records() {
+        let (_dir, path) = journal_path();
+        let journal = BuildJournal::open(&path).unwrap();
+        journal.record("a.html", "1").unwrap();
+        journal.record("b.html", "2").unwrap();
+        drop(journal);
+
+        let journal = BuildJournal::open(&path).unwrap();
+        assert_eq!(journal.len(), 2);
+        assert!(journal.is_done("a.html", "1"));
+        assert!(journal.is_done("b.html", "2"));
+    }
+
+    #[test]
+    fn test_later_record_replaces_hash() {
+        let (_dir, path) = journal_path();
+        let journal = BuildJournal::open(&path).unwrap();
+        journal.record("a.html", "1").unwrap();
+        journal.record("a.html", "3").unwrap();
+        let journal = BuildJournal::open(&path).unwrap();
+        assert_eq!(journal.len(), 1);
+        assert!(journal.is_done("a.html", "3"));
+        assert!(!journal.is_done("a.html", "1"));
+    }
+
+    #[test]
+    fn test_torn_last_line_is_ignored() {
+        let (_dir, path) = journal_path();
+        BuildJournal::open(&path)
+            .unwrap()
+            .record("a.html", "1")
+            .unwrap();
+
+        // A crash while writing the last line.
+        let mut file =
+            OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "{{\"path\":\"c.html\",\"ha").unwrap();
+        drop(file);
+
+        let journal = BuildJournal::open(&path).unwrap();
+        journal.record("d.html", "4").unwrap();
+        let journal = BuildJournal::open(&path).unwrap();
+        assert_eq!(journal.len(), 2);
+        assert!(journal.is_done("d.html", "4"));
+        assert!(!journal.is_done("c.html", ""));
+    }
+
+    #[test]
+    fn test_clear_empties_file() {
+        let (_dir, path) = journal_path();
+        let journal = BuildJournal::open(&path).unwrap();
+        journal.record("a.html", "1").unwrap();
+        journal.clear().unwrap();
+        assert!(journal.is_empty());
+        assert!(BuildJournal::open(&path).unwrap().is_empty());
+    }
+}
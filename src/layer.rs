@@ -0,0 +1,295 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Layer Module
+//!
+//! This module provides the `RenderLayer` trait, through which the
+//! stages a page goes through when it is served can be composed,
+//! reordered, and replaced.
+//!
+//! By default, an engine serves each page from its render caches, and
+//! renders it on a miss. An engine given a stack of layers with
+//! [`Engine::set_layers`](crate::Engine::set_layers) instead passes each
+//! [`RenderRequest`] down the stack: every layer may change the request,
+//! answer it itself, or call the [`Next`] layer and change its
+//! [`Rendered`] page. Below the last layer, the page is rendered without
+//! consulting any cache.
+//!
+//! Caching is the [`Cache`] layer, so it can be placed anywhere in the
+//! stack, or left out. Layers above it run on every page served; layers
+//! below it only run when a page is rendered, and their output is what
+//! gets cached:
+//!
+//! ```
+//! use staticweaver::engine::{EngineError, Rendered};
+//! use staticweaver::layer::{Cache, Next, RenderLayer, RenderRequest};
+//! use staticweaver::loader::MemoryLoader;
+//! use staticweaver::{Context, Engine};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! /// Minifies rendered pages before they are cached.
+//! #[derive(Debug)]
+//! struct Minify;
+//!
+//! impl RenderLayer for Minify {
+//!     fn call(&self, request: RenderRequest<'_>, next: Next<'_>) -> Result<Rendered, EngineError> {
+//!         let page = next.run(request)?;
+//!         let output = page.output.lines().map(str::trim).collect::<String>();
+//!         Ok(page.with_output(output))
+//!     }
+//! }
+//!
+//! let loader = MemoryLoader::new();
+//! loader.insert("templates/page.html", "<ul>\n  <li>{{item}}</li>\n</ul>");
+//! let mut engine = Engine::new("templates", Duration::from_secs(60));
+//! engine.set_loader(Arc::new(loader));
+//! engine.set_layers(vec![Arc::new(Cache), Arc::new(Minify)]);
+//!
+//! let mut context = Context::new();
+//! context.set("item", "a");
+//! assert_eq!(engine.render_page(&context, "page").unwrap(), "<ul><li>a</li></ul>");
+//! assert_eq!(engine.render_cache().len(), 1);
+//! ```
+//!
+//! Layers run inside the [hooks](crate::hooks) of the engine. Escaping
+//! is part of rendering itself, and is chosen for a page through the
+//! options of its request.
+
+use crate::context::Context;
+use crate::engine::{Engine, EngineError, RenderOptions, Rendered};
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+
+/// A page to serve, as passed down a stack of layers.
+#[derive(Debug, Clone)]
+pub struct RenderRequest<'a> {
+    /// The context of the page.
+    pub context: Cow<'a, Context>,
+    /// The layout of the page.
+    pub layout: Cow<'a, str>,
+    /// The settings the page is rendered with, such as its output
+    /// format.
+    pub options: RenderOptions,
+}
+
+impl<'a> RenderRequest<'a> {
+    /// Creates a request for the page of `context` and `layout`.
+    #[must_use]
+    pub fn new(
+        context: &'a Context,
+        layout: &'a str,
+        options: RenderOptions,
+    ) -> Self {
+        Self {
+            context: Cow::Borrowed(context),
+            layout: Cow::Borrowed(layout),
+            options,
+        }
+    }
+}
+
+/// A stage of serving a page, as described in the
+/// [module documentation](self).
+pub trait RenderLayer: fmt::Debug + Send + Sync {
+    /// Serves `request`, usually by calling `next`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the page cannot be served.
+    fn call(
+        &self,
+        request: RenderRequest<'_>,
+        next: Next<'_>,
+    ) -> Result<Rendered, EngineError>;
+}
+
+/// The layers below the one being called, down to the renderer.
+#[derive(Debug, Clone, Copy)]
+pub struct Next<'a> {
+    engine: &'a Engine,
+    layers: &'a [Arc<dyn RenderLayer>],
+}
+
+impl<'a> Next<'a> {
+    /// Starts `layers` for pages served by `engine`.
+    pub(crate) fn new(
+        engine: &'a Engine,
+        layers: &'a [Arc<dyn RenderLayer>],
+    ) -> Self {
+        Self { engine, layers }
+    }
+
+    /// Returns the engine serving the page.
+    #[must_use]
+    pub fn engine(&self) -> &'a Engine {
+        self.engine
+    }
+
+    /// Passes `request` to the next layer, or renders it below the last
+    /// one.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of the next layer, or of
+    /// [`Engine::render_page`].
+    pub fn run(
+        self,
+        request: RenderRequest<'_>,
+    ) -> Result<Rendered, EngineError> {
+        match self.layers.split_first() {
+            Some((layer, layers)) => layer.call(
+                request,
+                Next {
+                    engine: self.engine,
+                    layers,
+                },
+            ),
+            None => self.engine.render_request(&request),
+        }
+    }
+}
+
+/// Serves pages from the in-memory and disk render caches, and caches
+/// the pages the layers below it render.
+///
+/// Requests whose options bypass the cache are passed down as they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cache;
+
+impl RenderLayer for Cache {
+    fn call(
+        &self,
+        request: RenderRequest<'_>,
+        next: Next<'_>,
+    ) -> Result<Rendered, EngineError> {
+        next.engine().render_request_cached(request, next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::CacheStatus;
+    use crate::loader::MemoryLoader;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Appends its name to pages, and counts its calls.
+    #[derive(Debug)]
+    struct Mark {
+        name: &'static str,
+        calls: AtomicUsize,
+    }
+
+    impl Mark {
+        fn new(name: &'static str) -> Arc<Self> {
+            Arc::new(Self {
+                name,
+                calls: AtomicUsize::new(0),
+            })
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::Relaxed)
+        }
+    }
+
+    impl RenderLayer for Mark {
+        fn call(
+            &self,
+            request: RenderRequest<'_>,
+            next: Next<'_>,
+        ) -> Result<Rendered, EngineError> {
+            let _ = self.calls.fetch_add(1, Ordering::Relaxed);
+            let page = next.run(request)?;
+            let output = format!("{}{}", page.output, self.name);
+            Ok(page.with_output(output))
+        }
+    }
+
+    fn engine() -> Engine {
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/page.html", "{{title}}");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        engine
+    }
+
+    #[test]
+    fn test_layer_order() {
+        let mut engine = engine();
+        let (outer, inner) = (Mark::new("-outer"), Mark::new("-inner"));
+        engine.set_layers(vec![
+            outer.clone(),
+            Arc::new(Cache),
+            inner.clone(),
+        ]);
+        let mut context = Context::new();
+        context.set("title", "a");
+        let options = RenderOptions::new();
+
+        let page = engine
+            .render_page_detailed(&options, &context, "page")
+            .unwrap();
+        assert_eq!(&*page.output, "a-inner-outer");
+        assert_eq!(page.cache, CacheStatus::Miss);
+        let page = engine
+            .render_page_detailed(&options, &context, "page")
+            .unwrap();
+        assert_eq!(&*page.output, "a-inner-outer");
+        assert_eq!(page.cache, CacheStatus::Hit);
+        assert_eq!(
+            page.content_hash,
+            crate::integrity::sha256_hex(b"a-inner-outer")
+        );
+        assert_eq!((outer.calls(), inner.calls()), (2, 1));
+
+        let mut options = RenderOptions::new();
+        options.bypass_cache = true;
+        let page = engine
+            .render_page_detailed(&options, &context, "page")
+            .unwrap();
+        assert_eq!(page.cache, CacheStatus::Bypass);
+        assert_eq!((outer.calls(), inner.calls()), (3, 2));
+    }
+
+    #[test]
+    fn test_layers_without_cache() {
+        let mut engine = engine();
+        let mark = Mark::new("!");
+        engine.set_layers(vec![mark.clone()]);
+        assert_eq!(engine.layers().map(<[_]>::len), Some(1));
+        let mut context = Context::new();
+        context.set("title", "a");
+
+        assert_eq!(engine.render_page(&context, "page").unwrap(), "a!");
+        assert_eq!(engine.render_page(&context, "page").unwrap(), "a!");
+        assert!(engine.render_cache().is_empty());
+        assert_eq!(mark.calls(), 2);
+        assert!(engine.render_page(&context, "missing").is_err());
+
+        engine.clear_layers();
+        assert!(engine.layers().is_none());
+        assert_eq!(engine.render_page(&context, "page").unwrap(), "a");
+        assert_eq!(engine.render_cache().len(), 1);
+    }
+
+    #[test]
+    fn test_layers_of_shared_engine() {
+        use crate::shared::SharedEngine;
+
+        let mut engine = engine();
+        let mark = Mark::new("!");
+        engine.set_layers(vec![Arc::new(Cache), mark.clone()]);
+        let engine = SharedEngine::new(engine);
+        let mut context = Context::new();
+        context.set("title", "a");
+
+        assert_eq!(engine.render_page(&context, "page").unwrap(), "a!");
+        assert_eq!(engine.render_page(&context, "page").unwrap(), "a!");
+        assert_eq!(mark.calls(), 1);
+        assert_eq!(engine.read().render_cache().len(), 1);
+    }
+}
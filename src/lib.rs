@@ -17,20 +17,213 @@
 /// Contains the `Context` struct for managing template variables.
 pub mod context;
 
+/// Copies static assets to the output of a site, skipping fresh copies.
+pub mod asset;
+
 /// Provides the `Engine` struct for template rendering.
 pub mod engine;
 
+/// Fills in Content Security Policy nonces and inline hashes of pages.
+pub mod csp;
+
+/// Loads data files exposed to every template as `data`.
+pub mod data;
+
+/// Stores rendered pages on disk, behind the in-memory render cache.
+pub mod disk_cache;
+
+/// Provides the build clock and helpers of reproducible builds.
+pub mod determinism;
+
+/// Compares renders of a page line by line, for live previews.
+pub mod diff;
+
 /// Defines error types for template processing.
 pub mod error;
 
+/// Decodes template files with byte order marks or legacy encodings.
+pub mod encoding;
+
+/// Provides the build environment exposed to templates as `env`.
+pub mod environment;
+
+/// Provides output formats and value escaping.
+pub mod escape;
+
+/// Provides the fetchers through which remote templates are downloaded.
+pub mod fetch;
+
+/// Compiles layouts into Rust functions, for build scripts.
+pub mod codegen;
+
+/// Provides the built-in filters applied inside template tags.
+pub mod filter;
+
+/// Renders a template file checked at compile time, without an engine.
+///
+/// The template is read when the calling crate is compiled, from a path
+/// relative to its `Cargo.toml`, and rendered with the values bound to
+/// its keys, which must implement [`Display`](std::fmt::Display). Keys
+/// are bound as `key: value`, or `"key": value` for keys that are not
+/// identifiers. A key the template uses but that is not bound fails the
+/// build:
+///
+/// ```compile_fail
+/// let page = staticweaver::render_static!("tests/templates/static.html", {
+///     title: "Home",
+/// });
+/// ```
+///
+/// Tags are rendered as an [`Engine`] with the default settings would:
+/// the pragma of the template selects its syntax, filters apply in
+/// order, and values are escaped for the output format the file
+/// extension selects. Tags that need an engine, such as function calls,
+/// block tags, and `env.*` or `data.*` keys, also fail the build.
+///
+/// ```
+/// let tag = "Rust & Web";
+/// let page = staticweaver::render_static!("tests/templates/static.html", {
+///     title: "Home",
+///     tag: tag,
+///     body: "<em>Hi</em>",
+/// });
+/// assert!(page.contains("<h1>Home</h1>"));
+/// assert!(page.contains("<a href=\"/tags/rust-web\">Rust &amp; Web</a>"));
+/// assert!(page.contains("<p><em>Hi</em></p>"));
+/// ```
+#[cfg(feature = "macros")]
+pub use staticweaver_macros::render_static;
+
+#[doc(hidden)]
+pub mod static_render;
+
+/// Provides the registry of functions callable from templates.
+pub mod function;
+
 /// Implements caching mechanisms for improved performance.
 pub mod cache;
 
+/// Revalidates downloaded templates with conditional HTTP requests.
+pub mod http_cache;
+
+/// Verifies the checksums and signatures of downloaded templates.
+pub mod integrity;
+
+/// Records the pages a build has written, so that it can resume.
+pub mod journal;
+
+/// Runs hooks before and after every page an engine serves.
+pub mod hooks;
+
+/// Replaces block tags with the output of registered handlers.
+pub mod block;
+
+/// Streams built sites into a single `.zip` or `.tar.gz` archive.
+#[cfg(feature = "bundle")]
+pub mod bundle;
+
+/// Composes the stages of serving a page as a stack of layers.
+pub mod layer;
+
+/// Stops renders that run out of time or are cancelled.
+pub mod cancel;
+
+/// Caps the memory a single render may use.
+pub mod limits;
+
+/// Interns layout names for cheap render cache keys.
+mod intern;
+
+/// Provides the loaders through which layout files are read.
+pub mod loader;
+
+/// Lists the files a site build produced, for deploy tools.
+pub mod manifest;
+
+/// Reads and writes `.swpkg` template packages.
+#[cfg(feature = "archive")]
+pub mod package;
+
+/// Provides the template parser, for validating untrusted templates.
+pub mod parser;
+
+/// Provides the `SharedEngine` and `EngineConfig` structs for rendering from many threads.
+pub mod shared;
+
+/// Renders the pages of a site on worker threads, reporting progress.
+pub mod site;
+
+/// Provides the sinks through which site builds store rendered pages.
+pub mod sink;
+
+/// Expands shortcodes in content bodies with registered templates.
+pub mod shortcode;
+
+/// Collects taxonomy terms, such as tags, across the pages of a site.
+pub mod taxonomy;
+
+/// Anchors headings and builds tables of contents for rendered content.
+pub mod toc;
+
+/// Provides the built-in Open Graph and meta tag template functions.
+pub mod meta;
+
+/// Records where the time of a render is spent, for opt-in profiling.
+pub mod profile;
+
+/// Resolves the output files, such as HTML and JSON, of each page.
+pub mod output;
+
+/// Collects page aliases into redirect stubs and server redirect maps.
+pub mod redirect;
+
+/// Builds JSON search indexes of rendered pages.
+pub mod search;
+
+/// Provides render and snapshot assertions for tests.
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+
+/// Provides template syntax versions and their migration.
+pub mod syntax;
+
+/// Provides the `Theme` struct for packaging templates, defaults, and assets.
+pub mod theme;
+
+/// Provides the `ToContextValue` trait for converting values into context strings.
+pub mod value;
+
+/// Loads the pages of the previous build into the render cache.
+pub mod warm;
+
+/// Provides HTTP responses for rendered pages, with axum and actix-web support.
+pub mod web;
+
+/// Provides JavaScript bindings for rendering templates in the browser.
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+/// Provides a C ABI for embedding the engine from other languages.
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+/// Resizes images into responsive derivatives for the `image()` function.
+#[cfg(feature = "images")]
+pub mod images;
+
+/// Writes pre-compressed copies of rendered pages.
+#[cfg(feature = "compress")]
+pub mod compress;
+
 pub use context::Context;
-pub use engine::{Engine, PageOptions};
+pub use engine::{Engine, MissingKeys, PageOptions, RenderOptions};
 pub use error::{EngineError, TemplateError};
+pub use shared::EngineConfig;
+pub use value::ToContextValue;
 
 /// Prelude module for convenient imports
 pub mod prelude {
-    pub use crate::{Context, Engine, EngineError, TemplateError};
+    pub use crate::{
+        Context, Engine, EngineError, TemplateError, ToContextValue,
+    };
 }
@@ -0,0 +1,173 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Limits Module
+//!
+//! This module caps the memory a single render may use, so that a
+//! template that produces far more than expected fails instead of
+//! exhausting the memory of the process. The
+//! [`RenderOptions`](crate::engine::RenderOptions) of a page can cap:
+//!
+//! - `max_output`, the length of the rendered page, and
+//! - `max_allocation`, the length of any value built along the way: a
+//!   filtered or escaped value, the output of a function, or the
+//!   content and output of a block.
+//!
+//! ```
+//! use staticweaver::engine::{EngineError, RenderOptions};
+//! use staticweaver::limits::SizeLimit;
+//! use staticweaver::loader::MemoryLoader;
+//! use staticweaver::{Context, Engine};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let loader = MemoryLoader::new();
+//! loader.insert("templates/page.html", "<p>{{body}}</p>");
+//! let mut engine = Engine::new("templates", Duration::from_secs(60));
+//! engine.set_loader(Arc::new(loader));
+//!
+//! let mut options = RenderOptions::new();
+//! options.max_output = Some(16);
+//! let mut context = Context::new();
+//! context.set("body", "a".repeat(100));
+//! let err = engine.render_page_with(&options, &context, "page").unwrap_err();
+//! assert!(matches!(
+//!     err.root(),
+//!     EngineError::TooLarge { limit: SizeLimit::Output, max: 16 }
+//! ));
+//! ```
+//!
+//! Output past the cap is never written: the render stops with
+//! [`EngineError::TooLarge`] at the tag that would exceed it, and its
+//! page is not cached. Values in the context are not counted, since
+//! they exist before the render starts.
+
+use crate::engine::EngineError;
+use std::fmt;
+
+/// A memory cap of a render.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SizeLimit {
+    /// The cap on the length of the rendered page.
+    Output,
+    /// The cap on the length of each value built during the render.
+    Allocation,
+}
+
+impl fmt::Display for SizeLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Output => "Render output",
+            Self::Allocation => "Intermediate value",
+        })
+    }
+}
+
+/// Returns [`EngineError::TooLarge`] if `len` bytes exceed the cap
+/// `max` on `limit`, if any.
+pub(crate) fn check(
+    limit: SizeLimit,
+    max: Option<usize>,
+    len: usize,
+) -> Result<(), EngineError> {
+    match max {
+        Some(max) if len > max => {
+            Err(EngineError::TooLarge { limit, max })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A writer that counts the bytes written through it, and refuses the
+/// writes that would exceed its cap.
+#[derive(Debug)]
+pub(crate) struct Capped<'w, W> {
+    out: &'w mut W,
+    len: usize,
+    limit: SizeLimit,
+    max: Option<usize>,
+    exceeded: bool,
+}
+
+impl<'w, W: fmt::Write> Capped<'w, W> {
+    /// Wraps `out`, capped at `max` bytes on `limit`, if any.
+    pub(crate) fn new(
+        out: &'w mut W,
+        limit: SizeLimit,
+        max: Option<usize>,
+    ) -> Self {
+        Self {
+            out,
+            len: 0,
+            limit,
+            max,
+            exceeded: false,
+        }
+    }
+
+    /// Returns [`EngineError::TooLarge`] in place of `err` if a write
+    /// was refused, and `err` otherwise.
+    pub(crate) fn error(&self, err: EngineError) -> EngineError {
+        match self.max {
+            Some(max) if self.exceeded => EngineError::TooLarge {
+                limit: self.limit,
+                max,
+            },
+            _ => err,
+        }
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for Capped<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = self.len.saturating_add(s.len());
+        if self.max.map_or(false, |max| len > max) {
+            self.exceeded = true;
+            return Err(fmt::Error);
+        }
+        self.out.write_str(s)?;
+        self.len = len;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Write;
+
+    #[test]
+    fn test_capped() {
+        let mut page = String::new();
+        let mut out =
+            Capped::new(&mut page, SizeLimit::Output, Some(4));
+        assert!(out.write_str("abc").is_ok());
+        assert!(out.write_str("de").is_err());
+        assert_eq!(
+            out.error(EngineError::Render("write".to_string()))
+                .to_string(),
+            "Render output exceeds the limit of 4 bytes"
+        );
+        assert_eq!(page, "abc");
+
+        let mut page = String::new();
+        let mut out = Capped::new(&mut page, SizeLimit::Output, None);
+        assert!(out.write_str(&"a".repeat(100)).is_ok());
+        assert_eq!(
+            out.error(EngineError::Render(String::new())).code(),
+            "render"
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(check(SizeLimit::Allocation, None, 100).is_ok());
+        assert!(check(SizeLimit::Allocation, Some(100), 100).is_ok());
+        assert_eq!(
+            check(SizeLimit::Allocation, Some(99), 100)
+                .unwrap_err()
+                .to_string(),
+            "Intermediate value exceeds the limit of 99 bytes"
+        );
+    }
+}
@@ -0,0 +1,448 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Loader Module
+//!
+//! This module provides the `Loader` trait, through which the engine
+//! finds and reads layout files. Engines read the file system with the
+//! [`FsLoader`] by default; a [`MemoryLoader`] serves layouts from
+//! memory, so tests can add and remove layouts without temporary
+//! directories:
+//!
+//! ```
+//! use staticweaver::loader::MemoryLoader;
+//! use staticweaver::{Context, Engine};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let loader = MemoryLoader::new();
+//! loader.insert("templates/page.html", "<h1>{{title}}</h1>");
+//!
+//! let mut engine = Engine::new("templates", Duration::from_secs(60));
+//! engine.set_loader(Arc::new(loader.clone()));
+//!
+//! let mut context = Context::new();
+//! context.set("title", "Home");
+//! assert_eq!(engine.render_page(&context, "page").unwrap(), "<h1>Home</h1>");
+//!
+//! loader.remove("templates/page.html");
+//! engine.clear_cache();
+//! assert!(engine.render_page(&context, "page").is_err());
+//! ```
+//!
+//! Layout names are often taken from requests or content files, so the
+//! engine never resolves a name outside its template directories. Names
+//! that are absolute or climb with `..` are rejected by
+//! [`validate_layout_name`], and a file found through a symbolic link
+//! must lie inside its directory once the link is resolved, as checked
+//! by [`Loader::contains`]. Both fail with
+//! [`EngineError::InvalidTemplateName`].
+//!
+//! File systems that ignore case, as on macOS and Windows, find
+//! `Index.html` for the layout `index`, and the same site then fails to
+//! build on Linux. With
+//! [`Engine::set_strict_layout_case`](crate::Engine::set_strict_layout_case)
+//! enabled, a layout whose name differs from the file found by case
+//! fails with `InvalidTemplateName` on every system, naming the file as
+//! stored.
+
+use crate::engine::EngineError;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// A source of layout files.
+pub trait Loader: fmt::Debug + Send + Sync {
+    /// Returns whether `path` names a layout file.
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Reads the bytes of the layout file at `path`, which the engine
+    /// decodes as described in the [`encoding`](crate::encoding)
+    /// module.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::NotFound`] if there is
+    /// no such file, or any other error raised while reading it.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Returns whether `path`, a layout file found in the template
+    /// directory `dir`, lies inside `dir`.
+    ///
+    /// The default implementation returns `true`, for loaders whose
+    /// paths cannot be redirected by links.
+    fn contains(&self, dir: &Path, path: &Path) -> bool {
+        let _ = (dir, path);
+        true
+    }
+
+    /// Returns `path`, a layout file found in the template directory
+    /// `dir`, with the part below `dir` spelled as stored, or `None` if
+    /// the spelling cannot be told.
+    ///
+    /// The default implementation returns `path`, for loaders that match
+    /// names exactly.
+    fn stored_path(&self, dir: &Path, path: &Path) -> Option<PathBuf> {
+        let _ = dir;
+        Some(path.to_path_buf())
+    }
+
+    /// Returns the paths of the files in the template directory `dir`
+    /// and its subdirectories, relative to `dir`.
+    ///
+    /// The default implementation returns no file, for loaders that
+    /// cannot list their files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be listed.
+    fn files(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let _ = dir;
+        Ok(Vec::new())
+    }
+
+    /// Returns when the layout file at `path` was last modified, if
+    /// known.
+    ///
+    /// The default implementation returns `None`.
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        let _ = path;
+        None
+    }
+}
+
+/// Checks that `layout` names a file inside the template directories.
+///
+/// Layout names may contain subdirectories, such as `blog/post`, but
+/// may not be empty, absolute, contain NUL bytes, or climb out of their
+/// directory with `..`.
+///
+/// # Errors
+///
+/// Returns `EngineError::InvalidTemplateName` if the name is rejected.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::loader::validate_layout_name;
+///
+/// assert!(validate_layout_name("blog/post").is_ok());
+/// assert!(validate_layout_name("../../etc/passwd").is_err());
+/// assert!(validate_layout_name("/etc/passwd").is_err());
+/// ```
+pub fn validate_layout_name(layout: &str) -> Result<(), EngineError> {
+    let message = if layout.is_empty() {
+        Some("the name is empty")
+    } else if layout.contains('\0') {
+        Some("the name contains a NUL byte")
+    } else {
+        Path::new(layout).components().find_map(|component| {
+            match component {
+                Component::Normal(_) | Component::CurDir => None,
+                Component::ParentDir => {
+                    Some("the name escapes the template directory")
+                }
+                Component::RootDir | Component::Prefix(_) => {
+                    Some("the name is an absolute path")
+                }
+            }
+        })
+    };
+    match message {
+        Some(message) => Err(EngineError::InvalidTemplateName {
+            name: layout.to_string(),
+            message: message.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Reads layouts from the file system.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FsLoader;
+
+impl Loader for FsLoader {
+    fn exists(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    /// Resolves symbolic links in both paths, so that a link inside
+    /// `dir` to a file outside it is not followed.
+    fn contains(&self, dir: &Path, path: &Path) -> bool {
+        match (listable(dir).canonicalize(), path.canonicalize()) {
+            (Ok(dir), Ok(path)) => path.starts_with(dir),
+            _ => false,
+        }
+    }
+
+    /// Lists each directory below `dir` to find the stored spelling of
+    /// every component, preferring an exact match.
+    fn stored_path(&self, dir: &Path, path: &Path) -> Option<PathBuf> {
+        let mut stored = dir.to_path_buf();
+        for component in path.strip_prefix(dir).ok()?.components() {
+            let Component::Normal(name) = component else {
+                continue;
+            };
+            let names: Vec<_> = fs::read_dir(listable(&stored))
+                .ok()?
+                .flatten()
+                .map(|entry| entry.file_name())
+                .collect();
+            let found = names
+                .iter()
+                .find(|stored| *stored == name)
+                .or_else(|| {
+                    let name = name.to_string_lossy().to_lowercase();
+                    names.iter().find(|stored| {
+                        stored.to_string_lossy().to_lowercase() == name
+                    })
+                })?;
+            stored.push(found);
+        }
+        Some(stored)
+    }
+
+    fn files(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        let mut pending = vec![PathBuf::new()];
+        while let Some(relative) = pending.pop() {
+            for entry in fs::read_dir(listable(&dir.join(&relative)))? {
+                let entry = entry?;
+                let path = relative.join(entry.file_name());
+                if entry.file_type()?.is_dir() {
+                    pending.push(path);
+                } else {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|meta| meta.modified()).ok()
+    }
+}
+
+/// Returns `dir`, or the current directory if `dir` is empty, as it is
+/// for engines whose template path is `""`.
+fn listable(dir: &Path) -> &Path {
+    if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    }
+}
+
+/// Serves layouts from memory.
+///
+/// Clones share their files, so a test can keep one clone and change
+/// the files of the engine it handed the other to.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryLoader {
+    files: Arc<RwLock<BTreeMap<PathBuf, Vec<u8>>>>,
+}
+
+impl MemoryLoader {
+    /// Creates a loader without files.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces the file at `path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file, as the engine resolves it.
+    /// * `content` - The content of the file.
+    ///
+    /// # Returns
+    ///
+    /// The previous content of the file, if any.
+    pub fn insert(
+        &self,
+        path: impl Into<PathBuf>,
+        content: impl Into<Vec<u8>>,
+    ) -> Option<Vec<u8>> {
+        let mut files = match self.files.write() {
+            Ok(files) => files,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        files.insert(path.into(), content.into())
+    }
+
+    /// Removes the file at `path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The content of the removed file, if any.
+    pub fn remove(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        let mut files = match self.files.write() {
+            Ok(files) => files,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        files.remove(path.as_ref())
+    }
+
+    fn get(&self, path: &Path) -> Option<Vec<u8>> {
+        let files = match self.files.read() {
+            Ok(files) => files,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        files.get(path).cloned()
+    }
+}
+
+impl Loader for MemoryLoader {
+    fn exists(&self, path: &Path) -> bool {
+        self.get(path).is_some()
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.get(path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No such file: {}", path.display()),
+            )
+        })
+    }
+
+    fn files(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let files = match self.files.read() {
+            Ok(files) => files,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        Ok(files
+            .keys()
+            .filter_map(|path| path.strip_prefix(dir).ok())
+            .map(Path::to_path_buf)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_loader() {
+        let loader = MemoryLoader::new();
+        let path = Path::new("templates/page.html");
+        assert!(!loader.exists(path));
+        assert_eq!(
+            loader.read(path).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        assert_eq!(loader.insert(path, "a"), None);
+        let shared = loader.clone();
+        assert_eq!(shared.insert(path, "b"), Some(b"a".to_vec()));
+        assert!(loader.exists(path));
+        assert_eq!(loader.read(path).unwrap(), b"b");
+
+        assert_eq!(loader.remove(path), Some(b"b".to_vec()));
+        assert!(!shared.exists(path));
+        assert!(!FsLoader.exists(Path::new("templates/missing.html")));
+    }
+
+    #[test]
+    fn test_validate_layout_name() {
+        for layout in
+            ["page", "blog/post", "./page", "feed.xml", "a..b"]
+        {
+            assert!(validate_layout_name(layout).is_ok(), "{}", layout);
+        }
+        for layout in
+            ["", "..", "blog/../../page", "/etc/passwd", "a\0b"]
+        {
+            let err = validate_layout_name(layout).unwrap_err();
+            assert_eq!(
+                err.code(),
+                "invalid_template_name",
+                "{}",
+                layout
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fs_loader_contains() {
+        let dir = tempfile::tempdir().unwrap();
+        let templates = dir.path().join("templates");
+        fs::create_dir(&templates).unwrap();
+        fs::write(templates.join("page.html"), "page").unwrap();
+        fs::write(dir.path().join("secret.html"), "secret").unwrap();
+        std::os::unix::fs::symlink(
+            dir.path().join("secret.html"),
+            templates.join("secret.html"),
+        )
+        .unwrap();
+
+        assert!(
+            FsLoader.contains(&templates, &templates.join("page.html"))
+        );
+        assert!(!FsLoader
+            .contains(&templates, &templates.join("secret.html")));
+        assert!(
+            !FsLoader.contains(&templates, &templates.join("missing"))
+        );
+        assert!(MemoryLoader::new()
+            .contains(&templates, &templates.join("secret.html")));
+    }
+
+    #[test]
+    fn test_fs_loader_stored_path() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("Blog")).unwrap();
+        fs::write(dir.path().join("Blog/Post.html"), "post").unwrap();
+        fs::write(dir.path().join("index.html"), "index").unwrap();
+
+        let stored = FsLoader.stored_path(
+            dir.path(),
+            &dir.path().join("blog/post.html"),
+        );
+        // Case-sensitive file systems do not find the file at all.
+        if FsLoader.exists(&dir.path().join("blog/post.html")) {
+            assert_eq!(
+                stored.unwrap(),
+                dir.path().join("Blog/Post.html")
+            );
+        }
+        assert_eq!(
+            FsLoader
+                .stored_path(
+                    dir.path(),
+                    &dir.path().join("Blog/Post.html")
+                )
+                .unwrap(),
+            dir.path().join("Blog/Post.html")
+        );
+        assert_eq!(
+            FsLoader.files(dir.path()).unwrap(),
+            [Path::new("Blog/Post.html"), Path::new("index.html")]
+        );
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("templates/blog/post.html", "post");
+        let _ = loader.insert("other/page.html", "page");
+        assert_eq!(
+            loader.files(Path::new("templates")).unwrap(),
+            [Path::new("blog/post.html")]
+        );
+    }
+}
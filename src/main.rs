@@ -0,0 +1,960 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # StaticWeaver CLI
+//!
+//! The `staticweaver` binary renders templates with the library's engine,
+//! so that templates can be rendered, checked, and built without writing
+//! Rust. It is built with the `cli` feature:
+//!
+//! ```text
+//! cargo install staticweaver --features cli
+//!
+//! staticweaver render page.html --context data.json --out out.html
+//! staticweaver lint templates
+//! staticweaver build content public --templates templates --env dev
+//! ```
+
+use clap::{Parser, Subcommand};
+use staticweaver::asset::copy_asset;
+#[cfg(feature = "compress")]
+use staticweaver::compress::write_compressed;
+use staticweaver::data::DataDir;
+use staticweaver::engine::EngineError;
+use staticweaver::environment::{Environment, Mode};
+use staticweaver::error::Diagnostic;
+use staticweaver::output::{outputs, Output};
+use staticweaver::redirect::{stub_path, MapFormat, Redirects};
+use staticweaver::search::SearchIndex;
+use staticweaver::taxonomy::Taxonomy;
+use staticweaver::{Context, Engine};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+/// The cache TTL of the engine; each command renders every page once.
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// The layout of a content file without a `layout` key.
+const DEFAULT_LAYOUT: &str = "index";
+
+/// The default layout of taxonomy term pages.
+const TAXONOMY_LAYOUT: &str = "taxonomy";
+
+/// Renders templates with StaticWeaver.
+#[derive(Debug, Parser)]
+#[command(name = "staticweaver", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Renders a template file with a JSON context.
+    Render {
+        /// The template file to render.
+        template: PathBuf,
+        /// A JSON object with the values of the template tags.
+        #[arg(short, long)]
+        context: Option<PathBuf>,
+        /// The file to write; defaults to standard output.
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+    /// Checks the syntax of every template in a directory.
+    Lint {
+        /// The directory of templates to check.
+        dir: PathBuf,
+    },
+    /// Renders every JSON content file in a directory to a page.
+    ///
+    /// Each content file is the context of one page; its `layout` key
+    /// names the template to render, `index` by default. Pages whose
+    /// `draft` key is true are skipped unless building for `dev`.
+    ///
+    /// A page listing several `outputs`, such as `["html", "json"]`, is
+    /// also rendered with the layouts `<layout>.html` and `<layout>.json`.
+    ///
+    /// For each `--taxonomy`, such as `tags`, a page listing the pages of
+    /// each term is written to `<taxonomy>/<term>` in the output.
+    ///
+    /// Each URL in a page's `aliases` key is redirected to the page, with
+    /// a stub page or, with `--redirect-map`, a server redirect map.
+    ///
+    /// With `--search-index`, the HTML pages are also indexed into a JSON
+    /// file for client-side search, such as with Lunr.
+    ///
+    /// With `--precompress`, each page is also written as Brotli `.br`
+    /// and gzip `.gz` copies, unless it is too short or incompressible.
+    ///
+    /// Other files in the content directory, and every file in
+    /// `--static-dir`, are copied to the output unchanged, unless their
+    /// copy is already up to date.
+    Build {
+        /// The directory of JSON content files.
+        content_dir: PathBuf,
+        /// The directory to write the pages to.
+        out_dir: PathBuf,
+        /// The directory of templates.
+        #[arg(short, long, default_value = "templates")]
+        templates: PathBuf,
+        /// A directory of data files readable from templates as
+        /// `{{data.FILE.KEY}}`.
+        #[arg(short, long)]
+        data: Option<PathBuf>,
+        /// The environment to build for, `dev` or `prod`.
+        #[arg(short, long, default_value = "prod", value_parser = parse_mode)]
+        env: Mode,
+        /// A flag readable from templates as `{{env.NAME}}`.
+        #[arg(short, long, value_name = "NAME=VALUE", value_parser = parse_flag)]
+        flag: Vec<(String, String)>,
+        /// A context key listing the terms of a taxonomy, e.g. `tags`.
+        #[arg(long = "taxonomy", value_name = "KEY")]
+        taxonomies: Vec<String>,
+        /// The layout of taxonomy term pages.
+        #[arg(long, default_value = TAXONOMY_LAYOUT)]
+        taxonomy_layout: String,
+        /// The file to write the search index to, relative to the output
+        /// directory, e.g. `search.json`.
+        #[arg(long, value_name = "PATH")]
+        search_index: Option<PathBuf>,
+        /// The layout of redirect stubs; defaults to a built-in page.
+        #[arg(long, value_name = "LAYOUT")]
+        redirect_layout: Option<String>,
+        /// Writes redirects to a server redirect map, `netlify` or
+        /// `nginx`, instead of stub pages.
+        #[arg(long, value_name = "FORMAT", value_parser = parse_map_format)]
+        redirect_map: Option<MapFormat>,
+        /// Renders identical output on every run: `now()` reads
+        /// `SOURCE_DATE_EPOCH`, or the Unix epoch if it is unset.
+        #[arg(long)]
+        deterministic: bool,
+        /// A directory of assets, such as images and fonts, copied to
+        /// the output directory.
+        #[arg(long, value_name = "DIR")]
+        static_dir: Option<PathBuf>,
+        /// Also writes Brotli `.br` and gzip `.gz` copies of the pages,
+        /// for hosts that serve pre-compressed files.
+        #[cfg(feature = "compress")]
+        #[arg(long)]
+        precompress: bool,
+    },
+}
+
+/// The inputs of the `build` command.
+#[derive(Debug)]
+struct Site {
+    content_dir: PathBuf,
+    out_dir: PathBuf,
+    templates: PathBuf,
+    data: Option<PathBuf>,
+    environment: Environment,
+    taxonomies: Vec<String>,
+    taxonomy_layout: String,
+    search_index: Option<PathBuf>,
+    redirect_layout: Option<String>,
+    redirect_map: Option<MapFormat>,
+    deterministic: bool,
+    static_dir: Option<PathBuf>,
+    #[cfg(feature = "compress")]
+    precompress: bool,
+}
+
+/// A content page to render.
+#[derive(Debug)]
+struct Page {
+    source: PathBuf,
+    context: Context,
+    outputs: Vec<(Output, PathBuf)>,
+}
+
+fn main() {
+    let result = match Cli::parse().command {
+        Command::Render {
+            template,
+            context,
+            out,
+        } => render(&template, context.as_deref(), out.as_deref()),
+        Command::Lint { dir } => lint(&dir),
+        Command::Build {
+            content_dir,
+            out_dir,
+            templates,
+            data,
+            env,
+            flag,
+            taxonomies,
+            taxonomy_layout,
+            search_index,
+            redirect_layout,
+            redirect_map,
+            deterministic,
+            static_dir,
+            #[cfg(feature = "compress")]
+            precompress,
+        } => {
+            let mut environment = Environment::new(env);
+            for (name, value) in &flag {
+                environment.set_flag(name, value);
+            }
+            build(&Site {
+                content_dir,
+                out_dir,
+                templates,
+                data,
+                environment,
+                taxonomies,
+                taxonomy_layout,
+                search_index,
+                redirect_layout,
+                redirect_map,
+                deterministic,
+                static_dir,
+                #[cfg(feature = "compress")]
+                precompress,
+            })
+        }
+    };
+    match result {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+/// Renders `template` with the JSON context in `context`, writing the
+/// result to `out` or to standard output.
+fn render(
+    template: &Path,
+    context: Option<&Path>,
+    out: Option<&Path>,
+) -> Result<bool, Box<dyn Error>> {
+    let context = match context {
+        Some(path) => read_context(path)?,
+        None => Context::new(),
+    };
+    let engine = Engine::new("", CACHE_TTL);
+    let source = fs::read_to_string(template)?;
+    let format = engine.output_format_for_template(template, &source);
+    let page = engine
+        .render_template_with_format(&source, &context, format)?;
+    match out {
+        Some(out) => fs::write(out, page)?,
+        None => print!("{}", page),
+    }
+    Ok(true)
+}
+
+/// Reports the syntax errors of every template under `dir`.
+///
+/// Templates are rendered with an empty context, so unresolved tags are
+/// expected and not reported. Returns whether every template is valid.
+fn lint(dir: &Path) -> Result<bool, Box<dyn Error>> {
+    let engine = Engine::new("", CACHE_TTL);
+    let mut valid = true;
+    for path in files(dir)? {
+        let source = fs::read_to_string(&path)?;
+        let Err(report) =
+            engine.render_template_report(&source, &Context::new())
+        else {
+            continue;
+        };
+        for problem in &report.problems {
+            if matches!(problem.error, EngineError::Render(_)) {
+                continue;
+            }
+            valid = false;
+            let diagnostic = Diagnostic {
+                file: Some(path.clone()),
+                ..Diagnostic::from(problem)
+            };
+            eprintln!("{}", diagnostic);
+        }
+    }
+    Ok(valid)
+}
+
+/// Renders every `.json` file under the content directory of `site` to
+/// a page in its output directory.
+///
+/// The other files of the content directory and the files of the static
+/// directory are copied first, keeping their relative paths, so that the
+/// `sri()` function hashes the copies.
+///
+/// Each output of a page keeps the relative path of its content file,
+/// with the extension of its layout; the first output is the page URL
+/// listed in taxonomies and redirected to from aliases. Pages not
+/// published in the environment are skipped. The term pages of each
+/// taxonomy are written next, then the redirects of page aliases, and
+/// last the search index of the HTML content pages, if one is
+/// requested. Returns whether every page was built.
+fn build(site: &Site) -> Result<bool, Box<dyn Error>> {
+    let mut engine =
+        Engine::new(&site.templates.to_string_lossy(), CACHE_TTL);
+    engine.environment = site.environment.clone();
+    engine.deterministic = site.deterministic;
+    if let Some(data) = &site.data {
+        let _ = engine.set_data(DataDir::from_dir(data)?);
+    }
+    let mut taxonomies: Vec<Taxonomy> = site
+        .taxonomies
+        .iter()
+        .map(|name| Taxonomy::new(name))
+        .collect();
+
+    let (sources, assets): (Vec<_>, Vec<_>) =
+        files(&site.content_dir)?.into_iter().partition(|source| {
+            source.extension().map_or(false, |ext| ext == "json")
+        });
+    copy_assets(&site.content_dir, &assets, &site.out_dir)?;
+    if let Some(dir) = &site.static_dir {
+        copy_assets(dir, &files(dir)?, &site.out_dir)?;
+    }
+    engine.set_asset_dir(&site.out_dir);
+
+    let mut redirects = Redirects::new();
+    let mut pages = Vec::new();
+    for source in sources {
+        let context = read_context(&source)?;
+        if !engine.environment.publishes(&context) {
+            println!("{} skipped (draft)", source.display());
+            continue;
+        }
+        let layout =
+            engine.layout_for(&context, DEFAULT_LAYOUT).into_owned();
+        let relative = source.strip_prefix(&site.content_dir)?;
+        let outputs: Vec<_> = outputs(&engine, &context, &layout)
+            .into_iter()
+            .map(|output| {
+                let dest = relative.with_extension(&output.extension);
+                (output, dest)
+            })
+            .collect();
+        let url = outputs
+            .first()
+            .map_or_else(String::new, |(_, dest)| url(dest));
+        for taxonomy in &mut taxonomies {
+            taxonomy.add(&url, &context);
+        }
+        let _ = redirects.add(&url, &context);
+        pages.push(Page {
+            source,
+            context,
+            outputs,
+        });
+    }
+
+    let mut built = true;
+    let mut index = SearchIndex::new();
+    for page in &pages {
+        let mut indexed = false;
+        for (output, dest) in &page.outputs {
+            let rendered = write_page(
+                &engine,
+                site,
+                &page.source,
+                &page.context,
+                &output.layout,
+                &site.out_dir.join(dest),
+            )?;
+            match rendered {
+                Some(html)
+                    if !indexed && output.extension == "html" =>
+                {
+                    indexed =
+                        index.add(&url(dest), &page.context, &html);
+                }
+                Some(_) => {}
+                None => built = false,
+            }
+        }
+    }
+
+    let layout = &site.taxonomy_layout;
+    for taxonomy in &taxonomies {
+        let dir = site.out_dir.join(taxonomy.name());
+        for (term, _) in taxonomy.terms() {
+            let Some(context) = taxonomy.term_context(term) else {
+                continue;
+            };
+            let slug = context.get("slug").cloned().unwrap_or_default();
+            let source = Path::new(taxonomy.name()).join(term);
+            for output in outputs(&engine, &context, layout) {
+                let dest =
+                    dir.join(&slug).with_extension(&output.extension);
+                built &= write_page(
+                    &engine,
+                    site,
+                    &source,
+                    &context,
+                    &output.layout,
+                    &dest,
+                )?
+                .is_some();
+            }
+        }
+    }
+
+    built &= write_redirects(&engine, site, &pages, &redirects)?;
+
+    if let Some(path) = &site.search_index {
+        let dest = site.out_dir.join(path);
+        write_output(site, &dest, &index.to_json())?;
+        println!("{} pages -> {}", index.entries.len(), dest.display());
+    }
+    Ok(built)
+}
+
+/// Copies `assets`, files under `dir`, to the same relative paths under
+/// `out_dir`, skipping those whose copy is up to date.
+fn copy_assets(
+    dir: &Path,
+    assets: &[PathBuf],
+    out_dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    for source in assets {
+        let dest = out_dir.join(source.strip_prefix(dir)?);
+        if copy_asset(source, &dest)? {
+            println!("{} -> {}", source.display(), dest.display());
+        }
+    }
+    Ok(())
+}
+
+/// Renders `layout` with `context` to `dest`, reporting a render failure
+/// against `source`. Returns the written page, or `None` if it could not
+/// be rendered.
+fn write_page(
+    engine: &Engine,
+    site: &Site,
+    source: &Path,
+    context: &Context,
+    layout: &str,
+    dest: &Path,
+) -> Result<Option<String>, Box<dyn Error>> {
+    let page = match engine.render_page(context, layout) {
+        Ok(page) => page,
+        Err(err) => {
+            eprintln!(
+                "{}: {}",
+                source.display(),
+                Diagnostic::from(&err)
+            );
+            return Ok(None);
+        }
+    };
+    write_output(site, dest, &page)?;
+    println!("{} -> {}", source.display(), dest.display());
+    Ok(Some(page))
+}
+
+/// Writes `content` to `dest`, creating its directory, followed by its
+/// pre-compressed copies if `site` asks for them.
+fn write_output(
+    site: &Site,
+    dest: &Path,
+    content: &str,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(dest, content)?;
+    #[cfg(feature = "compress")]
+    if site.precompress {
+        let _ = write_compressed(dest, content.as_bytes())?;
+    }
+    #[cfg(not(feature = "compress"))]
+    let _ = site;
+    Ok(())
+}
+
+/// Writes `redirects` to the redirect map or stub pages of `site`,
+/// skipping stubs that would replace one of `pages`. Returns whether
+/// every stub was written.
+fn write_redirects(
+    engine: &Engine,
+    site: &Site,
+    pages: &[Page],
+    redirects: &Redirects,
+) -> Result<bool, Box<dyn Error>> {
+    if let Some(format) = site.redirect_map {
+        if !redirects.is_empty() {
+            let dest = site.out_dir.join(format.file_name());
+            fs::create_dir_all(&site.out_dir)?;
+            fs::write(&dest, redirects.to_map(format))?;
+            println!(
+                "{} redirects -> {}",
+                redirects.len(),
+                dest.display()
+            );
+        }
+        return Ok(true);
+    }
+
+    let mut built = true;
+    for (from, to) in redirects.iter() {
+        let Some(path) = stub_path(from) else {
+            eprintln!("{}: invalid alias", from);
+            built = false;
+            continue;
+        };
+        if pages.iter().any(|page| {
+            page.outputs.iter().any(|(_, dest)| *dest == path)
+        }) {
+            eprintln!("{}: alias of {} is a page, skipped", from, to);
+            continue;
+        }
+        let source = Path::new(from);
+        let dest = site.out_dir.join(path);
+        built &= match &site.redirect_layout {
+            Some(layout) => write_page(
+                engine,
+                site,
+                source,
+                &Redirects::stub_context(from, to),
+                layout,
+                &dest,
+            )?
+            .is_some(),
+            None => {
+                let stub = Redirects::render_stub(engine, from, to)?;
+                write_output(site, &dest, &stub)?;
+                println!("{} -> {}", from, dest.display());
+                true
+            }
+        };
+    }
+    Ok(built)
+}
+
+/// Returns the root-relative URL of the output file at `dest`.
+fn url(dest: &Path) -> String {
+    format!("/{}", dest.to_string_lossy().replace('\\', "/"))
+}
+
+/// Parses the `--env` argument of `build`.
+fn parse_mode(name: &str) -> Result<Mode, String> {
+    Mode::from_name(name)
+        .ok_or_else(|| format!("unknown environment '{}'", name))
+}
+
+/// Parses the `--redirect-map` argument of `build`.
+fn parse_map_format(name: &str) -> Result<MapFormat, String> {
+    MapFormat::from_name(name).ok_or_else(|| {
+        format!("unknown redirect map format '{}'", name)
+    })
+}
+
+/// Parses a `NAME=VALUE` flag argument of `build`.
+fn parse_flag(flag: &str) -> Result<(String, String), String> {
+    flag.split_once('=')
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected NAME=VALUE, got '{}'", flag))
+}
+
+/// Reads a JSON object context from `path`.
+fn read_context(path: &Path) -> Result<Context, Box<dyn Error>> {
+    Ok(Context::from_json_str(&fs::read_to_string(path)?)?)
+}
+
+/// Returns the files under `dir`, recursively, in sorted order.
+fn files(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_render() {
+        let dir = TempDir::new().unwrap();
+        let template = dir.path().join("page.xml");
+        let context = dir.path().join("data.json");
+        let out = dir.path().join("out.xml");
+        fs::write(&template, "<title>{{title}}</title>").unwrap();
+        fs::write(&context, r#"{"title": "Fish & Chips"}"#).unwrap();
+
+        assert!(render(&template, Some(&context), Some(&out)).unwrap());
+        assert_eq!(
+            fs::read_to_string(out).unwrap(),
+            "<title>Fish &amp; Chips</title>"
+        );
+    }
+
+    #[test]
+    fn test_lint() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("valid.html"), "{{title}}").unwrap();
+        assert!(lint(dir.path()).unwrap());
+
+        fs::create_dir(dir.path().join("partials")).unwrap();
+        fs::write(dir.path().join("partials/broken.html"), "{{title")
+            .unwrap();
+        assert!(!lint(dir.path()).unwrap());
+    }
+
+    fn site(dir: &Path, environment: Environment) -> Site {
+        Site {
+            content_dir: dir.join("content"),
+            out_dir: dir.join("public"),
+            templates: dir.join("templates"),
+            data: None,
+            environment,
+            taxonomies: Vec::new(),
+            taxonomy_layout: TAXONOMY_LAYOUT.to_string(),
+            search_index: None,
+            redirect_layout: None,
+            redirect_map: None,
+            deterministic: false,
+            static_dir: None,
+            #[cfg(feature = "compress")]
+            precompress: false,
+        }
+    }
+
+    #[test]
+    fn test_build() {
+        let dir = TempDir::new().unwrap();
+        let content = dir.path().join("content");
+        let templates = dir.path().join("templates");
+        let out = dir.path().join("public");
+        fs::create_dir_all(content.join("blog")).unwrap();
+        fs::create_dir(&templates).unwrap();
+        fs::write(
+            templates.join("index.html"),
+            "<p>{{title}}</p>{{env.mode}}",
+        )
+        .unwrap();
+        fs::write(
+            templates.join("feed.xml"),
+            "<title>{{title}}</title>",
+        )
+        .unwrap();
+        fs::write(content.join("index.json"), r#"{"title": "Home"}"#)
+            .unwrap();
+        fs::write(
+            content.join("blog/feed.json"),
+            r#"{"title": "Blog", "layout": "feed.xml"}"#,
+        )
+        .unwrap();
+
+        fs::write(
+            templates.join("index.json"),
+            r#"{"title": "{{title}}"}"#,
+        )
+        .unwrap();
+        fs::write(
+            content.join("quote.json"),
+            r#"{"title": "Say \"hi\"", "outputs": "html, json"}"#,
+        )
+        .unwrap();
+        fs::write(
+            content.join("blog/draft.json"),
+            r#"{"title": "Soon", "draft": "true"}"#,
+        )
+        .unwrap();
+
+        let prod = site(dir.path(), Environment::default());
+        assert!(build(&prod).unwrap());
+        assert_eq!(
+            fs::read_to_string(out.join("index.html")).unwrap(),
+            "<p>Home</p>prod"
+        );
+        assert!(!out.join("blog/draft.html").exists());
+        assert_eq!(
+            fs::read_to_string(out.join("quote.html")).unwrap(),
+            "<p>Say \"hi\"</p>prod"
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("quote.json")).unwrap(),
+            r#"{"title": "Say \"hi\""}"#
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("blog/feed.xml")).unwrap(),
+            "<title>Blog</title>"
+        );
+
+        fs::write(
+            content.join("missing.json"),
+            r#"{"layout": "none"}"#,
+        )
+        .unwrap();
+        assert!(!build(&prod).unwrap());
+
+        fs::remove_file(content.join("missing.json")).unwrap();
+        let dev = Site {
+            search_index: Some(PathBuf::from("search/index.json")),
+            ..site(dir.path(), Environment::new(Mode::Dev))
+        };
+        assert!(build(&dev).unwrap());
+        assert_eq!(
+            fs::read_to_string(out.join("blog/draft.html")).unwrap(),
+            "<p>Soon</p>dev"
+        );
+        let index: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(out.join("search/index.json")).unwrap(),
+        )
+        .unwrap();
+        let urls: Vec<_> = index
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["url"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            urls,
+            ["/blog/draft.html", "/index.html", "/quote.html"]
+        );
+        assert_eq!(index[1]["body"], "home dev");
+    }
+
+    #[cfg(feature = "compress")]
+    #[test]
+    fn test_build_precompress() {
+        let dir = TempDir::new().unwrap();
+        let content = dir.path().join("content");
+        let templates = dir.path().join("templates");
+        let out = dir.path().join("public");
+        fs::create_dir_all(&content).unwrap();
+        fs::create_dir(&templates).unwrap();
+        fs::write(templates.join("index.html"), "<p>{{title}}</p>")
+            .unwrap();
+        let long = "Lorem ipsum ".repeat(100);
+        fs::write(
+            content.join("long.json"),
+            format!(r#"{{"title": "{}"}}"#, long),
+        )
+        .unwrap();
+        fs::write(content.join("short.json"), r#"{"title": "Hi"}"#)
+            .unwrap();
+
+        let site = Site {
+            precompress: true,
+            ..site(dir.path(), Environment::default())
+        };
+        assert!(build(&site).unwrap());
+        assert!(out.join("long.html.br").is_file());
+        assert!(out.join("long.html.gz").is_file());
+        assert!(!out.join("short.html.gz").exists());
+    }
+
+    #[test]
+    fn test_build_taxonomies_and_data() {
+        let dir = TempDir::new().unwrap();
+        let content = dir.path().join("content");
+        let templates = dir.path().join("templates");
+        let out = dir.path().join("public");
+        fs::create_dir_all(&content).unwrap();
+        fs::create_dir(&templates).unwrap();
+        fs::write(
+            templates.join("index.html"),
+            "{{title}} - {{data.site.title}}",
+        )
+        .unwrap();
+        fs::create_dir(dir.path().join("data")).unwrap();
+        fs::write(
+            dir.path().join("data/site.json"),
+            r#"{"title": "Blog"}"#,
+        )
+        .unwrap();
+        fs::write(
+            templates.join("taxonomy.html"),
+            "{{taxonomy}}/{{term}}: {{pages.len}} \
+             {{pages.0.title}} {{pages.0.url}} of {{terms.len}}",
+        )
+        .unwrap();
+        fs::write(
+            content.join("a.json"),
+            r#"{"title": "A", "tags": ["Rust", "Static Sites"]}"#,
+        )
+        .unwrap();
+        fs::write(
+            content.join("b.json"),
+            r#"{"title": "B", "tags": "Rust"}"#,
+        )
+        .unwrap();
+
+        let site = Site {
+            taxonomies: vec!["tags".to_string()],
+            data: Some(dir.path().join("data")),
+            ..site(dir.path(), Environment::default())
+        };
+        assert!(build(&site).unwrap());
+        assert_eq!(
+            fs::read_to_string(out.join("a.html")).unwrap(),
+            "A - Blog"
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("tags/rust.html")).unwrap(),
+            "tags/Rust: 2 A /a.html of 2"
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("tags/static-sites.html"))
+                .unwrap(),
+            "tags/Static Sites: 1 A /a.html of 2"
+        );
+    }
+
+    #[test]
+    fn test_build_redirects() {
+        let dir = TempDir::new().unwrap();
+        let content = dir.path().join("content");
+        let templates = dir.path().join("templates");
+        let out = dir.path().join("public");
+        fs::create_dir_all(&content).unwrap();
+        fs::create_dir(&templates).unwrap();
+        fs::write(templates.join("index.html"), "{{title}}").unwrap();
+        fs::write(templates.join("moved.html"), "{{from}} -> {{url}}")
+            .unwrap();
+        fs::write(
+            content.join("new.json"),
+            r#"{"title": "New", "aliases": ["/old.html", "/2019/new/"]}"#,
+        )
+        .unwrap();
+        fs::write(
+            content.join("b.json"),
+            r#"{"title": "B", "aliases": "/new.html"}"#,
+        )
+        .unwrap();
+
+        let stubs = site(dir.path(), Environment::default());
+        assert!(build(&stubs).unwrap());
+        assert!(fs::read_to_string(out.join("old.html"))
+            .unwrap()
+            .contains("url=/new.html"));
+        assert!(out.join("2019/new/index.html").is_file());
+        assert_eq!(
+            fs::read_to_string(out.join("new.html")).unwrap(),
+            "New"
+        );
+
+        let layout = Site {
+            redirect_layout: Some("moved".to_string()),
+            ..site(dir.path(), Environment::default())
+        };
+        assert!(build(&layout).unwrap());
+        assert_eq!(
+            fs::read_to_string(out.join("old.html")).unwrap(),
+            "/old.html -> /new.html"
+        );
+
+        let map = Site {
+            redirect_map: Some(MapFormat::Netlify),
+            ..site(dir.path(), Environment::default())
+        };
+        assert!(build(&map).unwrap());
+        assert_eq!(
+            fs::read_to_string(out.join("_redirects")).unwrap(),
+            "/2019/new/ /new.html 301\n/new.html /b.html 301\n/old.html /new.html 301\n"
+        );
+    }
+
+    #[test]
+    fn test_build_deterministic() {
+        let dir = TempDir::new().unwrap();
+        let content = dir.path().join("content");
+        let templates = dir.path().join("templates");
+        fs::create_dir_all(&content).unwrap();
+        fs::create_dir(&templates).unwrap();
+        fs::write(templates.join("index.html"), "{{ now('unix') }}")
+            .unwrap();
+        fs::write(content.join("index.json"), "{}").unwrap();
+
+        let site = Site {
+            deterministic: true,
+            ..site(dir.path(), Environment::default())
+        };
+        let out = dir.path().join("public/index.html");
+        assert!(build(&site).unwrap());
+        let first = fs::read_to_string(&out).unwrap();
+        assert!(build(&site).unwrap());
+        assert_eq!(fs::read_to_string(&out).unwrap(), first);
+        assert_eq!(
+            Some(first),
+            staticweaver::determinism::format_time(
+                staticweaver::determinism::BuildTime::System.now(true),
+                "unix"
+            )
+        );
+    }
+
+    #[test]
+    fn test_build_assets() {
+        let dir = TempDir::new().unwrap();
+        let content = dir.path().join("content");
+        let assets = dir.path().join("static");
+        let out = dir.path().join("public");
+        fs::create_dir_all(content.join("img")).unwrap();
+        fs::create_dir_all(assets.join("fonts")).unwrap();
+        fs::create_dir(dir.path().join("templates")).unwrap();
+        fs::write(dir.path().join("templates/index.html"), "{{title}}")
+            .unwrap();
+        fs::write(content.join("index.json"), r#"{"title": "Home"}"#)
+            .unwrap();
+        let logo = b"\x89PNG\r\n\x1a\n{{title}}\xFF";
+        fs::write(content.join("img/logo.png"), logo).unwrap();
+        fs::write(assets.join("fonts/a.woff2"), [0_u8, 1, 2]).unwrap();
+
+        let site = Site {
+            static_dir: Some(assets),
+            ..site(dir.path(), Environment::default())
+        };
+        assert!(build(&site).unwrap());
+        assert_eq!(fs::read(out.join("img/logo.png")).unwrap(), logo);
+        assert_eq!(
+            fs::read(out.join("fonts/a.woff2")).unwrap(),
+            [0, 1, 2]
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("index.html")).unwrap(),
+            "Home"
+        );
+
+        let copied = fs::metadata(out.join("img/logo.png"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert!(build(&site).unwrap());
+        assert_eq!(
+            fs::metadata(out.join("img/logo.png"))
+                .unwrap()
+                .modified()
+                .unwrap(),
+            copied
+        );
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_mode("dev"), Ok(Mode::Dev));
+        assert!(parse_mode("staging").is_err());
+        assert_eq!(
+            parse_flag("analytics=on=off"),
+            Ok(("analytics".to_string(), "on=off".to_string()))
+        );
+        assert!(parse_flag("analytics").is_err());
+        assert_eq!(parse_map_format("netlify"), Ok(MapFormat::Netlify));
+        assert!(parse_map_format("apache").is_err());
+    }
+}
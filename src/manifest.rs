@@ -0,0 +1,354 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Manifest Module
+//!
+//! This module provides the `BuildManifest` struct, the list of the
+//! files a [`SiteBuilder`](crate::site::SiteBuilder) build produced,
+//! returned in its [`BuildReport`](crate::site::BuildReport). Each
+//! entry records where a page was written, what it was rendered from,
+//! and what it contains:
+//!
+//! ```text
+//! [{"path":"blog/index.html","layout":"list","source":"templates/list.html",
+//!   "content_hash":"9f86d0…","size":1532,"render_time_us":412,
+//!   "context_hash":"8f0c6d4e2b1a3957"}]
+//! ```
+//!
+//! Deploy tools compare the manifest with that of the previous deploy
+//! to upload only the [changed](BuildManifest::changed) files and
+//! delete the [removed](BuildManifest::removed) ones, without hashing
+//! the output directory again:
+//!
+//! ```
+//! use staticweaver::manifest::{BuildManifest, ManifestEntry};
+//!
+//! let entry = |path: &str, hash: &str| ManifestEntry {
+//!     path: path.to_string(),
+//!     layout: "page".to_string(),
+//!     source: None,
+//!     content_hash: hash.to_string(),
+//!     size: 0,
+//!     render_time: None,
+//!     context_hash: None,
+//! };
+//! let deployed: BuildManifest = vec![entry("a.html", "1"), entry("b.html", "2")]
+//!     .into_iter()
+//!     .collect();
+//! let saved = deployed.to_json();
+//!
+//! let built: BuildManifest = vec![entry("a.html", "1"), entry("c.html", "3")]
+//!     .into_iter()
+//!     .collect();
+//! let deployed = BuildManifest::from_json(&saved).unwrap();
+//! let upload: Vec<&str> = built.changed(&deployed).map(|entry| entry.path.as_str()).collect();
+//! assert_eq!(upload, ["c.html"]);
+//! assert_eq!(built.removed(&deployed).collect::<Vec<_>>(), ["b.html"]);
+//! ```
+//!
+//! Entries are sorted by path, so that the manifest of a build does not
+//! depend on the order its workers finished in.
+
+use crate::error::TemplateError;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// A file produced by a build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// The path of the file, relative to the output directory.
+    pub path: String,
+    /// The layout the page was rendered with.
+    pub layout: String,
+    /// The template file of the layout, if it could be resolved.
+    pub source: Option<PathBuf>,
+    /// The SHA-256 checksum of the file, in lowercase hexadecimal.
+    pub content_hash: String,
+    /// The length of the file in bytes.
+    pub size: u64,
+    /// The time taken to render the page, or `None` if the build
+    /// skipped it, an earlier build having written it.
+    pub render_time: Option<Duration>,
+    /// The hash of the context of the page in render cache keys, with
+    /// which [`Engine::warm_cache`](crate::Engine::warm_cache) finds the
+    /// page in the disk cache for the next build, or `None` if unknown.
+    pub context_hash: Option<u64>,
+}
+
+/// The files produced by a build, as described in the
+/// [module documentation](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildManifest {
+    entries: Vec<ManifestEntry>,
+}
+
+impl BuildManifest {
+    /// Creates an empty manifest.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file to the manifest, replacing any entry with the same
+    /// path.
+    pub fn insert(&mut self, entry: ManifestEntry) {
+        match self
+            .entries
+            .binary_search_by(|other| other.path.cmp(&entry.path))
+        {
+            Ok(index) => self.entries[index] = entry,
+            Err(index) => self.entries.insert(index, entry),
+        }
+    }
+
+    /// Returns the files of the manifest, sorted by path.
+    #[must_use]
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+
+    /// Returns the entry of the file at `path`.
+    #[must_use]
+    pub fn get(&self, path: &str) -> Option<&ManifestEntry> {
+        self.entries
+            .binary_search_by(|entry| entry.path.as_str().cmp(path))
+            .ok()
+            .map(|index| &self.entries[index])
+    }
+
+    /// Returns the number of files in the manifest.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the manifest lists no file.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the total length of the files in bytes.
+    #[must_use]
+    pub fn total_size(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size).sum()
+    }
+
+    /// Returns the files that are new or whose content differs from
+    /// those of `previous`.
+    pub fn changed<'a>(
+        &'a self,
+        previous: &'a Self,
+    ) -> impl Iterator<Item = &'a ManifestEntry> + 'a {
+        self.entries.iter().filter(move |entry| {
+            previous.get(&entry.path).map_or(true, |old| {
+                old.content_hash != entry.content_hash
+            })
+        })
+    }
+
+    /// Returns the paths of the files of `previous` that are no longer
+    /// in the manifest.
+    pub fn removed<'a>(
+        &'a self,
+        previous: &'a Self,
+    ) -> impl Iterator<Item = &'a str> + 'a {
+        previous
+            .entries
+            .iter()
+            .filter(move |entry| self.get(&entry.path).is_none())
+            .map(|entry| entry.path.as_str())
+    }
+
+    /// Returns the manifest as a JSON array, with render times in
+    /// microseconds and context hashes in hexadecimal, so that they
+    /// survive JavaScript numbers.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        Value::Array(
+            self.entries
+                .iter()
+                .map(|entry| {
+                    json!({
+                        "path": entry.path,
+                        "layout": entry.layout,
+                        "source": entry.source.as_ref().map(|source| {
+                            source.to_string_lossy().replace('\\', "/")
+                        }),
+                        "content_hash": entry.content_hash,
+                        "size": entry.size,
+                        "render_time_us": entry.render_time.map(|time| {
+                            u64::try_from(time.as_micros())
+                                .unwrap_or(u64::MAX)
+                        }),
+                        "context_hash": entry.context_hash.map(|hash| {
+                            format!("{:016x}", hash)
+                        }),
+                    })
+                })
+                .collect(),
+        )
+        .to_string()
+    }
+
+    /// Parses a manifest written by [`BuildManifest::to_json`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `TemplateError::InvalidSyntax` if `json` is not a JSON
+    /// array of entries.
+    pub fn from_json(json: &str) -> Result<Self, TemplateError> {
+        let invalid = |index: usize| {
+            TemplateError::InvalidSyntax(format!(
+                "invalid manifest entry {}",
+                index + 1
+            ))
+        };
+        let values: Vec<Value> =
+            serde_json::from_str(json).map_err(|err| {
+                TemplateError::InvalidSyntax(err.to_string())
+            })?;
+        let mut manifest = Self::new();
+        for (index, value) in values.iter().enumerate() {
+            let field =
+                |name: &str| value.get(name).and_then(Value::as_str);
+            let (Some(path), Some(layout), Some(hash), Some(size)) = (
+                field("path"),
+                field("layout"),
+                field("content_hash"),
+                value.get("size").and_then(Value::as_u64),
+            ) else {
+                return Err(invalid(index));
+            };
+            let context_hash = match field("context_hash") {
+                Some(hash) => Some(
+                    u64::from_str_radix(hash, 16)
+                        .map_err(|_| invalid(index))?,
+                ),
+                None => None,
+            };
+            manifest.insert(ManifestEntry {
+                path: path.to_string(),
+                layout: layout.to_string(),
+                source: field("source").map(PathBuf::from),
+                content_hash: hash.to_string(),
+                size,
+                render_time: value
+                    .get("render_time_us")
+                    .and_then(Value::as_u64)
+                    .map(Duration::from_micros),
+                context_hash,
+            });
+        }
+        Ok(manifest)
+    }
+}
+
+impl Extend<ManifestEntry> for BuildManifest {
+    fn extend<I: IntoIterator<Item = ManifestEntry>>(
+        &mut self,
+        iter: I,
+    ) {
+        for entry in iter {
+            self.insert(entry);
+        }
+    }
+}
+
+impl FromIterator<ManifestEntry> for BuildManifest {
+    fn from_iter<I: IntoIterator<Item = ManifestEntry>>(
+        iter: I,
+    ) -> Self {
+        let mut manifest = Self::new();
+        manifest.extend(iter);
+        manifest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, hash: &str) -> ManifestEntry {
+        ManifestEntry {
+            path: path.to_string(),
+            layout: "page".to_string(),
+            source: Some(PathBuf::from("templates/page.html")),
+            content_hash: hash.to_string(),
+            size: 3,
+            render_time: Some(Duration::from_micros(1500)),
+            context_hash: Some(1),
+        }
+    }
+
+    fn manifest() -> BuildManifest {
+        vec![entry("b.html", "2"), entry("a.html", "1")]
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_entries_are_sorted_by_path() {
+        let manifest = manifest();
+        let paths: Vec<&str> = manifest
+            .entries()
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .collect();
+        assert_eq!(paths, ["a.html", "b.html"]);
+    }
+
+    #[test]
+    fn test_insert_replaces_entry_of_path() {
+        let mut manifest = manifest();
+        manifest.insert(entry("b.html", "3"));
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(manifest.get("b.html").unwrap().content_hash, "3");
+    }
+
+    #[test]
+    fn test_total_size() {
+        assert_eq!(manifest().total_size(), 6);
+        assert_eq!(BuildManifest::new().total_size(), 0);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut manifest = manifest();
+        manifest.insert(ManifestEntry {
+            source: None,
+            render_time: None,
+            ..entry("b.html", "3")
+        });
+        let json = manifest.to_json();
+        assert!(json.contains("\"render_time_us\":1500"));
+        assert!(json.contains("\"source\":null"));
+        assert_eq!(BuildManifest::from_json(&json).unwrap(), manifest);
+    }
+
+    #[test]
+    fn test_json_context_hash_is_hex() {
+        let mut manifest = BuildManifest::new();
+        manifest.insert(ManifestEntry {
+            context_hash: Some(u64::MAX),
+            ..entry("a.html", "1")
+        });
+        manifest.insert(ManifestEntry {
+            context_hash: None,
+            ..entry("b.html", "2")
+        });
+        let json = manifest.to_json();
+        assert!(json.contains("\"context_hash\":\"ffffffffffffffff\""));
+        assert!(json.contains("\"context_hash\":null"));
+        assert_eq!(BuildManifest::from_json(&json).unwrap(), manifest);
+    }
+
+    #[test]
+    fn test_from_json_rejects_incomplete_entry() {
+        assert!(BuildManifest::from_json("[{\"path\": \"a\"}]")
+            .unwrap_err()
+            .to_string()
+            .ends_with("invalid manifest entry 1"));
+    }
+}
@@ -0,0 +1,314 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Meta Module
+//!
+//! This module provides the built-in `og_tags` and `meta_tags` template
+//! functions, which emit escaped `<meta>` markup from a page's metadata,
+//! and the `MetaConfig` struct holding their site-wide settings.
+//!
+//! Both functions take the prefix of the page's keys, so that
+//! `og_tags(page)` reads `page.title`, `page.description`, and so on.
+//! Without an argument, unprefixed keys are read:
+//!
+//! ```text
+//! <head>
+//!   {{ meta_tags(page) }}
+//!   {{ og_tags(page) }}
+//! </head>
+//! ```
+//!
+//! | Key | `og_tags` | `meta_tags` |
+//! |---|---|---|
+//! | `title` | `og:title`, `twitter:title` | |
+//! | `description` | `og:description`, `twitter:description` | `description` |
+//! | `url` | `og:url` | `<link rel="canonical">` |
+//! | `image` | `og:image`, `twitter:image` | |
+//! | `image_alt` | `og:image:alt` | |
+//! | `type` | `og:type` | |
+//! | `locale` | `og:locale` | |
+//! | `author` | | `author` |
+//! | `keywords` | | `keywords` |
+//! | `robots` | | `robots` |
+//!
+//! Missing keys are skipped. URLs are made absolute with the engine's
+//! `base_url`, if it is set.
+
+use crate::engine::{Engine, EngineError};
+use crate::escape::escape_html;
+use crate::filter::absolute_url;
+use crate::function::{Arg, Call, Functions};
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// The name of the Open Graph tags function.
+pub const OG_TAGS_FUNCTION: &str = "og_tags";
+
+/// The name of the meta tags function.
+pub const META_TAGS_FUNCTION: &str = "meta_tags";
+
+/// Site-wide settings of the `og_tags` and `meta_tags` functions.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::{Context, Engine};
+/// use std::time::Duration;
+///
+/// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+/// engine.base_url = Some("https://example.com".to_string());
+/// engine.meta.site_name = Some("Example".to_string());
+///
+/// let mut context = Context::new();
+/// context.set("page.title", "Fish & Chips");
+/// context.set("page.url", "/fish.html");
+/// let html = engine
+///     .render_template("{{ og_tags(page) }}", &context)
+///     .unwrap();
+/// assert!(html.contains(r#"<meta property="og:title" content="Fish &amp; Chips">"#));
+/// assert!(html.contains(r#"<meta property="og:url" content="https://example.com/fish.html">"#));
+/// assert!(html.contains(r#"<meta property="og:site_name" content="Example">"#));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetaConfig {
+    /// The name of the site, emitted as `og:site_name`.
+    pub site_name: Option<String>,
+    /// The `og:type` of pages without a `type` key.
+    pub default_type: String,
+    /// The image of pages without an `image` key.
+    pub default_image: Option<String>,
+    /// The `og:locale` of pages without a `locale` key.
+    pub locale: Option<String>,
+    /// The Twitter account of the site, e.g. `@example`, emitted as
+    /// `twitter:site`.
+    pub twitter_site: Option<String>,
+}
+
+impl Default for MetaConfig {
+    fn default() -> Self {
+        Self {
+            site_name: None,
+            default_type: "website".to_string(),
+            default_image: None,
+            locale: None,
+            twitter_site: None,
+        }
+    }
+}
+
+/// Registers the `og_tags` and `meta_tags` functions.
+pub(crate) fn register(functions: &mut Functions) {
+    functions.register(OG_TAGS_FUNCTION, og_tags);
+    functions.register(META_TAGS_FUNCTION, meta_tags);
+}
+
+/// The metadata of a page, read through a call's key prefix.
+struct Page<'a> {
+    call: &'a Call<'a>,
+    prefix: &'a str,
+}
+
+impl<'a> Page<'a> {
+    fn new(call: &'a Call<'a>) -> Result<Self, EngineError> {
+        let prefix = match call.args() {
+            [] => "",
+            [Arg::Key(prefix) | Arg::Literal(prefix)] => prefix,
+            _ => {
+                return Err(EngineError::Render(format!(
+                    "{} takes at most one argument",
+                    call.name()
+                )))
+            }
+        };
+        Ok(Self { call, prefix })
+    }
+
+    fn engine(&self) -> &'a Engine {
+        self.call.engine()
+    }
+
+    /// Returns the non-empty value of the page key `name`.
+    fn get(&self, name: &str) -> Option<Cow<'a, str>> {
+        let key = if self.prefix.is_empty() {
+            Cow::Borrowed(name)
+        } else {
+            Cow::Owned(format!("{}.{}", self.prefix, name))
+        };
+        self.engine()
+            .lookup(&key, self.call.context())
+            .filter(|value| !value.trim().is_empty())
+    }
+
+    /// Returns the page key `name` as an absolute URL.
+    fn url(&self, name: &str) -> Option<String> {
+        let url =
+            self.get(name).map(Cow::into_owned).or_else(|| {
+                (name == "image")
+                    .then(|| self.engine().meta.default_image.clone())
+                    .flatten()
+            })?;
+        Some(match &self.engine().base_url {
+            Some(base_url) => absolute_url(base_url, &url),
+            None => url,
+        })
+    }
+}
+
+/// Appends a `<meta>` tag to `html`.
+fn push_meta(
+    html: &mut String,
+    attribute: &str,
+    name: &str,
+    content: &str,
+) {
+    if !html.is_empty() {
+        html.push('\n');
+    }
+    let _ = write!(
+        html,
+        "<meta {}=\"{}\" content=\"{}\">",
+        attribute,
+        name,
+        escape_html(content)
+    );
+}
+
+/// Emits the Open Graph and Twitter card tags of a page.
+fn og_tags(call: &Call<'_>) -> Result<String, EngineError> {
+    let page = Page::new(call)?;
+    let config = &page.engine().meta;
+    let mut html = String::new();
+    let mut og = |name: &str, content: Option<&str>| {
+        if let Some(content) = content {
+            push_meta(&mut html, "property", name, content);
+        }
+    };
+
+    let title = page.get("title");
+    let description = page.get("description");
+    let image = page.url("image");
+    let kind = page.get("type");
+    let locale = page.get("locale");
+    og("og:title", title.as_deref());
+    og("og:description", description.as_deref());
+    og(
+        "og:type",
+        Some(kind.as_deref().unwrap_or(&config.default_type)),
+    );
+    og("og:url", page.url("url").as_deref());
+    og("og:image", image.as_deref());
+    if image.is_some() {
+        og("og:image:alt", page.get("image_alt").as_deref());
+    }
+    og("og:site_name", config.site_name.as_deref());
+    og("og:locale", locale.as_deref().or(config.locale.as_deref()));
+
+    let mut twitter = |name: &str, content: Option<&str>| {
+        if let Some(content) = content {
+            push_meta(&mut html, "name", name, content);
+        }
+    };
+    twitter(
+        "twitter:card",
+        Some(if image.is_some() {
+            "summary_large_image"
+        } else {
+            "summary"
+        }),
+    );
+    twitter("twitter:site", config.twitter_site.as_deref());
+    twitter("twitter:title", title.as_deref());
+    twitter("twitter:description", description.as_deref());
+    twitter("twitter:image", image.as_deref());
+    Ok(html)
+}
+
+/// Emits the standard meta tags and canonical link of a page.
+fn meta_tags(call: &Call<'_>) -> Result<String, EngineError> {
+    let page = Page::new(call)?;
+    let mut html = String::new();
+    for name in ["description", "author", "keywords", "robots"] {
+        if let Some(content) = page.get(name) {
+            push_meta(&mut html, "name", name, &content);
+        }
+    }
+    if let Some(url) = page.url("url") {
+        if !html.is_empty() {
+            html.push('\n');
+        }
+        let _ = write!(
+            html,
+            "<link rel=\"canonical\" href=\"{}\">",
+            escape_html(&url)
+        );
+    }
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Context, Engine};
+    use std::time::Duration;
+
+    #[test]
+    fn test_og_tags() {
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        engine.meta.twitter_site = Some("@site".to_string());
+        engine.meta.default_image = Some("/default.png".to_string());
+        let mut context = Context::new();
+        context.set("title", "Home");
+        context.set("description", " ");
+
+        assert_eq!(
+            engine.render_template("{{ og_tags() }}", &context).unwrap(),
+            "<meta property=\"og:title\" content=\"Home\">\n\
+             <meta property=\"og:type\" content=\"website\">\n\
+             <meta property=\"og:image\" content=\"/default.png\">\n\
+             <meta name=\"twitter:card\" content=\"summary_large_image\">\n\
+             <meta name=\"twitter:site\" content=\"@site\">\n\
+             <meta name=\"twitter:title\" content=\"Home\">\n\
+             <meta name=\"twitter:image\" content=\"/default.png\">"
+        );
+
+        engine.meta.default_image = None;
+        context.set("post.type", "article");
+        context.set("post.image", "\"cover\".png");
+        context.set("post.image_alt", "A <cover>");
+        let html = engine
+            .render_template("{{ og_tags('post') }}", &context)
+            .unwrap();
+        assert!(html.starts_with(
+            "<meta property=\"og:type\" content=\"article\">\n\
+             <meta property=\"og:image\" content=\"&quot;cover&quot;.png\">\n\
+             <meta property=\"og:image:alt\" content=\"A &lt;cover&gt;\">"
+        ));
+        assert!(engine
+            .render_template("{{ og_tags(a, b) }}", &context)
+            .is_err());
+    }
+
+    #[test]
+    fn test_meta_tags() {
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        engine.base_url = Some("https://example.com/".to_string());
+        let mut context = Context::new();
+        assert_eq!(
+            engine
+                .render_template("{{ meta_tags(page) }}", &context)
+                .unwrap(),
+            ""
+        );
+
+        context.set("page.description", "Tom & Jerry");
+        context.set("page.robots", "noindex");
+        context.set("page.url", "/a.html");
+        assert_eq!(
+            engine
+                .render_template("{{ meta_tags(page) }}", &context)
+                .unwrap(),
+            "<meta name=\"description\" content=\"Tom &amp; Jerry\">\n\
+             <meta name=\"robots\" content=\"noindex\">\n\
+             <link rel=\"canonical\" href=\"https://example.com/a.html\">"
+        );
+    }
+}
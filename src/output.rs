@@ -0,0 +1,202 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Output Module
+//!
+//! This module provides the `Output` struct, one of the files a page is
+//! rendered to. A page lists the names of its outputs under its
+//! `outputs` key, like taxonomy terms, as a JSON array or a
+//! comma-separated string:
+//!
+//! ```text
+//! {"title": "Post", "layout": "post", "outputs": ["html", "json", "amp"]}
+//! ```
+//!
+//! Each output has its own layout, the page layout followed by the
+//! output name, and its own extension, taken from that layout's file:
+//!
+//! | Output | Layout file | Page file |
+//! |---|---|---|
+//! | `html` | `post.html` | `index.html` |
+//! | `json` | `post.json` | `index.json` |
+//! | `amp` | `post.amp.html` | `index.amp.html` |
+//!
+//! Every output is rendered from the same context, so values are escaped
+//! for each layout's format and the render cache is shared between them.
+
+use crate::context::Context;
+use crate::engine::Engine;
+use crate::taxonomy::terms;
+use std::path::Path;
+
+/// The context key listing the output names of a page.
+pub const OUTPUTS_KEY: &str = "outputs";
+
+/// The extension of outputs whose layout cannot be found.
+pub const DEFAULT_OUTPUT_EXTENSION: &str = "html";
+
+/// A file a page is rendered to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    /// The name of the output, or an empty string for the single output
+    /// of a page without an `outputs` key.
+    pub name: String,
+    /// The layout the output is rendered with.
+    pub layout: String,
+    /// The extension of the output file, such as `json` or `amp.html`.
+    pub extension: String,
+}
+
+/// Returns the outputs of the page with `context` and `layout`, in the
+/// order the page lists them.
+///
+/// A page without an `outputs` key has a single output, rendered with
+/// `layout` itself. The extension of an output whose layout cannot be
+/// resolved is its name, or [`DEFAULT_OUTPUT_EXTENSION`] for that single
+/// output, so that rendering it reports the missing layout.
+///
+/// # Arguments
+///
+/// * `engine` - The engine resolving the layouts.
+/// * `context` - The context of the page.
+/// * `layout` - The layout of the page.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::output::outputs;
+/// use staticweaver::{Context, Engine};
+/// use std::time::Duration;
+///
+/// let engine = Engine::new("templates", Duration::from_secs(3600));
+/// let mut context = Context::new();
+/// context.set("outputs", "html, json");
+///
+/// let outputs = outputs(&engine, &context, "post");
+/// assert_eq!(outputs[1].layout, "post.json");
+/// assert_eq!(outputs[1].extension, "json");
+/// ```
+#[must_use]
+pub fn outputs(
+    engine: &Engine,
+    context: &Context,
+    layout: &str,
+) -> Vec<Output> {
+    let names = context.get(OUTPUTS_KEY).map(|names| terms(names));
+    let Some(names) = names.filter(|names| !names.is_empty()) else {
+        return vec![output(engine, "", layout, layout.to_string())];
+    };
+    let base = Path::new(layout)
+        .extension()
+        .map_or(layout, |ext| &layout[..layout.len() - ext.len() - 1]);
+    names
+        .into_iter()
+        .map(|name| {
+            let layout = format!("{}.{}", base, name);
+            output(engine, &name, base, layout)
+        })
+        .collect()
+}
+
+/// Returns the output `name` rendered with `layout`, whose extension is
+/// what its file name adds to the file name of `base`.
+fn output(
+    engine: &Engine,
+    name: &str,
+    base: &str,
+    layout: String,
+) -> Output {
+    let stem = Path::new(base)
+        .file_name()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = engine
+        .resolve_template(&layout)
+        .and_then(|path| {
+            let file_name =
+                path.file_name()?.to_string_lossy().into_owned();
+            let suffix = file_name.strip_prefix(&stem)?;
+            match suffix.strip_prefix('.') {
+                Some(extension) => Some(extension.to_string()),
+                None if suffix.is_empty() => path
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().into_owned()),
+                None => None,
+            }
+        })
+        .unwrap_or_else(|| {
+            if name.is_empty() {
+                DEFAULT_OUTPUT_EXTENSION.to_string()
+            } else {
+                name.to_string()
+            }
+        });
+    Output {
+        name: name.to_string(),
+        layout,
+        extension,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    /// Returns an engine over layouts for every output of a post and
+    /// a feed.
+    fn engine(dir: &TempDir) -> Engine {
+        for name in
+            ["post.html", "post.json", "post.amp.html", "feed.xml"]
+        {
+            fs::write(dir.path().join(name), "{{title}}").unwrap();
+        }
+        Engine::new(
+            &dir.path().to_string_lossy(),
+            Duration::from_secs(60),
+        )
+    }
+
+    #[test]
+    fn test_single_output_uses_layout_extension() {
+        let dir = TempDir::new().unwrap();
+        let single =
+            outputs(&engine(&dir), &Context::new(), "feed.xml");
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].layout, "feed.xml");
+        assert_eq!(single[0].extension, "xml");
+    }
+
+    #[test]
+    fn test_single_output_defaults_to_html() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(
+            outputs(&engine(&dir), &Context::new(), "none")[0]
+                .extension,
+            "html"
+        );
+    }
+
+    #[test]
+    fn test_outputs_key_lists_formats() {
+        let dir = TempDir::new().unwrap();
+        let mut context = Context::new();
+        context.set(OUTPUTS_KEY, r#"["html", "json", "amp", "txt"]"#);
+        let extensions: Vec<_> =
+            outputs(&engine(&dir), &context, "post.html")
+                .into_iter()
+                .map(|output| (output.layout, output.extension))
+                .collect();
+        assert_eq!(
+            extensions,
+            [
+                ("post.html".to_string(), "html".to_string()),
+                ("post.json".to_string(), "json".to_string()),
+                ("post.amp".to_string(), "amp.html".to_string()),
+                ("post.txt".to_string(), "txt".to_string()),
+            ]
+        );
+    }
+}
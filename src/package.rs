@@ -0,0 +1,472 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Package Module
+//!
+//! This module reads and writes template packages: `.swpkg` files that
+//! bundle a [theme](crate::theme) directory with its metadata and the
+//! checksums of its files, so a template set can be distributed as one
+//! file rather than loose files over raw URLs.
+//!
+//! A package is a `.tar` archive with the following layout:
+//!
+//! ```text
+//! minimal.swpkg
+//! ├── package.json   {"format": 1, "name": "...", "version": "..."}
+//! ├── SHA256SUMS     the SHA-256 checksum of every other file
+//! ├── theme.json     (optional) as in a theme directory
+//! ├── templates/
+//! └── static/        (optional)
+//! ```
+//!
+//! Packages are written by
+//! [`Engine::save_package`](crate::Engine::save_package) and loaded as
+//! the active theme by
+//! [`Engine::load_package`](crate::Engine::load_package). A package is rejected if a
+//! file does not match its checksum, is not listed, or is missing, as
+//! described in the [`integrity`](crate::integrity) module.
+//!
+//! ```
+//! use staticweaver::Engine;
+//! use std::time::Duration;
+//!
+//! let theme = tempfile::tempdir()?;
+//! std::fs::create_dir(theme.path().join("templates"))?;
+//! std::fs::write(theme.path().join("templates/page.html"), "{{title}}")?;
+//!
+//! let out = tempfile::tempdir()?;
+//! let package = out.path().join("minimal.swpkg");
+//! let info = Engine::save_package(theme.path(), &package)?;
+//! assert_eq!(info.files, ["templates/page.html"]);
+//!
+//! let mut engine = Engine::new("templates", Duration::from_secs(60));
+//! engine.load_package(package.to_str().unwrap())?;
+//! assert!(engine.resolve_template("page").is_some());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::engine::EngineError;
+use crate::integrity::{sha256_hex, Manifest, MANIFEST_FILE};
+use crate::theme::{Theme, THEME_MANIFEST};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The extension of template package files.
+pub const PACKAGE_EXTENSION: &str = "swpkg";
+
+/// The name of the package metadata file.
+pub const PACKAGE_MANIFEST: &str = "package.json";
+
+/// The version of the package format written by [`write`].
+pub const PACKAGE_FORMAT: u64 = 1;
+
+/// The metadata of a template package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageInfo {
+    /// The name of the package.
+    pub name: String,
+    /// The version of the package, if it declares one.
+    pub version: Option<String>,
+    /// The files of the package, relative to its root and separated by
+    /// `/`, in sorted order. The metadata and checksum files are not
+    /// listed.
+    pub files: Vec<String>,
+}
+
+/// Writes the theme directory `dir` as a package to `out`.
+///
+/// The package is named and versioned after the `name` and `version` of
+/// the theme manifest, if any, and named after the directory otherwise.
+/// Entries carry no timestamps or owners, so a directory always packages
+/// to the same bytes.
+///
+/// # Errors
+///
+/// * `EngineError::Io` - If `dir` is not a directory, or a file cannot
+///   be read or written.
+/// * `EngineError::InvalidTemplate` - If the theme manifest is
+///   malformed or a file name is not valid UTF-8.
+pub fn write<W: Write>(
+    dir: &Path,
+    out: W,
+) -> Result<PackageInfo, EngineError> {
+    if !dir.is_dir() {
+        return Err(EngineError::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Template directory not found: {}", dir.display()),
+        )));
+    }
+
+    let mut files = Vec::new();
+    for path in package_files(dir)? {
+        let name = relative_name(dir, &path)?;
+        if !is_reserved(&name) {
+            files.push((name, fs::read(&path)?));
+        }
+    }
+    files.sort();
+
+    let mut name = dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("theme")
+        .to_string();
+    let mut version = None;
+    let theme_manifest = dir.join(THEME_MANIFEST);
+    if theme_manifest.is_file() {
+        let manifest = parse_json(&theme_manifest)?;
+        if let Some(theme_name) =
+            manifest.get("name").and_then(|name| name.as_str())
+        {
+            name = theme_name.to_string();
+        }
+        version = manifest
+            .get("version")
+            .and_then(|version| version.as_str())
+            .map(str::to_string);
+    }
+
+    let metadata = serde_json::json!({
+        "format": PACKAGE_FORMAT,
+        "name": name,
+        "version": version,
+    });
+    let checksums: String = files
+        .iter()
+        .map(|(name, content)| {
+            format!("{}  {}\n", sha256_hex(content), name)
+        })
+        .collect();
+
+    let mut builder = tar::Builder::new(out);
+    append(
+        &mut builder,
+        PACKAGE_MANIFEST,
+        metadata.to_string().as_bytes(),
+    )?;
+    append(&mut builder, MANIFEST_FILE, checksums.as_bytes())?;
+    for (name, content) in &files {
+        append(&mut builder, name, content)?;
+    }
+    let _ = builder.into_inner()?;
+
+    Ok(PackageInfo {
+        name,
+        version,
+        files: files.into_iter().map(|(name, _)| name).collect(),
+    })
+}
+
+/// Reads the package in `archive` into a theme, after verifying every
+/// file against the checksums of the package.
+///
+/// The package is extracted into a temporary directory that is removed
+/// once the last clone of the theme is dropped.
+///
+/// # Errors
+///
+/// * `EngineError::Io` - If the archive cannot be read or extracted.
+/// * `EngineError::InvalidTemplate` - If the archive is not a package,
+///   has an unsupported format, or contains links or special files.
+/// * `EngineError::IntegrityCheckFailed` - If a file does not match its
+///   checksum, is not listed, or is missing.
+pub fn read<R: Read>(
+    archive: R,
+) -> Result<(PackageInfo, Theme), EngineError> {
+    let extracted = tempfile::tempdir()?;
+    let root = extracted.path();
+    let mut archive = tar::Archive::new(archive);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let kind = entry.header().entry_type();
+        if !kind.is_file() && !kind.is_dir() {
+            return Err(EngineError::InvalidTemplate(format!(
+                "Template package entry {} is not a file or directory",
+                entry.path()?.display()
+            )));
+        }
+        let _ = entry.unpack_in(root)?;
+    }
+
+    let metadata_path = root.join(PACKAGE_MANIFEST);
+    if !metadata_path.is_file() {
+        return Err(EngineError::InvalidTemplate(format!(
+            "Not a template package: missing {}",
+            PACKAGE_MANIFEST
+        )));
+    }
+    let metadata = parse_json(&metadata_path)?;
+    let format =
+        metadata.get("format").and_then(|format| format.as_u64());
+    if format != Some(PACKAGE_FORMAT) {
+        return Err(EngineError::InvalidTemplate(format!(
+            "Unsupported template package format: {}",
+            metadata.get("format").unwrap_or(&serde_json::Value::Null)
+        )));
+    }
+    let Some(name) =
+        metadata.get("name").and_then(|name| name.as_str())
+    else {
+        return Err(EngineError::InvalidTemplate(format!(
+            "Template package {} has no name",
+            PACKAGE_MANIFEST
+        )));
+    };
+    let version = metadata
+        .get("version")
+        .and_then(|version| version.as_str())
+        .map(str::to_string);
+
+    let checksums = fs::read_to_string(root.join(MANIFEST_FILE))
+        .map_err(|_| missing(MANIFEST_FILE))?;
+    let checksums = Manifest::parse(&checksums)?;
+    let mut files = Vec::new();
+    for path in package_files(root)? {
+        let name = relative_name(root, &path)?;
+        if !is_reserved(&name) {
+            checksums.verify(&name, &fs::read(&path)?)?;
+            files.push(name);
+        }
+    }
+    files.sort();
+    if let Some(file) = checksums.files().find(|file| {
+        files
+            .binary_search_by(|name| name.as_str().cmp(file))
+            .is_err()
+    }) {
+        return Err(missing(file));
+    }
+
+    let info = PackageInfo {
+        name: name.to_string(),
+        version,
+        files,
+    };
+    let theme = Theme::from_extracted(extracted, &info.name)?;
+    Ok((info, theme))
+}
+
+/// Appends a regular file to a package, without timestamps or owners.
+fn append<W: Write>(
+    builder: &mut tar::Builder<W>,
+    name: &str,
+    content: &[u8],
+) -> Result<(), EngineError> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    builder.append_data(&mut header, name, content)?;
+    Ok(())
+}
+
+/// Returns the files under `dir`, recursively, in sorted order.
+fn package_files(dir: &Path) -> Result<Vec<PathBuf>, EngineError> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Returns the name of `path` in a package rooted at `root`.
+fn relative_name(
+    root: &Path,
+    path: &Path,
+) -> Result<String, EngineError> {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()
+        .map(|parts| parts.join("/"))
+        .ok_or_else(|| {
+            EngineError::InvalidTemplate(format!(
+                "File name is not valid UTF-8: {}",
+                path.display()
+            ))
+        })
+}
+
+/// Returns whether `name` is the metadata or checksum file of a package.
+fn is_reserved(name: &str) -> bool {
+    name == PACKAGE_MANIFEST || name == MANIFEST_FILE
+}
+
+/// Parses the JSON file at `path`.
+fn parse_json(path: &Path) -> Result<serde_json::Value, EngineError> {
+    serde_json::from_str(&fs::read_to_string(path)?).map_err(|err| {
+        EngineError::InvalidTemplate(format!(
+            "Invalid manifest {}: {}",
+            path.display(),
+            err
+        ))
+    })
+}
+
+/// Returns the error of a file listed in a package but missing from it.
+fn missing(file: &str) -> EngineError {
+    EngineError::IntegrityCheckFailed {
+        file: file.to_string(),
+        message: "missing from the package".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_theme_dir() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("templates")).unwrap();
+        fs::create_dir_all(dir.path().join("static/css")).unwrap();
+        fs::write(dir.path().join("templates/page.html"), "{{title}}")
+            .unwrap();
+        fs::write(dir.path().join("static/css/site.css"), "body {}")
+            .unwrap();
+        fs::write(
+            dir.path().join(THEME_MANIFEST),
+            r#"{"name": "minimal", "version": "1.2.0"}"#,
+        )
+        .unwrap();
+        dir
+    }
+
+    /// Returns a package of `files`, with `checksums` as its checksums.
+    fn package_of(files: &[(&str, &str)], checksums: &str) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let metadata = r#"{"format": 1, "name": "minimal"}"#;
+        append(&mut builder, PACKAGE_MANIFEST, metadata.as_bytes())
+            .unwrap();
+        append(&mut builder, MANIFEST_FILE, checksums.as_bytes())
+            .unwrap();
+        for (name, content) in files {
+            append(&mut builder, name, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let dir = create_theme_dir();
+        let mut first = Vec::new();
+        let info = write(dir.path(), &mut first).unwrap();
+        assert_eq!(info.name, "minimal");
+        assert_eq!(info.version.as_deref(), Some("1.2.0"));
+        assert_eq!(
+            info.files,
+            [
+                "static/css/site.css",
+                "templates/page.html",
+                THEME_MANIFEST
+            ]
+        );
+
+        let mut second = Vec::new();
+        let _ = write(dir.path(), &mut second).unwrap();
+        assert_eq!(first, second);
+
+        let (read_info, theme) = read(first.as_slice()).unwrap();
+        assert_eq!(read_info, info);
+        assert_eq!(theme.name(), "minimal");
+        assert_eq!(
+            fs::read_to_string(theme.templates_dir().join("page.html"))
+                .unwrap(),
+            "{{title}}"
+        );
+        assert!(theme.static_dir().is_some());
+    }
+
+    #[test]
+    fn test_read_rejects_tampered_packages() {
+        let page = format!("{}  page.html\n", sha256_hex(b"page"));
+
+        assert!(read(
+            package_of(&[("page.html", "page")], &page).as_slice()
+        )
+        .is_ok());
+
+        let err = read(
+            package_of(&[("page.html", "tampered")], &page).as_slice(),
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), "integrity_check_failed");
+
+        let err = read(
+            package_of(
+                &[("page.html", "page"), ("extra.html", "")],
+                &page,
+            )
+            .as_slice(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().ends_with("not listed in SHA256SUMS"));
+
+        let err = read(package_of(&[], &page).as_slice()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Integrity check failed for page.html: missing from the package"
+        );
+
+        let err = read(&b""[..]).unwrap_err();
+        assert!(err.to_string().contains("Not a template package"));
+    }
+
+    #[test]
+    fn test_engine_packages() {
+        use crate::fetch::StaticFetcher;
+        use crate::integrity::Integrity;
+        use crate::{Context, Engine};
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let dir = create_theme_dir();
+        let out = TempDir::new().unwrap();
+        let path = out.path().join("minimal.swpkg");
+        let info = Engine::save_package(dir.path(), &path).unwrap();
+
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        assert_eq!(
+            engine.load_package(path.to_str().unwrap()).unwrap(),
+            info
+        );
+        let mut context = Context::new();
+        context.set("title".to_string(), "Hello".to_string());
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "Hello"
+        );
+
+        let url = "https://example.com/themes/minimal.swpkg";
+        let bytes = fs::read(&path).unwrap();
+        let fetcher = StaticFetcher::new();
+        fetcher.insert(url, String::from_utf8(bytes.clone()).unwrap());
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        engine.set_fetcher(Arc::new(fetcher.clone()));
+        engine.integrity = Some(Integrity::pinned(format!(
+            "{}  minimal.swpkg\n",
+            sha256_hex(&bytes)
+        )));
+        assert_eq!(engine.load_package(url).unwrap().name, "minimal");
+        assert_eq!(engine.theme().unwrap().name(), "minimal");
+
+        engine.integrity = Some(Integrity::pinned(format!(
+            "{}  minimal.swpkg\n",
+            sha256_hex(b"another package")
+        )));
+        let err = engine.load_package(url).unwrap_err();
+        assert_eq!(err.code(), "integrity_check_failed");
+    }
+}
@@ -0,0 +1,534 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Parser Module
+//!
+//! This module splits templates into literal text and tags. The engine
+//! renders templates with it, and it can be used on its own to validate
+//! untrusted templates before rendering them.
+//!
+//! The parser upholds the following invariants for any input, including
+//! invalid UTF-8 passed to [`parse_unchecked_input`]:
+//!
+//! - It never panics.
+//! - It produces at most one token per byte of input, and never more
+//!   than one error per tag, so memory use is linear in the input size.
+//! - The spans of the tokens it produces are in order, never overlap,
+//!   and cover the whole template, so that [`serialize`] reproduces the
+//!   parsed template exactly.
+//!
+//! # Examples
+//!
+//! ```
+//! use staticweaver::parser::{self, Segment};
+//!
+//! let tokens = parser::parse("Hello, {{name}}!").unwrap();
+//! assert_eq!(tokens[1].segment, Segment::Tag("name"));
+//! assert_eq!(parser::serialize(&tokens, "{{", "}}"), "Hello, {{name}}!");
+//! ```
+
+use crate::engine::EngineError;
+use memchr::memmem::{self, Finder};
+use std::fmt;
+use std::ops::Range;
+
+/// The default opening delimiter of tags.
+pub const DEFAULT_OPEN_DELIM: &str = "{{";
+
+/// The default closing delimiter of tags.
+pub const DEFAULT_CLOSE_DELIM: &str = "}}";
+
+/// The separator of filters inside tags, which delimiters must not
+/// contain.
+const FILTER_SEPARATOR: char = '|';
+
+/// The largest input, in bytes, accepted by [`parse_unchecked_input`].
+pub const MAX_INPUT_LEN: usize = 16 * 1024 * 1024;
+
+/// A piece of a template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment<'a> {
+    /// Literal text, copied to the output as is.
+    Text(&'a str),
+    /// The contents of a tag, without its delimiters.
+    Tag(&'a str),
+}
+
+/// A segment and the byte range it spans in the template, delimiters
+/// included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token<'a> {
+    /// The parsed segment.
+    pub segment: Segment<'a>,
+    /// The byte range of the segment in the template.
+    pub span: Range<usize>,
+}
+
+/// A problem found while parsing a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The byte range of the template the problem was found in.
+    pub span: Range<usize>,
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(span: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseError> for EngineError {
+    fn from(err: ParseError) -> Self {
+        Self::InvalidTemplate(err.message)
+    }
+}
+
+/// An iterator over the tokens of a template.
+///
+/// Errors in a single tag, such as nested delimiters, are reported and
+/// parsing continues with the next tag. Errors that make the rest of the
+/// template ambiguous, such as an unclosed tag, end the iteration.
+///
+/// Delimiters are found with the SIMD-accelerated substring search of
+/// the `memchr` crate, so scanning long runs of literal text is fast.
+#[derive(Debug, Clone)]
+pub struct Parser<'a> {
+    template: &'a str,
+    open: &'a str,
+    close: &'a str,
+    open_finder: Finder<'a>,
+    close_finder: Finder<'a>,
+    pos: usize,
+    state: State,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Start,
+    Parsing,
+    Done,
+}
+
+impl<'a> Parser<'a> {
+    /// Creates a parser for `template` with the given tag delimiters.
+    #[must_use]
+    pub fn new(
+        template: &'a str,
+        open: &'a str,
+        close: &'a str,
+    ) -> Self {
+        Self {
+            template,
+            open,
+            close,
+            open_finder: Finder::new(open),
+            close_finder: Finder::new(close),
+            pos: 0,
+            state: State::Start,
+        }
+    }
+
+    /// Checks the template as a whole before its first token.
+    fn check(&self) -> Result<(), ParseError> {
+        let template = self.template;
+        if self.open.is_empty() || self.close.is_empty() {
+            return Err(ParseError::new(
+                0..0,
+                "Template delimiters must not be empty",
+            ));
+        }
+        if template.trim().is_empty() {
+            return Err(ParseError::new(
+                0..template.len(),
+                "Template is empty",
+            ));
+        }
+        if self.find_open(template).is_some() {
+            return Ok(());
+        }
+        let single = self.open.chars().next().unwrap_or_default();
+        let mut buf = [0; 4];
+        let needle = single.encode_utf8(&mut buf).as_bytes();
+        if let Some(idx) = memmem::find(template.as_bytes(), needle) {
+            return Err(ParseError::new(
+                idx..idx + needle.len(),
+                format!(
+                    "Invalid template syntax: single '{}' are not allowed",
+                    single
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the offset of the first opening delimiter in `s`.
+    fn find_open(&self, s: &str) -> Option<usize> {
+        self.open_finder.find(s.as_bytes())
+    }
+
+    /// Returns the offset of the first closing delimiter in `s`.
+    fn find_close(&self, s: &str) -> Option<usize> {
+        self.close_finder.find(s.as_bytes())
+    }
+
+    /// Parses the token starting at the current position.
+    fn parse_token(&mut self) -> Option<Result<Token<'a>, ParseError>> {
+        let rest = self.template.get(self.pos..).unwrap_or_default();
+        if rest.is_empty() {
+            self.state = State::Done;
+            return None;
+        }
+        let start = self.pos;
+        let Some(found) = self.find_open(rest) else {
+            self.pos = self.template.len();
+            return Some(Ok(Token {
+                segment: Segment::Text(rest),
+                span: start..self.pos,
+            }));
+        };
+        if found > 0 {
+            self.pos += found;
+            return Some(Ok(Token {
+                segment: Segment::Text(&rest[..found]),
+                span: start..self.pos,
+            }));
+        }
+
+        let tag_start = start + self.open.len();
+        let Some(len) = self.find_close(&self.template[tag_start..])
+        else {
+            self.state = State::Done;
+            return Some(Err(ParseError::new(
+                start..self.template.len(),
+                "Unclosed template tag",
+            )));
+        };
+        let tag = &self.template[tag_start..tag_start + len];
+        self.pos = tag_start + len + self.close.len();
+        if self.find_open(tag).is_some() {
+            return Some(Err(ParseError::new(
+                start..self.pos,
+                "Nested delimiters are not allowed",
+            )));
+        }
+        Some(Ok(Token {
+            segment: Segment::Tag(tag),
+            span: start..self.pos,
+        }))
+    }
+}
+
+impl<'a> Iterator for Parser<'a> {
+    type Item = Result<Token<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.state {
+            State::Done => None,
+            State::Start => {
+                if let Err(err) = self.check() {
+                    self.state = State::Done;
+                    return Some(Err(err));
+                }
+                self.state = State::Parsing;
+                self.parse_token()
+            }
+            State::Parsing => self.parse_token(),
+        }
+    }
+}
+
+/// Checks that a pair of tag delimiters can be parsed unambiguously.
+///
+/// Delimiters must be non-empty, free of whitespace and of the `|`
+/// filter separator, and distinct, with neither one containing the
+/// other.
+///
+/// # Arguments
+///
+/// * `open` - The opening delimiter.
+/// * `close` - The closing delimiter.
+///
+/// # Errors
+///
+/// Returns [`EngineError::InvalidDelimiters`] describing the first
+/// problem found.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::parser::validate_delimiters;
+///
+/// assert!(validate_delimiters("<%", "%>").is_ok());
+/// assert!(validate_delimiters("", "}}").is_err());
+/// assert!(validate_delimiters("{{", "{{").is_err());
+/// assert!(validate_delimiters("[", "[[").is_err());
+/// assert!(validate_delimiters("{|", "|}").is_err());
+/// ```
+pub fn validate_delimiters(
+    open: &str,
+    close: &str,
+) -> Result<(), EngineError> {
+    let problem = if open.is_empty() || close.is_empty() {
+        Some("delimiters must not be empty".to_string())
+    } else if open.chars().chain(close.chars()).any(char::is_whitespace)
+    {
+        Some("delimiters must not contain whitespace".to_string())
+    } else if open.contains(FILTER_SEPARATOR)
+        || close.contains(FILTER_SEPARATOR)
+    {
+        Some(format!(
+            "delimiters must not contain the filter separator '{}'",
+            FILTER_SEPARATOR
+        ))
+    } else if open == close {
+        Some(
+            "the opening and closing delimiters must differ"
+                .to_string(),
+        )
+    } else if open.contains(close) || close.contains(open) {
+        Some("one delimiter must not contain the other".to_string())
+    } else {
+        None
+    };
+    match problem {
+        Some(message) => Err(EngineError::InvalidDelimiters {
+            open: open.to_string(),
+            close: close.to_string(),
+            message,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Parses `template` with the default delimiters, stopping at the first
+/// problem.
+///
+/// # Errors
+///
+/// Returns the first problem found in the template.
+pub fn parse(template: &str) -> Result<Vec<Token<'_>>, ParseError> {
+    Parser::new(template, DEFAULT_OPEN_DELIM, DEFAULT_CLOSE_DELIM)
+        .collect()
+}
+
+/// Parses untrusted bytes as a template with the default delimiters,
+/// stopping at the first problem.
+///
+/// Use this for templates from untrusted sources, such as user uploads:
+/// the input is checked for size and encoding before it is parsed.
+///
+/// # Errors
+///
+/// Returns an error if the input is longer than [`MAX_INPUT_LEN`], is not
+/// valid UTF-8, or is not a valid template.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::parser;
+///
+/// assert!(parser::parse_unchecked_input(b"Hello, {{name}}!").is_ok());
+/// assert!(parser::parse_unchecked_input(b"Hello, \xff").is_err());
+/// ```
+pub fn parse_unchecked_input(
+    input: &[u8],
+) -> Result<Vec<Token<'_>>, ParseError> {
+    if input.len() > MAX_INPUT_LEN {
+        return Err(ParseError::new(
+            MAX_INPUT_LEN..input.len(),
+            format!(
+                "Template is larger than the limit of {} bytes",
+                MAX_INPUT_LEN
+            ),
+        ));
+    }
+    let template = std::str::from_utf8(input).map_err(|err| {
+        let start = err.valid_up_to();
+        let len = err.error_len().unwrap_or(input.len() - start);
+        ParseError::new(
+            start..start + len,
+            format!("Template is not valid UTF-8: {}", err),
+        )
+    })?;
+    parse(template)
+}
+
+/// Writes `tokens` back out as template text, wrapping tags in the given
+/// delimiters.
+///
+/// For tokens produced by a successful parse, this reproduces the
+/// original template.
+#[must_use]
+pub fn serialize(
+    tokens: &[Token<'_>],
+    open: &str,
+    close: &str,
+) -> String {
+    let mut output = String::new();
+    for token in tokens {
+        match token.segment {
+            Segment::Text(text) => output.push_str(text),
+            Segment::Tag(tag) => {
+                output.push_str(open);
+                output.push_str(tag);
+                output.push_str(close);
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parse_segments() {
+        let tokens = parse("a{{b}}{{c}}d").unwrap();
+        let segments: Vec<_> =
+            tokens.iter().map(|token| token.segment).collect();
+        assert_eq!(
+            segments,
+            [
+                Segment::Text("a"),
+                Segment::Tag("b"),
+                Segment::Tag("c"),
+                Segment::Text("d"),
+            ]
+        );
+        assert_eq!(tokens[1].span, 1..6);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse(" ").unwrap_err().message,
+            "Template is empty"
+        );
+        assert_eq!(
+            parse("a {{b").unwrap_err(),
+            ParseError::new(2..5, "Unclosed template tag")
+        );
+        assert_eq!(parse("{b}").unwrap_err().span, 0..1);
+        assert_eq!(
+            parse("{{a {{b}} c}}").unwrap_err().message,
+            "Nested delimiters are not allowed"
+        );
+    }
+
+    #[test]
+    fn test_parser_continues_after_nested_delimiters() {
+        let results: Vec<_> = Parser::new("{{a{{b}}{{c}}", "{{", "}}")
+            .map(|result| result.is_ok())
+            .collect();
+        assert_eq!(results, [false, true]);
+    }
+
+    #[test]
+    fn test_parser_rejects_empty_delimiters() {
+        assert!(Parser::new("{{a}}", "", "}}")
+            .next()
+            .unwrap()
+            .is_err());
+        assert!(Parser::new("{{a}}", "{{", "")
+            .next()
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_validate_delimiters() {
+        for (open, close) in
+            [("{{", "}}"), ("<%", "%>"), ("«", "»"), ("[[", "]]")]
+        {
+            assert!(validate_delimiters(open, close).is_ok());
+        }
+        for (open, close) in [
+            ("", "}}"),
+            ("{{", ""),
+            ("{ {", "}}"),
+            ("{{", "}}\n"),
+            ("{|", "}}"),
+            ("%%", "%%"),
+            ("<", "<<"),
+            ("{{{", "{"),
+        ] {
+            let err = validate_delimiters(open, close).unwrap_err();
+            assert_eq!(err.code(), "invalid_delimiters", "{:?}", open);
+        }
+        assert_eq!(
+            validate_delimiters("%%", "%%").unwrap_err().to_string(),
+            "Invalid delimiters '%%' and '%%': the opening and closing \
+             delimiters must differ"
+        );
+    }
+
+    #[test]
+    fn test_parser_multibyte_delimiters() {
+        let tokens: Vec<_> = Parser::new("«a» «b»", "«", "»")
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(serialize(&tokens, "«", "»"), "«a» «b»");
+        assert!(Parser::new("«a", "««", "»").next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_parse_unchecked_input_limits() {
+        assert!(parse_unchecked_input(b"{{a}}\xc3").is_err());
+        let large = vec![b'a'; MAX_INPUT_LEN + 1];
+        assert!(parse_unchecked_input(&large)
+            .unwrap_err()
+            .message
+            .contains("larger than the limit"));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_parse_never_panics(input in any::<Vec<u8>>()) {
+            let _ = parse_unchecked_input(&input);
+        }
+
+        #[test]
+        fn prop_tokens_cover_template(
+            template in "[a-z{} ]{0,64}",
+            open in "[{<]{1,2}",
+            close in "[}>]{1,2}",
+        ) {
+            let mut pos = 0;
+            let mut count = 0;
+            for result in Parser::new(&template, &open, &close) {
+                let span = match result {
+                    Ok(token) => token.span,
+                    Err(err) => err.span,
+                };
+                prop_assert!(span.start >= pos || span.is_empty());
+                pos = span.end;
+                count += 1;
+            }
+            prop_assert!(count <= template.len() + 1);
+        }
+
+        #[test]
+        fn prop_round_trip(template in "([a-z ]{0,8}(\\{\\{[a-z|]{0,8}\\}\\})?){0,8}") {
+            if let Ok(tokens) = parse(&template) {
+                let serialized = serialize(&tokens, "{{", "}}");
+                prop_assert_eq!(&serialized, &template);
+                prop_assert_eq!(parse(&serialized).unwrap(), tokens);
+            }
+        }
+    }
+}
@@ -0,0 +1,292 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Profile Module
+//!
+//! This module provides the `RenderProfile` tree returned by
+//! [`Engine::render_page_profiled`](crate::Engine::render_page_profiled),
+//! which records the time spent in each step of a render:
+//!
+//! ```text
+//! page index 12.41ms
+//!   load index 0.05ms
+//!   template index 12.30ms
+//!     tag body | markdown 11.80ms
+//!       filter markdown 11.78ms
+//!     tag gallery() 0.40ms
+//!       function gallery 0.39ms
+//! ```
+//!
+//! Functions and shortcodes that render templates of their own record
+//! them as children of their call. Profiling is opt-in: an engine that
+//! is not profiling records nothing.
+
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The kind of step a profile node records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProfileKind {
+    /// The render of a page, the root of a profile.
+    Page,
+    /// The resolution and reading of a layout file.
+    Load,
+    /// The render of a template string.
+    Template,
+    /// The resolution of a tag and its filters.
+    Tag,
+    /// The application of a filter.
+    Filter,
+    /// A call to a template function.
+    Function,
+    /// The expansion of a shortcode.
+    Shortcode,
+    /// A call to a block handler.
+    Block,
+}
+
+impl ProfileKind {
+    /// Returns the lowercase name of the kind.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Page => "page",
+            Self::Load => "load",
+            Self::Template => "template",
+            Self::Tag => "tag",
+            Self::Filter => "filter",
+            Self::Function => "function",
+            Self::Shortcode => "shortcode",
+            Self::Block => "block",
+        }
+    }
+}
+
+impl fmt::Display for ProfileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A step of a render and the steps it performed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderProfile {
+    /// The kind of step.
+    pub kind: ProfileKind,
+    /// The name of the step, such as a layout, tag, or filter name.
+    pub name: String,
+    /// The time spent in the step, including its children.
+    pub duration: Duration,
+    /// The steps performed by this one, in order.
+    pub children: Vec<RenderProfile>,
+}
+
+impl RenderProfile {
+    /// Creates a node without duration or children.
+    #[must_use]
+    pub fn new(kind: ProfileKind, name: &str) -> Self {
+        Self {
+            kind,
+            name: name.to_string(),
+            duration: Duration::ZERO,
+            children: Vec::new(),
+        }
+    }
+
+    /// Returns the time spent in the step itself, excluding its
+    /// children.
+    #[must_use]
+    pub fn self_time(&self) -> Duration {
+        let children: Duration =
+            self.children.iter().map(|child| child.duration).sum();
+        self.duration.saturating_sub(children)
+    }
+
+    /// Returns the total time spent in steps of `kind` named `name`
+    /// across the tree, counting nested steps of the same name once.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The kind of step.
+    /// * `name` - The name of the step.
+    #[must_use]
+    pub fn total(&self, kind: ProfileKind, name: &str) -> Duration {
+        if self.kind == kind && self.name == name {
+            return self.duration;
+        }
+        self.children
+            .iter()
+            .map(|child| child.total(kind, name))
+            .sum()
+    }
+
+    /// Returns the nodes of the tree, depth first, with their depth.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Self)> {
+        let mut stack = vec![(0, self)];
+        std::iter::from_fn(move || {
+            let (depth, node) = stack.pop()?;
+            stack.extend(
+                node.children
+                    .iter()
+                    .rev()
+                    .map(|child| (depth + 1, child)),
+            );
+            Some((depth, node))
+        })
+    }
+}
+
+impl fmt::Display for RenderProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (depth, node)) in self.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(
+                f,
+                "{:indent$}{} {} {:.2}ms",
+                "",
+                node.kind,
+                node.name,
+                node.duration.as_secs_f64() * 1000.0,
+                indent = depth * 2
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Records the profile of a render in progress.
+#[derive(Debug)]
+pub(crate) struct Profiler {
+    /// The open steps, the root first.
+    stack: Mutex<Vec<RenderProfile>>,
+}
+
+impl Profiler {
+    /// Creates a profiler whose root step is `root`.
+    pub(crate) fn new(root: RenderProfile) -> Self {
+        Self {
+            stack: Mutex::new(vec![root]),
+        }
+    }
+
+    /// Runs `step` as a child of the innermost open step.
+    pub(crate) fn record<T>(
+        &self,
+        kind: ProfileKind,
+        name: &str,
+        step: impl FnOnce() -> T,
+    ) -> T {
+        self.stack().push(RenderProfile::new(kind, name));
+        let start = Instant::now();
+        let result = step();
+        let duration = start.elapsed();
+
+        let mut stack = self.stack();
+        if stack.len() > 1 {
+            if let Some(mut node) = stack.pop() {
+                node.duration = duration;
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(node);
+                }
+            }
+        }
+        result
+    }
+
+    /// Closes the profile, returning its root step with `duration`.
+    pub(crate) fn finish(self, duration: Duration) -> RenderProfile {
+        let mut stack = match self.stack.into_inner() {
+            Ok(stack) => stack,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        stack.truncate(1);
+        let mut root = stack.pop().unwrap_or_else(|| {
+            RenderProfile::new(ProfileKind::Page, "")
+        });
+        root.duration = duration;
+        root
+    }
+
+    fn stack(&self) -> std::sync::MutexGuard<'_, Vec<RenderProfile>> {
+        match self.stack.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the profile of a page with a tag calling a filter,
+    /// followed by another tag, rendered in one second.
+    fn profile() -> RenderProfile {
+        let profiler = Profiler::new(RenderProfile::new(
+            ProfileKind::Page,
+            "index",
+        ));
+        profiler.record(ProfileKind::Tag, "a | upper", || {
+            profiler.record(ProfileKind::Filter, "upper", || ())
+        });
+        profiler.record(ProfileKind::Tag, "b", || ());
+        profiler.finish(Duration::from_secs(1))
+    }
+
+    #[test]
+    fn test_record_returns_value() {
+        let profiler = Profiler::new(RenderProfile::new(
+            ProfileKind::Page,
+            "index",
+        ));
+        assert_eq!(profiler.record(ProfileKind::Tag, "a", || 1), 1);
+    }
+
+    #[test]
+    fn test_records_nest() {
+        let profile = profile();
+        let nodes: Vec<_> = profile
+            .iter()
+            .map(|(depth, node)| (depth, node.kind, node.name.as_str()))
+            .collect();
+        assert_eq!(
+            nodes,
+            [
+                (0, ProfileKind::Page, "index"),
+                (1, ProfileKind::Tag, "a | upper"),
+                (2, ProfileKind::Filter, "upper"),
+                (1, ProfileKind::Tag, "b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_finish_sets_duration() {
+        assert_eq!(profile().duration, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_self_time_excludes_children() {
+        let profile = profile();
+        assert!(profile.self_time() <= profile.duration);
+    }
+
+    #[test]
+    fn test_total_of_kind_and_name() {
+        let profile = profile();
+        assert_eq!(
+            profile.total(ProfileKind::Filter, "upper"),
+            profile.children[0].children[0].duration
+        );
+    }
+
+    #[test]
+    fn test_display_indents_children() {
+        assert!(profile()
+            .to_string()
+            .starts_with("page index 1000.00ms\n  tag a | upper "));
+    }
+}
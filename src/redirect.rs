@@ -0,0 +1,325 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Redirect Module
+//!
+//! This module provides the `Redirects` struct, which collects the old
+//! URLs that pages declare under their `aliases` key, so that links to
+//! them keep working after a page moves. Aliases are listed like
+//! taxonomy terms, as a JSON array or a comma-separated string:
+//!
+//! ```text
+//! {"title": "Post", "aliases": ["/2019/post.html", "/old/post/"]}
+//! ```
+//!
+//! Each redirect is written either as a stub page that sends browsers to
+//! the new URL with a meta refresh, or as a line of a server redirect
+//! map. Stubs are rendered from a template with the keys:
+//!
+//! ```text
+//! {{url}}     the URL to redirect to
+//! {{from}}    the alias being redirected
+//! ```
+
+use crate::context::Context;
+use crate::engine::{Engine, EngineError};
+use crate::escape::OutputFormat;
+use crate::taxonomy::terms;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
+
+/// The context key listing the aliases of a page.
+pub const ALIASES_KEY: &str = "aliases";
+
+/// The template of redirect stubs rendered by [`Redirects::render_stub`]
+/// without a layout.
+pub const REDIRECT_TEMPLATE: &str = "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>Redirecting to {{url}}</title>
+<link rel=\"canonical\" href=\"{{url}}\">
+<meta name=\"robots\" content=\"noindex\">
+<meta http-equiv=\"refresh\" content=\"0; url={{url}}\">
+</head>
+<body><a href=\"{{url}}\">{{url}}</a></body>
+</html>
+";
+
+/// The format of a server redirect map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapFormat {
+    /// A Netlify or Cloudflare Pages `_redirects` file.
+    Netlify,
+    /// Nginx `location` blocks, to be included in a `server` block.
+    Nginx,
+}
+
+impl MapFormat {
+    /// Returns the format named `name`, `netlify` or `nginx`, ignoring
+    /// case.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the format.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "netlify" => Some(Self::Netlify),
+            "nginx" => Some(Self::Nginx),
+            _ => None,
+        }
+    }
+
+    /// Returns the conventional file name of a map in this format.
+    #[must_use]
+    pub const fn file_name(self) -> &'static str {
+        match self {
+            Self::Netlify => "_redirects",
+            Self::Nginx => "redirects.conf",
+        }
+    }
+}
+
+/// Redirects from the aliases of pages to their URLs.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::redirect::{MapFormat, Redirects};
+/// use staticweaver::Context;
+///
+/// let mut redirects = Redirects::new();
+/// let mut context = Context::new();
+/// context.set("aliases", r#"["/old.html", "/older/"]"#);
+/// assert_eq!(redirects.add("/new.html", &context), 2);
+///
+/// assert_eq!(
+///     redirects.to_map(MapFormat::Netlify),
+///     "/old.html /new.html 301\n/older/ /new.html 301\n"
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Redirects {
+    redirects: BTreeMap<String, String>,
+}
+
+impl Redirects {
+    /// Creates an empty set of redirects.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a redirect to `url` from each alias of the page with
+    /// `context`. Aliases without a leading `/` are made root-relative;
+    /// an alias of `url` itself is ignored, and a later page declaring
+    /// the same alias replaces the earlier one.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the page.
+    /// * `context` - The context of the page.
+    ///
+    /// # Returns
+    ///
+    /// The number of redirects added.
+    pub fn add(&mut self, url: &str, context: &Context) -> usize {
+        let Some(aliases) = context.get(ALIASES_KEY) else {
+            return 0;
+        };
+        let mut added = 0;
+        for alias in terms(aliases) {
+            let from = if alias.starts_with('/') {
+                alias
+            } else {
+                format!("/{}", alias)
+            };
+            if from != url {
+                let _ = self.redirects.insert(from, url.to_string());
+                added += 1;
+            }
+        }
+        added
+    }
+
+    /// Returns the redirects as `(from, to)` pairs, sorted by alias.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.redirects
+            .iter()
+            .map(|(from, to)| (from.as_str(), to.as_str()))
+    }
+
+    /// Returns the number of redirects.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.redirects.len()
+    }
+
+    /// Returns whether there are no redirects.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.redirects.is_empty()
+    }
+
+    /// Returns the redirects as a server redirect map in `format`.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format of the map.
+    #[must_use]
+    pub fn to_map(&self, format: MapFormat) -> String {
+        let mut map = String::new();
+        for (from, to) in self.iter() {
+            let _ = match format {
+                MapFormat::Netlify => {
+                    writeln!(map, "{} {} 301", from, to)
+                }
+                MapFormat::Nginx => writeln!(
+                    map,
+                    "location = {} {{ return 301 {}; }}",
+                    from, to
+                ),
+            };
+        }
+        map
+    }
+
+    /// Returns the context of the stub redirecting `from` to `to`.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The alias being redirected.
+    /// * `to` - The URL to redirect to.
+    #[must_use]
+    pub fn stub_context(from: &str, to: &str) -> Context {
+        let mut context = Context::new();
+        context.set("url", to);
+        context.set("from", from);
+        context
+    }
+
+    /// Renders the [`REDIRECT_TEMPLATE`] stub redirecting `from` to `to`
+    /// with the delimiters of `engine`.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - The engine to render with.
+    /// * `from` - The alias being redirected.
+    /// * `to` - The URL to redirect to.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Engine::render_template`].
+    pub fn render_stub(
+        engine: &Engine,
+        from: &str,
+        to: &str,
+    ) -> Result<String, EngineError> {
+        let template = REDIRECT_TEMPLATE
+            .replace("{{", &engine.open_delim)
+            .replace("}}", &engine.close_delim);
+        engine.render_template_with_format(
+            &template,
+            &Self::stub_context(from, to),
+            OutputFormat::Html,
+        )
+    }
+}
+
+/// Returns the path of the stub page serving the alias `from`, relative
+/// to the output directory: `from` itself if it has an extension, or the
+/// `index.html` of the directory it names otherwise.
+///
+/// Returns `None` if `from` leaves the output directory.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::redirect::stub_path;
+/// use std::path::Path;
+///
+/// assert_eq!(stub_path("/old.html").unwrap(), Path::new("old.html"));
+/// assert_eq!(stub_path("/old/").unwrap(), Path::new("old/index.html"));
+/// assert_eq!(stub_path("/../etc/passwd"), None);
+/// ```
+#[must_use]
+pub fn stub_path(from: &str) -> Option<PathBuf> {
+    let path = Path::new(from.trim_start_matches('/'));
+    let mut stub = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => stub.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    if stub.as_os_str().is_empty()
+        || from.ends_with('/')
+        || stub.extension().is_none()
+    {
+        stub.push("index.html");
+    }
+    Some(stub)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_add() {
+        let mut redirects = Redirects::new();
+        let mut context = Context::new();
+        assert_eq!(redirects.add("/a.html", &context), 0);
+
+        context.set(ALIASES_KEY, "old/a.html, /a.html, /b/");
+        assert_eq!(redirects.add("/a.html", &context), 2);
+        context.set(ALIASES_KEY, "/b/");
+        assert_eq!(redirects.add("/c.html", &context), 1);
+
+        let pairs: Vec<_> = redirects.iter().collect();
+        assert_eq!(
+            pairs,
+            [("/b/", "/c.html"), ("/old/a.html", "/a.html")]
+        );
+        assert_eq!(
+            redirects.to_map(MapFormat::Nginx),
+            "location = /b/ { return 301 /c.html; }\n\
+             location = /old/a.html { return 301 /a.html; }\n"
+        );
+        assert_eq!(
+            MapFormat::from_name("NGINX"),
+            Some(MapFormat::Nginx)
+        );
+        assert_eq!(MapFormat::from_name("apache"), None);
+    }
+
+    #[test]
+    fn test_stub() {
+        assert_eq!(stub_path("/").unwrap(), Path::new("index.html"));
+        assert_eq!(
+            stub_path("old").unwrap(),
+            Path::new("old/index.html")
+        );
+        assert_eq!(
+            stub_path("/a/./b.htm").unwrap(),
+            Path::new("a/b.htm")
+        );
+
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        let stub =
+            Redirects::render_stub(&engine, "/old", "/a?x=1&y=2")
+                .unwrap();
+        assert!(stub.contains(
+            "<meta http-equiv=\"refresh\" content=\"0; url=/a?x=1&amp;y=2\">"
+        ));
+
+        engine.set_delimiters("<%", "%>").unwrap();
+        let stub =
+            Redirects::render_stub(&engine, "/old", "/a").unwrap();
+        assert!(stub.contains("<link rel=\"canonical\" href=\"/a\">"));
+    }
+}
@@ -0,0 +1,242 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Search Module
+//!
+//! This module provides the `SearchIndex` struct, which collects the
+//! text of rendered pages into a JSON search index for client-side
+//! search. The index is an array of documents that can be loaded into
+//! [Lunr](https://lunrjs.com) with `ref("id")`, or searched directly:
+//!
+//! ```text
+//! [
+//!   {"id": "/a.html", "url": "/a.html", "title": "A", "excerpt": "...", "body": "tokens ..."}
+//! ]
+//! ```
+//!
+//! Values are escaped with the engine's JSON output format.
+
+use crate::context::Context;
+use crate::escape::OutputFormat;
+use std::fmt::Write;
+
+/// The context key that excludes a page from the search index when it
+/// is a false boolean, as read by [`Context::get_bool`].
+pub const SEARCH_KEY: &str = "search";
+
+/// The maximum length of a generated excerpt, in characters.
+pub const EXCERPT_LEN: usize = 160;
+
+/// A page in a search index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchEntry {
+    /// The URL of the page, also used as its id.
+    pub url: String,
+    /// The title of the page.
+    pub title: String,
+    /// A short summary of the page.
+    pub excerpt: String,
+    /// The lowercase words of the page text, separated by spaces.
+    pub body: String,
+}
+
+/// The pages of a site, as indexed for search.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::search::SearchIndex;
+/// use staticweaver::Context;
+///
+/// let mut index = SearchIndex::new();
+/// let mut context = Context::new();
+/// context.set("title", "Hello");
+/// index.add("/hello.html", &context, "<h1>Hello, World!</h1>");
+///
+/// assert_eq!(
+///     index.to_json(),
+///     r#"[{"id":"/hello.html","url":"/hello.html","title":"Hello","excerpt":"Hello, World!","body":"hello world"}]"#
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchIndex {
+    /// The indexed pages, in the order they were added.
+    pub entries: Vec<SearchEntry>,
+}
+
+impl SearchIndex {
+    /// Creates an empty index.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Indexes the rendered page at `url`.
+    ///
+    /// The title is read from the `title` key of `context`, and the
+    /// excerpt from its `description` key; without one, the excerpt is
+    /// the start of the page text. Pages whose [`SEARCH_KEY`] value is
+    /// false are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the page.
+    /// * `context` - The context the page was rendered with.
+    /// * `html` - The rendered page.
+    ///
+    /// # Returns
+    ///
+    /// Whether the page was indexed.
+    pub fn add(
+        &mut self,
+        url: &str,
+        context: &Context,
+        html: &str,
+    ) -> bool {
+        if context.get(SEARCH_KEY).is_some()
+            && !context.get_bool(SEARCH_KEY).unwrap_or(true)
+        {
+            return false;
+        }
+        let text = text_content(html);
+        let excerpt = match context.get("description") {
+            Some(description) => description.trim().to_string(),
+            None => excerpt(&text, EXCERPT_LEN),
+        };
+        self.entries.push(SearchEntry {
+            url: url.to_string(),
+            title: context.get("title").cloned().unwrap_or_default(),
+            excerpt,
+            body: tokenize(&text).join(" "),
+        });
+        true
+    }
+
+    /// Returns the index as a JSON array of documents.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let json = OutputFormat::Json;
+        let mut out = String::from("[");
+        for (index, entry) in self.entries.iter().enumerate() {
+            if index > 0 {
+                out.push(',');
+            }
+            let url = json.escape(&entry.url);
+            let _ = write!(
+                out,
+                "{{\"id\":\"{}\",\"url\":\"{}\",\"title\":\"{}\",\
+                 \"excerpt\":\"{}\",\"body\":\"{}\"}}",
+                url,
+                url,
+                json.escape(&entry.title),
+                json.escape(&entry.excerpt),
+                json.escape(&entry.body)
+            );
+        }
+        out.push(']');
+        out
+    }
+}
+
+/// Returns the visible text of `html`: its markup, scripts, and styles
+/// removed, common entities decoded, and whitespace collapsed.
+fn text_content(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        text.push(' ');
+        let tag = &rest[start + 1..];
+        let end = tag.find('>').map_or(tag.len(), |end| end + 1);
+        let name = tag[..end]
+            .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        rest = &tag[end..];
+        if name == "script" || name == "style" {
+            let close = format!("</{}", name);
+            let lower = rest.to_ascii_lowercase();
+            rest = match lower.find(&close) {
+                Some(offset) => &rest[offset..],
+                None => "",
+            };
+        }
+    }
+    text.push_str(rest);
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Returns the lowercase alphanumeric words of `text`.
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Returns the start of `text`, cut at a word boundary to at most
+/// `max_len` characters and followed by `…` if it was cut.
+fn excerpt(text: &str, max_len: usize) -> String {
+    if text.chars().count() <= max_len {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max_len).collect();
+    let cut = match cut.rfind(' ') {
+        Some(space) => &cut[..space],
+        None => &cut,
+    };
+    format!("{}…", cut.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_content() {
+        assert_eq!(
+            text_content(
+                "<p>Fish&nbsp;&amp; <b>Chips</b></p>\
+                 <SCRIPT>var x = 1;</SCRIPT><style>p {}</style>\n end"
+            ),
+            "Fish & Chips end"
+        );
+        assert_eq!(
+            tokenize("Crème brûlée, 2024!"),
+            ["crème", "brûlée", "2024"]
+        );
+        assert_eq!(excerpt("one two three", 9), "one two…");
+        assert_eq!(excerpt("one", 9), "one");
+    }
+
+    #[test]
+    fn test_search_index() {
+        let mut index = SearchIndex::new();
+        let mut context = Context::new();
+        context.set("title", "Say \"hi\"");
+        context.set("description", "A greeting");
+        assert!(index.add("/hi.html", &context, "<p>Hi\tthere</p>"));
+
+        context.set(SEARCH_KEY, "false");
+        assert!(!index.add("/hidden.html", &context, "<p>Hidden</p>"));
+
+        assert_eq!(index.entries.len(), 1);
+        assert_eq!(index.entries[0].excerpt, "A greeting");
+        assert_eq!(
+            index.to_json(),
+            r#"[{"id":"/hi.html","url":"/hi.html","title":"Say \"hi\"","excerpt":"A greeting","body":"hi there"}]"#
+        );
+        let parsed: serde_json::Value =
+            serde_json::from_str(&index.to_json()).unwrap();
+        assert_eq!(parsed[0]["title"], "Say \"hi\"");
+        assert_eq!(SearchIndex::new().to_json(), "[]");
+    }
+}
@@ -0,0 +1,655 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Shared Module
+//!
+//! This module provides the `SharedEngine` struct, a thread-safe wrapper
+//! around an [`Engine`] for servers that render pages from many threads.
+//!
+//! Cache hits are served concurrently. When several threads request the
+//! same page while it is not cached, only one of them renders it; the
+//! others wait and reuse its result instead of rendering the same page
+//! again. An engine given [layers](crate::layer) serves each page
+//! through them instead, as [`Engine::render_page`] does; a
+//! [`Cache`](crate::layer::Cache) layer then serves cache hits, but
+//! concurrent misses of the same page are each rendered.
+//!
+//! It also provides the `EngineConfig` struct, an immutable snapshot of
+//! an engine taken with [`Engine::snapshot`]. A live-reload server can
+//! hand snapshots to its render workers and reconfigure its engine
+//! between them; each worker keeps rendering with the configuration it
+//! was given until it picks up a newer snapshot.
+
+use crate::context::Context;
+use crate::engine::{
+    CacheStatus, Engine, EngineError, PageKey, RenderOptions, Rendered,
+};
+use crate::escape::OutputFormat;
+use crate::layer::{Next, RenderRequest};
+use crate::parser::validate_delimiters;
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{
+    Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock,
+    RwLockReadGuard, RwLockWriteGuard,
+};
+use std::time::Instant;
+
+/// A page render in progress, awaited by the threads that requested the
+/// same page.
+#[derive(Debug, Default)]
+struct Flight {
+    /// `None` while rendering; then `Some` of the page, or of `None` if
+    /// the render failed.
+    outcome: Mutex<Option<Option<Arc<str>>>>,
+    done: Condvar,
+}
+
+impl Flight {
+    /// Blocks until the render finishes and returns the page, or `None`
+    /// if the render failed.
+    fn wait(&self) -> Option<Arc<str>> {
+        let outcome =
+            self.outcome.lock().unwrap_or_else(PoisonError::into_inner);
+        let outcome = self
+            .done
+            .wait_while(outcome, |outcome| outcome.is_none())
+            .unwrap_or_else(PoisonError::into_inner);
+        outcome.clone().flatten()
+    }
+}
+
+/// Publishes the outcome of a render to its waiting threads when
+/// dropped, even if the render failed or panicked.
+struct Leader<'a> {
+    engine: &'a SharedEngine,
+    key: PageKey,
+    flight: Arc<Flight>,
+    page: Option<Arc<str>>,
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        let _ = self.engine.flights().remove(&self.key);
+        *self
+            .flight
+            .outcome
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) =
+            Some(self.page.take());
+        self.flight.done.notify_all();
+    }
+}
+
+/// A page served by [`SharedEngine::serve`].
+struct Served {
+    output: Arc<str>,
+    cache: CacheStatus,
+    /// The page as served by the layers of the engine, if any.
+    layered: Option<Rendered>,
+    /// The render cache key of the page, unless the layers of the
+    /// engine served it or its hooks may have changed it.
+    key: Option<PageKey>,
+}
+
+/// A thread-safe [`Engine`] that renders each uncached page only once.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::shared::SharedEngine;
+/// use staticweaver::{Context, Engine};
+/// use std::sync::Arc;
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// let engine = Arc::new(SharedEngine::new(Engine::new(
+///     "templates",
+///     Duration::from_secs(3600),
+/// )));
+///
+/// let worker = Arc::clone(&engine);
+/// let handle = thread::spawn(move || {
+///     worker.render_page(&Context::new(), "index").is_ok()
+/// });
+/// let _ = handle.join();
+/// ```
+#[derive(Debug)]
+pub struct SharedEngine {
+    engine: RwLock<Engine>,
+    flights: Mutex<HashMap<PageKey, Arc<Flight>>>,
+}
+
+impl SharedEngine {
+    /// Creates a new `SharedEngine` wrapping `engine`.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - The engine to share, already configured.
+    #[must_use]
+    pub fn new(engine: Engine) -> Self {
+        Self {
+            engine: RwLock::new(engine),
+            flights: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Renders a page like [`Engine::render_page`].
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The rendering context.
+    /// * `layout` - The layout file to use for rendering.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Engine::render_page`].
+    pub fn render_page(
+        &self,
+        context: &Context,
+        layout: &str,
+    ) -> Result<String, EngineError> {
+        self.render_page_shared(context, layout)
+            .map(|page| page.to_string())
+    }
+
+    /// Renders a page like [`Engine::render_page_shared`], returning the
+    /// cached page itself rather than a copy.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The rendering context.
+    /// * `layout` - The layout file to use for rendering.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Engine::render_page`].
+    pub fn render_page_shared(
+        &self,
+        context: &Context,
+        layout: &str,
+    ) -> Result<Arc<str>, EngineError> {
+        self.render(context, layout, &RenderOptions::new())
+    }
+
+    /// Renders a page like [`Engine::render_page_as`], in an explicit
+    /// output format.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The rendering context.
+    /// * `layout` - The layout file to use for rendering.
+    /// * `format` - The output format used to escape values.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Engine::render_page`].
+    pub fn render_page_as(
+        &self,
+        context: &Context,
+        layout: &str,
+        format: OutputFormat,
+    ) -> Result<String, EngineError> {
+        let options = RenderOptions {
+            output_format: Some(format),
+            ..RenderOptions::default()
+        };
+        self.render(context, layout, &options)
+            .map(|page| page.to_string())
+    }
+
+    /// Renders a page like [`Engine::render_page_with`], with per-call
+    /// options.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The options overriding the engine settings.
+    /// * `context` - The rendering context.
+    /// * `layout` - The layout file to use for rendering.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Engine::render_page_with`].
+    pub fn render_page_with(
+        &self,
+        options: &RenderOptions,
+        context: &Context,
+        layout: &str,
+    ) -> Result<String, EngineError> {
+        if let Some((open, close)) = &options.delimiters {
+            validate_delimiters(open, close)?;
+        }
+        self.render(context, layout, options)
+            .map(|page| page.to_string())
+    }
+
+    /// Renders a page like [`Engine::render_page_detailed`], also
+    /// returning how it was served.
+    ///
+    /// A page another thread was rendering when it was requested is
+    /// reported as a [`CacheStatus::Hit`].
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The options overriding the engine settings.
+    /// * `context` - The rendering context.
+    /// * `layout` - The layout file to use for rendering.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Engine::render_page_with`].
+    pub fn render_page_detailed(
+        &self,
+        options: &RenderOptions,
+        context: &Context,
+        layout: &str,
+    ) -> Result<Rendered, EngineError> {
+        let start = Instant::now();
+        if let Some((open, close)) = &options.delimiters {
+            validate_delimiters(open, close)?;
+        }
+        let served = self.serve(context, layout, options)?;
+        let page = match served.layered {
+            Some(page) if Arc::ptr_eq(&page.output, &served.output) => {
+                page
+            }
+            // After hooks changed the page.
+            Some(page) => page.with_output(served.output),
+            None => self.read().rendered(
+                served.output,
+                served.cache,
+                start,
+                served.key,
+                layout,
+            ),
+        };
+        Ok(Rendered {
+            duration: start.elapsed(),
+            ..page
+        })
+    }
+
+    /// Locks the engine for reading, e.g. to inspect its settings.
+    ///
+    /// Renders also hold a read lock, so they run alongside each other.
+    pub fn read(&self) -> RwLockReadGuard<'_, Engine> {
+        self.engine.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the engine for writing, e.g. to change its settings or
+    /// clear its cache.
+    ///
+    /// Waits for renders in progress to finish.
+    pub fn write(&self) -> RwLockWriteGuard<'_, Engine> {
+        self.engine.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns an immutable snapshot of the engine, like
+    /// [`Engine::snapshot`].
+    #[must_use]
+    pub fn snapshot(&self) -> EngineConfig {
+        self.read().snapshot()
+    }
+
+    /// Consumes the wrapper and returns the engine.
+    #[must_use]
+    pub fn into_inner(self) -> Engine {
+        self.engine
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Serves a page from the cache, or renders it once across threads.
+    ///
+    /// A thread that finds no render in progress becomes the leader and
+    /// renders the page. Other threads wait for the leader; if it fails,
+    /// they start over so that each caller sees its own error.
+    fn render(
+        &self,
+        context: &Context,
+        layout: &str,
+        options: &RenderOptions,
+    ) -> Result<Arc<str>, EngineError> {
+        self.serve(context, layout, options)
+            .map(|served| served.output)
+    }
+
+    /// Implements [`SharedEngine::render`], also returning how the page
+    /// was served.
+    fn serve(
+        &self,
+        context: &Context,
+        layout: &str,
+        options: &RenderOptions,
+    ) -> Result<Served, EngineError> {
+        let hooks = self.read().hooks().clone();
+        let mut layered = None;
+        let mut key = None;
+        let (output, cache) =
+            hooks.run(context, layout, |context| {
+                let engine = self.read();
+                if let Some(layers) = engine.layers() {
+                    let page = Next::new(&engine, layers).run(
+                        RenderRequest::new(
+                            context,
+                            layout,
+                            options.clone(),
+                        ),
+                    )?;
+                    let result = (Arc::clone(&page.output), page.cache);
+                    layered = Some(page);
+                    return Ok(result);
+                }
+                let (context, page_key) =
+                    engine.page_key(context, layout, options);
+                key = Some(page_key);
+                self.render_unhooked(
+                    &engine, &context, layout, options, page_key,
+                )
+            })?;
+        Ok(Served {
+            output,
+            cache,
+            layered,
+            key: key.filter(|_| hooks.is_empty()),
+        })
+    }
+
+    /// Implements [`SharedEngine::serve`] with the read-locked
+    /// `engine`, without running its hooks, for the page of `context`
+    /// as returned by [`Engine::page_key`] with `key`.
+    ///
+    /// The lock is held from computing the cache key until the page is
+    /// cached, so that a writer cannot change the settings in between
+    /// and have a page of the new settings cached under a key of the
+    /// old ones.
+    fn render_unhooked(
+        &self,
+        engine: &Engine,
+        context: &Context,
+        layout: &str,
+        options: &RenderOptions,
+        key: PageKey,
+    ) -> Result<(Arc<str>, CacheStatus), EngineError> {
+        if options.bypass_cache {
+            return engine
+                .render_uncached(context, layout, options)
+                .map(|page| (page, CacheStatus::Bypass))
+                .map_err(|err| engine.remember_missing(layout, err));
+        }
+        loop {
+            if let Some(page) = engine.render_cache().get_shared(&key) {
+                return Ok((page, CacheStatus::Hit));
+            }
+
+            let mut flights = self.flights();
+            if let Some(flight) = flights.get(&key).map(Arc::clone) {
+                drop(flights);
+                if let Some(page) = flight.wait() {
+                    return Ok((page, CacheStatus::Hit));
+                }
+                continue;
+            }
+            let flight = Arc::new(Flight::default());
+            let _ = flights.insert(key, Arc::clone(&flight));
+            drop(flights);
+
+            let mut leader = Leader {
+                engine: self,
+                key,
+                flight,
+                page: None,
+            };
+            // A previous leader may have cached the page just before
+            // this thread took over.
+            if let Some(page) =
+                engine.render_cache().get_shared(&leader.key)
+            {
+                leader.page = Some(Arc::clone(&page));
+                return Ok((page, CacheStatus::Hit));
+            }
+            let (page, cache) = engine
+                .render_missed(context, layout, options, leader.key)?;
+            leader.page = Some(Arc::clone(&page));
+            return Ok((page, cache));
+        }
+    }
+
+    /// Locks the table of renders in progress.
+    fn flights(&self) -> MutexGuard<'_, HashMap<PageKey, Arc<Flight>>> {
+        self.flights.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl From<Engine> for SharedEngine {
+    fn from(engine: Engine) -> Self {
+        Self::new(engine)
+    }
+}
+
+/// An immutable snapshot of an [`Engine`], taken with
+/// [`Engine::snapshot`].
+///
+/// Snapshots dereference to the engine, so every method that borrows
+/// it, such as [`Engine::render_page`], is available. Clones share the
+/// snapshot and its render cache.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::{Context, Engine};
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// let engine = Engine::new("templates", Duration::from_secs(3600));
+/// let config = engine.snapshot();
+///
+/// let worker = config.clone();
+/// let handle = thread::spawn(move || {
+///     worker.render_page(&Context::new(), "index").is_ok()
+/// });
+/// let _ = handle.join();
+/// ```
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    engine: Arc<Engine>,
+}
+
+impl EngineConfig {
+    /// Creates a snapshot owning `engine`.
+    pub(crate) fn new(engine: Engine) -> Self {
+        Self {
+            engine: Arc::new(engine),
+        }
+    }
+
+    /// Returns a copy of the snapshot's engine, to be reconfigured
+    /// without affecting the snapshot.
+    #[must_use]
+    pub fn to_engine(&self) -> Engine {
+        Engine::clone(&self.engine)
+    }
+}
+
+impl Deref for EngineConfig {
+    type Target = Engine;
+
+    fn deref(&self) -> &Engine {
+        &self.engine
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+    use std::thread;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn create_engine(dir: &TempDir) -> Engine {
+        fs::write(dir.path().join("page.html"), "Hello, {{name}}!")
+            .unwrap();
+        Engine::new(
+            dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        )
+    }
+
+    #[test]
+    fn test_concurrent_renders_share_one_render() {
+        let dir = TempDir::new().unwrap();
+        let mut engine = create_engine(&dir);
+        let renders = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&renders);
+        engine.render_cache_mut().on_insert(move |_, _| {
+            let _ = counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let engine = Arc::new(SharedEngine::new(engine));
+        let barrier = Arc::new(Barrier::new(8));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let engine = Arc::clone(&engine);
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    let mut context = Context::new();
+                    context.set("name", "World");
+                    let _ = barrier.wait();
+                    engine.render_page(&context, "page").unwrap()
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), "Hello, World!");
+        }
+        assert_eq!(renders.load(Ordering::SeqCst), 1);
+        assert!(engine.flights().is_empty());
+    }
+
+    #[test]
+    fn test_bypass_cache_is_not_cached() {
+        let dir = TempDir::new().unwrap();
+        let engine = SharedEngine::new(create_engine(&dir));
+        let options = RenderOptions {
+            bypass_cache: true,
+            ..RenderOptions::default()
+        };
+        let mut context = Context::new();
+        context.set("name", "World");
+
+        assert_eq!(
+            engine
+                .render_page_with(&options, &context, "page")
+                .unwrap(),
+            "Hello, World!"
+        );
+        assert!(engine.read().render_cache().is_empty());
+    }
+
+    #[test]
+    fn test_render_page_detailed_reports_cache() {
+        let dir = TempDir::new().unwrap();
+        let engine = SharedEngine::new(create_engine(&dir));
+        let options = RenderOptions::new();
+        let mut context = Context::new();
+        context.set("name", "World");
+        let render = || {
+            engine
+                .render_page_detailed(&options, &context, "page")
+                .unwrap()
+        };
+
+        let page = render();
+        assert_eq!(&*page.output, "Hello, World!");
+        assert_eq!(page.cache, CacheStatus::Miss);
+        assert!(page.dependencies[0].ends_with("page.html"));
+        let hit = render();
+        assert_eq!(hit.cache, CacheStatus::Hit);
+        assert!(Arc::ptr_eq(&hit.output, &page.output));
+        assert_eq!(hit.content_hash, page.content_hash);
+    }
+
+    #[test]
+    fn test_write_between_renders_changes_page() {
+        let dir = TempDir::new().unwrap();
+        let engine = SharedEngine::new(create_engine(&dir));
+        fs::write(dir.path().join("page.html"), "{{name}} <%name%>")
+            .unwrap();
+        let mut context = Context::new();
+        context.set("name", "Ada");
+
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "Ada <%name%>"
+        );
+        engine.write().set_delimiters("<%", "%>").unwrap();
+        assert_eq!(
+            engine.render_page(&context, "page").unwrap(),
+            "{{name}} Ada"
+        );
+    }
+
+    #[test]
+    fn test_missing_layout_is_remembered() {
+        let dir = TempDir::new().unwrap();
+        let mut engine = create_engine(&dir);
+        engine.set_not_found_ttl(Some(Duration::from_secs(60)));
+        let engine = SharedEngine::new(engine);
+
+        assert!(engine.render_page(&Context::new(), "late").is_err());
+        fs::write(dir.path().join("late.html"), "late").unwrap();
+        assert!(engine.render_page(&Context::new(), "late").is_err());
+
+        engine.write().clear_cache();
+        assert_eq!(
+            engine.render_page(&Context::new(), "late").unwrap(),
+            "late"
+        );
+    }
+
+    #[test]
+    fn test_failed_render_reports_error() {
+        let dir = TempDir::new().unwrap();
+        let engine = SharedEngine::from(create_engine(&dir));
+
+        let result = engine.render_page(&Context::new(), "missing");
+        assert!(matches!(
+            result.unwrap_err().root(),
+            EngineError::Io(_)
+        ));
+        assert!(engine.flights().is_empty());
+        assert!(engine.into_inner().render_cache().is_empty());
+    }
+
+    #[test]
+    fn test_snapshot() {
+        use crate::loader::MemoryLoader;
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/page.html", "{{name}} <%name%>");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        let mut context = Context::new();
+        context.set("name", "Ada");
+
+        let config = engine.snapshot();
+        let worker = config.clone();
+        engine.set_delimiters("<%", "%>").unwrap();
+        engine.set_loader(Arc::new(MemoryLoader::new()));
+
+        assert!(engine.render_page(&context, "page").is_err());
+        let handle =
+            thread::spawn(move || worker.render_page(&context, "page"));
+        assert_eq!(handle.join().unwrap().unwrap(), "Ada <%name%>");
+        assert_eq!(config.render_cache().len(), 1);
+
+        let mut copy = config.to_engine();
+        copy.clear_cache();
+        assert_eq!(config.render_cache().len(), 1);
+        assert_eq!(copy.open_delim, "{{");
+    }
+}
@@ -0,0 +1,377 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Shortcode Module
+//!
+//! This module provides the `Shortcodes` registry, which expands
+//! shortcodes inside content bodies with small templates registered on
+//! the engine, so that authors can embed reusable snippets without
+//! writing raw HTML:
+//!
+//! ```text
+//! {{< youtube id="dQw4w9WgXcQ" >}}
+//! {{< note kind=warning >}}Back up your **data** first.{{< /note >}}
+//! ```
+//!
+//! A shortcode is a name followed by arguments, each `key="value"`,
+//! `key='value'`, `key=value`, or a bare value. Bare values are numbered
+//! from `0`. A shortcode followed by a matching `{{< /name >}}` is
+//! paired: the content in between is expanded first and passed as
+//! `inner`.
+//!
+//! The arguments are the context of the shortcode's template, which is
+//! rendered with [`Engine::render_template`]. Expansion only touches
+//! shortcodes, so it can run before or after markdown processing.
+
+use crate::context::Context;
+use crate::engine::{Engine, EngineError};
+use crate::profile::ProfileKind;
+use fnv::FnvHashMap;
+
+/// The opening delimiter of a shortcode.
+pub const SHORTCODE_OPEN: &str = "{{<";
+
+/// The closing delimiter of a shortcode.
+pub const SHORTCODE_CLOSE: &str = ">}}";
+
+/// The context key holding the content of a paired shortcode.
+pub const INNER_KEY: &str = "inner";
+
+/// Templates registered by shortcode name.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::engine::Engine;
+/// use std::time::Duration;
+///
+/// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+/// engine.shortcodes.register(
+///     "youtube",
+///     r#"<iframe src="https://www.youtube.com/embed/{{id}}"></iframe>"#,
+/// );
+///
+/// let html = engine
+///     .expand_shortcodes(r#"Watch: {{< youtube id="abc" >}}"#)
+///     .unwrap();
+/// assert_eq!(
+///     html,
+///     r#"Watch: <iframe src="https://www.youtube.com/embed/abc"></iframe>"#
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Shortcodes {
+    templates: FnvHashMap<String, String>,
+}
+
+impl Shortcodes {
+    /// Creates an empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the template of the shortcode `name`, replacing any
+    /// previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The shortcode name.
+    /// * `template` - The template rendered for each use.
+    ///
+    /// # Returns
+    ///
+    /// The previous template of the shortcode, if any.
+    pub fn register(
+        &mut self,
+        name: &str,
+        template: &str,
+    ) -> Option<String> {
+        self.templates
+            .insert(name.to_string(), template.to_string())
+    }
+
+    /// Removes the shortcode `name`, returning its template.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The shortcode name.
+    pub fn unregister(&mut self, name: &str) -> Option<String> {
+        self.templates.remove(name)
+    }
+
+    /// Returns the template of the shortcode `name`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The shortcode name.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.templates.get(name).map(String::as_str)
+    }
+
+    /// Returns the number of registered shortcodes.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    /// Returns whether no shortcode is registered.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+
+    /// Expands every shortcode in `content` with `engine`.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - The engine rendering the shortcode templates.
+    /// * `content` - The content to expand.
+    ///
+    /// # Errors
+    ///
+    /// * `EngineError::InvalidTemplate` - If a shortcode is not closed by
+    ///   `>}}`, has no name, or closes a shortcode that is not open.
+    /// * `EngineError::Render` - If a shortcode is not registered or its
+    ///   template cannot be rendered.
+    pub fn expand(
+        &self,
+        engine: &Engine,
+        content: &str,
+    ) -> Result<String, EngineError> {
+        let mut out = String::with_capacity(content.len());
+        let mut rest = content;
+        while let Some((before, tag, after)) = next_tag(rest)? {
+            out.push_str(before);
+            if tag.closing {
+                return Err(EngineError::InvalidTemplate(format!(
+                    "Unexpected closing shortcode: {}",
+                    tag.name
+                )));
+            }
+
+            let mut context = tag.args;
+            rest = after;
+            if let Some((inner, after)) = split_inner(tag.name, after)?
+            {
+                context.set(INNER_KEY, self.expand(engine, inner)?);
+                rest = after;
+            }
+
+            let template = self.get(tag.name).ok_or_else(|| {
+                EngineError::Render(format!(
+                    "Unknown shortcode: {}",
+                    tag.name
+                ))
+            })?;
+            out.push_str(&engine.profile(
+                ProfileKind::Shortcode,
+                tag.name,
+                || engine.render_template(template, &context),
+            )?);
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
+/// A parsed shortcode tag.
+#[derive(Debug)]
+struct Tag<'a> {
+    name: &'a str,
+    closing: bool,
+    args: Context,
+}
+
+/// Finds the next shortcode in `input`, returning the text before it,
+/// the tag, and the text after it.
+fn next_tag(
+    input: &str,
+) -> Result<Option<(&str, Tag<'_>, &str)>, EngineError> {
+    let Some(start) = input.find(SHORTCODE_OPEN) else {
+        return Ok(None);
+    };
+    let body_start = start + SHORTCODE_OPEN.len();
+    let len =
+        input[body_start..].find(SHORTCODE_CLOSE).ok_or_else(|| {
+            EngineError::InvalidTemplate(format!(
+                "Unclosed shortcode at byte {}",
+                start
+            ))
+        })?;
+    let body = input[body_start..body_start + len].trim();
+    let after = &input[body_start + len + SHORTCODE_CLOSE.len()..];
+
+    let (closing, body) = match body.strip_prefix('/') {
+        Some(body) => (true, body.trim_start()),
+        None => (false, body),
+    };
+    let name_len = body.find(char::is_whitespace).unwrap_or(body.len());
+    let name = &body[..name_len];
+    if name.is_empty() {
+        return Err(EngineError::InvalidTemplate(format!(
+            "Shortcode without a name at byte {}",
+            start
+        )));
+    }
+    let tag = Tag {
+        name,
+        closing,
+        args: parse_args(&body[name_len..]),
+    };
+    Ok(Some((&input[..start], tag, after)))
+}
+
+/// Splits `input` at the `{{< /name >}}` closing the shortcode `name`,
+/// skipping nested pairs of the same shortcode.
+///
+/// Returns the content before the closing tag and the text after it, or
+/// `None` if the shortcode is not paired.
+fn split_inner<'a>(
+    name: &str,
+    input: &'a str,
+) -> Result<Option<(&'a str, &'a str)>, EngineError> {
+    let mut depth = 0_usize;
+    let mut rest = input;
+    while let Some((before, tag, after)) = next_tag(rest)? {
+        if tag.name == name {
+            if !tag.closing {
+                depth += 1;
+            } else if depth == 0 {
+                let end = input.len() - rest.len() + before.len();
+                return Ok(Some((&input[..end], after)));
+            } else {
+                depth -= 1;
+            }
+        }
+        rest = after;
+    }
+    Ok(None)
+}
+
+/// Parses shortcode arguments into a context.
+///
+/// Named arguments are `key="value"`, `key='value'`, or `key=value`;
+/// bare values are stored under their position among the bare values.
+pub(crate) fn parse_args(input: &str) -> Context {
+    let mut args = Context::new();
+    let mut position = 0;
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        let token_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '"')
+            .unwrap_or(rest.len());
+        let (key, value, after) =
+            match rest[token_end..].strip_prefix('=') {
+                Some(value) if token_end > 0 => {
+                    let (value, after) = quoted(value);
+                    (Some(&rest[..token_end]), value, after)
+                }
+                _ => {
+                    let (value, after) = quoted(rest);
+                    (None, value, after)
+                }
+            };
+        match key {
+            Some(key) => args.set(key, value),
+            None => {
+                args.set(position.to_string(), value);
+                position += 1;
+            }
+        }
+        rest = after.trim_start();
+    }
+    args
+}
+
+/// Reads a value that is either quoted with `"` or `'`, or ends at the
+/// next whitespace, returning it and the text after it.
+fn quoted(input: &str) -> (&str, &str) {
+    for quote in ['"', '\''] {
+        if let Some(value) = input.strip_prefix(quote) {
+            return match value.find(quote) {
+                Some(end) => (&value[..end], &value[end + 1..]),
+                None => (value, ""),
+            };
+        }
+    }
+    let end = input.find(char::is_whitespace).unwrap_or(input.len());
+    (&input[..end], &input[end..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn engine() -> Engine {
+        let mut engine = Engine::new("", Duration::from_secs(60));
+        let _ = engine
+            .shortcodes
+            .register("figure", "<img src=\"{{src}}\" alt=\"{{0}}\">");
+        let _ = engine.shortcodes.register(
+            "note",
+            "<aside class=\"{{kind}}\">{{inner}}</aside>",
+        );
+        engine
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args =
+            parse_args(r#" src="a b.png" 'first' kind=warn second "#);
+        assert_eq!(args.get("src"), Some(&"a b.png".to_string()));
+        assert_eq!(args.get("kind"), Some(&"warn".to_string()));
+        assert_eq!(args.get("0"), Some(&"first".to_string()));
+        assert_eq!(args.get("1"), Some(&"second".to_string()));
+        assert_eq!(parse_args("").len(), 0);
+    }
+
+    #[test]
+    fn test_expand() {
+        let engine = engine();
+        assert_eq!(
+            engine.expand_shortcodes("No shortcodes {{here}}").unwrap(),
+            "No shortcodes {{here}}"
+        );
+        assert_eq!(
+            engine
+                .expand_shortcodes(
+                    r#"A {{<figure src="a.png" "A cat">}}!"#
+                )
+                .unwrap(),
+            r#"A <img src="a.png" alt="A cat">!"#
+        );
+        assert_eq!(
+            engine
+                .expand_shortcodes(
+                    "{{< note kind=tip >}}Use {{< note kind=x >}}\
+                     {{< figure src=b.png alt >}}{{< /note >}}.\
+                     {{< /note >}}"
+                )
+                .unwrap(),
+            "<aside class=\"tip\">Use <aside class=\"x\">\
+             <img src=\"b.png\" alt=\"alt\"></aside>.</aside>"
+        );
+    }
+
+    #[test]
+    fn test_expand_errors() {
+        let engine = engine();
+        for content in ["{{< figure", "{{< >}}", "{{< /note >}}"] {
+            assert!(matches!(
+                engine.expand_shortcodes(content),
+                Err(EngineError::InvalidTemplate(_))
+            ));
+        }
+        assert!(matches!(
+            engine.expand_shortcodes("{{< youtube >}}"),
+            Err(EngineError::Render(_))
+        ));
+        assert!(matches!(
+            engine.expand_shortcodes("{{< note >}}"),
+            Err(EngineError::Render(_))
+        ));
+    }
+}
@@ -0,0 +1,701 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Sink Module
+//!
+//! This module provides the `OutputSink` trait, through which a
+//! [`SiteBuilder`](crate::site::SiteBuilder) stores the pages it
+//! renders. Builders write to the file system with the [`FsSink`] by
+//! default; a [`MemorySink`] keeps pages in memory, for tests and for
+//! embedders that serve or post-process them without writing files:
+//!
+//! ```
+//! use staticweaver::loader::MemoryLoader;
+//! use staticweaver::shared::SharedEngine;
+//! use staticweaver::sink::MemorySink;
+//! use staticweaver::site::{SiteBuilder, SitePage};
+//! use staticweaver::{Context, Engine};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let loader = MemoryLoader::new();
+//! loader.insert("templates/page.html", "<h1>{{title}}</h1>");
+//! let mut engine = Engine::new("templates", Duration::from_secs(60));
+//! engine.set_loader(Arc::new(loader));
+//!
+//! let sink = MemorySink::new();
+//! let mut builder = SiteBuilder::new(Arc::new(SharedEngine::new(engine)), "public");
+//! builder.set_sink(Arc::new(sink.clone()));
+//!
+//! let mut context = Context::new();
+//! context.set("title", "Home");
+//! let report = builder.build(vec![SitePage::new("index.html", "page", context)]);
+//! assert!(report.is_success());
+//! assert_eq!(sink.get("index.html").unwrap(), b"<h1>Home</h1>");
+//! ```
+//!
+//! With the `s3` feature, an [`S3Sink`] uploads each page to a bucket of
+//! Amazon S3 or a compatible object store, such as MinIO or Cloudflare
+//! R2, as soon as it is rendered.
+//!
+//! Page paths are relative and use `/` as separator. Paths that are
+//! absolute or climb with `..` are rejected by [`validate_page_path`]
+//! before anything is stored.
+
+use crate::engine::EngineError;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// A destination of rendered pages.
+pub trait OutputSink: fmt::Debug + Send + Sync {
+    /// Stores `content` as the page at `path`, replacing any page
+    /// stored there.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `path` is not a valid page path, as checked
+    /// by [`validate_page_path`], or if the page cannot be stored.
+    fn write(
+        &self,
+        path: &str,
+        content: &[u8],
+    ) -> Result<(), EngineError>;
+
+    /// Returns the content of the page stored at `path`, or `None` if
+    /// there is none or it cannot be read.
+    ///
+    /// Builders read pages back to skip those an earlier build stored,
+    /// as its [journal](crate::journal) records. The default
+    /// implementation returns `None`, for sinks that cannot read back,
+    /// whose pages are then always built again.
+    fn read(&self, path: &str) -> Option<Vec<u8>> {
+        let _ = path;
+        None
+    }
+}
+
+/// Checks that `path` is a relative page path that stays inside the
+/// output, made of normal components only.
+///
+/// # Errors
+///
+/// Returns `EngineError::Io` of kind [`io::ErrorKind::InvalidInput`] if
+/// `path` is empty, absolute, or has `.` or `..` components.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::sink::validate_page_path;
+///
+/// assert!(validate_page_path("blog/index.html").is_ok());
+/// assert!(validate_page_path("../index.html").is_err());
+/// assert!(validate_page_path("/index.html").is_err());
+/// ```
+pub fn validate_page_path(path: &str) -> Result<(), EngineError> {
+    let mut components = Path::new(path).components().peekable();
+    if components.peek().is_some()
+        && components
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Ok(());
+    }
+    Err(EngineError::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Page path outside the output directory: {}", path),
+    )))
+}
+
+/// Writes pages to a directory of the file system, creating the
+/// directories of each page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsSink {
+    dir: PathBuf,
+}
+
+impl FsSink {
+    /// Creates a sink writing pages under `dir`.
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Returns the directory pages are written under.
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl OutputSink for FsSink {
+    fn write(
+        &self,
+        path: &str,
+        content: &[u8],
+    ) -> Result<(), EngineError> {
+        validate_page_path(path)?;
+        let dest = self.dir.join(path);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(dest, content)?;
+        Ok(())
+    }
+
+    fn read(&self, path: &str) -> Option<Vec<u8>> {
+        validate_page_path(path).ok()?;
+        fs::read(self.dir.join(path)).ok()
+    }
+}
+
+/// Keeps pages in memory.
+///
+/// Clones share their pages, so an embedder can keep one clone and
+/// read the pages a builder given the other wrote.
+#[derive(Debug, Default, Clone)]
+pub struct MemorySink {
+    pages: Arc<RwLock<BTreeMap<String, Vec<u8>>>>,
+}
+
+impl MemorySink {
+    /// Creates a sink without pages.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the content of the page at `path`.
+    #[must_use]
+    pub fn get(&self, path: &str) -> Option<Vec<u8>> {
+        self.pages
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(path)
+            .cloned()
+    }
+
+    /// Removes the page at `path`, returning its content, if any.
+    pub fn remove(&self, path: &str) -> Option<Vec<u8>> {
+        self.pages
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(path)
+    }
+
+    /// Returns the paths of the pages, sorted.
+    #[must_use]
+    pub fn paths(&self) -> Vec<String> {
+        self.pages
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the number of pages.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.pages
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Returns whether the sink holds no page.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl OutputSink for MemorySink {
+    fn write(
+        &self,
+        path: &str,
+        content: &[u8],
+    ) -> Result<(), EngineError> {
+        validate_page_path(path)?;
+        let _ = self
+            .pages
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(path.to_string(), content.to_vec());
+        Ok(())
+    }
+
+    fn read(&self, path: &str) -> Option<Vec<u8>> {
+        self.get(path)
+    }
+}
+
+#[cfg(feature = "s3")]
+pub use self::s3::S3Sink;
+
+#[cfg(feature = "s3")]
+mod s3 {
+    use super::{validate_page_path, OutputSink};
+    use crate::determinism::format_time;
+    use crate::engine::EngineError;
+    use crate::escape::OutputFormat;
+    use crate::integrity::sha256_hex;
+    use reqwest::blocking::{Client, RequestBuilder};
+    use reqwest::header::CONTENT_TYPE;
+    use reqwest::{Method, Url};
+    use sha2::{Digest, Sha256};
+    use std::fmt;
+    use std::io;
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+
+    /// Uploads pages to a bucket of an S3-compatible object store,
+    /// signing each request with AWS Signature Version 4.
+    ///
+    /// Objects are addressed by path, as
+    /// `{endpoint}/{bucket}/{prefix}{page path}`, which Amazon S3,
+    /// MinIO, and Cloudflare R2 all accept. Each object is given the
+    /// `Content-Type` of its extension.
+    ///
+    /// This type requires the `s3` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use staticweaver::sink::S3Sink;
+    ///
+    /// let mut sink = S3Sink::new(
+    ///     "https://s3.eu-west-1.amazonaws.com",
+    ///     "eu-west-1",
+    ///     "example-site",
+    ///     std::env::var("AWS_ACCESS_KEY_ID").unwrap(),
+    ///     std::env::var("AWS_SECRET_ACCESS_KEY").unwrap(),
+    /// );
+    /// sink.set_prefix("preview/");
+    /// ```
+    #[derive(Clone)]
+    pub struct S3Sink {
+        endpoint: String,
+        region: String,
+        bucket: String,
+        access_key_id: String,
+        secret_access_key: String,
+        session_token: Option<String>,
+        prefix: String,
+        timeout: Duration,
+        client: Client,
+    }
+
+    impl S3Sink {
+        /// Creates a sink uploading to `bucket`.
+        ///
+        /// # Arguments
+        ///
+        /// * `endpoint` - The base URL of the object store, such as
+        ///   `https://s3.eu-west-1.amazonaws.com`.
+        /// * `region` - The region requests are signed for, such as
+        ///   `eu-west-1`, or `auto` for Cloudflare R2.
+        /// * `bucket` - The name of the bucket.
+        /// * `access_key_id` - The access key requests are signed with.
+        /// * `secret_access_key` - The secret of the access key.
+        #[must_use]
+        pub fn new(
+            endpoint: impl Into<String>,
+            region: impl Into<String>,
+            bucket: impl Into<String>,
+            access_key_id: impl Into<String>,
+            secret_access_key: impl Into<String>,
+        ) -> Self {
+            Self {
+                endpoint: endpoint
+                    .into()
+                    .trim_end_matches('/')
+                    .to_string(),
+                region: region.into(),
+                bucket: bucket.into(),
+                access_key_id: access_key_id.into(),
+                secret_access_key: secret_access_key.into(),
+                session_token: None,
+                prefix: String::new(),
+                timeout: Duration::from_secs(30),
+                client: Client::new(),
+            }
+        }
+
+        /// Sets the prefix of object keys, such as `preview/`, so that
+        /// the page `index.html` is stored as `preview/index.html`.
+        pub fn set_prefix(&mut self, prefix: impl Into<String>) {
+            self.prefix = prefix.into();
+        }
+
+        /// Sets the session token of temporary credentials, sent as the
+        /// `x-amz-security-token` header.
+        pub fn set_session_token(&mut self, token: Option<String>) {
+            self.session_token = token;
+        }
+
+        /// Sets how long a request may take.
+        pub fn set_timeout(&mut self, timeout: Duration) {
+            self.timeout = timeout;
+        }
+
+        /// Returns the signed request for the object of the page at
+        /// `path`, whose body has the SHA-256 checksum `payload_hash`.
+        fn request(
+            &self,
+            method: Method,
+            path: &str,
+            payload_hash: &str,
+        ) -> Result<RequestBuilder, EngineError> {
+            let uri = format!(
+                "/{}/{}",
+                encode(&self.bucket),
+                encode(&format!("{}{}", self.prefix, path))
+            );
+            let url = Url::parse(&format!("{}{}", self.endpoint, uri))
+                .map_err(|err| {
+                    EngineError::Io(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Invalid S3 endpoint: {}", err),
+                    ))
+                })?;
+            let host = match (url.host_str(), url.port()) {
+                (Some(host), Some(port)) => {
+                    format!("{}:{}", host, port)
+                }
+                (Some(host), None) => host.to_string(),
+                (None, _) => String::new(),
+            };
+            let mut request = self
+                .client
+                .request(method.clone(), url)
+                .timeout(self.timeout);
+            for (name, value) in self.sign(
+                method.as_str(),
+                &uri,
+                &host,
+                payload_hash,
+                SystemTime::now(),
+            ) {
+                request = request.header(name, value);
+            }
+            Ok(request)
+        }
+
+        /// Returns the headers that sign a request for `uri` on `host`
+        /// made at `time`, the `Authorization` header last.
+        fn sign(
+            &self,
+            method: &str,
+            uri: &str,
+            host: &str,
+            payload_hash: &str,
+            time: SystemTime,
+        ) -> Vec<(&'static str, String)> {
+            let stamp: String = format_time(time, "rfc3339")
+                .unwrap_or_default()
+                .chars()
+                .filter(|c| *c != '-' && *c != ':')
+                .collect();
+            let date = &stamp[..stamp.len().min(8)];
+            let mut headers = vec![
+                ("host", host.to_string()),
+                ("x-amz-content-sha256", payload_hash.to_string()),
+                ("x-amz-date", stamp.clone()),
+            ];
+            if let Some(token) = &self.session_token {
+                headers.push(("x-amz-security-token", token.clone()));
+            }
+            let signed: Vec<&str> =
+                headers.iter().map(|(name, _)| *name).collect();
+            let signed = signed.join(";");
+            let canonical: String = headers
+                .iter()
+                .map(|(name, value)| format!("{}:{}\n", name, value))
+                .collect();
+            let request = format!(
+                "{}\n{}\n\n{}\n{}\n{}",
+                method, uri, canonical, signed, payload_hash
+            );
+            let scope =
+                format!("{}/{}/s3/aws4_request", date, self.region);
+            let to_sign = format!(
+                "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+                stamp,
+                scope,
+                sha256_hex(request.as_bytes())
+            );
+            let key = [self.region.as_str(), "s3", "aws4_request"]
+                .iter()
+                .fold(
+                    hmac(
+                        format!("AWS4{}", self.secret_access_key)
+                            .as_bytes(),
+                        date.as_bytes(),
+                    ),
+                    |key, part| hmac(&key, part.as_bytes()),
+                );
+            let signature: String = hmac(&key, to_sign.as_bytes())
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            let _ = headers.remove(0);
+            headers.push((
+                "authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, \
+                     SignedHeaders={}, Signature={}",
+                    self.access_key_id, scope, signed, signature
+                ),
+            ));
+            headers
+        }
+    }
+
+    impl fmt::Debug for S3Sink {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("S3Sink")
+                .field("endpoint", &self.endpoint)
+                .field("region", &self.region)
+                .field("bucket", &self.bucket)
+                .field("access_key_id", &self.access_key_id)
+                .field("prefix", &self.prefix)
+                .field("timeout", &self.timeout)
+                .finish_non_exhaustive()
+        }
+    }
+
+    impl OutputSink for S3Sink {
+        fn write(
+            &self,
+            path: &str,
+            content: &[u8],
+        ) -> Result<(), EngineError> {
+            validate_page_path(path)?;
+            let response = self
+                .request(Method::PUT, path, &sha256_hex(content))?
+                .header(CONTENT_TYPE, content_type(path))
+                .body(content.to_vec())
+                .send()?;
+            if !response.status().is_success() {
+                return Err(EngineError::Io(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!(
+                        "Failed to upload {}: HTTP {}",
+                        path,
+                        response.status()
+                    ),
+                )));
+            }
+            Ok(())
+        }
+
+        fn read(&self, path: &str) -> Option<Vec<u8>> {
+            validate_page_path(path).ok()?;
+            let response = self
+                .request(Method::GET, path, &sha256_hex(b""))
+                .ok()?
+                .send()
+                .ok()?;
+            if !response.status().is_success() {
+                return None;
+            }
+            Some(response.bytes().ok()?.to_vec())
+        }
+    }
+
+    /// Returns the `Content-Type` of the page at `path`.
+    fn content_type(path: &str) -> &'static str {
+        let path = Path::new(path);
+        match path.extension().and_then(|extension| extension.to_str())
+        {
+            Some("css") => "text/css; charset=utf-8",
+            Some("js" | "mjs") => "text/javascript; charset=utf-8",
+            _ => OutputFormat::from_path(path).content_type(),
+        }
+    }
+
+    /// Percent-encodes `key` for the path of a request, keeping `/`.
+    fn encode(key: &str) -> String {
+        key.bytes()
+            .map(|byte| match byte {
+                b'A'..=b'Z'
+                | b'a'..=b'z'
+                | b'0'..=b'9'
+                | b'-'
+                | b'_'
+                | b'.'
+                | b'~'
+                | b'/' => char::from(byte).to_string(),
+                _ => format!("%{:02X}", byte),
+            })
+            .collect()
+    }
+
+    /// Returns the HMAC-SHA256 of `message` with `key`.
+    fn hmac(key: &[u8], message: &[u8]) -> Vec<u8> {
+        const BLOCK: usize = 64;
+        let mut block = [0_u8; BLOCK];
+        if key.len() > BLOCK {
+            block[..32].copy_from_slice(&Sha256::digest(key));
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+        let pad = |byte: u8| -> Vec<u8> {
+            block.iter().map(|key| key ^ byte).collect()
+        };
+        let inner = Sha256::new()
+            .chain_update(pad(0x36))
+            .chain_update(message)
+            .finalize();
+        Sha256::new()
+            .chain_update(pad(0x5c))
+            .chain_update(inner)
+            .finalize()
+            .to_vec()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+        use std::thread;
+        use std::time::UNIX_EPOCH;
+
+        #[test]
+        fn test_hmac() {
+            // RFC 4231, test case 2.
+            let mac: String =
+                hmac(b"Jefe", b"what do ya want for nothing?")
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect();
+            assert_eq!(
+                mac,
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+            );
+            assert_eq!(hmac(&[7; 100], b"").len(), 32);
+        }
+
+        #[test]
+        fn test_sign() {
+            let mut sink = S3Sink::new(
+                "https://s3.example.com/",
+                "eu-west-1",
+                "site",
+                "AKID",
+                "secret",
+            );
+            sink.set_session_token(Some("token".to_string()));
+            let time = UNIX_EPOCH + Duration::from_secs(1_709_296_200);
+            let headers =
+                sink.sign("PUT", "/site/a%20b.html", "h", "00", time);
+            let names: Vec<&str> =
+                headers.iter().map(|(name, _)| *name).collect();
+            assert_eq!(
+                names,
+                [
+                    "x-amz-content-sha256",
+                    "x-amz-date",
+                    "x-amz-security-token",
+                    "authorization"
+                ]
+            );
+            assert_eq!(headers[1].1, "20240301T123000Z");
+            let authorization = &headers[3].1;
+            assert!(authorization.starts_with(
+                "AWS4-HMAC-SHA256 Credential=AKID/20240301/eu-west-1/s3/aws4_request, \
+                 SignedHeaders=host;x-amz-content-sha256;x-amz-date;x-amz-security-token, \
+                 Signature="
+            ));
+            assert_eq!(
+                sink.sign("PUT", "/site/a%20b.html", "h", "00", time),
+                headers
+            );
+            assert!(!format!("{:?}", sink).contains("secret"));
+            assert_eq!(encode("a b/ü.html"), "a%20b/%C3%BC.html");
+        }
+
+        #[test]
+        fn test_s3_upload() {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let endpoint =
+                format!("http://{}", listener.local_addr().unwrap());
+            let server = thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"<h1>Hi</h1>") {
+                    let read = stream.read(&mut buffer).unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                stream
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n",
+                    )
+                    .unwrap();
+                String::from_utf8(request).unwrap()
+            });
+
+            let mut sink =
+                S3Sink::new(endpoint, "auto", "site", "AKID", "secret");
+            sink.set_prefix("preview/");
+            sink.write("blog/index.html", b"<h1>Hi</h1>").unwrap();
+            let request = server.join().unwrap().to_lowercase();
+            assert!(request.starts_with(
+                "put /site/preview/blog/index.html http/1.1"
+            ));
+            assert!(request.contains("content-type: text/html"));
+            assert!(request.contains(&sha256_hex(b"<h1>Hi</h1>")));
+            assert!(request.contains("authorization: aws4-hmac-sha256"));
+            assert!(sink.write("../index.html", b"").is_err());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fs_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = FsSink::new(dir.path());
+        sink.write("blog/index.html", b"<h1>Blog</h1>").unwrap();
+        assert_eq!(
+            fs::read(dir.path().join("blog/index.html")).unwrap(),
+            b"<h1>Blog</h1>"
+        );
+        assert_eq!(
+            sink.read("blog/index.html").unwrap(),
+            b"<h1>Blog</h1>"
+        );
+        assert!(sink.read("missing.html").is_none());
+        assert_eq!(
+            sink.write("../escape.html", b"").unwrap_err().code(),
+            "io"
+        );
+        assert!(!dir.path().join("../escape.html").exists());
+    }
+
+    #[test]
+    fn test_memory_sink() {
+        let sink = MemorySink::new();
+        let clone = sink.clone();
+        clone.write("b.html", b"b").unwrap();
+        clone.write("a.html", b"a").unwrap();
+        clone.write("a.html", b"A").unwrap();
+        assert_eq!(sink.paths(), ["a.html", "b.html"]);
+        assert_eq!(sink.read("a.html").unwrap(), b"A");
+        assert_eq!(sink.remove("b.html").unwrap(), b"b");
+        assert_eq!(sink.len(), 1);
+        assert!(sink.write("", b"").is_err());
+        assert!(sink.write("./a.html", b"").is_err());
+    }
+}
@@ -0,0 +1,729 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Site Module
+//!
+//! This module provides the `SiteBuilder` struct, which renders the
+//! pages of a site on worker threads and writes them to an output
+//! directory, reporting its progress as it goes. Pages can be stored
+//! elsewhere, such as in memory or object storage, through an
+//! [`OutputSink`](crate::sink::OutputSink).
+//!
+//! Each page goes through a series of [`BuildEvent`]s, passed to the
+//! progress callback on the thread that called
+//! [`SiteBuilder::build_with_progress`], in the order they happened:
+//!
+//! | Event | When |
+//! |---|---|
+//! | [`Started`](BuildEventKind::Started) | A worker takes the page |
+//! | [`Rendered`](BuildEventKind::Rendered) | The page was rendered |
+//! | [`Cached`](BuildEventKind::Cached) | The page was served from a cache, without rendering |
+//! | [`Written`](BuildEventKind::Written) | The page was written |
+//! | [`Skipped`](BuildEventKind::Skipped) | An earlier build wrote the page, as its [journal](crate::journal) records |
+//! | [`Failed`](BuildEventKind::Failed) | The page could not be rendered or written |
+//!
+//! Every event carries the [`BuildProgress`] of the build, from which
+//! an embedder can show counts and an estimated time remaining:
+//!
+//! ```
+//! use staticweaver::loader::MemoryLoader;
+//! use staticweaver::shared::SharedEngine;
+//! use staticweaver::site::{BuildEventKind, SiteBuilder, SitePage};
+//! use staticweaver::{Context, Engine};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let loader = MemoryLoader::new();
+//! loader.insert("templates/page.html", "<h1>{{title}}</h1>");
+//! let mut engine = Engine::new("templates", Duration::from_secs(60));
+//! engine.set_loader(Arc::new(loader));
+//!
+//! let pages: Vec<SitePage> = ["Home", "About"]
+//!     .iter()
+//!     .map(|title| {
+//!         let mut context = Context::new();
+//!         context.set("title", *title);
+//!         SitePage::new(format!("{}.html", title.to_lowercase()), "page", context)
+//!     })
+//!     .collect();
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let builder = SiteBuilder::new(Arc::new(SharedEngine::new(engine)), dir.path());
+//! let report = builder.build_with_progress(pages, |event| {
+//!     if let BuildEventKind::Written { .. } = event.kind {
+//!         println!("[{}/{}] {}", event.progress.done, event.progress.total, event.path);
+//!     }
+//! });
+//! assert!(report.is_success());
+//! assert_eq!(report.written, 2);
+//! assert_eq!(std::fs::read_to_string(dir.path().join("about.html")).unwrap(), "<h1>About</h1>");
+//! ```
+//!
+//! With a [journal](SiteBuilder::set_journal), a build interrupted by a
+//! crash or a cancellation resumes where it stopped: the pages it wrote
+//! are skipped when it runs again, unless their inputs changed.
+//!
+//! A page that fails to render is reported and the build goes on,
+//! unless the builder is set to [fail fast](SiteBuilder::set_fail_fast).
+//! A page that cannot be written stops the build, as does cancelling
+//! the builder's [`CancelToken`]: workers take no new pages, renders in
+//! progress stop at their next tag, and every worker has finished by
+//! the time the build returns. Only the token stops later builds of the
+//! same builder; a build that stops after a fatal error does not.
+
+use crate::cancel::CancelToken;
+use crate::context::Context;
+use crate::engine::{CacheStatus, EngineError, RenderOptions};
+use crate::integrity::sha256_hex;
+use crate::journal::BuildJournal;
+use crate::manifest::{BuildManifest, ManifestEntry};
+use crate::shared::SharedEngine;
+use crate::sink::{FsSink, OutputSink};
+use crate::warm::WarmReport;
+use std::panic;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The number of worker threads of a new [`SiteBuilder`].
+pub const DEFAULT_BUILD_THREADS: usize = 8;
+
+/// How often a build waiting for events checks whether its
+/// [`CancelToken`] was cancelled.
+const CANCEL_POLL: Duration = Duration::from_millis(50);
+
+/// A page of a site: where it is written, and how it is rendered.
+#[derive(Debug, Clone)]
+pub struct SitePage {
+    /// The path of the page file, relative to the output directory.
+    pub path: String,
+    /// The layout of the page.
+    pub layout: String,
+    /// The context of the page.
+    pub context: Context,
+}
+
+impl SitePage {
+    /// Creates a page written to `path` from `layout` and `context`.
+    #[must_use]
+    pub fn new(
+        path: impl Into<String>,
+        layout: impl Into<String>,
+        context: Context,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            layout: layout.into(),
+            context,
+        }
+    }
+}
+
+/// How far a build has got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildProgress {
+    /// The number of pages to build.
+    pub total: usize,
+    /// The number of pages written, skipped, or failed.
+    pub done: usize,
+    /// The number of pages that failed.
+    pub failed: usize,
+    /// The time since the build started.
+    pub elapsed: Duration,
+}
+
+impl BuildProgress {
+    /// Returns the estimated time until every page is done, from the
+    /// average time per page so far, or `None` before the first page is
+    /// done.
+    #[must_use]
+    pub fn eta(&self) -> Option<Duration> {
+        if self.done == 0 {
+            return None;
+        }
+        let remaining = self.total.saturating_sub(self.done);
+        Some(Duration::from_secs_f64(
+            self.elapsed.as_secs_f64() * remaining as f64
+                / self.done as f64,
+        ))
+    }
+}
+
+/// What happened to a page, as described in the
+/// [module documentation](self).
+#[derive(Debug)]
+pub enum BuildEventKind {
+    /// A worker took the page.
+    Started,
+    /// The page was rendered.
+    Rendered,
+    /// The page was served from the in-memory or disk render cache,
+    /// without rendering.
+    Cached,
+    /// The page was written.
+    Written {
+        /// The length of the page in bytes.
+        bytes: usize,
+    },
+    /// The page was written by an earlier build, with the same input
+    /// hash, as recorded in the journal of the builder.
+    Skipped,
+    /// The page could not be rendered or written.
+    Failed(EngineError),
+}
+
+/// An event of a page, as passed to the progress callback.
+#[derive(Debug)]
+pub struct BuildEvent {
+    /// The path of the page, as given in its [`SitePage`].
+    pub path: String,
+    /// What happened to the page.
+    pub kind: BuildEventKind,
+    /// The progress of the build, this event included.
+    pub progress: BuildProgress,
+}
+
+/// The outcome of a build.
+#[derive(Debug, Default)]
+pub struct BuildReport {
+    /// The number of pages to build.
+    pub total: usize,
+    /// The number of pages written.
+    pub written: usize,
+    /// The number of pages written that were served from a cache.
+    pub cached: usize,
+    /// The number of pages skipped, having been written by an earlier
+    /// build.
+    pub skipped: usize,
+    /// The paths of the pages that failed, and why.
+    pub failed: Vec<(String, EngineError)>,
+    /// Whether the build stopped before every page was done, after a
+    /// fatal error or a cancellation.
+    pub stopped: bool,
+    /// The time the build took.
+    pub elapsed: Duration,
+    /// The files written or skipped, as described in the
+    /// [`manifest`](crate::manifest) module.
+    pub manifest: BuildManifest,
+}
+
+impl BuildReport {
+    /// Returns whether every page was written.
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty() && !self.stopped
+    }
+}
+
+/// Renders the pages of a site on worker threads, as described in the
+/// [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct SiteBuilder {
+    engine: Arc<SharedEngine>,
+    sink: Arc<dyn OutputSink>,
+    threads: usize,
+    fail_fast: bool,
+    options: RenderOptions,
+    cancel: CancelToken,
+    journal: Option<Arc<BuildJournal>>,
+}
+
+impl SiteBuilder {
+    /// Creates a builder writing the pages `engine` renders to
+    /// `out_dir`.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - The engine rendering the pages.
+    /// * `out_dir` - The directory the pages are written to, unless
+    ///   another [sink](SiteBuilder::set_sink) is set.
+    #[must_use]
+    pub fn new(
+        engine: Arc<SharedEngine>,
+        out_dir: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            engine,
+            sink: Arc::new(FsSink::new(out_dir)),
+            threads: DEFAULT_BUILD_THREADS,
+            fail_fast: false,
+            options: RenderOptions::default(),
+            cancel: CancelToken::new(),
+            journal: None,
+        }
+    }
+
+    /// Sets the number of worker threads, at least one.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Sets whether the first page that fails to render stops the
+    /// build, rather than only pages that cannot be written.
+    pub fn set_fail_fast(&mut self, fail_fast: bool) {
+        self.fail_fast = fail_fast;
+    }
+
+    /// Sets the options every page is rendered with, such as a
+    /// [`timeout`](RenderOptions::timeout) or memory caps.
+    ///
+    /// The cancellation token of the options is replaced by that of the
+    /// builder.
+    pub fn set_options(&mut self, options: RenderOptions) {
+        self.options = options;
+    }
+
+    /// Sets the token that stops the build once cancelled, e.g. from a
+    /// signal handler.
+    pub fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
+
+    /// Sets the sink the pages are written to, in place of the output
+    /// directory.
+    pub fn set_sink(&mut self, sink: Arc<dyn OutputSink>) {
+        self.sink = sink;
+    }
+
+    /// Sets the journal recording the pages written, so that a build
+    /// skips those an earlier build wrote, as described in the
+    /// [`journal`](crate::journal) module.
+    pub fn set_journal(&mut self, journal: Option<Arc<BuildJournal>>) {
+        self.journal = journal;
+    }
+
+    /// Returns the token that stops the build once cancelled.
+    #[must_use]
+    pub fn cancel_token(&self) -> &CancelToken {
+        &self.cancel
+    }
+
+    /// Loads the pages of `previous`, the manifest of an earlier build,
+    /// from the disk cache into the render cache of the engine, as
+    /// described in the [`warm`](crate::warm) module.
+    ///
+    /// Pages are looked up with the options of the builder, so it is
+    /// called once they are set.
+    #[must_use]
+    pub fn warm(&self, previous: &BuildManifest) -> WarmReport {
+        self.engine.read().warm_cache(previous, &self.options)
+    }
+
+    /// Renders and writes `pages`, without reporting progress.
+    #[must_use]
+    pub fn build(&self, pages: Vec<SitePage>) -> BuildReport {
+        self.build_with_progress(pages, |_| {})
+    }
+
+    /// Renders and writes `pages`, passing each [`BuildEvent`] to
+    /// `progress` on the calling thread.
+    ///
+    /// Returns once every worker has finished. A panic in a worker is
+    /// resumed on the calling thread.
+    pub fn build_with_progress<F>(
+        &self,
+        pages: Vec<SitePage>,
+        mut progress: F,
+    ) -> BuildReport
+    where
+        F: FnMut(&BuildEvent),
+    {
+        let started = Instant::now();
+        let pages = Arc::new(pages);
+        let next = Arc::new(AtomicUsize::new(0));
+        // Stops this build only, so that a fatal error does not cancel
+        // the builder's token for later builds.
+        let stop = CancelToken::new();
+        if self.cancel.is_cancelled() {
+            stop.cancel();
+        }
+        let (sender, receiver) = mpsc::channel();
+        let workers: Vec<_> = (0..self.threads.min(pages.len()))
+            .map(|_| {
+                let worker = Worker {
+                    builder: self.clone(),
+                    stop: stop.clone(),
+                    pages: Arc::clone(&pages),
+                    next: Arc::clone(&next),
+                    events: sender.clone(),
+                };
+                thread::spawn(move || worker.run())
+            })
+            .collect();
+        drop(sender);
+
+        let mut report = BuildReport {
+            total: pages.len(),
+            ..BuildReport::default()
+        };
+        let (mut done, mut failed) = (0, 0);
+        let mut cached = vec![false; pages.len()];
+        loop {
+            if self.cancel.is_cancelled() {
+                stop.cancel();
+            }
+            let (index, kind, entry) =
+                match receiver.recv_timeout(CANCEL_POLL) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+            match &kind {
+                BuildEventKind::Written { .. } => {
+                    done += 1;
+                    report.written += 1;
+                    if cached[index] {
+                        report.cached += 1;
+                    }
+                }
+                BuildEventKind::Failed(_) => {
+                    done += 1;
+                    failed += 1;
+                }
+                BuildEventKind::Skipped => {
+                    done += 1;
+                    report.skipped += 1;
+                }
+                BuildEventKind::Cached => cached[index] = true,
+                _ => {}
+            }
+            let event = BuildEvent {
+                path: pages[index].path.clone(),
+                kind,
+                progress: BuildProgress {
+                    total: pages.len(),
+                    done,
+                    failed,
+                    elapsed: started.elapsed(),
+                },
+            };
+            if let Some(entry) = entry {
+                report.manifest.insert(entry);
+            }
+            progress(&event);
+            if let BuildEventKind::Failed(err) = event.kind {
+                report.failed.push((event.path, err));
+            }
+        }
+        for worker in workers {
+            if let Err(panic) = worker.join() {
+                panic::resume_unwind(panic);
+            }
+        }
+        report.stopped = done < pages.len();
+        report.elapsed = started.elapsed();
+        report
+    }
+}
+
+/// A worker thread of a build, taking pages until none are left.
+#[derive(Debug)]
+struct Worker {
+    builder: SiteBuilder,
+    stop: CancelToken,
+    pages: Arc<Vec<SitePage>>,
+    next: Arc<AtomicUsize>,
+    events: Sender<(usize, BuildEventKind, Option<ManifestEntry>)>,
+}
+
+impl Worker {
+    /// Builds pages until none are left or the build is cancelled.
+    fn run(self) {
+        let mut options = self.builder.options.clone();
+        options.cancel = Some(self.stop.clone());
+        while !self.stop.is_cancelled() {
+            let index = self.next.fetch_add(1, Ordering::Relaxed);
+            let Some(page) = self.pages.get(index) else {
+                break;
+            };
+            self.send(index, BuildEventKind::Started);
+            let hash = self.builder.journal.as_ref().and_then(|_| {
+                self.builder.engine.read().page_input_hash(
+                    &page.context,
+                    &page.layout,
+                    &options,
+                )
+            });
+            if let (Some(journal), Some(hash)) =
+                (&self.builder.journal, &hash)
+            {
+                let written = journal
+                    .is_done(&page.path, hash)
+                    .then(|| self.builder.sink.read(&page.path))
+                    .flatten();
+                if let Some(written) = written {
+                    let engine = self.builder.engine.read();
+                    let entry = ManifestEntry {
+                        path: page.path.clone(),
+                        layout: page.layout.clone(),
+                        source: engine.resolve_template(&page.layout),
+                        content_hash: sha256_hex(&written),
+                        size: written.len() as u64,
+                        render_time: None,
+                        context_hash: Some(
+                            engine.context_hash(&page.context),
+                        ),
+                    };
+                    drop(engine);
+                    self.send_entry(
+                        index,
+                        BuildEventKind::Skipped,
+                        entry,
+                    );
+                    continue;
+                }
+            }
+            let rendered = self.builder.engine.render_page_detailed(
+                &options,
+                &page.context,
+                &page.layout,
+            );
+            let rendered = match rendered {
+                Ok(rendered) => rendered,
+                Err(_) if self.stop.is_cancelled() => break,
+                Err(err) => {
+                    if self.builder.fail_fast {
+                        self.stop.cancel();
+                    }
+                    self.send(index, BuildEventKind::Failed(err));
+                    continue;
+                }
+            };
+            self.send(
+                index,
+                match rendered.cache {
+                    CacheStatus::Hit | CacheStatus::DiskHit => {
+                        BuildEventKind::Cached
+                    }
+                    CacheStatus::Miss | CacheStatus::Bypass => {
+                        BuildEventKind::Rendered
+                    }
+                },
+            );
+            let written = self
+                .builder
+                .sink
+                .write(&page.path, rendered.output.as_bytes())
+                .and_then(|()| match (&self.builder.journal, &hash) {
+                    (Some(journal), Some(hash)) => {
+                        journal.record(&page.path, hash)
+                    }
+                    _ => Ok(()),
+                });
+            match written {
+                Ok(()) => self.send_entry(
+                    index,
+                    BuildEventKind::Written {
+                        bytes: rendered.output.len(),
+                    },
+                    ManifestEntry {
+                        path: page.path.clone(),
+                        layout: page.layout.clone(),
+                        source: rendered.dependencies.first().cloned(),
+                        content_hash: rendered.content_hash,
+                        size: rendered.output.len() as u64,
+                        render_time: Some(rendered.duration),
+                        context_hash: Some(
+                            self.builder
+                                .engine
+                                .read()
+                                .context_hash(&page.context),
+                        ),
+                    },
+                ),
+                Err(err) => {
+                    self.stop.cancel();
+                    self.send(index, BuildEventKind::Failed(err));
+                }
+            }
+        }
+    }
+
+    /// Sends the event `kind` of the page at `index`.
+    fn send(&self, index: usize, kind: BuildEventKind) {
+        let _ = self.events.send((index, kind, None));
+    }
+
+    /// Sends the event `kind` of the page at `index`, with the manifest
+    /// entry of its file.
+    fn send_entry(
+        &self,
+        index: usize,
+        kind: BuildEventKind,
+        entry: ManifestEntry,
+    ) {
+        let _ = self.events.send((index, kind, Some(entry)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::MemoryLoader;
+    use crate::sink::MemorySink;
+    use crate::Engine;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn builder(dir: &TempDir) -> SiteBuilder {
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/page.html", "<h1>{{title}}</h1>");
+        let _ = loader.insert("site/broken.html", "{{missing}}");
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        let mut builder = SiteBuilder::new(
+            Arc::new(SharedEngine::new(engine)),
+            dir.path(),
+        );
+        builder.set_threads(2);
+        builder
+    }
+
+    fn pages(layouts: &[&str]) -> Vec<SitePage> {
+        layouts
+            .iter()
+            .enumerate()
+            .map(|(index, layout)| {
+                let mut context = Context::new();
+                context.set("title", index.to_string());
+                SitePage::new(
+                    format!("dir/{}.html", index),
+                    *layout,
+                    context,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_build_events() {
+        let dir = TempDir::new().unwrap();
+        let builder = builder(&dir);
+        let mut events = Vec::new();
+        let report = builder.build_with_progress(
+            pages(&["page", "broken", "page"]),
+            |event| events.push((event.path.clone(), event.progress)),
+        );
+        assert_eq!((report.total, report.written), (3, 2));
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, "dir/1.html");
+        assert!(!report.stopped && !report.is_success());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("dir/2.html")).unwrap(),
+            "<h1>2</h1>"
+        );
+        // Started, Rendered and Written for each page written, Started
+        // and Failed for the other.
+        assert_eq!(events.len(), 8);
+        let last = events.last().unwrap().1;
+        assert_eq!((last.done, last.failed, last.total), (3, 1, 3));
+        assert_eq!(last.eta(), Some(Duration::ZERO));
+
+        let entry = &report.manifest.entries()[0];
+        assert_eq!(report.manifest.len(), 2);
+        assert_eq!(entry.path, "dir/0.html");
+        assert_eq!(entry.content_hash, sha256_hex(b"<h1>0</h1>"));
+        assert_eq!(entry.size, 10);
+        assert!(entry.source.as_ref().unwrap().ends_with("page.html"));
+        assert!(entry.render_time.is_some());
+
+        let report = builder.build(pages(&["page"]));
+        assert_eq!((report.written, report.cached), (1, 1));
+        assert!(report.is_success());
+    }
+
+    #[test]
+    fn test_build_counts_cached_pages_written() {
+        let dir = TempDir::new().unwrap();
+        let builder = builder(&dir);
+        let _ = builder.build(pages(&["page"]));
+
+        // The page is served from the cache but cannot be written.
+        let mut page = pages(&["page"]).remove(0);
+        page.path = "../escape.html".to_string();
+        let report = builder.build(vec![page]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.cached, 0);
+    }
+
+    #[test]
+    fn test_build_resumes() {
+        let dir = TempDir::new().unwrap();
+        let journal = Arc::new(
+            BuildJournal::open(dir.path().join("build.journal"))
+                .unwrap(),
+        );
+        let mut builder = builder(&dir);
+        builder.set_journal(Some(Arc::clone(&journal)));
+        let report = builder.build(pages(&["page", "broken"]));
+        assert_eq!((report.written, report.skipped), (1, 0));
+        assert_eq!(journal.len(), 1);
+
+        let mut pages = pages(&["page", "page", "page"]);
+        let report = builder.build(pages.clone());
+        assert_eq!((report.written, report.skipped), (2, 1));
+        assert!(report.is_success());
+        let skipped = report.manifest.get("dir/0.html").unwrap();
+        assert_eq!(skipped.content_hash, sha256_hex(b"<h1>0</h1>"));
+        assert_eq!(skipped.render_time, None);
+
+        pages[0].context.set("title", "changed");
+        fs::remove_file(dir.path().join("dir/1.html")).unwrap();
+        let report = builder.build(pages);
+        assert_eq!((report.written, report.skipped), (2, 1));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("dir/0.html")).unwrap(),
+            "<h1>changed</h1>"
+        );
+    }
+
+    #[test]
+    fn test_build_to_sink() {
+        let dir = TempDir::new().unwrap();
+        let sink = MemorySink::new();
+        let mut builder = builder(&dir);
+        builder.set_sink(Arc::new(sink.clone()));
+        builder.set_journal(Some(Arc::new(
+            BuildJournal::open(dir.path().join("build.journal"))
+                .unwrap(),
+        )));
+        let report = builder.build(pages(&["page", "page"]));
+        assert_eq!(report.written, 2);
+        assert_eq!(sink.paths(), ["dir/0.html", "dir/1.html"]);
+        assert_eq!(sink.get("dir/1.html").unwrap(), b"<h1>1</h1>");
+        assert!(!dir.path().join("dir").exists());
+
+        let _ = sink.remove("dir/0.html");
+        let report = builder.build(pages(&["page", "page"]));
+        assert_eq!((report.written, report.skipped), (1, 1));
+        assert_eq!(sink.len(), 2);
+    }
+
+    #[test]
+    fn test_build_stops() {
+        let dir = TempDir::new().unwrap();
+        let mut builder = builder(&dir);
+        builder.set_threads(1);
+        builder.set_fail_fast(true);
+        let report = builder.build(pages(&["broken", "page", "page"]));
+        assert_eq!((report.written, report.failed.len()), (0, 1));
+        assert!(report.stopped);
+
+        let dir = TempDir::new().unwrap();
+        let builder = builder.clone();
+        let mut page = pages(&["page"]).remove(0);
+        page.path = "../escape.html".to_string();
+        let report = builder.build(vec![page]);
+        assert_eq!(report.failed[0].1.code(), "io");
+        assert!(!dir.path().join("../escape.html").exists());
+
+        let builder = self::builder(&dir);
+        builder.cancel_token().cancel();
+        let report = builder.build(pages(&["page", "page"]));
+        assert_eq!(report.written, 0);
+        assert!(report.stopped && report.failed.is_empty());
+    }
+}
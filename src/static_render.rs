@@ -0,0 +1,64 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Support for the code generated by the `render_static!` macro and by
+//! [`Engine::compile_templates`](crate::Engine::compile_templates),
+//! which is not part of the public API.
+
+use crate::escape::OutputFormat;
+use crate::filter::Filter;
+use crate::syntax::SyntaxVersion;
+use std::borrow::Cow;
+use std::fmt::{self, Write};
+use std::path::Path;
+
+/// Returns the output format of the template at `path` declaring the
+/// syntax version `syntax`, as [`Engine::output_format_for_template`]
+/// would with the default settings.
+///
+/// [`Engine::output_format_for_template`]: crate::Engine::output_format_for_template
+#[must_use]
+pub fn output_format(path: &str, syntax: u32) -> OutputFormat {
+    let escapes_html = SyntaxVersion::from_number(syntax)
+        .map_or(false, SyntaxVersion::escapes_html);
+    match OutputFormat::from_path(Path::new(path)) {
+        OutputFormat::Html if !escapes_html => OutputFormat::Plain,
+        format => format,
+    }
+}
+
+/// Writes `value` to `out` through `filters`, escaped for `format`
+/// unless the last filter is safe, like a tag rendered by an engine.
+pub fn write_value<T: fmt::Display + ?Sized>(
+    out: &mut String,
+    value: &T,
+    filters: &[Filter],
+    format: OutputFormat,
+) {
+    if filters.is_empty() && format == OutputFormat::Plain {
+        let _ = write!(out, "{}", value);
+    } else {
+        write_str(out, &value.to_string(), filters, format);
+    }
+}
+
+/// Writes `value` to `out` like [`write_value`], without formatting it
+/// first.
+pub fn write_str(
+    out: &mut String,
+    value: &str,
+    filters: &[Filter],
+    format: OutputFormat,
+) {
+    let mut value = Cow::Borrowed(value);
+    let mut safe = false;
+    for filter in filters {
+        value = Cow::Owned(filter.apply(&value, None));
+        safe = filter.is_safe();
+    }
+    if safe {
+        out.push_str(&value);
+    } else {
+        out.push_str(&format.escape(&value));
+    }
+}
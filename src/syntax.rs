@@ -0,0 +1,317 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Syntax Module
+//!
+//! This module provides the `SyntaxVersion` enum, so that changes to the
+//! template syntax can be adopted one template at a time. Templates are
+//! read with the engine's [`syntax`](crate::Engine::syntax) version
+//! unless they start with a pragma naming their own, on a line of its
+//! own:
+//!
+//! ```text
+//! {{#syntax 2}}
+//! <h1>{{ title }}</h1>
+//! ```
+//!
+//! | Rule | Version 1 | Version 2 |
+//! |---|---|---|
+//! | Tag keys | Trimmed only in tags with filters, unless `trim_tag_keys` is set | Always trimmed |
+//! | HTML layouts | Values inserted verbatim, unless `auto_escape` is set | Values HTML-escaped; `\| safe` inserts one verbatim |
+//!
+//! [`Engine::migrate_template`](crate::Engine::migrate_template) and
+//! [`Engine::migrate_layout`](crate::Engine::migrate_layout) rewrite a
+//! template to the latest version without changing its output.
+
+use crate::engine::{Engine, EngineError};
+use crate::escape::OutputFormat;
+use crate::filter::Filter;
+use crate::function::is_call;
+use crate::parser::{Parser, Segment, Token};
+
+/// The name of the pragma that sets the syntax version of a template.
+pub const SYNTAX_PRAGMA: &str = "#syntax";
+
+/// A version of the template syntax.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum SyntaxVersion {
+    /// The original syntax.
+    #[default]
+    V1,
+    /// Tag keys are always trimmed, and HTML layouts are auto-escaped.
+    V2,
+}
+
+impl SyntaxVersion {
+    /// The latest version, that migrations rewrite templates to.
+    pub const LATEST: Self = Self::V2;
+
+    /// Returns the version numbered `number` in pragmas.
+    ///
+    /// # Arguments
+    ///
+    /// * `number` - The version number, such as `2`.
+    #[must_use]
+    pub const fn from_number(number: u32) -> Option<Self> {
+        match number {
+            1 => Some(Self::V1),
+            2 => Some(Self::V2),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of the version in pragmas.
+    #[must_use]
+    pub const fn number(self) -> u32 {
+        match self {
+            Self::V1 => 1,
+            Self::V2 => 2,
+        }
+    }
+
+    /// Returns whether tag keys are always trimmed.
+    #[must_use]
+    pub const fn trims_keys(self) -> bool {
+        matches!(self, Self::V2)
+    }
+
+    /// Returns whether values rendered into HTML layouts are escaped.
+    #[must_use]
+    pub const fn escapes_html(self) -> bool {
+        matches!(self, Self::V2)
+    }
+}
+
+/// Returns the version named by the pragma at the start of `template`,
+/// and the length of the pragma including the line break after it.
+///
+/// # Arguments
+///
+/// * `template` - The template.
+/// * `open` - The opening delimiter of tags.
+/// * `close` - The closing delimiter of tags.
+///
+/// # Errors
+///
+/// Returns [`EngineError::InvalidTemplate`] if the pragma names an
+/// unknown version.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::syntax::{pragma, SyntaxVersion};
+///
+/// let template = "{{#syntax 2}}\n<p>{{body}}</p>";
+/// assert_eq!(
+///     pragma(template, "{{", "}}").unwrap(),
+///     Some((SyntaxVersion::V2, 14))
+/// );
+/// assert_eq!(pragma("<p>{{body}}</p>", "{{", "}}").unwrap(), None);
+/// assert!(pragma("{{#syntax 9}}", "{{", "}}").is_err());
+/// ```
+pub fn pragma(
+    template: &str,
+    open: &str,
+    close: &str,
+) -> Result<Option<(SyntaxVersion, usize)>, EngineError> {
+    let Some(rest) = template.strip_prefix(open) else {
+        return Ok(None);
+    };
+    let Some(end) = rest.find(close) else {
+        return Ok(None);
+    };
+    let Some(number) = rest[..end].trim().strip_prefix(SYNTAX_PRAGMA)
+    else {
+        return Ok(None);
+    };
+    let version = number
+        .trim()
+        .parse()
+        .ok()
+        .and_then(SyntaxVersion::from_number)
+        .ok_or_else(|| {
+            EngineError::InvalidTemplate(format!(
+                "Unsupported syntax version: {}",
+                number.trim()
+            ))
+        })?;
+    let len = open.len() + end + close.len();
+    let line_break = if template[len..].starts_with("\r\n") {
+        2
+    } else {
+        usize::from(template[len..].starts_with('\n'))
+    };
+    Ok(Some((version, len + line_break)))
+}
+
+/// Rewrites `source`, a template rendered in `old_format` under its
+/// current syntax and in `new_format` under the latest, to the latest
+/// syntax.
+pub(crate) fn migrate(
+    engine: &Engine,
+    source: &str,
+    old_format: OutputFormat,
+    new_format: OutputFormat,
+) -> String {
+    let open = engine.open_delim.as_str();
+    let close = engine.close_delim.as_str();
+    let (version, start) = match pragma(source, open, close) {
+        Ok(Some((version, len))) => (version, len),
+        Ok(None) => (engine.syntax, 0),
+        Err(_) => return source.to_string(),
+    };
+    if version == SyntaxVersion::LATEST {
+        return source.to_string();
+    }
+    let mark_safe = old_format != new_format
+        && new_format == OutputFormat::Html
+        && old_format == OutputFormat::Plain;
+
+    let body = &source[start..];
+    let mut migrated = format!(
+        "{}{} {}{}\n",
+        open,
+        SYNTAX_PRAGMA,
+        SyntaxVersion::LATEST.number(),
+        close
+    );
+    let mut end = 0;
+    for result in Parser::new(body, open, close) {
+        match result {
+            Ok(Token {
+                segment: Segment::Tag(tag),
+                span,
+            }) if mark_safe
+                && !is_safe(tag)
+                && !engine.blocks.is_block_tag(tag.trim()) =>
+            {
+                let kept = tag.trim_end();
+                migrated.push_str(open);
+                migrated.push_str(kept);
+                migrated.push_str(" | safe");
+                migrated.push_str(&tag[kept.len()..]);
+                migrated.push_str(close);
+                end = span.end;
+            }
+            Ok(Token { span, .. }) => {
+                migrated.push_str(&body[span.clone()]);
+                end = span.end;
+            }
+            Err(err) => {
+                migrated.push_str(&body[err.span.clone()]);
+                end = err.span.end;
+            }
+        }
+    }
+    migrated.push_str(&body[end..]);
+    migrated
+}
+
+/// Returns whether `tag` is written verbatim in every version: a
+/// function call, or a tag whose last filter is safe.
+fn is_safe(tag: &str) -> bool {
+    if is_call(tag.trim()) {
+        return true;
+    }
+    tag.rsplit_once('|')
+        .and_then(|(_, name)| Filter::from_name(name.trim()))
+        .map_or(false, Filter::is_safe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Context;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[test]
+    fn test_pragma() {
+        assert_eq!(
+            pragma("<%#syntax 1%>\r\nbody", "<%", "%>").unwrap(),
+            Some((SyntaxVersion::V1, 15))
+        );
+        assert_eq!(
+            pragma("{{#syntax 2}}", "{{", "}}").unwrap().unwrap().1,
+            13
+        );
+        assert_eq!(pragma("{{title}}", "{{", "}}").unwrap(), None);
+        assert_eq!(pragma(" {{#syntax 2}}", "{{", "}}").unwrap(), None);
+        assert!(pragma("{{#syntax two}}", "{{", "}}").is_err());
+    }
+
+    #[test]
+    fn test_migrate() {
+        let engine = Engine::new("", Duration::from_secs(60));
+        let source = "<h1>{{ title }}</h1>{{body | cdata}}\
+                      {{ name | slugify }}{{ og_tags() }}{{open";
+        let migrated =
+            engine.migrate_layout(source, Path::new("page.html"));
+        assert_eq!(
+            migrated,
+            "{{#syntax 2}}\n<h1>{{ title | safe }}</h1>{{body | cdata}}\
+             {{ name | slugify | safe }}{{ og_tags() }}{{open"
+        );
+        assert_eq!(
+            engine.migrate_layout(&migrated, Path::new("page.html")),
+            migrated
+        );
+        assert_eq!(
+            engine.migrate_template("{{#syntax 1}}\n{{ title }}"),
+            "{{#syntax 2}}\n{{ title }}"
+        );
+
+        let mut context = Context::new();
+        context.set("title", "<b>Hi</b>");
+        let mut v2 = Engine::new("", Duration::from_secs(60));
+        v2.syntax = SyntaxVersion::V2;
+        assert_eq!(
+            v2.render_template_with_format(
+                "{{ title }} {{title | safe}}",
+                &context,
+                OutputFormat::Html
+            )
+            .unwrap(),
+            "&lt;b&gt;Hi&lt;/b&gt; <b>Hi</b>"
+        );
+    }
+
+    #[test]
+    fn test_render_with_pragma() {
+        use crate::loader::MemoryLoader;
+        use std::sync::Arc;
+
+        let loader = MemoryLoader::new();
+        let _ = loader.insert("site/v1.html", "<p>{{title}}</p>");
+        let _ = loader.insert(
+            "site/v2.html",
+            "{{#syntax 2}}\n<p>{{ title }}</p>{{title | safe}}",
+        );
+        let mut engine = Engine::new("site", Duration::from_secs(60));
+        engine.set_loader(Arc::new(loader));
+        let mut context = Context::new();
+        context.set("title", "<b>");
+
+        assert_eq!(
+            engine.render_page(&context, "v1").unwrap(),
+            "<p><b></p>"
+        );
+        assert_eq!(
+            engine.render_page(&context, "v2").unwrap(),
+            "<p>&lt;b&gt;</p><b>"
+        );
+
+        let report = engine
+            .render_template_report(
+                "{{#syntax 2}}\n\n{{ missing }}",
+                &context,
+            )
+            .unwrap_err();
+        assert_eq!(report.problems[0].line, 3);
+        assert!(engine
+            .render_template("{{#syntax 3}}", &context)
+            .is_err());
+    }
+}
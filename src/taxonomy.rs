@@ -0,0 +1,261 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Taxonomy Module
+//!
+//! This module provides the `Taxonomy` struct, which collects the terms
+//! of a taxonomy, such as `tags` or `categories`, across the pages of a
+//! site, and builds the contexts of the taxonomy and per-term index
+//! pages.
+//!
+//! A page lists its terms under the taxonomy key, either as a JSON array
+//! or as a comma-separated string. Contexts are flat, so collections are
+//! exposed to templates as numbered keys:
+//!
+//! ```text
+//! {{taxonomy}}               tags
+//! {{terms.len}}              number of terms
+//! {{terms.0.name}}           first term, in sorted order
+//! {{terms.0.slug}}           its slug, as built by the `slugify` filter
+//! {{terms.0.count}}          number of pages with the term
+//!
+//! {{term}} / {{slug}}        the term of a per-term page
+//! {{pages.len}}              number of pages with the term
+//! {{pages.0.title}}          title of the first page
+//! {{pages.0.url}}            URL of the first page
+//! ```
+
+use crate::context::Context;
+use crate::filter::slugify;
+use std::collections::BTreeMap;
+
+/// A page listed under a taxonomy term.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TermPage {
+    /// The title of the page, or its URL if it has no `title` value.
+    pub title: String,
+    /// The URL of the page.
+    pub url: String,
+}
+
+/// The terms of one taxonomy across a site, with the pages of each term.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::taxonomy::Taxonomy;
+/// use staticweaver::Context;
+///
+/// let mut tags = Taxonomy::new("tags");
+/// let mut page = Context::new();
+/// page.set("title", "Hello");
+/// page.set("tags", r#"["rust", "Static Sites"]"#);
+/// tags.add("/hello.html", &page);
+///
+/// let index = tags.index_context();
+/// assert_eq!(index.get("terms.len"), Some(&"2".to_string()));
+/// assert_eq!(index.get("terms.0.slug"), Some(&"static-sites".to_string()));
+///
+/// let term = tags.term_context("rust").unwrap();
+/// assert_eq!(term.get("pages.0.url"), Some(&"/hello.html".to_string()));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Taxonomy {
+    name: String,
+    terms: BTreeMap<String, Vec<TermPage>>,
+}
+
+impl Taxonomy {
+    /// Creates an empty taxonomy read from the context key `name`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The context key listing a page's terms, e.g. `tags`.
+    #[must_use]
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            terms: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the name of the taxonomy.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Adds the page at `url` under each of the terms listed in its
+    /// `context`. Pages without terms are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the page.
+    /// * `context` - The context of the page.
+    pub fn add(&mut self, url: &str, context: &Context) {
+        let Some(value) = context.get(&self.name) else {
+            return;
+        };
+        for term in terms(value) {
+            let pages = self.terms.entry(term).or_default();
+            if pages.iter().all(|page| page.url != url) {
+                pages.push(TermPage {
+                    title: context
+                        .get("title")
+                        .map_or_else(|| url.to_string(), Clone::clone),
+                    url: url.to_string(),
+                });
+            }
+        }
+    }
+
+    /// Returns the terms and their pages, sorted by term.
+    pub fn terms(&self) -> impl Iterator<Item = (&str, &[TermPage])> {
+        self.terms
+            .iter()
+            .map(|(term, pages)| (term.as_str(), pages.as_slice()))
+    }
+
+    /// Returns the number of terms.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    /// Returns whether no page has any term.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Returns the context listing every term, with the `taxonomy` and
+    /// `terms.*` keys described in the [module documentation](self).
+    #[must_use]
+    pub fn index_context(&self) -> Context {
+        let mut context = Context::new();
+        context.set("taxonomy", self.name.as_str());
+        context.set("terms.len", self.terms.len().to_string());
+        for (index, (term, pages)) in self.terms.iter().enumerate() {
+            let prefix = format!("terms.{}", index);
+            context.set(format!("{}.name", prefix), term.as_str());
+            context.set(format!("{}.slug", prefix), slugify(term));
+            context.set(
+                format!("{}.count", prefix),
+                pages.len().to_string(),
+            );
+        }
+        context
+    }
+
+    /// Returns the context of the index page of `term`: the keys of
+    /// [`Taxonomy::index_context`] plus `term`, `slug`, and `pages.*`.
+    ///
+    /// Returns `None` if no page has the term.
+    ///
+    /// # Arguments
+    ///
+    /// * `term` - The term.
+    #[must_use]
+    pub fn term_context(&self, term: &str) -> Option<Context> {
+        let pages = self.terms.get(term)?;
+        let mut context = self.index_context();
+        context.set("term", term);
+        context.set("slug", slugify(term));
+        context.set("pages.len", pages.len().to_string());
+        for (index, page) in pages.iter().enumerate() {
+            context.set(
+                format!("pages.{}.title", index),
+                page.title.as_str(),
+            );
+            context
+                .set(format!("pages.{}.url", index), page.url.as_str());
+        }
+        Some(context)
+    }
+}
+
+/// Splits a taxonomy value into its terms.
+///
+/// A JSON array of strings lists one term per element; any other value
+/// is split on commas. Terms are trimmed and empty terms are dropped.
+///
+/// # Arguments
+///
+/// * `value` - The taxonomy value of a page.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::taxonomy::terms;
+///
+/// assert_eq!(terms(r#"["a", " b "]"#), ["a", "b"]);
+/// assert_eq!(terms("a, b,,"), ["a", "b"]);
+/// ```
+#[must_use]
+pub fn terms(value: &str) -> Vec<String> {
+    let terms = match serde_json::from_str::<Vec<String>>(value) {
+        Ok(terms) => terms,
+        Err(_) => value.split(',').map(str::to_string).collect(),
+    };
+    terms
+        .into_iter()
+        .map(|term| term.trim().to_string())
+        .filter(|term| !term.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(title: Option<&str>, tags: &str) -> Context {
+        let mut context = Context::new();
+        if let Some(title) = title {
+            context.set("title", title);
+        }
+        context.set("tags", tags);
+        context
+    }
+
+    #[test]
+    fn test_taxonomy() {
+        let mut tags = Taxonomy::new("tags");
+        assert!(tags.is_empty());
+        tags.add("/a.html", &page(Some("A"), "rust, web"));
+        tags.add("/b.html", &page(None, r#"["rust"]"#));
+        tags.add("/b.html", &page(None, "rust"));
+        tags.add("/c.html", &Context::new());
+
+        assert_eq!(tags.name(), "tags");
+        assert_eq!(tags.len(), 2);
+        let terms: Vec<_> = tags
+            .terms()
+            .map(|(term, pages)| (term, pages.len()))
+            .collect();
+        assert_eq!(terms, [("rust", 2), ("web", 1)]);
+
+        let context = tags.term_context("rust").unwrap();
+        assert_eq!(context.get("taxonomy"), Some(&"tags".to_string()));
+        assert_eq!(
+            context.get("terms.0.count"),
+            Some(&"2".to_string())
+        );
+        assert_eq!(context.get("pages.len"), Some(&"2".to_string()));
+        assert_eq!(
+            context.get("pages.0.title"),
+            Some(&"A".to_string())
+        );
+        assert_eq!(
+            context.get("pages.1.title"),
+            Some(&"/b.html".to_string())
+        );
+        assert!(tags.term_context("go").is_none());
+    }
+
+    #[test]
+    fn test_terms() {
+        assert_eq!(terms("[1, 2]"), ["[1", "2]"]);
+        assert!(terms("").is_empty());
+        assert!(terms("[]").is_empty());
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Testing Module
+//!
+//! This module provides assertions for the tests of sites built on the
+//! engine, available with the `test-util` feature:
+//!
+//! * [`assert_renders_to!`](crate::assert_renders_to) renders a template
+//!   string and compares it with the expected output.
+//! * [`assert_snapshot`] compares rendered output with a golden file,
+//!   ignoring differences in whitespace. Missing golden files are
+//!   written, and all of them are rewritten when the
+//!   [`UPDATE_SNAPSHOTS`] environment variable is set:
+//!
+//! ```text
+//! STATICWEAVER_UPDATE_SNAPSHOTS=1 cargo test
+//! ```
+
+use crate::context::Context;
+use crate::engine::Engine;
+use std::fs;
+use std::path::Path;
+
+/// The environment variable that makes [`assert_snapshot`] rewrite
+/// golden files instead of comparing them.
+pub const UPDATE_SNAPSHOTS: &str = "STATICWEAVER_UPDATE_SNAPSHOTS";
+
+/// Asserts that rendering a template string with a context produces the
+/// expected output.
+///
+/// # Panics
+///
+/// Panics with the render error if the template cannot be rendered, or
+/// with both outputs if they differ.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "test-util")]
+/// # {
+/// use staticweaver::{assert_renders_to, Context, Engine};
+/// use std::time::Duration;
+///
+/// let engine = Engine::new("templates", Duration::from_secs(60));
+/// let mut context = Context::new();
+/// context.set("name", "World");
+/// assert_renders_to!(engine, "Hello, {{name}}!", context, "Hello, World!");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! assert_renders_to {
+    ($engine:expr, $template:expr, $context:expr, $expected:expr $(,)?) => {
+        $crate::testing::assert_renders_to(
+            &$engine, $template, &$context, $expected,
+        )
+    };
+}
+
+/// Asserts that `engine` renders `template` with `context` to
+/// `expected`, as the [`assert_renders_to!`](crate::assert_renders_to)
+/// macro does.
+///
+/// # Arguments
+///
+/// * `engine` - The engine to render with.
+/// * `template` - The template string.
+/// * `context` - The context to render with.
+/// * `expected` - The expected output.
+///
+/// # Panics
+///
+/// Panics with the render error if the template cannot be rendered, or
+/// with both outputs if they differ.
+#[track_caller]
+pub fn assert_renders_to(
+    engine: &Engine,
+    template: &str,
+    context: &Context,
+    expected: &str,
+) {
+    match engine.render_template(template, context) {
+        Ok(actual) if actual == expected => {}
+        Ok(actual) => panic!(
+            "template rendered differently\n  template: {:?}\n  expected: {:?}\n    actual: {:?}",
+            template, expected, actual
+        ),
+        Err(err) => panic!(
+            "template failed to render\n  template: {:?}\n     error: {}",
+            template, err
+        ),
+    }
+}
+
+/// Returns `text` with each line trimmed, runs of whitespace within
+/// lines collapsed to a single space, and blank lines removed.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "test-util")]
+/// # {
+/// use staticweaver::testing::normalize_whitespace;
+///
+/// assert_eq!(
+///     normalize_whitespace("<ul>\n\n    <li>a   b</li>\t\n</ul>\n"),
+///     "<ul>\n<li>a b</li>\n</ul>"
+/// );
+/// # }
+/// ```
+#[must_use]
+pub fn normalize_whitespace(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            line.split_whitespace().collect::<Vec<_>>().join(" ")
+        })
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Asserts that `actual` matches the golden file at `path`, after
+/// [`normalize_whitespace`] is applied to both.
+///
+/// If the file does not exist, or the [`UPDATE_SNAPSHOTS`] environment
+/// variable is set, the normalized output is written to it instead,
+/// creating its parent directories.
+///
+/// # Arguments
+///
+/// * `path` - The path of the golden file.
+/// * `actual` - The rendered output.
+///
+/// # Panics
+///
+/// Panics if the golden file cannot be read or written, or with both
+/// outputs if they differ.
+#[track_caller]
+pub fn assert_snapshot(path: impl AsRef<Path>, actual: &str) {
+    let path = path.as_ref();
+    let actual = normalize_whitespace(actual);
+    if !path.exists() || std::env::var_os(UPDATE_SNAPSHOTS).is_some() {
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(path, format!("{}\n", actual)));
+        if let Err(err) = written {
+            panic!("cannot write snapshot {}: {}", path.display(), err);
+        }
+        return;
+    }
+    let expected = match fs::read_to_string(path) {
+        Ok(expected) => normalize_whitespace(&expected),
+        Err(err) => {
+            panic!("cannot read snapshot {}: {}", path.display(), err)
+        }
+    };
+    if actual != expected {
+        panic!(
+            "output differs from snapshot {}\n--- expected\n{}\n--- actual\n{}\n\
+             (set {} to update the snapshot)",
+            path.display(),
+            expected,
+            actual,
+            UPDATE_SNAPSHOTS
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_assert_renders_to() {
+        let engine = Engine::new("", Duration::from_secs(60));
+        let mut context = Context::new();
+        context.set("name", "World");
+        crate::assert_renders_to!(
+            engine,
+            "Hi {{name}}",
+            context,
+            "Hi World"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "template rendered differently")]
+    fn test_assert_renders_to_mismatch() {
+        let engine = Engine::new("", Duration::from_secs(60));
+        assert_renders_to(&engine, "Sun", &Context::new(), "Moon");
+    }
+
+    #[test]
+    fn test_assert_snapshot() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("pages/home.html");
+
+        assert_snapshot(&path, "<p>\n   Home  </p>\n\n");
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "<p>\nHome </p>\n"
+        );
+        assert_snapshot(&path, "<p>\nHome\t</p>");
+    }
+
+    #[test]
+    #[should_panic(expected = "output differs from snapshot")]
+    fn test_assert_snapshot_mismatch() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("home.html");
+        fs::write(&path, "<p>Home</p>").unwrap();
+        assert_snapshot(&path, "<p>Away</p>");
+    }
+}
@@ -0,0 +1,472 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Theme Module
+//!
+//! This module provides the `Theme` struct, which bundles a set of
+//! templates, default context values, and static assets so that a whole
+//! look-and-feel can be activated on an [`Engine`](crate::Engine) in one
+//! call.
+//!
+//! A theme directory has the following layout:
+//!
+//! ```text
+//! my-theme/
+//! ├── theme.json     (optional) {"name": "...", "defaults": {"key": "value"}}
+//! ├── templates/     layouts; the theme root is used if this is absent
+//! └── static/        (optional) assets copied verbatim into the output
+//! ```
+//!
+//! Themes are layered *under* the site: the engine searches its own
+//! template directories before the theme's, context values override the
+//! theme defaults, and existing output files are never overwritten by
+//! theme assets.
+
+use crate::context::Context;
+use crate::engine::EngineError;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "archive")]
+use std::sync::Arc;
+
+/// The name of the optional theme manifest file.
+pub const THEME_MANIFEST: &str = "theme.json";
+
+/// A packaged set of templates, default context values, and assets.
+///
+/// # Examples
+///
+/// ```no_run
+/// use staticweaver::theme::Theme;
+/// use staticweaver::Engine;
+/// use std::time::Duration;
+///
+/// let theme = Theme::from_dir("themes/minimal").unwrap();
+/// let mut engine = Engine::new("templates", Duration::from_secs(3600));
+/// engine.set_theme(theme);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Theme {
+    name: String,
+    root: PathBuf,
+    templates_dir: PathBuf,
+    static_dir: Option<PathBuf>,
+    defaults: Context,
+    /// Keeps an extracted archive alive for as long as the theme is used.
+    #[cfg(feature = "archive")]
+    _extracted: Option<Arc<tempfile::TempDir>>,
+}
+
+impl Theme {
+    /// Loads a theme from a directory.
+    ///
+    /// The theme name defaults to the directory name unless the manifest
+    /// provides one.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The root directory of the theme.
+    ///
+    /// # Errors
+    ///
+    /// * `EngineError::Io` - If `path` is not a directory, or the
+    ///   manifest cannot be read.
+    /// * `EngineError::InvalidTemplate` - If the manifest is malformed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::theme::Theme;
+    ///
+    /// let dir = tempfile::tempdir().unwrap();
+    /// std::fs::write(dir.path().join("page.html"), "{{title}}").unwrap();
+    ///
+    /// let theme = Theme::from_dir(dir.path()).unwrap();
+    /// assert_eq!(theme.templates_dir(), dir.path());
+    /// ```
+    pub fn from_dir<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<Self, EngineError> {
+        let root = path.as_ref().to_path_buf();
+        if !root.is_dir() {
+            return Err(EngineError::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "Theme directory not found: {}",
+                    root.display()
+                ),
+            )));
+        }
+
+        let mut name = root
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("theme")
+            .to_string();
+        let mut defaults = Context::new();
+
+        let manifest_path = root.join(THEME_MANIFEST);
+        if manifest_path.is_file() {
+            let manifest = fs::read_to_string(&manifest_path)?;
+            let manifest: serde_json::Value =
+                serde_json::from_str(&manifest).map_err(|err| {
+                    EngineError::InvalidTemplate(format!(
+                        "Invalid theme manifest {}: {}",
+                        manifest_path.display(),
+                        err
+                    ))
+                })?;
+            if let Some(manifest_name) =
+                manifest.get("name").and_then(|name| name.as_str())
+            {
+                name = manifest_name.to_string();
+            }
+            if let Some(values) =
+                manifest.get("defaults").and_then(|d| d.as_object())
+            {
+                for (key, value) in values {
+                    let value = match value {
+                        serde_json::Value::String(value) => {
+                            value.clone()
+                        }
+                        other => other.to_string(),
+                    };
+                    defaults.set(key.clone(), value);
+                }
+            }
+        }
+
+        let templates_dir = root.join("templates");
+        let templates_dir = if templates_dir.is_dir() {
+            templates_dir
+        } else {
+            root.clone()
+        };
+        let static_dir =
+            Some(root.join("static")).filter(|d| d.is_dir());
+
+        Ok(Self {
+            name,
+            root,
+            templates_dir,
+            static_dir,
+            defaults,
+            #[cfg(feature = "archive")]
+            _extracted: None,
+        })
+    }
+
+    /// Loads a theme from a `.tar` archive.
+    ///
+    /// The archive is extracted into a temporary directory that is
+    /// removed once the last clone of the theme is dropped. Its contents
+    /// must follow the same layout as a theme directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the archive.
+    ///
+    /// # Errors
+    ///
+    /// * `EngineError::Io` - If the archive cannot be read or extracted.
+    /// * Any error returned by [`Theme::from_dir`].
+    #[cfg(feature = "archive")]
+    pub fn from_archive<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<Self, EngineError> {
+        let extracted = tempfile::tempdir()?;
+        let file = fs::File::open(path.as_ref())?;
+        tar::Archive::new(file).unpack(extracted.path())?;
+
+        let fallback_name = path
+            .as_ref()
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("theme");
+        Self::from_extracted(extracted, fallback_name)
+    }
+
+    /// Loads a theme from an extracted archive, which is removed once
+    /// the last clone of the theme is dropped. The theme is named
+    /// `fallback_name` unless its manifest provides a name.
+    #[cfg(feature = "archive")]
+    pub(crate) fn from_extracted(
+        extracted: tempfile::TempDir,
+        fallback_name: &str,
+    ) -> Result<Self, EngineError> {
+        let mut theme = Self::from_dir(extracted.path())?;
+        if !extracted.path().join(THEME_MANIFEST).is_file() {
+            theme.name = fallback_name.to_string();
+        }
+        theme._extracted = Some(Arc::new(extracted));
+        Ok(theme)
+    }
+
+    /// Returns the theme name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the root directory of the theme.
+    #[must_use]
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the directory holding the theme's templates.
+    #[must_use]
+    pub fn templates_dir(&self) -> &Path {
+        &self.templates_dir
+    }
+
+    /// Returns the directory holding the theme's static assets, if any.
+    #[must_use]
+    pub fn static_dir(&self) -> Option<&Path> {
+        self.static_dir.as_deref()
+    }
+
+    /// Returns the default context values provided by the theme.
+    #[must_use]
+    pub fn defaults(&self) -> &Context {
+        &self.defaults
+    }
+
+    /// Returns a mutable reference to the theme's default context values.
+    pub fn defaults_mut(&mut self) -> &mut Context {
+        &mut self.defaults
+    }
+
+    /// Layers `context` over the theme defaults.
+    ///
+    /// Keys present in `context` win over the theme's defaults.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The site or page context.
+    ///
+    /// # Returns
+    ///
+    /// A new `Context` containing the merged values.
+    #[must_use]
+    pub fn layer(&self, context: &Context) -> Context {
+        let mut merged = self.defaults.clone();
+        merged.merge(context);
+        merged
+    }
+
+    /// Copies the theme's static assets into `dest`.
+    ///
+    /// Files that already exist in `dest` are left untouched, so assets
+    /// written by the site take precedence over the theme's.
+    ///
+    /// # Arguments
+    ///
+    /// * `dest` - The output directory.
+    ///
+    /// # Returns
+    ///
+    /// The number of files copied.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EngineError::Io` if reading or writing a file fails.
+    pub fn copy_static_assets<P: AsRef<Path>>(
+        &self,
+        dest: P,
+    ) -> Result<usize, EngineError> {
+        let mut copied = Vec::new();
+        if let Some(static_dir) = &self.static_dir {
+            copy_dir_missing(
+                static_dir,
+                dest.as_ref(),
+                false,
+                &mut copied,
+            )?;
+        }
+        Ok(copied.len())
+    }
+
+    /// Lists the files [`Theme::copy_static_assets`] would write into
+    /// `dest`, without writing anything.
+    ///
+    /// # Arguments
+    ///
+    /// * `dest` - The output directory.
+    ///
+    /// # Returns
+    ///
+    /// The paths of the files that would be copied.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EngineError::Io` if reading the theme's assets fails.
+    pub fn plan_static_assets<P: AsRef<Path>>(
+        &self,
+        dest: P,
+    ) -> Result<Vec<PathBuf>, EngineError> {
+        let mut planned = Vec::new();
+        if let Some(static_dir) = &self.static_dir {
+            copy_dir_missing(
+                static_dir,
+                dest.as_ref(),
+                true,
+                &mut planned,
+            )?;
+        }
+        Ok(planned)
+    }
+}
+
+/// Recursively copies files from `src` to `dest`, skipping existing ones,
+/// and records the paths written. Nothing is written if `dry_run` is set.
+fn copy_dir_missing(
+    src: &Path,
+    dest: &Path,
+    dry_run: bool,
+    written: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    if !dry_run {
+        fs::create_dir_all(dest)?;
+    }
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_missing(&entry.path(), &target, dry_run, written)?;
+        } else if !target.exists() {
+            if !dry_run {
+                let _ = fs::copy(entry.path(), &target)?;
+            }
+            written.push(target);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_theme_dir() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("templates")).unwrap();
+        fs::create_dir_all(dir.path().join("static/css")).unwrap();
+        fs::write(dir.path().join("templates/page.html"), "{{title}}")
+            .unwrap();
+        fs::write(dir.path().join("static/css/site.css"), "body {}")
+            .unwrap();
+        fs::write(
+            dir.path().join(THEME_MANIFEST),
+            r#"{"name": "minimal", "defaults": {"title": "Untitled", "columns": 2}}"#,
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_from_dir() {
+        let dir = create_theme_dir();
+        let theme = Theme::from_dir(dir.path()).unwrap();
+
+        assert_eq!(theme.name(), "minimal");
+        assert_eq!(theme.templates_dir(), dir.path().join("templates"));
+        assert_eq!(
+            theme.static_dir(),
+            Some(dir.path().join("static").as_path())
+        );
+        assert_eq!(
+            theme.defaults().get("title"),
+            Some(&"Untitled".to_string())
+        );
+        assert_eq!(
+            theme.defaults().get("columns"),
+            Some(&"2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_from_dir_missing() {
+        let result = Theme::from_dir("nonexistent/theme");
+        assert!(matches!(
+            result,
+            Err(EngineError::Io(err)) if err.kind() == io::ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn test_from_dir_invalid_manifest() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join(THEME_MANIFEST), "{").unwrap();
+        let result = Theme::from_dir(dir.path());
+        assert!(matches!(result, Err(EngineError::InvalidTemplate(_))));
+    }
+
+    #[test]
+    fn test_layer() {
+        let dir = create_theme_dir();
+        let theme = Theme::from_dir(dir.path()).unwrap();
+
+        let mut context = Context::new();
+        context.set("title", "Home");
+        context.set_lazy("lazy", || "value".to_string());
+        let merged = theme.layer(&context);
+
+        assert_eq!(merged.get("title"), Some(&"Home".to_string()));
+        assert_eq!(merged.get("columns"), Some(&"2".to_string()));
+        assert_eq!(merged.resolve("lazy").as_deref(), Some("value"));
+    }
+
+    #[test]
+    fn test_copy_static_assets_keeps_existing() {
+        let dir = create_theme_dir();
+        let theme = Theme::from_dir(dir.path()).unwrap();
+        let out = TempDir::new().unwrap();
+
+        assert_eq!(theme.copy_static_assets(out.path()).unwrap(), 1);
+        fs::write(out.path().join("css/site.css"), "site").unwrap();
+        assert_eq!(theme.copy_static_assets(out.path()).unwrap(), 0);
+        assert_eq!(
+            fs::read_to_string(out.path().join("css/site.css"))
+                .unwrap(),
+            "site"
+        );
+    }
+
+    #[test]
+    fn test_plan_static_assets() {
+        let dir = create_theme_dir();
+        let theme = Theme::from_dir(dir.path()).unwrap();
+        let out = TempDir::new().unwrap();
+
+        let planned = theme.plan_static_assets(out.path()).unwrap();
+        assert_eq!(planned, [out.path().join("css/site.css")]);
+        assert!(!out.path().join("css").exists());
+
+        assert_eq!(theme.copy_static_assets(out.path()).unwrap(), 1);
+        assert!(theme
+            .plan_static_assets(out.path())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[cfg(feature = "archive")]
+    #[test]
+    fn test_from_archive() {
+        let dir = create_theme_dir();
+        let archive_dir = TempDir::new().unwrap();
+        let archive_path = archive_dir.path().join("minimal.tar");
+
+        let mut builder =
+            tar::Builder::new(fs::File::create(&archive_path).unwrap());
+        builder.append_dir_all(".", dir.path()).unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        let theme = Theme::from_archive(&archive_path).unwrap();
+        assert_eq!(theme.name(), "minimal");
+        assert!(theme.templates_dir().join("page.html").is_file());
+    }
+}
@@ -0,0 +1,411 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Table of Contents Module
+//!
+//! This module assigns stable `id` anchors to the headings of rendered
+//! HTML content and builds a table of contents from them.
+//!
+//! [`add_toc`] anchors the headings of a page's content in its context
+//! and exposes the table of contents to the layout:
+//!
+//! ```text
+//! {{page.toc}}              nested <ul> of links to the headings
+//! {{page.toc.len}}          number of headings
+//! {{page.toc.0.level}}      level of the first heading, 1 to 6
+//! {{page.toc.0.id}}         its anchor
+//! {{page.toc.0.title}}      its text
+//! ```
+//!
+//! Anchors only depend on the content, and both are stored in the
+//! context before rendering, so the render cache always returns a page
+//! whose anchors match its table of contents.
+
+use crate::context::Context;
+use crate::filter::slugify;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// The context key of the rendered table of contents.
+pub const TOC_KEY: &str = "page.toc";
+
+/// A heading listed in a table of contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    /// The heading level, from 1 for `<h1>` to 6 for `<h6>`.
+    pub level: u8,
+    /// The `id` anchor of the heading.
+    pub id: String,
+    /// The text of the heading, without markup.
+    pub title: String,
+}
+
+/// The headings of a page, in document order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Toc {
+    /// The headings.
+    pub headings: Vec<Heading>,
+}
+
+impl Toc {
+    /// Renders the table of contents as nested `<ul>` lists of links.
+    ///
+    /// Returns an empty string if there are no headings.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::toc::anchor_headings;
+    ///
+    /// let (_, toc) = anchor_headings("<h2>Intro</h2><h3>Setup</h3>");
+    /// assert_eq!(
+    ///     toc.to_html(),
+    ///     "<ul><li><a href=\"#intro\">Intro</a>\
+    ///      <ul><li><a href=\"#setup\">Setup</a></li></ul></li></ul>"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        let mut open: Vec<u8> = Vec::new();
+        for heading in &self.headings {
+            while open.len() > 1
+                && open
+                    .last()
+                    .map_or(false, |&level| heading.level < level)
+            {
+                let _ = open.pop();
+                html.push_str("</li></ul>");
+            }
+            match open.last() {
+                Some(&level) if heading.level <= level => {
+                    html.push_str("</li><li>");
+                }
+                _ => {
+                    html.push_str("<ul><li>");
+                    open.push(heading.level);
+                }
+            }
+            let _ = write!(
+                html,
+                "<a href=\"#{}\">{}</a>",
+                heading.id, heading.title
+            );
+        }
+        for _ in open {
+            html.push_str("</li></ul>");
+        }
+        html
+    }
+
+    /// Stores the table of contents in `context` under the
+    /// [`TOC_KEY`] keys described in the [module documentation](self).
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The context of the page.
+    pub fn insert_into(&self, context: &mut Context) {
+        context.set(TOC_KEY, self.to_html());
+        context.set(
+            format!("{}.len", TOC_KEY),
+            self.headings.len().to_string(),
+        );
+        for (index, heading) in self.headings.iter().enumerate() {
+            let prefix = format!("{}.{}", TOC_KEY, index);
+            context.set(
+                format!("{}.level", prefix),
+                heading.level.to_string(),
+            );
+            context.set(format!("{}.id", prefix), heading.id.as_str());
+            context.set(
+                format!("{}.title", prefix),
+                heading.title.as_str(),
+            );
+        }
+    }
+}
+
+/// Adds an `id` anchor to every heading of `html` without one, and
+/// returns the anchored HTML with its table of contents.
+///
+/// Anchors are slugs of the heading text, as built by the `slugify`
+/// filter; repeated slugs are numbered `-1`, `-2`, and so on. Existing
+/// `id` attributes are kept and listed as they are.
+///
+/// # Arguments
+///
+/// * `html` - The HTML content.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::toc::anchor_headings;
+///
+/// let (html, toc) = anchor_headings("<h2>Intro</h2><h2>Intro</h2>");
+/// assert_eq!(html, "<h2 id=\"intro\">Intro</h2><h2 id=\"intro-1\">Intro</h2>");
+/// assert_eq!(toc.headings[1].id, "intro-1");
+/// ```
+#[must_use]
+pub fn anchor_headings(html: &str) -> (String, Toc) {
+    let mut out = String::with_capacity(html.len());
+    let mut toc = Toc::default();
+    let mut used = HashSet::new();
+    let mut rest = html;
+    while let Some((start, level)) = find_heading(rest) {
+        let open_end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let close = format!("</h{}", level);
+        let Some(close_start) =
+            find_ignore_case(&rest[open_end..], &close)
+                .map(|offset| open_end + offset)
+        else {
+            break;
+        };
+
+        let attrs = &rest[start + 3..open_end];
+        let inner = &rest[open_end + 1..close_start];
+        let title = strip_tags(inner);
+        let id = match attribute(attrs, "id") {
+            Some(id) => {
+                let _ = used.insert(id.to_string());
+                out.push_str(&rest[..close_start]);
+                id.to_string()
+            }
+            None => {
+                let id = unique_id(&decode_entities(&title), &mut used);
+                out.push_str(&rest[..open_end]);
+                let _ = write!(out, " id=\"{}\">", id);
+                out.push_str(inner);
+                id
+            }
+        };
+        toc.headings.push(Heading { level, id, title });
+        rest = &rest[close_start..];
+    }
+    out.push_str(rest);
+    (out, toc)
+}
+
+/// Anchors the headings of the HTML stored under `key` in `context`, and
+/// stores its table of contents under the [`TOC_KEY`] keys.
+///
+/// Does nothing but store an empty table of contents if `key` is not in
+/// the context.
+///
+/// # Arguments
+///
+/// * `context` - The context of the page.
+/// * `key` - The key of the page content, e.g. `content`.
+///
+/// # Returns
+///
+/// The table of contents.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::toc::add_toc;
+/// use staticweaver::Context;
+///
+/// let mut context = Context::new();
+/// context.set("content", "<h2>Intro</h2>");
+/// let toc = add_toc(&mut context, "content");
+/// assert_eq!(toc.headings.len(), 1);
+/// assert_eq!(context.get("content").unwrap(), "<h2 id=\"intro\">Intro</h2>");
+/// assert_eq!(context.get("page.toc.0.id").unwrap(), "intro");
+/// ```
+pub fn add_toc(context: &mut Context, key: &str) -> Toc {
+    let toc = match context.get(key) {
+        Some(html) => {
+            let (html, toc) = anchor_headings(html);
+            context.set(key, html);
+            toc
+        }
+        None => Toc::default(),
+    };
+    toc.insert_into(context);
+    toc
+}
+
+/// Finds the next `<h1>` to `<h6>` start tag, returning its offset and
+/// level.
+fn find_heading(html: &str) -> Option<(usize, u8)> {
+    let bytes = html.as_bytes();
+    let mut from = 0;
+    while let Some(offset) = html[from..].find('<') {
+        let start = from + offset;
+        let tag = &bytes[start + 1..];
+        if tag.len() >= 2
+            && tag[0].eq_ignore_ascii_case(&b'h')
+            && (b'1'..=b'6').contains(&tag[1])
+            && tag.get(2).map_or(false, |&c| {
+                c == b'>' || c.is_ascii_whitespace()
+            })
+        {
+            return Some((start, tag[1] - b'0'));
+        }
+        from = start + 1;
+    }
+    None
+}
+
+/// Finds `needle`, an ASCII string, in `haystack`, ignoring case.
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| {
+            window.eq_ignore_ascii_case(needle.as_bytes())
+        })
+}
+
+/// Returns the value of the attribute `name` in the attributes of a
+/// start tag.
+fn attribute<'a>(attrs: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = attrs;
+    while let Some(offset) = find_ignore_case(rest, name) {
+        let preceded = rest[..offset]
+            .chars()
+            .next_back()
+            .map_or(true, char::is_whitespace);
+        let after = rest[offset + name.len()..].trim_start();
+        if preceded {
+            if let Some(value) = after.strip_prefix('=') {
+                let value = value.trim_start();
+                for quote in ['"', '\''] {
+                    if let Some(value) = value.strip_prefix(quote) {
+                        return value
+                            .find(quote)
+                            .map(|end| &value[..end]);
+                    }
+                }
+                let end = value
+                    .find(|c: char| c.is_whitespace() || c == '>')
+                    .unwrap_or(value.len());
+                return Some(&value[..end]);
+            }
+        }
+        rest = &rest[offset + name.len()..];
+    }
+    None
+}
+
+/// Removes the tags from an HTML fragment and trims the text.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.trim().to_string()
+}
+
+/// Decodes the entities escaped by the HTML escaper.
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Returns the slug of `title`, numbered if it is already `used`.
+fn unique_id(title: &str, used: &mut HashSet<String>) -> String {
+    let mut slug = slugify(title);
+    if slug.is_empty() {
+        slug = "section".to_string();
+    }
+    let mut id = slug.clone();
+    let mut n = 0;
+    while used.contains(&id) {
+        n += 1;
+        id = format!("{}-{}", slug, n);
+    }
+    let _ = used.insert(id.clone());
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchor_headings() {
+        let (html, toc) = anchor_headings(
+            "<H1 class=\"x\">Fish &amp; <em>Chips</em></H1>\
+             <p>text</p><h2 id='kept'>Kept</h2><h2>kept</h2>\
+             <h7>No</h7><header>No</header><h3>!!</h3>",
+        );
+        assert_eq!(
+            html,
+            "<H1 class=\"x\" id=\"fish-chips\">Fish &amp; <em>Chips</em></H1>\
+             <p>text</p><h2 id='kept'>Kept</h2><h2 id=\"kept-1\">kept</h2>\
+             <h7>No</h7><header>No</header><h3 id=\"section\">!!</h3>"
+        );
+        let ids: Vec<_> = toc
+            .headings
+            .iter()
+            .map(|h| (h.level, h.id.as_str()))
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                (1, "fish-chips"),
+                (2, "kept"),
+                (2, "kept-1"),
+                (3, "section")
+            ]
+        );
+        assert_eq!(toc.headings[0].title, "Fish &amp; Chips");
+
+        let (html, toc) = anchor_headings("<h2>Unclosed");
+        assert_eq!(html, "<h2>Unclosed");
+        assert!(toc.headings.is_empty());
+    }
+
+    #[test]
+    fn test_to_html() {
+        let heading = |level, id: &str| Heading {
+            level,
+            id: id.to_string(),
+            title: id.to_uppercase(),
+        };
+        let toc = Toc {
+            headings: vec![
+                heading(2, "a"),
+                heading(4, "b"),
+                heading(3, "c"),
+                heading(1, "d"),
+            ],
+        };
+        assert_eq!(
+            toc.to_html(),
+            "<ul><li><a href=\"#a\">A</a>\
+             <ul><li><a href=\"#b\">B</a></li></ul>\
+             <ul><li><a href=\"#c\">C</a></li></ul>\
+             </li><li><a href=\"#d\">D</a></li></ul>"
+        );
+        assert_eq!(Toc::default().to_html(), "");
+    }
+
+    #[test]
+    fn test_add_toc() {
+        let mut context = Context::new();
+        let toc = add_toc(&mut context, "content");
+        assert!(toc.headings.is_empty());
+        assert_eq!(context.get("page.toc.len").unwrap(), "0");
+
+        context.set("content", "<h2>A</h2><h3>B</h3>");
+        let _ = add_toc(&mut context, "content");
+        assert_eq!(context.get("page.toc.len").unwrap(), "2");
+        assert_eq!(context.get("page.toc.1.level").unwrap(), "3");
+        assert_eq!(context.get("page.toc.1.title").unwrap(), "B");
+    }
+}
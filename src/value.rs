@@ -0,0 +1,215 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Value Module
+//!
+//! This module provides the `ToContextValue` trait, which converts Rust
+//! values into the strings stored in a [`Context`](crate::Context).
+//!
+//! Implementations are provided for strings, characters, booleans, all
+//! primitive numbers, `Option`, slices, `Vec`, and `HashMap`/`BTreeMap`.
+//! With the `chrono` feature, dates and times are supported as well.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+
+/// Converts a value into the string stored in a [`Context`](crate::Context).
+///
+/// # Formatting
+///
+/// - `None` becomes the empty string and `Some(value)` formats `value`.
+/// - Sequences are joined with `", "`.
+/// - Maps are written as `key: value` pairs, sorted by key and joined
+///   with `", "`, so the output does not depend on hash order.
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::value::ToContextValue;
+///
+/// assert_eq!(42.to_context_value(), "42");
+/// assert_eq!(true.to_context_value(), "true");
+/// assert_eq!(None::<u8>.to_context_value(), "");
+/// assert_eq!(vec!["rust", "web"].to_context_value(), "rust, web");
+/// ```
+pub trait ToContextValue {
+    /// Returns the string representation of the value.
+    fn to_context_value(&self) -> String;
+}
+
+impl ToContextValue for str {
+    fn to_context_value(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ToContextValue for String {
+    fn to_context_value(&self) -> String {
+        self.clone()
+    }
+}
+
+impl ToContextValue for Cow<'_, str> {
+    fn to_context_value(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl<T: ToContextValue + ?Sized> ToContextValue for &T {
+    fn to_context_value(&self) -> String {
+        (**self).to_context_value()
+    }
+}
+
+/// Implements `ToContextValue` through `Display` for each listed type.
+macro_rules! impl_display {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl ToContextValue for $ty {
+                fn to_context_value(&self) -> String {
+                    self.to_string()
+                }
+            }
+        )*
+    };
+}
+
+impl_display!(
+    char, bool, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64,
+    u128, usize, f32, f64,
+);
+
+impl<T: ToContextValue> ToContextValue for Option<T> {
+    fn to_context_value(&self) -> String {
+        self.as_ref()
+            .map(ToContextValue::to_context_value)
+            .unwrap_or_default()
+    }
+}
+
+impl<T: ToContextValue> ToContextValue for [T] {
+    fn to_context_value(&self) -> String {
+        self.iter()
+            .map(ToContextValue::to_context_value)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl<T: ToContextValue> ToContextValue for Vec<T> {
+    fn to_context_value(&self) -> String {
+        self.as_slice().to_context_value()
+    }
+}
+
+impl<K, V> ToContextValue for BTreeMap<K, V>
+where
+    K: ToContextValue,
+    V: ToContextValue,
+{
+    fn to_context_value(&self) -> String {
+        join_pairs(self.iter())
+    }
+}
+
+impl<K, V, S> ToContextValue for HashMap<K, V, S>
+where
+    K: ToContextValue,
+    V: ToContextValue,
+    S: BuildHasher,
+{
+    fn to_context_value(&self) -> String {
+        join_pairs(self.iter())
+    }
+}
+
+/// Formats map entries as sorted `key: value` pairs joined by `", "`.
+fn join_pairs<'a, K, V>(
+    entries: impl Iterator<Item = (&'a K, &'a V)>,
+) -> String
+where
+    K: ToContextValue + 'a,
+    V: ToContextValue + 'a,
+{
+    let mut pairs: Vec<(String, String)> = entries
+        .map(|(key, value)| {
+            (key.to_context_value(), value.to_context_value())
+        })
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(key, value)| format!("{}: {}", key, value))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Dates are written as `YYYY-MM-DD`.
+#[cfg(feature = "chrono")]
+impl ToContextValue for chrono::NaiveDate {
+    fn to_context_value(&self) -> String {
+        self.format("%Y-%m-%d").to_string()
+    }
+}
+
+/// Naive date-times are written as `YYYY-MM-DDTHH:MM:SS`.
+#[cfg(feature = "chrono")]
+impl ToContextValue for chrono::NaiveDateTime {
+    fn to_context_value(&self) -> String {
+        self.format("%Y-%m-%dT%H:%M:%S").to_string()
+    }
+}
+
+/// Time-zone-aware date-times are written in RFC 3339 format.
+#[cfg(feature = "chrono")]
+impl<Tz> ToContextValue for chrono::DateTime<Tz>
+where
+    Tz: chrono::TimeZone,
+    Tz::Offset: std::fmt::Display,
+{
+    fn to_context_value(&self) -> String {
+        self.to_rfc3339()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_primitives() {
+        assert_eq!('x'.to_context_value(), "x");
+        assert_eq!((-7i64).to_context_value(), "-7");
+        assert_eq!(1.5f64.to_context_value(), "1.5");
+        assert_eq!("text".to_context_value(), "text");
+        assert_eq!(Some("set").to_context_value(), "set");
+    }
+
+    #[test]
+    fn test_maps_are_sorted() {
+        let mut map = HashMap::new();
+        let _ = map.insert("b", 2);
+        let _ = map.insert("a", 1);
+        assert_eq!(map.to_context_value(), "a: 1, b: 2");
+
+        let tree: BTreeMap<String, Vec<u8>> =
+            [("k".to_string(), vec![1, 2])].into_iter().collect();
+        assert_eq!(tree.to_context_value(), "k: 1, 2");
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono_values() {
+        use chrono::{NaiveDate, TimeZone, Utc};
+
+        let date = NaiveDate::from_ymd_opt(2024, 3, 9).unwrap();
+        assert_eq!(date.to_context_value(), "2024-03-09");
+        let datetime =
+            Utc.with_ymd_and_hms(2024, 3, 9, 8, 30, 0).unwrap();
+        assert_eq!(
+            datetime.to_context_value(),
+            "2024-03-09T08:30:00+00:00"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Warm Module
+//!
+//! This module provides the `WarmReport` of warming the render cache.
+//! The [manifest](crate::manifest) of a build records the context hash
+//! of each page it writes, and is saved next to its
+//! [disk cache](crate::disk_cache).
+//!
+//! The next build passes the saved manifest to
+//! [`SiteBuilder::warm`](crate::site::SiteBuilder::warm) before it
+//! starts, which loads the pages listed from the disk cache into the
+//! in-memory render cache, reading files in parallel. The first pages of
+//! the build are then served from memory instead of waiting on the disk
+//! one by one.
+//!
+//! ```
+//! use staticweaver::disk_cache::DiskCache;
+//! use staticweaver::loader::MemoryLoader;
+//! use staticweaver::manifest::BuildManifest;
+//! use staticweaver::shared::SharedEngine;
+//! use staticweaver::site::{SiteBuilder, SitePage};
+//! use staticweaver::{Context, Engine};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let (cache, out) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+//! let loader = MemoryLoader::new();
+//! loader.insert("templates/page.html", "<h1>{{title}}</h1>");
+//! let mut engine = Engine::new("templates", Duration::from_secs(60));
+//! engine.set_loader(Arc::new(loader));
+//! engine.set_disk_cache(Some(DiskCache::new(cache.path())));
+//! let engine = Arc::new(SharedEngine::new(engine));
+//! let builder = SiteBuilder::new(engine.clone(), out.path());
+//!
+//! // The previous build.
+//! let mut context = Context::new();
+//! context.set("title", "Home");
+//! let report = builder.build(vec![SitePage::new("index.html", "page", context)]);
+//! let saved = report.manifest.to_json();
+//!
+//! // The next build.
+//! engine.write().clear_cache();
+//! let report = builder.warm(&BuildManifest::from_json(&saved).unwrap());
+//! assert_eq!(report.loaded, 1);
+//! assert_eq!(engine.read().render_cache().len(), 1);
+//! ```
+
+/// The maximum number of threads reading pages in
+/// [`Engine::warm_cache`](crate::Engine::warm_cache).
+pub const WARM_THREADS: usize = 8;
+
+/// The outcome of [`Engine::warm_cache`](crate::Engine::warm_cache).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WarmReport {
+    /// The number of pages loaded into the render cache, or found there
+    /// already.
+    pub loaded: usize,
+    /// The paths of the pages that were not found in the disk cache,
+    /// such as pages whose layout changed since the previous build.
+    pub missed: Vec<String>,
+}
@@ -0,0 +1,75 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Wasm Module
+//!
+//! This module exposes template rendering to JavaScript through
+//! `wasm-bindgen`, for in-browser template previews.
+//!
+//! Build for `wasm32-unknown-unknown` with default features disabled,
+//! since downloading templates needs a blocking HTTP client:
+//!
+//! ```text
+//! cargo build --target wasm32-unknown-unknown --no-default-features --features wasm
+//! ```
+//!
+//! From JavaScript, call `renderTemplate(template, contextJson)`:
+//!
+//! ```text
+//! renderTemplate("Hello, {{name}}!", JSON.stringify({ name: "World" }));
+//! ```
+
+use crate::context::Context;
+use crate::engine::Engine;
+use std::time::Duration;
+use wasm_bindgen::prelude::*;
+
+/// Renders `template` with the values of the JSON object `context`.
+///
+/// The context is read with [`Context::from_json_str`].
+///
+/// # Errors
+///
+/// Throws an `Error` if `context` is not a JSON object or the template
+/// cannot be rendered.
+#[wasm_bindgen(js_name = renderTemplate)]
+pub fn render_template(
+    template: &str,
+    context: &str,
+) -> Result<String, JsError> {
+    render_json(template, context).map_err(|err| JsError::new(&err))
+}
+
+/// Renders `template` with a JSON object context, reporting errors as
+/// messages.
+fn render_json(
+    template: &str,
+    context: &str,
+) -> Result<String, String> {
+    let context = Context::from_json_str(context)
+        .map_err(|err| err.to_string())?;
+    Engine::new("", Duration::from_secs(60))
+        .render_template(template, &context)
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_json() {
+        assert_eq!(
+            render_json(
+                "{{name}} is {{age}}",
+                r#"{"name": "Ada", "age": 36}"#
+            )
+            .unwrap(),
+            "Ada is 36"
+        );
+        assert!(render_json("{{name}}", "[]").is_err());
+        assert!(render_json("{{name}}", "{}")
+            .unwrap_err()
+            .contains("Unresolved template tag"));
+    }
+}
@@ -0,0 +1,445 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! # Web Module
+//!
+//! This module turns rendered pages and render errors into HTTP
+//! responses, for servers that embed the engine to preview a site or
+//! serve low-traffic pages.
+//!
+//! [`RenderedPage`] carries a page together with its content type, and
+//! [`WebError`] maps an [`EngineError`] to an HTTP status code. With the
+//! `axum` feature both implement axum's `IntoResponse`; with the `actix`
+//! feature `RenderedPage` implements actix-web's `Responder` and
+//! `WebError` its `ResponseError`.
+//!
+//! Pages rendered by [`render`] and [`render_shared`] carry a strong
+//! `ETag`, the content hash of the page, which the engine computes once
+//! per cached page. actix-web responses answer a matching
+//! `If-None-Match` request header with `304 Not Modified`; axum handlers
+//! can do the same with [`RenderedPage::is_not_modified`].
+
+use crate::context::Context;
+use crate::engine::{Engine, EngineError, RenderOptions};
+use crate::escape::OutputFormat;
+use crate::shared::SharedEngine;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+/// A rendered page and the format that determines its content type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedPage {
+    body: Arc<str>,
+    format: OutputFormat,
+    etag: Option<String>,
+}
+
+impl RenderedPage {
+    /// Creates a new `RenderedPage`.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - The rendered page.
+    /// * `format` - The format of the page.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::escape::OutputFormat;
+    /// use staticweaver::web::RenderedPage;
+    ///
+    /// let page = RenderedPage::new("<h1>Hi</h1>", OutputFormat::Html);
+    /// assert_eq!(page.content_type(), "text/html; charset=utf-8");
+    /// ```
+    #[must_use]
+    pub fn new<B: Into<Arc<str>>>(
+        body: B,
+        format: OutputFormat,
+    ) -> Self {
+        Self {
+            body: body.into(),
+            format,
+            etag: None,
+        }
+    }
+
+    /// Sets the value of the page's `ETag` header, a quoted entity tag
+    /// such as the one returned by
+    /// [`Rendered::etag`](crate::engine::Rendered::etag).
+    ///
+    /// # Arguments
+    ///
+    /// * `etag` - The entity tag.
+    #[must_use]
+    pub fn with_etag(mut self, etag: impl Into<String>) -> Self {
+        self.etag = Some(etag.into());
+        self
+    }
+
+    /// Returns the rendered page.
+    #[must_use]
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    /// Returns the format of the page.
+    #[must_use]
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Returns the value of the page's `Content-Type` header.
+    #[must_use]
+    pub fn content_type(&self) -> &'static str {
+        self.format.content_type()
+    }
+
+    /// Returns the value of the page's `ETag` header, if it has one.
+    #[must_use]
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
+    /// Returns whether a request with the `If-None-Match` header
+    /// `if_none_match` already has this page, and should be answered
+    /// with `304 Not Modified`.
+    ///
+    /// Entity tags are compared weakly, as HTTP requires for
+    /// `If-None-Match`. A page without an `ETag` is never fresh.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::escape::OutputFormat;
+    /// use staticweaver::web::RenderedPage;
+    ///
+    /// let page = RenderedPage::new("<h1>Hi</h1>", OutputFormat::Html)
+    ///     .with_etag("\"abc\"");
+    /// assert!(page.is_not_modified("\"xyz\", W/\"abc\""));
+    /// assert!(page.is_not_modified("*"));
+    /// assert!(!page.is_not_modified("\"xyz\""));
+    /// ```
+    #[must_use]
+    pub fn is_not_modified(&self, if_none_match: &str) -> bool {
+        let Some(etag) = &self.etag else {
+            return false;
+        };
+        let etag = etag.strip_prefix("W/").unwrap_or(etag);
+        if_none_match.split(',').map(str::trim).any(|tag| {
+            tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag
+        })
+    }
+}
+
+/// An [`EngineError`] to be reported as an HTTP error response.
+///
+/// Missing layouts and invalid layout names map to `404 Not Found`, so
+/// that probing for files outside the template directories reveals
+/// nothing, pages vetoed by a [hook](crate::hooks) to `403 Forbidden`,
+/// failed remote requests to
+/// `502 Bad Gateway`, and every other error to
+/// `500 Internal Server Error`. The response body is the error message.
+#[derive(Debug)]
+pub struct WebError(pub EngineError);
+
+impl WebError {
+    /// Returns the HTTP status code of the error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use staticweaver::engine::EngineError;
+    /// use staticweaver::web::WebError;
+    ///
+    /// let err = WebError(EngineError::Render("oops".to_string()));
+    /// assert_eq!(err.status(), 500);
+    /// ```
+    #[must_use]
+    pub fn status(&self) -> u16 {
+        match self.0.root() {
+            EngineError::Io(err)
+                if err.kind() == io::ErrorKind::NotFound =>
+            {
+                404
+            }
+            EngineError::InvalidTemplateName { .. } => 404,
+            EngineError::Vetoed { .. } => 403,
+            EngineError::Timeout { .. } => 503,
+            #[cfg(feature = "remote")]
+            EngineError::Reqwest(_) => 502,
+            _ => 500,
+        }
+    }
+}
+
+impl fmt::Display for WebError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for WebError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+impl From<EngineError> for WebError {
+    fn from(err: EngineError) -> Self {
+        Self(err)
+    }
+}
+
+/// Renders a page for an HTTP response.
+///
+/// The content type follows the engine's `output_format` if set, and the
+/// extension of the layout file otherwise.
+///
+/// # Arguments
+///
+/// * `engine` - The engine to render with.
+/// * `context` - The rendering context.
+/// * `layout` - The layout file to use for rendering.
+///
+/// # Errors
+///
+/// Returns a [`WebError`] wrapping the errors of [`Engine::render_page`].
+///
+/// # Examples
+///
+/// ```
+/// use staticweaver::web::render;
+/// use staticweaver::{Context, Engine};
+/// use std::time::Duration;
+///
+/// let engine = Engine::new("templates", Duration::from_secs(3600));
+/// let err = render(&engine, &Context::new(), "missing").unwrap_err();
+/// assert_eq!(err.status(), 404);
+/// ```
+pub fn render(
+    engine: &Engine,
+    context: &Context,
+    layout: &str,
+) -> Result<RenderedPage, WebError> {
+    let page = engine.render_page_detailed(
+        &RenderOptions::new(),
+        context,
+        layout,
+    )?;
+    Ok(RenderedPage::new(
+        page.output.clone(),
+        response_format(engine, layout),
+    )
+    .with_etag(page.etag()))
+}
+
+/// Renders a page for an HTTP response with a [`SharedEngine`], like
+/// [`render`].
+///
+/// # Arguments
+///
+/// * `engine` - The shared engine to render with.
+/// * `context` - The rendering context.
+/// * `layout` - The layout file to use for rendering.
+///
+/// # Errors
+///
+/// Returns a [`WebError`] wrapping the errors of [`Engine::render_page`].
+pub fn render_shared(
+    engine: &SharedEngine,
+    context: &Context,
+    layout: &str,
+) -> Result<RenderedPage, WebError> {
+    let body = engine.render_page_shared(context, layout)?;
+    let engine = engine.read();
+    let (_, cache_key) =
+        engine.page_key(context, layout, &RenderOptions::new());
+    let etag = format!("\"{}\"", engine.content_hash(cache_key, &body));
+    Ok(RenderedPage::new(body, response_format(&engine, layout))
+        .with_etag(etag))
+}
+
+/// Returns the format that determines the content type of a page.
+fn response_format(engine: &Engine, layout: &str) -> OutputFormat {
+    engine.output_format.unwrap_or_else(|| {
+        engine
+            .resolve_template(layout)
+            .map_or(OutputFormat::Plain, |path| {
+                OutputFormat::from_path(&path)
+            })
+    })
+}
+
+/// axum support, enabled by the `axum` feature.
+#[cfg(feature = "axum")]
+mod axum_impl {
+    use super::{RenderedPage, WebError};
+    use axum_core::response::{IntoResponse, Response};
+    use http::header::{CONTENT_TYPE, ETAG};
+    use http::{HeaderValue, StatusCode};
+
+    impl IntoResponse for RenderedPage {
+        fn into_response(self) -> Response {
+            let mut response = (
+                [(CONTENT_TYPE, self.content_type())],
+                self.body.to_string(),
+            )
+                .into_response();
+            if let Some(etag) = self
+                .etag
+                .and_then(|etag| HeaderValue::from_str(&etag).ok())
+            {
+                let _ = response.headers_mut().insert(ETAG, etag);
+            }
+            response
+        }
+    }
+
+    impl IntoResponse for WebError {
+        fn into_response(self) -> Response {
+            let status = StatusCode::from_u16(self.status())
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            (status, self.to_string()).into_response()
+        }
+    }
+}
+
+/// actix-web support, enabled by the `actix` feature.
+#[cfg(feature = "actix")]
+mod actix_impl {
+    use super::{RenderedPage, WebError};
+    use actix_web::body::BoxBody;
+    use actix_web::http::header::{ETAG, IF_NONE_MATCH};
+    use actix_web::http::StatusCode;
+    use actix_web::{
+        HttpRequest, HttpResponse, Responder, ResponseError,
+    };
+
+    impl Responder for RenderedPage {
+        type Body = BoxBody;
+
+        fn respond_to(self, req: &HttpRequest) -> HttpResponse {
+            let not_modified = req
+                .headers()
+                .get(IF_NONE_MATCH)
+                .and_then(|value| value.to_str().ok())
+                .map_or(false, |value| self.is_not_modified(value));
+            let mut response = if not_modified {
+                HttpResponse::NotModified()
+            } else {
+                HttpResponse::Ok()
+            };
+            let response = match &self.etag {
+                Some(etag) => {
+                    response.insert_header((ETAG, etag.as_str()))
+                }
+                None => &mut response,
+            };
+            if not_modified {
+                return response.finish();
+            }
+            response
+                .content_type(self.content_type())
+                .body(self.body.to_string())
+        }
+    }
+
+    impl ResponseError for WebError {
+        fn status_code(&self) -> StatusCode {
+            StatusCode::from_u16(self.status())
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_render_content_type() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("feed.xml"),
+            "<title>{{title}}</title>",
+        )
+        .unwrap();
+        let engine = Engine::new(
+            dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+        let mut context = Context::new();
+        context.set("title", "News");
+
+        let page = render(&engine, &context, "feed.xml").unwrap();
+        assert_eq!(page.body(), "<title>News</title>");
+        assert_eq!(
+            page.content_type(),
+            "application/xml; charset=utf-8"
+        );
+
+        let etag = format!(
+            "\"{}\"",
+            crate::integrity::sha256_hex(b"<title>News</title>")
+        );
+        assert_eq!(page.etag(), Some(etag.as_str()));
+        assert!(page.is_not_modified(&format!("W/{}", etag)));
+
+        let shared = SharedEngine::new(engine);
+        let page =
+            render_shared(&shared, &context, "feed.xml").unwrap();
+        assert_eq!(page.format(), OutputFormat::Xml);
+        assert_eq!(page.etag(), Some(etag.as_str()));
+        assert!(!RenderedPage::new("", OutputFormat::Html)
+            .is_not_modified("*"));
+    }
+
+    #[test]
+    fn test_error_status() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("page.html"), "{{missing}}").unwrap();
+        let engine = Engine::new(
+            dir.path().to_str().unwrap(),
+            Duration::from_secs(60),
+        );
+
+        let err = render(&engine, &Context::new(), "nope").unwrap_err();
+        assert_eq!(err.status(), 404);
+        let err = render(&engine, &Context::new(), "page").unwrap_err();
+        assert_eq!(err.status(), 500);
+    }
+
+    #[cfg(feature = "axum")]
+    #[test]
+    fn test_axum_responses() {
+        use axum_core::response::IntoResponse;
+
+        let response =
+            RenderedPage::new("{}", OutputFormat::Json).into_response();
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/json"
+        );
+
+        let err = WebError(EngineError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "missing",
+        )));
+        assert_eq!(err.into_response().status(), 404);
+    }
+
+    #[cfg(feature = "actix")]
+    #[test]
+    fn test_actix_responses() {
+        use actix_web::ResponseError;
+
+        let err = WebError(EngineError::Render("oops".to_string()));
+        assert_eq!(err.status_code().as_u16(), 500);
+        assert_eq!(err.error_response().status().as_u16(), 500);
+    }
+}
@@ -0,0 +1,52 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Unit tests for layouts compiled by `Engine::compile_templates`.
+
+use staticweaver::{Context, Engine};
+use std::fs;
+use std::time::Duration;
+
+/// The layouts of `tests/templates`, compiled by `Engine::compile_templates`.
+pub mod compiled {
+    include!("fixtures/templates.rs");
+}
+
+fn engine() -> Engine {
+    Engine::new("tests/templates", Duration::from_secs(60))
+}
+
+/// Test that the compiled fixture is up to date with the layouts.
+#[test]
+fn test_compiled_fixture_is_current() {
+    let source = engine().compile_templates().unwrap();
+    assert_eq!(
+        source,
+        fs::read_to_string("tests/fixtures/templates.rs").unwrap()
+    );
+}
+
+/// Test that compiled layouts render as the engine does.
+#[test]
+fn test_compiled_layouts_match_engine() {
+    let engine = engine();
+    let mut context = Context::new();
+    context.set("name", "<Ada>");
+    assert_eq!(
+        compiled::card(&compiled::Card { name: "<Ada>" }),
+        engine.render_page(&context, "card").unwrap()
+    );
+
+    let page = compiled::Static {
+        title: "3 <posts>",
+        tag: "Rust & Web",
+        body: "<em>new</em>",
+    };
+    context.set("title", page.title);
+    context.set("tag", page.tag);
+    context.set("body", page.body);
+    assert_eq!(
+        compiled::r#static(&page),
+        engine.render_page(&context, "static").unwrap()
+    );
+}
@@ -1,3 +1,8 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Unit tests for the `Context` struct and its methods.
+
 use staticweaver::Context;
 
 /// Unit tests for Context operations.
@@ -16,7 +21,7 @@ fn test_context_new() {
     #[test]
     fn test_context_set_and_get() {
         let mut context = Context::new();
-        context.set("name".to_string(), "Alice".to_string());
+        context.set("name", "Alice");
         assert_eq!(context.get("name"), Some(&"Alice".to_string()));
     }
 
@@ -24,8 +29,8 @@ fn test_context_set_and_get() {
     #[test]
     fn test_context_update_existing_key() {
         let mut context = Context::new();
-        context.set("name".to_string(), "Alice".to_string());
-        context.set("name".to_string(), "Bob".to_string());
+        context.set("name", "Alice");
+        context.set("name", "Bob");
         assert_eq!(context.get("name"), Some(&"Bob".to_string()));
     }
 
@@ -33,8 +38,8 @@ fn test_context_update_existing_key() {
     #[test]
     fn test_context_set_same_value() {
         let mut context = Context::new();
-        context.set("name".to_string(), "Alice".to_string());
-        context.set("name".to_string(), "Alice".to_string()); // Set same value again
+        context.set("name", "Alice");
+        context.set("name", "Alice"); // Set same value again
         assert_eq!(context.get("name"), Some(&"Alice".to_string()));
     }
 
@@ -49,8 +54,8 @@ fn test_context_get_nonexistent_key() {
     #[test]
     fn test_context_multiple_entries() {
         let mut context = Context::new();
-        context.set("name".to_string(), "Alice".to_string());
-        context.set("age".to_string(), "30".to_string());
+        context.set("name", "Alice");
+        context.set("age", "30");
         assert_eq!(context.get("name"), Some(&"Alice".to_string()));
         assert_eq!(context.get("age"), Some(&"30".to_string()));
     }
@@ -59,7 +64,7 @@ fn test_context_multiple_entries() {
     #[test]
     fn test_context_remove_key() {
         let mut context = Context::new();
-        context.set("name".to_string(), "Alice".to_string());
+        context.set("name", "Alice");
         assert_eq!(context.remove("name"), Some("Alice".to_string()));
         assert_eq!(context.get("name"), None);
     }
@@ -75,7 +80,7 @@ fn test_context_remove_nonexistent_key() {
     #[test]
     fn test_context_empty_key_value() {
         let mut context = Context::new();
-        context.set("".to_string(), "".to_string());
+        context.set("", "");
         assert_eq!(context.get(""), Some(&"".to_string()));
     }
 
@@ -83,7 +88,7 @@ fn test_context_empty_key_value() {
     #[test]
     fn test_context_non_ascii_keys_values() {
         let mut context = Context::new();
-        context.set("名前".to_string(), "アリス".to_string()); // Japanese for "name" and "Alice"
+        context.set("名前", "アリス"); // Japanese for "name" and "Alice"
         assert_eq!(context.get("名前"), Some(&"アリス".to_string()));
     }
 
@@ -91,9 +96,9 @@ fn test_context_non_ascii_keys_values() {
     #[test]
     fn test_context_clone() {
         let mut context = Context::new();
-        context.set("name".to_string(), "Alice".to_string());
+        context.set("name", "Alice");
         let mut cloned_context = context.clone();
-        cloned_context.set("name".to_string(), "Bob".to_string());
+        cloned_context.set("name", "Bob");
 
         assert_eq!(context.get("name"), Some(&"Alice".to_string())); // Original unchanged
         assert_eq!(
@@ -106,7 +111,7 @@ fn test_context_clone() {
     #[test]
     fn test_context_get_after_remove() {
         let mut context = Context::new();
-        context.set("name".to_string(), "Alice".to_string());
+        context.set("name", "Alice");
         let _ = context.remove("name");
         assert_eq!(context.get("name"), None); // After removal, should be None
     }
@@ -131,10 +136,10 @@ fn test_context_large_entries() {
     #[test]
     fn test_context_mutation_preserves_old_state() {
         let mut context = Context::new();
-        context.set("name".to_string(), "Alice".to_string());
+        context.set("name", "Alice");
         assert_eq!(context.get("name"), Some(&"Alice".to_string()));
 
-        context.set("age".to_string(), "30".to_string());
+        context.set("age", "30");
         assert_eq!(context.get("name"), Some(&"Alice".to_string())); // Name should still be "Alice"
         assert_eq!(context.get("age"), Some(&"30".to_string()));
     }
@@ -6,10 +6,11 @@
 #[cfg(test)]
 mod tests {
     use fnv::FnvHashMap;
-    use staticweaver::engine::EngineError;
+    use staticweaver::engine::{EngineError, PageKey};
     use staticweaver::{Context, Engine, PageOptions};
     use std::fs::File;
     use std::io::Write;
+    use std::sync::Arc;
     use std::time::Duration;
     use tempfile::tempdir;
 
@@ -18,6 +19,11 @@ fn create_engine() -> Engine {
         Engine::new("dummy/path", Duration::from_secs(60))
     }
 
+    /// Helper function to get the render cache key of a page.
+    fn page_key(engine: &Engine, layout: &str) -> PageKey {
+        engine.plan_page(&Context::new(), layout).cache_key
+    }
+
     /// Helper function to create a basic context with default values.
     fn create_basic_context() -> FnvHashMap<String, String> {
         let mut context = FnvHashMap::default();
@@ -149,24 +155,48 @@ mod file_tests {
             use super::*;
             use staticweaver::engine::EngineError::Io;
 
+            #[cfg(feature = "test-util")]
             #[test]
             fn test_engine_download_file() {
-                let engine = create_engine();
-                let url = "https://raw.githubusercontent.com/sebastienrousseau/shokunin/main/template";
-                let result = engine.create_template_folder(Some(url));
-                assert!(result.is_ok());
+                use staticweaver::fetch::StaticFetcher;
+
+                let url = "https://example.com/template";
+                let fetcher = StaticFetcher::new();
+                for file in [
+                    "contact.html",
+                    "index.html",
+                    "page.html",
+                    "post.html",
+                    "main.js",
+                    "sw.js",
+                ] {
+                    fetcher.insert(format!("{}/{}", url, file), file);
+                }
+                let mut engine = create_engine();
+                engine.set_fetcher(Arc::new(fetcher));
+
+                let folder =
+                    engine.create_template_folder(Some(url)).unwrap();
+                assert_eq!(
+                    std::fs::read_to_string(
+                        folder.path().join("index.html")
+                    )
+                    .unwrap(),
+                    "index.html"
+                );
             }
 
             #[test]
             fn test_engine_invalid_template_path() {
-                let mut engine = Engine::new(
+                let engine = Engine::new(
                     "invalid/path",
                     Duration::from_secs(60),
                 );
                 let context = Context::new();
-                let result =
-                    engine.render_page(&context, "nonexistent_layout");
-                assert!(matches!(result, Err(Io(_))));
+                let err = engine
+                    .render_page(&context, "nonexistent_layout")
+                    .unwrap_err();
+                assert!(matches!(err.root(), Io(_)));
             }
 
             #[test]
@@ -184,15 +214,14 @@ fn test_render_page_valid_path() {
                     temp_dir.path().to_str().unwrap()
                 );
 
-                let mut engine = Engine::new(
+                let engine = Engine::new(
                     temp_dir.path().to_str().unwrap(),
                     Duration::from_secs(60),
                 );
 
                 let mut context = Context::new();
-                context
-                    .set("greeting".to_string(), "Hello".to_string());
-                context.set("name".to_string(), "World".to_string());
+                context.set("greeting", "Hello");
+                context.set("name", "World");
 
                 let result = engine.render_page(&context, "layout");
                 assert!(
@@ -212,14 +241,15 @@ fn test_render_page_valid_path() {
 
             #[test]
             fn test_render_page_missing_file() {
-                let mut engine = Engine::new(
+                let engine = Engine::new(
                     "missing/path",
                     Duration::from_secs(60),
                 );
                 let context = Context::new();
-                let result =
-                    engine.render_page(&context, "nonexistent_layout");
-                assert!(matches!(result, Err(Io(_))));
+                let err = engine
+                    .render_page(&context, "nonexistent_layout")
+                    .unwrap_err();
+                assert!(matches!(err.root(), Io(_)));
             }
         }
 
@@ -236,8 +266,7 @@ fn test_page_options_new() {
             #[test]
             fn test_page_options_set_get() {
                 let mut options = PageOptions::new();
-                options
-                    .set("title".to_string(), "My Title".to_string());
+                options.set("title", "My Title");
                 assert_eq!(
                     options.get("title"),
                     Some(&"My Title".to_string())
@@ -326,14 +355,15 @@ fn test_clear_cache() {
                 let mut engine =
                     Engine::new("templates", Duration::from_secs(3600));
 
+                let key = page_key(&engine, "key1");
                 let _ = engine
-                    .render_cache
-                    .insert("key1".to_string(), "value1".to_string());
-                assert!(!engine.render_cache.is_empty());
+                    .render_cache_mut()
+                    .insert(key, Arc::from("value1"));
+                assert!(!engine.render_cache().is_empty());
 
                 // Clear the cache
                 engine.clear_cache();
-                assert!(engine.render_cache.is_empty());
+                assert!(engine.render_cache().is_empty());
             }
 
             #[test]
@@ -342,18 +372,20 @@ fn test_set_max_cache_size() {
                     Engine::new("templates", Duration::from_secs(3600));
 
                 // Insert multiple entries to simulate cache size exceeding max limit
+                let key = page_key(&engine, "key1");
                 let _ = engine
-                    .render_cache
-                    .insert("key1".to_string(), "value1".to_string());
+                    .render_cache_mut()
+                    .insert(key, Arc::from("value1"));
+                let key = page_key(&engine, "key2");
                 let _ = engine
-                    .render_cache
-                    .insert("key2".to_string(), "value2".to_string());
-                assert_eq!(engine.render_cache.len(), 2);
+                    .render_cache_mut()
+                    .insert(key, Arc::from("value2"));
+                assert_eq!(engine.render_cache().len(), 2);
 
                 // Set max cache size to 1
                 engine.set_max_cache_size(1);
                 // Cache should be cleared as the limit is exceeded
-                assert!(engine.render_cache.is_empty());
+                assert!(engine.render_cache().is_empty());
             }
         }
     }
@@ -1,6 +1,8 @@
 // Copyright © 2024 StaticWeaver. All rights reserved.
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
+//! Unit tests for the `EngineError` and `TemplateError` types.
+
 use staticweaver::EngineError;
 use staticweaver::TemplateError;
 
@@ -22,6 +24,7 @@ fn test_template_error_io() {
 
     /// Test the `Reqwest` variant of the `TemplateError` enum.
     /// This test checks if an HTTP request error is correctly wrapped inside a `TemplateError::Reqwest`.
+    #[cfg(feature = "remote")]
     #[test]
     fn test_template_error_reqwest() {
         let reqwest_error =
@@ -69,6 +72,7 @@ fn test_template_error_io_display() {
 
     /// Test the `Display` implementation for the `TemplateError::Reqwest` variant.
     /// This test checks if the display output for a Reqwest error is formatted correctly.
+    #[cfg(feature = "remote")]
     #[test]
     fn test_template_error_reqwest_display() {
         let reqwest_error =
@@ -97,6 +101,7 @@ fn test_template_error_io_chaining() {
 
     /// Test chaining of Reqwest errors using the `#[from]` attribute.
     /// This ensures that Reqwest errors are correctly converted into `TemplateError::Reqwest`.
+    #[cfg(feature = "remote")]
     #[test]
     fn test_template_error_reqwest_chaining() {
         let reqwest_error =
@@ -133,6 +138,7 @@ fn test_template_error_unreachable() {
 
     /// Test conversion consistency between different types of errors.
     /// This ensures that both I/O and Reqwest errors are correctly handled by `TemplateError`.
+    #[cfg(feature = "remote")]
     #[test]
     fn test_template_error_conversion_consistency() {
         let io_error: io::Error =
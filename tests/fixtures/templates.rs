@@ -0,0 +1,45 @@
+// @generated by staticweaver from its layouts. Do not edit.
+
+/// The context of the `card` layout.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Card<'a> {
+    /// The value of the key "name".
+    pub name: &'a str,
+}
+
+/// Renders the `card` layout, compiled from `tests/templates/card.html`.
+#[must_use]
+pub fn card(context: &Card<'_>) -> String {
+    let mut out = String::with_capacity(33);
+    out.push_str("<div class=\"card\">");
+    ::staticweaver::static_render::write_str(&mut out, context.name, &[], ::staticweaver::escape::OutputFormat::Plain);
+    out.push_str("</div>\n");
+    out
+}
+
+/// The context of the `static` layout.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Static<'a> {
+    /// The value of the key "body".
+    pub body: &'a str,
+    /// The value of the key "tag".
+    pub tag: &'a str,
+    /// The value of the key "title".
+    pub title: &'a str,
+}
+
+/// Renders the `static` layout, compiled from `tests/templates/static.html`.
+#[must_use]
+pub fn r#static(context: &Static<'_>) -> String {
+    let mut out = String::with_capacity(92);
+    out.push_str("<h1>");
+    ::staticweaver::static_render::write_str(&mut out, context.title, &[], ::staticweaver::escape::OutputFormat::Html);
+    out.push_str("</h1>\n<a href=\"/tags/");
+    ::staticweaver::static_render::write_str(&mut out, context.tag, &[::staticweaver::filter::Filter::Slugify], ::staticweaver::escape::OutputFormat::Html);
+    out.push_str("\">");
+    ::staticweaver::static_render::write_str(&mut out, context.tag, &[], ::staticweaver::escape::OutputFormat::Html);
+    out.push_str("</a>\n<p>");
+    ::staticweaver::static_render::write_str(&mut out, context.body, &[::staticweaver::filter::Filter::Safe], ::staticweaver::escape::OutputFormat::Html);
+    out.push_str("</p>\n");
+    out
+}
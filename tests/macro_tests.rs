@@ -0,0 +1,29 @@
+// Copyright © 2024 StaticWeaver. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Unit tests for the `render_static!` macro.
+
+#![cfg(feature = "macros")]
+
+use staticweaver::{render_static, Context, Engine};
+use std::time::Duration;
+
+/// Test that `render_static!` renders a template as an engine does.
+#[test]
+fn test_render_static_matches_engine() {
+    let count = 3;
+    let page = render_static!("tests/templates/static.html", {
+        title: format!("{} <posts>", count),
+        tag: "Rust & Web",
+        "body": "<em>new</em>",
+    });
+
+    let engine =
+        Engine::new("tests/templates", Duration::from_secs(60));
+    let mut context = Context::new();
+    context.set("title", "3 <posts>");
+    context.set("tag", "Rust & Web");
+    context.set("body", "<em>new</em>");
+    assert_eq!(page, engine.render_page(&context, "static").unwrap());
+    assert!(page.contains("<h1>3 &lt;posts&gt;</h1>"));
+}